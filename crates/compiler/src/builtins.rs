@@ -63,14 +63,14 @@ fn mk_builtin_table() -> Vec<Builtin> {
             min_args: Q(0),
             max_args: Q(0),
             types: vec![],
-            implemented: false,
+            implemented: true,
         },
         Builtin {
             name: "verb_cache_stats".to_string(),
             min_args: Q(0),
             max_args: Q(0),
             types: vec![],
-            implemented: false,
+            implemented: true,
         },
         Builtin {
             name: "call_function".to_string(),
@@ -90,7 +90,7 @@ fn mk_builtin_table() -> Vec<Builtin> {
             name: "suspend".to_string(),
             min_args: Q(0),
             max_args: Q(1),
-            types: vec![Typed(TYPE_INT)],
+            types: vec![AnyNum],
             implemented: true,
         },
         Builtin {
@@ -138,8 +138,8 @@ fn mk_builtin_table() -> Vec<Builtin> {
         Builtin {
             name: "callers".to_string(),
             min_args: Q(0),
-            max_args: Q(1),
-            types: vec![Any],
+            max_args: Q(2),
+            types: vec![Any, Typed(TYPE_INT)],
             implemented: true,
         },
         Builtin {
@@ -161,7 +161,7 @@ fn mk_builtin_table() -> Vec<Builtin> {
             min_args: Q(0),
             max_args: Q(0),
             types: vec![],
-            implemented: false,
+            implemented: true,
         },
         Builtin {
             name: "value_bytes".to_string(),
@@ -215,15 +215,15 @@ fn mk_builtin_table() -> Vec<Builtin> {
         Builtin {
             name: "setadd".to_string(),
             min_args: Q(2),
-            max_args: Q(2),
-            types: vec![Typed(TYPE_LIST), Any],
+            max_args: Q(3),
+            types: vec![Typed(TYPE_LIST), Any, Any],
             implemented: true,
         },
         Builtin {
             name: "setremove".to_string(),
             min_args: Q(2),
-            max_args: Q(2),
-            types: vec![Typed(TYPE_LIST), Any],
+            max_args: Q(3),
+            types: vec![Typed(TYPE_LIST), Any, Any],
             implemented: true,
         },
         Builtin {
@@ -558,15 +558,15 @@ fn mk_builtin_table() -> Vec<Builtin> {
         Builtin {
             name: "object_bytes".to_string(),
             min_args: Q(1),
-            max_args: Q(1),
-            types: vec![Typed(TYPE_OBJ)],
-            implemented: false,
+            max_args: Q(2),
+            types: vec![Typed(TYPE_OBJ), Any],
+            implemented: true,
         },
         Builtin {
             name: "valid".to_string(),
             min_args: Q(1),
             max_args: Q(1),
-            types: vec![Typed(TYPE_OBJ)],
+            types: vec![Any],
             implemented: true,
         },
         Builtin {
@@ -725,16 +725,16 @@ fn mk_builtin_table() -> Vec<Builtin> {
         },
         Builtin {
             name: "open_network_connection".to_string(),
-            min_args: Q(0),
-            max_args: U,
-            types: vec![],
-            implemented: false,
+            min_args: Q(2),
+            max_args: Q(3),
+            types: vec![Typed(TYPE_STR), Typed(TYPE_INT), Typed(TYPE_OBJ)],
+            implemented: true,
         },
         Builtin {
             name: "connected_players".to_string(),
             min_args: Q(0),
-            max_args: Q(1),
-            types: vec![Any],
+            max_args: Q(2),
+            types: vec![Any, Any],
             implemented: true,
         },
         Builtin {
@@ -768,8 +768,8 @@ fn mk_builtin_table() -> Vec<Builtin> {
         Builtin {
             name: "boot_player".to_string(),
             min_args: Q(1),
-            max_args: Q(1),
-            types: vec![Typed(TYPE_OBJ)],
+            max_args: Q(2),
+            types: vec![Typed(TYPE_OBJ), Any],
             implemented: true,
         },
         Builtin {
@@ -777,21 +777,21 @@ fn mk_builtin_table() -> Vec<Builtin> {
             min_args: Q(3),
             max_args: Q(3),
             types: vec![Typed(TYPE_OBJ), Typed(TYPE_STR), Any],
-            implemented: false,
+            implemented: true,
         },
         Builtin {
             name: "connection_option".to_string(),
             min_args: Q(2),
             max_args: Q(2),
             types: vec![Typed(TYPE_OBJ), Typed(TYPE_STR)],
-            implemented: false,
+            implemented: true,
         },
         Builtin {
             name: "connection_options".to_string(),
             min_args: Q(1),
             max_args: Q(1),
             types: vec![Typed(TYPE_OBJ)],
-            implemented: false,
+            implemented: true,
         },
         Builtin {
             name: "listen".to_string(),
@@ -812,7 +812,7 @@ fn mk_builtin_table() -> Vec<Builtin> {
             min_args: Q(0),
             max_args: Q(0),
             types: vec![],
-            implemented: false,
+            implemented: true,
         },
         Builtin {
             name: "buffered_output_length".to_string(),
@@ -854,7 +854,7 @@ fn mk_builtin_table() -> Vec<Builtin> {
             min_args: Q(0),
             max_args: Q(1),
             types: vec![Typed(TYPE_OBJ)],
-            implemented: false,
+            implemented: true,
         },
         Builtin {
             name: "resume".to_string(),
@@ -947,6 +947,195 @@ fn mk_builtin_table() -> Vec<Builtin> {
             types: vec![Typed(TYPE_STR)],
             implemented: true,
         },
+        Builtin {
+            name: "isa".to_string(),
+            min_args: Q(2),
+            max_args: Q(2),
+            types: vec![Typed(TYPE_OBJ), Typed(TYPE_OBJ)],
+            implemented: true,
+        },
+        Builtin {
+            name: "string_hmac".to_string(),
+            min_args: Q(2),
+            max_args: Q(3),
+            types: vec![Typed(TYPE_STR), Typed(TYPE_STR), Typed(TYPE_STR)],
+            implemented: true,
+        },
+        Builtin {
+            name: "fromliteral".to_string(),
+            min_args: Q(1),
+            max_args: Q(1),
+            types: vec![Typed(TYPE_STR)],
+            implemented: true,
+        },
+        Builtin {
+            name: "explode".to_string(),
+            min_args: Q(1),
+            max_args: Q(3),
+            types: vec![Typed(TYPE_STR), Typed(TYPE_STR), Any],
+            implemented: true,
+        },
+        Builtin {
+            name: "strjoin".to_string(),
+            min_args: Q(1),
+            max_args: Q(2),
+            types: vec![Typed(TYPE_LIST), Typed(TYPE_STR)],
+            implemented: true,
+        },
+        Builtin {
+            name: "random_bytes".to_string(),
+            min_args: Q(1),
+            max_args: Q(1),
+            types: vec![Typed(TYPE_INT)],
+            implemented: true,
+        },
+        Builtin {
+            name: "set_random_seed".to_string(),
+            min_args: Q(1),
+            max_args: Q(1),
+            types: vec![Typed(TYPE_INT)],
+            implemented: true,
+        },
+        Builtin {
+            name: "task_perms".to_string(),
+            min_args: Q(0),
+            max_args: Q(0),
+            types: vec![],
+            implemented: true,
+        },
+        Builtin {
+            name: "first".to_string(),
+            min_args: Q(1),
+            max_args: Q(1),
+            types: vec![Typed(TYPE_LIST)],
+            implemented: true,
+        },
+        Builtin {
+            name: "last".to_string(),
+            min_args: Q(1),
+            max_args: Q(1),
+            types: vec![Typed(TYPE_LIST)],
+            implemented: true,
+        },
+        Builtin {
+            name: "nth".to_string(),
+            min_args: Q(2),
+            max_args: Q(2),
+            types: vec![Typed(TYPE_LIST), Typed(TYPE_INT)],
+            implemented: true,
+        },
+        Builtin {
+            name: "sort".to_string(),
+            min_args: Q(1),
+            max_args: Q(3),
+            types: vec![Typed(TYPE_LIST), Typed(TYPE_LIST), Any],
+            implemented: true,
+        },
+        Builtin {
+            name: "slice".to_string(),
+            min_args: Q(2),
+            max_args: Q(2),
+            types: vec![Typed(TYPE_LIST), Any],
+            implemented: true,
+        },
+        Builtin {
+            name: "notify_presence".to_string(),
+            min_args: Q(3),
+            max_args: Q(3),
+            types: vec![Typed(TYPE_OBJ), Typed(TYPE_OBJ), Typed(TYPE_STR)],
+            implemented: true,
+        },
+        Builtin {
+            name: "server_features".to_string(),
+            min_args: Q(0),
+            max_args: Q(0),
+            types: vec![],
+            implemented: true,
+        },
+        Builtin {
+            name: "parents".to_string(),
+            min_args: Q(1),
+            max_args: Q(1),
+            types: vec![Typed(TYPE_LIST)],
+            implemented: true,
+        },
+        Builtin {
+            name: "children_of".to_string(),
+            min_args: Q(1),
+            max_args: Q(1),
+            types: vec![Typed(TYPE_LIST)],
+            implemented: true,
+        },
+        Builtin {
+            name: "export_database".to_string(),
+            min_args: Q(2),
+            max_args: Q(2),
+            types: vec![Typed(TYPE_STR), Typed(TYPE_STR)],
+            implemented: true,
+        },
+        Builtin {
+            name: "verify_database".to_string(),
+            min_args: Q(0),
+            max_args: Q(0),
+            types: vec![],
+            implemented: true,
+        },
+        Builtin {
+            name: "notify_connection".to_string(),
+            min_args: Q(2),
+            max_args: Q(3),
+            types: vec![Typed(TYPE_OBJ), Typed(TYPE_STR), Typed(TYPE_STR)],
+            implemented: true,
+        },
+        Builtin {
+            name: "revoke_tokens".to_string(),
+            min_args: Q(1),
+            max_args: Q(1),
+            types: vec![Typed(TYPE_OBJ)],
+            implemented: true,
+        },
+        Builtin {
+            name: "forked_tasks".to_string(),
+            min_args: Q(0),
+            max_args: Q(0),
+            types: vec![],
+            implemented: true,
+        },
+        Builtin {
+            name: "kill_forks".to_string(),
+            min_args: Q(0),
+            max_args: Q(0),
+            types: vec![],
+            implemented: true,
+        },
+        Builtin {
+            name: "set_quota".to_string(),
+            min_args: Q(2),
+            max_args: Q(3),
+            types: vec![Typed(TYPE_OBJ), Typed(TYPE_STR), Typed(TYPE_INT)],
+            implemented: true,
+        },
+        Builtin {
+            name: "quota".to_string(),
+            min_args: Q(1),
+            max_args: Q(1),
+            types: vec![Typed(TYPE_OBJ)],
+            implemented: true,
+        },
+        Builtin {
+            name: "raw_recycle".to_string(),
+            min_args: Q(1),
+            max_args: Q(1),
+            types: vec![Typed(TYPE_OBJ)],
+            implemented: true,
+        },
+        Builtin {
+            name: "notify_system".to_string(),
+            min_args: Q(2),
+            max_args: Q(3),
+            types: vec![Typed(TYPE_OBJ), Typed(TYPE_STR), Typed(TYPE_STR)],
+            implemented: true,
+        },
     ]
 }
 
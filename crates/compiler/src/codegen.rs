@@ -153,6 +153,23 @@ impl CodegenState {
     }
 
     fn generate_assign(&mut self, left: &Expr, right: &Expr) -> Result<(), CompileError> {
+        // `x = {@x, item}` appends to the list in x directly, rather than copying it to add one
+        // item; so long as evaluating `item` can't itself change x. x is still pushed and checked
+        // first, as it would be to build the list, so that a missing variable or non-list raises
+        // before `item` is evaluated.
+        if let (Expr::Id(name), Expr::List(args)) = (left, right) {
+            if let [Arg::Splice(Expr::Id(spliced)), Arg::Normal(item)] = args.as_slice() {
+                if spliced == name && !may_assign(item) {
+                    self.emit(Op::Push(*name));
+                    self.push_stack(1);
+                    self.emit(Op::CheckListForSplice);
+                    self.generate_expr(item)?;
+                    self.emit(Op::ListAddTailLocal(*name));
+                    self.pop_stack(1);
+                    return Ok(());
+                }
+            }
+        }
         self.push_lvalue(left, false)?;
         self.generate_expr(right)?;
         match left {
@@ -763,6 +780,45 @@ impl CodegenState {
     }
 }
 
+/// Whether evaluating `expr` could assign to any variable.
+fn may_assign(expr: &Expr) -> bool {
+    let args_may_assign = |args: &[Arg]| {
+        args.iter().any(|a| match a {
+            Arg::Normal(e) | Arg::Splice(e) => may_assign(e),
+        })
+    };
+    match expr {
+        Expr::Assign { .. } | Expr::Scatter(..) => true,
+        Expr::Value(_) | Expr::Id(_) | Expr::Length => false,
+        Expr::Pass { args } | Expr::Call { args, .. } | Expr::List(args) => args_may_assign(args),
+        Expr::Binary(_, l, r) | Expr::And(l, r) | Expr::Or(l, r) | Expr::Index(l, r) => {
+            may_assign(l) || may_assign(r)
+        }
+        Expr::Unary(_, e) => may_assign(e),
+        Expr::Prop { location, property } => may_assign(location) || may_assign(property),
+        Expr::Verb {
+            location,
+            verb,
+            args,
+        } => may_assign(location) || may_assign(verb) || args_may_assign(args),
+        Expr::Range { base, from, to } => may_assign(base) || may_assign(from) || may_assign(to),
+        Expr::Cond {
+            condition,
+            consequence,
+            alternative,
+        } => may_assign(condition) || may_assign(consequence) || may_assign(alternative),
+        Expr::Catch {
+            trye,
+            codes,
+            except,
+        } => {
+            may_assign(trye)
+                || matches!(codes, CatchCodes::Codes(codes) if args_may_assign(codes))
+                || except.as_ref().is_some_and(|e| may_assign(e))
+        }
+    }
+}
+
 pub fn compile(program: &str) -> Result<Program, CompileError> {
     let compile_span = tracing::trace_span!("compile");
     let _compile_guard = compile_span.enter();
@@ -1509,4 +1509,43 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn test_append_to_self() {
+        let program = "l = {@l, x + 1};";
+        let binary = compile(program).unwrap();
+        let l = binary.find_var("l");
+        let x = binary.find_var("x");
+        assert_eq!(
+            *binary.main_vector.as_ref(),
+            vec![
+                Push(l),
+                CheckListForSplice,
+                Push(x),
+                ImmInt(1),
+                Add,
+                ListAddTailLocal(l),
+                Pop,
+                Done
+            ]
+        );
+
+        // Not when evaluating the new item could change the variable underneath us.
+        let program = "l = {@l, l = 1};";
+        let binary = compile(program).unwrap();
+        let l = binary.find_var("l");
+        assert_eq!(
+            *binary.main_vector.as_ref(),
+            vec![
+                Push(l),
+                CheckListForSplice,
+                ImmInt(1),
+                Put(l),
+                ListAddTail,
+                Put(l),
+                Pop,
+                Done
+            ]
+        );
+    }
 }
@@ -404,6 +404,20 @@ impl Decompile {
                     right: Box::new(expr),
                 });
             }
+            Op::ListAddTailLocal(varname) => {
+                let item = self.pop_expr()?;
+                // The variable, as pushed and checked by `CheckListForSplice`.
+                let Expr::List(_) = self.pop_expr()? else {
+                    return Err(MalformedProgram("expected list".to_string()));
+                };
+                self.push_expr(Expr::Assign {
+                    left: Box::new(Expr::Id(varname)),
+                    right: Box::new(Expr::List(vec![
+                        Arg::Splice(Expr::Id(varname)),
+                        Arg::Normal(item),
+                    ])),
+                });
+            }
             Op::And(label) => {
                 let left = self.pop_expr()?;
                 self.decompile_statements_until(&label)?;
@@ -1061,4 +1075,11 @@ return 0 && "Automatically Added Return";
         let (parse, decompiled) = parse_decompile(program);
         assert_trees_match_recursive(&parse.stmts, &decompiled.stmts);
     }
+
+    #[test]
+    fn test_decompile_append_to_self() {
+        let program = r#"l = {}; for i in [1..3] l = {@l, i * 2}; endfor return l;"#;
+        let (parse, decompiled) = parse_decompile(program);
+        assert_trees_match_recursive(&parse.stmts, &decompiled.stmts);
+    }
 }
@@ -0,0 +1,241 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Breaks a compiled program out into a listing of its literals, labels, variables, and opcodes,
+//! for `disassemble()` and for external debuggers.
+
+use moor_values::model::{DisassembledOp, Disassembly};
+use strum::EnumCount;
+
+use crate::opcode::Op;
+use crate::program::Program;
+use crate::GlobalName;
+
+/// The opcode's mnemonic, and its operands rendered the way the compiler's debug form of the
+/// opcode would show them.
+fn opcode_and_operands(op: &Op) -> (&'static str, String) {
+    let none = String::new;
+    match op {
+        Op::Add => ("Add", none()),
+        Op::And(label) => ("And", format!("{label:?}")),
+        Op::CallVerb => ("CallVerb", none()),
+        Op::Catch(label) => ("Catch", format!("{label:?}")),
+        Op::CheckListForSplice => ("CheckListForSplice", none()),
+        Op::Continue => ("Continue", none()),
+        Op::Div => ("Div", none()),
+        Op::Done => ("Done", none()),
+        Op::Eif(label) => ("Eif", format!("{label:?}")),
+        Op::EndCatch(label) => ("EndCatch", format!("{label:?}")),
+        Op::EndExcept(label) => ("EndExcept", format!("{label:?}")),
+        Op::EndFinally => ("EndFinally", none()),
+        Op::Eq => ("Eq", none()),
+        Op::Exit { stack, label } => ("Exit", format!("{{ stack: {stack:?}, label: {label:?} }}")),
+        Op::ExitId(label) => ("ExitId", format!("{label:?}")),
+        Op::Exp => ("Exp", none()),
+        Op::ForList { id, end_label } => (
+            "ForList",
+            format!("{{ id: {id:?}, end_label: {end_label:?} }}"),
+        ),
+        Op::ForRange { id, end_label } => (
+            "ForRange",
+            format!("{{ id: {id:?}, end_label: {end_label:?} }}"),
+        ),
+        Op::Fork { fv_offset, id } => (
+            "Fork",
+            format!("{{ fv_offset: {fv_offset:?}, id: {id:?} }}"),
+        ),
+        Op::FuncCall { id } => ("FuncCall", format!("{{ id: {id:?} }}")),
+        Op::GPush { id } => ("GPush", format!("{{ id: {id:?} }}")),
+        Op::GPut { id } => ("GPut", format!("{{ id: {id:?} }}")),
+        Op::Ge => ("Ge", none()),
+        Op::GetProp => ("GetProp", none()),
+        Op::Gt => ("Gt", none()),
+        Op::IfQues(label) => ("IfQues", format!("{label:?}")),
+        Op::Imm(label) => ("Imm", format!("{label:?}")),
+        Op::ImmBigInt(i) => ("ImmBigInt", format!("{i:?}")),
+        Op::ImmEmptyList => ("ImmEmptyList", none()),
+        Op::ImmErr(e) => ("ImmErr", format!("{e:?}")),
+        Op::ImmInt(i) => ("ImmInt", format!("{i:?}")),
+        Op::ImmNone => ("ImmNone", none()),
+        Op::ImmObjid(o) => ("ImmObjid", format!("{o:?}")),
+        Op::In => ("In", none()),
+        Op::IndexSet => ("IndexSet", none()),
+        Op::Jump { label } => ("Jump", format!("{{ label: {label:?} }}")),
+        Op::Le => ("Le", none()),
+        Op::Length(offset) => ("Length", format!("{offset:?}")),
+        Op::ListAddTail => ("ListAddTail", none()),
+        Op::ListAppend => ("ListAppend", none()),
+        Op::Lt => ("Lt", none()),
+        Op::MakeSingletonList => ("MakeSingletonList", none()),
+        Op::Mod => ("Mod", none()),
+        Op::Mul => ("Mul", none()),
+        Op::Ne => ("Ne", none()),
+        Op::Not => ("Not", none()),
+        Op::Or(label) => ("Or", format!("{label:?}")),
+        Op::Pass => ("Pass", none()),
+        Op::Pop => ("Pop", none()),
+        Op::Push(name) => ("Push", format!("{name:?}")),
+        Op::PushGetProp => ("PushGetProp", none()),
+        Op::PushLabel(label) => ("PushLabel", format!("{label:?}")),
+        Op::PushRef => ("PushRef", none()),
+        Op::PushTemp => ("PushTemp", none()),
+        Op::Put(name) => ("Put", format!("{name:?}")),
+        Op::PutProp => ("PutProp", none()),
+        Op::PutTemp => ("PutTemp", none()),
+        Op::RangeRef => ("RangeRef", none()),
+        Op::RangeSet => ("RangeSet", none()),
+        Op::Ref => ("Ref", none()),
+        Op::Return => ("Return", none()),
+        Op::Return0 => ("Return0", none()),
+        Op::Scatter(args) => ("Scatter", format!("{args:?}")),
+        Op::Sub => ("Sub", none()),
+        Op::TryExcept { num_excepts } => {
+            ("TryExcept", format!("{{ num_excepts: {num_excepts:?} }}"))
+        }
+        Op::TryFinally(label) => ("TryFinally", format!("{label:?}")),
+        Op::UnaryMinus => ("UnaryMinus", none()),
+        Op::While(label) => ("While", format!("{label:?}")),
+        Op::WhileId { id, end_label } => (
+            "WhileId",
+            format!("{{ id: {id:?}, end_label: {end_label:?} }}"),
+        ),
+        Op::If(label) => ("If", format!("{label:?}")),
+        Op::ListAddTailLocal(name) => ("ListAddTailLocal", format!("{name:?}")),
+    }
+}
+
+fn disassemble_op(offset: usize, line: Option<usize>, op: &Op) -> DisassembledOp {
+    let (opcode, operands) = opcode_and_operands(op);
+    DisassembledOp {
+        offset,
+        line,
+        opcode: opcode.to_string(),
+        operands,
+    }
+}
+
+pub fn disassemble(program: &Program) -> Disassembly {
+    let literals = program.literals.iter().map(|l| l.to_literal()).collect();
+
+    // The first labels are reserved for the global names, and aren't interesting.
+    let jump_labels = program
+        .jump_labels
+        .iter()
+        .enumerate()
+        .skip(GlobalName::COUNT)
+        .map(|(i, l)| {
+            let name = l
+                .name
+                .as_ref()
+                .and_then(|name| program.var_names.name_of(name))
+                .map(|name| name.to_string());
+            (i, l.position.0 as usize, name)
+        })
+        .collect();
+
+    let variables = program.var_names.names.clone();
+
+    let main_vector = program
+        .main_vector
+        .iter()
+        .enumerate()
+        .map(|(i, op)| {
+            let mut line = None;
+            let mut last_line_no = 0;
+            for (pc, line_no) in &program.line_number_spans {
+                if *pc == i {
+                    line = Some(last_line_no);
+                    break;
+                }
+                last_line_no = *line_no;
+            }
+            disassemble_op(i, line, op)
+        })
+        .collect();
+
+    let fork_vectors = program
+        .fork_vectors
+        .iter()
+        .map(|fv| {
+            fv.iter()
+                .enumerate()
+                .map(|(i, op)| disassemble_op(i, None, op))
+                .collect()
+        })
+        .collect();
+
+    Disassembly {
+        literals,
+        jump_labels,
+        variables,
+        main_vector,
+        fork_vectors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::compile;
+    use crate::disassemble::disassemble;
+
+    #[test]
+    fn test_disassemble_opcodes_and_forks() {
+        let program = compile("x = 1; fork (5) return x + 1; endfork return x;").unwrap();
+        let disassembly = disassemble(&program);
+
+        let opcodes: Vec<_> = disassembly
+            .main_vector
+            .iter()
+            .map(|op| op.opcode.as_str())
+            .collect();
+        assert!(opcodes.contains(&"ImmInt"));
+        assert!(opcodes.contains(&"Fork"));
+        assert!(opcodes.contains(&"Return"));
+        assert_eq!(disassembly.variables.len(), program.var_names.names.len());
+
+        assert_eq!(disassembly.fork_vectors.len(), 1);
+        assert!(disassembly.fork_vectors[0]
+            .iter()
+            .any(|op| op.opcode == "Add"));
+    }
+
+    #[test]
+    fn test_disassembled_ops_read_like_the_compilers() {
+        let program = compile(
+            r#"x = 1;
+               {a, ?b = 2, @c} = {1, 2, 3};
+               while loop (x < 3)
+                 x = x + 1;
+                 break loop;
+               endwhile
+               for i in [1..3]
+                 x = x + i;
+               endfor
+               try
+                 return x[1..2];
+               except e (E_PERM)
+                 return #0:foo(e);
+               endtry
+               return -x || {@c, x};"#,
+        )
+        .unwrap();
+        let disassembly = disassemble(&program);
+
+        // Rendered back out, each op reads the same as the compiler's own form of it.
+        assert_eq!(disassembly.main_vector.len(), program.main_vector.len());
+        for (op, disassembled) in program.main_vector.iter().zip(&disassembly.main_vector) {
+            assert_eq!(format!("{:?}", op), disassembled.to_string());
+        }
+    }
+}
@@ -13,6 +13,7 @@
 //
 
 use bincode::{Decode, Encode};
+use moor_values::model::{CompileDiagnostic, DiagnosticSeverity};
 use strum::{Display, EnumCount, EnumIter, FromRepr};
 use thiserror::Error;
 
@@ -20,6 +21,7 @@ mod ast;
 mod builtins;
 mod codegen;
 mod decompile;
+mod disassemble;
 mod labels;
 mod parse;
 mod unparse;
@@ -31,8 +33,10 @@ mod program;
 pub use crate::builtins::{offset_for_builtin, ArgCount, ArgType, Builtin, BUILTIN_DESCRIPTORS};
 pub use crate::codegen::compile;
 pub use crate::decompile::program_to_tree;
+pub use crate::disassemble::disassemble;
 pub use crate::labels::{JumpLabel, Label, Name, Names, Offset};
 pub use crate::opcode::{Op, ScatterLabel};
+pub use crate::parse::parse_literal;
 pub use crate::program::{Program, EMPTY_PROGRAM};
 pub use crate::unparse::unparse;
 
@@ -68,10 +72,36 @@ pub enum GlobalName {
 pub enum CompileError {
     #[error("Failure to parse string: {0}")]
     StringLexError(String),
-    #[error("Failure to parse program: {0}")]
-    ParseError(String),
+    #[error("Failure to parse program at line {line}, column {column}: {message}")]
+    ParseError {
+        line: usize,
+        column: usize,
+        message: String,
+    },
     #[error("Unknown built-in function: {0}")]
     UnknownBuiltinFunction(String),
     #[error("Could not find loop with id: {0}")]
     UnknownLoopLabel(String),
+    #[error("Not a literal value: {0}")]
+    NotALiteral(String),
+}
+
+impl CompileError {
+    /// This error as a diagnostic an editor can show against the source.
+    pub fn diagnostic(&self) -> CompileDiagnostic {
+        let (line, column, message) = match self {
+            CompileError::ParseError {
+                line,
+                column,
+                message,
+            } => (Some(*line), Some(*column), message.clone()),
+            e => (None, None, e.to_string()),
+        };
+        CompileDiagnostic {
+            line,
+            column,
+            severity: DiagnosticSeverity::Error,
+            message,
+        }
+    }
 }
@@ -90,6 +90,10 @@ pub enum Op {
     While(Label),
     WhileId { id: Name, end_label: Label },
     If(Label),
+    /// `id = {@id, <value>}`: pop the value, and the copy of the (checked) list in the variable
+    /// pushed ahead of it, and add the value to the end of the list in the variable -- in place,
+    /// where nothing else shares the list -- then push the new list.
+    ListAddTailLocal(Name),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Encode, Decode)]
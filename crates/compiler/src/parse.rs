@@ -19,6 +19,7 @@ use std::rc::Rc;
 use std::str::FromStr;
 
 use moor_values::SYSTEM_OBJECT;
+use pest::error::LineColLocation;
 use pest::pratt_parser::{Assoc, Op, PrattParser};
 pub use pest::Parser as PestParser;
 use tracing::{instrument, warn};
@@ -27,8 +28,8 @@ use moor_values::var::Error::{
     E_ARGS, E_DIV, E_FLOAT, E_INVARG, E_INVIND, E_MAXREC, E_NACC, E_NONE, E_PERM, E_PROPNF,
     E_QUOTA, E_RANGE, E_RECMOVE, E_TYPE, E_VARNF, E_VERBNF,
 };
-use moor_values::var::Objid;
-use moor_values::var::{v_err, v_float, v_int, v_objid, v_str, v_string};
+use moor_values::var::{v_err, v_float, v_int, v_listv, v_objid, v_str, v_string};
+use moor_values::var::{Objid, Var, Variant};
 
 use crate::ast::Arg::{Normal, Splice};
 use crate::ast::{
@@ -759,8 +760,15 @@ pub fn parse_program(program_text: &str) -> Result<Parse, CompileError> {
     let pairs = match MooParser::parse(Rule::program, program_text) {
         Ok(pairs) => pairs,
         Err(e) => {
-            let msg = format!("Parse error: {}", e);
-            return Err(CompileError::ParseError(msg));
+            let (line, column) = match e.line_col {
+                LineColLocation::Pos(pos) => pos,
+                LineColLocation::Span(start, _) => start,
+            };
+            return Err(CompileError::ParseError {
+                line,
+                column,
+                message: e.variant.message().to_string(),
+            });
         }
     };
 
@@ -799,6 +807,48 @@ pub fn parse_program(program_text: &str) -> Result<Parse, CompileError> {
     })
 }
 
+/// Parse a MOO value literal, as produced by `toliteral`, back into the value it describes.
+/// Only ints, floats, strings, objects, errors and lists of those are accepted; anything which
+/// would need evaluating (variables, operators other than negation of a number, calls, etc.) is
+/// rejected, so this is safe to use on untrusted input.
+pub fn parse_literal(literal_text: &str) -> Result<Var, CompileError> {
+    let not_literal = || CompileError::NotALiteral(literal_text.to_string());
+    let parse = parse_program(&format!("return {literal_text};")).map_err(|_| not_literal())?;
+    let [Stmt {
+        node: StmtNode::Return(Some(expr)),
+        ..
+    }] = parse.stmts.as_slice()
+    else {
+        return Err(not_literal());
+    };
+    literal_value(expr).ok_or_else(not_literal)
+}
+
+fn literal_value(expr: &Expr) -> Option<Var> {
+    match expr {
+        Expr::Value(v) => Some(v.clone()),
+        Expr::Unary(UnaryOp::Neg, operand) => match operand.as_ref() {
+            Expr::Value(v) => match v.variant() {
+                Variant::Int(i) => Some(v_int(i.checked_neg()?)),
+                Variant::Float(f) => Some(v_float(-f)),
+                _ => None,
+            },
+            _ => None,
+        },
+        Expr::List(args) => {
+            let mut values = Vec::with_capacity(args.len());
+            for arg in args {
+                let Normal(arg) = arg else {
+                    return None;
+                };
+                values.push(literal_value(arg)?);
+            }
+            Some(v_listv(values))
+        }
+        _ => None,
+    }
+}
+
 // Lex a simpe MOO string literal.  Expectation is:
 //   " and " at beginning and end
 //   \" is "
@@ -844,8 +894,9 @@ pub fn unquote_str(s: &str) -> Result<String, CompileError> {
 
 #[cfg(test)]
 mod tests {
-    use moor_values::var::Error::{E_INVARG, E_PROPNF, E_VARNF};
-    use moor_values::var::{v_err, v_float, v_int, v_obj, v_str};
+    use moor_values::model::DiagnosticSeverity;
+    use moor_values::var::Error::{E_INVARG, E_PERM, E_PROPNF, E_VARNF};
+    use moor_values::var::{v_err, v_float, v_int, v_list, v_obj, v_str};
 
     use crate::ast::Arg::{Normal, Splice};
     use crate::ast::Expr::{Call, Id, Prop, Value, Verb};
@@ -854,7 +905,7 @@ mod tests {
         UnaryOp,
     };
     use crate::labels::Names;
-    use crate::parse::{parse_program, unquote_str};
+    use crate::parse::{parse_literal, parse_program, unquote_str};
     use crate::CompileError;
 
     fn stripped_stmts(statements: &[Stmt]) -> Vec<StmtNode> {
@@ -2018,6 +2069,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_error_position() {
+        let program = "x = 1;\ny = ;\nreturn x;";
+        let Err(e) = parse_program(program) else {
+            panic!("expected parse error");
+        };
+        assert!(matches!(e, CompileError::ParseError { line: 2, .. }));
+        let diagnostic = e.diagnostic();
+        assert_eq!(diagnostic.line, Some(2));
+        assert_eq!(diagnostic.severity, DiagnosticSeverity::Error);
+    }
+
     #[test]
     fn test_unknown_label() {
         let program = r#"
@@ -2035,4 +2098,44 @@ mod tests {
         let parse = parse_program(program);
         assert!(matches!(parse, Err(CompileError::UnknownLoopLabel(_))));
     }
+
+    #[test]
+    fn test_parse_literal() {
+        let value =
+            parse_literal(r#"{1, -2, -3.5, "foo \"bar\"", #-1, E_PERM, {}, {{1e+100}}}"#).unwrap();
+        assert_eq!(
+            value,
+            v_list(&[
+                v_int(1),
+                v_int(-2),
+                v_float(-3.5),
+                v_str("foo \"bar\""),
+                v_obj(-1),
+                v_err(E_PERM),
+                v_list(&[]),
+                v_list(&[v_list(&[v_float(1e100)])]),
+            ])
+        );
+        assert_eq!(parse_literal(&value.to_literal()).unwrap(), value);
+
+        for not_literal in [
+            "1 + 2",
+            "x",
+            "{1, @x}",
+            "length(\"foo\")",
+            "$nothing",
+            "#0.name",
+            "1; return 2",
+            "-\"foo\"",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    parse_literal(not_literal),
+                    Err(CompileError::NotALiteral(_))
+                ),
+                "{not_literal}"
+            );
+        }
+    }
 }
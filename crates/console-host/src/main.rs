@@ -112,7 +112,13 @@ fn perform_auth(
             true,
         ),
     ) {
-        Ok(RpcResult::Success(RpcResponse::LoginResult(Some((
+        Ok(RpcResult::Success(RpcResponse::LoginSucceeded(
+            auth_token,
+            connect_type,
+            player,
+            _metadata,
+        )))
+        | Ok(RpcResult::Success(RpcResponse::LoginResult(Some((
             auth_token,
             connect_type,
             player,
@@ -247,16 +253,28 @@ fn console_loop(
                         .print(
                             (match msg.event() {
                                 moor_values::model::Event::TextNotify(s) => s,
+                                moor_values::model::Event::RichNotify(s, _content_type) => s,
                             })
                             .to_string(),
                         )
                         .unwrap();
                 }
-                Ok(ConnectionEvent::SystemMessage(o, msg)) => {
+                Ok(ConnectionEvent::SystemMessage(o, msg, _content_type)) => {
                     printer
                         .print(format!("System message from {}: {}", o.yellow(), msg.red()))
                         .unwrap();
                 }
+                // Nothing useful to show for presence on a console.
+                Ok(ConnectionEvent::Presence(..)) => {}
+                Ok(ConnectionEvent::EventsLost(count)) => {
+                    printer
+                        .print(
+                            format!("{} messages of output were lost", count)
+                                .red()
+                                .to_string(),
+                        )
+                        .unwrap();
+                }
                 Ok(ConnectionEvent::Disconnect()) => {
                     printer
                         .print("Received disconnect event; Session ending.".to_string())
@@ -299,6 +317,8 @@ fn console_loop(
                     return;
                 }
             }
+            // The console host doesn't make outbound connections.
+            Ok(BroadcastEvent::OpenConnection { .. }) => {}
             Err(e) => {
                 error!("Error receiving broadcast event: {:?}; Session ending.", e);
                 return;
@@ -12,16 +12,212 @@
 // this program. If not, see <https://www.gnu.org/licenses/>.
 //
 
-use std::time::{Duration, SystemTime};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant, SystemTime};
 
 use uuid::Uuid;
 
 use moor_kernel::tasks::sessions::SessionError;
+use moor_kernel::tasks::TaskId;
+use moor_values::model::NarrativeEvent;
 use moor_values::var::Objid;
 use rpc_common::RpcRequestError;
 
 pub const CONNECTION_TIMEOUT_DURATION: Duration = Duration::from_secs(30);
 
+/// When one of a player's clients connected, and when it was last active.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientTimes {
+    pub client_id: Uuid,
+    pub connect_time: SystemTime,
+    pub last_activity: SystemTime,
+}
+
+/// How long a player has been idle, given all their connections: that of their *least* idle one,
+/// since activity on any connection means the player is active. `None` if there are none.
+pub fn idle_seconds(times: &[ClientTimes], now: SystemTime) -> Option<f64> {
+    times
+        .iter()
+        .map(|t| t.last_activity)
+        .max()
+        .map(|t| now.duration_since(t).unwrap_or_default().as_secs_f64())
+}
+
+/// How long a player has been connected, given all their connections: that of their
+/// longest-lived one. `None` if there are none.
+pub fn connected_seconds(times: &[ClientTimes], now: SystemTime) -> Option<f64> {
+    times
+        .iter()
+        .map(|t| t.connect_time)
+        .min()
+        .map(|t| now.duration_since(t).unwrap_or_default().as_secs_f64())
+}
+
+/// The clients, of the given connections, which have been idle for longer than `timeout`. Unlike
+/// `idle_seconds`, this goes client by client: an idle client is dropped even if its player is
+/// busy on another one. Connections for which `exempt` is true are left alone.
+pub fn idle_clients(
+    connections: &[(Objid, Vec<ClientTimes>)],
+    now: SystemTime,
+    timeout: Duration,
+    exempt: impl Fn(Objid) -> bool,
+) -> Vec<(Objid, Uuid)> {
+    let mut idle = vec![];
+    for (connection, times) in connections {
+        if exempt(*connection) {
+            continue;
+        }
+        for t in times {
+            if now.duration_since(t.last_activity).unwrap_or_default() > timeout {
+                idle.push((*connection, t.client_id));
+            }
+        }
+    }
+    idle
+}
+
+/// The tasks each client has started (commands, out of band, ...) which may still be running, so
+/// they can be cancelled when the client goes away rather than being left orphaned.
+#[derive(Debug, Default)]
+pub struct ClientTasks {
+    task_handles: HashMap<Uuid, HashSet<TaskId>>,
+}
+
+impl ClientTasks {
+    /// Note that `client_id` started `task_id`. Whatever the client started earlier that
+    /// `is_running` says has since finished is forgotten at the same time, so a long-lived
+    /// client's set doesn't grow forever.
+    pub fn record(
+        &mut self,
+        client_id: Uuid,
+        task_id: TaskId,
+        is_running: impl Fn(TaskId) -> bool,
+    ) {
+        let tasks = self.task_handles.entry(client_id).or_default();
+        tasks.retain(|t| is_running(*t));
+        tasks.insert(task_id);
+    }
+
+    /// Forget everything about `client_id`, returning the tasks it may still have running.
+    pub fn take(&mut self, client_id: Uuid) -> HashSet<TaskId> {
+        self.task_handles.remove(&client_id).unwrap_or_default()
+    }
+
+    pub fn is_tracking(&self, client_id: Uuid) -> bool {
+        self.task_handles.contains_key(&client_id)
+    }
+}
+
+/// The clients a narrative event for `player` goes to: all of theirs, or, if it's for one
+/// connection in particular, just that one (so long as it is one of theirs).
+pub fn narrative_recipients(
+    connections: &dyn ConnectionsDB,
+    player: Objid,
+    only_client: Option<Uuid>,
+) -> Result<Vec<Uuid>, SessionError> {
+    let client_ids = connections.client_ids_for(player)?;
+    Ok(match only_client {
+        None => client_ids,
+        Some(client_id) => client_ids.into_iter().filter(|c| *c == client_id).collect(),
+    })
+}
+
+/// How many of each player's most recent narrative events `NarrativeHistory` keeps.
+pub const NARRATIVE_HISTORY_LENGTH: usize = 1000;
+
+/// Each player's most recent narrative events, numbered in the order they were published. The
+/// numbers are per player rather than per connection, so a client reconnecting can ask for what it
+/// missed, and put that and what's new in order.
+///
+/// The history itself doesn't survive a restart, but the numbering carries on from the time the
+/// daemon started (in microseconds since the epoch) rather than from 1, so it keeps increasing
+/// across restarts.
+#[derive(Debug)]
+pub struct NarrativeHistory {
+    first_seq: u64,
+    players: HashMap<Objid, (u64, VecDeque<NarrativeEvent>)>,
+}
+
+impl NarrativeHistory {
+    pub fn new(start_time: SystemTime) -> Self {
+        let first_seq = start_time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64
+            + 1;
+        Self {
+            first_seq,
+            players: Default::default(),
+        }
+    }
+
+    /// Number `event` as the next one published to `player`, and keep it.
+    pub fn record(&mut self, player: Objid, event: NarrativeEvent) -> NarrativeEvent {
+        let first_seq = self.first_seq;
+        let (next_seq, events) = self
+            .players
+            .entry(player)
+            .or_insert_with(|| (first_seq, VecDeque::new()));
+        let event = event.with_seq(*next_seq);
+        *next_seq += 1;
+        if events.len() >= NARRATIVE_HISTORY_LENGTH {
+            events.pop_front();
+        }
+        events.push_back(event.clone());
+        event
+    }
+
+    /// The events kept for `player` which came after the one numbered `seq`, oldest first.
+    pub fn since(&self, player: Objid, seq: u64) -> Vec<NarrativeEvent> {
+        let Some((_, events)) = self.players.get(&player) else {
+            return vec![];
+        };
+        events.iter().filter(|e| e.seq() > seq).cloned().collect()
+    }
+}
+
+/// How many tasks (commands, evals) each player may start, as a token bucket per player: up to a
+/// second's worth at once, topped up continuously at `rate` per second. With no rate, anything
+/// goes.
+#[derive(Debug, Default)]
+pub struct TaskRateLimiter {
+    rate: Option<u32>,
+    buckets: HashMap<Objid, (f64, Instant)>,
+}
+
+impl TaskRateLimiter {
+    pub fn new(rate: Option<u32>) -> Self {
+        Self {
+            rate,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Whether `player` may start another task at `now`, taking from their allowance if so.
+    pub fn admit(&mut self, player: Objid, now: Instant) -> bool {
+        let Some(rate) = self.rate else {
+            return true;
+        };
+        let capacity = f64::from(rate);
+        let (tokens, last) = self.buckets.entry(player).or_insert((capacity, now));
+        let refill = now.saturating_duration_since(*last).as_secs_f64() * capacity;
+        *tokens = (*tokens + refill).min(capacity);
+        *last = now;
+        if *tokens < 1.0 {
+            return false;
+        }
+        *tokens -= 1.0;
+        true
+    }
+
+    /// Forget players who've had a second to refill their allowance; they're no different from
+    /// players we've never seen.
+    pub fn prune(&mut self, now: Instant) {
+        self.buckets
+            .retain(|_, (_, last)| now.saturating_duration_since(*last) < Duration::from_secs(1));
+    }
+}
+
 pub trait ConnectionsDB {
     /// Update the connection record for the given connection object to point to the given player.
     /// This is used when a player logs in.
@@ -56,6 +252,9 @@ pub trait ConnectionsDB {
 
     fn client_ids_for(&self, player: Objid) -> Result<Vec<Uuid>, SessionError>;
 
+    /// The connect and activity times of each of the player's clients.
+    fn client_times_for(&self, player: Objid) -> Result<Vec<ClientTimes>, SessionError>;
+
     /// Return all connection objects (player or not)
     fn connections(&self) -> Vec<Objid>;
 
@@ -65,3 +264,148 @@ pub trait ConnectionsDB {
     /// Remove the given client from the connection database.
     fn remove_client_connection(&self, client_id: Uuid) -> Result<(), eyre::Error>;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant, SystemTime};
+
+    use uuid::Uuid;
+
+    use moor_values::model::NarrativeEvent;
+    use moor_values::var::Objid;
+
+    use crate::connections::{
+        connected_seconds, idle_clients, idle_seconds, ClientTasks, ClientTimes, NarrativeHistory,
+        TaskRateLimiter, NARRATIVE_HISTORY_LENGTH,
+    };
+
+    #[test]
+    fn test_two_connections_of_differing_activity() {
+        let now = SystemTime::now();
+        let ago = |secs| now - Duration::from_secs(secs);
+        // An old connection the player has left alone, and a newer one they're typing into.
+        let times = [
+            ClientTimes {
+                client_id: Uuid::new_v4(),
+                connect_time: ago(3600),
+                last_activity: ago(1800),
+            },
+            ClientTimes {
+                client_id: Uuid::new_v4(),
+                connect_time: ago(60),
+                last_activity: ago(5),
+            },
+        ];
+        assert_eq!(idle_seconds(&times, now), Some(5.0));
+        assert_eq!(connected_seconds(&times, now), Some(3600.0));
+
+        // Order doesn't matter.
+        let reversed = [times[1], times[0]];
+        assert_eq!(idle_seconds(&reversed, now), Some(5.0));
+        assert_eq!(connected_seconds(&reversed, now), Some(3600.0));
+
+        assert_eq!(idle_seconds(&[], now), None);
+        assert_eq!(connected_seconds(&[], now), None);
+    }
+
+    #[test]
+    fn test_idle_clients_are_reaped() {
+        let now = SystemTime::now();
+        let ago = |secs| now - Duration::from_secs(secs);
+        let times = |last_activity| ClientTimes {
+            client_id: Uuid::new_v4(),
+            connect_time: ago(3600),
+            last_activity,
+        };
+        let (player, bot) = (Objid(1), Objid(2));
+        let (idle, busy) = (times(ago(600)), times(ago(5)));
+        let connections = [(player, vec![idle, busy]), (bot, vec![times(ago(3000))])];
+        let timeout = Duration::from_secs(300);
+
+        // Just the idle client goes; the player's busy one stays.
+        assert_eq!(
+            idle_clients(&connections, now, timeout, |c| c == bot),
+            vec![(player, idle.client_id)]
+        );
+
+        // Unless exempt, the bot would go too.
+        assert_eq!(idle_clients(&connections, now, timeout, |_| false).len(), 2);
+    }
+
+    #[test]
+    fn test_detach_mid_command_leaves_no_orphans() {
+        let mut client_tasks = ClientTasks::default();
+        let client = Uuid::new_v4();
+        let other_client = Uuid::new_v4();
+
+        // The client has finished one command, and is midway through another, when it detaches.
+        client_tasks.record(client, 1, |_| true);
+        client_tasks.record(client, 2, |t| t != 1);
+        client_tasks.record(other_client, 3, |_| true);
+
+        let to_abort = client_tasks.take(client);
+        assert_eq!(to_abort.into_iter().collect::<Vec<_>>(), vec![2]);
+        assert!(!client_tasks.is_tracking(client));
+        assert!(client_tasks.take(client).is_empty());
+
+        // Other clients are left alone.
+        assert!(client_tasks.is_tracking(other_client));
+    }
+
+    #[test]
+    fn test_narrative_history_is_bounded_and_per_player() {
+        let start_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1);
+        let mut history = NarrativeHistory::new(start_time);
+        let (player, other_player) = (Objid(1), Objid(2));
+
+        let published: Vec<_> = (0..NARRATIVE_HISTORY_LENGTH + 10)
+            .map(|i| history.record(player, NarrativeEvent::notify_text(player, i.to_string())))
+            .collect();
+        history.record(
+            other_player,
+            NarrativeEvent::notify_text(player, "x".into()),
+        );
+
+        // Numbering starts from the daemon's start time, so it carries on past a restart.
+        assert_eq!(published[0].seq(), 1_000_001);
+        assert_eq!(history.since(other_player, 0)[0].seq(), 1_000_001);
+
+        // Only the most recent are kept.
+        assert_eq!(history.since(player, 0), published[10..]);
+        let last_seen = published[published.len() - 3].seq();
+        assert_eq!(
+            history.since(player, last_seen),
+            published[published.len() - 2..]
+        );
+        assert!(history.since(Objid(3), 0).is_empty());
+    }
+
+    #[test]
+    fn test_hammering_player_is_throttled() {
+        let mut limiter = TaskRateLimiter::new(Some(5));
+        let (player, other_player) = (Objid(1), Objid(2));
+        let now = Instant::now();
+
+        // A burst of a second's worth goes through, and then no more.
+        let admitted = (0..100).filter(|_| limiter.admit(player, now)).count();
+        assert_eq!(admitted, 5);
+
+        // Someone else is unaffected.
+        assert!(limiter.admit(other_player, now));
+
+        // The allowance comes back over time.
+        let later = now + Duration::from_millis(200);
+        assert!(limiter.admit(player, later));
+        assert!(!limiter.admit(player, later));
+
+        // Once they've let up for long enough to be back to a full allowance, they're forgotten.
+        limiter.prune(now + Duration::from_secs(1));
+        assert_eq!(limiter.buckets.len(), 1);
+        limiter.prune(later + Duration::from_secs(1));
+        assert!(limiter.buckets.is_empty());
+
+        // And without a limit, there's no throttling at all.
+        let mut unlimited = TaskRateLimiter::new(None);
+        assert!((0..1000).all(|_| unlimited.admit(player, now)));
+    }
+}
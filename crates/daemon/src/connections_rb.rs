@@ -32,7 +32,7 @@ use moor_values::AsByteBuffer;
 use relbox::{relation_info_for, RelBox, RelationId, RelationInfo, Transaction};
 use rpc_common::RpcRequestError;
 
-use crate::connections::{ConnectionsDB, CONNECTION_TIMEOUT_DURATION};
+use crate::connections::{ClientTimes, ConnectionsDB, CONNECTION_TIMEOUT_DURATION};
 
 #[repr(usize)]
 // Don't warn about same-prefix, "I did that on purpose"
@@ -133,7 +133,8 @@ impl ConnectionsRb {
                 );
             }
         }
-        times.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap());
+        // Oldest first, so that the most recent is the one popped off the end.
+        times.sort_by(|(_, a), (_, b)| a.cmp(b));
         Ok(times)
     }
 }
@@ -361,6 +362,39 @@ impl ConnectionsDB for ConnectionsRb {
         Ok(seconds)
     }
 
+    fn client_times_for(&self, player: Objid) -> Result<Vec<ClientTimes>, SessionError> {
+        let tx = self.tb.clone().start_tx();
+        let Ok(clients) = tx
+            .relation(RelationId(ConnectionRelation::ClientConnection as usize))
+            .seek_by_codomain(SliceRef::from_byte_source(
+                player.as_bytes().expect("Invalid player object"),
+            ))
+        else {
+            return Ok(vec![]);
+        };
+
+        let mut times = Vec::new();
+        for client in clients {
+            let connect_time = tx
+                .relation(RelationId(ConnectionRelation::ClientConnectTime as usize))
+                .seek_unique_by_domain(client.domain());
+            let last_activity = tx
+                .relation(RelationId(ConnectionRelation::ClientActivity as usize))
+                .seek_unique_by_domain(client.domain());
+            let (Ok(connect_time), Ok(last_activity)) = (connect_time, last_activity) else {
+                warn!(client = ?client.domain(), ?player, "Missing connect or activity time for client");
+                continue;
+            };
+            times.push(ClientTimes {
+                client_id: Uuid::from_slice(client.domain().as_slice()).expect("Invalid UUID"),
+                connect_time: bytes_as_time(connect_time.codomain()),
+                last_activity: bytes_as_time(last_activity.codomain()),
+            });
+        }
+        tx.commit().expect("Unable to commit transaction");
+        Ok(times)
+    }
+
     fn client_ids_for(&self, player: Objid) -> Result<Vec<Uuid>, SessionError> {
         let tx = self.tb.clone().start_tx();
         let Ok(clients) = tx
@@ -0,0 +1,467 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! An implementation of the connections db that keeps its state in Redis, so that several daemons
+//! can share a single view of who is connected.
+//!
+//! Layout, under a configurable key prefix:
+//!     * `<prefix>:client:<client id>` -- hash of `connection`, `name`, and the `activity`,
+//!       `connect_time` and `ping_time` timestamps (milliseconds since the epoch)
+//!     * `<prefix>:connection:<objid>` -- set of client ids attached to the connection/player object
+//!     * `<prefix>:clients` -- set of all client ids, for full scans
+//!     * `<prefix>:connection_seq` -- counter for allocating (negative) connection objects
+
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use eyre::Error;
+use redis::{Commands, Connection};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use moor_kernel::tasks::sessions::SessionError;
+use moor_values::var::Objid;
+use rpc_common::RpcRequestError;
+
+use crate::connections::{ClientTimes, ConnectionsDB, CONNECTION_TIMEOUT_DURATION};
+
+pub struct ConnectionsRedis {
+    connection: Mutex<Connection>,
+    prefix: String,
+}
+
+impl ConnectionsRedis {
+    pub fn new(url: &str, prefix: &str) -> Result<Self, Error> {
+        let client = redis::Client::open(url)?;
+        let connection = client.get_connection()?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+            prefix: prefix.to_string(),
+        })
+    }
+
+    fn client_key(&self, client_id: Uuid) -> String {
+        format!("{}:client:{client_id}", self.prefix)
+    }
+
+    fn connection_key(&self, connection: Objid) -> String {
+        format!("{}:connection:{}", self.prefix, connection.0)
+    }
+
+    fn clients_key(&self) -> String {
+        format!("{}:clients", self.prefix)
+    }
+
+    fn sequence_key(&self) -> String {
+        format!("{}:connection_seq", self.prefix)
+    }
+
+    fn clients_of(&self, con: &mut Connection, connection: Objid) -> redis::RedisResult<Vec<Uuid>> {
+        let client_ids: Vec<String> = con.smembers(self.connection_key(connection))?;
+        Ok(client_ids
+            .iter()
+            .filter_map(|c| Uuid::parse_str(c).ok())
+            .collect())
+    }
+
+    /// The given timestamp field for each of the connection's clients.
+    fn client_times(
+        &self,
+        connection: Objid,
+        field: &str,
+    ) -> Result<Vec<(Uuid, SystemTime)>, SessionError> {
+        let mut con = self.connection.lock().unwrap();
+        let fetch = |con: &mut Connection| -> redis::RedisResult<Vec<(Uuid, SystemTime)>> {
+            let mut times = vec![];
+            for client_id in self.clients_of(con, connection)? {
+                let millis: Option<u64> = con.hget(self.client_key(client_id), field)?;
+                match millis {
+                    Some(millis) => times.push((client_id, millis_as_time(millis))),
+                    None => warn!(?client_id, ?connection, field, "Missing time for client"),
+                }
+            }
+            Ok(times)
+        };
+        fetch(&mut *con).map_err(|e| {
+            error!(error = ?e, ?connection, "Unable to read client times from Redis");
+            SessionError::NoConnectionForPlayer(connection)
+        })
+    }
+
+    /// Stamp one of a client's times, unless the client has been removed in the meantime; a bare
+    /// HSET would leave a partial hash behind for it.
+    fn touch_client(&self, client_id: Uuid, field: &str) -> redis::RedisResult<()> {
+        let mut con = self.connection.lock().unwrap();
+        let client_key = self.client_key(client_id);
+        redis::transaction(&mut *con, &[&client_key], |con, pipe| {
+            if !con.exists::<_, bool>(&client_key)? {
+                return Ok(Some(()));
+            }
+            pipe.hset(&client_key, field, now_as_millis())
+                .ignore()
+                .query(con)
+        })
+    }
+
+    /// Remove a client and its entries in the sets. If `expired_before` is given, only if the
+    /// client hasn't pinged since then, as of the same transaction.
+    fn remove_client(
+        &self,
+        client_id: Uuid,
+        expired_before: Option<SystemTime>,
+    ) -> redis::RedisResult<()> {
+        let mut con = self.connection.lock().unwrap();
+        let client_key = self.client_key(client_id);
+        redis::transaction(&mut *con, &[&client_key], |con, pipe| {
+            let (connection, ping_time): (Option<i64>, Option<u64>) =
+                con.hget(&client_key, &["connection", "ping_time"])?;
+            if let Some(threshold) = expired_before {
+                // Anything without a ping time is a leftover, and can go too.
+                if ping_time.is_some_and(|t| millis_as_time(t) >= threshold) {
+                    return Ok(Some(()));
+                }
+            }
+            pipe.del(&client_key)
+                .ignore()
+                .srem(self.clients_key(), client_id.to_string())
+                .ignore();
+            if let Some(connection) = connection {
+                pipe.srem(
+                    self.connection_key(Objid(connection)),
+                    client_id.to_string(),
+                )
+                .ignore();
+            }
+            pipe.query(con)
+        })
+    }
+
+    /// The client attached to the connection with the most recent activity.
+    fn most_recent_client(&self, connection: Objid) -> Result<(Uuid, SystemTime), SessionError> {
+        self.client_times(connection, "activity")?
+            .into_iter()
+            .max_by_key(|(_, time)| *time)
+            .ok_or(SessionError::NoConnectionForPlayer(connection))
+    }
+}
+
+fn now_as_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+fn millis_as_time(millis: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+impl ConnectionsDB for ConnectionsRedis {
+    fn update_client_connection(
+        &self,
+        from_connection: Objid,
+        to_player: Objid,
+    ) -> Result<(), Error> {
+        let mut con = self.connection.lock().unwrap();
+        // Watching the connection's set means we start over if another daemon attaches or removes
+        // one of its clients between our read and our writes.
+        let from_key = self.connection_key(from_connection);
+        let moved = redis::transaction(&mut *con, &[&from_key], |con, pipe| {
+            let client_ids = self.clients_of(con, from_connection)?;
+            if client_ids.is_empty() {
+                return Ok(Some(false));
+            }
+            for client_id in client_ids {
+                let client_id_str = client_id.to_string();
+                pipe.hset(self.client_key(client_id), "connection", to_player.0)
+                    .ignore()
+                    .srem(&from_key, &client_id_str)
+                    .ignore()
+                    .sadd(self.connection_key(to_player), &client_id_str)
+                    .ignore();
+            }
+            Ok(pipe.query::<Option<()>>(con)?.map(|_| true))
+        })?;
+        if !moved {
+            error!(?from_connection, ?to_player, "No client ids for connection");
+            return Err(Error::msg("No client ids for connection"));
+        }
+        Ok(())
+    }
+
+    fn new_connection(
+        &self,
+        client_id: Uuid,
+        hostname: String,
+        player: Option<Objid>,
+    ) -> Result<Objid, RpcRequestError> {
+        let mut con = self.connection.lock().unwrap();
+        let redis_err = |e: redis::RedisError| RpcRequestError::InternalError(e.to_string());
+        let connection_oid = match player {
+            None => {
+                // Connection objects grow downwards from -4; the first INCR gives us 1.
+                let connection_id: i64 = con.incr(self.sequence_key(), 1).map_err(redis_err)?;
+                Objid(-3 - connection_id)
+            }
+            Some(player) => player,
+        };
+
+        let now = now_as_millis();
+        let client_key = self.client_key(client_id);
+        redis::pipe()
+            .atomic()
+            .hset(&client_key, "connection", connection_oid.0)
+            .ignore()
+            .hset(&client_key, "name", &hostname)
+            .ignore()
+            .hset(&client_key, "activity", now)
+            .ignore()
+            .hset(&client_key, "connect_time", now)
+            .ignore()
+            .hset(&client_key, "ping_time", now)
+            .ignore()
+            .sadd(self.connection_key(connection_oid), client_id.to_string())
+            .ignore()
+            .sadd(self.clients_key(), client_id.to_string())
+            .ignore()
+            .query::<()>(&mut *con)
+            .map_err(redis_err)?;
+
+        Ok(connection_oid)
+    }
+
+    fn record_client_activity(&self, client_id: Uuid, _connobj: Objid) -> Result<(), Error> {
+        self.touch_client(client_id, "activity")?;
+        Ok(())
+    }
+
+    fn notify_is_alive(&self, client_id: Uuid, _connection: Objid) -> Result<(), Error> {
+        self.touch_client(client_id, "ping_time")?;
+        Ok(())
+    }
+
+    fn ping_check(&self) {
+        let timeout_threshold = SystemTime::now() - CONNECTION_TIMEOUT_DURATION;
+
+        let expired = {
+            let mut con = self.connection.lock().unwrap();
+            let client_ids: Vec<String> = match con.smembers(self.clients_key()) {
+                Ok(client_ids) => client_ids,
+                Err(e) => {
+                    error!(error = ?e, "Unable to scan clients in Redis");
+                    return;
+                }
+            };
+            let mut expired = vec![];
+            for client_id in client_ids.iter().filter_map(|c| Uuid::parse_str(c).ok()) {
+                let ping_time: Option<u64> = con
+                    .hget(self.client_key(client_id), "ping_time")
+                    .unwrap_or(None);
+                // Anything without a ping time is a leftover, and can go too.
+                if ping_time.map_or(true, |t| millis_as_time(t) < timeout_threshold) {
+                    expired.push(client_id);
+                }
+            }
+            expired
+        };
+
+        // A client may have pinged since the scan, so the removal checks again.
+        for client_id in expired {
+            if let Err(e) = self.remove_client(client_id, Some(timeout_threshold)) {
+                error!(error = ?e, ?client_id, "Unable to remove expired client");
+            }
+        }
+    }
+
+    fn last_activity_for(&self, connection: Objid) -> Result<SystemTime, SessionError> {
+        Ok(self.most_recent_client(connection)?.1)
+    }
+
+    fn connection_name_for(&self, connection: Objid) -> Result<String, SessionError> {
+        let (client_id, _) = self.most_recent_client(connection)?;
+        let mut con = self.connection.lock().unwrap();
+        let name: Option<String> = con.hget(self.client_key(client_id), "name").map_err(|e| {
+            error!(error = ?e, ?client_id, "Unable to read client name from Redis");
+            SessionError::NoConnectionForPlayer(connection)
+        })?;
+        name.ok_or(SessionError::NoConnectionForPlayer(connection))
+    }
+
+    fn connected_seconds_for(&self, player: Objid) -> Result<f64, SessionError> {
+        // The earliest connection time for the player is how long they've been connected.
+        let earliest = self
+            .client_times(player, "connect_time")?
+            .into_iter()
+            .map(|(_, time)| time)
+            .min()
+            .ok_or(SessionError::NoConnectionForPlayer(player))?;
+        Ok(earliest.elapsed().unwrap_or_default().as_secs_f64())
+    }
+
+    fn client_times_for(&self, player: Objid) -> Result<Vec<ClientTimes>, SessionError> {
+        let activity = self.client_times(player, "activity")?;
+        let times = self
+            .client_times(player, "connect_time")?
+            .into_iter()
+            .filter_map(|(client_id, connect_time)| {
+                let (_, last_activity) = activity.iter().find(|(c, _)| *c == client_id)?;
+                Some(ClientTimes {
+                    client_id,
+                    connect_time,
+                    last_activity: *last_activity,
+                })
+            })
+            .collect();
+        Ok(times)
+    }
+
+    fn client_ids_for(&self, player: Objid) -> Result<Vec<Uuid>, SessionError> {
+        let mut con = self.connection.lock().unwrap();
+        self.clients_of(&mut con, player).map_err(|e| {
+            error!(error = ?e, ?player, "Unable to read clients from Redis");
+            SessionError::NoConnectionForPlayer(player)
+        })
+    }
+
+    fn connections(&self) -> Vec<Objid> {
+        let mut con = self.connection.lock().unwrap();
+        let scan = |con: &mut Connection| -> redis::RedisResult<Vec<Objid>> {
+            let client_ids: Vec<String> = con.smembers(self.clients_key())?;
+            let mut connections = vec![];
+            for client_id in client_ids.iter().filter_map(|c| Uuid::parse_str(c).ok()) {
+                let connection: Option<i64> = con.hget(self.client_key(client_id), "connection")?;
+                if let Some(connection) = connection {
+                    connections.push(Objid(connection));
+                }
+            }
+            connections.sort();
+            connections.dedup();
+            Ok(connections)
+        };
+        scan(&mut *con).unwrap_or_else(|e| {
+            error!(error = ?e, "Unable to scan connections in Redis");
+            vec![]
+        })
+    }
+
+    fn connection_object_for_client(&self, client_id: Uuid) -> Option<Objid> {
+        let mut con = self.connection.lock().unwrap();
+        let connection: Option<i64> = con
+            .hget(self.client_key(client_id), "connection")
+            .unwrap_or_else(|e| {
+                error!(error = ?e, ?client_id, "Unable to read client connection from Redis");
+                None
+            });
+        connection.map(Objid)
+    }
+
+    fn remove_client_connection(&self, client_id: Uuid) -> Result<(), Error> {
+        self.remove_client(client_id, None)?;
+        Ok(())
+    }
+}
+
+/// These need a Redis server, so are ignored by default; run them with
+/// `MOOR_TEST_REDIS_URL=redis://... cargo test --features redis -- --ignored`. Each test uses its own
+/// key prefix.
+#[cfg(test)]
+mod tests {
+    use moor_values::var::Objid;
+
+    use crate::connections::ConnectionsDB;
+    use crate::connections_redis::ConnectionsRedis;
+
+    fn test_db() -> ConnectionsRedis {
+        let url = std::env::var("MOOR_TEST_REDIS_URL")
+            .expect("MOOR_TEST_REDIS_URL must be set to run the Redis tests");
+        let prefix = format!("moor-test-{}", uuid::Uuid::new_v4());
+        ConnectionsRedis::new(&url, &prefix).expect("Unable to connect to Redis")
+    }
+
+    /// Attach, log in, see activity, and detach a client.
+    #[test]
+    #[ignore = "needs a Redis server at MOOR_TEST_REDIS_URL"]
+    fn test_single_connection() {
+        let db = test_db();
+        let client_id = uuid::Uuid::new_v4();
+        let oid = db
+            .new_connection(client_id, "localhost".to_string(), None)
+            .unwrap();
+        assert!(oid.0 <= -4);
+        assert_eq!(db.client_ids_for(oid).unwrap(), vec![client_id]);
+        assert_eq!(db.connection_object_for_client(client_id), Some(oid));
+
+        db.record_client_activity(client_id, oid).unwrap();
+        db.notify_is_alive(client_id, oid).unwrap();
+        let last_activity = db.last_activity_for(oid).unwrap();
+        assert!(last_activity.elapsed().unwrap().as_secs_f64() < 1.0);
+        assert_eq!(db.connection_name_for(oid).unwrap(), "localhost");
+
+        db.update_client_connection(oid, Objid(1)).unwrap();
+        assert!(db.client_ids_for(oid).unwrap().is_empty());
+        assert_eq!(db.client_ids_for(Objid(1)).unwrap(), vec![client_id]);
+        assert_eq!(db.connections(), vec![Objid(1)]);
+
+        db.remove_client_connection(client_id).unwrap();
+        assert!(db.client_ids_for(Objid(1)).unwrap().is_empty());
+        assert_eq!(db.connection_object_for_client(client_id), None);
+        assert!(db.connections().is_empty());
+    }
+
+    /// Test that a given player can have multiple clients connected to it.
+    #[test]
+    #[ignore = "needs a Redis server at MOOR_TEST_REDIS_URL"]
+    fn test_multiple_connections() {
+        let db = test_db();
+        let client_id1 = uuid::Uuid::new_v4();
+        let client_id2 = uuid::Uuid::new_v4();
+        let con_oid1 = db
+            .new_connection(client_id1, "localhost".to_string(), None)
+            .unwrap();
+        let con_oid2 = db
+            .new_connection(client_id2, "localhost".to_string(), None)
+            .unwrap();
+        assert_ne!(con_oid1, con_oid2);
+        db.update_client_connection(con_oid1, Objid(1)).unwrap();
+        db.update_client_connection(con_oid2, Objid(1)).unwrap();
+        let client_ids = db.client_ids_for(Objid(1)).unwrap();
+        assert_eq!(client_ids.len(), 2);
+        assert!(client_ids.contains(&client_id1));
+        assert!(client_ids.contains(&client_id2));
+
+        db.record_client_activity(client_id1, Objid(1)).unwrap();
+        let times = db.client_times_for(Objid(1)).unwrap();
+        assert_eq!(times.len(), 2);
+        let active = times.iter().find(|t| t.client_id == client_id1).unwrap();
+        let other = times.iter().find(|t| t.client_id == client_id2).unwrap();
+        assert!(active.last_activity >= other.last_activity);
+
+        db.remove_client_connection(client_id1).unwrap();
+        assert_eq!(db.client_ids_for(Objid(1)).unwrap(), vec![client_id2]);
+    }
+
+    // Validate that ping check leaves live clients alone.
+    #[test]
+    #[ignore = "needs a Redis server at MOOR_TEST_REDIS_URL"]
+    fn ping_test() {
+        let db = test_db();
+        let client_id1 = uuid::Uuid::new_v4();
+        let ob = db
+            .new_connection(client_id1, "localhost".to_string(), None)
+            .unwrap();
+        db.ping_check();
+        assert_eq!(db.connections(), vec![ob]);
+        assert_eq!(db.connection_object_for_client(client_id1), Some(ob));
+    }
+}
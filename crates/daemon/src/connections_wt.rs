@@ -35,7 +35,7 @@ use moor_values::var::Objid;
 use moor_values::{AsByteBuffer, DecodingError, EncodingError};
 use rpc_common::RpcRequestError;
 
-use crate::connections::{ConnectionsDB, CONNECTION_TIMEOUT_DURATION};
+use crate::connections::{ClientTimes, ConnectionsDB, CONNECTION_TIMEOUT_DURATION};
 use crate::connections_wt::ConnectionRelation::{
     ClientActivity, ClientConnectTime, ClientConnection, ClientName, ClientPingTime,
 };
@@ -142,7 +142,8 @@ impl ConnectionsWT {
                 );
             }
         }
-        times.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap());
+        // Oldest first, so that the most recent is the one popped off the end.
+        times.sort_by(|(_, a), (_, b)| a.cmp(b));
         Ok(times)
     }
 }
@@ -444,6 +445,39 @@ impl ConnectionsDB for ConnectionsWT {
         })
     }
 
+    fn client_times_for(&self, player: Objid) -> Result<Vec<ClientTimes>, SessionError> {
+        retry_tx_action(&self.db, |tx| {
+            let clients =
+                tx.seek_by_codomain::<ClientId, Objid, ClientSet>(ClientConnection, player)?;
+            let mut times = vec![];
+            for client in clients.iter() {
+                let connect_time =
+                    tx.seek_unique_by_domain::<_, SystemTimeHolder>(ClientConnectTime, client)?;
+                let last_activity =
+                    tx.seek_unique_by_domain::<_, SystemTimeHolder>(ClientActivity, client)?;
+                let (Some(connect_time), Some(last_activity)) = (connect_time, last_activity)
+                else {
+                    warn!(
+                        ?client,
+                        ?player,
+                        "Missing connect or activity time for client"
+                    );
+                    continue;
+                };
+                times.push(ClientTimes {
+                    client_id: client.0,
+                    connect_time: connect_time.0,
+                    last_activity: last_activity.0,
+                });
+            }
+            Ok(times)
+        })
+        .map_err(|e| match e {
+            RelationalError::NotFound => SessionError::NoConnectionForPlayer(player),
+            _ => panic!("Unexpected error: {:?}", e),
+        })
+    }
+
     fn connections(&self) -> Vec<Objid> {
         // Full scan from ClientConnection relation to get all connections, and dump them into a
         // hashset (to remove dupes) and return as a vector.
@@ -486,7 +520,7 @@ mod tests {
 
     use moor_values::var::Objid;
 
-    use crate::connections::ConnectionsDB;
+    use crate::connections::{narrative_recipients, ConnectionsDB};
     use crate::connections_wt::ConnectionsWT;
 
     /// Simple test of:
@@ -583,6 +617,10 @@ mod tests {
                     .unwrap()
                     .as_secs_f64();
                 assert!(last_activity < 1.0);
+                let times = db.client_times_for(new_conn).unwrap();
+                assert_eq!(times.len(), 2);
+                assert!(times.iter().any(|t| t.client_id == client_id1));
+                assert!(times.iter().any(|t| t.client_id == client_id2));
                 db.remove_client_connection(client_id1).unwrap();
                 let client_ids = db.client_ids_for(new_conn).unwrap();
                 assert_eq!(client_ids.len(), 1);
@@ -611,4 +649,37 @@ mod tests {
         assert_eq!(client_ids.len(), 1);
         assert_eq!(client_ids[0], client_id1);
     }
+
+    /// A player with two connections; an event for one of them goes only there.
+    #[test]
+    fn test_targeted_narrative_event() {
+        let db = ConnectionsWT::new(None);
+        let player = Objid(1);
+        let client_id1 = uuid::Uuid::new_v4();
+        let client_id2 = uuid::Uuid::new_v4();
+        for client_id in [client_id1, client_id2] {
+            let con_oid = db
+                .new_connection(client_id, "localhost".to_string(), None)
+                .unwrap();
+            db.update_client_connection(con_oid, player).unwrap();
+        }
+
+        let mut everyone = narrative_recipients(&db, player, None).unwrap();
+        everyone.sort();
+        let mut both = vec![client_id1, client_id2];
+        both.sort();
+        assert_eq!(everyone, both);
+
+        assert_eq!(
+            narrative_recipients(&db, player, Some(client_id2)).unwrap(),
+            vec![client_id2]
+        );
+
+        // Not one of the player's connections, so nobody gets it.
+        assert!(
+            narrative_recipients(&db, player, Some(uuid::Uuid::new_v4()))
+                .unwrap()
+                .is_empty()
+        );
+    }
 }
@@ -0,0 +1,286 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! The daemon's end of the event channel, which hosts subscribe to either by client id (for
+//! events meant for one connection) or by the broadcast topic (for all of them).
+//!
+//! A plain PUB socket silently drops whatever a slow subscriber can't keep up with, which would
+//! leave a lagging client missing output with no way to know. So this is an XPUB socket set not to
+//! drop, and sent to without blocking: when a client is at its high-water mark, the send fails
+//! and we drop the event ourselves, count it, and tell the client how much it lost once it's
+//! caught up again.
+//!
+//! Events for a client are framed with a leading byte saying whether they're compressed, which
+//! large ones are if a threshold is configured; but only for clients whose host has said it speaks
+//! a protocol version which expects that. Broadcasts are small, and go out as they are.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use tracing::warn;
+use uuid::Uuid;
+use zmq::{Socket, SocketType};
+
+use rpc_common::{
+    frame_connection_event, ConnectionEvent, BROADCAST_TOPIC, EVENT_FRAMING_PROTOCOL_VERSION,
+};
+
+/// The high-water mark to use if the config doesn't give one.
+pub const DEFAULT_NARRATIVE_HWM: i32 = 10_000;
+
+pub struct EventPublisher {
+    socket: Socket,
+    /// Clients which have had events dropped that they haven't been told about yet, and how many.
+    lost: HashMap<Uuid, u64>,
+    /// How many events have been dropped altogether.
+    dropped: u64,
+    /// Events for a client at least this many bytes long are compressed. None to never compress.
+    compress_threshold: Option<usize>,
+    /// The protocol version each client's host last said it speaks.
+    client_versions: HashMap<Uuid, u16>,
+}
+
+impl EventPublisher {
+    /// Bind to `endpoint`, queueing at most `hwm` events for any one subscriber.
+    pub fn bind(
+        zmq_context: &zmq::Context,
+        endpoint: &str,
+        hwm: i32,
+        compress_threshold: Option<usize>,
+    ) -> Result<Self, zmq::Error> {
+        let socket = zmq_context.socket(SocketType::XPUB)?;
+        socket.set_xpub_nodrop(true)?;
+        socket.set_sndhwm(hwm)?;
+        socket.bind(endpoint)?;
+        Ok(Self {
+            socket,
+            lost: HashMap::new(),
+            dropped: 0,
+            compress_threshold,
+            client_versions: HashMap::new(),
+        })
+    }
+
+    /// Note the protocol version the host of `client_id` speaks, which decides how its events are
+    /// sent. Until we've heard from it, a client gets them the way the oldest hosts expect.
+    pub fn set_client_version(&mut self, client_id: Uuid, version: u16) {
+        self.client_versions.insert(client_id, version);
+    }
+
+    /// Send an (encoded) event to one client. If the client isn't keeping up the event is
+    /// dropped rather than sent, and the next one to get through is preceded by an `EventsLost`
+    /// (for hosts new enough to know what that is).
+    pub fn send_to(&mut self, client_id: Uuid, event_bytes: &[u8]) -> Result<(), zmq::Error> {
+        self.drain_subscriptions()?;
+        let framed = self
+            .client_versions
+            .get(&client_id)
+            .is_some_and(|version| *version >= EVENT_FRAMING_PROTOCOL_VERSION);
+        if !framed {
+            self.lost.remove(&client_id);
+        } else if let Some(&count) = self.lost.get(&client_id) {
+            let marker = bincode::encode_to_vec(
+                ConnectionEvent::EventsLost(count),
+                bincode::config::standard(),
+            )
+            .expect("Unable to serialize lost events marker");
+            let marker = frame_connection_event(&marker, None);
+            if !self.try_send(client_id.as_bytes(), &marker)? {
+                self.record_drop(client_id);
+                return Ok(());
+            }
+            self.lost.remove(&client_id);
+        }
+        let frame = if framed {
+            Cow::Owned(frame_connection_event(event_bytes, self.compress_threshold))
+        } else {
+            Cow::Borrowed(event_bytes)
+        };
+        if !self.try_send(client_id.as_bytes(), &frame)? {
+            self.record_drop(client_id);
+        }
+        Ok(())
+    }
+
+    /// Send an (encoded) event to every host. Returns false if it couldn't go out because some
+    /// subscriber is at its high-water mark.
+    pub fn broadcast(&mut self, event_bytes: &[u8]) -> Result<bool, zmq::Error> {
+        self.drain_subscriptions()?;
+        let sent = self.try_send(BROADCAST_TOPIC, event_bytes)?;
+        if !sent {
+            self.dropped += 1;
+            warn!(
+                dropped = self.dropped,
+                "A subscriber isn't keeping up; dropped broadcast event"
+            );
+        }
+        Ok(sent)
+    }
+
+    /// Stop keeping track of lost events for a client which has gone away.
+    pub fn forget(&mut self, client_id: Uuid) {
+        self.lost.remove(&client_id);
+        self.client_versions.remove(&client_id);
+    }
+
+    /// How many events have been dropped since startup.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn try_send(&self, topic: &[u8], event_bytes: &[u8]) -> Result<bool, zmq::Error> {
+        match self
+            .socket
+            .send_multipart([topic, event_bytes], zmq::DONTWAIT)
+        {
+            Ok(()) => Ok(true),
+            Err(zmq::Error::EAGAIN) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn record_drop(&mut self, client_id: Uuid) {
+        self.dropped += 1;
+        let lost = self.lost.entry(client_id).or_default();
+        *lost += 1;
+        if *lost == 1 {
+            warn!(
+                ?client_id,
+                dropped = self.dropped,
+                "Client isn't keeping up with its events; dropping them"
+            );
+        }
+    }
+
+    /// An XPUB socket hears about every (un)subscription. We don't need them, but they'd pile up
+    /// if never read, so every send clears out what's arrived since the last.
+    fn drain_subscriptions(&self) -> Result<(), zmq::Error> {
+        loop {
+            match self.socket.recv_bytes(zmq::DONTWAIT) {
+                Ok(_) => continue,
+                Err(zmq::Error::EAGAIN) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use uuid::Uuid;
+
+    use rpc_common::{
+        decode_connection_event, ConnectionEvent, EVENT_FRAMING_PROTOCOL_VERSION,
+        RPC_PROTOCOL_VERSION,
+    };
+
+    use crate::event_publisher::EventPublisher;
+
+    #[test]
+    fn test_backed_up_client_is_told_what_it_lost() {
+        let zmq_context = zmq::Context::new();
+        let endpoint = "inproc://test-event-publisher";
+        let mut publisher = EventPublisher::bind(&zmq_context, endpoint, 1, None).unwrap();
+        let client_id = Uuid::new_v4();
+        publisher.set_client_version(client_id, RPC_PROTOCOL_VERSION);
+        let sub = zmq_context.socket(zmq::SUB).unwrap();
+        sub.set_rcvhwm(1).unwrap();
+        sub.connect(endpoint).unwrap();
+        sub.set_subscribe(client_id.as_bytes()).unwrap();
+        // Wait for the subscription to land.
+        publisher.socket.recv_bytes(0).unwrap();
+
+        // The client doesn't read any of these, so most can't be delivered.
+        let sent = 100;
+        for _ in 0..sent {
+            publisher.send_to(client_id, b"event").unwrap();
+        }
+        assert!(publisher.dropped() > 0);
+
+        // It catches up...
+        let mut received = 0;
+        while let Ok(frames) = sub.recv_multipart(zmq::DONTWAIT) {
+            assert_eq!(frames[1], b"\0event");
+            received += 1;
+        }
+        assert_eq!(received + publisher.dropped(), sent);
+        std::thread::sleep(Duration::from_millis(50));
+
+        // ... and hears how much it missed before the next event.
+        publisher.send_to(client_id, b"last").unwrap();
+        let frames = sub.recv_multipart(0).unwrap();
+        let marker = decode_connection_event(&frames[1]).unwrap();
+        assert_eq!(marker, ConnectionEvent::EventsLost(sent - received));
+        assert_eq!(sub.recv_multipart(0).unwrap()[1], b"\0last");
+    }
+
+    #[test]
+    fn test_only_hosts_which_expect_framing_get_it() {
+        let zmq_context = zmq::Context::new();
+        let endpoint = "inproc://test-event-publisher-framing";
+        let mut publisher = EventPublisher::bind(&zmq_context, endpoint, 10, Some(1)).unwrap();
+        let sub = zmq_context.socket(zmq::SUB).unwrap();
+        sub.connect(endpoint).unwrap();
+        let (unknown, old, new) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        publisher.set_client_version(old, EVENT_FRAMING_PROTOCOL_VERSION - 1);
+        publisher.set_client_version(new, EVENT_FRAMING_PROTOCOL_VERSION);
+        for client_id in [unknown, old, new] {
+            sub.set_subscribe(client_id.as_bytes()).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(100));
+
+        let event =
+            bincode::encode_to_vec(ConnectionEvent::Disconnect(), bincode::config::standard())
+                .unwrap();
+        for client_id in [unknown, old, new] {
+            publisher.send_to(client_id, &event).unwrap();
+        }
+
+        // Clients we haven't heard from, and those on older hosts, get the bare event even though
+        // it's over the compression threshold.
+        assert_eq!(sub.recv_multipart(0).unwrap()[1], event);
+        assert_eq!(sub.recv_multipart(0).unwrap()[1], event);
+        let framed = &sub.recv_multipart(0).unwrap()[1];
+        assert_ne!(framed, &event);
+        assert_eq!(
+            decode_connection_event(framed).unwrap(),
+            ConnectionEvent::Disconnect()
+        );
+    }
+
+    #[test]
+    fn test_subscriptions_dont_pile_up() {
+        let zmq_context = zmq::Context::new();
+        let endpoint = "inproc://test-event-publisher-subscriptions";
+        let mut publisher = EventPublisher::bind(&zmq_context, endpoint, 10, None).unwrap();
+        let sub = zmq_context.socket(zmq::SUB).unwrap();
+        sub.connect(endpoint).unwrap();
+
+        // Clients coming and going, with no broadcasts in between.
+        for _ in 0..100 {
+            let client_id = Uuid::new_v4();
+            sub.set_subscribe(client_id.as_bytes()).unwrap();
+            sub.set_unsubscribe(client_id.as_bytes()).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(100));
+
+        publisher.send_to(Uuid::new_v4(), b"event").unwrap();
+        assert!(matches!(
+            publisher.socket.recv_bytes(zmq::DONTWAIT),
+            Err(zmq::Error::EAGAIN)
+        ));
+    }
+}
@@ -14,6 +14,7 @@
 
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use clap::builder::ValueHint;
 use clap::Parser;
@@ -30,9 +31,11 @@ use tracing::{info, warn};
 use moor_db_wiredtiger::WiredTigerDatabaseBuilder;
 use moor_kernel::config::Config;
 use moor_kernel::tasks::scheduler::Scheduler;
-use moor_kernel::textdump::textdump_load;
+use moor_kernel::textdump::{textdump_load, ImportMode};
+use rpc_common::MIN_TOKEN_TTL;
 
 use crate::rpc_server::zmq_loop;
+use crate::tokens::{PreviousKey, Tokens};
 
 #[cfg(feature = "relbox")]
 use moor_db_relbox::RelBoxDatabaseBuilder;
@@ -41,9 +44,14 @@ mod connections;
 
 #[cfg(feature = "relbox")]
 mod connections_rb;
+#[cfg(feature = "redis")]
+mod connections_redis;
 mod connections_wt;
+mod event_publisher;
+mod resolver;
 mod rpc_server;
 mod rpc_session;
+mod tokens;
 
 #[macro_export]
 macro_rules! clap_enum_variants {
@@ -66,6 +74,21 @@ struct Args {
     #[arg(short, long, value_name = "textdump", help = "Path to textdump to import", value_hint = ValueHint::FilePath)]
     textdump: Option<PathBuf>,
 
+    #[arg(
+        long,
+        help = "Give the textdump's objects fresh ids when importing it, rather than keeping the ones in the dump",
+        default_value = "false"
+    )]
+    textdump_remap: bool,
+
+    #[arg(
+        long,
+        value_name = "merge-textdump",
+        help = "Merge a textdump into the (existing) database, giving its objects fresh ids, then exit",
+        value_hint = ValueHint::FilePath
+    )]
+    merge_textdump: Option<PathBuf>,
+
     #[arg(
         long,
         value_name = "textdump-output",
@@ -73,6 +96,14 @@ struct Args {
     )]
     textdump_out: Option<PathBuf>,
 
+    #[arg(
+        long,
+        value_name = "export-dir",
+        help = "Directory `export_database()` may write into, if any",
+        value_hint = ValueHint::DirPath
+    )]
+    export_dir: Option<PathBuf>,
+
     #[arg(
         short,
         long,
@@ -83,6 +114,25 @@ struct Args {
     )]
     connections_file: PathBuf,
 
+    #[cfg(feature = "redis")]
+    #[arg(
+        long,
+        value_name = "connections-redis-url",
+        help = "Keep the connections list in Redis at this URL (e.g. redis://127.0.0.1/) instead of \
+                the connections database, so that several daemons can share it"
+    )]
+    connections_redis_url: Option<String>,
+
+    #[cfg(feature = "redis")]
+    #[arg(
+        long,
+        value_name = "connections-redis-prefix",
+        help = "Prefix for the connections list's keys in Redis; daemons share connections only if \
+                they use the same prefix",
+        default_value = "moor"
+    )]
+    connections_redis_prefix: String,
+
     #[arg(
         long,
         value_name = "rpc-listen",
@@ -123,6 +173,36 @@ struct Args {
     )]
     generate_keypair: bool,
 
+    #[arg(
+        long,
+        value_name = "previous-public-key",
+        help = "File containing a public key that tokens used to be signed with, whose tokens should still be accepted after rotating to a new keypair. May be given more than once"
+    )]
+    previous_public_key: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "previous-key-grace",
+        help = "Seconds after startup to keep accepting tokens signed with the --previous-public-key keys. Unset to accept them for as long as they're configured"
+    )]
+    previous_key_grace: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "client-token-ttl",
+        value_parser = clap::value_parser!(u64).range(MIN_TOKEN_TTL.as_secs()..),
+        help = "Seconds a client (connection) token stays valid before the host must refresh it. Hosts refresh every minute, so at least 180. Unset for no expiry"
+    )]
+    client_token_ttl: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "auth-token-ttl",
+        value_parser = clap::value_parser!(u64).range(MIN_TOKEN_TTL.as_secs()..),
+        help = "Seconds a player's auth token stays valid before it must be refreshed, or the player log in again. At least 180. Unset for no expiry"
+    )]
+    auth_token_ttl: Option<u64>,
+
     #[arg(
         long,
         value_name = "num-io-threads",
@@ -131,6 +211,21 @@ struct Args {
     )]
     num_io_threads: i32,
 
+    #[arg(
+        long,
+        value_name = "narrative-hwm",
+        help = "How many events to queue for a client which isn't keeping up before dropping them (and telling it so)",
+        default_value = "10000"
+    )]
+    narrative_hwm: i32,
+
+    #[arg(
+        long,
+        value_name = "narrative-compress-threshold",
+        help = "Compress (with zstd) events sent to clients which are at least this many bytes, e.g. big tables. Unset to never compress"
+    )]
+    narrative_compress_threshold: Option<usize>,
+
     #[arg(
         long,
         value_name = "max_buffer_pool_bytes",
@@ -162,6 +257,108 @@ struct Args {
     )]
     checkpoint_interval_seconds: u16,
 
+    #[arg(
+        long,
+        value_name = "tick-profile-interval",
+        help = "If set, sample where tasks spend their ticks every N ticks, and log the heaviest verbs when a task is aborted for exceeding its tick limit"
+    )]
+    tick_profile_interval: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Measure and index strings by byte rather than by character, for legacy cores which assumed bytes",
+        default_value = "false"
+    )]
+    byte_string_semantics: bool,
+
+    #[arg(
+        long,
+        help = "Count invocations of, and time spent in, each verb, for operators to find the hottest ones",
+        default_value = "false"
+    )]
+    verb_metrics: bool,
+
+    #[arg(
+        long,
+        value_name = "random-seed",
+        help = "Seed every task's random() stream with this value, for reproducible runs (e.g. tests)"
+    )]
+    random_seed: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "max-concurrent-tasks",
+        help = "Run at most this many tasks at once, queueing any more until one finishes. Unset for no limit"
+    )]
+    max_concurrent_tasks: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "background-tick-slice",
+        help = "How many ticks background (forked, resumed) tasks run before giving way to running commands",
+        default_value = "1000"
+    )]
+    background_tick_slice: usize,
+
+    #[arg(
+        long,
+        value_name = "max-background-wait-ms",
+        help = "The longest, in milliseconds, a background task waits for running commands before carrying on anyway",
+        default_value = "50"
+    )]
+    max_background_wait_ms: u64,
+
+    #[arg(
+        long,
+        value_name = "task-rate-limit",
+        help = "How many commands and evals per second each (non-wizard) player may start. Unset for no limit"
+    )]
+    task_rate_limit: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "debug-pause-timeout",
+        help = "Abort tasks left paused for the debugger for longer than this many seconds",
+        default_value = "300"
+    )]
+    debug_pause_timeout: u64,
+
+    #[arg(
+        long,
+        value_name = "idle-timeout",
+        help = "Drop connections which have been idle for this many seconds, unless they're set `idle-exempt`. Unset to never drop them"
+    )]
+    idle_timeout: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Look up the hostnames of connecting clients (in the background), for connection_name()",
+        default_value = "false"
+    )]
+    reverse_dns: bool,
+
+    #[arg(
+        long,
+        value_name = "login-timeout",
+        help = "Give up on (and abort) a login whose `do_login_command` hasn't finished after this many seconds",
+        default_value = "60"
+    )]
+    login_timeout: u64,
+
+    #[arg(
+        long,
+        help = "Don't rewrite a verb when it's programmed with code that compiles to what it already has",
+        default_value = "false"
+    )]
+    skip_unchanged_verb_programs: bool,
+
+    #[arg(
+        long,
+        help = "Have move() just relocate objects, without calling :accept, :exitfunc or :enterfunc",
+        default_value = "false"
+    )]
+    raw_moves: bool,
+
     #[arg(long, help = "Enable debug logging", default_value = "false")]
     debug: bool,
 }
@@ -226,6 +423,31 @@ fn main() -> Result<(), Report> {
             );
         }
     };
+    let mut tokens = Tokens::new(
+        keypair,
+        args.client_token_ttl.map(Duration::from_secs),
+        args.auth_token_ttl.map(Duration::from_secs),
+    );
+    let trusted_until = args
+        .previous_key_grace
+        .map(|grace| chrono::Utc::now() + Duration::from_secs(grace));
+    for path in args.previous_public_key {
+        let pubkey_pem = std::fs::read(&path).expect("Unable to read previous public key");
+        let pubkey_pem = pem::parse(pubkey_pem).expect("Unable to parse previous public key");
+        let public_key = pubkey_pem
+            .contents()
+            .try_into()
+            .expect("Previous public key is not a 32-byte ed25519 key");
+        info!(
+            ?path,
+            ?trusted_until,
+            "Accepting tokens signed with previous key"
+        );
+        tokens = tokens.with_previous_key(PreviousKey {
+            public_key,
+            trusted_until,
+        });
+    }
 
     info!("Daemon starting...");
     let (db_source, freshly_made) = match args.db_flavour {
@@ -247,18 +469,24 @@ fn main() -> Result<(), Report> {
     };
     info!(path = ?args.db, "Opened database");
 
-    // If the database already existed, do not try to import the textdump...
+    // If the database already existed, do not try to import the textdump, remapped or not; it's
+    // already in there from the first time round. (`--merge-textdump` is for adding to one.)
     if let Some(textdump) = args.textdump {
         if !freshly_made {
             info!("Database already exists, skipping textdump import");
         } else {
+            let mode = if args.textdump_remap {
+                ImportMode::Remap
+            } else {
+                ImportMode::Preserve
+            };
             info!("Loading textdump...");
             let start = std::time::Instant::now();
             let mut loader_interface = db_source
                 .clone()
                 .loader_client()
                 .expect("Unable to get loader interface from database");
-            textdump_load(loader_interface.as_ref(), textdump).unwrap();
+            textdump_load(loader_interface.as_ref(), textdump, mode).unwrap();
             let duration = start.elapsed();
             info!("Loaded textdump in {:?}", duration);
             loader_interface
@@ -267,9 +495,41 @@ fn main() -> Result<(), Report> {
         }
     }
 
+    // Merging is a one-off, done against whatever's already in the database, rather than
+    // something to do every time the daemon starts; so it's all we do.
+    if let Some(textdump) = args.merge_textdump {
+        info!(?textdump, "Merging textdump into database...");
+        let start = std::time::Instant::now();
+        let mut loader_interface = db_source
+            .clone()
+            .loader_client()
+            .expect("Unable to get loader interface from database");
+        textdump_load(loader_interface.as_ref(), textdump, ImportMode::Remap)
+            .expect("Unable to merge textdump");
+        loader_interface
+            .commit()
+            .expect("Failure to commit merged database...");
+        info!("Merged textdump in {:?}", start.elapsed());
+        return Ok(());
+    }
+
     let config = Config {
         textdump_output: args.textdump_out,
+        export_directory: args.export_dir,
+        tick_profile_interval: args.tick_profile_interval,
+        byte_string_semantics: args.byte_string_semantics,
+        random_seed: args.random_seed,
+        skip_unchanged_verb_programs: args.skip_unchanged_verb_programs,
+        raw_moves: args.raw_moves,
+        narrative_hwm: Some(args.narrative_hwm),
+        max_concurrent_tasks: args.max_concurrent_tasks,
+        background_tick_slice: Some(args.background_tick_slice),
+        max_background_wait: Some(Duration::from_millis(args.max_background_wait_ms)),
+        debug_pause_timeout: Some(Duration::from_secs(args.debug_pause_timeout)),
+        idle_timeout: args.idle_timeout.map(Duration::from_secs),
+        verb_metrics: args.verb_metrics,
     };
+    let idle_timeout = config.idle_timeout;
 
     let state_source = db_source
         .clone()
@@ -294,9 +554,7 @@ fn main() -> Result<(), Report> {
     let _checkpoint_thread = std::thread::Builder::new()
         .name("moor-checkpoint".to_string())
         .spawn(move || loop {
-            std::thread::sleep(std::time::Duration::from_secs(
-                args.checkpoint_interval_seconds as u64,
-            ));
+            std::thread::sleep(Duration::from_secs(args.checkpoint_interval_seconds as u64));
             if checkpoint_kill_switch.load(std::sync::atomic::Ordering::Relaxed) {
                 break;
             }
@@ -309,16 +567,29 @@ fn main() -> Result<(), Report> {
     let rpc_listen = args.rpc_listen.clone();
     let rpc_narrative_listen = args.narrative_listen.clone();
     let rpc_scheduler = scheduler.clone();
+    #[cfg(feature = "redis")]
+    let connections_redis = args
+        .connections_redis_url
+        .clone()
+        .map(|url| (url, args.connections_redis_prefix.clone()));
+    #[cfg(not(feature = "redis"))]
+    let connections_redis = None;
     let rpc_loop_thread = std::thread::Builder::new()
         .name("moor-rpc".to_string())
         .spawn(move || {
             let _ = zmq_loop(
-                keypair,
+                tokens,
                 args.connections_file,
+                connections_redis,
                 state_source,
                 rpc_scheduler,
                 rpc_listen,
                 rpc_narrative_listen,
+                args.narrative_compress_threshold,
+                args.task_rate_limit,
+                idle_timeout,
+                args.reverse_dns,
+                Duration::from_secs(args.login_timeout),
                 Some(args.num_io_threads),
                 rpc_kill_switch,
                 args.db_flavour,
@@ -0,0 +1,355 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Reverse DNS for connection addresses, so `connection_name()` can give a hostname as LambdaMOO
+//! cores expect. Lookups are handed to a small, fixed pool of worker threads, so they never hold up
+//! connection setup, and until one finishes (or if it fails, or the pool is too backed up to take
+//! it) the raw address is used instead. Results, including failures, are kept in a bounded LRU cache
+//! by IP address for a while.
+//!
+//! The system resolver can't be interrupted, so each lookup runs on a thread of its own which the
+//! worker waits on for only so long. One that takes longer is treated as a failure and left to
+//! finish by itself, and the worker gets on with the next address; so a DNS server which never
+//! answers can't tie the pool up.
+
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+use tracing::{debug, warn};
+
+/// How long a lookup result is trusted before a new connection from the address looks it up again.
+const CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// How many addresses' results we remember.
+const CACHE_CAPACITY: usize = 10_000;
+
+/// How many lookups can be waited on at once.
+const LOOKUP_THREADS: usize = 4;
+
+/// How long a lookup is waited on before it's given up on.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How many lookup threads, including given-up-on ones still stuck in the system resolver, may be
+/// running at once. Past that, new addresses just aren't looked up.
+const MAX_RUNNING_LOOKUPS: usize = 64;
+
+/// How many lookups can wait for a thread. Past that, new addresses just aren't looked up.
+const LOOKUP_QUEUE_LEN: usize = 256;
+
+/// A connection's address, as the host reported it, and the hostname it resolved to, if it has.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionDetails {
+    pub address: String,
+    pub resolved_name: Option<String>,
+}
+
+impl ConnectionDetails {
+    /// The resolved hostname if there is one, otherwise the raw address.
+    pub fn name(&self) -> &str {
+        self.resolved_name.as_deref().unwrap_or(&self.address)
+    }
+}
+
+struct CacheEntry {
+    /// None while the lookup is in flight, or if it failed.
+    name: Option<String>,
+    looked_up_at: Instant,
+}
+
+type Cache = Arc<Mutex<LruCache<IpAddr, CacheEntry>>>;
+
+pub struct Resolver {
+    lookups: SyncSender<IpAddr>,
+    cache: Cache,
+}
+
+impl Resolver {
+    /// Resolve with the system resolver.
+    pub fn new() -> Self {
+        Self::with_lookup(
+            LOOKUP_THREADS,
+            LOOKUP_QUEUE_LEN,
+            CACHE_CAPACITY,
+            LOOKUP_TIMEOUT,
+            reverse_lookup,
+        )
+    }
+
+    /// Resolve with `lookup`, on `threads` threads, with at most `queue_len` lookups waiting for
+    /// one, giving up on any which takes longer than `timeout`, and remembering the results for up
+    /// to `capacity` addresses.
+    pub fn with_lookup(
+        threads: usize,
+        queue_len: usize,
+        capacity: usize,
+        timeout: Duration,
+        lookup: fn(IpAddr) -> Option<String>,
+    ) -> Self {
+        let cache: Cache = Arc::new(Mutex::new(LruCache::new(
+            NonZeroUsize::new(capacity).expect("Resolver cache capacity must be non-zero"),
+        )));
+        let (lookups, queue) = sync_channel(queue_len);
+        let queue = Arc::new(Mutex::new(queue));
+        let running = Arc::new(AtomicUsize::new(0));
+        for i in 0..threads {
+            let (queue, cache, running) = (queue.clone(), cache.clone(), running.clone());
+            std::thread::Builder::new()
+                .name(format!("moor-resolve-{i}"))
+                .spawn(move || lookup_loop(queue, cache, lookup, timeout, running))
+                .expect("Could not spawn resolver thread");
+        }
+        Self { lookups, cache }
+    }
+
+    /// Start looking up `address` (an IP address, with or without a port) in the background,
+    /// unless we already have a fresh enough answer for it, or are already looking.
+    pub fn resolve(&self, address: &str) {
+        let Some(ip) = parse_ip(address) else {
+            return;
+        };
+        let mut cache = self.cache.lock().unwrap();
+        if cache
+            .peek(&ip)
+            .is_some_and(|entry| entry.looked_up_at.elapsed() < CACHE_TTL)
+        {
+            return;
+        }
+        match self.lookups.try_send(ip) {
+            Ok(()) => {
+                cache.put(
+                    ip,
+                    CacheEntry {
+                        name: None,
+                        looked_up_at: Instant::now(),
+                    },
+                );
+            }
+            Err(TrySendError::Full(_)) => {
+                warn!(?ip, "Too many reverse DNS lookups waiting; skipping");
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                warn!(?ip, "No reverse DNS threads to look up address");
+            }
+        }
+    }
+
+    /// What we know about `address`: always the address itself, plus its hostname once (and if)
+    /// it has resolved.
+    pub fn details(&self, address: &str) -> ConnectionDetails {
+        let resolved_name = parse_ip(address).and_then(|ip| {
+            let mut cache = self.cache.lock().unwrap();
+            cache.get(&ip).and_then(|entry| entry.name.clone())
+        });
+        ConnectionDetails {
+            address: address.to_string(),
+            resolved_name,
+        }
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Take addresses off the queue and look them up, until the resolver goes away.
+fn lookup_loop(
+    queue: Arc<Mutex<Receiver<IpAddr>>>,
+    cache: Cache,
+    lookup: fn(IpAddr) -> Option<String>,
+    timeout: Duration,
+    running: Arc<AtomicUsize>,
+) {
+    loop {
+        let Ok(ip) = queue.lock().unwrap().recv() else {
+            return;
+        };
+        let name = lookup_within(ip, lookup, timeout, &running);
+        debug!(?ip, ?name, "Reverse DNS lookup done");
+        cache.lock().unwrap().put(
+            ip,
+            CacheEntry {
+                name,
+                looked_up_at: Instant::now(),
+            },
+        );
+    }
+}
+
+/// Run `lookup` on a thread of its own, waiting at most `timeout` for it. `running` counts the
+/// lookup threads which haven't finished yet, whether or not anyone's still waiting on them.
+fn lookup_within(
+    ip: IpAddr,
+    lookup: fn(IpAddr) -> Option<String>,
+    timeout: Duration,
+    running: &Arc<AtomicUsize>,
+) -> Option<String> {
+    if running.fetch_add(1, Ordering::SeqCst) >= MAX_RUNNING_LOOKUPS {
+        running.fetch_sub(1, Ordering::SeqCst);
+        warn!(?ip, "Too many reverse DNS lookups still running; skipping");
+        return None;
+    }
+    let (send, receive) = sync_channel(1);
+    let lookup_running = running.clone();
+    let spawned = std::thread::Builder::new()
+        .name("moor-resolve-lookup".to_string())
+        .spawn(move || {
+            // Nobody may be listening by the time this is done, which is fine.
+            let _ = send.send(lookup(ip));
+            lookup_running.fetch_sub(1, Ordering::SeqCst);
+        });
+    if spawned.is_err() {
+        running.fetch_sub(1, Ordering::SeqCst);
+        warn!(?ip, "Could not spawn reverse DNS lookup thread");
+        return None;
+    }
+    match receive.recv_timeout(timeout) {
+        Ok(name) => name,
+        Err(_) => {
+            warn!(
+                ?ip,
+                ?timeout,
+                "Reverse DNS lookup timed out; giving up on it"
+            );
+            None
+        }
+    }
+}
+
+fn parse_ip(address: &str) -> Option<IpAddr> {
+    address
+        .parse::<SocketAddr>()
+        .map(|addr| addr.ip())
+        .or_else(|_| address.parse::<IpAddr>())
+        .ok()
+}
+
+/// Look up the hostname for `ip`, requiring an actual name rather than the address back again.
+fn reverse_lookup(ip: IpAddr) -> Option<String> {
+    dns_lookup::getnameinfo(&SocketAddr::new(ip, 0), libc::NI_NAMEREQD)
+        .ok()
+        .map(|(name, _service)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::{Duration, Instant};
+
+    use crate::resolver::Resolver;
+
+    fn slow_lookup(ip: IpAddr) -> Option<String> {
+        std::thread::sleep(Duration::from_millis(50));
+        ip.is_loopback().then(|| "localhost.test".to_string())
+    }
+
+    fn fast_lookup(ip: IpAddr) -> Option<String> {
+        Some(format!("host-{ip}.test"))
+    }
+
+    /// As if the DNS server for 10.0.0.1 had been blackholed.
+    fn hanging_lookup(ip: IpAddr) -> Option<String> {
+        if ip == IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)) {
+            std::thread::sleep(Duration::from_secs(3600));
+        }
+        fast_lookup(ip)
+    }
+
+    fn wait_for_name(resolver: &Resolver, address: &str) {
+        let start = Instant::now();
+        while resolver.details(address).resolved_name.is_none() {
+            assert!(start.elapsed() < Duration::from_secs(1), "never resolved");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_raw_address_first_then_resolved_name() {
+        let resolver = Resolver::with_lookup(1, 8, 16, Duration::from_secs(1), slow_lookup);
+        let address = "127.0.0.1:7777";
+        resolver.resolve(address);
+
+        // The lookup is still going, but the address is there regardless.
+        let details = resolver.details(address);
+        assert_eq!(details.address, address);
+        assert_eq!(details.resolved_name, None);
+        assert_eq!(details.name(), address);
+
+        wait_for_name(&resolver, address);
+        assert_eq!(resolver.details(address).name(), "localhost.test");
+
+        // Another port on the same host shares the answer.
+        assert_eq!(
+            resolver.details("127.0.0.1:8888").resolved_name.as_deref(),
+            Some("localhost.test")
+        );
+    }
+
+    #[test]
+    fn test_unresolvable_and_overflowing_addresses_keep_raw_address() {
+        // No threads to do the lookups, and room for just one to wait.
+        let resolver = Resolver::with_lookup(0, 1, 16, Duration::from_secs(1), slow_lookup);
+
+        // Not an address at all (e.g. the console host), so nothing to look up.
+        resolver.resolve("console");
+        assert_eq!(resolver.details("console").name(), "console");
+
+        // The queue fills up, and the rest are dropped rather than piling up.
+        resolver.resolve("127.0.0.1:7777");
+        resolver.resolve("127.0.0.2:7777");
+        assert_eq!(resolver.details("127.0.0.1:7777").resolved_name, None);
+        assert_eq!(resolver.details("127.0.0.2:7777").resolved_name, None);
+    }
+
+    #[test]
+    fn test_cache_forgets_least_recently_used() {
+        let resolver = Resolver::with_lookup(1, 8, 2, Duration::from_secs(1), fast_lookup);
+        resolver.resolve("10.0.0.1");
+        resolver.resolve("10.0.0.2");
+        resolver.resolve("10.0.0.3");
+
+        // One thread, so they're done in order, and the first is gone by the time the last lands.
+        wait_for_name(&resolver, "10.0.0.3");
+        assert_eq!(
+            resolver.details("10.0.0.2").resolved_name.as_deref(),
+            Some("host-10.0.0.2.test")
+        );
+        assert_eq!(resolver.details("10.0.0.1").resolved_name, None);
+    }
+
+    #[test]
+    fn test_hanging_lookup_doesnt_block_later_ones() {
+        // One worker, which the first lookup would tie up for good if it were waited on.
+        let resolver = Resolver::with_lookup(1, 8, 16, Duration::from_millis(100), hanging_lookup);
+        resolver.resolve("10.0.0.1:7777");
+        resolver.resolve("10.0.0.2:7777");
+        resolver.resolve("10.0.0.3:7777");
+
+        wait_for_name(&resolver, "10.0.0.2:7777");
+        wait_for_name(&resolver, "10.0.0.3:7777");
+        assert_eq!(
+            resolver.details("10.0.0.3:7777").name(),
+            "host-10.0.0.3.test"
+        );
+
+        // The one that hung is just the raw address.
+        assert_eq!(resolver.details("10.0.0.1:7777").name(), "10.0.0.1:7777");
+    }
+}
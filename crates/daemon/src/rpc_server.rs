@@ -14,28 +14,31 @@
 
 //! The core of the server logic for the RPC daemon
 
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 
 use eyre::{Context, Error};
 
 use moor_db::DatabaseFlavour;
-use rusty_paseto::core::{
-    Footer, Paseto, PasetoAsymmetricPrivateKey, PasetoAsymmetricPublicKey, Payload, Public, V4,
-};
-use rusty_paseto::prelude::Key;
-use serde_json::json;
 use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
-use zmq::{Socket, SocketType};
 
 use moor_kernel::tasks::scheduler::{Scheduler, SchedulerError, TaskResult};
 use moor_kernel::tasks::sessions::SessionError::DeliveryError;
 use moor_kernel::tasks::sessions::{Session, SessionError};
-use moor_kernel::tasks::TaskHandle;
+use moor_kernel::tasks::{CommitHook, TaskHandle, TaskId};
+use moor_kernel::vm::verb_perf_counters;
+use moor_kernel::{component_versions, SERVER_VERSION};
+use moor_values::model::CommandError;
+use moor_values::model::ListenerPoint;
 use moor_values::model::NarrativeEvent;
+use moor_values::model::ObjFlag;
+use moor_values::model::PresenceKind;
+use moor_values::model::VerbProgramError;
+use moor_values::model::WorldState;
 use moor_values::model::WorldStateSource;
 use moor_values::util::parse_into_words;
 use moor_values::var::Objid;
@@ -45,24 +48,119 @@ use moor_values::var::{v_bool, v_objid, v_str, v_string};
 use moor_values::SYSTEM_OBJECT;
 use rpc_common::RpcResponse::{LoginResult, NewConnection};
 use rpc_common::{
-    AuthToken, BroadcastEvent, ClientToken, ConnectType, ConnectionEvent, RpcRequest,
-    RpcRequestError, RpcResponse, RpcResult, BROADCAST_TOPIC, MOOR_AUTH_TOKEN_FOOTER,
-    MOOR_SESSION_TOKEN_FOOTER,
+    decode_request, AuthToken, BroadcastEvent, ClientToken, ConnectType, ConnectionEvent,
+    DebugRequest, PlayerMetadata, RpcRequest, RpcRequestError, RpcResponse, RpcResult, ServerInfo,
+    VerbPerfCounter, LOGIN_SUCCEEDED_PROTOCOL_VERSION,
 };
 
-use crate::connections::ConnectionsDB;
+use crate::connections::{
+    connected_seconds, idle_clients, idle_seconds, narrative_recipients, ClientTasks,
+    ConnectionsDB, NarrativeHistory, TaskRateLimiter, CONNECTION_TIMEOUT_DURATION,
+};
 use crate::connections_wt::ConnectionsWT;
+use crate::event_publisher::{EventPublisher, DEFAULT_NARRATIVE_HWM};
+use crate::resolver::Resolver;
 use crate::rpc_session::RpcSession;
+use crate::tokens::Tokens;
 
 #[cfg(feature = "relbox")]
 use crate::connections_rb::ConnectionsRb;
+#[cfg(feature = "redis")]
+use crate::connections_redis::ConnectionsRedis;
+
+/// How long `open_network_connection()` waits for a host to report back.
+const OUTBOUND_CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Follow a dotted property path from the system object, the way `$login.welcome_message` would
+/// in MOO code: `#0.login`, then `.welcome_message` on whatever that is.
+/// Returns None if any link in the chain is missing, or isn't an object when there's more to follow.
+fn resolve_sysprop_path(world_state: &dyn WorldState, path: &str) -> Option<Var> {
+    let mut value = v_objid(SYSTEM_OBJECT);
+    for name in path.split('.') {
+        let Variant::Obj(obj) = value.variant() else {
+            return None;
+        };
+        value = world_state
+            .retrieve_property(SYSTEM_OBJECT, *obj, name)
+            .ok()?;
+    }
+    Some(value)
+}
+
+/// Wait for the result of a `do_login_command` task.
+/// If it returned an objid, that's the player who logged in. If it returned anything else,
+/// that's an ordinary failed login (`Ok(None)`), which is different from the verb erroring out.
+fn await_login(
+    receiver: oneshot::Receiver<TaskResult>,
+    timeout: Duration,
+) -> Result<Option<Objid>, RpcRequestError> {
+    match receiver.recv_timeout(timeout) {
+        Ok(TaskResult::Success(v)) => match v.variant() {
+            Variant::Obj(o) => Ok(Some(*o)),
+            _ => Ok(None),
+        },
+        Ok(TaskResult::Error(e)) => {
+            error!(error = ?e, "Error waiting for login results");
+            Err(RpcRequestError::LoginTaskFailed)
+        }
+        Err(oneshot::RecvTimeoutError::Timeout) => Err(RpcRequestError::LoginTimeout),
+        Err(e) => {
+            error!(error = ?e, "Error waiting for login results");
+            Err(RpcRequestError::InternalError(e.to_string()))
+        }
+    }
+}
+
+/// Gather the bits of a newly logged in player that hosts want up front, in one transaction.
+/// None if the player can't be read, e.g. because the login verb returned a bogus object.
+fn player_metadata(world_state: &dyn WorldState, player: Objid) -> Option<PlayerMetadata> {
+    let flags = world_state.flags_of(player).ok()?;
+    let name = world_state.retrieve_property(player, player, "name").ok()?;
+    let Variant::Str(name) = name.variant() else {
+        return None;
+    };
+    let location = world_state.location_of(player, player).ok()?;
+    Some(PlayerMetadata {
+        name: name.as_str().to_string(),
+        location,
+        programmer: flags.contains(ObjFlag::Programmer),
+        wizard: flags.contains(ObjFlag::Wizard),
+    })
+}
+
+/// An outbound connection we've asked the hosts to open.
+struct OutboundRequest {
+    /// Where the connection object (or why there isn't one) goes.
+    waiter: oneshot::Sender<Result<Objid, String>>,
+    /// The client id of the host which claimed the request. Only it may report back.
+    claimed_by: Option<Uuid>,
+}
 
 pub struct RpcServer {
-    keypair: Key<64>,
-    publish: Arc<Mutex<Socket>>,
+    tokens: Tokens,
+    events: Mutex<EventPublisher>,
     world_state_source: Arc<dyn WorldStateSource>,
     scheduler: Arc<Scheduler>,
     connections: Arc<dyn ConnectionsDB + Send + Sync>,
+    /// Outbound connections we've asked hosts to open, and who's waiting for the result.
+    outbound_requests: Mutex<HashMap<Uuid, OutboundRequest>>,
+    /// Tasks started on behalf of each client, to be cancelled when it detaches.
+    client_tasks: Mutex<ClientTasks>,
+    narrative_history: Mutex<NarrativeHistory>,
+    /// How often each player may start commands and evals.
+    task_rate_limiter: Mutex<TaskRateLimiter>,
+    /// How long a connection may sit idle before we drop it, if there's a limit.
+    idle_timeout: Option<Duration>,
+    /// Options set with `set_connection_option()`, by player (or connection) object.
+    connection_options: Mutex<HashMap<Objid, HashMap<String, Var>>>,
+    /// Looks up the hostnames of connecting clients, if we've been asked to.
+    resolver: Option<Resolver>,
+    /// How long `do_login_command` may run before we give up on the login.
+    login_timeout: Duration,
+    /// Where each host (by the id it registered under) is accepting connections, and when it last
+    /// registered. Hosts re-register whenever we ping, and are forgotten once they stop.
+    host_listeners: Mutex<HashMap<Uuid, (Instant, Vec<ListenerPoint>)>>,
+    start_time: SystemTime,
 }
 
 pub(crate) fn make_response(result: Result<RpcResponse, RpcRequestError>) -> Vec<u8> {
@@ -73,11 +171,25 @@ pub(crate) fn make_response(result: Result<RpcResponse, RpcRequestError>) -> Vec
     bincode::encode_to_vec(&rpc_result, bincode::config::standard()).unwrap()
 }
 impl RpcServer {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        keypair: Key<64>,
+        tokens: Tokens,
         connections_db_path: PathBuf,
+        // If set (and built with the `redis` feature), share connections through Redis instead,
+        // at this URL and under this key prefix.
+        connections_redis: Option<(String, String)>,
         zmq_context: zmq::Context,
         narrative_endpoint: &str,
+        // Compress events for a client which are at least this many bytes.
+        narrative_compress_threshold: Option<usize>,
+        // How many commands and evals per second a (non-wizard) player may start.
+        task_rate_limit: Option<u32>,
+        // Drop connections idle for longer than this.
+        idle_timeout: Option<Duration>,
+        // Look up the hostnames of connecting clients.
+        reverse_dns: bool,
+        // Abort logins which take longer than this.
+        login_timeout: Duration,
         wss: Arc<dyn WorldStateSource>,
         scheduler: Arc<Scheduler>,
         // For determining the flavor for the connections database.
@@ -87,37 +199,75 @@ impl RpcServer {
             "Creating new RPC server; with {} ZMQ IO threads...",
             zmq_context.get_io_threads().unwrap()
         );
-        let publish = zmq_context
-            .socket(SocketType::PUB)
-            .expect("Unable to create ZMQ PUB socket");
-        publish
-            .bind(narrative_endpoint)
-            .expect("Unable to bind ZMQ PUB socket");
-        let connections: Arc<dyn ConnectionsDB + Send + Sync> = match db_flavor {
-            DatabaseFlavour::WiredTiger => Arc::new(ConnectionsWT::new(Some(connections_db_path))),
-            #[cfg(feature = "relbox")]
-            DatabaseFlavour::RelBox => Arc::new(ConnectionsRb::new(Some(connections_db_path))),
+        let events = EventPublisher::bind(
+            &zmq_context,
+            narrative_endpoint,
+            scheduler
+                .config()
+                .narrative_hwm
+                .unwrap_or(DEFAULT_NARRATIVE_HWM),
+            narrative_compress_threshold,
+        )
+        .expect("Unable to bind ZMQ event socket");
+        let connections: Arc<dyn ConnectionsDB + Send + Sync> = match connections_redis {
+            #[cfg(feature = "redis")]
+            Some((url, prefix)) => Arc::new(
+                ConnectionsRedis::new(&url, &prefix)
+                    .expect("Unable to connect to Redis for connections"),
+            ),
+            _ => match db_flavor {
+                DatabaseFlavour::WiredTiger => {
+                    Arc::new(ConnectionsWT::new(Some(connections_db_path)))
+                }
+                #[cfg(feature = "relbox")]
+                DatabaseFlavour::RelBox => Arc::new(ConnectionsRb::new(Some(connections_db_path))),
+            },
         };
         info!(
             "Created connections list, with {} initial known connections",
             connections.connections().len()
         );
+        let start_time = SystemTime::now();
         Self {
-            keypair,
+            tokens,
             world_state_source: wss,
             scheduler,
             connections,
-            publish: Arc::new(Mutex::new(publish)),
+            events: Mutex::new(events),
+            outbound_requests: Default::default(),
+            client_tasks: Default::default(),
+            narrative_history: Mutex::new(NarrativeHistory::new(start_time)),
+            task_rate_limiter: Mutex::new(TaskRateLimiter::new(task_rate_limit)),
+            idle_timeout,
+            connection_options: Default::default(),
+            resolver: reverse_dns.then(Resolver::new),
+            login_timeout,
+            host_listeners: Default::default(),
+            start_time,
         }
     }
 
-    /// Process a request (originally ZMQ REQ) and produce a reply (becomes ZMQ REP)
-    pub fn process_request(self: Arc<Self>, client_id: Uuid, request: RpcRequest) -> Vec<u8> {
+    /// Process a request (originally ZMQ REQ) from a host speaking protocol `version`, and produce
+    /// a reply (becomes ZMQ REP)
+    pub fn process_request(
+        self: Arc<Self>,
+        client_id: Uuid,
+        version: u16,
+        request: RpcRequest,
+    ) -> Vec<u8> {
+        // Which decides how this client's events are sent.
+        self.events
+            .lock()
+            .unwrap()
+            .set_client_version(client_id, version);
         match request {
             RpcRequest::ConnectionEstablish(hostname) => {
+                if let Some(resolver) = &self.resolver {
+                    resolver.resolve(&hostname);
+                }
                 match self.connections.new_connection(client_id, hostname, None) {
                     Ok(oid) => {
-                        let token = self.make_client_token(client_id);
+                        let token = self.tokens.make_client_token(client_id);
                         make_response(Ok(NewConnection(token, oid)))
                     }
                     Err(e) => make_response(Err(e)),
@@ -125,7 +275,7 @@ impl RpcServer {
             }
             RpcRequest::Attach(auth_token, connect_type, hostname) => {
                 // Validate the auth token, and get the player.
-                let Ok(player) = self.validate_auth_token(auth_token, None) else {
+                let Ok(player) = self.tokens.validate_auth_token(auth_token, None) else {
                     warn!("Invalid auth token for attach request");
                     return make_response(Err(RpcRequestError::PermissionDenied));
                 };
@@ -134,11 +284,12 @@ impl RpcServer {
                         .connections
                         .new_connection(client_id, hostname, Some(player))
                     {
-                        Ok(_) => self.make_client_token(client_id),
+                        Ok(_) => self.tokens.make_client_token(client_id),
                         Err(e) => return make_response(Err(e)),
                     };
 
                 if let Some(connect_type) = connect_type {
+                    let connect_type = self.take_over_connections(client_id, player, connect_type);
                     trace!(?player, "Submitting user_connected task");
                     if let Err(e) =
                         self.clone()
@@ -164,7 +315,7 @@ impl RpcServer {
                     warn!("Received Pong from invalid client: {}", client_id);
                     return response;
                 };
-                let Ok(_) = self.validate_client_token(token, client_id) else {
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
                     warn!(
                         ?client_id,
                         ?connection,
@@ -185,7 +336,7 @@ impl RpcServer {
                 else {
                     return make_response(Err(RpcRequestError::NoConnection));
                 };
-                let Ok(_) = self.validate_client_token(token, client_id) else {
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
                     warn!(
                         ?client_id,
                         ?connection,
@@ -196,12 +347,20 @@ impl RpcServer {
 
                 make_response(self.clone().request_sys_prop(object, property))
             }
+            RpcRequest::ServerInfo(token) => {
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
+                    warn!(?client_id, "Client token validation failed for request");
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
+
+                make_response(self.server_info(client_id))
+            }
             RpcRequest::LoginCommand(token, args, attach) => {
                 let Some(connection) = self.connections.connection_object_for_client(client_id)
                 else {
                     return make_response(Err(RpcRequestError::NoConnection));
                 };
-                let Ok(_) = self.validate_client_token(token, client_id) else {
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
                     warn!(
                         ?client_id,
                         ?connection,
@@ -212,7 +371,7 @@ impl RpcServer {
 
                 make_response(
                     self.clone()
-                        .perform_login(client_id, connection, args, attach),
+                        .perform_login(client_id, version, connection, args, attach),
                 )
             }
             RpcRequest::Command(token, auth_token, command) => {
@@ -221,7 +380,7 @@ impl RpcServer {
                     return make_response(Err(RpcRequestError::NoConnection));
                 };
 
-                let Ok(_) = self.validate_client_token(token, client_id) else {
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
                     warn!(
                         ?client_id,
                         ?connection,
@@ -230,7 +389,10 @@ impl RpcServer {
                     return make_response(Err(RpcRequestError::PermissionDenied));
                 };
 
-                let Ok(_) = self.validate_auth_token(auth_token, Some(connection)) else {
+                let Ok(_) = self
+                    .tokens
+                    .validate_auth_token(auth_token, Some(connection))
+                else {
                     warn!(
                         ?client_id,
                         ?connection,
@@ -246,7 +408,7 @@ impl RpcServer {
                     return make_response(Err(RpcRequestError::NoConnection));
                 };
 
-                let Ok(_) = self.validate_client_token(token, client_id) else {
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
                     warn!(
                         ?client_id,
                         ?connection,
@@ -255,7 +417,10 @@ impl RpcServer {
                     return make_response(Err(RpcRequestError::PermissionDenied));
                 };
 
-                let Ok(_) = self.validate_auth_token(auth_token, Some(connection)) else {
+                let Ok(_) = self
+                    .tokens
+                    .validate_auth_token(auth_token, Some(connection))
+                else {
                     warn!(
                         ?client_id,
                         ?connection,
@@ -274,7 +439,7 @@ impl RpcServer {
                 else {
                     return make_response(Err(RpcRequestError::NoConnection));
                 };
-                let Ok(_) = self.validate_client_token(token, client_id) else {
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
                     warn!(
                         ?client_id,
                         ?connection,
@@ -283,7 +448,10 @@ impl RpcServer {
                     return make_response(Err(RpcRequestError::PermissionDenied));
                 };
 
-                let Ok(_) = self.validate_auth_token(auth_token, Some(connection)) else {
+                let Ok(_) = self
+                    .tokens
+                    .validate_auth_token(auth_token, Some(connection))
+                else {
                     warn!(
                         ?client_id,
                         ?connection,
@@ -304,7 +472,7 @@ impl RpcServer {
                     return make_response(Err(RpcRequestError::NoConnection));
                 };
 
-                let Ok(_) = self.validate_client_token(token, client_id) else {
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
                     warn!(
                         ?client_id,
                         ?connection,
@@ -313,7 +481,10 @@ impl RpcServer {
                     return make_response(Err(RpcRequestError::PermissionDenied));
                 };
 
-                let Ok(_) = self.validate_auth_token(auth_token, Some(connection)) else {
+                let Ok(_) = self
+                    .tokens
+                    .validate_auth_token(auth_token, Some(connection))
+                else {
                     warn!(
                         ?client_id,
                         ?connection,
@@ -324,29 +495,101 @@ impl RpcServer {
                 make_response(self.clone().eval(client_id, connection, evalstr))
             }
             RpcRequest::Detach(token) => {
-                let Ok(_) = self.validate_client_token(token, client_id) else {
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
                     warn!(?client_id, "Client token validation failed for request");
                     return make_response(Err(RpcRequestError::PermissionDenied));
                 };
-
                 info!("Detaching client: {}", client_id);
-
-                // Detach this client id from the player/connection object.
-                let Ok(_) = self.connections.remove_client_connection(client_id) else {
-                    return make_response(Err(RpcRequestError::InternalError(
-                        "Unable to remove client connection".to_string(),
-                    )));
+                make_response(self.clone().detach(client_id, true))
+            }
+            RpcRequest::Abort(token) => {
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
+                    warn!(?client_id, "Client token validation failed for request");
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
+                info!("Aborting client: {}", client_id);
+                make_response(self.clone().detach(client_id, false))
+            }
+            RpcRequest::ClaimOutboundConnection(request_id) => {
+                // Every host hears the request; the first to claim it is the one which connects.
+                let request_id = Uuid::from_u128(request_id);
+                let mut outbound_requests = self.outbound_requests.lock().unwrap();
+                let Some(request) = outbound_requests.get_mut(&request_id) else {
+                    return make_response(Err(RpcRequestError::InvalidRequest));
+                };
+                if request.claimed_by.is_some() {
+                    return make_response(Err(RpcRequestError::InvalidRequest));
+                }
+                request.claimed_by = Some(client_id);
+                let token = self.tokens.make_client_token(client_id);
+                make_response(Ok(RpcResponse::OutboundConnectionClaimed(token)))
+            }
+            RpcRequest::OutboundConnectionEstablished(token, request_id) => {
+                let Some(connection) = self.connections.connection_object_for_client(client_id)
+                else {
+                    return make_response(Err(RpcRequestError::NoConnection));
+                };
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Client token validation failed for request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
                 };
 
+                // If nobody's waiting any more (timed out), or this isn't the host which claimed
+                // it, the host should hang up.
+                let Some(waiter) = self.take_outbound_request(request_id, client_id) else {
+                    return make_response(Err(RpcRequestError::InvalidRequest));
+                };
+                if waiter.send(Ok(connection)).is_err() {
+                    return make_response(Err(RpcRequestError::InvalidRequest));
+                }
+                make_response(Ok(RpcResponse::OutboundConnectionAccepted(connection)))
+            }
+            RpcRequest::OutboundConnectionFailed(token, request_id, reason) => {
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
+                    warn!(?client_id, "Client token validation failed for request");
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
+                let Some(waiter) = self.take_outbound_request(request_id, client_id) else {
+                    return make_response(Err(RpcRequestError::InvalidRequest));
+                };
+                let _ = waiter.send(Err(reason));
                 make_response(Ok(RpcResponse::Disconnected))
             }
+            RpcRequest::OutboundRequestedInput(token, request_id, input) => {
+                let Some(connection) = self.connections.connection_object_for_client(client_id)
+                else {
+                    return make_response(Err(RpcRequestError::NoConnection));
+                };
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Client token validation failed for request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
+
+                // Outbound connections never log in, so they have no player to speak for.
+                if connection.0 >= 0 {
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                }
+                let request_id = Uuid::from_u128(request_id);
+                make_response(
+                    self.clone()
+                        .respond_input(client_id, connection, request_id, input),
+                )
+            }
             RpcRequest::Program(token, auth_token, object, verb, code) => {
                 let Some(connection) = self.connections.connection_object_for_client(client_id)
                 else {
                     return make_response(Err(RpcRequestError::NoConnection));
                 };
 
-                let Ok(_) = self.validate_client_token(token, client_id) else {
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
                     warn!(
                         ?client_id,
                         ?connection,
@@ -355,7 +598,10 @@ impl RpcServer {
                     return make_response(Err(RpcRequestError::PermissionDenied));
                 };
 
-                let Ok(_) = self.validate_auth_token(auth_token, Some(connection)) else {
+                let Ok(_) = self
+                    .tokens
+                    .validate_auth_token(auth_token, Some(connection))
+                else {
                     warn!(
                         ?client_id,
                         ?connection,
@@ -366,124 +612,690 @@ impl RpcServer {
 
                 make_response(
                     self.clone()
-                        .program_verb(client_id, connection, object, verb, code),
+                        .program_verb(client_id, connection, object, verb, code, false),
                 )
             }
-        }
-    }
+            RpcRequest::ProgramWithDiagnostics(token, auth_token, object, verb, code) => {
+                let Some(connection) = self.connections.connection_object_for_client(client_id)
+                else {
+                    return make_response(Err(RpcRequestError::NoConnection));
+                };
 
-    pub(crate) fn new_session(
-        self: Arc<Self>,
-        client_id: Uuid,
-        connection: Objid,
-    ) -> Result<Arc<dyn Session>, SessionError> {
-        debug!(?client_id, ?connection, "Started session",);
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Client token validation failed for request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
 
-        Ok(Arc::new(RpcSession::new(
-            client_id,
-            self.clone(),
-            connection,
-        )))
-    }
+                let Ok(_) = self
+                    .tokens
+                    .validate_auth_token(auth_token, Some(connection))
+                else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Auth token validation failed for request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
 
-    pub(crate) fn connection_name_for(&self, player: Objid) -> Result<String, SessionError> {
-        self.connections.connection_name_for(player)
-    }
+                make_response(
+                    self.clone()
+                        .program_verb(client_id, connection, object, verb, code, true),
+                )
+            }
+            RpcRequest::CompileCheck(token, auth_token, code) => {
+                let Some(connection) = self.connections.connection_object_for_client(client_id)
+                else {
+                    return make_response(Err(RpcRequestError::NoConnection));
+                };
 
-    #[allow(dead_code)]
-    fn last_activity_for(&self, player: Objid) -> Result<SystemTime, SessionError> {
-        self.connections.last_activity_for(player)
-    }
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Client token validation failed for request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
 
-    pub(crate) fn idle_seconds_for(&self, player: Objid) -> Result<f64, SessionError> {
-        let last_activity = self.connections.last_activity_for(player)?;
-        Ok(last_activity.elapsed().unwrap().as_secs_f64())
-    }
+                let Ok(_) = self
+                    .tokens
+                    .validate_auth_token(auth_token, Some(connection))
+                else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Auth token validation failed for request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
 
-    pub(crate) fn connected_seconds_for(&self, player: Objid) -> Result<f64, SessionError> {
-        self.connections.connected_seconds_for(player)
-    }
+                make_response(self.clone().compile_check(connection, code))
+            }
+            RpcRequest::RevokeTokens(token, auth_token, player) => {
+                let Some(connection) = self.connections.connection_object_for_client(client_id)
+                else {
+                    return make_response(Err(RpcRequestError::NoConnection));
+                };
 
-    // TODO this will issue physical disconnects to *all* connections for this player.
-    //   which probably isn't what you really want. This is just here to keep the existing behaviour
-    //   of @quit and @boot-player working.
-    //   in reality players using "@quit" will probably really want to just "sleep", and cores
-    //   should be modified to reflect that.
-    pub(crate) fn disconnect(&self, player: Objid) -> Result<(), SessionError> {
-        warn!("Disconnecting player: {}", player);
-        let all_client_ids = self.connections.client_ids_for(player)?;
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Client token validation failed for request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
 
-        let publish = self.publish.lock().unwrap();
-        let event = ConnectionEvent::Disconnect();
-        let event_bytes = bincode::encode_to_vec(event, bincode::config::standard())
-            .expect("Unable to serialize disconnection event");
-        for client_id in all_client_ids {
-            let payload = vec![client_id.as_bytes().to_vec(), event_bytes.clone()];
-            publish.send_multipart(payload, 0).map_err(|e| {
-                error!(
-                    "Unable to send disconnection event to narrative channel: {}",
-                    e
-                );
-                DeliveryError
-            })?
-        }
+                let Ok(_) = self
+                    .tokens
+                    .validate_auth_token(auth_token, Some(connection))
+                else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Auth token validation failed for request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
 
-        Ok(())
-    }
+                make_response(self.revoke_tokens_for(connection, player))
+            }
+            RpcRequest::RequestPerformanceCounters(token, auth_token) => {
+                let Some(connection) = self.connections.connection_object_for_client(client_id)
+                else {
+                    return make_response(Err(RpcRequestError::NoConnection));
+                };
 
-    pub(crate) fn connected_players(&self) -> Result<Vec<Objid>, SessionError> {
-        let connections = self.connections.connections();
-        Ok(connections.iter().filter(|o| o.0 > 0).cloned().collect())
-    }
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Client token validation failed for request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
 
-    fn request_sys_prop(
-        self: Arc<Self>,
-        object: String,
-        property: String,
-    ) -> Result<RpcResponse, RpcRequestError> {
-        let Ok(world_state) = self.world_state_source.new_world_state() else {
-            return Err(RpcRequestError::CreateSessionFailed);
-        };
+                let Ok(_) = self
+                    .tokens
+                    .validate_auth_token(auth_token, Some(connection))
+                else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Auth token validation failed for request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
 
-        let Ok(sysprop) =
-            world_state.retrieve_property(SYSTEM_OBJECT, SYSTEM_OBJECT, object.as_str())
-        else {
-            return Err(RpcRequestError::ErrorCouldNotRetrieveSysProp(
-                "could not access system object".to_string(),
-            ));
-        };
+                make_response(self.performance_counters_for(connection))
+            }
+            RpcRequest::DisassembleVerb(token, auth_token, object, verb) => {
+                let Some(connection) = self.connections.connection_object_for_client(client_id)
+                else {
+                    return make_response(Err(RpcRequestError::NoConnection));
+                };
 
-        let Variant::Obj(sysprop) = sysprop.variant() else {
-            return Err(RpcRequestError::ErrorCouldNotRetrieveSysProp(
-                "system object invalid".to_string(),
-            ));
-        };
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Client token validation failed for request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
 
-        let Ok(property_value) =
-            world_state.retrieve_property(SYSTEM_OBJECT, *sysprop, property.as_str())
-        else {
-            return Err(RpcRequestError::ErrorCouldNotRetrieveSysProp(
-                "could not sysprop".to_string(),
-            ));
-        };
+                let Ok(_) = self
+                    .tokens
+                    .validate_auth_token(auth_token, Some(connection))
+                else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Auth token validation failed for request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
 
-        Ok(RpcResponse::SysPropValue(Some(property_value)))
-    }
+                make_response(self.disassemble_verb(connection, object, verb))
+            }
+            RpcRequest::Debug(token, auth_token, request) => {
+                let Some(connection) = self.connections.connection_object_for_client(client_id)
+                else {
+                    return make_response(Err(RpcRequestError::NoConnection));
+                };
 
-    fn perform_login(
-        self: Arc<Self>,
-        client_id: Uuid,
-        connection: Objid,
-        args: Vec<String>,
-        attach: bool,
-    ) -> Result<RpcResponse, RpcRequestError> {
-        // TODO: change result of login to return this information, rather than just Objid, so
-        //   we're not dependent on this.
-        let connect_type = if args.first() == Some(&"create".to_string()) {
-            ConnectType::Created
-        } else {
-            ConnectType::Connected
-        };
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Client token validation failed for request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
+
+                let Ok(_) = self
+                    .tokens
+                    .validate_auth_token(auth_token, Some(connection))
+                else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Auth token validation failed for request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
+
+                make_response(self.debug(connection, request))
+            }
+            RpcRequest::RequestHistory(token, auth_token, seq) => {
+                let Some(connection) = self.connections.connection_object_for_client(client_id)
+                else {
+                    return make_response(Err(RpcRequestError::NoConnection));
+                };
+
+                let Ok(_) = self.tokens.validate_client_token(token, client_id) else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Client token validation failed for request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
+
+                let Ok(_) = self
+                    .tokens
+                    .validate_auth_token(auth_token, Some(connection))
+                else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Auth token validation failed for request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
+
+                make_response(Ok(self.narrative_history_since(connection, seq)))
+            }
+            RpcRequest::RegisterHost(listeners) => {
+                self.register_host(client_id, listeners, Instant::now());
+                make_response(Ok(RpcResponse::HostRegistered))
+            }
+            RpcRequest::RefreshTokens(token, auth_token) => {
+                let Some(connection) = self.connections.connection_object_for_client(client_id)
+                else {
+                    return make_response(Err(RpcRequestError::NoConnection));
+                };
+
+                // Only a client still holding good tokens gets new ones; once they've expired,
+                // it has to log in again.
+                let Ok((client_token, auth)) = self.tokens.refresh(token, client_id, auth_token)
+                else {
+                    warn!(
+                        ?client_id,
+                        ?connection,
+                        "Token validation failed for refresh request"
+                    );
+                    return make_response(Err(RpcRequestError::PermissionDenied));
+                };
+                if let Some((_, player)) = &auth {
+                    if *player != connection {
+                        warn!(
+                            ?client_id,
+                            ?connection,
+                            ?player,
+                            "Auth token player does not match connection for refresh request"
+                        );
+                        return make_response(Err(RpcRequestError::PermissionDenied));
+                    }
+                }
+                let auth_token = auth.map(|(auth_token, _)| auth_token);
+                make_response(Ok(RpcResponse::TokensRefreshed(client_token, auth_token)))
+            }
+        }
+    }
+
+    /// Revoke the given player's auth tokens on behalf of `requester`, which must be a wizard.
+    fn revoke_tokens_for(
+        &self,
+        requester: Objid,
+        player: Objid,
+    ) -> Result<RpcResponse, RpcRequestError> {
+        if !self.is_wizard(requester)? {
+            warn!(?requester, ?player, "Non-wizard attempted to revoke tokens");
+            return Err(RpcRequestError::PermissionDenied);
+        }
+        self.revoke_tokens(player);
+        Ok(RpcResponse::TokensRevoked)
+    }
+
+    /// The per-verb perf counters, for `requester`, which must be a wizard.
+    fn performance_counters_for(&self, requester: Objid) -> Result<RpcResponse, RpcRequestError> {
+        if !self.is_wizard(requester)? {
+            warn!(?requester, "Non-wizard asked for performance counters");
+            return Err(RpcRequestError::PermissionDenied);
+        }
+        let counters = verb_perf_counters()
+            .into_iter()
+            .map(|(key, counters)| VerbPerfCounter {
+                definer: key.definer,
+                verb_name: key.verb_name,
+                invocations: counters.invocations,
+                cumulative_time: counters.cumulative_time,
+            })
+            .collect();
+        Ok(RpcResponse::PerformanceCounters(counters))
+    }
+
+    /// Carry out a debugger request for `requester`, which must be a wizard.
+    fn debug(
+        &self,
+        requester: Objid,
+        request: DebugRequest,
+    ) -> Result<RpcResponse, RpcRequestError> {
+        if !self.is_wizard(requester)? {
+            warn!(
+                ?requester,
+                ?request,
+                "Non-wizard attempted to use the debugger"
+            );
+            return Err(RpcRequestError::PermissionDenied);
+        }
+        match request {
+            DebugRequest::SetBreakpoint(breakpoint) => {
+                info!(?requester, ?breakpoint, "Breakpoint set");
+                self.scheduler.set_breakpoint(requester, breakpoint);
+                Ok(RpcResponse::BreakpointSet)
+            }
+            DebugRequest::ClearBreakpoint(breakpoint) => Ok(RpcResponse::BreakpointCleared(
+                self.scheduler.clear_breakpoint(requester, &breakpoint),
+            )),
+            DebugRequest::PausedTasks => {
+                Ok(RpcResponse::PausedTasks(self.scheduler.paused_tasks()))
+            }
+            DebugRequest::Resume { task_id, step } => {
+                match self.scheduler.debug_resume(task_id, step) {
+                    Ok(()) => Ok(RpcResponse::DebugResumed),
+                    Err(e) => Err(RpcRequestError::InternalError(e.to_string())),
+                }
+            }
+            DebugRequest::Locals { task_id } => match self.scheduler.paused_task_locals(task_id) {
+                Ok(locals) => Ok(RpcResponse::Locals(locals)),
+                Err(e) => Err(RpcRequestError::InternalError(e.to_string())),
+            },
+            DebugRequest::SetLocal {
+                task_id,
+                name,
+                value,
+            } => {
+                info!(
+                    ?requester,
+                    task_id,
+                    name = name.as_str(),
+                    ?value,
+                    "Setting local of paused task"
+                );
+                match self.scheduler.set_paused_task_local(task_id, name, value) {
+                    Ok(()) => Ok(RpcResponse::LocalSet),
+                    Err(e) => Err(RpcRequestError::InternalError(e.to_string())),
+                }
+            }
+        }
+    }
+
+    fn is_wizard(&self, player: Objid) -> Result<bool, RpcRequestError> {
+        let world_state = self
+            .world_state_source
+            .new_world_state()
+            .map_err(RpcRequestError::DatabaseError)?;
+        let flags = world_state
+            .flags_of(player)
+            .map_err(RpcRequestError::DatabaseError)?;
+        Ok(flags.contains(ObjFlag::Wizard))
+    }
+
+    /// Clean up after a client that's going away: cancel whatever it still has running and
+    /// detach it from its connection object. If `graceful`, and this was a logged-in player's
+    /// last connection, `user_disconnected` is run for them.
+    fn detach(
+        self: Arc<Self>,
+        client_id: Uuid,
+        graceful: bool,
+    ) -> Result<RpcResponse, RpcRequestError> {
+        let connection = self.connections.connection_object_for_client(client_id);
+
+        // Nobody's left to see the output of the client's in-flight tasks, so stop them.
+        let tasks = self.client_tasks.lock().unwrap().take(client_id);
+        for task_id in tasks {
+            // It may well have finished on its own in the meantime, which is fine.
+            if self.scheduler.abort_task(task_id).is_ok() {
+                debug!(?client_id, task_id, "Aborted task of detaching client");
+            }
+        }
+
+        self.events.lock().unwrap().forget(client_id);
+
+        // Detach this client id from the player/connection object.
+        let Ok(_) = self.connections.remove_client_connection(client_id) else {
+            return Err(RpcRequestError::InternalError(
+                "Unable to remove client connection".to_string(),
+            ));
+        };
+
+        if !graceful {
+            return Ok(RpcResponse::Disconnected);
+        }
+
+        // If that was a logged-in player's last connection, they've now left.
+        if let Some(player) = connection.filter(|c| c.0 > 0) {
+            let last_connection = self
+                .connections
+                .client_ids_for(player)
+                .map(|clients| clients.is_empty())
+                .unwrap_or(true);
+            if last_connection {
+                self.connection_options.lock().unwrap().remove(&player);
+                trace!(?player, "Submitting user_disconnected task");
+                if let Err(e) = self.clone().submit_disconnected_task(client_id, player) {
+                    error!(error = ?e, "Error submitting user_disconnected task");
+                }
+            }
+        }
+
+        Ok(RpcResponse::Disconnected)
+    }
+
+    /// Remember that `client_id` started `task_id`, so it can be cancelled if the client leaves.
+    fn track_client_task(&self, client_id: Uuid, task_id: TaskId) {
+        self.client_tasks
+            .lock()
+            .unwrap()
+            .record(client_id, task_id, |t| self.scheduler.task_exists(t));
+    }
+
+    pub(crate) fn new_session(
+        self: Arc<Self>,
+        client_id: Uuid,
+        connection: Objid,
+    ) -> Result<Arc<dyn Session>, SessionError> {
+        debug!(?client_id, ?connection, "Started session",);
+
+        Ok(Arc::new(RpcSession::new(
+            client_id,
+            self.clone(),
+            connection,
+        )))
+    }
+
+    /// The hostname of the player's most recent connection if it has been resolved, otherwise
+    /// the address the host gave us for it.
+    pub(crate) fn connection_name_for(&self, player: Objid) -> Result<String, SessionError> {
+        let address = self.connections.connection_name_for(player)?;
+        match &self.resolver {
+            Some(resolver) => Ok(resolver.details(&address).name().to_string()),
+            None => Ok(address),
+        }
+    }
+
+    #[allow(dead_code)]
+    fn last_activity_for(&self, player: Objid) -> Result<SystemTime, SessionError> {
+        self.connections.last_activity_for(player)
+    }
+
+    /// A player with several connections is as idle as the least idle of them.
+    pub(crate) fn idle_seconds_for(&self, player: Objid) -> Result<f64, SessionError> {
+        let times = self.connections.client_times_for(player)?;
+        idle_seconds(&times, SystemTime::now()).ok_or(SessionError::NoConnectionForPlayer(player))
+    }
+
+    /// A player with several connections has been connected as long as the oldest of them.
+    pub(crate) fn connected_seconds_for(&self, player: Objid) -> Result<f64, SessionError> {
+        let times = self.connections.client_times_for(player)?;
+        connected_seconds(&times, SystemTime::now())
+            .ok_or(SessionError::NoConnectionForPlayer(player))
+    }
+
+    /// Issue physical disconnects to *all* connections for this player. Use `disconnect_client`
+    /// to drop just one of them.
+    // TODO: players using "@quit" will probably really want to just "sleep", and cores
+    //   should be modified to reflect that.
+    pub(crate) fn disconnect(&self, player: Objid) -> Result<(), SessionError> {
+        warn!("Disconnecting player: {}", player);
+        let all_client_ids = self.connections.client_ids_for(player)?;
+        for client_id in all_client_ids {
+            self.disconnect_client(client_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Ask the host to drop the single connection for the given client. The host then detaches it,
+    /// at which point its record goes away.
+    pub(crate) fn disconnect_client(&self, client_id: Uuid) -> Result<(), SessionError> {
+        warn!(?client_id, "Disconnecting client");
+        let mut events = self.events.lock().unwrap();
+        let event = ConnectionEvent::Disconnect();
+        let event_bytes = bincode::encode_to_vec(event, bincode::config::standard())
+            .expect("Unable to serialize disconnection event");
+        events.send_to(client_id, &event_bytes).map_err(|e| {
+            error!(
+                "Unable to send disconnection event to narrative channel: {}",
+                e
+            );
+            DeliveryError
+        })
+    }
+
+    pub(crate) fn set_connection_option(&self, player: Objid, option: &str, value: Var) {
+        self.connection_options
+            .lock()
+            .unwrap()
+            .entry(player)
+            .or_default()
+            .insert(option.to_string(), value);
+    }
+
+    pub(crate) fn connection_options(&self, player: Objid) -> Vec<(String, Var)> {
+        self.connection_options
+            .lock()
+            .unwrap()
+            .get(&player)
+            .map(|options| {
+                options
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Drop every connection which has been idle for longer than the idle timeout (if there is
+    /// one), other than those marked `idle-exempt`. They're detached straight away, so
+    /// `user_disconnected` runs for players left with no connections, rather than waiting on the
+    /// host to tell us it has hung up.
+    fn reap_idle_connections(self: Arc<Self>) {
+        let Some(timeout) = self.idle_timeout else {
+            return;
+        };
+        let connections: Vec<_> = self
+            .connections
+            .connections()
+            .into_iter()
+            .filter_map(|c| Some((c, self.connections.client_times_for(c).ok()?)))
+            .collect();
+        let exempt = |connection| {
+            self.connection_options
+                .lock()
+                .unwrap()
+                .get(&connection)
+                .and_then(|options| options.get("idle-exempt"))
+                .is_some_and(|v| v.is_true())
+        };
+        for (connection, client_id) in
+            idle_clients(&connections, SystemTime::now(), timeout, exempt)
+        {
+            info!(?connection, ?client_id, "Dropping idle connection");
+            if let Err(e) = self.disconnect_client(client_id) {
+                warn!(?client_id, error = ?e, "Unable to tell host to drop idle connection");
+            }
+            if let Err(e) = self.clone().detach(client_id, true) {
+                error!(?client_id, error = ?e, "Unable to detach idle connection");
+            }
+        }
+    }
+
+    /// Make all the auth tokens issued to this player so far unusable, and drop their current
+    /// connections, whose tokens no longer work for anything they'd send.
+    pub(crate) fn revoke_tokens(&self, player: Objid) {
+        warn!(?player, "Revoking auth tokens");
+        self.tokens.revoke_player(player);
+        match self.disconnect(player) {
+            Ok(()) | Err(SessionError::NoConnectionForPlayer(_)) => {}
+            Err(e) => {
+                warn!(?player, error = ?e, "Unable to disconnect player after revoking their tokens");
+            }
+        }
+    }
+
+    /// Record (or refresh) where a host is accepting connections.
+    fn register_host(&self, host_id: Uuid, listeners: Vec<ListenerPoint>, now: Instant) {
+        let mut hosts = self.host_listeners.lock().unwrap();
+        if !hosts.contains_key(&host_id) {
+            info!(?host_id, ?listeners, "Host registered");
+        }
+        hosts.insert(host_id, (now, listeners));
+    }
+
+    /// Forget hosts which haven't registered again since a ping as long ago as we'd give a
+    /// connection to answer one.
+    fn prune_hosts(&self, now: Instant) {
+        self.host_listeners
+            .lock()
+            .unwrap()
+            .retain(|host_id, (last_seen, _)| {
+                let alive = now.duration_since(*last_seen) <= CONNECTION_TIMEOUT_DURATION;
+                if !alive {
+                    info!(
+                        ?host_id,
+                        "Host stopped answering pings; forgetting its listeners"
+                    );
+                }
+                alive
+            });
+    }
+
+    /// All the places the registered hosts are accepting connections.
+    pub(crate) fn listeners(&self) -> Vec<ListenerPoint> {
+        self.host_listeners
+            .lock()
+            .unwrap()
+            .values()
+            .flat_map(|(_, listeners)| listeners)
+            .cloned()
+            .collect()
+    }
+
+    /// Return the connection object for the given client, if it is still connected.
+    pub(crate) fn connection_object_for_client(&self, client_id: Uuid) -> Option<Objid> {
+        self.connections.connection_object_for_client(client_id)
+    }
+
+    pub(crate) fn connected_players(
+        &self,
+        include_all: bool,
+        max_idle: Option<f64>,
+    ) -> Result<Vec<Objid>, SessionError> {
+        let connections = self.connections.connections();
+        let players = connections
+            .iter()
+            .filter(|o| include_all || o.0 > 0)
+            .cloned();
+        let Some(max_idle) = max_idle else {
+            return Ok(players.collect());
+        };
+        // A player whose activity can't be determined (e.g. disconnected since we listed them)
+        // isn't considered present.
+        Ok(players
+            .filter(|p| {
+                self.idle_seconds_for(*p)
+                    .map(|idle| idle <= max_idle)
+                    .unwrap_or(false)
+            })
+            .collect())
+    }
+
+    /// The counts leave out the asking client, whose connection (e.g. the one the web host opens
+    /// for each `/status` request) exists only to ask.
+    fn server_info(&self, client_id: Uuid) -> Result<RpcResponse, RpcRequestError> {
+        let mut connected_players = 0;
+        let mut connections = 0;
+        for connection in self.connections.connections() {
+            let clients = self
+                .connections
+                .client_ids_for(connection)
+                .unwrap_or_default();
+            let others = clients.iter().filter(|c| **c != client_id).count();
+            connections += others;
+            if connection.0 > 0 && others > 0 {
+                connected_players += 1;
+            }
+        }
+        Ok(RpcResponse::ServerInfo(ServerInfo {
+            version: SERVER_VERSION.to_string(),
+            components: component_versions()
+                .into_iter()
+                .map(|(component, version)| (component.to_string(), version))
+                .collect(),
+            start_time: self.start_time,
+            connected_players,
+            connections,
+            hosts: self.host_listeners.lock().unwrap().len(),
+            events_dropped: self.events.lock().unwrap().dropped(),
+        }))
+    }
+
+    fn request_sys_prop(
+        self: Arc<Self>,
+        object: String,
+        property: String,
+    ) -> Result<RpcResponse, RpcRequestError> {
+        let Ok(world_state) = self.world_state_source.new_world_state() else {
+            return Err(RpcRequestError::CreateSessionFailed);
+        };
+
+        // Either half may itself be dotted (or the property left empty), so
+        // ("login", "welcome_message") and ("login.welcome_message", "") are the same request.
+        let path = [object.as_str(), property.as_str()]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(".");
+
+        Ok(RpcResponse::SysPropValue(resolve_sysprop_path(
+            world_state.as_ref(),
+            &path,
+        )))
+    }
+
+    fn perform_login(
+        self: Arc<Self>,
+        client_id: Uuid,
+        version: u16,
+        connection: Objid,
+        args: Vec<String>,
+        attach: bool,
+    ) -> Result<RpcResponse, RpcRequestError> {
+        // TODO: change result of login to return this information, rather than just Objid, so
+        //   we're not dependent on this.
+        let connect_type = if args.first() == Some(&"create".to_string()) {
+            ConnectType::Created
+        } else {
+            ConnectType::Connected
+        };
 
         debug!(
             "Performing {:?} login for client: {}",
@@ -492,7 +1304,18 @@ impl RpcServer {
         let Ok(session) = self.clone().new_session(client_id, connection) else {
             return Err(RpcRequestError::CreateSessionFailed);
         };
-        let task_handle = match self.clone().scheduler.submit_verb_task(
+        // The metadata is read as the login task commits, so it's consistent with what the login
+        // did (say, creating the player).
+        let metadata = Arc::new(Mutex::new(None));
+        let commit_hook: CommitHook = {
+            let metadata = metadata.clone();
+            Arc::new(move |world_state: &dyn WorldState, result: &Var| {
+                if let Variant::Obj(player) = result.variant() {
+                    *metadata.lock().unwrap() = player_metadata(world_state, *player);
+                }
+            })
+        };
+        let task_handle = match self.clone().scheduler.submit_verb_task_with_commit_hook(
             connection,
             SYSTEM_OBJECT,
             "do_login_command".to_string(),
@@ -500,6 +1323,7 @@ impl RpcServer {
             args.join(" "),
             SYSTEM_OBJECT,
             session,
+            Some(commit_hook),
         ) {
             Ok(t) => t,
             Err(e) => {
@@ -508,30 +1332,18 @@ impl RpcServer {
                 return Err(RpcRequestError::InternalError(e.to_string()));
             }
         };
-        let receiver = task_handle.into_receiver();
-        let player = match receiver.recv() {
-            Ok(TaskResult::Success(v)) => {
-                // If v is an objid, we have a successful login and we need to rewrite this
-                // client id to use the player objid and then return a result to the client.
-                // with its new player objid and login result.
-                // If it's not an objid, that's considered an auth failure.
-                match v.variant() {
-                    Variant::Obj(o) => *o,
-                    _ => {
-                        return Ok(LoginResult(None));
-                    }
+        let task_id = task_handle.task_id();
+        let player = match await_login(task_handle.into_receiver(), self.login_timeout) {
+            Ok(Some(player)) => player,
+            Ok(None) => return Ok(LoginResult(None)),
+            Err(RpcRequestError::LoginTimeout) => {
+                warn!(?client_id, task_id, "Login task timed out; aborting it");
+                if let Err(e) = self.scheduler.abort_task(task_id) {
+                    warn!(error = ?e, task_id, "Could not abort timed out login task");
                 }
+                return Err(RpcRequestError::LoginTimeout);
             }
-            Ok(TaskResult::Error(e)) => {
-                error!(error = ?e, "Error waiting for login results");
-
-                return Err(RpcRequestError::LoginTaskFailed);
-            }
-            Err(e) => {
-                error!(error = ?e, "Error waiting for login results");
-
-                return Err(RpcRequestError::InternalError(e.to_string()));
-            }
+            Err(e) => return Err(e),
         };
 
         // Update the connection records.
@@ -549,7 +1361,9 @@ impl RpcServer {
             ));
         };
 
+        let mut connect_type = connect_type;
         if attach {
+            connect_type = self.take_over_connections(client_id, player, connect_type);
             trace!(?player, "Submitting user_connected task");
             if let Err(e) = self
                 .clone()
@@ -562,9 +1376,50 @@ impl RpcServer {
             }
         }
 
-        let auth_token = self.make_auth_token(player);
+        let auth_token = self.tokens.make_auth_token(player);
 
-        Ok(LoginResult(Some((auth_token, connect_type, player))))
+        if version < LOGIN_SUCCEEDED_PROTOCOL_VERSION {
+            return Ok(LoginResult(Some((auth_token, connect_type, player))));
+        }
+        let metadata = metadata.lock().unwrap().take();
+        Ok(RpcResponse::LoginSucceeded(
+            auth_token,
+            connect_type,
+            player,
+            metadata,
+        ))
+    }
+
+    /// If the core has asked for connection takeover (`$server_options.connection_takeover`), and
+    /// the player already has connections other than this client's, drop those and report the
+    /// connection as `Redirected` instead.
+    fn take_over_connections(
+        &self,
+        client_id: Uuid,
+        player: Objid,
+        connect_type: ConnectType,
+    ) -> ConnectType {
+        if connect_type != ConnectType::Connected
+            || !self.scheduler.server_options().connection_takeover
+        {
+            return connect_type;
+        }
+        let Ok(client_ids) = self.connections.client_ids_for(player) else {
+            return connect_type;
+        };
+        let previous: Vec<_> = client_ids
+            .into_iter()
+            .filter(|other| *other != client_id)
+            .collect();
+        if previous.is_empty() {
+            return connect_type;
+        }
+        for other in previous {
+            if let Err(e) = self.disconnect_client(other) {
+                warn!(?player, client_id = ?other, error = ?e, "Could not drop connection being taken over");
+            }
+        }
+        ConnectType::Redirected
     }
 
     fn submit_connected_task(
@@ -582,6 +1437,7 @@ impl RpcServer {
             ConnectType::Connected => "user_connected".to_string(),
             ConnectType::Reconnected => "user_reconnected".to_string(),
             ConnectType::Created => "user_created".to_string(),
+            ConnectType::Redirected => "user_redirected".to_string(),
         };
         self.scheduler
             .submit_verb_task(
@@ -597,12 +1453,53 @@ impl RpcServer {
         Ok(())
     }
 
+    fn submit_disconnected_task(
+        self: Arc<Self>,
+        client_id: Uuid,
+        player: Objid,
+    ) -> Result<(), eyre::Error> {
+        let session = self
+            .clone()
+            .new_session(client_id, player)
+            .with_context(|| "could not create 'disconnected' task session for player")?;
+
+        self.scheduler
+            .submit_verb_task(
+                player,
+                SYSTEM_OBJECT,
+                "user_disconnected".to_string(),
+                vec![v_objid(player)],
+                "".to_string(),
+                SYSTEM_OBJECT,
+                session,
+            )
+            .with_context(|| "could not submit 'disconnected' task")?;
+        Ok(())
+    }
+
+    /// Refuse to start a task for `player` if they've been starting them too quickly. Wizards
+    /// are exempt.
+    fn check_task_rate(&self, player: Objid) -> Result<(), RpcRequestError> {
+        let admitted = self
+            .task_rate_limiter
+            .lock()
+            .unwrap()
+            .admit(player, Instant::now());
+        if !admitted && !self.is_wizard(player)? {
+            warn!(?player, "Player is starting tasks too quickly; throttling");
+            return Err(RpcRequestError::TooManyRequests);
+        }
+        Ok(())
+    }
+
     fn perform_command(
         self: Arc<Self>,
         client_id: Uuid,
         connection: Objid,
         command: String,
     ) -> Result<RpcResponse, RpcRequestError> {
+        self.check_task_rate(connection)?;
+
         let Ok(session) = self.clone().new_session(client_id, connection) else {
             return Err(RpcRequestError::CreateSessionFailed);
         };
@@ -629,6 +1526,7 @@ impl RpcServer {
             session.clone(),
         ) {
             let task_id = do_command_task_handle.task_id();
+            self.track_client_task(client_id, task_id);
             if let Ok(value) = self.clone().watch_command_task(do_command_task_handle) {
                 if value != v_bool(false) {
                     return Ok(RpcResponse::CommandSubmitted(task_id));
@@ -660,9 +1558,9 @@ impl RpcServer {
                 }
             };
 
-        Ok(RpcResponse::CommandSubmitted(
-            parse_command_task_handle.task_id(),
-        ))
+        let task_id = parse_command_task_handle.task_id();
+        self.track_client_task(client_id, task_id);
+        Ok(RpcResponse::CommandSubmitted(task_id))
     }
 
     fn respond_input(
@@ -740,7 +1638,9 @@ impl RpcServer {
         // let the session run to completion on its own and output back to the client.
         // Maybe we should be returning a value from this for the future, but the way clients are
         // written right now, there's little point.
-        Ok(RpcResponse::CommandSubmitted(task_handle.task_id()))
+        let task_id = task_handle.task_id();
+        self.track_client_task(client_id, task_id);
+        Ok(RpcResponse::CommandSubmitted(task_id))
     }
 
     fn eval(
@@ -749,6 +1649,8 @@ impl RpcServer {
         connection: Objid,
         expression: String,
     ) -> Result<RpcResponse, RpcRequestError> {
+        self.check_task_rate(connection)?;
+
         let Ok(session) = self.clone().new_session(client_id, connection) else {
             return Err(RpcRequestError::CreateSessionFailed);
         };
@@ -782,6 +1684,38 @@ impl RpcServer {
         }
     }
 
+    fn compile_check(
+        self: Arc<Self>,
+        connection: Objid,
+        code: Vec<String>,
+    ) -> Result<RpcResponse, RpcRequestError> {
+        match self.scheduler.check_program(connection, code) {
+            Ok(()) => Ok(RpcResponse::CompileCheckPassed),
+            Err(SchedulerError::VerbProgramFailed(e)) => Err(RpcRequestError::VerbProgramFailed(e)),
+            Err(SchedulerError::CommandExecutionError(e)) => Err(RpcRequestError::CommandError(e)),
+            Err(e) => Err(RpcRequestError::InternalError(e.to_string())),
+        }
+    }
+
+    fn disassemble_verb(
+        &self,
+        connection: Objid,
+        object: String,
+        verb: String,
+    ) -> Result<RpcResponse, RpcRequestError> {
+        match self
+            .scheduler
+            .disassemble_verb(connection, connection, object, verb)
+        {
+            Ok(disassembly) => Ok(RpcResponse::VerbDisassembly(disassembly)),
+            Err(SchedulerError::CommandExecutionError(CommandError::PermissionDenied)) => {
+                Err(RpcRequestError::PermissionDenied)
+            }
+            Err(SchedulerError::CommandExecutionError(e)) => Err(RpcRequestError::CommandError(e)),
+            Err(e) => Err(RpcRequestError::InternalError(e.to_string())),
+        }
+    }
+
     fn program_verb(
         self: Arc<Self>,
         client_id: Uuid,
@@ -789,6 +1723,7 @@ impl RpcServer {
         object: String,
         verb: String,
         code: Vec<String>,
+        diagnostics: bool,
     ) -> Result<RpcResponse, RpcRequestError> {
         if self.clone().new_session(client_id, connection).is_err() {
             return Err(RpcRequestError::CreateSessionFailed);
@@ -800,6 +1735,14 @@ impl RpcServer {
             .program_verb(connection, connection, object, verb, code)
         {
             Ok((obj, verb)) => Ok(RpcResponse::ProgramSuccess(obj, verb)),
+            // Older clients only know how to show compilation failures as lines of text.
+            Err(SchedulerError::VerbProgramFailed(VerbProgramError::Diagnostics(d)))
+                if !diagnostics =>
+            {
+                Err(RpcRequestError::VerbProgramFailed(
+                    VerbProgramError::CompilationError(d.iter().map(|d| d.to_string()).collect()),
+                ))
+            }
             Err(SchedulerError::VerbProgramFailed(e)) => Err(RpcRequestError::VerbProgramFailed(e)),
             Err(e) => {
                 error!(error = ?e, "Error processing increment");
@@ -809,18 +1752,27 @@ impl RpcServer {
         }
     }
 
+    /// The events published to `player` after the one numbered `seq`, as far back as we have them.
+    fn narrative_history_since(&self, player: Objid, seq: u64) -> RpcResponse {
+        RpcResponse::History(self.narrative_history.lock().unwrap().since(player, seq))
+    }
+
+    /// Send each event to `player`'s connections, or just to the given one of them.
     pub(crate) fn publish_narrative_events(
         &self,
-        events: &[(Objid, NarrativeEvent)],
+        events: &[(Objid, Option<Uuid>, NarrativeEvent)],
     ) -> Result<(), Error> {
-        let publish = self.publish.lock().unwrap();
-        for (player, event) in events {
-            let client_ids = self.connections.client_ids_for(*player)?;
-            let event = ConnectionEvent::Narrative(*player, event.clone());
+        // Numbered under the events lock, so the numbering is the order they go out in.
+        let mut publisher = self.events.lock().unwrap();
+        let mut history = self.narrative_history.lock().unwrap();
+        for (player, only_client, event) in events {
+            let client_ids =
+                narrative_recipients(self.connections.as_ref(), *player, *only_client)?;
+            let event = history.record(*player, event.clone());
+            let event = ConnectionEvent::Narrative(*player, event);
             let event_bytes = bincode::encode_to_vec(&event, bincode::config::standard())?;
             for client_id in &client_ids {
-                let payload = vec![client_id.as_bytes().to_vec(), event_bytes.clone()];
-                publish.send_multipart(payload, 0).map_err(|e| {
+                publisher.send_to(*client_id, &event_bytes).map_err(|e| {
                     error!(error = ?e, "Unable to send narrative event");
                     DeliveryError
                 })?;
@@ -829,19 +1781,42 @@ impl RpcServer {
         Ok(())
     }
 
+    /// Send a presence signal about `who` to every connection `player` has. Hosts which can't
+    /// show presence drop it.
+    pub(crate) fn publish_presence(
+        &self,
+        player: Objid,
+        who: Objid,
+        kind: PresenceKind,
+    ) -> Result<(), SessionError> {
+        let client_ids = self.connections.client_ids_for(player)?;
+        let event = ConnectionEvent::Presence(who, kind);
+        let event_bytes = bincode::encode_to_vec(event, bincode::config::standard())
+            .expect("Unable to serialize presence event");
+        let mut events = self.events.lock().unwrap();
+        for client_id in &client_ids {
+            events.send_to(*client_id, &event_bytes).map_err(|e| {
+                error!(error = ?e, "Unable to send presence event");
+                DeliveryError
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Send a system message to every one of the player's connections.
     pub(crate) fn send_system_message(
         &self,
-        client_id: Uuid,
         player: Objid,
         message: String,
+        content_type: Option<String>,
     ) -> Result<(), SessionError> {
-        let event = ConnectionEvent::SystemMessage(player, message);
+        let client_ids = self.connections.client_ids_for(player)?;
+        let event = ConnectionEvent::SystemMessage(player, message, content_type);
         let event_bytes = bincode::encode_to_vec(event, bincode::config::standard())
             .expect("Unable to serialize system message");
-        let payload = vec![client_id.as_bytes().to_vec(), event_bytes];
-        {
-            let publish = self.publish.lock().unwrap();
-            publish.send_multipart(payload, 0).map_err(|e| {
+        let mut events = self.events.lock().unwrap();
+        for client_id in &client_ids {
+            events.send_to(*client_id, &event_bytes).map_err(|e| {
                 error!(error = ?e, "Unable to send system message");
                 DeliveryError
             })?;
@@ -861,21 +1836,23 @@ impl RpcServer {
         // Mark this client as in `input mode`, which means that instead of dispatching its next
         // line to the scheduler as a command, it should instead dispatch it as an input event.
 
-        // Validate first.
-        let Some(connection) = self.connections.connection_object_for_client(client_id) else {
-            return Err(SessionError::NoConnectionForPlayer(player));
+        // If the input is wanted from the task's own connection, ask just that client. Otherwise
+        // (a wizard reading from someone else's connection) ask all of that player's clients;
+        // whichever answers first wins.
+        let client_ids = match self.connections.connection_object_for_client(client_id) {
+            Some(connection) if connection == player => vec![client_id],
+            _ => self.connections.client_ids_for(player)?,
         };
-        if connection != player {
+        if client_ids.is_empty() {
             return Err(SessionError::NoConnectionForPlayer(player));
         }
 
         let event = ConnectionEvent::RequestInput(input_request_id.as_u128());
         let event_bytes = bincode::encode_to_vec(event, bincode::config::standard())
             .expect("Unable to serialize input request");
-        let payload = vec![client_id.as_bytes().to_vec(), event_bytes];
-        {
-            let publish = self.publish.lock().unwrap();
-            publish.send_multipart(payload, 0).map_err(|e| {
+        let mut events = self.events.lock().unwrap();
+        for client_id in client_ids {
+            events.send_to(client_id, &event_bytes).map_err(|e| {
                 error!(error = ?e, "Unable to send input request");
                 DeliveryError
             })?;
@@ -883,180 +1860,108 @@ impl RpcServer {
         Ok(())
     }
 
-    fn ping_pong(&self) -> Result<(), SessionError> {
-        let event = BroadcastEvent::PingPong(SystemTime::now());
-        let event_bytes = bincode::encode_to_vec(event, bincode::config::standard()).unwrap();
-
-        // We want responses from all clients, so send on this broadcast "topic"
-        let payload = vec![BROADCAST_TOPIC.to_vec(), event_bytes];
-        {
-            let publish = self.publish.lock().unwrap();
-            publish.send_multipart(payload, 0).map_err(|e| {
-                error!(error = ?e, "Unable to send PingPong to client");
-                DeliveryError
-            })?;
-        }
-        self.connections.ping_check();
-        Ok(())
-    }
-
-    /// Construct a PASETO token for this client_id and player combination. This token is used to
-    /// validate the client connection to the daemon for future requests.
-    fn make_client_token(&self, client_id: Uuid) -> ClientToken {
-        let privkey: PasetoAsymmetricPrivateKey<V4, Public> =
-            PasetoAsymmetricPrivateKey::from(self.keypair.as_ref());
-        let token = Paseto::<V4, Public>::default()
-            .set_footer(Footer::from(MOOR_SESSION_TOKEN_FOOTER))
-            .set_payload(Payload::from(
-                json!({
-                    "client_id": client_id.to_string(),
-                    "iss": "moor",
-                    "aud": "moor_connection",
-                })
-                .to_string()
-                .as_str(),
-            ))
-            .try_sign(&privkey)
-            .expect("Unable to build Paseto token");
-
-        ClientToken(token)
-    }
-
-    /// Construct a PASETO token for this player login. This token is used to provide credentials
-    /// for requests, to allow reconnection with a different client_id.
-    fn make_auth_token(&self, oid: Objid) -> AuthToken {
-        let privkey = PasetoAsymmetricPrivateKey::from(self.keypair.as_ref());
-        let token = Paseto::<V4, Public>::default()
-            .set_footer(Footer::from(MOOR_AUTH_TOKEN_FOOTER))
-            .set_payload(Payload::from(
-                json!({
-                    "player": oid.0,
-                })
-                .to_string()
-                .as_str(),
-            ))
-            .try_sign(&privkey)
-            .expect("Unable to build Paseto token");
-        AuthToken(token)
-    }
-
-    /// Validate the provided PASETO token against the provided client id
-    /// If they do not match, the request is rejected, permissions denied.
-    fn validate_client_token(
+    /// Remove the given outbound connection request, if it's still waiting and was claimed by
+    /// `client_id`, returning who to tell about the outcome.
+    fn take_outbound_request(
         &self,
-        token: ClientToken,
+        request_id: u128,
         client_id: Uuid,
-    ) -> Result<(), SessionError> {
-        let key: Key<32> = Key::from(&self.keypair[32..]);
-        let pk: PasetoAsymmetricPublicKey<V4, Public> = PasetoAsymmetricPublicKey::from(&key);
-        let verified_token = Paseto::<V4, Public>::try_verify(
-            token.0.as_str(),
-            &pk,
-            Footer::from(MOOR_SESSION_TOKEN_FOOTER),
-            None,
-        )
-        .map_err(|e| {
-            warn!(error = ?e, "Unable to parse/validate token");
-            SessionError::InvalidToken
-        })?;
-
-        let verified_token = serde_json::from_str::<serde_json::Value>(verified_token.as_str())
-            .map_err(|e| {
-                warn!(error = ?e, "Unable to parse/validate token");
-                SessionError::InvalidToken
-            })?;
-
-        // Does the token match the client it came from? If not, reject it.
-        let Some(token_client_id) = verified_token.get("client_id") else {
-            debug!("Token does not contain client_id");
-            return Err(SessionError::InvalidToken);
-        };
-        let Some(token_client_id) = token_client_id.as_str() else {
-            debug!("Token client_id is null");
-            return Err(SessionError::InvalidToken);
-        };
-        let Ok(token_client_id) = Uuid::parse_str(token_client_id) else {
-            debug!("Token client_id is not a valid UUID");
-            return Err(SessionError::InvalidToken);
-        };
-        if client_id != token_client_id {
-            debug!(
+    ) -> Option<oneshot::Sender<Result<Objid, String>>> {
+        let request_id = Uuid::from_u128(request_id);
+        let mut outbound_requests = self.outbound_requests.lock().unwrap();
+        if outbound_requests.get(&request_id)?.claimed_by != Some(client_id) {
+            warn!(
                 ?client_id,
-                ?token_client_id,
-                "Token client_id does not match client_id"
+                ?request_id,
+                "Outbound connection report from a host which didn't claim it"
             );
-            return Err(SessionError::InvalidToken);
+            return None;
         }
-
-        Ok(())
+        outbound_requests
+            .remove(&request_id)
+            .map(|request| request.waiter)
     }
 
-    /// Validate that the provided PASETO token is valid.
-    /// If a player id is provided, validate it matches the player id.
-    /// Return the player id if it is valid.
-    /// Note that this is merely validating that the token is valid, not that the actual player
-    /// inside the token is valid and has the capabilities it thinks it has. That must be done in
-    /// the runtime itself.
-    fn validate_auth_token(
+    /// Ask the hosts to open an outbound connection, and wait for the one which claims it to
+    /// report back with the connection object it was given.
+    pub(crate) fn open_network_connection(
         &self,
-        token: AuthToken,
-        objid: Option<Objid>,
+        host: &str,
+        port: u16,
     ) -> Result<Objid, SessionError> {
-        let key: Key<32> = Key::from(&self.keypair[32..]);
-        let pk: PasetoAsymmetricPublicKey<V4, Public> = PasetoAsymmetricPublicKey::from(&key);
-        let verified_token = Paseto::<V4, Public>::try_verify(
-            token.0.as_str(),
-            &pk,
-            Footer::from(MOOR_AUTH_TOKEN_FOOTER),
-            None,
-        )
-        .map_err(|e| {
-            warn!(error = ?e, "Unable to parse/validate token");
-            SessionError::InvalidToken
-        })?;
-
-        let verified_token = serde_json::from_str::<serde_json::Value>(verified_token.as_str())
-            .map_err(|e| {
-                warn!(error = ?e, "Unable to parse/validate token");
-                SessionError::InvalidToken
-            })
-            .unwrap();
+        let request_id = Uuid::new_v4();
+        let (send, receive) = oneshot::channel();
+        self.outbound_requests.lock().unwrap().insert(
+            request_id,
+            OutboundRequest {
+                waiter: send,
+                claimed_by: None,
+            },
+        );
 
-        let Some(token_player) = verified_token.get("player") else {
-            debug!("Token does not contain player");
-            return Err(SessionError::InvalidToken);
+        let event = BroadcastEvent::OpenConnection {
+            request_id: request_id.as_u128(),
+            host: host.to_string(),
+            port,
         };
-        let Some(token_player) = token_player.as_i64() else {
-            debug!("Token player is not valid");
-            return Err(SessionError::InvalidToken);
-        };
-        let token_player = Objid(token_player);
-        if let Some(objid) = objid {
-            // Does the 'player' match objid? If not, reject it.
-            if objid != token_player {
-                debug!(?objid, ?token_player, "Token player does not match objid");
-                return Err(SessionError::InvalidToken);
+        let event_bytes = bincode::encode_to_vec(event, bincode::config::standard()).unwrap();
+        {
+            let mut events = self.events.lock().unwrap();
+            if !matches!(events.broadcast(&event_bytes), Ok(true)) {
+                error!("Unable to send outbound connection request");
+                self.outbound_requests.lock().unwrap().remove(&request_id);
+                return Err(DeliveryError);
             }
         }
 
-        // TODO: we will need to verify that the player object id inside the token is valid inside
-        //   moor itself. And really only something with a WorldState can do that. So it's not
-        //   enough to have validated the auth token here, we will need to pepper the scheduler/task
-        //   code with checks to make sure that the player objid is valid before letting it go
-        //   forwards.
+        let result = receive.recv_timeout(OUTBOUND_CONNECTION_TIMEOUT);
+        self.outbound_requests.lock().unwrap().remove(&request_id);
+        match result {
+            Ok(Ok(connection)) => Ok(connection),
+            Ok(Err(reason)) => Err(SessionError::OutboundConnectionFailed(reason)),
+            Err(_) => Err(SessionError::OutboundConnectionFailed(
+                "no host responded".to_string(),
+            )),
+        }
+    }
+
+    fn ping_pong(&self) -> Result<(), SessionError> {
+        let event = BroadcastEvent::PingPong(SystemTime::now());
+        let event_bytes = bincode::encode_to_vec(event, bincode::config::standard()).unwrap();
 
-        Ok(token_player)
+        // We want responses from all clients, so send on this broadcast "topic"
+        let sent = {
+            let mut events = self.events.lock().unwrap();
+            events.broadcast(&event_bytes).map_err(|e| {
+                error!(error = ?e, "Unable to send PingPong to client");
+                DeliveryError
+            })?
+        };
+        // If some host is too backed up for the ping to go out, nobody got a chance to answer it,
+        // so don't prune anybody for not answering.
+        if sent {
+            self.connections.ping_check();
+            self.prune_hosts(Instant::now());
+        }
+        self.task_rate_limiter.lock().unwrap().prune(Instant::now());
+        Ok(())
     }
 }
 
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn zmq_loop(
-    keypair: Key<64>,
+    tokens: Tokens,
     connections_db_path: PathBuf,
+    connections_redis: Option<(String, String)>,
     wss: Arc<dyn WorldStateSource>,
     scheduler: Arc<Scheduler>,
     rpc_endpoint: String,
     narrative_endpoint: String,
+    narrative_compress_threshold: Option<usize>,
+    task_rate_limit: Option<u32>,
+    idle_timeout: Option<Duration>,
+    reverse_dns: bool,
+    login_timeout: Duration,
     num_threads: Option<i32>,
     kill_switch: Arc<AtomicBool>,
     db_flavour: DatabaseFlavour,
@@ -1067,10 +1972,16 @@ pub(crate) fn zmq_loop(
     }
 
     let rpc_server = Arc::new(RpcServer::new(
-        keypair,
+        tokens,
         connections_db_path,
+        connections_redis,
         zmq_ctx.clone(),
         &narrative_endpoint,
+        narrative_compress_threshold,
+        task_rate_limit,
+        idle_timeout,
+        reverse_dns,
+        login_timeout,
         wss,
         scheduler,
         db_flavour,
@@ -1083,6 +1994,7 @@ pub(crate) fn zmq_loop(
         .spawn(move || loop {
             std::thread::sleep(std::time::Duration::from_secs(5));
             t_rpc_server.ping_pong().expect("Unable to play ping-pong");
+            t_rpc_server.clone().reap_idle_connections();
         })?;
 
     // We need to bind a generic publisher to the narrative endpoint, so that subsequent sessions
@@ -1115,26 +2027,25 @@ pub(crate) fn zmq_loop(
             Ok(request) => {
                 trace!(num_parts = request.len(), "ZQM Request received");
 
-                // Components are:
-                if request.len() != 2 {
-                    error!("Invalid request received, ignoring");
-
-                    rpc_socket.send_multipart(
-                        vec![make_response(Err(RpcRequestError::InvalidRequest))],
-                        0,
-                    )?;
-                    continue;
-                }
-
-                if request.len() != 2 {
-                    rpc_socket.send_multipart(
-                        vec![make_response(Err(RpcRequestError::InvalidRequest))],
-                        0,
-                    )?;
-                    continue;
-                }
+                // Components are: client id, protocol version (absent from hosts which predate
+                // versioning), and the request itself.
+                let (client_id, version, request_body) = match &request[..] {
+                    [client_id, request_body] => (client_id, 0, request_body),
+                    [client_id, version, request_body] if version.len() == 2 => (
+                        client_id,
+                        u16::from_be_bytes([version[0], version[1]]),
+                        request_body,
+                    ),
+                    _ => {
+                        error!("Invalid request received, ignoring");
 
-                let (client_id, request_body) = (&request[0], &request[1]);
+                        rpc_socket.send_multipart(
+                            vec![make_response(Err(RpcRequestError::InvalidRequest))],
+                            0,
+                        )?;
+                        continue;
+                    }
+                };
 
                 let Ok(client_id) = Uuid::from_slice(client_id) else {
                     rpc_socket.send_multipart(
@@ -1144,25 +2055,720 @@ pub(crate) fn zmq_loop(
                     continue;
                 };
 
-                // Decode 'request_body' as a bincode'd ClientEvent.
-                let request =
-                    match bincode::decode_from_slice(request_body, bincode::config::standard()) {
-                        Ok((request, _)) => request,
-                        Err(_) => {
-                            rpc_socket.send_multipart(
-                                vec![make_response(Err(RpcRequestError::InvalidRequest))],
-                                0,
-                            )?;
-
-                            continue;
-                        }
-                    };
+                // Decode 'request_body' as a bincode'd RpcRequest.
+                let request = match decode_request(version, request_body) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        warn!(?client_id, version, error = ?e, "Unable to decode request");
+                        rpc_socket.send_multipart(vec![make_response(Err(e))], 0)?;
+
+                        continue;
+                    }
+                };
 
                 // The remainder of the payload are all the request arguments, which vary depending
                 // on the type.
-                let response = rpc_server.clone().process_request(client_id, request);
+                let response = rpc_server
+                    .clone()
+                    .process_request(client_id, version, request);
                 rpc_socket.send_multipart(vec![response], 0)?;
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use ed25519_dalek::SigningKey;
+    use moor_db::DatabaseFlavour;
+    use moor_db_wiredtiger::WiredTigerDatabaseBuilder;
+    use moor_kernel::config::Config;
+    use moor_kernel::tasks::scheduler::{Scheduler, SchedulerError, TaskResult};
+    use moor_kernel::tasks::sessions::NoopClientSession;
+    use moor_kernel::SERVER_VERSION;
+    use moor_values::model::{Event, HostType, ListenerPoint, NarrativeEvent, ObjFlag};
+    use moor_values::util::BitEnum;
+    use moor_values::var::Objid;
+    use moor_values::var::{v_bool, v_int, v_list, v_objid, v_str};
+    use moor_values::{NOTHING, SYSTEM_OBJECT};
+    use rand::rngs::OsRng;
+    use rpc_common::{
+        decode_connection_event, ConnectType, ConnectionEvent, PlayerMetadata, RpcRequest,
+        RpcRequestError, RpcResponse, RpcResult, ServerInfo, LOGIN_SUCCEEDED_PROTOCOL_VERSION,
+        RPC_PROTOCOL_VERSION,
+    };
+    use rusty_paseto::core::Key;
+    use uuid::Uuid;
+
+    use crate::connections::{
+        TaskRateLimiter, CONNECTION_TIMEOUT_DURATION, NARRATIVE_HISTORY_LENGTH,
+    };
+    use crate::rpc_server::{await_login, player_metadata, resolve_sysprop_path, RpcServer};
+    use crate::tokens::Tokens;
+
+    #[test]
+    fn test_dotted_sysprop_paths() {
+        let (db, _) = WiredTigerDatabaseBuilder::new().open_db().unwrap();
+        let source = db.world_state_source().unwrap();
+
+        let mut tx = source.new_world_state().unwrap();
+        let sysobj = tx
+            .create_object(SYSTEM_OBJECT, NOTHING, SYSTEM_OBJECT, BitEnum::all())
+            .unwrap();
+        let login = tx
+            .create_object(SYSTEM_OBJECT, NOTHING, SYSTEM_OBJECT, BitEnum::all())
+            .unwrap();
+        tx.define_property(
+            SYSTEM_OBJECT,
+            sysobj,
+            sysobj,
+            "login",
+            SYSTEM_OBJECT,
+            BitEnum::all(),
+            Some(v_objid(login)),
+        )
+        .unwrap();
+        tx.define_property(
+            SYSTEM_OBJECT,
+            login,
+            login,
+            "welcome_message",
+            SYSTEM_OBJECT,
+            BitEnum::all(),
+            Some(v_str("Welcome!")),
+        )
+        .unwrap();
+        // $gone points at an object that doesn't exist, and $count isn't an object at all.
+        tx.define_property(
+            SYSTEM_OBJECT,
+            sysobj,
+            sysobj,
+            "gone",
+            SYSTEM_OBJECT,
+            BitEnum::all(),
+            Some(v_objid(NOTHING)),
+        )
+        .unwrap();
+        tx.define_property(
+            SYSTEM_OBJECT,
+            sysobj,
+            sysobj,
+            "count",
+            SYSTEM_OBJECT,
+            BitEnum::all(),
+            Some(v_int(5)),
+        )
+        .unwrap();
+        tx.commit().unwrap();
+
+        let tx = source.new_world_state().unwrap();
+        assert_eq!(
+            resolve_sysprop_path(tx.as_ref(), "login.welcome_message"),
+            Some(v_str("Welcome!"))
+        );
+        assert_eq!(
+            resolve_sysprop_path(tx.as_ref(), "login"),
+            Some(v_objid(login))
+        );
+        assert_eq!(
+            resolve_sysprop_path(tx.as_ref(), "gone.welcome_message"),
+            None
+        );
+        assert_eq!(
+            resolve_sysprop_path(tx.as_ref(), "missing.welcome_message"),
+            None
+        );
+        assert_eq!(
+            resolve_sysprop_path(tx.as_ref(), "count.welcome_message"),
+            None
+        );
+        assert_eq!(resolve_sysprop_path(tx.as_ref(), "login.missing"), None);
+    }
+
+    #[test]
+    fn test_login_metadata() {
+        let (db, _) = WiredTigerDatabaseBuilder::new().open_db().unwrap();
+        let source = db.world_state_source().unwrap();
+
+        let mut tx = source.new_world_state().unwrap();
+        let room = tx
+            .create_object(SYSTEM_OBJECT, NOTHING, SYSTEM_OBJECT, BitEnum::all())
+            .unwrap();
+        let player = tx
+            .create_object(
+                SYSTEM_OBJECT,
+                NOTHING,
+                SYSTEM_OBJECT,
+                BitEnum::new_with(ObjFlag::User) | ObjFlag::Programmer,
+            )
+            .unwrap();
+        tx.update_property(SYSTEM_OBJECT, player, "name", &v_str("Bob"))
+            .unwrap();
+        tx.move_object(SYSTEM_OBJECT, player, room).unwrap();
+        tx.commit().unwrap();
+
+        let tx = source.new_world_state().unwrap();
+        assert_eq!(
+            player_metadata(tx.as_ref(), player),
+            Some(PlayerMetadata {
+                name: "Bob".to_string(),
+                location: room,
+                programmer: true,
+                wizard: false,
+            })
+        );
+        assert_eq!(player_metadata(tx.as_ref(), NOTHING), None);
+    }
+
+    #[test]
+    fn test_login_response_depends_on_host_version() {
+        let connections_dir = tempfile::tempdir().unwrap();
+        let (rpc_server, scheduler, wizard) = test_rpc_server(
+            connections_dir.path(),
+            zmq::Context::new(),
+            "inproc://test-login-response",
+        );
+        let loop_scheduler = scheduler.clone();
+        let scheduler_loop_jh = std::thread::spawn(move || loop_scheduler.run());
+
+        let mut tx = rpc_server.world_state_source.new_world_state().unwrap();
+        let player = tx
+            .create_object(
+                SYSTEM_OBJECT,
+                NOTHING,
+                SYSTEM_OBJECT,
+                BitEnum::new_with(ObjFlag::User) | ObjFlag::Programmer,
+            )
+            .unwrap();
+        tx.update_property(SYSTEM_OBJECT, player, "name", &v_str("Bob"))
+            .unwrap();
+        tx.commit().unwrap();
+        let program_login = format!(
+            r#"add_verb(#0, {{{wizard}, "rxd", "do_login_command"}}, {{"this", "none", "this"}});
+               set_verb_code(#0, "do_login_command", {{"return {player};"}});"#
+        );
+        rpc_server
+            .clone()
+            .eval(Uuid::new_v4(), wizard, program_login)
+            .unwrap();
+
+        let login = |version| {
+            let client_id = Uuid::new_v4();
+            let connection = rpc_server
+                .connections
+                .new_connection(client_id, "test".to_string(), None)
+                .unwrap();
+            rpc_server.clone().perform_login(
+                client_id,
+                version,
+                connection,
+                vec!["connect".to_string(), "Bob".to_string()],
+                false,
+            )
+        };
+
+        // Hosts which know about it hear who the player is along with the login.
+        let Ok(RpcResponse::LoginSucceeded(_, ConnectType::Connected, logged_in, metadata)) =
+            login(LOGIN_SUCCEEDED_PROTOCOL_VERSION)
+        else {
+            panic!("Expected LoginSucceeded");
+        };
+        assert_eq!(logged_in, player);
+        assert_eq!(
+            metadata,
+            Some(PlayerMetadata {
+                name: "Bob".to_string(),
+                location: NOTHING,
+                programmer: true,
+                wizard: false,
+            })
+        );
+
+        // Older ones get what they always did.
+        let Ok(RpcResponse::LoginResult(Some((_, ConnectType::Connected, logged_in)))) =
+            login(LOGIN_SUCCEEDED_PROTOCOL_VERSION - 1)
+        else {
+            panic!("Expected LoginResult");
+        };
+        assert_eq!(logged_in, player);
+
+        scheduler
+            .submit_shutdown(0, Some("Test is done".to_string()))
+            .unwrap();
+        scheduler_loop_jh.join().unwrap();
+    }
+
+    #[test]
+    fn test_login_timeout() {
+        // A login verb that never finishes, e.g. because it's sitting in read().
+        let (_send, receive) = oneshot::channel();
+        assert_eq!(
+            await_login(receive, Duration::from_millis(50)),
+            Err(RpcRequestError::LoginTimeout)
+        );
+    }
+
+    #[test]
+    fn test_login_results() {
+        let (send, receive) = oneshot::channel();
+        send.send(TaskResult::Success(v_objid(Objid(5)))).unwrap();
+        assert_eq!(
+            await_login(receive, Duration::from_secs(1)),
+            Ok(Some(Objid(5)))
+        );
+
+        // Anything other than an object is a bad username or password, not an error.
+        let (send, receive) = oneshot::channel();
+        send.send(TaskResult::Success(v_int(0))).unwrap();
+        assert_eq!(await_login(receive, Duration::from_secs(1)), Ok(None));
+
+        let (send, receive) = oneshot::channel();
+        send.send(TaskResult::Error(SchedulerError::TaskAbortedError))
+            .unwrap();
+        assert_eq!(
+            await_login(receive, Duration::from_secs(1)),
+            Err(RpcRequestError::LoginTaskFailed)
+        );
+    }
+
+    /// An RPC server over a fresh database holding just a wizard, along with its (not yet
+    /// running) scheduler, and that wizard.
+    fn test_rpc_server(
+        connections_dir: &Path,
+        zmq_context: zmq::Context,
+        narrative_endpoint: &str,
+    ) -> (Arc<RpcServer>, Arc<Scheduler>, Objid) {
+        let (db, _) = WiredTigerDatabaseBuilder::new().open_db().unwrap();
+        let source = db.clone().world_state_source().unwrap();
+        let mut tx = source.new_world_state().unwrap();
+        let wizard = tx
+            .create_object(SYSTEM_OBJECT, NOTHING, SYSTEM_OBJECT, BitEnum::all())
+            .unwrap();
+        tx.commit().unwrap();
+
+        let scheduler = Arc::new(Scheduler::new(db, Config::default()));
+        let keypair = Key::from(SigningKey::generate(&mut OsRng).to_keypair_bytes());
+        let rpc_server = Arc::new(RpcServer::new(
+            Tokens::new(keypair, None, None),
+            connections_dir.to_path_buf(),
+            None,
+            zmq_context,
+            narrative_endpoint,
+            None,
+            None,
+            None,
+            false,
+            Duration::from_secs(5),
+            source,
+            scheduler.clone(),
+            DatabaseFlavour::WiredTiger,
+        ));
+        (rpc_server, scheduler, wizard)
+    }
+
+    #[test]
+    fn test_detach_aborts_client_tasks() {
+        let connections_dir = tempfile::tempdir().unwrap();
+        let (rpc_server, scheduler, wizard) = test_rpc_server(
+            connections_dir.path(),
+            zmq::Context::new(),
+            "inproc://test-detach-aborts-client-tasks",
+        );
+        let loop_scheduler = scheduler.clone();
+        let scheduler_loop_jh = std::thread::spawn(move || loop_scheduler.run());
+
+        // A client starts something long-running, and goes away in the middle of it.
+        let client_id = Uuid::new_v4();
+        rpc_server
+            .connections
+            .new_connection(client_id, "test".to_string(), None)
+            .unwrap();
+        let handle = scheduler
+            .submit_eval_task(
+                wizard,
+                wizard,
+                "suspend(60); return 1;".to_string(),
+                Arc::new(NoopClientSession::new()),
+            )
+            .unwrap();
+        let task_id = handle.task_id();
+        rpc_server.track_client_task(client_id, task_id);
+        rpc_server.clone().detach(client_id, false).unwrap();
+
+        // Rather than being left to finish with nobody to see it, the task is aborted.
+        assert!(matches!(
+            handle.into_receiver().recv_timeout(Duration::from_secs(5)),
+            Ok(TaskResult::Error(SchedulerError::TaskAbortedCancelled))
+        ));
+        assert!(!scheduler.task_exists(task_id));
+
+        scheduler
+            .submit_shutdown(0, Some("Test is done".to_string()))
+            .unwrap();
+        scheduler_loop_jh.join().unwrap();
+    }
+
+    #[test]
+    fn test_task_rate_limit_spares_wizards() {
+        let connections_dir = tempfile::tempdir().unwrap();
+        let (rpc_server, scheduler, wizard) = test_rpc_server(
+            connections_dir.path(),
+            zmq::Context::new(),
+            "inproc://test-task-rate-limit-spares-wizards",
+        );
+        *rpc_server.task_rate_limiter.lock().unwrap() = TaskRateLimiter::new(Some(3));
+        let loop_scheduler = scheduler.clone();
+        let scheduler_loop_jh = std::thread::spawn(move || loop_scheduler.run());
+
+        let mut tx = rpc_server.world_state_source.new_world_state().unwrap();
+        let player = tx
+            .create_object(
+                SYSTEM_OBJECT,
+                NOTHING,
+                SYSTEM_OBJECT,
+                BitEnum::new_with(ObjFlag::User) | ObjFlag::Programmer,
+            )
+            .unwrap();
+        tx.commit().unwrap();
+
+        // A programmer gets their burst of evals, and is then told to slow down, commands and all.
+        let client_id = Uuid::new_v4();
+        for _ in 0..3 {
+            assert_eq!(
+                rpc_server
+                    .clone()
+                    .eval(client_id, player, "return 1;".to_string()),
+                Ok(RpcResponse::EvalResult(v_int(1)))
+            );
+        }
+        assert_eq!(
+            rpc_server
+                .clone()
+                .eval(client_id, player, "return 1;".to_string()),
+            Err(RpcRequestError::TooManyRequests)
+        );
+        assert_eq!(
+            rpc_server
+                .clone()
+                .perform_command(client_id, player, "look".to_string()),
+            Err(RpcRequestError::TooManyRequests)
+        );
+
+        // A wizard doing the same is left alone.
+        for _ in 0..10 {
+            assert_eq!(
+                rpc_server
+                    .clone()
+                    .eval(client_id, wizard, "return 1;".to_string()),
+                Ok(RpcResponse::EvalResult(v_int(1)))
+            );
+        }
+
+        scheduler
+            .submit_shutdown(0, Some("Test is done".to_string()))
+            .unwrap();
+        scheduler_loop_jh.join().unwrap();
+    }
+
+    #[test]
+    fn test_system_message_goes_to_the_target_players_connections() {
+        let connections_dir = tempfile::tempdir().unwrap();
+        let zmq_context = zmq::Context::new();
+        let endpoint = "inproc://test-system-message";
+        let (rpc_server, scheduler, wizard) =
+            test_rpc_server(connections_dir.path(), zmq_context.clone(), endpoint);
+        let loop_scheduler = scheduler.clone();
+        let scheduler_loop_jh = std::thread::spawn(move || loop_scheduler.run());
+
+        // The wizard has one connection; the player they notify has two.
+        let other = Objid(100);
+        let wizard_client = Uuid::new_v4();
+        let other_clients = [Uuid::new_v4(), Uuid::new_v4()];
+        let sub = zmq_context.socket(zmq::SUB).unwrap();
+        sub.connect(endpoint).unwrap();
+        sub.set_rcvtimeo(1000).unwrap();
+        for (client_id, player) in [
+            (wizard_client, wizard),
+            (other_clients[0], other),
+            (other_clients[1], other),
+        ] {
+            rpc_server
+                .connections
+                .new_connection(client_id, "test".to_string(), Some(player))
+                .unwrap();
+            rpc_server
+                .events
+                .lock()
+                .unwrap()
+                .set_client_version(client_id, RPC_PROTOCOL_VERSION);
+            sub.set_subscribe(client_id.as_bytes()).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(100));
+
+        let session = rpc_server
+            .clone()
+            .new_session(wizard_client, wizard)
+            .unwrap();
+        let handle = scheduler
+            .submit_eval_task(
+                wizard,
+                wizard,
+                format!("notify_system({}, \"hello\"); return 1;", other),
+                session,
+            )
+            .unwrap();
+        assert!(matches!(
+            handle.into_receiver().recv_timeout(Duration::from_secs(5)),
+            Ok(TaskResult::Success(_))
+        ));
+
+        // Each of the other player's connections gets it, and the wizard's doesn't.
+        let mut recipients = vec![];
+        while let Ok(frames) = sub.recv_multipart(0) {
+            assert_eq!(
+                decode_connection_event(&frames[1]).unwrap(),
+                ConnectionEvent::SystemMessage(other, "hello".to_string(), None)
+            );
+            recipients.push(Uuid::from_slice(&frames[0]).unwrap());
+        }
+        recipients.sort();
+        let mut expected = other_clients.to_vec();
+        expected.sort();
+        assert_eq!(recipients, expected);
+
+        scheduler
+            .submit_shutdown(0, Some("Test is done".to_string()))
+            .unwrap();
+        scheduler_loop_jh.join().unwrap();
+    }
+
+    #[test]
+    fn test_revoking_tokens_disconnects_the_player() {
+        let connections_dir = tempfile::tempdir().unwrap();
+        let zmq_context = zmq::Context::new();
+        let endpoint = "inproc://test-revoke-tokens";
+        let (rpc_server, _scheduler, wizard) =
+            test_rpc_server(connections_dir.path(), zmq_context.clone(), endpoint);
+
+        // A player with a live connection, and a wizard with one of their own.
+        let player = Objid(100);
+        let player_client = Uuid::new_v4();
+        let wizard_client = Uuid::new_v4();
+        let sub = zmq_context.socket(zmq::SUB).unwrap();
+        sub.connect(endpoint).unwrap();
+        sub.set_rcvtimeo(1000).unwrap();
+        for (client_id, who) in [(player_client, player), (wizard_client, wizard)] {
+            rpc_server
+                .connections
+                .new_connection(client_id, "test".to_string(), Some(who))
+                .unwrap();
+            rpc_server
+                .events
+                .lock()
+                .unwrap()
+                .set_client_version(client_id, RPC_PROTOCOL_VERSION);
+            sub.set_subscribe(client_id.as_bytes()).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(100));
+        let client_token = rpc_server.tokens.make_client_token(player_client);
+        let auth_token = rpc_server.tokens.make_auth_token(player);
+
+        rpc_server.revoke_tokens(player);
+
+        // The player's connection is told to go, and the wizard's isn't.
+        let frames = sub.recv_multipart(0).unwrap();
+        assert_eq!(Uuid::from_slice(&frames[0]).unwrap(), player_client);
+        assert_eq!(
+            decode_connection_event(&frames[1]).unwrap(),
+            ConnectionEvent::Disconnect()
+        );
+        assert!(sub.recv_multipart(0).is_err());
+
+        // Which it may as well, since nothing it sends with its old tokens is accepted.
+        let response = rpc_server.clone().process_request(
+            player_client,
+            RPC_PROTOCOL_VERSION,
+            RpcRequest::Command(client_token, auth_token, "look".to_string()),
+        );
+        let (response, _): (RpcResult, _) =
+            bincode::decode_from_slice(&response, bincode::config::standard()).unwrap();
+        assert_eq!(
+            response,
+            RpcResult::Failure(RpcRequestError::PermissionDenied)
+        );
+    }
+
+    #[test]
+    fn test_registered_listeners_until_the_host_goes_quiet() {
+        let connections_dir = tempfile::tempdir().unwrap();
+        let (rpc_server, scheduler, wizard) = test_rpc_server(
+            connections_dir.path(),
+            zmq::Context::new(),
+            "inproc://test-registered-listeners",
+        );
+        let loop_scheduler = scheduler.clone();
+        let scheduler_loop_jh = std::thread::spawn(move || loop_scheduler.run());
+
+        let start = Instant::now();
+        let host_id = Uuid::new_v4();
+        let listener = ListenerPoint {
+            host_type: HostType::Tcp,
+            addr: "127.0.0.1:7777".parse().unwrap(),
+            handler: SYSTEM_OBJECT,
+            print_messages: true,
+            out_of_band_prefix: None,
+        };
+        rpc_server.register_host(host_id, vec![listener.clone()], start);
+
+        // MOO code sees it through `listeners()`.
+        let client_id = Uuid::new_v4();
+        rpc_server
+            .connections
+            .new_connection(client_id, "test".to_string(), Some(wizard))
+            .unwrap();
+        let session = rpc_server.clone().new_session(client_id, wizard).unwrap();
+        let handle = scheduler
+            .submit_eval_task(wizard, wizard, "return listeners();".to_string(), session)
+            .unwrap();
+        let Ok(TaskResult::Success(result)) =
+            handle.into_receiver().recv_timeout(Duration::from_secs(5))
+        else {
+            panic!("listeners() failed");
+        };
+        assert_eq!(
+            result,
+            v_list(&[v_list(&[v_objid(SYSTEM_OBJECT), v_int(7777), v_bool(true)])])
+        );
+
+        // A host which keeps re-registering (as it does when pinged) is kept...
+        let later = start + CONNECTION_TIMEOUT_DURATION;
+        rpc_server.register_host(host_id, vec![listener.clone()], later);
+        rpc_server.prune_hosts(later + Duration::from_secs(5));
+        assert_eq!(rpc_server.listeners(), vec![listener]);
+
+        // ...and one which has gone quiet is forgotten.
+        rpc_server.prune_hosts(later + CONNECTION_TIMEOUT_DURATION + Duration::from_secs(1));
+        assert_eq!(rpc_server.listeners(), vec![]);
+
+        scheduler
+            .submit_shutdown(0, Some("Test is done".to_string()))
+            .unwrap();
+        scheduler_loop_jh.join().unwrap();
+    }
+
+    #[test]
+    fn test_server_info_leaves_out_the_asker() {
+        let connections_dir = tempfile::tempdir().unwrap();
+        let (rpc_server, _scheduler, wizard) = test_rpc_server(
+            connections_dir.path(),
+            zmq::Context::new(),
+            "inproc://test-server-info",
+        );
+
+        // The wizard is logged in twice, someone else is still at the login prompt, and the
+        // client asking has a connection of its own.
+        let asker = Uuid::new_v4();
+        for (client_id, player) in [
+            (Uuid::new_v4(), Some(wizard)),
+            (Uuid::new_v4(), Some(wizard)),
+            (Uuid::new_v4(), None),
+            (asker, None),
+        ] {
+            rpc_server
+                .connections
+                .new_connection(client_id, "test".to_string(), player)
+                .unwrap();
+        }
+        rpc_server.register_host(Uuid::new_v4(), vec![], Instant::now());
+
+        let Ok(RpcResponse::ServerInfo(info)) = rpc_server.server_info(asker) else {
+            panic!("Expected server info");
+        };
+        assert_eq!(
+            info,
+            ServerInfo {
+                version: SERVER_VERSION.to_string(),
+                components: info.components.clone(),
+                start_time: rpc_server.start_time,
+                connected_players: 1,
+                connections: 3,
+                hosts: 1,
+                events_dropped: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_history_recalls_events_in_order() {
+        let connections_dir = tempfile::tempdir().unwrap();
+        let (rpc_server, _scheduler, wizard) = test_rpc_server(
+            connections_dir.path(),
+            zmq::Context::new(),
+            "inproc://test-history",
+        );
+        rpc_server
+            .connections
+            .new_connection(Uuid::new_v4(), "test".to_string(), Some(wizard))
+            .unwrap();
+
+        // More than are kept, as fast as we can, so that plenty share a timestamp; some singly,
+        // some in batches.
+        let emitted: Vec<_> = (0..NARRATIVE_HISTORY_LENGTH + 200)
+            .map(|i| i.to_string())
+            .collect();
+        for (i, chunk) in emitted.chunks(7).enumerate() {
+            if i % 2 == 0 {
+                let events: Vec<_> = chunk
+                    .iter()
+                    .map(|text| {
+                        (
+                            wizard,
+                            None,
+                            NarrativeEvent::notify_text(wizard, text.clone()),
+                        )
+                    })
+                    .collect();
+                rpc_server.publish_narrative_events(&events).unwrap();
+            } else {
+                for text in chunk {
+                    rpc_server
+                        .publish_narrative_events(&[(
+                            wizard,
+                            None,
+                            NarrativeEvent::notify_text(wizard, text.clone()),
+                        )])
+                        .unwrap();
+                }
+            }
+        }
+
+        let texts = |events: &[NarrativeEvent]| -> Vec<String> {
+            events
+                .iter()
+                .map(|e| match &e.event {
+                    Event::TextNotify(text) => text.clone(),
+                    event => panic!("Unexpected event {:?}", event),
+                })
+                .collect()
+        };
+
+        // Everything still kept comes back, in the order it was sent.
+        let RpcResponse::History(recalled) = rpc_server.narrative_history_since(wizard, 0) else {
+            panic!("Expected history");
+        };
+        assert_eq!(texts(&recalled), emitted[200..]);
+        assert!(recalled.windows(2).all(|w| w[0].seq() < w[1].seq()));
+
+        // A client which saw up to some point gets just what came after.
+        let last_seen = recalled[499].seq();
+        let RpcResponse::History(missed) = rpc_server.narrative_history_since(wizard, last_seen)
+        else {
+            panic!("Expected history");
+        };
+        assert_eq!(missed, recalled[500..]);
+    }
+}
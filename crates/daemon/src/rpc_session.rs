@@ -19,8 +19,8 @@ use tracing::trace;
 use uuid::Uuid;
 
 use moor_kernel::tasks::sessions::{Session, SessionError};
-use moor_values::model::NarrativeEvent;
-use moor_values::var::Objid;
+use moor_values::model::{ListenerPoint, NarrativeEvent, PresenceKind};
+use moor_values::var::{Objid, Var};
 
 use crate::rpc_server::RpcServer;
 
@@ -33,7 +33,8 @@ pub struct RpcSession {
     //  should be mmap'd to disk or something.
     // TODO: We could also use Boxcar or other append-only lockless container for this, since we only
     //  ever append.
-    session_buffer: Mutex<Vec<(Objid, NarrativeEvent)>>,
+    /// Events for a player, and the one client they're for if not all of the player's.
+    session_buffer: Mutex<Vec<(Objid, Option<Uuid>, NarrativeEvent)>>,
 }
 
 impl RpcSession {
@@ -87,16 +88,48 @@ impl Session for RpcSession {
     }
 
     fn send_event(&self, player: Objid, event: NarrativeEvent) -> Result<(), SessionError> {
-        self.session_buffer.lock().unwrap().push((player, event));
+        self.session_buffer
+            .lock()
+            .unwrap()
+            .push((player, None, event));
         Ok(())
     }
 
-    fn send_system_msg(&self, player: Objid, msg: &str) -> Result<(), SessionError> {
-        self.rpc_server
-            .send_system_message(self.client_id, player, msg.to_string())?;
+    fn send_connection_event(
+        &self,
+        player: Objid,
+        event: NarrativeEvent,
+    ) -> Result<(), SessionError> {
+        self.session_buffer
+            .lock()
+            .unwrap()
+            .push((player, Some(self.client_id), event));
+        Ok(())
+    }
+
+    fn send_system_msg(
+        &self,
+        player: Objid,
+        msg: &str,
+        content_type: Option<&str>,
+    ) -> Result<(), SessionError> {
+        self.rpc_server.send_system_message(
+            player,
+            msg.to_string(),
+            content_type.map(str::to_string),
+        )?;
         Ok(())
     }
 
+    fn send_presence(
+        &self,
+        player: Objid,
+        who: Objid,
+        kind: PresenceKind,
+    ) -> Result<(), SessionError> {
+        self.rpc_server.publish_presence(player, who, kind)
+    }
+
     fn shutdown(&self, _msg: Option<String>) -> Result<(), SessionError> {
         todo!()
     }
@@ -109,8 +142,28 @@ impl Session for RpcSession {
         self.rpc_server.disconnect(player)
     }
 
-    fn connected_players(&self) -> Result<Vec<Objid>, SessionError> {
-        self.rpc_server.connected_players()
+    fn disconnect_connection(&self, player: Objid) -> Result<(), SessionError> {
+        if self.rpc_server.connection_object_for_client(self.client_id) != Some(player) {
+            return Err(SessionError::NoConnectionForPlayer(player));
+        }
+        self.rpc_server.disconnect_client(self.client_id)
+    }
+
+    fn revoke_tokens(&self, player: Objid) -> Result<(), SessionError> {
+        self.rpc_server.revoke_tokens(player);
+        Ok(())
+    }
+
+    fn listeners(&self) -> Result<Vec<ListenerPoint>, SessionError> {
+        Ok(self.rpc_server.listeners())
+    }
+
+    fn connected_players(
+        &self,
+        include_all: bool,
+        max_idle: Option<f64>,
+    ) -> Result<Vec<Objid>, SessionError> {
+        self.rpc_server.connected_players(include_all, max_idle)
     }
 
     fn connected_seconds(&self, player: Objid) -> Result<f64, SessionError> {
@@ -120,4 +173,22 @@ impl Session for RpcSession {
     fn idle_seconds(&self, player: Objid) -> Result<f64, SessionError> {
         self.rpc_server.idle_seconds_for(player)
     }
+
+    fn open_network_connection(&self, host: &str, port: u16) -> Result<Objid, SessionError> {
+        self.rpc_server.open_network_connection(host, port)
+    }
+
+    fn set_connection_option(
+        &self,
+        player: Objid,
+        option: &str,
+        value: Var,
+    ) -> Result<(), SessionError> {
+        self.rpc_server.set_connection_option(player, option, value);
+        Ok(())
+    }
+
+    fn connection_options(&self, player: Objid) -> Result<Vec<(String, Var)>, SessionError> {
+        Ok(self.rpc_server.connection_options(player))
+    }
 }
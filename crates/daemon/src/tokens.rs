@@ -0,0 +1,454 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Minting and validation of the PASETO tokens handed out to hosts: client tokens, which tie
+//! requests to the connection they came from, and auth tokens, which carry a logged-in player
+//! across reconnects.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rusty_paseto::core::{
+    Footer, Paseto, PasetoAsymmetricPrivateKey, PasetoAsymmetricPublicKey, Payload, Public, V4,
+};
+use rusty_paseto::prelude::Key;
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use moor_kernel::tasks::sessions::SessionError;
+use moor_values::var::Objid;
+use rpc_common::{AuthToken, ClientToken, MOOR_AUTH_TOKEN_FOOTER, MOOR_SESSION_TOKEN_FOOTER};
+
+/// A public key we used to sign tokens with before the current keypair, whose tokens are still
+/// accepted for a while so that rotating keys doesn't log everybody out at once.
+pub struct PreviousKey {
+    pub public_key: [u8; 32],
+    /// When to stop accepting tokens signed with this key. None to keep accepting them until the
+    /// key is taken out of the ring.
+    pub trusted_until: Option<DateTime<Utc>>,
+}
+
+pub struct Tokens {
+    /// What new tokens are signed with.
+    keypair: Key<64>,
+    /// Keys we still accept tokens from, but no longer sign with.
+    previous_keys: Vec<PreviousKey>,
+    /// How long a client token is good for after it's minted. None for forever.
+    client_token_ttl: Option<Duration>,
+    /// How long an auth token is good for after it's minted. None for forever.
+    auth_token_ttl: Option<Duration>,
+    /// Players whose auth tokens were revoked, and when. Their tokens issued before then are no
+    /// longer accepted. This lives only in memory, so restarting the daemon forgets it.
+    revoked: Mutex<HashMap<Objid, DateTime<Utc>>>,
+}
+
+impl Tokens {
+    pub fn new(
+        keypair: Key<64>,
+        client_token_ttl: Option<Duration>,
+        auth_token_ttl: Option<Duration>,
+    ) -> Self {
+        Self {
+            keypair,
+            previous_keys: vec![],
+            client_token_ttl,
+            auth_token_ttl,
+            revoked: Default::default(),
+        }
+    }
+
+    /// Also accept tokens signed with this older key, as part of a key rotation.
+    pub fn with_previous_key(mut self, previous_key: PreviousKey) -> Self {
+        self.previous_keys.push(previous_key);
+        self
+    }
+
+    /// Construct a PASETO token for this client_id and player combination. This token is used to
+    /// validate the client connection to the daemon for future requests.
+    pub fn make_client_token(&self, client_id: Uuid) -> ClientToken {
+        let payload = json!({
+            "client_id": client_id.to_string(),
+            "iss": "moor",
+            "aud": "moor_connection",
+        });
+        ClientToken(self.sign(payload, self.client_token_ttl, MOOR_SESSION_TOKEN_FOOTER))
+    }
+
+    /// Construct a PASETO token for this player login. This token is used to provide credentials
+    /// for requests, to allow reconnection with a different client_id.
+    pub fn make_auth_token(&self, oid: Objid) -> AuthToken {
+        let payload = json!({
+            "player": oid.0,
+            "iat": Utc::now().to_rfc3339(),
+        });
+        AuthToken(self.sign(payload, self.auth_token_ttl, MOOR_AUTH_TOKEN_FOOTER))
+    }
+
+    /// Validate the provided PASETO token against the provided client id
+    /// If they do not match, or the token has expired, the request is rejected, permissions denied.
+    pub fn validate_client_token(
+        &self,
+        token: ClientToken,
+        client_id: Uuid,
+    ) -> Result<(), SessionError> {
+        let verified_token = self.verify(&token.0, MOOR_SESSION_TOKEN_FOOTER)?;
+
+        // Does the token match the client it came from? If not, reject it.
+        let Some(token_client_id) = verified_token.get("client_id") else {
+            debug!("Token does not contain client_id");
+            return Err(SessionError::InvalidToken);
+        };
+        let Some(token_client_id) = token_client_id.as_str() else {
+            debug!("Token client_id is null");
+            return Err(SessionError::InvalidToken);
+        };
+        let Ok(token_client_id) = Uuid::parse_str(token_client_id) else {
+            debug!("Token client_id is not a valid UUID");
+            return Err(SessionError::InvalidToken);
+        };
+        if client_id != token_client_id {
+            debug!(
+                ?client_id,
+                ?token_client_id,
+                "Token client_id does not match client_id"
+            );
+            return Err(SessionError::InvalidToken);
+        }
+
+        Ok(())
+    }
+
+    /// Reject all the auth tokens issued to this player up until now. Tokens minted after this,
+    /// e.g. when they next log in, are unaffected.
+    pub fn revoke_player(&self, player: Objid) {
+        self.revoked.lock().unwrap().insert(player, Utc::now());
+    }
+
+    /// Validate that the provided PASETO token is valid, and hasn't expired or been revoked.
+    /// If a player id is provided, validate it matches the player id.
+    /// Return the player id if it is valid.
+    /// Note that this is merely validating that the token is valid, not that the actual player
+    /// inside the token is valid and has the capabilities it thinks it has. That must be done in
+    /// the runtime itself.
+    pub fn validate_auth_token(
+        &self,
+        token: AuthToken,
+        objid: Option<Objid>,
+    ) -> Result<Objid, SessionError> {
+        let verified_token = self.verify(&token.0, MOOR_AUTH_TOKEN_FOOTER)?;
+
+        let Some(token_player) = verified_token.get("player") else {
+            debug!("Token does not contain player");
+            return Err(SessionError::InvalidToken);
+        };
+        let Some(token_player) = token_player.as_i64() else {
+            debug!("Token player is not valid");
+            return Err(SessionError::InvalidToken);
+        };
+        let token_player = Objid(token_player);
+        if let Some(revoked_at) = self.revoked.lock().unwrap().get(&token_player) {
+            // Tokens without an issue time predate our stamping one, so must predate revocation.
+            let issued_at = verified_token
+                .get("iat")
+                .and_then(|iat| iat.as_str())
+                .and_then(|iat| DateTime::parse_from_rfc3339(iat).ok());
+            if !issued_at.is_some_and(|iat| iat > *revoked_at) {
+                debug!(?token_player, "Token has been revoked");
+                return Err(SessionError::InvalidToken);
+            }
+        }
+        if let Some(objid) = objid {
+            // Does the 'player' match objid? If not, reject it.
+            if objid != token_player {
+                debug!(?objid, ?token_player, "Token player does not match objid");
+                return Err(SessionError::InvalidToken);
+            }
+        }
+
+        // TODO: we will need to verify that the player object id inside the token is valid inside
+        //   moor itself. And really only something with a WorldState can do that. So it's not
+        //   enough to have validated the auth token here, we will need to pepper the scheduler/task
+        //   code with checks to make sure that the player objid is valid before letting it go
+        //   forwards.
+
+        Ok(token_player)
+    }
+
+    /// Hand a still-connected client a fresh client token, and a fresh auth token too if it's
+    /// logged in, provided the ones it has are still good. The new auth token comes with the
+    /// player it's for.
+    pub fn refresh(
+        &self,
+        client_token: ClientToken,
+        client_id: Uuid,
+        auth_token: Option<AuthToken>,
+    ) -> Result<(ClientToken, Option<(AuthToken, Objid)>), SessionError> {
+        self.validate_client_token(client_token, client_id)?;
+        let auth = match auth_token {
+            None => None,
+            Some(auth_token) => {
+                let player = self.validate_auth_token(auth_token, None)?;
+                Some((self.make_auth_token(player), player))
+            }
+        };
+        Ok((self.make_client_token(client_id), auth))
+    }
+
+    /// Sign the payload, stamping it with an `exp` claim (RFC 3339, as PASETO has it) if the
+    /// token is to expire.
+    fn sign(&self, mut payload: Value, ttl: Option<Duration>, footer: &str) -> String {
+        if let Some(ttl) = ttl {
+            let exp = Utc::now() + ttl;
+            payload["exp"] = Value::String(exp.to_rfc3339());
+        }
+        let privkey: PasetoAsymmetricPrivateKey<V4, Public> =
+            PasetoAsymmetricPrivateKey::from(self.keypair.as_ref());
+        Paseto::<V4, Public>::default()
+            .set_footer(Footer::from(footer))
+            .set_payload(Payload::from(payload.to_string().as_str()))
+            .try_sign(&privkey)
+            .expect("Unable to build Paseto token")
+    }
+
+    /// Check the token's signature, against the current key and then any previous ones we still
+    /// trust, and its expiry, and hand back its claims.
+    fn verify(&self, token: &str, footer: &str) -> Result<Value, SessionError> {
+        let now = Utc::now();
+        let previous_keys = self
+            .previous_keys
+            .iter()
+            .filter(|k| k.trusted_until.map_or(true, |until| until > now))
+            .map(|k| &k.public_key[..]);
+        let verified_token = std::iter::once(&self.keypair[32..])
+            .chain(previous_keys)
+            .find_map(|public_key| {
+                let key: Key<32> = Key::from(public_key);
+                let pk: PasetoAsymmetricPublicKey<V4, Public> =
+                    PasetoAsymmetricPublicKey::from(&key);
+                Paseto::<V4, Public>::try_verify(token, &pk, Footer::from(footer), None).ok()
+            })
+            .ok_or_else(|| {
+                warn!("Unable to parse/validate token with any trusted key");
+                SessionError::InvalidToken
+            })?;
+
+        let claims = serde_json::from_str::<Value>(verified_token.as_str()).map_err(|e| {
+            warn!(error = ?e, "Unable to parse/validate token");
+            SessionError::InvalidToken
+        })?;
+
+        // Tokens minted without a TTL carry no `exp`, and are good until the key changes.
+        if let Some(exp) = claims.get("exp") {
+            let Some(exp) = exp
+                .as_str()
+                .and_then(|e| DateTime::parse_from_rfc3339(e).ok())
+            else {
+                debug!("Token exp is not a valid timestamp");
+                return Err(SessionError::InvalidToken);
+            };
+            if exp <= Utc::now() {
+                debug!(?exp, "Token has expired");
+                return Err(SessionError::InvalidToken);
+            }
+        }
+
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ed25519_dalek::SigningKey;
+    use rusty_paseto::prelude::Key;
+    use uuid::Uuid;
+
+    use moor_values::var::Objid;
+
+    use chrono::Utc;
+
+    use crate::tokens::{PreviousKey, Tokens};
+
+    fn keypair() -> Key<64> {
+        let mut csprng = rand::rngs::OsRng;
+        let signing_key = SigningKey::generate(&mut csprng);
+        Key::from(signing_key.to_keypair_bytes())
+    }
+
+    fn public_key(keypair: &Key<64>) -> [u8; 32] {
+        keypair[32..].try_into().unwrap()
+    }
+
+    #[test]
+    fn test_tokens_without_ttl_dont_expire() {
+        let tokens = Tokens::new(keypair(), None, None);
+        let client_id = Uuid::new_v4();
+        let client_token = tokens.make_client_token(client_id);
+        let auth_token = tokens.make_auth_token(Objid(3));
+
+        assert!(tokens
+            .validate_client_token(client_token.clone(), client_id)
+            .is_ok());
+        assert!(tokens
+            .validate_client_token(client_token, Uuid::new_v4())
+            .is_err());
+        assert_eq!(
+            tokens.validate_auth_token(auth_token.clone(), None).ok(),
+            Some(Objid(3))
+        );
+        assert!(tokens
+            .validate_auth_token(auth_token, Some(Objid(4)))
+            .is_err());
+    }
+
+    #[test]
+    fn test_expired_tokens_are_rejected() {
+        let ttl = Some(Duration::from_millis(200));
+        let tokens = Tokens::new(keypair(), ttl, ttl);
+        let client_id = Uuid::new_v4();
+        let client_token = tokens.make_client_token(client_id);
+        let auth_token = tokens.make_auth_token(Objid(3));
+        assert!(tokens
+            .validate_client_token(client_token.clone(), client_id)
+            .is_ok());
+        assert!(tokens.validate_auth_token(auth_token.clone(), None).is_ok());
+
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(tokens
+            .validate_client_token(client_token, client_id)
+            .is_err());
+        assert!(tokens.validate_auth_token(auth_token, None).is_err());
+    }
+
+    #[test]
+    fn test_refresh_before_expiry() {
+        let ttl = Some(Duration::from_millis(1000));
+        let tokens = Tokens::new(keypair(), ttl, ttl);
+        let client_id = Uuid::new_v4();
+        let client_token = tokens.make_client_token(client_id);
+        let auth_token = tokens.make_auth_token(Objid(3));
+
+        std::thread::sleep(Duration::from_millis(600));
+        let (new_client_token, Some((new_auth_token, player))) = tokens
+            .refresh(client_token.clone(), client_id, Some(auth_token.clone()))
+            .expect("Refresh should succeed while the tokens are still good")
+        else {
+            panic!("Refresh should hand back a new auth token");
+        };
+        assert_eq!(player, Objid(3));
+        // A client which hasn't logged in yet can refresh just its client token.
+        let (_, auth) = tokens
+            .refresh(client_token.clone(), client_id, None)
+            .expect("Refresh should succeed without an auth token");
+        assert!(auth.is_none());
+
+        // Past the original tokens' expiry, but not the refreshed ones'.
+        std::thread::sleep(Duration::from_millis(600));
+        assert!(tokens
+            .validate_client_token(client_token.clone(), client_id)
+            .is_err());
+        assert!(tokens
+            .validate_auth_token(auth_token.clone(), None)
+            .is_err());
+        assert!(tokens
+            .validate_client_token(new_client_token, client_id)
+            .is_ok());
+        assert_eq!(
+            tokens.validate_auth_token(new_auth_token, None).ok(),
+            Some(Objid(3))
+        );
+
+        // And once they've expired, they can't be used to get new ones.
+        assert!(tokens
+            .refresh(client_token, client_id, Some(auth_token))
+            .is_err());
+    }
+
+    #[test]
+    fn test_revoked_tokens_are_rejected() {
+        let tokens = Tokens::new(keypair(), None, None);
+        let auth_token = tokens.make_auth_token(Objid(3));
+        let other_auth_token = tokens.make_auth_token(Objid(4));
+        assert!(tokens.validate_auth_token(auth_token.clone(), None).is_ok());
+
+        tokens.revoke_player(Objid(3));
+        assert!(tokens
+            .validate_auth_token(auth_token.clone(), Some(Objid(3)))
+            .is_err());
+        // Nor can it be used to get new ones.
+        let client_id = Uuid::new_v4();
+        let client_token = tokens.make_client_token(client_id);
+        assert!(tokens
+            .refresh(client_token, client_id, Some(auth_token))
+            .is_err());
+
+        // Other players are unaffected, and logging in again yields a working token.
+        assert!(tokens.validate_auth_token(other_auth_token, None).is_ok());
+        let new_auth_token = tokens.make_auth_token(Objid(3));
+        assert_eq!(
+            tokens.validate_auth_token(new_auth_token, None).ok(),
+            Some(Objid(3))
+        );
+    }
+
+    #[test]
+    fn test_rotated_keys() {
+        let old_keypair = keypair();
+        let old_public_key = public_key(&old_keypair);
+        let retired_keypair = keypair();
+        let retired_public_key = public_key(&retired_keypair);
+        let old_tokens = Tokens::new(old_keypair, None, None);
+        let retired_tokens = Tokens::new(retired_keypair, None, None);
+        let old_auth_token = old_tokens.make_auth_token(Objid(3));
+        let retired_auth_token = retired_tokens.make_auth_token(Objid(3));
+
+        let tokens = Tokens::new(keypair(), None, None)
+            .with_previous_key(PreviousKey {
+                public_key: old_public_key,
+                trusted_until: Some(Utc::now() + Duration::from_secs(3600)),
+            })
+            .with_previous_key(PreviousKey {
+                public_key: retired_public_key,
+                trusted_until: Some(Utc::now() - Duration::from_secs(1)),
+            });
+
+        // Signed with a key still in its grace window, so accepted.
+        assert_eq!(
+            tokens.validate_auth_token(old_auth_token, None).ok(),
+            Some(Objid(3))
+        );
+        // Signed with a key whose grace window is over.
+        assert!(tokens
+            .validate_auth_token(retired_auth_token, None)
+            .is_err());
+        // Signed with a key that was never in the ring.
+        let stranger_auth_token = Tokens::new(keypair(), None, None).make_auth_token(Objid(3));
+        assert!(tokens
+            .validate_auth_token(stranger_auth_token, None)
+            .is_err());
+
+        // New tokens are signed with the current key, which the old ring doesn't know about.
+        let new_auth_token = tokens.make_auth_token(Objid(3));
+        assert!(tokens
+            .validate_auth_token(new_auth_token.clone(), None)
+            .is_ok());
+        assert!(old_tokens
+            .validate_auth_token(new_auth_token, None)
+            .is_err());
+    }
+}
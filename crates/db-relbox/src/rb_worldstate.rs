@@ -89,14 +89,18 @@ mod tests {
 
     use moor_db::{
         perform_reparent_props, perform_test_create_object, perform_test_create_object_fixed_id,
-        perform_test_descendants, perform_test_location_contents, perform_test_object_move_commits,
+        perform_test_create_objects, perform_test_descendants, perform_test_descendants_cycle,
+        perform_test_location_contents, perform_test_object_move_commits, perform_test_owner_bytes,
         perform_test_parent_children, perform_test_recycle_object,
         perform_test_regression_properties, perform_test_rename_property,
-        perform_test_simple_property, perform_test_transitive_property_resolution,
+        perform_test_reparent_cycle, perform_test_reparent_descendants,
+        perform_test_reparent_property_conflict, perform_test_resolve_properties,
+        perform_test_savepoint_rollback_to, perform_test_simple_property,
+        perform_test_transitive_property_resolution,
         perform_test_transitive_property_resolution_clear_property, perform_test_verb_add_update,
         perform_test_verb_resolve, perform_test_verb_resolve_inherited,
-        perform_test_verb_resolve_wildcard, RelationalWorldStateTransaction, WorldStateSequence,
-        WorldStateTable,
+        perform_test_verb_resolve_wildcard, perform_test_verify_integrity,
+        RelationalWorldStateTransaction, WorldStateSequence, WorldStateTable,
     };
     use relbox::{relation_info_for, RelBox, RelationInfo};
 
@@ -127,18 +131,42 @@ mod tests {
         perform_test_create_object_fixed_id(|| begin_tx(&db));
     }
 
+    #[test]
+    fn test_create_objects() {
+        let db = test_db();
+        perform_test_create_objects(|| begin_tx(&db));
+    }
+
     #[test]
     fn test_parent_children() {
         let db = test_db();
         perform_test_parent_children(|| begin_tx(&db));
     }
 
+    #[test]
+    fn test_reparent_cycle() {
+        let db = test_db();
+        perform_test_reparent_cycle(|| begin_tx(&db));
+    }
+
+    #[test]
+    fn test_reparent_property_conflict() {
+        let db = test_db();
+        perform_test_reparent_property_conflict(|| begin_tx(&db));
+    }
+
     #[test]
     fn test_descendants() {
         let db = test_db();
         perform_test_descendants(|| begin_tx(&db));
     }
 
+    #[test]
+    fn test_descendants_cycle() {
+        let db = test_db();
+        perform_test_descendants_cycle(|| begin_tx(&db));
+    }
+
     #[test]
     fn test_location_contents() {
         let db = test_db();
@@ -183,6 +211,18 @@ mod tests {
         perform_test_rename_property(|| begin_tx(&db));
     }
 
+    #[test]
+    fn test_resolve_properties() {
+        let db = test_db();
+        perform_test_resolve_properties(|| begin_tx(&db));
+    }
+
+    #[test]
+    fn test_savepoint_rollback_to() {
+        let db = test_db();
+        perform_test_savepoint_rollback_to(|| begin_tx(&db));
+    }
+
     #[test]
     fn test_regression_properties() {
         let db = test_db();
@@ -213,9 +253,27 @@ mod tests {
         perform_reparent_props(|| begin_tx(&db));
     }
 
+    #[test]
+    fn test_reparent_descendants() {
+        let db = test_db();
+        perform_test_reparent_descendants(|| begin_tx(&db));
+    }
+
     #[test]
     fn test_recycle_object() {
         let db = test_db();
         perform_test_recycle_object(|| begin_tx(&db));
     }
+
+    #[test]
+    fn test_verify_integrity() {
+        let db = test_db();
+        perform_test_verify_integrity(|| begin_tx(&db));
+    }
+
+    #[test]
+    fn test_owner_bytes() {
+        let db = test_db();
+        perform_test_owner_bytes(|| begin_tx(&db));
+    }
 }
@@ -14,14 +14,26 @@
 
 use bytes::Bytes;
 use daumtils::SliceRef;
-use moor_db::{RelationalError, RelationalTransaction};
+use moor_db::{RelationalError, RelationalTransaction, SavepointId};
 use moor_values::model::{CommitResult, ValSet};
 use moor_values::{AsByteBuffer, EncodingError};
 use relbox::{RelationError, Transaction};
 use std::fmt::Debug;
+use std::sync::Mutex;
+
+/// The prior codomain (if any) of a tuple in a relation, recorded before it was mutated.
+struct UndoEntry {
+    relation: usize,
+    domain: Vec<u8>,
+    prior: Option<Vec<u8>>,
+}
 
 pub struct RelboxTransaction<T> {
     tx: Transaction,
+    /// Log of the prior values of all tuples touched in this transaction, in mutation order, which
+    /// savepoints index into. None until the first savepoint is taken, so that transactions which
+    /// never take one don't pay for a read before every write.
+    undo_log: Mutex<Option<Vec<UndoEntry>>>,
     _phantom: std::marker::PhantomData<T>,
 }
 
@@ -29,9 +41,32 @@ impl<T> RelboxTransaction<T> {
     pub fn new(tx: Transaction) -> Self {
         Self {
             tx,
+            undo_log: Mutex::new(None),
             _phantom: std::marker::PhantomData,
         }
     }
+
+    fn record_undo(&self, relation: usize, domain: &[u8]) -> Result<()> {
+        let mut undo_log = self.undo_log.lock().unwrap();
+        let Some(undo_log) = undo_log.as_mut() else {
+            return Ok(());
+        };
+        let prior = match self
+            .tx
+            .relation(relbox::RelationId(relation))
+            .seek_unique_by_domain(SliceRef::from_bytes(domain))
+        {
+            Ok(t) => Some(t.codomain().as_slice().to_vec()),
+            Err(RelationError::TupleNotFound) => None,
+            Err(e) => return Err(err_map(e)),
+        };
+        undo_log.push(UndoEntry {
+            relation,
+            domain: domain.to_vec(),
+            prior,
+        });
+        Ok(())
+    }
 }
 type Result<T> = std::result::Result<T, RelationalError>;
 
@@ -100,6 +135,33 @@ where
         self.tx.rollback().expect("Failed to rollback transaction");
     }
 
+    fn savepoint(&self) -> SavepointId {
+        let mut undo_log = self.undo_log.lock().unwrap();
+        SavepointId(undo_log.get_or_insert_with(Vec::new).len())
+    }
+
+    fn rollback_to(&self, savepoint: SavepointId) -> Result<()> {
+        let mut undo_log = self.undo_log.lock().unwrap();
+        let undo_log = undo_log.get_or_insert_with(Vec::new);
+        if savepoint.0 > undo_log.len() {
+            return Err(RelationalError::InvalidSavepoint);
+        }
+        for entry in undo_log.drain(savepoint.0..).rev() {
+            let relation = self.tx.relation(relbox::RelationId(entry.relation));
+            let domain = SliceRef::from_bytes(&entry.domain);
+            match entry.prior {
+                Some(prior) => relation
+                    .upsert_by_domain(domain, SliceRef::from_bytes(&prior))
+                    .map_err(err_map)?,
+                None => match relation.remove_by_domain(domain) {
+                    Ok(_) | Err(RelationError::TupleNotFound) => {}
+                    Err(e) => return Err(err_map(e)),
+                },
+            }
+        }
+        Ok(())
+    }
+
     fn increment_sequence<S: Into<u8>>(&self, seq: S) -> i64 {
         self.tx.increment_sequence(seq.into() as usize) as i64
     }
@@ -119,9 +181,12 @@ where
         rel: T,
         domain: Domain,
     ) -> Result<()> {
+        let relation = rel.into();
+        let domain = domain.as_bytes().unwrap();
+        self.record_undo(relation, domain.as_ref())?;
         self.tx
-            .relation(relbox::RelationId(rel.into()))
-            .remove_by_domain(SliceRef::from_byte_source(domain.as_bytes().unwrap()))
+            .relation(relbox::RelationId(relation))
+            .remove_by_domain(SliceRef::from_byte_source(domain))
             .map_err(err_map)
     }
 
@@ -135,8 +200,10 @@ where
         domain_b: DomainB,
     ) -> Result<()> {
         let composite = Composite::new(domain_a, domain_b);
+        let relation = rel.into();
+        self.record_undo(relation, composite.bytes.as_ref())?;
         self.tx
-            .relation(relbox::RelationId(rel.into()))
+            .relation(relbox::RelationId(relation))
             .remove_by_domain(composite.as_bytes().unwrap())
             .map_err(err_map)
     }
@@ -158,10 +225,13 @@ where
         domain: Domain,
         codomain: Codomain,
     ) -> Result<()> {
+        let relation = rel.into();
+        let domain = domain.as_bytes().unwrap();
+        self.record_undo(relation, domain.as_ref())?;
         self.tx
-            .relation(relbox::RelationId(rel.into()))
+            .relation(relbox::RelationId(relation))
             .upsert_by_domain(
-                SliceRef::from_bytes(domain.as_bytes().unwrap().as_ref()),
+                SliceRef::from_bytes(domain.as_ref()),
                 SliceRef::from_bytes(codomain.as_bytes().unwrap().as_ref()),
             )
             .map_err(err_map)
@@ -176,10 +246,13 @@ where
         domain: Domain,
         codomain: Codomain,
     ) -> Result<()> {
+        let relation = rel.into();
+        let domain = domain.as_bytes().unwrap();
+        self.record_undo(relation, domain.as_ref())?;
         self.tx
-            .relation(relbox::RelationId(rel.into()))
+            .relation(relbox::RelationId(relation))
             .insert_tuple(
-                SliceRef::from_bytes(domain.as_bytes().unwrap().as_ref()),
+                SliceRef::from_bytes(domain.as_ref()),
                 SliceRef::from_bytes(codomain.as_bytes().unwrap().as_ref()),
             )
             .map_err(err_map)
@@ -401,8 +474,10 @@ where
         codomain: Codomain,
     ) -> Result<()> {
         let composite = Composite::new(domain_a, domain_b);
+        let relation = rel.into();
+        self.record_undo(relation, composite.bytes.as_ref())?;
         self.tx
-            .relation(relbox::RelationId(rel.into()))
+            .relation(relbox::RelationId(relation))
             .insert_tuple(
                 composite.as_bytes().unwrap(),
                 SliceRef::from_bytes(codomain.as_bytes().unwrap().as_ref()),
@@ -420,8 +495,10 @@ where
         domain_b: DomainB,
     ) -> Result<()> {
         let composite = Composite::new(domain_a, domain_b);
+        let relation = rel.into();
+        self.record_undo(relation, composite.bytes.as_ref())?;
         self.tx
-            .relation(relbox::RelationId(rel.into()))
+            .relation(relbox::RelationId(relation))
             .remove_by_domain(composite.as_bytes().unwrap())
             .map_err(err_map)
     }
@@ -438,8 +515,10 @@ where
         value: Codomain,
     ) -> Result<()> {
         let composite = Composite::new(domain_a, domain_b);
+        let relation = rel.into();
+        self.record_undo(relation, composite.bytes.as_ref())?;
         self.tx
-            .relation(relbox::RelationId(rel.into()))
+            .relation(relbox::RelationId(relation))
             .upsert_by_domain(
                 composite.as_bytes().unwrap(),
                 SliceRef::from_bytes(value.as_bytes().unwrap().as_ref()),
@@ -452,9 +531,12 @@ where
         rel: T,
         domain: Domain,
     ) -> Result<()> {
+        let relation = rel.into();
+        let domain = domain.as_bytes().unwrap();
+        self.record_undo(relation, domain.as_ref())?;
         self.tx
-            .relation(relbox::RelationId(rel.into()))
-            .remove_by_domain(SliceRef::from_bytes(domain.as_bytes().unwrap().as_ref()))
+            .relation(relbox::RelationId(relation))
+            .remove_by_domain(SliceRef::from_bytes(domain.as_ref()))
             .map_err(err_map)
     }
 }
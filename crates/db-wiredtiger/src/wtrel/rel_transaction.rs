@@ -14,14 +14,14 @@
 
 use std::fmt::Debug;
 use std::sync::atomic::AtomicI64;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use moor_db::{RelationalError, RelationalTransaction};
+use moor_db::{RelationalError, RelationalTransaction, SavepointId};
 use moor_values::model::{CommitResult, ValSet};
 use moor_values::AsByteBuffer;
 
 use crate::bindings::FormatType::RawByte;
-use crate::bindings::{CursorConfig, Datum, Error, Pack, Session};
+use crate::bindings::{CursorConfig, DataSource, Datum, Error, Pack, Session};
 use crate::wtrel::rel_db::MAX_NUM_SEQUENCES;
 use crate::wtrel::relation::WiredTigerRelation;
 use crate::wtrel::{from_datum, to_datum};
@@ -30,9 +30,22 @@ fn cursor_options() -> CursorConfig {
     CursorConfig::new().raw(true)
 }
 
+/// The prior state of a single tuple, recorded before it was mutated, so that the mutation can be
+/// reverted by `rollback_to`.
+struct UndoEntry {
+    table: DataSource,
+    key: Vec<u8>,
+    prior: Option<Vec<u8>>,
+}
+
 pub struct WiredTigerRelTransaction<TableType: WiredTigerRelation> {
     session: Session,
     sequences: Arc<[AtomicI64; MAX_NUM_SEQUENCES]>,
+    /// Log of the prior values of all tuples touched in this transaction, in mutation order.
+    /// WiredTiger has no nested transactions, so savepoints are positions in this log. None until
+    /// the first savepoint is taken, so that transactions which never take one don't pay for a
+    /// read before every write.
+    undo_log: Mutex<Option<Vec<UndoEntry>>>,
     _phantom: std::marker::PhantomData<TableType>,
 }
 
@@ -46,6 +59,7 @@ where
         WiredTigerRelTransaction {
             session,
             sequences,
+            undo_log: Mutex::new(None),
             _phantom: std::marker::PhantomData,
         }
     }
@@ -73,6 +87,32 @@ where
         pack.push_item(b.as_ref());
         pack.pack()
     }
+
+    /// Remember the current value (if any) stored under `key` in `table`, before it is mutated.
+    fn record_undo(&self, table: &DataSource, key: &Datum) -> Result<()> {
+        let mut undo_log = self.undo_log.lock().unwrap();
+        let Some(undo_log) = undo_log.as_mut() else {
+            return Ok(());
+        };
+        let cursor = self
+            .session
+            .open_cursor(table, Some(cursor_options().readonly(true)))
+            .map_err(err_map)?;
+        cursor
+            .set_key(Datum::from_vec(key.as_slice().to_vec()))
+            .map_err(err_map)?;
+        let prior = match cursor.search() {
+            Ok(_) => Some(cursor.get_value().map_err(err_map)?.as_slice().to_vec()),
+            Err(Error::NotFound) => None,
+            Err(e) => return Err(err_map(e)),
+        };
+        undo_log.push(UndoEntry {
+            table: table.clone(),
+            key: key.as_slice().to_vec(),
+            prior,
+        });
+        Ok(())
+    }
 }
 
 fn err_map(e: Error) -> RelationalError {
@@ -106,6 +146,41 @@ where
             .expect("Failed to rollback transaction")
     }
 
+    fn savepoint(&self) -> SavepointId {
+        let mut undo_log = self.undo_log.lock().unwrap();
+        SavepointId(undo_log.get_or_insert_with(Vec::new).len())
+    }
+
+    /// Restore the prior values of everything touched since the savepoint, most recent first.
+    /// Note that sequences are not transactional, and are not rolled back.
+    fn rollback_to(&self, savepoint: SavepointId) -> Result<()> {
+        let mut undo_log = self.undo_log.lock().unwrap();
+        let undo_log = undo_log.get_or_insert_with(Vec::new);
+        if savepoint.0 > undo_log.len() {
+            return Err(RelationalError::InvalidSavepoint);
+        }
+        for entry in undo_log.drain(savepoint.0..).rev() {
+            let cursor = self
+                .session
+                .open_cursor(&entry.table, Some(cursor_options().overwrite(true)))
+                .map_err(err_map)?;
+            cursor
+                .set_key(Datum::from_vec(entry.key))
+                .map_err(err_map)?;
+            match entry.prior {
+                Some(prior) => {
+                    cursor.set_value(Datum::from_vec(prior)).map_err(err_map)?;
+                    cursor.insert().map_err(err_map)?;
+                }
+                None => match cursor.remove() {
+                    Ok(_) | Err(Error::NotFound) => {}
+                    Err(e) => return Err(err_map(e)),
+                },
+            }
+        }
+        Ok(())
+    }
+
     fn increment_sequence<S: Into<u8>>(&self, seq: S) -> i64 {
         self.sequences[seq.into() as usize].fetch_add(1, std::sync::atomic::Ordering::Relaxed)
     }
@@ -145,6 +220,7 @@ where
             .map_err(err_map)?;
 
         let domain_datum = to_datum(&self.session, &domain);
+        self.record_undo(&table, &domain_datum)?;
         cursor.set_key(domain_datum).map_err(err_map)?;
         cursor.remove().map_err(err_map)?;
         Ok(())
@@ -165,6 +241,7 @@ where
             .session
             .open_cursor(&table, Some(cursor_options()))
             .map_err(err_map)?;
+        self.record_undo(&table, &key_bytes)?;
         cursor.set_key(key_bytes).map_err(err_map)?;
         if let Err(Error::NotFound) = cursor.search() {
             return Ok(());
@@ -178,16 +255,19 @@ where
         rel: Tables,
         codomain: Codomain,
     ) -> Result<()> {
-        let table = rel.get_secondary_index();
+        let table = rel.into();
+        let index = rel.get_secondary_index();
         let cursor = self
             .session
-            .open_cursor(&table, Some(cursor_options()))
+            .open_cursor(&index, Some(cursor_options()))
             .map_err(err_map)?;
         let codomain_sr = to_datum(&self.session, &codomain);
         cursor.set_key(codomain_sr).map_err(err_map)?;
         if let Err(Error::NotFound) = cursor.search() {
             return Ok(());
         }
+        // The index's value is the (projected) domain, which is the primary key in the table.
+        self.record_undo(&table, &cursor.get_value().map_err(err_map)?)?;
         cursor.remove().map_err(err_map)?;
         loop {
             match cursor.next() {
@@ -197,6 +277,7 @@ where
                     if codomain_scan != codomain {
                         break;
                     }
+                    self.record_undo(&table, &cursor.get_value().map_err(err_map)?)?;
                     cursor.remove().map_err(err_map)?;
                 }
                 Err(Error::NotFound) => break,
@@ -220,9 +301,9 @@ where
             .session
             .open_cursor(&table, Some(cursor_options().overwrite(true)))
             .map_err(err_map)?;
-        cursor
-            .set_key(to_datum(&self.session, &domain))
-            .map_err(err_map)?;
+        let domain_datum = to_datum(&self.session, &domain);
+        self.record_undo(&table, &domain_datum)?;
+        cursor.set_key(domain_datum).map_err(err_map)?;
         cursor
             .set_value(to_datum(&self.session, &codomain))
             .map_err(err_map)?;
@@ -243,9 +324,9 @@ where
             .session
             .open_cursor(&table, Some(cursor_options().overwrite(false)))
             .map_err(err_map)?;
-        cursor
-            .set_key(to_datum(&self.session, &domain))
-            .map_err(err_map)?;
+        let domain_datum = to_datum(&self.session, &domain);
+        self.record_undo(&table, &domain_datum)?;
+        cursor.set_key(domain_datum).map_err(err_map)?;
         cursor
             .set_value(to_datum(&self.session, &codomain))
             .map_err(err_map)?;
@@ -489,6 +570,7 @@ where
             .session
             .open_cursor(&table, Some(cursor_options().overwrite(false)))
             .map_err(err_map)?;
+        self.record_undo(&table, &key_bytes)?;
         cursor.set_key(key_bytes).map_err(err_map)?;
         cursor
             .set_value(to_datum(&self.session, &codomain))
@@ -517,6 +599,7 @@ where
             .session
             .open_cursor(&table, Some(cursor_options()))
             .map_err(err_map)?;
+        self.record_undo(&table, &key_bytes)?;
         cursor.set_key(key_bytes).map_err(err_map)?;
         match cursor.remove() {
             Ok(_) => Ok(()),
@@ -543,6 +626,7 @@ where
             .open_cursor(&table, Some(cursor_options().overwrite(true)))
             .map_err(err_map)?;
 
+        self.record_undo(&table, &key_bytes)?;
         cursor.set_key(key_bytes).map_err(err_map)?;
         cursor
             .set_value(to_datum(&self.session, &value))
@@ -562,9 +646,9 @@ where
             .session
             .open_cursor(&table, Some(cursor_options()))
             .map_err(err_map)?;
-        cursor
-            .set_key(to_datum(&self.session, &domain))
-            .map_err(err_map)?;
+        let domain_datum = to_datum(&self.session, &domain);
+        self.record_undo(&table, &domain_datum)?;
+        cursor.set_key(domain_datum).map_err(err_map)?;
         match cursor.remove() {
             Ok(_) => Ok(()),
             Err(Error::NotFound) => Ok(()),
@@ -850,4 +934,104 @@ mod tests {
             .unwrap();
         assert_eq!(children, ObjSet::from_items(&[Objid(4)]));
     }
+
+    #[test]
+    fn test_savepoint_rollback_to() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let db = test_db(tmpdir.path());
+        let tx = db.clone().start_tx();
+
+        tx.insert_tuple(OneToOne, Objid(1), Objid(2)).unwrap();
+        tx.insert_tuple(OneToOne, Objid(2), Objid(3)).unwrap();
+        let savepoint = tx.savepoint();
+        tx.insert_tuple(OneToOne, Objid(3), Objid(4)).unwrap();
+        tx.insert_tuple(OneToOne, Objid(4), Objid(5)).unwrap();
+        tx.rollback_to(savepoint).unwrap();
+
+        // Only the inserts made after the savepoint should have vanished.
+        assert_eq!(
+            tx.seek_unique_by_domain::<Objid, Objid>(OneToOne, Objid(1))
+                .unwrap(),
+            Some(Objid(2))
+        );
+        assert_eq!(
+            tx.seek_unique_by_domain::<Objid, Objid>(OneToOne, Objid(2))
+                .unwrap(),
+            Some(Objid(3))
+        );
+        assert_eq!(
+            tx.seek_unique_by_domain::<Objid, Objid>(OneToOne, Objid(3))
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            tx.seek_unique_by_domain::<Objid, Objid>(OneToOne, Objid(4))
+                .unwrap(),
+            None
+        );
+
+        // And the rest of the transaction should still commit.
+        assert_eq!(tx.commit(), super::CommitResult::Success);
+        let tx = db.clone().start_tx();
+        assert_eq!(
+            tx.seek_unique_by_domain::<Objid, Objid>(OneToOne, Objid(2))
+                .unwrap(),
+            Some(Objid(3))
+        );
+        assert_eq!(
+            tx.seek_unique_by_domain::<Objid, Objid>(OneToOne, Objid(3))
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_savepoint_restores_updates_and_removals() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let db = test_db(tmpdir.path());
+        let tx = db.clone().start_tx();
+
+        tx.insert_tuple(OneToOneSecondaryIndexed, Objid(1), Objid(0))
+            .unwrap();
+        tx.insert_tuple(OneToOneSecondaryIndexed, Objid(2), Objid(0))
+            .unwrap();
+        tx.insert_composite_domain_tuple(CompositeToOne, Objid(1), Objid(2), Objid(3))
+            .unwrap();
+
+        let savepoint = tx.savepoint();
+        tx.upsert::<Objid, Objid>(OneToOneSecondaryIndexed, Objid(1), Objid(2))
+            .unwrap();
+        tx.remove_by_domain(OneToOneSecondaryIndexed, Objid(2))
+            .unwrap();
+        tx.upsert_composite(CompositeToOne, Objid(1), Objid(2), Objid(4))
+            .unwrap();
+        tx.rollback_to(savepoint).unwrap();
+
+        assert_eq!(
+            tx.seek_by_codomain::<Objid, Objid, ObjSet>(OneToOneSecondaryIndexed, Objid(0))
+                .unwrap(),
+            ObjSet::from_items(&[Objid(1), Objid(2)])
+        );
+        assert_eq!(
+            tx.seek_by_codomain::<Objid, Objid, ObjSet>(OneToOneSecondaryIndexed, Objid(2))
+                .unwrap(),
+            ObjSet::empty()
+        );
+        assert_eq!(
+            tx.seek_by_unique_composite_domain::<Objid, Objid, Objid>(
+                CompositeToOne,
+                Objid(1),
+                Objid(2)
+            )
+            .unwrap(),
+            Some(Objid(3))
+        );
+
+        // A savepoint that was rolled past is no longer valid.
+        let savepoint = tx.savepoint();
+        tx.insert_tuple(OneToOne, Objid(5), Objid(6)).unwrap();
+        let nested = tx.savepoint();
+        tx.rollback_to(savepoint).unwrap();
+        assert!(tx.rollback_to(nested).is_err());
+    }
 }
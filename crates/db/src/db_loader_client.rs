@@ -40,6 +40,9 @@ impl LoaderInterface for DbTxWorldState {
     ) -> Result<Objid, WorldStateError> {
         self.tx.create_object(objid, attrs.clone())
     }
+    fn create_objects(&self, objects: &[(Objid, ObjAttrs)]) -> Result<(), WorldStateError> {
+        self.tx.create_objects(objects)
+    }
     fn set_object_parent(&self, obj: Objid, parent: Objid) -> Result<(), WorldStateError> {
         self.tx.set_object_parent(obj, parent)
     }
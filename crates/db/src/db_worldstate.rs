@@ -12,9 +12,14 @@
 // this program. If not, see <https://www.gnu.org/licenses/>.
 //
 
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use uuid::Uuid;
 
 use moor_values::model::HasUuid;
+use moor_values::model::IntegrityFinding;
+use moor_values::model::Named;
 use moor_values::model::ObjSet;
 use moor_values::model::Perms;
 use moor_values::model::VerbInfo;
@@ -23,18 +28,53 @@ use moor_values::model::WorldStateError;
 use moor_values::model::{ArgSpec, PrepSpec, VerbArgsSpec};
 use moor_values::model::{BinaryType, VerbAttrs, VerbFlag};
 use moor_values::model::{CommitResult, PropPerms, ValSet};
-use moor_values::model::{ObjAttrs, ObjFlag};
+use moor_values::model::{ObjAttrs, ObjFlag, ObjectBytes};
 use moor_values::model::{PropAttrs, PropFlag};
 use moor_values::model::{PropDef, PropDefs};
 use moor_values::model::{VerbDef, VerbDefs};
+use moor_values::model::{BYTE_QUOTA_PROPERTY, OWNERSHIP_QUOTA_PROPERTY};
 use moor_values::util::BitEnum;
 use moor_values::var::Variant;
 use moor_values::var::{v_int, v_objid, Var};
 use moor_values::var::{v_listv, Objid};
+use moor_values::AsByteBuffer;
 use moor_values::NOTHING;
 
 use crate::worldstate_transaction::WorldStateTransaction;
 
+/// Properties which every object has, which are backed by object attributes rather than by a
+/// property definition.
+const BUILTIN_PROPERTIES: [&str; 9] = [
+    "name",
+    "location",
+    "contents",
+    "owner",
+    "programmer",
+    "wizard",
+    "r",
+    "w",
+    "f",
+];
+
+/// Counts of method verb lookups (`find_method_verb_on`) across all transactions, by outcome.
+static VERB_LOOKUP_HITS: AtomicUsize = AtomicUsize::new(0);
+static VERB_LOOKUP_MISSES: AtomicUsize = AtomicUsize::new(0);
+
+/// How many method verb lookups have found a verb, and how many haven't, since startup.
+/// There's no verb cache, so these measure the lookups a cache would be absorbing.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct VerbLookupStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+pub fn verb_lookup_stats() -> VerbLookupStats {
+    VerbLookupStats {
+        hits: VERB_LOOKUP_HITS.load(Ordering::Relaxed),
+        misses: VERB_LOOKUP_MISSES.load(Ordering::Relaxed),
+    }
+}
+
 pub struct DbTxWorldState {
     pub tx: Box<dyn WorldStateTransaction>,
 }
@@ -63,9 +103,82 @@ impl DbTxWorldState {
             return Err(WorldStateError::VerbPermissionDenied);
         }
 
+        // Growing the verb has to fit in the object owner's byte quota.
+        let mut adding = 0;
+        if let Some(names) = &verb_attrs.names {
+            let old_names = verbdef.names().iter().map(|n| n.len()).sum::<usize>();
+            adding += names.iter().map(|n| n.len()).sum::<usize>() as isize - old_names as isize;
+        }
+        if let Some(binary) = &verb_attrs.binary {
+            let old_binary = self.tx.get_verb_binary(obj, verbdef.uuid())?.len();
+            adding += binary.len() as isize - old_binary as isize;
+        }
+        self.check_byte_quota(perms.who, self.owner_of(obj)?, adding.max(0) as usize)?;
+
         self.tx.update_verb(obj, verbdef.uuid(), verb_attrs)?;
         Ok(())
     }
+
+    /// The owner's quota in the given property, if they have one (i.e. it holds an integer).
+    /// Returns the property's uuid too, for writing it back.
+    fn quota(&self, owner: Objid, pname: &str) -> Result<Option<(Uuid, i64)>, WorldStateError> {
+        if owner == NOTHING {
+            return Ok(None);
+        }
+        match self.tx.resolve_property(owner, pname.to_string()) {
+            Ok((pdef, value, _, _)) => match value.variant() {
+                Variant::Int(quota) => Ok(Some((pdef.uuid(), *quota))),
+                _ => Ok(None),
+            },
+            Err(WorldStateError::PropertyNotFound(_, _)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Take one object out of the owner's `ownership_quota`, if they have one. As in LambdaMOO,
+    /// an exhausted quota stops anyone but a wizard, but wizards still use it up.
+    fn charge_ownership_quota(&self, perms: Objid, owner: Objid) -> Result<(), WorldStateError> {
+        let Some((uuid, quota)) = self.quota(owner, OWNERSHIP_QUOTA_PROPERTY)? else {
+            return Ok(());
+        };
+        if quota <= 0 && !self.perms(perms)?.check_is_wizard()? {
+            return Err(WorldStateError::QuotaExceeded(owner));
+        }
+        self.tx.set_property(owner, uuid, v_int(quota - 1))
+    }
+
+    /// Give a recycled object back to its owner's `ownership_quota`, if they have one.
+    fn refund_ownership_quota(&self, owner: Objid) -> Result<(), WorldStateError> {
+        let Some((uuid, quota)) = self.quota(owner, OWNERSHIP_QUOTA_PROPERTY)? else {
+            return Ok(());
+        };
+        self.tx.set_property(owner, uuid, v_int(quota + 1))
+    }
+
+    /// Check that `adding` more bytes on one of the owner's objects would keep everything they
+    /// own within their `byte_quota`, if they have one. Wizards aren't held to it. Changes which
+    /// don't grow anything (`adding` of 0) always fit.
+    fn check_byte_quota(
+        &self,
+        perms: Objid,
+        owner: Objid,
+        adding: usize,
+    ) -> Result<(), WorldStateError> {
+        if adding == 0 {
+            return Ok(());
+        }
+        let Some((_, quota)) = self.quota(owner, BYTE_QUOTA_PROPERTY)? else {
+            return Ok(());
+        };
+        if self.perms(perms)?.check_is_wizard()? {
+            return Ok(());
+        }
+        let used = self.tx.get_owner_bytes(owner)? + adding;
+        if used as i64 > quota {
+            return Err(WorldStateError::QuotaExceeded(owner));
+        }
+        Ok(())
+    }
 }
 
 impl WorldState for DbTxWorldState {
@@ -119,6 +232,14 @@ impl WorldState for DbTxWorldState {
     }
 
     fn object_bytes(&self, perms: Objid, obj: Objid) -> Result<usize, WorldStateError> {
+        Ok(self.object_bytes_detailed(perms, obj)?.total())
+    }
+
+    fn object_bytes_detailed(
+        &self,
+        perms: Objid,
+        obj: Objid,
+    ) -> Result<ObjectBytes, WorldStateError> {
         self.perms(perms)?.check_wizard()?;
         self.tx.get_object_size_bytes(obj)
     }
@@ -140,10 +261,7 @@ impl WorldState for DbTxWorldState {
             )?;
         }
 
-        // TODO: ownership_quota support
-        //    If the intended owner of the new object has a property named `ownership_quota' and the value of that property is an integer, then `create()' treats that value
-        //    as a "quota".  If the quota is less than or equal to zero, then the quota is considered to be exhausted and `create()' raises `E_QUOTA' instead of creating an
-        //    object.  Otherwise, the quota is decremented and stored back into the `ownership_quota' property as a part of the creation of the new object.
+        self.charge_ownership_quota(perms, owner)?;
         let attrs = ObjAttrs::new(owner, parent, NOTHING, flags, "");
         self.tx.create_object(None, attrs)
     }
@@ -153,6 +271,7 @@ impl WorldState for DbTxWorldState {
         self.perms(perms)?
             .check_object_allows(owner, flags, ObjFlag::Write.into())?;
 
+        self.refund_ownership_quota(owner)?;
         self.tx.recycle_object(obj)
     }
 
@@ -212,7 +331,7 @@ impl WorldState for DbTxWorldState {
 
         // Special properties like namnne, location, and contents get treated specially.
         if pname == "name" {
-            return self.names_of(perms, obj).map(|(name, _)| Var::from(name));
+            return self.tx.get_object_name(obj).map(Var::from);
         } else if pname == "location" {
             return self.location_of(perms, obj).map(Var::from);
         } else if pname == "contents" {
@@ -263,6 +382,40 @@ impl WorldState for DbTxWorldState {
         Ok(value)
     }
 
+    fn get_properties(
+        &self,
+        perms: Objid,
+        obj: Objid,
+        pnames: &[&str],
+    ) -> Result<HashMap<String, Var>, WorldStateError> {
+        if obj == NOTHING || !self.valid(obj)? {
+            return Err(WorldStateError::ObjectNotFound(obj));
+        }
+
+        // The special builtin properties don't live on the inheritance chain, so get those one
+        // at a time, and resolve all the rest in one go.
+        let (builtin, defined): (Vec<&str>, Vec<&str>) = pnames
+            .iter()
+            .copied()
+            .partition(|pname| BUILTIN_PROPERTIES.contains(pname));
+        let mut values = HashMap::with_capacity(pnames.len());
+        for pname in builtin {
+            if let Ok(value) = self.retrieve_property(perms, obj, pname) {
+                values.insert(pname.to_string(), value);
+            }
+        }
+        let perms = self.perms(perms)?;
+        for (pname, (_, value, propperms, _)) in self.tx.resolve_properties(obj, &defined)? {
+            if perms
+                .check_property_allows(&propperms, PropFlag::Read)
+                .is_ok()
+            {
+                values.insert(pname, value);
+            }
+        }
+        Ok(values)
+    }
+
     fn get_property_info(
         &self,
         perms: Objid,
@@ -296,10 +449,8 @@ impl WorldState for DbTxWorldState {
         self.perms(perms)?
             .check_property_allows(&propperms, PropFlag::Write)?;
 
-        // TODO Also keep a close eye on 'clear' & perms:
-        //  "raises `E_INVARG' if <owner> is not valid" & If <object> is the definer of the property
-        //   <prop-name>, as opposed to an inheritor of the property, then `clear_property()' raises
-        //   `E_INVARG'
+        // TODO Also keep a close eye on perms:
+        //  "raises `E_INVARG' if <owner> is not valid"
 
         self.tx
             .update_property_info(obj, pdef.uuid(), attrs.owner, attrs.flags, attrs.name)?;
@@ -329,6 +480,9 @@ impl WorldState for DbTxWorldState {
                 let Variant::Str(name) = value.variant() else {
                     return Err(WorldStateError::PropertyTypeMismatch);
                 };
+                let old_name = self.tx.get_object_name(obj)?;
+                let adding = name.as_str().len().saturating_sub(old_name.len());
+                self.check_byte_quota(perms, objowner, adding)?;
                 self.tx.set_object_name(obj, name.to_string())?;
                 return Ok(());
             }
@@ -405,10 +559,16 @@ impl WorldState for DbTxWorldState {
             return Ok(());
         }
 
-        let (pdef, _, propperms, _) = self.tx.resolve_property(obj, pname.to_string())?;
+        let (pdef, old_value, propperms, clear) =
+            self.tx.resolve_property(obj, pname.to_string())?;
         self.perms(perms)?
             .check_property_allows(&propperms, PropFlag::Write)?;
 
+        // A clear property has no value of its own yet, so the whole new value is growth.
+        let old_size = if clear { 0 } else { old_value.size_bytes() };
+        let adding = value.size_bytes().saturating_sub(old_size);
+        self.check_byte_quota(perms, self.owner_of(obj)?, adding)?;
+
         self.tx.set_property(obj, pdef.uuid(), value.clone())?;
         Ok(())
     }
@@ -436,6 +596,13 @@ impl WorldState for DbTxWorldState {
         let (pdef, _, propperms, _) = self.tx.resolve_property(obj, pname.to_string())?;
         self.perms(perms)?
             .check_property_allows(&propperms, PropFlag::Write)?;
+        // There's nothing above the definer for its value to come from.
+        if pdef.definer() == obj {
+            return Err(WorldStateError::ClearDefinedProperty(
+                obj,
+                pname.to_string(),
+            ));
+        }
         self.tx.clear_property(obj, pdef.uuid())?;
         Ok(())
     }
@@ -457,6 +624,8 @@ impl WorldState for DbTxWorldState {
         self.perms(perms)?
             .check_object_allows(objowner, flags, ObjFlag::Write.into())?;
         self.perms(perms)?.check_obj_owner_perms(propowner)?;
+        let adding = pname.len() + initial_value.as_ref().map_or(0, |v| v.size_bytes());
+        self.check_byte_quota(perms, objowner, adding)?;
 
         self.tx.define_property(
             definer,
@@ -502,6 +671,8 @@ impl WorldState for DbTxWorldState {
         let (objflags, obj_owner) = (self.flags_of(obj)?, self.owner_of(obj)?);
         self.perms(perms)?
             .check_object_allows(obj_owner, objflags, ObjFlag::Write.into())?;
+        let adding = names.iter().map(|n| n.len()).sum::<usize>() + binary.len();
+        self.check_byte_quota(perms, obj_owner, adding)?;
 
         self.tx
             .add_object_verb(obj, owner, names, binary, binary_type, flags, args)?;
@@ -606,7 +777,18 @@ impl WorldState for DbTxWorldState {
         obj: Objid,
         vname: &str,
     ) -> Result<VerbInfo, WorldStateError> {
-        let vh = self.tx.resolve_verb(obj, vname.to_string(), None)?;
+        let vh = match self.tx.resolve_verb(obj, vname.to_string(), None) {
+            Ok(vh) => {
+                VERB_LOOKUP_HITS.fetch_add(1, Ordering::Relaxed);
+                vh
+            }
+            Err(e) => {
+                if let WorldStateError::VerbNotFound(..) = e {
+                    VERB_LOOKUP_MISSES.fetch_add(1, Ordering::Relaxed);
+                }
+                return Err(e);
+            }
+        };
         self.perms(perms)?
             .check_verb_allows(vh.owner(), vh.flags(), VerbFlag::Read)?;
 
@@ -671,13 +853,19 @@ impl WorldState for DbTxWorldState {
         self.tx.get_object_parent(obj)
     }
 
+    #[tracing::instrument(skip(self))]
+    fn ancestors_of(&self, _perms: Objid, obj: Objid) -> Result<ObjSet, WorldStateError> {
+        self.tx.ancestors(obj)
+    }
+
     fn change_parent(
         &mut self,
         perms: Objid,
         obj: Objid,
         new_parent: Objid,
     ) -> Result<(), WorldStateError> {
-        if obj == new_parent {
+        // Reject making obj its own ancestor, whether directly or via new_parent's own ancestry.
+        if new_parent != NOTHING && self.tx.ancestors(new_parent)?.contains(obj) {
             return Err(WorldStateError::RecursiveMove(obj, new_parent));
         }
 
@@ -738,6 +926,11 @@ impl WorldState for DbTxWorldState {
         self.tx.db_usage()
     }
 
+    fn verify_database(&self, perms: Objid) -> Result<Vec<IntegrityFinding>, WorldStateError> {
+        self.perms(perms)?.check_wizard()?;
+        self.tx.verify_integrity()
+    }
+
     #[tracing::instrument(skip(self))]
     fn commit(&mut self) -> Result<CommitResult, WorldStateError> {
         self.tx.commit()
@@ -37,6 +37,8 @@ pub trait LoaderInterface {
         objid: Option<Objid>,
         attrs: &ObjAttrs,
     ) -> Result<Objid, WorldStateError>;
+    /// Create many objects at once, all with their ids given.
+    fn create_objects(&self, objects: &[(Objid, ObjAttrs)]) -> Result<(), WorldStateError>;
     fn set_object_parent(&self, obj: Objid, parent: Objid) -> Result<(), WorldStateError>;
 
     fn set_object_location(&self, o: Objid, location: Objid) -> Result<(), WorldStateError>;
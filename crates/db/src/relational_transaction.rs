@@ -24,6 +24,7 @@ pub enum RelationalError {
     ConflictRetry,
     Duplicate(String),
     NotFound,
+    InvalidSavepoint,
 }
 
 impl Display for RelationalError {
@@ -32,17 +33,29 @@ impl Display for RelationalError {
             RelationalError::ConflictRetry => write!(f, "ConflictRetry"),
             RelationalError::Duplicate(s) => write!(f, "Duplicate: {}", s),
             RelationalError::NotFound => write!(f, "NotFound"),
+            RelationalError::InvalidSavepoint => write!(f, "InvalidSavepoint"),
         }
     }
 }
 
 impl Error for RelationalError {}
 
+/// A position in a transaction's history which it can later be partially rolled back to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SavepointId(pub usize);
+
 /// Traits defining a generic quasi binary-relational database transaction.
 pub trait RelationalTransaction<Relation> {
     fn commit(&self) -> CommitResult;
     fn rollback(&self);
 
+    /// Mark the current point in the transaction, so that changes made after it can be discarded
+    /// with `rollback_to` without abandoning the whole transaction.
+    fn savepoint(&self) -> SavepointId;
+    /// Discard all changes made since `savepoint` was taken. Any savepoints taken after it are
+    /// invalidated.
+    fn rollback_to(&self, savepoint: SavepointId) -> Result<()>;
+
     fn increment_sequence<S: Into<u8>>(&self, seq: S) -> i64;
     fn update_sequence_max<S: Into<u8>>(&self, seq: S, value: i64) -> i64;
     fn get_sequence<S: Into<u8>>(&self, seq: S) -> i64;
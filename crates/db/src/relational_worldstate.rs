@@ -19,9 +19,9 @@ use crate::{
 };
 use bytes::Bytes;
 use moor_values::model::{
-    BinaryType, CommitResult, HasUuid, Named, ObjAttrs, ObjFlag, ObjSet, PropDef, PropDefs,
-    PropFlag, PropPerms, ValSet, VerbArgsSpec, VerbAttrs, VerbDef, VerbDefs, VerbFlag,
-    WorldStateError,
+    BinaryType, CommitResult, HasUuid, IntegrityFinding, IntegrityProblem, Named, ObjAttrs,
+    ObjFlag, ObjSet, ObjectBytes, PropDef, PropDefs, PropFlag, PropPerms, ValSet, VerbArgsSpec,
+    VerbAttrs, VerbDef, VerbDefs, VerbFlag, WorldStateError,
 };
 use moor_values::util::BitEnum;
 use moor_values::var::{v_none, Objid, Var};
@@ -185,45 +185,58 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
             }
         };
 
-        let owner = attrs.owner().unwrap_or(id);
-        self.tx
-            .as_ref()
-            .unwrap()
-            .upsert(WorldStateTable::ObjectOwner, id, owner)
-            .expect("Unable to insert initial owner");
+        self.insert_object(id, &attrs);
 
-        // Set initial name
-        let name = attrs.name().unwrap_or_default();
+        // Update the maximum object number if ours is higher than the current one. This is for the
+        // textdump case, where our numbers are coming in arbitrarily.
         self.tx
             .as_ref()
             .unwrap()
-            .upsert(WorldStateTable::ObjectName, id, StringHolder(name))
-            .expect("Unable to insert initial name");
+            .update_sequence_max(WorldStateSequence::MaximumObject, id.0 + 1);
 
-        // We use our own setters for these, since there's biz-logic attached here...
-        if let Some(parent) = attrs.parent() {
-            self.set_object_parent(id, parent)
-                .expect("Unable to set parent");
-        }
-        if let Some(location) = attrs.location() {
-            self.set_object_location(id, location)
-                .expect("Unable to set location");
+        Ok(id)
+    }
+
+    fn create_objects(&self, objects: &[(Objid, ObjAttrs)]) -> Result<(), WorldStateError> {
+        let Some(max) = objects.iter().map(|(id, _)| id.0).max() else {
+            return Ok(());
+        };
+
+        // Everything in the batch is brand new, so its rows can go straight in.
+        let tx = self.tx.as_ref().unwrap();
+        for (id, attrs) in objects {
+            let insert_err = |e: RelationalError| match e {
+                RelationalError::Duplicate(_) => WorldStateError::ObjectAlreadyExists(*id),
+                e => err_map(e),
+            };
+            let owner = attrs.owner().unwrap_or(*id);
+            tx.insert_tuple(WorldStateTable::ObjectOwner, *id, owner)
+                .map_err(insert_err)?;
+            let name = attrs.name().unwrap_or_default();
+            tx.insert_tuple(WorldStateTable::ObjectName, *id, StringHolder(name))
+                .map_err(insert_err)?;
+            tx.insert_tuple(WorldStateTable::ObjectFlags, *id, attrs.flags())
+                .map_err(insert_err)?;
         }
 
-        self.tx
-            .as_ref()
-            .unwrap()
-            .upsert(WorldStateTable::ObjectFlags, id, attrs.flags())
-            .expect("Unable to insert initial flags");
+        // Parents and locations go through the setters, now that every object in the batch
+        // exists to be pointed at.
+        for (id, attrs) in objects {
+            if let Some(parent) = attrs.parent() {
+                self.set_object_parent(*id, parent)?;
+            }
+            if let Some(location) = attrs.location() {
+                self.set_object_location(*id, location)?;
+            }
+        }
 
-        // Update the maximum object number if ours is higher than the current one. This is for the
-        // textdump case, where our numbers are coming in arbitrarily.
+        // And bump the maximum object number just the once, for the whole lot.
         self.tx
             .as_ref()
             .unwrap()
-            .update_sequence_max(WorldStateSequence::MaximumObject, id.0 + 1);
+            .update_sequence_max(WorldStateSequence::MaximumObject, max + 1);
 
-        Ok(id)
+        Ok(())
     }
 
     fn recycle_object(&self, obj: Objid) -> Result<(), WorldStateError> {
@@ -304,6 +317,29 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
 
     // TODO: wiredtiger has joins. we should add join&transitive join to the interface and use it
     fn set_object_parent(&self, o: Objid, new_parent: Objid) -> Result<(), WorldStateError> {
+        // Detect inheritance cycles, before we touch anything.
+        let mut oid = new_parent;
+        while oid != NOTHING {
+            if oid == o {
+                return Err(WorldStateError::RecursiveMove(o, new_parent));
+            }
+            let Some(parent) = self
+                .tx
+                .as_ref()
+                .unwrap()
+                .seek_unique_by_domain(WorldStateTable::ObjectParent, oid)
+                .map_err(err_map)?
+            else {
+                break;
+            };
+            oid = parent;
+        }
+
+        // Nor can the move leave two properties of the same name on any object in o's subtree.
+        if new_parent != NOTHING {
+            self.check_property_conflicts(o, new_parent)?;
+        }
+
         // Steps for object re-parenting:
 
         // Get o's old-parents's children
@@ -319,6 +355,8 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
         // c) all the new ancestors we'd have after the reparenting, all in one go. Hopefully.
         let (_shared_ancestor, new_ancestors, old_ancestors) =
             self.closest_common_ancestor_with_ancestors(new_parent, o)?;
+        // The old ancestry includes me, but my own properties come along with me.
+        let old_ancestors = old_ancestors.with_removed(o);
 
         // Remove from _me_ any of the properties defined by any of my ancestors
         if let Some(old_props) = self
@@ -330,7 +368,7 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
         {
             let mut delort_props = vec![];
             for p in old_props.iter() {
-                if old_ancestors.contains(&p.definer()) {
+                if old_ancestors.contains(p.definer()) {
                     delort_props.push(p.uuid());
 
                     self.tx
@@ -352,9 +390,9 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
                 .expect("Unable to update propdefs");
         }
 
-        // Now walk all-my-children and destroy all the properties whose definer is me or any
-        // of my ancestors not shared by the new parent.
-        let descendants = self.descendants(o)?;
+        // Now walk all-my-children and destroy all the properties whose definer is any of my
+        // ancestors not shared by the new parent.
+        let descendants = self.descendants(o, None)?;
 
         let mut descendant_props = HashMap::new();
         for c in descendants.iter() {
@@ -364,11 +402,11 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
                 .tx
                 .as_ref()
                 .unwrap()
-                .seek_unique_by_domain::<Objid, PropDefs>(WorldStateTable::ObjectPropDefs, o)
+                .seek_unique_by_domain::<Objid, PropDefs>(WorldStateTable::ObjectPropDefs, c)
                 .map_err(err_map)?
             {
                 for p in old_props.iter() {
-                    if old_ancestors.contains(&p.definer()) {
+                    if old_ancestors.contains(p.definer()) {
                         inherited_props.push(p.uuid());
                         self.tx
                             .as_ref()
@@ -409,6 +447,14 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
             .upsert(WorldStateTable::ObjectParent, o, new_parent)
             .expect("Unable to update parent");
 
+        for (c, c_props) in descendant_props {
+            self.tx
+                .as_ref()
+                .unwrap()
+                .upsert(WorldStateTable::ObjectPropDefs, c, c_props)
+                .expect("Unable to update propdefs");
+        }
+
         if new_parent == NOTHING {
             return Ok(());
         }
@@ -419,7 +465,7 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
         // Now collect properties defined on the new ancestors so we can define the owners on
         // the new descendants.
         let mut new_props = vec![];
-        for a in new_ancestors {
+        for a in new_ancestors.iter() {
             if let Some(props) = self
                 .tx
                 .as_ref()
@@ -447,7 +493,9 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
         }
         // Then put clear copies on each of the descendants ... and me.
         // This really just means defining the property with no value, which is what we do.
-        let descendants = self.descendants(o).expect("Unable to get descendants");
+        let descendants = self
+            .descendants(o, None)
+            .expect("Unable to get descendants");
         for c in descendants.iter().chain(std::iter::once(o)) {
             for (p, propperms) in new_props.iter() {
                 self.tx
@@ -491,37 +539,37 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
             .map_err(err_map)
     }
 
-    fn get_object_size_bytes(&self, obj: Objid) -> Result<usize, WorldStateError> {
-        let mut size = 0;
-        size += self
+    fn get_object_size_bytes(&self, obj: Objid) -> Result<ObjectBytes, WorldStateError> {
+        let mut size = ObjectBytes::default();
+        size.overhead += self
             .tx
             .as_ref()
             .unwrap()
             .tuple_size_for_unique_domain(WorldStateTable::ObjectOwner, obj)
             .map_err(err_map)?
             .unwrap_or(0);
-        size += self
+        size.overhead += self
             .tx
             .as_ref()
             .unwrap()
             .tuple_size_for_unique_domain(WorldStateTable::ObjectFlags, obj)
             .map_err(err_map)?
             .unwrap_or(0);
-        size += self
+        size.overhead += self
             .tx
             .as_ref()
             .unwrap()
             .tuple_size_for_unique_domain(WorldStateTable::ObjectName, obj)
             .map_err(err_map)?
             .unwrap_or(0);
-        size += self
+        size.overhead += self
             .tx
             .as_ref()
             .unwrap()
             .tuple_size_for_unique_domain(WorldStateTable::ObjectParent, obj)
             .map_err(err_map)?
             .unwrap_or(0);
-        size += self
+        size.overhead += self
             .tx
             .as_ref()
             .unwrap()
@@ -536,7 +584,7 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
             .seek_unique_by_domain::<Objid, VerbDefs>(WorldStateTable::ObjectVerbs, obj)
             .map_err(err_map)?
         {
-            size += self
+            size.verbs += self
                 .tx
                 .as_ref()
                 .unwrap()
@@ -544,7 +592,7 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
                 .map_err(err_map)?
                 .unwrap_or(0);
             for v in verbs.iter() {
-                size += self
+                size.programs += self
                     .tx
                     .as_ref()
                     .unwrap()
@@ -565,7 +613,7 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
             .seek_unique_by_domain::<Objid, PropDefs>(WorldStateTable::ObjectPropDefs, obj)
             .map_err(err_map)?
         {
-            size += self
+            size.props += self
                 .tx
                 .as_ref()
                 .unwrap()
@@ -573,7 +621,7 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
                 .map_err(err_map)?
                 .unwrap_or(0);
             for p in props.iter() {
-                size += self
+                size.props += self
                     .tx
                     .as_ref()
                     .unwrap()
@@ -904,7 +952,7 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
         perms: BitEnum<PropFlag>,
         value: Option<Var>,
     ) -> Result<Uuid, WorldStateError> {
-        let descendants = self.descendants(location)?;
+        let descendants = self.descendants(location, None)?;
 
         // If the property is already defined at us or above or below us, that's a failure.
         let props = match self
@@ -1063,13 +1111,12 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
             .as_ref()
             .unwrap()
             .delete_composite_if_exists(WorldStateTable::ObjectPropertyValue, obj, UUIDHolder(uuid))
-            .unwrap_or(());
-        Ok(())
+            .map_err(err_map)
     }
 
     fn delete_property(&self, obj: Objid, uuid: Uuid) -> Result<(), WorldStateError> {
         // delete propdef from self and all descendants
-        let descendants = self.descendants(obj)?;
+        let descendants = self.descendants(obj, None)?;
         let locations = ObjSet::from_items(&[obj]).with_concatenated(descendants);
         for location in locations.iter() {
             let props: PropDefs = self
@@ -1093,6 +1140,24 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
         Ok(())
     }
 
+    fn get_owner_bytes(&self, owner: Objid) -> Result<usize, WorldStateError> {
+        // Measured afresh each time, rather than kept as a running total which every write to
+        // any of the owner's objects would have to update (and so contend on).
+        let owned = self
+            .tx
+            .as_ref()
+            .unwrap()
+            .scan_with_predicate(WorldStateTable::ObjectOwner, |_: &Objid, o: &Objid| {
+                *o == owner
+            })
+            .map_err(err_map)?;
+        let mut used = 0;
+        for (obj, _) in owned {
+            used += self.get_object_size_bytes(obj)?.total();
+        }
+        Ok(used)
+    }
+
     fn retrieve_property(
         &self,
         obj: Objid,
@@ -1221,10 +1286,165 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
         }
     }
 
+    fn resolve_properties(
+        &self,
+        obj: Objid,
+        names: &[&str],
+    ) -> Result<HashMap<String, (PropDef, Var, PropPerms, bool)>, WorldStateError> {
+        // Gather the inheritance chain once, from obj up to the root.
+        let mut chain = vec![obj];
+        let mut search_obj = obj;
+        while let Some(parent) = self
+            .tx
+            .as_ref()
+            .unwrap()
+            .seek_unique_by_domain(WorldStateTable::ObjectParent, search_obj)
+            .map_err(err_map)?
+        {
+            if parent == NOTHING {
+                break;
+            }
+            chain.push(parent);
+            search_obj = parent;
+        }
+
+        // Find the definition for each name, nearest first, stopping once they're all found.
+        let mut propdefs = HashMap::new();
+        for search_obj in &chain {
+            let defined = self.get_properties(*search_obj)?;
+            for name in names {
+                if propdefs.contains_key(*name) {
+                    continue;
+                }
+                if let Some(propdef) = defined.find_first_named(name) {
+                    propdefs.insert(name.to_string(), propdef);
+                }
+            }
+            if propdefs.len() == names.len() {
+                break;
+            }
+        }
+
+        // Then the perms from obj itself, and the value from the nearest object on the chain which
+        // has one (i.e. isn't 'clear').
+        let mut resolved = HashMap::with_capacity(propdefs.len());
+        for (name, propdef) in propdefs {
+            let perms = self
+                .tx
+                .as_ref()
+                .unwrap()
+                .seek_by_unique_composite_domain::<_, _, PropPerms>(
+                    WorldStateTable::ObjectPropertyPermissions,
+                    obj,
+                    UUIDHolder(propdef.uuid()),
+                )
+                .map_err(err_map)?
+                .expect("Unable to get property permissions, coherence problem");
+
+            let mut value = None;
+            for (depth, search_obj) in chain.iter().enumerate() {
+                if let Some(v) = self
+                    .tx
+                    .as_ref()
+                    .unwrap()
+                    .seek_by_unique_composite_domain::<_, _, Var>(
+                        WorldStateTable::ObjectPropertyValue,
+                        *search_obj,
+                        UUIDHolder(propdef.uuid()),
+                    )
+                    .map_err(err_map)?
+                {
+                    value = Some((v, depth != 0));
+                    break;
+                }
+            }
+            let (value, clear) = value.unwrap_or((v_none(), true));
+            resolved.insert(name, (propdef, value, perms, clear));
+        }
+        Ok(resolved)
+    }
+
     fn db_usage(&self) -> Result<usize, WorldStateError> {
         todo!("Implement db_usage")
     }
 
+    fn verify_integrity(&self) -> Result<Vec<IntegrityFinding>, WorldStateError> {
+        let mut findings = vec![];
+        let mut finding = |kind, obj, detail: String| {
+            findings.push(IntegrityFinding { kind, obj, detail });
+        };
+        for obj in self.get_objects()?.iter() {
+            let parent = self.get_object_parent(obj)?;
+            if parent != NOTHING {
+                if !self.object_valid(parent)? {
+                    finding(
+                        IntegrityProblem::BadParent,
+                        obj,
+                        format!("parent {} does not exist", parent),
+                    );
+                }
+            }
+            // Children and contents are looked up from the parent and location relations, so they
+            // always agree with those; but rows left behind by a half-recycled object still list
+            // it.
+            for child in self.get_object_children(obj)?.iter() {
+                if !self.object_valid(child)? {
+                    finding(
+                        IntegrityProblem::StaleChild,
+                        obj,
+                        format!("child {} does not exist", child),
+                    );
+                }
+            }
+
+            let location = self.get_object_location(obj)?;
+            if location != NOTHING {
+                if !self.object_valid(location)? {
+                    finding(
+                        IntegrityProblem::BadLocation,
+                        obj,
+                        format!("location {} does not exist", location),
+                    );
+                }
+            }
+            for content in self.get_object_contents(obj)?.iter() {
+                if !self.object_valid(content)? {
+                    finding(
+                        IntegrityProblem::StaleContent,
+                        obj,
+                        format!("{} is in its contents but does not exist", content),
+                    );
+                }
+            }
+
+            // Every property defined here should have a slot here and on every descendant.
+            let propdefs = self.get_properties(obj)?;
+            if propdefs.is_empty() {
+                continue;
+            }
+            let holders =
+                ObjSet::from_items(&[obj]).with_concatenated(self.descendants(obj, None)?);
+            for propdef in propdefs.iter() {
+                for holder in holders.iter() {
+                    match self.retrieve_property_permissions(holder, propdef.uuid()) {
+                        Ok(_) => {}
+                        Err(WorldStateError::PropertyNotFound(_, _)) => finding(
+                            IntegrityProblem::MissingPropval,
+                            holder,
+                            format!(
+                                "no value for property {} defined on {}",
+                                propdef.name(),
+                                obj
+                            ),
+                        ),
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+        }
+        Ok(findings)
+    }
+
     fn commit(&mut self) -> Result<CommitResult, WorldStateError> {
         Ok(self.tx.take().unwrap().commit())
     }
@@ -1236,78 +1456,132 @@ impl<RTX: RelationalTransaction<WorldStateTable>> WorldStateTransaction
 }
 
 impl<RTX: RelationalTransaction<WorldStateTable>> RelationalWorldStateTransaction<RTX> {
-    pub fn descendants(&self, obj: Objid) -> Result<ObjSet, WorldStateError> {
-        let children = self
-            .tx
+    /// Write out the initial attributes of a new object with an already-chosen id.
+    fn insert_object(&self, id: Objid, attrs: &ObjAttrs) {
+        let owner = attrs.owner().unwrap_or(id);
+        self.tx
             .as_ref()
             .unwrap()
-            .seek_by_codomain::<Objid, Objid, ObjSet>(WorldStateTable::ObjectParent, obj)
-            .map_err(err_map)?;
+            .upsert(WorldStateTable::ObjectOwner, id, owner)
+            .expect("Unable to insert initial owner");
+
+        // Set initial name
+        let name = attrs.name().unwrap_or_default();
+        self.tx
+            .as_ref()
+            .unwrap()
+            .upsert(WorldStateTable::ObjectName, id, StringHolder(name))
+            .expect("Unable to insert initial name");
 
+        // We use our own setters for these, since there's biz-logic attached here...
+        if let Some(parent) = attrs.parent() {
+            self.set_object_parent(id, parent)
+                .expect("Unable to set parent");
+        }
+        if let Some(location) = attrs.location() {
+            self.set_object_location(id, location)
+                .expect("Unable to set location");
+        }
+
+        self.tx
+            .as_ref()
+            .unwrap()
+            .upsert(WorldStateTable::ObjectFlags, id, attrs.flags())
+            .expect("Unable to insert initial flags");
+    }
+
+    /// All the descendants of `obj`, breadth first. With a `max_depth`, stop that many
+    /// generations down (1 being just the children).
+    /// Each object is returned at most once, even if the parent/child relation has a cycle in it.
+    pub fn descendants(
+        &self,
+        obj: Objid,
+        max_depth: Option<usize>,
+    ) -> Result<ObjSet, WorldStateError> {
         let mut descendants = vec![];
-        let mut queue: VecDeque<_> = children.iter().collect();
-        while let Some(o) = queue.pop_front() {
-            descendants.push(o);
+        let mut visited = HashSet::from([obj]);
+        let mut queue = VecDeque::from([(obj, 0)]);
+        while let Some((o, depth)) = queue.pop_front() {
+            if max_depth.is_some_and(|max_depth| depth >= max_depth) {
+                continue;
+            }
             let children = self
                 .tx
                 .as_ref()
                 .unwrap()
                 .seek_by_codomain::<Objid, Objid, ObjSet>(WorldStateTable::ObjectParent, o)
                 .map_err(err_map)?;
-            queue.extend(children.iter());
+            for child in children.iter() {
+                if visited.insert(child) {
+                    descendants.push(child);
+                    queue.push_back((child, depth + 1));
+                }
+            }
         }
 
         Ok(ObjSet::from_items(&descendants))
     }
 
-    #[allow(clippy::type_complexity)]
+    /// Check that nothing `o` or its descendants define shares a name with a property defined on
+    /// `new_parent` or any of its ancestors. Returns the first such conflict found.
+    fn check_property_conflicts(&self, o: Objid, new_parent: Objid) -> Result<(), WorldStateError> {
+        let mut inherited = vec![];
+        for ancestor in self.ancestors(new_parent)?.iter() {
+            if let Some(props) = self
+                .tx
+                .as_ref()
+                .unwrap()
+                .seek_unique_by_domain::<Objid, PropDefs>(WorldStateTable::ObjectPropDefs, ancestor)
+                .map_err(err_map)?
+            {
+                inherited.push(props);
+            }
+        }
+        if inherited.is_empty() {
+            return Ok(());
+        }
+        let subtree = std::iter::once(o).chain(self.descendants(o, None)?.iter());
+        for obj in subtree {
+            let Some(props) = self
+                .tx
+                .as_ref()
+                .unwrap()
+                .seek_unique_by_domain::<Objid, PropDefs>(WorldStateTable::ObjectPropDefs, obj)
+                .map_err(err_map)?
+            else {
+                continue;
+            };
+            for p in props.iter().filter(|p| p.definer() == obj) {
+                if inherited
+                    .iter()
+                    .any(|props| props.find_first_named(p.name()).is_some())
+                {
+                    return Err(WorldStateError::DuplicatePropertyDefinition(
+                        obj,
+                        p.name().to_string(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the closest ancestor `a` and `b` have in common (if any), along with the ancestors
+    /// (+ self) of each which the other doesn't share.
     fn closest_common_ancestor_with_ancestors(
         &self,
         a: Objid,
         b: Objid,
-    ) -> Result<(Option<Objid>, HashSet<Objid>, HashSet<Objid>), WorldStateError> {
-        let mut ancestors_a = HashSet::new();
-        let mut search_a = a;
-
-        let mut ancestors_b = HashSet::new();
-        let mut search_b = b;
-
-        loop {
-            if search_a == NOTHING && search_b == NOTHING {
-                return Ok((None, ancestors_a, ancestors_b)); // No common ancestor found
-            }
-
-            if ancestors_b.contains(&search_a) {
-                return Ok((Some(search_a), ancestors_a, ancestors_b)); // Common ancestor found
-            }
-
-            if ancestors_a.contains(&search_b) {
-                return Ok((Some(search_b), ancestors_a, ancestors_b)); // Common ancestor found
-            }
-
-            if search_a != NOTHING {
-                ancestors_a.insert(search_a);
-                let parent = self
-                    .tx
-                    .as_ref()
-                    .unwrap()
-                    .seek_unique_by_domain(WorldStateTable::ObjectParent, search_a)
-                    .map_err(err_map)?
-                    .unwrap_or(NOTHING);
-                search_a = parent;
-            }
-
-            if search_b != NOTHING {
-                ancestors_b.insert(search_b);
-                let parent = self
-                    .tx
-                    .as_ref()
-                    .unwrap()
-                    .seek_unique_by_domain(WorldStateTable::ObjectParent, search_b)
-                    .map_err(err_map)?
-                    .unwrap_or(NOTHING);
-                search_b = parent;
-            }
-        }
+    ) -> Result<(Option<Objid>, ObjSet, ObjSet), WorldStateError> {
+        let ancestors_a = self.ancestors(a)?;
+        let ancestors_b = self.ancestors(b)?;
+
+        // Ancestors come nearest-first, so the first shared one is the closest.
+        let closest = ancestors_a.intersection(&ancestors_b).iter().next();
+        Ok((
+            closest,
+            ancestors_a.difference(&ancestors_b),
+            ancestors_b.difference(&ancestors_a),
+        ))
     }
 }
@@ -15,11 +15,11 @@
 //! A set of common tests for any world state implementation.
 
 use crate::worldstate_transaction::WorldStateTransaction;
-use crate::{RelationalTransaction, RelationalWorldStateTransaction, WorldStateTable};
+use crate::{RelationalTransaction, RelationalWorldStateTransaction, UUIDHolder, WorldStateTable};
 use moor_values::model::ObjSet;
 use moor_values::model::VerbArgsSpec;
 use moor_values::model::{BinaryType, VerbAttrs};
-use moor_values::model::{CommitResult, WorldStateError};
+use moor_values::model::{CommitResult, IntegrityProblem, WorldStateError};
 use moor_values::model::{HasUuid, Named};
 use moor_values::model::{ObjAttrs, PropFlag, ValSet};
 use moor_values::util::BitEnum;
@@ -71,6 +71,55 @@ where
     assert_eq!(tx.commit(), Ok(CommitResult::Success));
 }
 
+pub fn perform_test_create_objects<F, TX>(begin_tx: F)
+where
+    F: Fn() -> RelationalWorldStateTransaction<TX>,
+    TX: RelationalTransaction<WorldStateTable>,
+{
+    let mut tx = begin_tx();
+
+    // Out of order, with gaps, and one parented on another in the batch.
+    tx.create_objects(&[
+        (
+            Objid(5),
+            ObjAttrs::new(NOTHING, NOTHING, NOTHING, BitEnum::new(), "five"),
+        ),
+        (
+            Objid(2),
+            ObjAttrs::new(Objid(2), NOTHING, NOTHING, BitEnum::new(), "two"),
+        ),
+        (
+            Objid(9),
+            ObjAttrs::new(NOTHING, Objid(5), NOTHING, BitEnum::new(), "nine"),
+        ),
+    ])
+    .unwrap();
+    assert_eq!(tx.get_object_name(Objid(5)).unwrap(), "five");
+    assert_eq!(tx.get_object_name(Objid(2)).unwrap(), "two");
+    assert_eq!(tx.get_object_name(Objid(9)).unwrap(), "nine");
+    assert_eq!(tx.get_object_owner(Objid(2)).unwrap(), Objid(2));
+    assert_eq!(tx.get_object_parent(Objid(9)).unwrap(), Objid(5));
+    assert!(!tx.object_valid(Objid(3)).unwrap());
+
+    // The next allocated id follows on from the highest in the batch.
+    let oid = tx.create_object(None, ObjAttrs::default()).unwrap();
+    assert_eq!(oid, Objid(10));
+
+    // An empty batch is a no-op.
+    tx.create_objects(&[]).unwrap();
+
+    // Ids already in use are refused, rather than overwritten.
+    assert_eq!(
+        tx.create_objects(&[(
+            Objid(2),
+            ObjAttrs::new(NOTHING, NOTHING, NOTHING, BitEnum::new(), "again"),
+        )]),
+        Err(WorldStateError::ObjectAlreadyExists(Objid(2)))
+    );
+    assert_eq!(tx.get_object_name(Objid(2)).unwrap(), "two");
+    assert_eq!(tx.commit(), Ok(CommitResult::Success));
+}
+
 pub fn perform_test_parent_children<F, TX>(begin_tx: F)
 where
     F: Fn() -> RelationalWorldStateTransaction<TX>,
@@ -140,6 +189,150 @@ where
     assert_eq!(tx.commit(), Ok(CommitResult::Success));
 }
 
+pub fn perform_test_reparent_cycle<F, TX>(begin_tx: F)
+where
+    F: Fn() -> RelationalWorldStateTransaction<TX>,
+    TX: RelationalTransaction<WorldStateTable>,
+{
+    let mut tx = begin_tx();
+
+    let a = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, NOTHING, NOTHING, BitEnum::new(), "a"),
+        )
+        .unwrap();
+    let b = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, a, NOTHING, BitEnum::new(), "b"),
+        )
+        .unwrap();
+    let c = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, b, NOTHING, BitEnum::new(), "c"),
+        )
+        .unwrap();
+
+    // Direct: a is b's parent, so a can't become b's child.
+    match tx.set_object_parent(a, b).err() {
+        Some(WorldStateError::RecursiveMove(_, _)) => {}
+        _ => {
+            panic!("Expected recursive move error");
+        }
+    }
+
+    // Indirect: a is c's grandparent.
+    match tx.set_object_parent(a, c).err() {
+        Some(WorldStateError::RecursiveMove(_, _)) => {}
+        _ => {
+            panic!("Expected recursive move error");
+        }
+    }
+
+    // Nor can anything be its own parent.
+    match tx.set_object_parent(a, a).err() {
+        Some(WorldStateError::RecursiveMove(_, _)) => {}
+        _ => {
+            panic!("Expected recursive move error");
+        }
+    }
+
+    // Nothing was changed by the failed attempts.
+    assert_eq!(tx.get_object_parent(a).unwrap(), NOTHING);
+    assert_eq!(tx.get_object_parent(b).unwrap(), a);
+    assert_eq!(tx.get_object_parent(c).unwrap(), b);
+    assert!(tx
+        .descendants(a, None)
+        .unwrap()
+        .is_same(ObjSet::from_items(&[b, c])));
+
+    // Moving c up under a is fine.
+    tx.set_object_parent(c, a).unwrap();
+    assert_eq!(tx.get_object_parent(c).unwrap(), a);
+
+    assert_eq!(tx.commit(), Ok(CommitResult::Success));
+}
+
+pub fn perform_test_reparent_property_conflict<F, TX>(begin_tx: F)
+where
+    F: Fn() -> RelationalWorldStateTransaction<TX>,
+    TX: RelationalTransaction<WorldStateTable>,
+{
+    let mut tx = begin_tx();
+
+    let a = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, NOTHING, NOTHING, BitEnum::new(), "a"),
+        )
+        .unwrap();
+    let a_child = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, a, NOTHING, BitEnum::new(), "a_child"),
+        )
+        .unwrap();
+    let b = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, NOTHING, NOTHING, BitEnum::new(), "b"),
+        )
+        .unwrap();
+    let c = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, b, NOTHING, BitEnum::new(), "c"),
+        )
+        .unwrap();
+
+    tx.define_property(a, a, "x".into(), NOTHING, BitEnum::new(), Some(v_int(1)))
+        .unwrap();
+    tx.define_property(c, c, "X".into(), NOTHING, BitEnum::new(), Some(v_int(2)))
+        .unwrap();
+    tx.define_property(b, b, "y".into(), NOTHING, BitEnum::new(), Some(v_int(3)))
+        .unwrap();
+
+    // c (a descendant of b) defines a property with the same name as one a defines, whether a
+    // is to be the direct parent or further up the chain.
+    match tx.set_object_parent(b, a).err() {
+        Some(WorldStateError::DuplicatePropertyDefinition(obj, name)) => {
+            assert_eq!(obj, c);
+            assert_eq!(name, "X");
+        }
+        e => {
+            panic!("Expected duplicate property definition error, got {:?}", e);
+        }
+    }
+    match tx.set_object_parent(b, a_child).err() {
+        Some(WorldStateError::DuplicatePropertyDefinition(obj, name)) => {
+            assert_eq!(obj, c);
+            assert_eq!(name, "X");
+        }
+        e => {
+            panic!("Expected duplicate property definition error, got {:?}", e);
+        }
+    }
+
+    // Nothing was changed by the failed attempt.
+    assert_eq!(tx.get_object_parent(b).unwrap(), NOTHING);
+    assert!(tx.get_object_children(a_child).unwrap().is_empty());
+    assert!(tx.resolve_property(b, "x".into()).is_err());
+    let (_, v, _, _) = tx.resolve_property(c, "x".into()).unwrap();
+    assert_eq!(v, v_int(2));
+
+    // Once the clashing definition is gone, the move goes through.
+    let (xdef, _, _, _) = tx.resolve_property(c, "x".into()).unwrap();
+    tx.delete_property(c, xdef.uuid()).unwrap();
+    tx.set_object_parent(b, a_child).unwrap();
+    assert_eq!(tx.get_object_parent(b).unwrap(), a_child);
+    let (_, v, _, _) = tx.resolve_property(c, "x".into()).unwrap();
+    assert_eq!(v, v_int(1));
+
+    assert_eq!(tx.commit(), Ok(CommitResult::Success));
+}
+
 pub fn perform_test_descendants<F, TX>(begin_tx: F)
 where
     F: Fn() -> RelationalWorldStateTransaction<TX>,
@@ -180,11 +373,11 @@ where
     assert_eq!(d, Objid(3));
 
     assert!(tx
-        .descendants(a)
+        .descendants(a, None)
         .unwrap()
         .is_same(ObjSet::from_items(&[b, c, d])));
-    assert_eq!(tx.descendants(b).unwrap(), ObjSet::empty());
-    assert_eq!(tx.descendants(c).unwrap(), ObjSet::from_items(&[d]));
+    assert_eq!(tx.descendants(b, None).unwrap(), ObjSet::empty());
+    assert_eq!(tx.descendants(c, None).unwrap(), ObjSet::from_items(&[d]));
 
     // Now reparent d to b
     tx.set_object_parent(d, b).unwrap();
@@ -195,14 +388,81 @@ where
     assert_eq!(tx.get_object_children(b).unwrap(), ObjSet::from_items(&[d]));
     assert_eq!(tx.get_object_children(c).unwrap(), ObjSet::empty());
     assert!(tx
-        .descendants(a)
+        .descendants(a, None)
         .unwrap()
         .is_same(ObjSet::from_items(&[b, c, d])));
-    assert_eq!(tx.descendants(b).unwrap(), ObjSet::from_items(&[d]));
-    assert_eq!(tx.descendants(c).unwrap(), ObjSet::empty());
+    assert_eq!(tx.descendants(b, None).unwrap(), ObjSet::from_items(&[d]));
+    assert_eq!(tx.descendants(c, None).unwrap(), ObjSet::empty());
     assert_eq!(tx.commit(), Ok(CommitResult::Success));
 }
 
+pub fn perform_test_descendants_cycle<F, TX>(begin_tx: F)
+where
+    F: Fn() -> RelationalWorldStateTransaction<TX>,
+    TX: RelationalTransaction<WorldStateTable>,
+{
+    let mut tx = begin_tx();
+
+    let a = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, NOTHING, NOTHING, BitEnum::new(), "a"),
+        )
+        .unwrap();
+    let b = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, a, NOTHING, BitEnum::new(), "b"),
+        )
+        .unwrap();
+    let c = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, b, NOTHING, BitEnum::new(), "c"),
+        )
+        .unwrap();
+    let d = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, a, NOTHING, BitEnum::new(), "d"),
+        )
+        .unwrap();
+
+    // Bounded by depth.
+    assert!(tx
+        .descendants(a, Some(1))
+        .unwrap()
+        .is_same(ObjSet::from_items(&[b, d])));
+    assert!(tx
+        .descendants(a, Some(2))
+        .unwrap()
+        .is_same(ObjSet::from_items(&[b, c, d])));
+    assert_eq!(tx.descendants(a, Some(0)).unwrap(), ObjSet::empty());
+
+    // Corrupt the db behind set_object_parent's back, so that a -> b -> c -> a.
+    tx.tx
+        .as_ref()
+        .unwrap()
+        .upsert(WorldStateTable::ObjectParent, a, c)
+        .unwrap();
+
+    // Each object still comes back once, and the walk terminates.
+    assert!(tx
+        .descendants(a, None)
+        .unwrap()
+        .is_same(ObjSet::from_items(&[b, c, d])));
+    assert!(tx
+        .descendants(b, None)
+        .unwrap()
+        .is_same(ObjSet::from_items(&[c, a, d])));
+    assert_eq!(
+        tx.descendants(c, Some(1)).unwrap(),
+        ObjSet::from_items(&[a])
+    );
+
+    tx.rollback().unwrap();
+}
+
 pub fn perform_test_location_contents<F, TX>(begin_tx: F)
 where
     F: Fn() -> RelationalWorldStateTransaction<TX>,
@@ -580,6 +840,79 @@ where
     assert_eq!(tx.commit(), Ok(CommitResult::Success));
 }
 
+pub fn perform_test_resolve_properties<F, TX>(begin_tx: F)
+where
+    F: Fn() -> RelationalWorldStateTransaction<TX>,
+    TX: RelationalTransaction<WorldStateTable>,
+{
+    let mut tx = begin_tx();
+
+    let a = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, NOTHING, NOTHING, BitEnum::new(), "a"),
+        )
+        .unwrap();
+    let b = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, a, NOTHING, BitEnum::new(), "b"),
+        )
+        .unwrap();
+    let c = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, b, NOTHING, BitEnum::new(), "c"),
+        )
+        .unwrap();
+
+    // "inherited" comes from a untouched, "overridden" gets a new value on b, and "local" is
+    // defined on c itself.
+    tx.define_property(
+        a,
+        a,
+        "inherited".into(),
+        NOTHING,
+        BitEnum::new(),
+        Some(v_str("from a")),
+    )
+    .unwrap();
+    tx.define_property(
+        a,
+        a,
+        "overridden".into(),
+        NOTHING,
+        BitEnum::new(),
+        Some(v_str("from a")),
+    )
+    .unwrap();
+    tx.define_property(c, c, "local".into(), c, BitEnum::new(), Some(v_int(3)))
+        .unwrap();
+    let (overridden, _, _, _) = tx.resolve_property(b, "overridden".into()).unwrap();
+    tx.set_property(b, overridden.uuid(), v_str("from b"))
+        .unwrap();
+
+    let names = ["inherited", "overridden", "local", "missing"];
+    let resolved = tx.resolve_properties(c, &names).unwrap();
+    assert_eq!(resolved.len(), 3);
+    for name in ["inherited", "overridden", "local"] {
+        let (prop, v, perms, is_clear) = tx.resolve_property(c, name.into()).unwrap();
+        let (bulk_prop, bulk_v, bulk_perms, bulk_is_clear) = &resolved[name];
+        assert_eq!(bulk_prop.uuid(), prop.uuid());
+        assert_eq!(*bulk_v, v);
+        assert_eq!(*bulk_perms, perms);
+        assert_eq!(*bulk_is_clear, is_clear);
+    }
+    assert_eq!(resolved["inherited"].1, v_str("from a"));
+    assert_eq!(resolved["overridden"].1, v_str("from b"));
+    assert!(resolved["overridden"].3);
+    assert_eq!(resolved["local"].1, v_int(3));
+    assert!(!resolved["local"].3);
+    assert!(!resolved.contains_key("missing"));
+
+    assert_eq!(tx.commit(), Ok(CommitResult::Success));
+}
+
 pub fn perform_test_rename_property<F, TX>(begin_tx: F)
 where
     F: Fn() -> RelationalWorldStateTransaction<TX>,
@@ -961,6 +1294,103 @@ where
     assert!(is_clear);
 }
 
+pub fn perform_test_reparent_descendants<F, TX>(begin_tx: F)
+where
+    F: Fn() -> RelationalWorldStateTransaction<TX>,
+    TX: RelationalTransaction<WorldStateTable>,
+{
+    let mut tx = begin_tx();
+    let a = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, NOTHING, NOTHING, BitEnum::new(), "a"),
+        )
+        .unwrap();
+    // o, with a child, with a child of its own.
+    let o = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, a, NOTHING, BitEnum::new(), "o"),
+        )
+        .unwrap();
+    let c1 = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, o, NOTHING, BitEnum::new(), "c1"),
+        )
+        .unwrap();
+    let c2 = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, c1, NOTHING, BitEnum::new(), "c2"),
+        )
+        .unwrap();
+
+    tx.define_property(
+        a,
+        a,
+        "from_a".into(),
+        NOTHING,
+        BitEnum::new(),
+        Some(v_str("a")),
+    )
+    .unwrap();
+    tx.define_property(o, o, "own".into(), o, BitEnum::new(), Some(v_str("o")))
+        .unwrap();
+    tx.define_property(
+        c1,
+        c1,
+        "c1_own".into(),
+        c1,
+        BitEnum::new(),
+        Some(v_str("c1")),
+    )
+    .unwrap();
+
+    let d = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, NOTHING, NOTHING, BitEnum::new(), "d"),
+        )
+        .unwrap();
+    tx.define_property(d, d, "from_d".into(), d, BitEnum::new(), Some(v_str("d")))
+        .unwrap();
+
+    tx.set_object_parent(o, d).unwrap();
+
+    for obj in [o, c1, c2] {
+        // Everyone gains d's property, clear...
+        let (prop, v, perms, is_clear) = tx.resolve_property(obj, "from_d".into()).unwrap();
+        assert_eq!(prop.definer(), d);
+        assert_eq!(v, v_str("d"));
+        assert_eq!(perms.owner(), d);
+        assert!(is_clear);
+
+        // ... loses a's ...
+        assert_eq!(
+            tx.resolve_property(obj, "from_a".into()).err().unwrap(),
+            WorldStateError::PropertyNotFound(obj, "from_a".into())
+        );
+
+        // ... and still has o's.
+        let (prop, v, perms, is_clear) = tx.resolve_property(obj, "own".into()).unwrap();
+        assert_eq!(prop.definer(), o);
+        assert_eq!(v, v_str("o"));
+        assert_eq!(perms.owner(), o);
+        assert_eq!(is_clear, obj != o);
+    }
+
+    // And the descendants keep their own properties too.
+    for obj in [c1, c2] {
+        let (prop, v, _, is_clear) = tx.resolve_property(obj, "c1_own".into()).unwrap();
+        assert_eq!(prop.definer(), c1);
+        assert_eq!(v, v_str("c1"));
+        assert_eq!(is_clear, obj != c1);
+    }
+
+    assert_eq!(tx.commit(), Ok(CommitResult::Success));
+}
+
 pub fn perform_test_recycle_object<F, TX>(begin_tx: F)
 where
     F: Fn() -> RelationalWorldStateTransaction<TX>,
@@ -1071,3 +1501,216 @@ where
         WorldStateError::PropertyNotFound(d, "test2".into())
     );
 }
+
+pub fn perform_test_verify_integrity<F, TX>(begin_tx: F)
+where
+    F: Fn() -> RelationalWorldStateTransaction<TX>,
+    TX: RelationalTransaction<WorldStateTable>,
+{
+    let tx = begin_tx();
+    let a = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, NOTHING, NOTHING, BitEnum::new(), "a"),
+        )
+        .unwrap();
+    let b = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, a, NOTHING, BitEnum::new(), "b"),
+        )
+        .unwrap();
+    let c = tx
+        .create_object(None, ObjAttrs::new(NOTHING, a, b, BitEnum::new(), "c"))
+        .unwrap();
+    let d = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, a, NOTHING, BitEnum::new(), "d"),
+        )
+        .unwrap();
+    let uuid = tx
+        .define_property(
+            a,
+            a,
+            "test".into(),
+            NOTHING,
+            BitEnum::new(),
+            Some(v_str("test_value")),
+        )
+        .unwrap();
+
+    // A consistent database has nothing to report.
+    assert_eq!(tx.verify_integrity().unwrap(), vec![]);
+
+    // Point c's parent and location at an object which doesn't exist, drop d's owner (as a
+    // half-done recycle would) so it no longer exists but is still a's child, and drop the value
+    // slot b inherited from a.
+    let missing = Objid(1000);
+    tx.tx
+        .as_ref()
+        .unwrap()
+        .upsert(WorldStateTable::ObjectParent, c, missing)
+        .unwrap();
+    tx.tx
+        .as_ref()
+        .unwrap()
+        .upsert(WorldStateTable::ObjectLocation, c, missing)
+        .unwrap();
+    tx.tx
+        .as_ref()
+        .unwrap()
+        .remove_by_domain(WorldStateTable::ObjectOwner, d)
+        .unwrap();
+    tx.tx
+        .as_ref()
+        .unwrap()
+        .remove_by_composite_domain(
+            WorldStateTable::ObjectPropertyPermissions,
+            b,
+            UUIDHolder(uuid),
+        )
+        .unwrap();
+
+    let findings = tx.verify_integrity().unwrap();
+    assert_eq!(findings.len(), 4);
+    assert!(findings
+        .iter()
+        .any(|f| f.kind == IntegrityProblem::BadParent && f.obj == c));
+    assert!(findings
+        .iter()
+        .any(|f| f.kind == IntegrityProblem::BadLocation && f.obj == c));
+    assert!(findings
+        .iter()
+        .any(|f| f.kind == IntegrityProblem::StaleChild && f.obj == a));
+    assert!(findings
+        .iter()
+        .any(|f| f.kind == IntegrityProblem::MissingPropval && f.obj == b));
+}
+
+pub fn perform_test_owner_bytes<F, TX>(begin_tx: F)
+where
+    F: Fn() -> RelationalWorldStateTransaction<TX>,
+    TX: RelationalTransaction<WorldStateTable>,
+{
+    let tx = begin_tx();
+    let owner = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, NOTHING, NOTHING, BitEnum::new(), "owner"),
+        )
+        .unwrap();
+    let a = tx
+        .create_object(
+            None,
+            ObjAttrs::new(owner, NOTHING, NOTHING, BitEnum::new(), "a"),
+        )
+        .unwrap();
+
+    // Everything the owner owns, measured from scratch.
+    let measured = |tx: &RelationalWorldStateTransaction<TX>| {
+        let mut used = 0;
+        for o in tx.get_objects().unwrap().iter() {
+            if tx.get_object_owner(o).unwrap() == owner {
+                used += tx.get_object_size_bytes(o).unwrap().total();
+            }
+        }
+        used
+    };
+    assert_eq!(tx.get_owner_bytes(owner).unwrap(), measured(&tx));
+
+    // It keeps up as things change.
+    let b = tx
+        .create_object(None, ObjAttrs::new(owner, a, NOTHING, BitEnum::new(), "b"))
+        .unwrap();
+    let uuid = tx
+        .define_property(
+            a,
+            a,
+            "test".into(),
+            owner,
+            BitEnum::new(),
+            Some(v_str("value")),
+        )
+        .unwrap();
+    tx.set_property(a, uuid, v_str("a rather longer value"))
+        .unwrap();
+    tx.add_object_verb(
+        a,
+        owner,
+        vec!["test".into()],
+        vec![1, 2, 3],
+        BinaryType::LambdaMoo18X,
+        BitEnum::new(),
+        VerbArgsSpec::this_none_this(),
+    )
+    .unwrap();
+    tx.set_object_name(b, "a rather longer name".into())
+        .unwrap();
+    tx.set_object_location(b, a).unwrap();
+    assert_eq!(tx.get_owner_bytes(owner).unwrap(), measured(&tx));
+
+    // Giving an object away, or recycling it, takes it off the count.
+    tx.set_object_owner(a, NOTHING).unwrap();
+    assert_eq!(tx.get_owner_bytes(owner).unwrap(), measured(&tx));
+    tx.recycle_object(b).unwrap();
+    assert_eq!(tx.get_owner_bytes(owner).unwrap(), measured(&tx));
+
+    // Which leaves just the owner, who owns themselves.
+    assert_eq!(
+        tx.get_owner_bytes(owner).unwrap(),
+        tx.get_object_size_bytes(owner).unwrap().total()
+    );
+    let c = tx
+        .create_object(
+            None,
+            ObjAttrs::new(owner, NOTHING, NOTHING, BitEnum::new(), "c"),
+        )
+        .unwrap();
+    assert_eq!(tx.commit(), Ok(CommitResult::Success));
+
+    // No running total is kept, so concurrent changes to different objects of one owner don't
+    // collide over one.
+    let tx1 = begin_tx();
+    let tx2 = begin_tx();
+    tx1.set_object_name(owner, "renamed owner".into()).unwrap();
+    tx2.set_object_name(c, "renamed c".into()).unwrap();
+    assert_eq!(tx1.commit(), Ok(CommitResult::Success));
+    assert_eq!(tx2.commit(), Ok(CommitResult::Success));
+}
+
+/// Changes made after a savepoint are undone by rolling back to it, while those made before it are
+/// kept, through to commit.
+pub fn perform_test_savepoint_rollback_to<F, TX>(begin_tx: F)
+where
+    F: Fn() -> RelationalWorldStateTransaction<TX>,
+    TX: RelationalTransaction<WorldStateTable>,
+{
+    let mut tx = begin_tx();
+    let a = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, NOTHING, NOTHING, BitEnum::new(), "a"),
+        )
+        .unwrap();
+
+    let savepoint = tx.tx.as_ref().unwrap().savepoint();
+    tx.set_object_name(a, "renamed".to_string()).unwrap();
+    let b = tx
+        .create_object(
+            None,
+            ObjAttrs::new(NOTHING, a, NOTHING, BitEnum::new(), "b"),
+        )
+        .unwrap();
+    assert_eq!(tx.get_object_children(a).unwrap(), ObjSet::from_items(&[b]));
+    tx.tx.as_ref().unwrap().rollback_to(savepoint).unwrap();
+
+    assert_eq!(tx.get_object_name(a).unwrap(), "a");
+    assert!(!tx.object_valid(b).unwrap());
+    assert_eq!(tx.get_object_children(a).unwrap(), ObjSet::empty());
+
+    assert_eq!(tx.commit().unwrap(), CommitResult::Success);
+    let tx = begin_tx();
+    assert_eq!(tx.get_object_name(a).unwrap(), "a");
+    assert!(!tx.object_valid(b).unwrap());
+}
@@ -12,14 +12,16 @@
 // this program. If not, see <https://www.gnu.org/licenses/>.
 //
 
+use std::collections::HashMap;
+
 use bytes::Bytes;
 use uuid::Uuid;
 
 use moor_values::model::PropFlag;
 use moor_values::model::VerbArgsSpec;
 use moor_values::model::{BinaryType, VerbAttrs, VerbFlag};
-use moor_values::model::{CommitResult, WorldStateError};
-use moor_values::model::{ObjAttrs, ObjFlag};
+use moor_values::model::{CommitResult, IntegrityFinding, WorldStateError};
+use moor_values::model::{ObjAttrs, ObjFlag, ObjectBytes};
 use moor_values::model::{ObjSet, PropPerms};
 use moor_values::model::{PropDef, PropDefs};
 use moor_values::model::{VerbDef, VerbDefs};
@@ -68,6 +70,11 @@ pub trait WorldStateTransaction {
     /// the property inheritance and ownership rules around the object.
     fn create_object(&self, id: Option<Objid>, attrs: ObjAttrs) -> Result<Objid, WorldStateError>;
 
+    /// Create a batch of objects with already-known ids (e.g. from a textdump), settling owners'
+    /// byte counts and the maximum object id once for the batch rather than per object.
+    /// None of the ids may already be in use; that's `ObjectAlreadyExists`, not an overwrite.
+    fn create_objects(&self, objects: &[(Objid, ObjAttrs)]) -> Result<(), WorldStateError>;
+
     /// Destroy the given object, and restructure the property inheritance accordingly.
     fn recycle_object(&self, obj: Objid) -> Result<(), WorldStateError>;
     /// Get the parent of the given object.
@@ -87,7 +94,7 @@ pub trait WorldStateTransaction {
     fn get_object_contents(&self, obj: Objid) -> Result<ObjSet, WorldStateError>;
 
     /// Get the stored size of the given object & all its properties, verbs, etc.
-    fn get_object_size_bytes(&self, obj: Objid) -> Result<usize, WorldStateError>;
+    fn get_object_size_bytes(&self, obj: Objid) -> Result<ObjectBytes, WorldStateError>;
 
     /// Set the location of the given object.
     fn set_object_location(&self, obj: Objid, location: Objid) -> Result<(), WorldStateError>;
@@ -197,9 +204,27 @@ pub trait WorldStateTransaction {
         name: String,
     ) -> Result<(PropDef, Var, PropPerms, bool), WorldStateError>;
 
+    /// Resolve several property names on the given object at once, walking the chain of parents
+    /// only once for all of them.
+    /// Returns the same as `resolve_property` for each name which was found; names which aren't
+    /// defined anywhere on the chain are absent from the result.
+    fn resolve_properties(
+        &self,
+        obj: Objid,
+        names: &[&str],
+    ) -> Result<HashMap<String, (PropDef, Var, PropPerms, bool)>, WorldStateError>;
+
+    /// The bytes taken up by everything `owner` owns, as `get_object_size_bytes` measures them.
+    /// Measured when asked for, so nothing has to keep a running total up to date.
+    fn get_owner_bytes(&self, owner: Objid) -> Result<usize, WorldStateError>;
+
     /// Return the (rough) size of the database in bytes.
     fn db_usage(&self) -> Result<usize, WorldStateError>;
 
+    /// Check every object's relationships and property slots for consistency, returning whatever
+    /// doesn't add up.
+    fn verify_integrity(&self) -> Result<Vec<IntegrityFinding>, WorldStateError>;
+
     /// Attempt to commit the transaction, returning the result of the commit.
     fn commit(&mut self) -> Result<CommitResult, WorldStateError>;
 
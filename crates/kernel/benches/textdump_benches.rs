@@ -0,0 +1,122 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Benchmarks of loading a database from scratch: whole textdumps (the small Test.db, and the
+//! much bigger JHCore-DEV-2.db), and the object creation at the front of a load, one object at a
+//! time against all in one batch.
+//!
+//! To compare the import path across a change, run with `--save-baseline before` on the old tree,
+//! then `--baseline before` on the new one.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use moor_db::Database;
+use moor_db_wiredtiger::WiredTigerDB;
+use moor_kernel::textdump::{textdump_load, ImportMode};
+use moor_moot::test_db_path;
+use moor_values::model::{CommitResult, ObjAttrs};
+use moor_values::util::BitEnum;
+use moor_values::var::Objid;
+use moor_values::NOTHING;
+
+const NUM_OBJECTS: i64 = 2000;
+
+fn fresh_db() -> Arc<dyn Database + Send + Sync> {
+    let (db, _) = WiredTigerDB::open(None);
+    Arc::new(db)
+}
+
+fn big_core_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../JHCore-DEV-2.db")
+}
+
+fn do_load(iters: u64, path: &Path) -> Duration {
+    let mut cumulative = Duration::new(0, 0);
+    for _ in 0..iters {
+        let db = fresh_db();
+        let start = Instant::now();
+        let mut tx = db.loader_client().unwrap();
+        textdump_load(tx.as_ref(), path.to_path_buf(), ImportMode::Preserve)
+            .expect("Could not load textdump");
+        assert_eq!(tx.commit().unwrap(), CommitResult::Success);
+        cumulative += start.elapsed();
+    }
+    cumulative
+}
+
+fn objects() -> Vec<(Objid, ObjAttrs)> {
+    (0..NUM_OBJECTS)
+        .map(|i| {
+            let attrs = ObjAttrs::new(NOTHING, NOTHING, NOTHING, BitEnum::new(), "object");
+            (Objid(i), attrs)
+        })
+        .collect()
+}
+
+fn do_create(iters: u64, batched: bool) -> Duration {
+    let objects = objects();
+    let mut cumulative = Duration::new(0, 0);
+    for _ in 0..iters {
+        let db = fresh_db();
+        let start = Instant::now();
+        let mut tx = db.loader_client().unwrap();
+        if batched {
+            tx.create_objects(&objects).unwrap();
+        } else {
+            for (id, attrs) in &objects {
+                tx.create_object(Some(*id), attrs).unwrap();
+            }
+        }
+        assert_eq!(tx.commit().unwrap(), CommitResult::Success);
+        cumulative += start.elapsed();
+    }
+    cumulative
+}
+
+fn textdump_load_time(c: &mut Criterion) {
+    let mut group = c.benchmark_group("textdump_load");
+    group.sample_size(20);
+    group.measurement_time(Duration::from_secs(20));
+    group.bench_function("test_db", |b| {
+        b.iter_custom(|iters| do_load(iters, &test_db_path()))
+    });
+    group.finish();
+
+    // Big enough that the per-object cost of the import is what's being measured.
+    let mut group = c.benchmark_group("textdump_load_big");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(120));
+    group.bench_function("jhcore_dev_2", |b| {
+        b.iter_custom(|iters| do_load(iters, &big_core_path()))
+    });
+    group.finish();
+}
+
+fn object_creation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("object_creation");
+    group.sample_size(20);
+    group.throughput(criterion::Throughput::Elements(NUM_OBJECTS as u64));
+    group.bench_function("one_at_a_time", |b| {
+        b.iter_custom(|iters| do_create(iters, false))
+    });
+    group.bench_function("batched", |b| b.iter_custom(|iters| do_create(iters, true)));
+    group.finish();
+}
+
+criterion_group!(benches, textdump_load_time, object_creation);
+criterion_main!(benches);
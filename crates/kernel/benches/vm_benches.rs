@@ -135,9 +135,12 @@ fn execute(world_state: &mut dyn WorldState, vm_host: &mut VmHost) -> bool {
             VMHostResponse::Suspend(_) => {
                 panic!("Unexpected suspend");
             }
-            VMHostResponse::SuspendNeedInput => {
+            VMHostResponse::SuspendNeedInput(_) => {
                 panic!("Unexpected suspend need input");
             }
+            VMHostResponse::DebugPause(_) => {
+                panic!("Unexpected debug pause");
+            }
             VMHostResponse::CompleteAbort => {
                 panic!("Unexpected abort");
             }
@@ -216,6 +219,21 @@ fn opcode_throughput(c: &mut Criterion) {
             )
         });
     });
+    // Appending to a list held in a variable, which happens in place
+    group.bench_function("list_append_50k", |b| {
+        b.iter_custom(|iters| {
+            do_program(
+                r#"while(1)
+                            base_list = {};
+                            for i in [1..50000]
+                                base_list = {@base_list, i};
+                            endfor
+                          endwhile"#,
+                num_ticks,
+                iters,
+            )
+        });
+    });
     // Measure how costly it is to append to a list
     group.bench_function("list_set", |b| {
         b.iter_custom(|iters| {
@@ -12,20 +12,21 @@
 // this program. If not, see <https://www.gnu.org/licenses/>.
 //
 
+use std::cmp::Ordering;
 use std::ops::BitOr;
 use std::sync::Arc;
 
 use onig::{Region, SearchOptions, SyntaxOperator};
 
 use moor_compiler::offset_for_builtin;
-use moor_values::var::Error::{E_ARGS, E_INVARG, E_TYPE};
-use moor_values::var::Variant;
+use moor_values::var::Error::{E_ARGS, E_INVARG, E_RANGE, E_TYPE};
 use moor_values::var::{v_empty_list, v_int, v_list, v_string};
-use moor_values::var::{v_listv, Error};
+use moor_values::var::{v_listv, Error, Var};
+use moor_values::var::{Str, StringSemantics, Variant};
 
 use crate::bf_declare;
 use crate::builtins::BfRet::Ret;
-use crate::builtins::{BfCallState, BfErr, BfRet, BuiltinFunction};
+use crate::builtins::{world_state_bf_err, BfCallState, BfErr, BfRet, BuiltinFunction};
 use crate::vm::vm_execute::one_to_zero_index;
 use crate::vm::VM;
 
@@ -130,15 +131,21 @@ fn bf_listset(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 bf_declare!(listset, bf_listset);
 
 fn bf_setadd(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
-    if bf_args.args.len() != 2 {
+    if bf_args.args.len() < 2 || bf_args.args.len() > 3 {
         return Err(BfErr::Code(E_ARGS));
     }
+    let case_matters = bf_args.args.get(2).map(|a| a.is_true()).unwrap_or(false);
     let value = bf_args.args[1].clone();
     let list = &mut bf_args.args[0];
     let Variant::List(ref mut list) = list.variant_mut() else {
         return Err(BfErr::Code(E_TYPE));
     };
-    if !list.contains(&value) {
+    let present = if case_matters {
+        list.contains_case_sensitive(&value)
+    } else {
+        list.contains(&value)
+    };
+    if !present {
         return Ok(Ret(list.push(value.clone())));
     }
     Ok(Ret(bf_args.args[0].clone()))
@@ -146,18 +153,196 @@ fn bf_setadd(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 bf_declare!(setadd, bf_setadd);
 
 fn bf_setremove(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
-    if bf_args.args.len() != 2 {
+    if bf_args.args.len() < 2 || bf_args.args.len() > 3 {
         return Err(BfErr::Code(E_ARGS));
     }
+    let case_matters = bf_args.args.get(2).map(|a| a.is_true()).unwrap_or(false);
     let value = bf_args.args[1].clone();
     let list = bf_args.args[0].variant_mut();
     let Variant::List(ref mut list) = list else {
         return Err(BfErr::Code(E_TYPE));
     };
+    if case_matters {
+        return Ok(Ret(list.setremove_case_sensitive(&value)));
+    }
     Ok(Ret(list.setremove(&value)))
 }
 bf_declare!(setremove, bf_setremove);
 
+fn bf_first(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 1 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let Variant::List(list) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    list.get(0).map(Ret).ok_or(BfErr::Code(E_RANGE))
+}
+bf_declare!(first, bf_first);
+
+fn bf_last(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 1 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let Variant::List(list) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    if list.is_empty() {
+        return Err(BfErr::Code(E_RANGE));
+    }
+    list.get(list.len() - 1)
+        .map(Ret)
+        .ok_or(BfErr::Code(E_RANGE))
+}
+bf_declare!(last, bf_last);
+
+fn bf_nth(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 2 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let Variant::List(list) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    let Variant::Int(index) = bf_args.args[1].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    if *index < 1 {
+        return Err(BfErr::Code(E_RANGE));
+    }
+    list.get((*index - 1) as usize)
+        .map(Ret)
+        .ok_or(BfErr::Code(E_RANGE))
+}
+bf_declare!(nth, bf_nth);
+
+/// The total ordering `sort()` uses across values of mixed types. Numbers come first, with ints
+/// and floats compared by value (an int sorts before an equal float), then objects by number,
+/// strings case-insensitively, errors, lists element by element, and finally `none`.
+fn sort_order(a: &Var, b: &Var) -> Ordering {
+    fn rank(v: &Variant) -> u8 {
+        match v {
+            Variant::Int(_) | Variant::Float(_) => 0,
+            Variant::Obj(_) => 1,
+            Variant::Str(_) => 2,
+            Variant::Err(_) => 3,
+            Variant::List(_) => 4,
+            Variant::None => 5,
+        }
+    }
+    match (a.variant(), b.variant()) {
+        (Variant::Int(l), Variant::Int(r)) => l.cmp(r),
+        (Variant::Float(l), Variant::Float(r)) => l.total_cmp(r),
+        (Variant::Int(l), Variant::Float(r)) => (*l as f64).total_cmp(r).then(Ordering::Less),
+        (Variant::Float(l), Variant::Int(r)) => l.total_cmp(&(*r as f64)).then(Ordering::Greater),
+        (Variant::Obj(l), Variant::Obj(r)) => l.0.cmp(&r.0),
+        (Variant::Str(l), Variant::Str(r)) => {
+            let l = l.as_str().chars().flat_map(char::to_lowercase);
+            let r = r.as_str().chars().flat_map(char::to_lowercase);
+            l.cmp(r)
+        }
+        (Variant::Err(l), Variant::Err(r)) => l.cmp(r),
+        (Variant::List(l), Variant::List(r)) => {
+            for (x, y) in l.iter().zip(r.iter()) {
+                match sort_order(&x, &y) {
+                    Ordering::Equal => continue,
+                    o => return o,
+                }
+            }
+            l.len().cmp(&r.len())
+        }
+        (l, r) => rank(l).cmp(&rank(r)),
+    }
+}
+
+fn bf_sort(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.is_empty() || bf_args.args.len() > 3 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let Variant::List(list) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    let reverse = bf_args.args.get(2).map(|a| a.is_true()).unwrap_or(false);
+    let order = |a: &Var, b: &Var| {
+        if reverse {
+            sort_order(b, a)
+        } else {
+            sort_order(a, b)
+        }
+    };
+
+    // An empty keys list means "sort by the values themselves", so that `reverse` can be given
+    // without keys.
+    let keys = match bf_args.args.get(1).map(|k| k.variant()) {
+        None => None,
+        Some(Variant::List(keys)) if keys.is_empty() => None,
+        Some(Variant::List(keys)) => Some(keys),
+        Some(_) => return Err(BfErr::Code(E_TYPE)),
+    };
+
+    let sorted = match keys {
+        None => {
+            let mut items: Vec<Var> = list.iter().collect();
+            items.sort_by(order);
+            items
+        }
+        Some(keys) => {
+            if keys.len() != list.len() {
+                return Err(BfErr::Code(E_INVARG));
+            }
+            let mut pairs: Vec<(Var, Var)> = keys.iter().zip(list.iter()).collect();
+            pairs.sort_by(|(a, _), (b, _)| order(a, b));
+            pairs.into_iter().map(|(_, v)| v).collect()
+        }
+    };
+    Ok(Ret(v_listv(sorted)))
+}
+bf_declare!(sort, bf_sort);
+
+/// `slice(list, index)` picks the index'th element out of each sublist of `list`, and
+/// `slice(list, propname)` reads the named property from each object in it, with the task's
+/// permissions.
+fn bf_slice(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 2 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let Variant::List(list) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    let mut result = Vec::with_capacity(list.len());
+    match bf_args.args[1].variant() {
+        Variant::Int(index) => {
+            if *index < 1 {
+                return Err(BfErr::Code(E_RANGE));
+            }
+            for item in list.iter() {
+                let Variant::List(sublist) = item.variant() else {
+                    return Err(BfErr::Code(E_TYPE));
+                };
+                let Some(v) = sublist.get((*index - 1) as usize) else {
+                    return Err(BfErr::Code(E_RANGE));
+                };
+                result.push(v);
+            }
+        }
+        Variant::Str(propname) => {
+            let perms = bf_args.task_perms_who();
+            for item in list.iter() {
+                let Variant::Obj(obj) = item.variant() else {
+                    return Err(BfErr::Code(E_TYPE));
+                };
+                let v = bf_args
+                    .world_state
+                    .retrieve_property(perms, *obj, propname.as_str())
+                    .map_err(world_state_bf_err)?;
+                result.push(v);
+            }
+        }
+        _ => return Err(BfErr::Code(E_TYPE)),
+    }
+    Ok(Ret(v_listv(result)))
+}
+bf_declare!(slice, bf_slice);
+
 /// Translate a MOO pattern into a more standard syntax.  Effectively, this
 /// just involves remove `%' escapes into `\' escapes.
 fn translate_pattern(pattern: &str) -> Option<String> {
@@ -202,7 +387,10 @@ fn translate_pattern(pattern: &str) -> Option<String> {
 type Span = (isize, isize);
 type MatchSpans = (Span, Vec<Span>);
 
+/// Match `pattern` against `subject`, returning the 1-based, inclusive spans of the overall match
+/// and its captures, counted as `semantics` says.
 fn perform_regex_match(
+    semantics: StringSemantics,
     pattern: &str,
     subject: &str,
     case_matters: bool,
@@ -249,14 +437,21 @@ fn perform_regex_match(
         return Ok(None);
     };
 
-    let overall = ((start + 1) as isize, end as isize);
+    // Onig gives us byte offsets, but MOO code indexes strings by character.
+    let span = |(start, end): (usize, usize)| {
+        (
+            (semantics.offset_of(subject, start) + 1) as isize,
+            semantics.offset_of(subject, end) as isize,
+        )
+    };
+    let overall = span((start, end));
     // Now we'll iterate through the captures, and build up a Vec<Span> of the captured groups.
     // MOO match() returns 9 subpatterns, no more, no less. So we start with a Vec of 9
     // (-1, -1) pairs and then fill that in with the captured groups, if any.
     let mut match_vec = vec![(0, -1); 9];
     for i in 1..=8 {
-        if let Some((start, end)) = region.pos(i) {
-            match_vec[i - 1] = ((start + 1) as isize, end as isize);
+        if let Some(pos) = region.pos(i) {
+            match_vec[i - 1] = span(pos);
         }
     }
 
@@ -283,9 +478,14 @@ fn do_re_match(bf_args: &mut BfCallState<'_>, reverse: bool) -> Result<BfRet, Bf
     };
 
     // TODO: Regex pattern cache?
-    let Some((overall, match_vec)) =
-        perform_regex_match(pattern.as_str(), subject.as_str(), case_matters, reverse)
-            .map_err(BfErr::Code)?
+    let Some((overall, match_vec)) = perform_regex_match(
+        bf_args.exec_state.config.string_semantics(),
+        pattern.as_str(),
+        subject.as_str(),
+        case_matters,
+        reverse,
+    )
+    .map_err(BfErr::Code)?
     else {
         return Ok(Ret(v_empty_list()));
     };
@@ -313,7 +513,12 @@ fn bf_rmatch(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(rmatch, bf_rmatch);
 
-fn substitute(template: &str, subs: &[(isize, isize)], source: &str) -> Result<String, Error> {
+fn substitute(
+    semantics: StringSemantics,
+    template: &str,
+    subs: &[(isize, isize)],
+    source: &Str,
+) -> Result<String, Error> {
     // textual patterns of form %<int> (e.g. %1, %9, %11) are replaced by the text matched by the
     // offsets (1-indexed) into source given by the corresponding value in `subs`.
 
@@ -363,13 +568,15 @@ fn substitute(template: &str, subs: &[(isize, isize)], source: &str) -> Result<S
         let (start, end) = (subs[number].0, subs[number].1);
 
         // Now validate the range in the source string, and raise an E_INVARG if it's invalid.
-        if start < 0 || start > end || end > (source.len() as isize) {
+        if start < 1 || start > end {
             return Err(E_INVARG);
         }
+        let Some(range) = source.byte_range(semantics, start as usize - 1..end as usize) else {
+            return Err(E_INVARG);
+        };
 
-        let (start, end) = (start as usize - 1, end as usize);
         // Now append the corresponding substring to `result`.
-        result.push_str(&source[start..end]);
+        result.push_str(&source.as_str()[range]);
         if let Some(last_c) = last_c {
             result.push(last_c);
         }
@@ -417,7 +624,12 @@ fn bf_substitute(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
         mysubs.push((*start as isize, *end as isize));
     }
 
-    match substitute(template.as_str(), &mysubs, source.as_str()) {
+    match substitute(
+        bf_args.exec_state.config.string_semantics(),
+        template.as_str(),
+        &mysubs,
+        source,
+    ) {
         Ok(r) => Ok(Ret(v_string(r))),
         Err(e) => Err(BfErr::Code(e)),
     }
@@ -433,6 +645,11 @@ impl VM {
         self.builtins[offset_for_builtin("listset")] = Arc::new(BfListset {});
         self.builtins[offset_for_builtin("setadd")] = Arc::new(BfSetadd {});
         self.builtins[offset_for_builtin("setremove")] = Arc::new(BfSetremove {});
+        self.builtins[offset_for_builtin("first")] = Arc::new(BfFirst {});
+        self.builtins[offset_for_builtin("last")] = Arc::new(BfLast {});
+        self.builtins[offset_for_builtin("nth")] = Arc::new(BfNth {});
+        self.builtins[offset_for_builtin("sort")] = Arc::new(BfSort {});
+        self.builtins[offset_for_builtin("slice")] = Arc::new(BfSlice {});
         self.builtins[offset_for_builtin("match")] = Arc::new(BfMatch {});
         self.builtins[offset_for_builtin("rmatch")] = Arc::new(BfRmatch {});
         self.builtins[offset_for_builtin("substitute")] = Arc::new(BfSubstitute {});
@@ -441,14 +658,43 @@ impl VM {
 
 #[cfg(test)]
 mod tests {
-    use crate::builtins::bf_list_sets::{perform_regex_match, substitute};
+    use std::cmp::Ordering;
+
+    use moor_values::var::StringSemantics::{Bytes, CodePoints};
+    use moor_values::var::{v_float, v_int, v_list, v_objid, v_str, Objid, Str};
+
+    use crate::builtins::bf_list_sets::{perform_regex_match, sort_order, substitute};
+
+    #[test]
+    fn test_sort_order_mixed_types() {
+        assert_eq!(sort_order(&v_int(1), &v_float(1.5)), Ordering::Less);
+        assert_eq!(sort_order(&v_float(2.5), &v_int(2)), Ordering::Greater);
+        // Equal ints and floats are still ordered, int first.
+        assert_eq!(sort_order(&v_int(1), &v_float(1.0)), Ordering::Less);
+        assert_eq!(sort_order(&v_float(1.0), &v_int(1)), Ordering::Greater);
+        assert_eq!(
+            sort_order(&v_objid(Objid(100)), &v_str("a")),
+            Ordering::Less
+        );
+        assert_eq!(
+            sort_order(&v_str("a"), &v_objid(Objid(100))),
+            Ordering::Greater
+        );
+        assert_eq!(sort_order(&v_str("B"), &v_str("a")), Ordering::Greater);
+        assert_eq!(sort_order(&v_str("abc"), &v_str("ABC")), Ordering::Equal);
+        assert_eq!(
+            sort_order(&v_list(&[v_int(1)]), &v_list(&[v_int(1), v_int(0)])),
+            Ordering::Less
+        );
+    }
 
     #[test]
     fn test_match_substitute() {
         let source = "*** Welcome to LambdaMOO!!!";
-        let (overall, subs) = perform_regex_match("%(%w*%) to %(%w*%)", source, false, false)
-            .unwrap()
-            .unwrap();
+        let (overall, subs) =
+            perform_regex_match(CodePoints, "%(%w*%) to %(%w*%)", source, false, false)
+                .unwrap()
+                .unwrap();
         assert_eq!(overall, (5, 24));
         assert_eq!(
             subs,
@@ -464,17 +710,30 @@ mod tests {
                 (0, -1)
             ]
         );
-        let result = substitute("I thank you for your %1 here in %2.", &subs, source).unwrap();
+        let result = substitute(
+            CodePoints,
+            "I thank you for your %1 here in %2.",
+            &subs,
+            &Str::from_string(source.to_string()),
+        )
+        .unwrap();
         assert_eq!(result, "I thank you for your Welcome here in LambdaMOO.");
     }
 
     #[test]
     fn test_substitute_regression() {
         let source = "help @options";
-        let (_, subs) = perform_regex_match("^help %('%|[^ <][^ ]*%)$", source, false, false)
-            .unwrap()
-            .unwrap();
-        let result = substitute("%1", &subs, source).unwrap();
+        let (_, subs) =
+            perform_regex_match(CodePoints, "^help %('%|[^ <][^ ]*%)$", source, false, false)
+                .unwrap()
+                .unwrap();
+        let result = substitute(
+            CodePoints,
+            "%1",
+            &subs,
+            &Str::from_string(source.to_string()),
+        )
+        .unwrap();
         assert_eq!(result, "@options");
     }
 
@@ -482,6 +741,7 @@ mod tests {
     fn test_substitute_off_by_one() {
         let source = "@edit-o";
         let (overall, subs) = perform_regex_match(
+            CodePoints,
             "^@%([^-]*%)%(o%|opt?i?o?n?s?%|-o?p?t?i?o?n?s?%)$",
             source,
             false,
@@ -504,7 +764,13 @@ mod tests {
                 (0, -1),
             ]
         );
-        let result = substitute("%1", &subs, source).unwrap();
+        let result = substitute(
+            CodePoints,
+            "%1",
+            &subs,
+            &Str::from_string(source.to_string()),
+        )
+        .unwrap();
         assert_eq!(result, "edit");
     }
 
@@ -513,7 +779,7 @@ mod tests {
         let source = "2";
         // In MOO this should yield (1,1). In Python re it's (0,1).
         // 'twas returning None because + support got broken.
-        let (overall, _) = perform_regex_match("[0-9]+ *", source, false, false)
+        let (overall, _) = perform_regex_match(CodePoints, "[0-9]+ *", source, false, false)
             .unwrap()
             .unwrap();
         assert_eq!(overall, (1, 1));
@@ -521,7 +787,7 @@ mod tests {
 
     #[test]
     fn test_rmatch() {
-        let m = perform_regex_match("o*b", "foobar", false, true)
+        let m = perform_regex_match(CodePoints, "o*b", "foobar", false, true)
             .unwrap()
             .unwrap();
         // {4, 4, {{0, -1}
@@ -543,4 +809,28 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn test_match_substitute_multibyte() {
+        let source = "café au lait";
+        let (overall, subs) =
+            perform_regex_match(CodePoints, "%(au%) %(l[a-z]*%)", source, false, false)
+                .unwrap()
+                .unwrap();
+        assert_eq!(overall, (6, 12));
+        assert_eq!(&subs[..2], &[(6, 7), (9, 12)]);
+        let source_str = Str::from_string(source.to_string());
+        let result = substitute(CodePoints, "%2 %1", &subs, &source_str).unwrap();
+        assert_eq!(result, "lait au");
+
+        // Under byte semantics, the same match is reported (and substituted) in bytes.
+        let (overall, subs) =
+            perform_regex_match(Bytes, "%(au%) %(l[a-z]*%)", source, false, false)
+                .unwrap()
+                .unwrap();
+        assert_eq!(overall, (7, 13));
+        assert_eq!(&subs[..2], &[(7, 8), (10, 13)]);
+        let result = substitute(Bytes, "%2 %1", &subs, &source_str).unwrap();
+        assert_eq!(result, "lait au");
+    }
 }
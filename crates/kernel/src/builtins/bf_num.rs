@@ -15,16 +15,17 @@
 use std::sync::Arc;
 
 use decorum::R64;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 use moor_compiler::offset_for_builtin;
 use moor_values::var::Error::{E_ARGS, E_INVARG, E_TYPE};
 use moor_values::var::Variant;
-use moor_values::var::{v_float, v_int, v_str};
+use moor_values::var::{v_float, v_int, v_none, v_str};
 
 use crate::bf_declare;
 use crate::builtins::BfRet::Ret;
-use crate::builtins::{BfCallState, BfErr, BfRet, BuiltinFunction};
+use crate::builtins::{world_state_bf_err, BfCallState, BfErr, BfRet, BuiltinFunction};
 use crate::vm::VM;
 
 fn bf_abs(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
@@ -77,7 +78,7 @@ fn bf_random(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
         return Err(BfErr::Code(E_ARGS));
     }
 
-    let mut rng = rand::thread_rng();
+    let rng = &mut bf_args.exec_state.rng;
     match bf_args.args.first().map(|var| var.variant()) {
         Some(Variant::Int(i)) if *i > 0 => Ok(Ret(v_int(rng.gen_range(1..=*i)))),
         Some(Variant::Int(_)) => Err(BfErr::Code(E_INVARG)),
@@ -87,6 +88,30 @@ fn bf_random(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(random, bf_random);
 
+/*
+none set_random_seed (int seed)
+
+Wizard-only. Reseeds the current task's random() stream, so that the numbers it goes on to return
+are the same for every run with the same seed. Other tasks' streams (including tasks this one
+forks or resumes later) and random_bytes() are unaffected.
+*/
+fn bf_set_random_seed(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 1 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let Variant::Int(seed) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    bf_args
+        .task_perms()
+        .map_err(world_state_bf_err)?
+        .check_wizard()
+        .map_err(world_state_bf_err)?;
+    bf_args.exec_state.rng = StdRng::seed_from_u64(*seed as u64);
+    Ok(Ret(v_none()))
+}
+bf_declare!(set_random_seed, bf_set_random_seed);
+
 fn bf_floatstr(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     if bf_args.args.len() < 2 || bf_args.args.len() > 3 {
         return Err(BfErr::Code(E_ARGS));
@@ -368,6 +393,7 @@ bf_declare!(trunc, bf_trunc);
 impl VM {
     pub(crate) fn register_bf_num(&mut self) {
         self.builtins[offset_for_builtin("abs")] = Arc::new(BfAbs {});
+        self.builtins[offset_for_builtin("set_random_seed")] = Arc::new(BfSetRandomSeed {});
         self.builtins[offset_for_builtin("min")] = Arc::new(BfMin {});
         self.builtins[offset_for_builtin("max")] = Arc::new(BfMax {});
         self.builtins[offset_for_builtin("random")] = Arc::new(BfRandom {});
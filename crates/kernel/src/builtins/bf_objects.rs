@@ -19,12 +19,13 @@ use tracing::{debug, error, trace};
 use moor_compiler::offset_for_builtin;
 use moor_values::model::Named;
 use moor_values::model::WorldStateError;
-use moor_values::model::{ObjFlag, ValSet};
+use moor_values::model::{ObjFlag, PropFlag, ValSet};
+use moor_values::model::{BYTE_QUOTA_PROPERTY, OWNERSHIP_QUOTA_PROPERTY};
 use moor_values::util::BitEnum;
 use moor_values::var::v_listv;
 use moor_values::var::Error::{E_ARGS, E_INVARG, E_NACC, E_PERM, E_TYPE};
 use moor_values::var::{v_bool, v_int, v_none, v_objid, v_str};
-use moor_values::var::{List, Variant};
+use moor_values::var::{List, Objid, Var, Variant};
 use moor_values::NOTHING;
 
 use crate::bf_declare;
@@ -37,11 +38,20 @@ use crate::vm::VM;
 /*
 Function: int valid (obj object)
 Returns a non-zero integer (i.e., a true value) if object is a valid object (one that has been created and not yet recycled) and zero (i.e., a false value) otherwise.
+Given a list of objects instead, returns a list of the validity of each.
 */
 fn bf_valid(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     if bf_args.args.len() != 1 {
         return Err(BfErr::Code(E_ARGS));
     }
+    if let Variant::List(_) = bf_args.args[0].variant() {
+        let mut valids = vec![];
+        for obj in objects_of(&bf_args.args[0])? {
+            let is_valid = bf_args.world_state.valid(obj).map_err(world_state_bf_err)?;
+            valids.push(v_bool(is_valid));
+        }
+        return Ok(Ret(v_listv(valids)));
+    }
     let Variant::Obj(obj) = bf_args.args[0].variant() else {
         return Err(BfErr::Code(E_TYPE));
     };
@@ -71,6 +81,46 @@ fn bf_parent(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(parent, bf_parent);
 
+/*
+Function: int isa (obj object, obj ancestor)
+Returns true if ancestor is object itself, its parent, or any of its parent's ancestors.
+*/
+fn bf_isa(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 2 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let Variant::Obj(obj) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    let Variant::Obj(ancestor) = bf_args.args[1].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    if !bf_args
+        .world_state
+        .valid(*obj)
+        .map_err(world_state_bf_err)?
+    {
+        return Err(BfErr::Code(E_INVARG));
+    }
+    // Every chain ends at #-1, but nothing descends from it.
+    if *ancestor == NOTHING {
+        return Ok(Ret(v_bool(false)));
+    }
+    if !bf_args
+        .world_state
+        .valid(*ancestor)
+        .map_err(world_state_bf_err)?
+    {
+        return Err(BfErr::Code(E_INVARG));
+    }
+    let ancestors = bf_args
+        .world_state
+        .ancestors_of(bf_args.task_perms_who(), *obj)
+        .map_err(world_state_bf_err)?;
+    Ok(Ret(v_bool(ancestors.contains(*ancestor))))
+}
+bf_declare!(isa, bf_isa);
+
 fn bf_chparent(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     if bf_args.args.len() != 2 {
         return Err(BfErr::Code(E_ARGS));
@@ -106,6 +156,62 @@ fn bf_children(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(children, bf_children);
 
+/// The objects in `objs`, which must all be objects.
+fn objects_of(objs: &Var) -> Result<Vec<Objid>, BfErr> {
+    let Variant::List(objs) = objs.variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    objs.iter()
+        .map(|o| match o.variant() {
+            Variant::Obj(o) => Ok(*o),
+            _ => Err(BfErr::Code(E_TYPE)),
+        })
+        .collect()
+}
+
+/*
+Function: list parents (list objects)
+Returns a list of the parent of each of the given objects, as `parent()` would for each.
+*/
+fn bf_parents(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 1 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let mut parents = vec![];
+    for obj in objects_of(&bf_args.args[0])? {
+        if obj.0 < 0 {
+            return Err(BfErr::Code(E_INVARG));
+        }
+        let parent = bf_args
+            .world_state
+            .parent_of(bf_args.task_perms_who(), obj)
+            .map_err(world_state_bf_err)?;
+        parents.push(v_objid(parent));
+    }
+    Ok(Ret(v_listv(parents)))
+}
+bf_declare!(parents, bf_parents);
+
+/*
+Function: list children_of (list objects)
+Returns a list of the children of each of the given objects, as `children()` would for each.
+*/
+fn bf_children_of(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 1 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let mut children = vec![];
+    for obj in objects_of(&bf_args.args[0])? {
+        let obj_children = bf_args
+            .world_state
+            .children_of(bf_args.task_perms_who(), obj)
+            .map_err(world_state_bf_err)?;
+        children.push(v_listv(obj_children.iter().map(v_objid).collect()));
+    }
+    Ok(Ret(v_listv(children)))
+}
+bf_declare!(children_of, bf_children_of);
+
 /*
 Syntax:  create (obj <parent> [, obj <owner>])   => obj
  */
@@ -200,59 +306,38 @@ fn bf_recycle(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     };
 
     let valid = bf_args.world_state.valid(*obj);
-    if valid == Ok(false)
-        || valid
-            .err()
-            .map(|e| e.database_error_msg() == Some("NotFound"))
-            .unwrap_or_default()
-    {
-        return Err(BfErr::Code(E_INVARG));
-    }
+    if bf_args.exec_state.top().bf_trampoline.is_some() {
+        // Coming back from one of the verbs, which may have gone and recycled it already.
+        if !matches!(valid, Ok(true)) {
+            return Ok(Ret(v_int(0)));
+        }
+    } else {
+        if valid == Ok(false)
+            || valid
+                .err()
+                .map(|e| e.database_error_msg() == Some("NotFound"))
+                .unwrap_or_default()
+        {
+            return Err(BfErr::Code(E_INVARG));
+        }
 
-    // Check if the given task perms can control the object before continuing.
-    if !bf_args
-        .world_state
-        .controls(bf_args.task_perms_who(), *obj)
-        .map_err(world_state_bf_err)?
-    {
-        return Err(BfErr::Code(E_PERM));
+        // Check if the given task perms can control the object before continuing.
+        if !bf_args
+            .world_state
+            .controls(bf_args.task_perms_who(), *obj)
+            .map_err(world_state_bf_err)?
+        {
+            return Err(BfErr::Code(E_PERM));
+        }
     }
 
-    // Before actually recycling the object, we need to move all its contents to #-1. While
-    // `recycle_object` will actually do this, we need to make sure :exitfunc is called on each
-    // object, so we'll do it manually here.
-
+    // :recycle is called on the object first, then :exitfunc on each thing still in it, and
+    // only then is it actually destroyed (which moves whatever's left in it to #-1). An error
+    // raised by any of those verbs aborts the recycle before anything has been destroyed.
     'outer: loop {
         let tramp = bf_args.exec_state.top().bf_trampoline;
         match tramp {
             None => {
-                // Starting out, we need to call "recycle" on the object, if it exists.
-                // The next point in the trampoline is CALL_EXITFUNC and it will expect a list of
-                // objects to move/call :exitfunc on. So let's get the initial list of objects
-                // now
-                let object_contents = bf_args
-                    .world_state
-                    .contents_of(bf_args.task_perms_who(), *obj)
-                    .map_err(world_state_bf_err)?;
-                // Filter contents for objects that have an :exitfunc verb.
-                let mut contents = vec![];
-                for o in object_contents.iter() {
-                    match bf_args.world_state.find_method_verb_on(
-                        bf_args.task_perms_who(),
-                        o,
-                        "exitfunc",
-                    ) {
-                        Ok(_) => {
-                            contents.push(v_objid(o));
-                        }
-                        Err(WorldStateError::VerbNotFound(_, _)) => {}
-                        Err(e) => {
-                            error!("Error looking up exitfunc verb: {:?}", e);
-                            return Err(BfErr::Code(E_NACC));
-                        }
-                    }
-                }
-                let contents = v_listv(contents);
                 match bf_args.world_state.find_method_verb_on(
                     bf_args.task_perms_who(),
                     *obj,
@@ -272,7 +357,7 @@ fn bf_recycle(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
                                 caller: bf_args.exec_state.top().this,
                             },
                             trampoline: Some(BF_RECYCLE_TRAMPOLINE_CALL_EXITFUNC),
-                            trampoline_arg: Some(contents),
+                            trampoline_arg: None,
                             command: None,
                         }));
                     }
@@ -280,7 +365,7 @@ fn bf_recycle(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
                         // Short-circuit fake-tramp state change.
                         bf_args.exec_state.top_mut().bf_trampoline =
                             Some(BF_RECYCLE_TRAMPOLINE_CALL_EXITFUNC);
-                        bf_args.exec_state.top_mut().bf_trampoline_arg = Some(contents);
+                        bf_args.exec_state.top_mut().bf_trampoline_arg = None;
                         // Fall through to the next case.
                     }
                     Err(e) => {
@@ -290,38 +375,57 @@ fn bf_recycle(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
                 }
             }
             Some(BF_RECYCLE_TRAMPOLINE_CALL_EXITFUNC) => {
-                // Check the arguments, which must be a list of objects. IF it's empty, we can
-                // move onto DONE_MOVE, if not, take the head of the list, and call :exitfunc on it
-                // (if it exists), and then back to this state.
-                let contents = bf_args.exec_state.top().bf_trampoline_arg.clone().unwrap();
-                let Variant::List(contents) = contents.variant() else {
-                    panic!("Invalid trampoline argument for bf_recycle");
+                // The argument is the list of contents yet to get their :exitfunc call. The
+                // first time through there isn't one yet: it's only taken now, after :recycle,
+                // which may well have moved things out.
+                let mut contents = match bf_args.exec_state.top().bf_trampoline_arg.clone() {
+                    Some(contents) => contents,
+                    None => {
+                        let contents = bf_args
+                            .world_state
+                            .contents_of(bf_args.task_perms_who(), *obj)
+                            .map_err(world_state_bf_err)?;
+                        v_listv(contents.iter().map(v_objid).collect())
+                    }
                 };
-                'inner: loop {
-                    debug!(?obj, contents = ?contents, "Calling :exitfunc for objects contents");
-                    if contents.is_empty() {
+                loop {
+                    let Variant::List(remaining) = contents.variant() else {
+                        panic!("Invalid trampoline argument for bf_recycle");
+                    };
+                    debug!(?obj, contents = ?remaining, "Calling :exitfunc for objects contents");
+                    if remaining.is_empty() {
                         bf_args.exec_state.top_mut().bf_trampoline_arg = None;
                         bf_args.exec_state.top_mut().bf_trampoline =
                             Some(BF_RECYCLE_TRAMPOLINE_DONE_MOVE);
                         continue 'outer;
                     }
-                    let (head_obj, contents) = contents.pop_front();
+                    let (head_obj, rest) = remaining.pop_front();
+                    contents = rest;
                     let Variant::Obj(head_obj) = head_obj.variant() else {
                         panic!("Invalid trampoline argument for bf_recycle");
                     };
-                    // :exitfunc *should* exist because we looked for it earlier, and we're supposed to
-                    // be transactionally isolated. But we need to do resolution anyways, so we will
-                    // look again anyways.
-                    let Ok(exitfunc) = bf_args.world_state.find_method_verb_on(
+                    // An earlier :exitfunc may have moved it somewhere else, or recycled it.
+                    if bf_args
+                        .world_state
+                        .location_of(bf_args.task_perms_who(), *head_obj)
+                        .ok()
+                        != Some(*obj)
+                    {
+                        continue;
+                    }
+                    let exitfunc = match bf_args.world_state.find_method_verb_on(
                         bf_args.task_perms_who(),
                         *head_obj,
                         "exitfunc",
-                    ) else {
-                        // If there's no :exitfunc, we can just move on to the next object.
-                        bf_args.exec_state.top_mut().bf_trampoline_arg = Some(contents);
-                        continue 'inner;
+                    ) {
+                        Ok(exitfunc) => exitfunc,
+                        Err(WorldStateError::VerbNotFound(_, _)) => continue,
+                        Err(e) => {
+                            error!("Error looking up exitfunc verb: {:?}", e);
+                            return Err(BfErr::Code(E_NACC));
+                        }
                     };
-                    // Call :exitfunc on the head object.
+                    // Call :exitfunc on the head object, and come back here for the rest.
                     return Ok(VmInstr(ContinueVerb {
                         permissions: bf_args.task_perms_who(),
                         resolved_verb: exitfunc,
@@ -356,6 +460,34 @@ fn bf_recycle(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(recycle, bf_recycle);
 
+/*
+Function: none raw_recycle (obj object)
+Destroys object as recycle() does, but without calling its `recycle' verb or the `exitfunc' verbs of
+its contents; they're simply moved to #-1. For wizards cleaning up objects whose verbs are broken.
+*/
+fn bf_raw_recycle(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 1 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let Variant::Obj(obj) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    bf_args
+        .task_perms()
+        .map_err(world_state_bf_err)?
+        .check_wizard()
+        .map_err(world_state_bf_err)?;
+    if !matches!(bf_args.world_state.valid(*obj), Ok(true)) {
+        return Err(BfErr::Code(E_INVARG));
+    }
+    bf_args
+        .world_state
+        .recycle_object(bf_args.task_perms_who(), *obj)
+        .map_err(world_state_bf_err)?;
+    Ok(Ret(v_int(0)))
+}
+bf_declare!(raw_recycle, bf_raw_recycle);
+
 fn bf_max_object(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     if !bf_args.args.is_empty() {
         return Err(BfErr::Code(E_ARGS));
@@ -389,6 +521,16 @@ fn bf_move(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     //   If the destination is something that's inside me, that's also E_RECMOVE
     //   And so on...
 
+    // The server may be configured to skip the :accept / :exitfunc / :enterfunc protocol, and
+    // just relocate.
+    if bf_args.exec_state.config.raw_moves {
+        bf_args
+            .world_state
+            .move_object(bf_args.task_perms_who(), *what, *whereto)
+            .map_err(world_state_bf_err)?;
+        return Ok(Ret(v_none()));
+    }
+
     // 'Trampoline' state machine:
     //    None => look up :accept, if it exists, set tramp to 1, and ask for it to be invoked.
     //            if it doesn't & perms not wizard, set raise E_NACC (as if :accept returned false)
@@ -682,6 +824,130 @@ fn bf_players(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(players, bf_players);
 
+/// The kinds of quota `set_quota()` and `quota()` deal in, and the property on the owner each is
+/// kept in.
+const QUOTA_KINDS: [(&str, &str); 2] = [
+    ("objects", OWNERSHIP_QUOTA_PROPERTY),
+    ("bytes", BYTE_QUOTA_PROPERTY),
+];
+
+fn quota_property(kind: &Var) -> Result<&'static str, BfErr> {
+    let Variant::Str(kind) = kind.variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    QUOTA_KINDS
+        .iter()
+        .find(|(name, _)| kind.as_str() == *name)
+        .map(|(_, pname)| *pname)
+        .ok_or(BfErr::Code(E_INVARG))
+}
+
+/*
+Function: none set_quota (obj owner, str kind [, int value])
+Sets owner's quota of the given kind: "objects" is how many more objects they may create (kept in
+their `ownership_quota' property, as in LambdaMOO), and "bytes" is how many bytes all their objects
+together may take up (kept in `byte_quota'). The property is added to owner if it doesn't have it.
+Without a value, owner's own quota property is removed, lifting the limit; E_PROPNF is raised if
+owner has no such property of its own. Wizards only.
+*/
+fn bf_set_quota(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() < 2 || bf_args.args.len() > 3 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let Variant::Obj(owner) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    let pname = quota_property(&bf_args.args[1])?;
+    bf_args
+        .task_perms()
+        .map_err(world_state_bf_err)?
+        .check_wizard()
+        .map_err(world_state_bf_err)?;
+    if !bf_args
+        .world_state
+        .valid(*owner)
+        .map_err(world_state_bf_err)?
+    {
+        return Err(BfErr::Code(E_INVARG));
+    }
+
+    let perms = bf_args.task_perms_who();
+    let Some(value) = bf_args.args.get(2).cloned() else {
+        bf_args
+            .world_state
+            .delete_property(perms, *owner, pname)
+            .map_err(world_state_bf_err)?;
+        return Ok(Ret(v_none()));
+    };
+    let Variant::Int(_) = value.variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    match bf_args.world_state.retrieve_property(perms, *owner, pname) {
+        Ok(_) => bf_args
+            .world_state
+            .update_property(perms, *owner, pname, &value)
+            .map_err(world_state_bf_err)?,
+        // Owned by the wizard setting it, and readable, so owners can see but not change it.
+        Err(WorldStateError::PropertyNotFound(_, _)) => bf_args
+            .world_state
+            .define_property(
+                perms,
+                *owner,
+                *owner,
+                pname,
+                perms,
+                BitEnum::new_with(PropFlag::Read),
+                Some(value),
+            )
+            .map_err(world_state_bf_err)?,
+        Err(e) => return Err(world_state_bf_err(e)),
+    }
+    Ok(Ret(v_none()))
+}
+bf_declare!(set_quota, bf_set_quota);
+
+/*
+Function: list quota (obj owner)
+Returns owner's quotas, as a list of {kind, value} pairs (see set_quota()), leaving out any kind
+owner has no limit on. Wizards only.
+*/
+fn bf_quota(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 1 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let Variant::Obj(owner) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    bf_args
+        .task_perms()
+        .map_err(world_state_bf_err)?
+        .check_wizard()
+        .map_err(world_state_bf_err)?;
+    if !bf_args
+        .world_state
+        .valid(*owner)
+        .map_err(world_state_bf_err)?
+    {
+        return Err(BfErr::Code(E_INVARG));
+    }
+
+    let mut quotas = vec![];
+    for (kind, pname) in QUOTA_KINDS {
+        match bf_args
+            .world_state
+            .retrieve_property(bf_args.task_perms_who(), *owner, pname)
+        {
+            Ok(value) if matches!(value.variant(), Variant::Int(_)) => {
+                quotas.push(v_listv(vec![v_str(kind), value]));
+            }
+            Ok(_) | Err(WorldStateError::PropertyNotFound(_, _)) => {}
+            Err(e) => return Err(world_state_bf_err(e)),
+        }
+    }
+    Ok(Ret(v_listv(quotas)))
+}
+bf_declare!(quota, bf_quota);
+
 impl VM {
     pub(crate) fn register_bf_objects(&mut self) {
         self.builtins[offset_for_builtin("create")] = Arc::new(BfCreate {});
@@ -690,11 +956,17 @@ impl VM {
         self.builtins[offset_for_builtin("properties")] = Arc::new(BfProperties {});
         self.builtins[offset_for_builtin("parent")] = Arc::new(BfParent {});
         self.builtins[offset_for_builtin("children")] = Arc::new(BfChildren {});
+        self.builtins[offset_for_builtin("parents")] = Arc::new(BfParents {});
+        self.builtins[offset_for_builtin("children_of")] = Arc::new(BfChildrenOf {});
         self.builtins[offset_for_builtin("move")] = Arc::new(BfMove {});
         self.builtins[offset_for_builtin("chparent")] = Arc::new(BfChparent {});
         self.builtins[offset_for_builtin("set_player_flag")] = Arc::new(BfSetPlayerFlag {});
         self.builtins[offset_for_builtin("recycle")] = Arc::new(BfRecycle {});
+        self.builtins[offset_for_builtin("raw_recycle")] = Arc::new(BfRawRecycle {});
         self.builtins[offset_for_builtin("max_object")] = Arc::new(BfMaxObject {});
         self.builtins[offset_for_builtin("players")] = Arc::new(BfPlayers {});
+        self.builtins[offset_for_builtin("isa")] = Arc::new(BfIsa {});
+        self.builtins[offset_for_builtin("set_quota")] = Arc::new(BfSetQuota {});
+        self.builtins[offset_for_builtin("quota")] = Arc::new(BfQuota {});
     }
 }
@@ -13,6 +13,7 @@
 //
 
 use std::io::Read;
+use std::path::{Component, Path};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
@@ -23,19 +24,25 @@ use tracing::{debug, error, info, warn};
 
 use moor_compiler::compile;
 use moor_compiler::{offset_for_builtin, ArgCount, ArgType, Builtin, BUILTIN_DESCRIPTORS};
+use moor_db::db_worldstate::verb_lookup_stats;
 use moor_values::model::ObjFlag;
-use moor_values::model::{NarrativeEvent, WorldStateError};
-use moor_values::var::Error::{E_ARGS, E_PERM, E_TYPE};
+use moor_values::model::{NarrativeEvent, PresenceKind, WorldStateError};
+use moor_values::var::Error::{E_ARGS, E_INVARG, E_PERM, E_TYPE};
 use moor_values::var::Variant;
-use moor_values::var::{v_bool, v_int, v_list, v_none, v_objid, v_str, v_string, Var};
-use moor_values::var::{v_listv, Error};
+use moor_values::var::{v_bool, v_int, v_list, v_none, v_objid, v_str, v_string, Objid, Var};
+use moor_values::var::{v_err, v_listv, Error};
+use moor_values::SYSTEM_OBJECT;
 
 use crate::bf_declare;
 use crate::builtins::BfRet::{Ret, VmInstr};
 use crate::builtins::{world_state_bf_err, BfCallState, BfErr, BfRet, BuiltinFunction};
+use crate::tasks::server_options::ServerOptions;
+use crate::tasks::sessions::CONNECTION_OPTIONS;
 use crate::tasks::task_messages::SchedulerControlMsg;
 use crate::tasks::TaskId;
-use crate::vm::{ExecutionResult, VM};
+use crate::textdump::ExportFormat;
+use crate::vm::{ExecutionResult, FinallyReason, VM};
+use crate::{component_versions, SERVER_VERSION};
 
 fn bf_noop(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     // TODO: Remove bf_noop panic
@@ -46,8 +53,9 @@ fn bf_noop(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(noop, bf_noop);
 
-fn bf_notify(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
-    if bf_args.args.len() != 2 {
+/// The player and event for a `notify`-style call of `(player, msg [, content_type])`.
+fn notify_args(bf_args: &BfCallState<'_>) -> Result<(Objid, NarrativeEvent), BfErr> {
+    if bf_args.args.len() < 2 || bf_args.args.len() > 3 {
         return Err(BfErr::Code(E_ARGS));
     }
     let player = bf_args.args[0].variant();
@@ -66,16 +74,31 @@ fn bf_notify(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
         .check_obj_owner_perms(*player)
         .map_err(world_state_bf_err)?;
 
-    let event = NarrativeEvent::notify_text(bf_args.exec_state.caller(), msg.to_string());
+    // A string third argument is the content type of `msg`, for clients which can render it.
+    // Anything else is LambdaMOO's `no_flush` flag, which doesn't mean anything to us.
+    let event = match bf_args.args.get(2).map(|a| a.variant()) {
+        Some(Variant::Str(content_type))
+            if !content_type.is_empty() && content_type.as_str() != "text/plain" =>
+        {
+            NarrativeEvent::notify_rich(
+                bf_args.exec_state.caller(),
+                msg.to_string(),
+                content_type.to_string(),
+            )
+        }
+        _ => NarrativeEvent::notify_text(bf_args.exec_state.caller(), msg.to_string()),
+    };
+    Ok((*player, event))
+}
+
+fn bf_notify(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    let (player, event) = notify_args(bf_args)?;
 
     bf_args
         .scheduler_sender
         .send((
             bf_args.exec_state.task_id,
-            SchedulerControlMsg::Notify {
-                player: *player,
-                event,
-            },
+            SchedulerControlMsg::Notify { player, event },
         ))
         .expect("scheduler is not listening");
 
@@ -84,15 +107,135 @@ fn bf_notify(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(notify, bf_notify);
 
+/*
+int notify_connection(obj player, str msg [, str content_type])
+
+Like notify(), but the message only goes to the connection the current task is running for, not to
+every connection player has. For output that belongs with one session, like a prompt.
+*/
+fn bf_notify_connection(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    let (player, event) = notify_args(bf_args)?;
+
+    bf_args
+        .scheduler_sender
+        .send((
+            bf_args.exec_state.task_id,
+            SchedulerControlMsg::NotifyConnection { player, event },
+        ))
+        .expect("scheduler is not listening");
+
+    Ok(Ret(v_int(1)))
+}
+bf_declare!(notify_connection, bf_notify_connection);
+
+/*
+none notify_presence (obj player, obj who, str kind)
+
+Tell player's connections that who is now "typing", "idle" or "active", for clients which can show
+such things. Clients which can't (e.g. telnet) ignore it. Unlike notify(), this is sent right away
+rather than when the task commits.
+*/
+fn bf_notify_presence(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 3 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let (Variant::Obj(player), Variant::Obj(who), Variant::Str(kind)) = (
+        bf_args.args[0].variant(),
+        bf_args.args[1].variant(),
+        bf_args.args[2].variant(),
+    ) else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    let kind = match kind.as_str().to_lowercase().as_str() {
+        "typing" => PresenceKind::Typing,
+        "idle" => PresenceKind::Idle,
+        "active" => PresenceKind::Active,
+        _ => return Err(BfErr::Code(E_INVARG)),
+    };
+
+    // Same rule as notify(): only the player themselves, or a wizard.
+    bf_args
+        .task_perms()
+        .map_err(world_state_bf_err)?
+        .check_obj_owner_perms(*player)
+        .map_err(world_state_bf_err)?;
+
+    if let Err(e) = bf_args.session.send_presence(*player, *who, kind) {
+        debug!(?player, ?who, "Could not send presence: {e}");
+    }
+    Ok(Ret(v_none()))
+}
+bf_declare!(notify_presence, bf_notify_presence);
+
+/*
+none notify_system (obj player, str msg [, str content_type])
+
+Send msg to player's connections as a system message, e.g. a banner or a notice from the server.
+Unlike notify(), this is sent right away rather than when the task commits. If content_type is
+given (e.g. "text/html" or "text/djot"), clients which can render it will; the others just show the
+text.
+*/
+fn bf_notify_system(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() < 2 || bf_args.args.len() > 3 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let (Variant::Obj(player), Variant::Str(msg)) =
+        (bf_args.args[0].variant(), bf_args.args[1].variant())
+    else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    let content_type = match bf_args.args.get(2).map(|a| a.variant()) {
+        None => None,
+        Some(Variant::Str(content_type))
+            if content_type.is_empty() || content_type.as_str() == "text/plain" =>
+        {
+            None
+        }
+        Some(Variant::Str(content_type)) => Some(content_type.as_str()),
+        Some(_) => return Err(BfErr::Code(E_TYPE)),
+    };
+
+    // Same rule as notify(): only the player themselves, or a wizard.
+    bf_args
+        .task_perms()
+        .map_err(world_state_bf_err)?
+        .check_obj_owner_perms(*player)
+        .map_err(world_state_bf_err)?;
+
+    if let Err(e) = bf_args
+        .session
+        .send_system_msg(*player, msg.as_str(), content_type)
+    {
+        debug!(?player, "Could not send system message: {e}");
+    }
+    Ok(Ret(v_none()))
+}
+bf_declare!(notify_system, bf_notify_system);
+
+// Syntax:  connected_players ([include-all [, max-idle]])   => list
+//
+// With a true include-all, connections which haven't logged in yet are listed too. With a
+// max-idle, only those idle for no more than that many seconds are.
 fn bf_connected_players(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
-    if !bf_args.args.is_empty() {
+    if bf_args.args.len() > 2 {
         return Err(BfErr::Code(E_ARGS));
     }
 
+    let include_all = bf_args.args.first().is_some_and(|a| a.is_true());
+    let max_idle = match bf_args.args.get(1).map(|a| a.variant()) {
+        None => None,
+        Some(Variant::Int(i)) => Some(*i as f64),
+        Some(Variant::Float(f)) => Some(*f),
+        Some(_) => return Err(BfErr::Code(E_TYPE)),
+    };
+    if max_idle.is_some_and(|m| m < 0.0) {
+        return Err(BfErr::Code(E_INVARG));
+    }
+
     Ok(Ret(v_listv(
         bf_args
             .session
-            .connected_players()
+            .connected_players(include_all, max_idle)
             .unwrap()
             .iter()
             .map(|p| v_objid(*p))
@@ -128,6 +271,21 @@ fn bf_caller_perms(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(caller_perms, bf_caller_perms);
 
+/*
+obj task_perms ()
+
+The permissions the running task currently has: those of the running verb, unless changed with
+set_task_perms(). caller_perms(), by contrast, gives the permissions of whatever called the verb.
+*/
+fn bf_task_perms(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if !bf_args.args.is_empty() {
+        return Err(BfErr::Code(E_ARGS));
+    }
+
+    Ok(Ret(v_objid(bf_args.task_perms_who())))
+}
+bf_declare!(task_perms, bf_task_perms);
+
 fn bf_set_task_perms(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     if bf_args.args.len() != 1 {
         return Err(BfErr::Code(E_ARGS));
@@ -136,6 +294,15 @@ fn bf_set_task_perms(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
         return Err(BfErr::Code(E_TYPE));
     };
 
+    // Running as a recycled or nonexistent object would just fail confusingly later.
+    if !bf_args
+        .world_state
+        .valid(perms_for)
+        .map_err(world_state_bf_err)?
+    {
+        return Err(BfErr::Code(E_INVARG));
+    }
+
     // If the caller is not a wizard, perms_for must be the caller
     let perms = bf_args.task_perms().map_err(world_state_bf_err)?;
     if !perms.check_is_wizard().map_err(world_state_bf_err)? && perms_for != perms.who {
@@ -147,18 +314,35 @@ fn bf_set_task_perms(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(set_task_perms, bf_set_task_perms);
 
+/*
+list callers ([include-line-numbers [, depth]])
+
+One entry per calling frame, innermost first:
+    {this, verb-name, programmer, verb-loc, player[, line-number], is-builtin}
+The line number is left out if include-line-numbers is given and false. is-builtin is true for
+frames of built-in functions which called out to a verb. With depth, at most that many frames are
+described.
+*/
 fn bf_callers(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
-    if !bf_args.args.is_empty() {
+    if bf_args.args.len() > 2 {
         return Err(BfErr::Code(E_ARGS));
     }
+    let include_line_numbers = bf_args.args.first().map(|a| a.is_true()).unwrap_or(true);
+    let depth = match bf_args.args.get(1).map(|a| a.variant()) {
+        None => None,
+        Some(Variant::Int(depth)) if *depth >= 0 => Some(*depth as usize),
+        Some(Variant::Int(_)) => return Err(BfErr::Code(E_INVARG)),
+        Some(_) => return Err(BfErr::Code(E_TYPE)),
+    };
 
     // We have to exempt ourselves from the callers list.
-    let callers = bf_args.exec_state.callers()[1..].to_vec();
+    let callers = bf_args.exec_state.callers(depth.map(|d| d + 1));
     Ok(Ret(v_listv(
         callers
             .iter()
+            .skip(1)
             .map(|c| {
-                let callers = vec![
+                let mut callers = vec![
                     // this
                     v_objid(c.this),
                     // verb name
@@ -169,9 +353,11 @@ fn bf_callers(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
                     v_objid(c.definer),
                     // player
                     v_objid(c.player),
-                    // line number
-                    v_int(c.line_number as i64),
                 ];
+                if include_line_numbers {
+                    callers.push(v_int(c.line_number as i64));
+                }
+                callers.push(v_bool(c.is_builtin));
                 v_listv(callers)
             })
             .collect::<Vec<Var>>(),
@@ -253,6 +439,145 @@ fn bf_connection_name(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(connection_name, bf_connection_name);
 
+/// Resolve the `conn` argument of the connection option builtins, which the programmer must either
+/// be, or be a wizard.
+fn connection_option_target(bf_args: &mut BfCallState<'_>) -> Result<Objid, BfErr> {
+    let Variant::Obj(player) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    if !bf_args
+        .task_perms()
+        .map_err(world_state_bf_err)?
+        .check_is_wizard()
+        .map_err(world_state_bf_err)?
+        && bf_args.caller_perms() != *player
+    {
+        return Err(BfErr::Code(E_PERM));
+    }
+    Ok(*player)
+}
+
+/*
+none set_connection_option(obj conn, str option, value)
+
+Sets the given option on conn's connections. The options understood are:
+    "idle-exempt": if true, the connection is never dropped for being idle, however long the
+                   daemon's idle timeout. For bots and the like.
+*/
+fn bf_set_connection_option(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 3 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let player = connection_option_target(bf_args)?;
+    let Variant::Str(option) = bf_args.args[1].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    if !CONNECTION_OPTIONS.contains(&option.as_str()) {
+        return Err(BfErr::Code(E_INVARG));
+    }
+    bf_args
+        .session
+        .set_connection_option(player, option.as_str(), bf_args.args[2].clone())
+        .map_err(|_| BfErr::Code(E_INVARG))?;
+    Ok(Ret(v_none()))
+}
+bf_declare!(set_connection_option, bf_set_connection_option);
+
+/*
+value connection_option(obj conn, str option)
+
+The current value of the given option on conn's connections; 0 if it hasn't been set.
+*/
+fn bf_connection_option(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 2 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let player = connection_option_target(bf_args)?;
+    let Variant::Str(option) = bf_args.args[1].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    if !CONNECTION_OPTIONS.contains(&option.as_str()) {
+        return Err(BfErr::Code(E_INVARG));
+    }
+    let options = bf_args
+        .session
+        .connection_options(player)
+        .map_err(|_| BfErr::Code(E_INVARG))?;
+    let value = options
+        .into_iter()
+        .find(|(name, _)| name == option.as_str())
+        .map_or(v_int(0), |(_, value)| value);
+    Ok(Ret(value))
+}
+bf_declare!(connection_option, bf_connection_option);
+
+/*
+list connection_options(obj conn)
+
+Returns {{name, value}, ...} for every option which has been set on conn's connections.
+*/
+fn bf_connection_options(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 1 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let player = connection_option_target(bf_args)?;
+    let options = bf_args
+        .session
+        .connection_options(player)
+        .map_err(|_| BfErr::Code(E_INVARG))?;
+    let options: Vec<_> = options
+        .into_iter()
+        .map(|(name, value)| v_list(&[v_string(name), value]))
+        .collect();
+    Ok(Ret(v_listv(options)))
+}
+bf_declare!(connection_options, bf_connection_options);
+
+fn bf_open_network_connection(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    // Syntax:  open_network_connection(STR host, INT port [, OBJ listener])   => OBJ
+    //
+    // Opens an outbound connection via one of the connected hosts, and returns its (negative)
+    // connection object. Output is sent with `notify`, and input lines are retrieved with `read`.
+    // The listener argument is accepted for compatibility, but no listener verbs are called.
+    if bf_args.args.len() < 2 || bf_args.args.len() > 3 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let Variant::Str(host) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    let Variant::Int(port) = bf_args.args[1].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    if bf_args.args.len() == 3 {
+        let Variant::Obj(_listener) = bf_args.args[2].variant() else {
+            return Err(BfErr::Code(E_TYPE));
+        };
+    }
+
+    bf_args
+        .task_perms()
+        .map_err(world_state_bf_err)?
+        .check_wizard()
+        .map_err(world_state_bf_err)?;
+
+    let Ok(port) = u16::try_from(*port) else {
+        return Err(BfErr::Code(E_INVARG));
+    };
+    match bf_args.session.open_network_connection(host.as_str(), port) {
+        Ok(connection) => Ok(Ret(v_objid(connection))),
+        Err(e) => {
+            warn!(
+                ?e,
+                host = host.as_str(),
+                port,
+                "open_network_connection() failed"
+            );
+            Err(BfErr::Code(E_INVARG))
+        }
+    }
+}
+bf_declare!(open_network_connection, bf_open_network_connection);
+
 fn bf_shutdown(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     if bf_args.args.len() > 1 {
         return Err(BfErr::Code(E_ARGS));
@@ -361,34 +686,73 @@ fn bf_raise(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 
 bf_declare!(raise, bf_raise);
 
+/*
+server_version([structured])
+
+Returns the server's version as a string. If `structured` is true, instead returns a list of
+{component, version} pairs, for each of "kernel", "db" (the data layout version) and "protocol"
+(the host RPC protocol version), so cores can tell what the server supports.
+*/
 fn bf_server_version(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
-    if !bf_args.args.is_empty() {
+    if bf_args.args.len() > 1 {
         return Err(BfErr::Code(E_ARGS));
     }
+    if bf_args.args.first().map(|a| a.is_true()).unwrap_or(false) {
+        let components: Vec<_> = component_versions()
+            .into_iter()
+            .map(|(component, version)| v_list(&[v_str(component), v_string(version)]))
+            .collect();
+        return Ok(Ret(v_list(&components)));
+    }
     // TODO: Support server version flag passed down the pipe, rather than hardcoded
     //   This is a placeholder for now, should be set by the server on startup. But right now
     //   there isn't a good place to stash this other than WorldState. I intend on refactoring the
     //   signature for BF invocations, and when I do this, I'll get additional metadata on there.
-    Ok(Ret(v_string("0.0.1".to_string())))
+    Ok(Ret(v_string(SERVER_VERSION.to_string())))
 }
 bf_declare!(server_version, bf_server_version);
 
+/*
+server_features()
+
+Returns a list of the names of the optional server behaviours switched on in this server's
+configuration (e.g. "byte_string_semantics"), so cores can adapt to them.
+*/
+fn bf_server_features(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if !bf_args.args.is_empty() {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let features: Vec<_> = bf_args
+        .exec_state
+        .config
+        .features()
+        .into_iter()
+        .map(v_str)
+        .collect();
+    Ok(Ret(v_list(&features)))
+}
+bf_declare!(server_features, bf_server_features);
+
 fn bf_suspend(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     // Syntax:  suspend(<seconds>)   => none
     //
     // Suspends the current task for <seconds> seconds.  If <seconds> is not specified, the task is suspended indefinitely.  The task may be resumed early by
     // calling `resume' on it.
+    // <seconds> may also be a float, for delays finer than a second (to the millisecond).
     if bf_args.args.len() > 1 {
         return Err(BfErr::Code(E_ARGS));
     }
 
-    let seconds = if bf_args.args.is_empty() {
-        None
-    } else {
-        let Variant::Int(seconds) = bf_args.args[0].variant() else {
-            return Err(BfErr::Code(E_TYPE));
-        };
-        Some(Duration::from_secs(*seconds as u64))
+    let seconds = match bf_args.args.first().map(|a| a.variant()) {
+        None => None,
+        Some(Variant::Int(seconds)) => Some(Duration::from_secs(*seconds as u64)),
+        Some(Variant::Float(seconds)) => {
+            if !seconds.is_finite() || *seconds < 0.0 {
+                return Err(BfErr::Code(E_INVARG));
+            }
+            Some(Duration::from_millis((seconds * 1000.0).round() as u64))
+        }
+        Some(_) => return Err(BfErr::Code(E_TYPE)),
     };
 
     Ok(VmInstr(ExecutionResult::Suspend(seconds)))
@@ -396,30 +760,35 @@ fn bf_suspend(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 bf_declare!(suspend, bf_suspend);
 
 fn bf_read(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
-    if bf_args.args.len() > 1 {
+    if bf_args.args.len() > 2 {
         return Err(BfErr::Code(E_ARGS));
     }
 
-    // We don't actually support reading from arbitrary connections that aren't the current player,
-    // so we'll raise E_INVARG for anything else, because we don't support LambdaMOO's
-    // network listener model.
-    if bf_args.args.len() == 1 {
-        let Variant::Obj(requested_player) = bf_args.args[0].variant() else {
-            return Err(BfErr::Code(E_ARGS));
-        };
-        let player = bf_args.exec_state.top().player;
-        if *requested_player != player {
-            // We log this because we'd like to know if cores are trying to do this.
-            warn!(
-                requested_player = ?requested_player,
-                caller = ?bf_args.exec_state.caller(),
-                ?player,
-                "read() called with non-current player");
-            return Err(BfErr::Code(E_ARGS));
-        }
+    let player = bf_args.exec_state.top().player;
+    let requested_player = match bf_args.args.first().map(|a| a.variant()) {
+        None => player,
+        Some(Variant::Obj(requested_player)) => *requested_player,
+        Some(_) => return Err(BfErr::Code(E_TYPE)),
+    };
+
+    // Reading from someone else's connection is for wizards only.
+    if requested_player != player
+        && !bf_args
+            .task_perms()
+            .map_err(world_state_bf_err)?
+            .check_is_wizard()
+            .map_err(world_state_bf_err)?
+    {
+        return Err(BfErr::Code(E_PERM));
+    }
+
+    // Hosts do not buffer unsolicited lines of input on our behalf; anything typed is dispatched
+    // as a command. So there is never input already queued for a non-blocking read.
+    if bf_args.args.len() == 2 && bf_args.args[1].is_true() {
+        return Ok(Ret(v_int(0)));
     }
 
-    Ok(VmInstr(ExecutionResult::NeedInput))
+    Ok(VmInstr(ExecutionResult::NeedInput(requested_player)))
 }
 bf_declare!(read, bf_read);
 
@@ -443,6 +812,8 @@ fn bf_queued_tasks(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     // return in form:
     //     {<task-id>, <start-time>, <x>, <y>,
     //      <programmer>, <verb-loc>, <verb-name>, <line>, <this>}
+    // in the order the tasks suspended, which is also the order the scheduler will resume any
+    // of them which come due at the same time.
     let tasks: Vec<_> = tasks
         .iter()
         .map(|task| {
@@ -471,6 +842,42 @@ fn bf_queued_tasks(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(queued_tasks, bf_queued_tasks);
 
+/*
+list queue_info([obj player])
+
+Returns {<running>, <queued>}: how many tasks are executing, and how many are waiting to start because
+the server's limit on concurrent tasks has been reached. Suspended tasks, and tasks waiting in read(),
+are neither. If player is given, only that player's tasks are counted.
+*/
+fn bf_queue_info(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    let player = match bf_args.args.len() {
+        0 => None,
+        1 => {
+            let Variant::Obj(player) = bf_args.args[0].variant() else {
+                return Err(BfErr::Code(E_TYPE));
+            };
+            Some(*player)
+        }
+        _ => return Err(BfErr::Code(E_ARGS)),
+    };
+
+    let (send, receive) = oneshot::channel();
+    bf_args
+        .scheduler_sender
+        .send((
+            bf_args.exec_state.task_id,
+            SchedulerControlMsg::QueueInfo {
+                player,
+                reply: send,
+            },
+        ))
+        .expect("scheduler is not listening");
+    let (running, queued) = receive.recv().expect("scheduler is not listening");
+
+    Ok(Ret(v_list(&[v_int(running as i64), v_int(queued as i64)])))
+}
+bf_declare!(queue_info, bf_queue_info);
+
 fn bf_kill_task(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     // Syntax:  kill_task(<task-id>)   => none
     //
@@ -483,13 +890,13 @@ fn bf_kill_task(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
         return Err(BfErr::Code(E_TYPE));
     };
 
-    // If the task ID is itself, that means returning an Complete execution result, which will cascade
-    // back to the task loop and it will terminate itself.
-    // Not sure this is *exactly* what MOO does, but it's close enough for now.
+    // If the task ID is itself, the task aborts, just as if another task had killed it: what it
+    // has done since it last committed is rolled back, and it goes no further.
+    // Unknown task ids raise E_INVARG, and other owners' tasks E_PERM (unless we're a wizard).
     let victim_task_id = *victim_task_id as TaskId;
 
     if victim_task_id == bf_args.exec_state.task_id {
-        return Ok(VmInstr(ExecutionResult::Complete(v_none())));
+        return Ok(VmInstr(ExecutionResult::Exception(FinallyReason::Abort)));
     }
 
     let (send, receive) = oneshot::channel();
@@ -513,6 +920,59 @@ fn bf_kill_task(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(kill_task, bf_kill_task);
 
+/*
+list forked_tasks()
+
+Returns the ids of the tasks forked by the current task which haven't yet finished, in the order
+they were forked. Tasks forked by those tasks in turn are not included.
+*/
+fn bf_forked_tasks(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if !bf_args.args.is_empty() {
+        return Err(BfErr::Code(E_ARGS));
+    }
+
+    let (send, receive) = oneshot::channel();
+    bf_args
+        .scheduler_sender
+        .send((
+            bf_args.exec_state.task_id,
+            SchedulerControlMsg::ForkedTasks(send),
+        ))
+        .expect("scheduler is not listening");
+    let forks = receive.recv().expect("scheduler is not listening");
+
+    let forks: Vec<_> = forks
+        .into_iter()
+        .map(|task_id| v_int(task_id as i64))
+        .collect();
+    Ok(Ret(v_listv(forks)))
+}
+bf_declare!(forked_tasks, bf_forked_tasks);
+
+/*
+int kill_forks()
+
+Kills every task listed by `forked_tasks()`, returning how many there were.
+*/
+fn bf_kill_forks(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if !bf_args.args.is_empty() {
+        return Err(BfErr::Code(E_ARGS));
+    }
+
+    let (send, receive) = oneshot::channel();
+    bf_args
+        .scheduler_sender
+        .send((
+            bf_args.exec_state.task_id,
+            SchedulerControlMsg::KillForks(send),
+        ))
+        .expect("scheduler is not listening");
+    let killed = receive.recv().expect("scheduler is not listening");
+
+    Ok(Ret(v_int(killed as i64)))
+}
+bf_declare!(kill_forks, bf_kill_forks);
+
 fn bf_resume(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     if bf_args.args.len() < 2 {
         return Err(BfErr::Code(E_ARGS));
@@ -558,6 +1018,78 @@ fn bf_resume(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(resume, bf_resume);
 
+fn bf_verb_cache_stats(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    // Syntax:  verb_cache_stats()   => {INT hits, INT misses}
+    //
+    // moor has no verb cache; these are the counts of method verb lookups which found a verb and
+    // which didn't, since server start.
+    if !bf_args.args.is_empty() {
+        return Err(BfErr::Code(E_ARGS));
+    }
+
+    bf_args
+        .task_perms()
+        .map_err(world_state_bf_err)?
+        .check_wizard()
+        .map_err(world_state_bf_err)?;
+
+    let stats = verb_lookup_stats();
+    Ok(Ret(v_listv(vec![
+        v_int(stats.hits as i64),
+        v_int(stats.misses as i64),
+    ])))
+}
+bf_declare!(verb_cache_stats, bf_verb_cache_stats);
+
+fn bf_log_cache_stats(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if !bf_args.args.is_empty() {
+        return Err(BfErr::Code(E_ARGS));
+    }
+
+    bf_args
+        .task_perms()
+        .map_err(world_state_bf_err)?
+        .check_wizard()
+        .map_err(world_state_bf_err)?;
+
+    let stats = verb_lookup_stats();
+    info!(
+        hits = stats.hits,
+        misses = stats.misses,
+        "Verb lookup stats"
+    );
+    Ok(Ret(v_none()))
+}
+bf_declare!(log_cache_stats, bf_log_cache_stats);
+
+fn bf_load_server_options(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    // Syntax:  load_server_options()   => 0
+    //
+    // Re-reads the limits set on `$server_options` (see `ServerOptions` for which ones are
+    // honored), and applies them to all tasks started from here on.
+    if !bf_args.args.is_empty() {
+        return Err(BfErr::Code(E_ARGS));
+    }
+
+    bf_args
+        .task_perms()
+        .map_err(world_state_bf_err)?
+        .check_wizard()
+        .map_err(world_state_bf_err)?;
+
+    let server_options = ServerOptions::load(bf_args.world_state, SYSTEM_OBJECT);
+    bf_args
+        .scheduler_sender
+        .send((
+            bf_args.exec_state.task_id,
+            SchedulerControlMsg::SetServerOptions(server_options),
+        ))
+        .expect("scheduler is not listening");
+
+    Ok(Ret(v_int(0)))
+}
+bf_declare!(load_server_options, bf_load_server_options);
+
 fn bf_ticks_left(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     // Syntax:  ticks_left()   => int
     //
@@ -593,10 +1125,12 @@ fn bf_seconds_left(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 bf_declare!(seconds_left, bf_seconds_left);
 
 fn bf_boot_player(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
-    // Syntax:  boot_player(<player>)   => none
+    // Syntax:  boot_player(<player> [, <connection-only>])   => none
     //
-    // Disconnects the player with the given object number.
-    if bf_args.args.len() != 1 {
+    // Disconnects the player with the given object number. If <connection-only> is true, only the
+    // connection this task is running for is dropped, and the player's other connections survive;
+    // that connection belongs to the task's player, so booting anyone else that way is E_INVARG.
+    if bf_args.args.is_empty() || bf_args.args.len() > 2 {
         return Err(BfErr::Code(E_ARGS));
     }
 
@@ -604,6 +1138,11 @@ fn bf_boot_player(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
         return Err(BfErr::Code(E_TYPE));
     };
 
+    let connection_only = bf_args.args.len() == 2 && bf_args.args[1].is_true();
+    if connection_only && *player != bf_args.exec_state.top().player {
+        return Err(BfErr::Code(E_INVARG));
+    }
+
     let task_perms = bf_args.task_perms().map_err(world_state_bf_err)?;
     if task_perms.who != *player && !task_perms.check_is_wizard().map_err(world_state_bf_err)? {
         return Err(BfErr::Code(E_PERM));
@@ -616,6 +1155,7 @@ fn bf_boot_player(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
             SchedulerControlMsg::BootPlayer {
                 player: *player,
                 sender_permissions: task_perms,
+                connection_only,
             },
         ))
         .expect("scheduler is not listening");
@@ -767,13 +1307,23 @@ fn bf_listeners(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
         return Err(BfErr::Code(E_ARGS));
     }
 
-    // TODO: Return something better from bf_listeners, rather than hardcoded value
-    //   this function is hardcoded to just return {{#0, 7777, 1}}
-    //   this is on account that existing cores expect this to be the case
-    //   but we have no intend of supporting other network listener magic at this point
-    let listeners = v_list(&[v_list(&[v_int(0), v_int(7777), v_int(1)])]);
+    // As in LambdaMOO, each is {handler object, port, print-messages}. There's no `listen()`
+    // though; it's the hosts which decide where to listen, and tell the daemon.
+    let Ok(listeners) = bf_args.session.listeners() else {
+        return Err(BfErr::Code(E_INVARG));
+    };
+    let listeners: Vec<_> = listeners
+        .iter()
+        .map(|l| {
+            v_list(&[
+                v_objid(l.handler),
+                v_int(l.addr.port() as i64),
+                v_bool(l.print_messages),
+            ])
+        })
+        .collect();
 
-    Ok(Ret(listeners))
+    Ok(Ret(v_list(&listeners)))
 }
 bf_declare!(listeners, bf_listeners);
 
@@ -843,6 +1393,111 @@ fn bf_dump_database(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(dump_database, bf_dump_database);
 
+/*
+export_database(str name, str format)
+
+Writes the whole database, from a snapshot, to the file `name` in the server's export directory,
+as either a "textdump" or a "binary" dump, returning true once it's written. `name` must be a
+plain file name. Raises E_PERM if no export directory is configured, and E_INVARG if the file
+couldn't be written. Wizard only.
+*/
+fn bf_export_database(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 2 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    bf_args
+        .task_perms()
+        .map_err(world_state_bf_err)?
+        .check_wizard()
+        .map_err(world_state_bf_err)?;
+    let (Variant::Str(path), Variant::Str(format)) =
+        (bf_args.args[0].variant(), bf_args.args[1].variant())
+    else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    let Some(format) = ExportFormat::from_name(format.as_str()) else {
+        return Err(BfErr::Code(E_INVARG));
+    };
+    // Nothing but a file name, so it can't reach outside the export directory.
+    let components = Path::new(path.as_str()).components().collect::<Vec<_>>();
+    if !matches!(components.as_slice(), [Component::Normal(_)]) {
+        return Err(BfErr::Code(E_INVARG));
+    }
+
+    let (send, receive) = oneshot::channel();
+    bf_args
+        .scheduler_sender
+        .send((
+            bf_args.exec_state.task_id,
+            SchedulerControlMsg::ExportDatabase {
+                name: path.as_str().to_string(),
+                format,
+                result_sender: send,
+            },
+        ))
+        .expect("scheduler is not listening");
+
+    // If the export never got going, there's no reply coming.
+    let result = receive.recv().unwrap_or_else(|_| v_err(E_INVARG));
+    if let Variant::Err(err) = result.variant() {
+        return Err(BfErr::Code(*err));
+    }
+    Ok(Ret(result))
+}
+bf_declare!(export_database, bf_export_database);
+
+/*
+verify_database()
+
+Scans the database for structural inconsistencies, returning a list of `{kind, obj, detail}`
+findings, where `kind` is one of "bad_parent", "bad_location", "stale_child",
+"stale_content" or "missing_propval". An empty list means nothing was found. Wizard only.
+*/
+fn bf_verify_database(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if !bf_args.args.is_empty() {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let findings = bf_args
+        .world_state
+        .verify_database(bf_args.task_perms_who())
+        .map_err(world_state_bf_err)?;
+    let findings: Vec<_> = findings
+        .into_iter()
+        .map(|f| v_list(&[v_str(f.kind.name()), v_objid(f.obj), v_string(f.detail)]))
+        .collect();
+    Ok(Ret(v_list(&findings)))
+}
+bf_declare!(verify_database, bf_verify_database);
+
+/*
+revoke_tokens(obj player)
+
+Invalidates every auth token issued to `player` so far, so that they have to log in again.
+Their current connections are dropped too, since the tokens those hold stop working for anything
+they'd send. Wizard only.
+*/
+fn bf_revoke_tokens(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 1 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let Variant::Obj(player) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+
+    bf_args
+        .task_perms()
+        .map_err(world_state_bf_err)?
+        .check_wizard()
+        .map_err(world_state_bf_err)?;
+
+    bf_args
+        .session
+        .revoke_tokens(*player)
+        .map_err(|_| BfErr::Code(E_INVARG))?;
+    Ok(Ret(v_none()))
+}
+bf_declare!(revoke_tokens, bf_revoke_tokens);
+
 fn bf_memory_usage(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     if !bf_args.args.is_empty() {
         return Err(BfErr::Code(E_ARGS));
@@ -921,24 +1576,42 @@ bf_declare!(db_disk_size, db_disk_size);
 impl VM {
     pub(crate) fn register_bf_server(&mut self) {
         self.builtins[offset_for_builtin("notify")] = Arc::new(BfNotify {});
+        self.builtins[offset_for_builtin("notify_connection")] = Arc::new(BfNotifyConnection {});
+        self.builtins[offset_for_builtin("revoke_tokens")] = Arc::new(BfRevokeTokens {});
+        self.builtins[offset_for_builtin("notify_presence")] = Arc::new(BfNotifyPresence {});
+        self.builtins[offset_for_builtin("notify_system")] = Arc::new(BfNotifySystem {});
         self.builtins[offset_for_builtin("connected_players")] = Arc::new(BfConnectedPlayers {});
         self.builtins[offset_for_builtin("is_player")] = Arc::new(BfIsPlayer {});
         self.builtins[offset_for_builtin("caller_perms")] = Arc::new(BfCallerPerms {});
+        self.builtins[offset_for_builtin("task_perms")] = Arc::new(BfTaskPerms {});
         self.builtins[offset_for_builtin("set_task_perms")] = Arc::new(BfSetTaskPerms {});
         self.builtins[offset_for_builtin("callers")] = Arc::new(BfCallers {});
         self.builtins[offset_for_builtin("task_id")] = Arc::new(BfTaskId {});
         self.builtins[offset_for_builtin("idle_seconds")] = Arc::new(BfIdleSeconds {});
         self.builtins[offset_for_builtin("connected_seconds")] = Arc::new(BfConnectedSeconds {});
         self.builtins[offset_for_builtin("connection_name")] = Arc::new(BfConnectionName {});
+        self.builtins[offset_for_builtin("set_connection_option")] =
+            Arc::new(BfSetConnectionOption {});
+        self.builtins[offset_for_builtin("connection_option")] = Arc::new(BfConnectionOption {});
+        self.builtins[offset_for_builtin("connection_options")] = Arc::new(BfConnectionOptions {});
+        self.builtins[offset_for_builtin("open_network_connection")] =
+            Arc::new(BfOpenNetworkConnection {});
         self.builtins[offset_for_builtin("time")] = Arc::new(BfTime {});
         self.builtins[offset_for_builtin("ctime")] = Arc::new(BfCtime {});
         self.builtins[offset_for_builtin("raise")] = Arc::new(BfRaise {});
         self.builtins[offset_for_builtin("server_version")] = Arc::new(BfServerVersion {});
+        self.builtins[offset_for_builtin("server_features")] = Arc::new(BfServerFeatures {});
         self.builtins[offset_for_builtin("shutdown")] = Arc::new(BfShutdown {});
         self.builtins[offset_for_builtin("suspend")] = Arc::new(BfSuspend {});
         self.builtins[offset_for_builtin("queued_tasks")] = Arc::new(BfQueuedTasks {});
+        self.builtins[offset_for_builtin("queue_info")] = Arc::new(BfQueueInfo {});
         self.builtins[offset_for_builtin("kill_task")] = Arc::new(BfKillTask {});
+        self.builtins[offset_for_builtin("forked_tasks")] = Arc::new(BfForkedTasks {});
+        self.builtins[offset_for_builtin("kill_forks")] = Arc::new(BfKillForks {});
         self.builtins[offset_for_builtin("resume")] = Arc::new(BfResume {});
+        self.builtins[offset_for_builtin("verb_cache_stats")] = Arc::new(BfVerbCacheStats {});
+        self.builtins[offset_for_builtin("log_cache_stats")] = Arc::new(BfLogCacheStats {});
+        self.builtins[offset_for_builtin("load_server_options")] = Arc::new(BfLoadServerOptions {});
         self.builtins[offset_for_builtin("ticks_left")] = Arc::new(BfTicksLeft {});
         self.builtins[offset_for_builtin("seconds_left")] = Arc::new(BfSecondsLeft {});
         self.builtins[offset_for_builtin("boot_player")] = Arc::new(BfBootPlayer {});
@@ -949,6 +1622,8 @@ impl VM {
         self.builtins[offset_for_builtin("eval")] = Arc::new(BfEval {});
         self.builtins[offset_for_builtin("read")] = Arc::new(BfRead {});
         self.builtins[offset_for_builtin("dump_database")] = Arc::new(BfDumpDatabase {});
+        self.builtins[offset_for_builtin("export_database")] = Arc::new(BfExportDatabase {});
+        self.builtins[offset_for_builtin("verify_database")] = Arc::new(BfVerifyDatabase {});
         self.builtins[offset_for_builtin("memory_usage")] = Arc::new(BfMemoryUsage {});
         self.builtins[offset_for_builtin("db_disk_size")] = Arc::new(BfDbDiskSize {});
     }
@@ -12,22 +12,69 @@
 // this program. If not, see <https://www.gnu.org/licenses/>.
 //
 
+use hmac::digest::KeyInit;
+use hmac::{Hmac, Mac};
 use md5::Digest;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::ops::Range;
 use std::sync::Arc;
 
+use pwhash::bcrypt::{BcryptSetup, BcryptVariant};
 use rand::distributions::Alphanumeric;
-use rand::Rng;
+use rand::rngs::OsRng;
+use rand::{Rng, RngCore};
 
 use moor_compiler::offset_for_builtin;
 use moor_values::var::Error::{E_ARGS, E_INVARG, E_TYPE};
-use moor_values::var::Variant;
-use moor_values::var::{v_int, v_str, v_string};
+use moor_values::var::{v_int, v_listv, v_str, v_string};
+use moor_values::var::{StringSemantics, Variant};
 
 use crate::bf_declare;
 use crate::builtins::BfRet::Ret;
 use crate::builtins::{BfCallState, BfErr, BfRet, BuiltinFunction};
 use crate::vm::VM;
 
+/// If `what` occurs at the very start of `subject`, how many bytes of `subject` it covers.
+/// Compares character by character, ignoring case unless `case_matters`.
+fn match_at(subject: &str, what: &str, case_matters: bool) -> Option<usize> {
+    let mut subject_chars = subject.char_indices();
+    for w in what.chars() {
+        let (_, c) = subject_chars.next()?;
+        if c != w && (case_matters || !c.to_lowercase().eq(w.to_lowercase())) {
+            return None;
+        }
+    }
+    Some(
+        subject_chars
+            .next()
+            .map(|(i, _)| i)
+            .unwrap_or(subject.len()),
+    )
+}
+
+/// The byte range of the first (or with `last`, the final) occurrence of `what` in `subject`.
+fn find_str(subject: &str, what: &str, case_matters: bool, last: bool) -> Option<Range<usize>> {
+    let mut starts = subject
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(subject.len()));
+    let found = |start: usize| {
+        match_at(&subject[start..], what, case_matters).map(|len| start..start + len)
+    };
+    if last {
+        starts.rev().find_map(found)
+    } else {
+        starts.find_map(found)
+    }
+}
+
+/// Turn a byte offset into `s` into the 1-based string index MOO code sees, which counts code
+/// points (or bytes, under byte string semantics).
+fn moo_index(semantics: StringSemantics, s: &str, byte_offset: usize) -> i64 {
+    semantics.offset_of(s, byte_offset) as i64 + 1
+}
+
 fn strsub(subject: &str, what: &str, with: &str, case_matters: bool) -> String {
     let mut result = String::new();
     let mut source = subject;
@@ -36,15 +83,10 @@ fn strsub(subject: &str, what: &str, with: &str, case_matters: bool) -> String {
         return subject.to_string();
     }
 
-    while let Some(index) = if case_matters {
-        source.find(what)
-    } else {
-        source.to_lowercase().find(&what.to_lowercase())
-    } {
-        result.push_str(&source[..index]);
+    while let Some(found) = find_str(source, what, case_matters, false) {
+        result.push_str(&source[..found.start]);
         result.push_str(with);
-        let next = index + what.len();
-        source = &source[next..];
+        source = &source[found.end..];
     }
 
     result.push_str(source);
@@ -78,28 +120,16 @@ fn bf_strsub(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(strsub, bf_strsub);
 
-fn str_index(subject: &str, what: &str, case_matters: bool) -> i64 {
-    if case_matters {
-        subject.find(what).map(|i| i as i64 + 1).unwrap_or(0)
-    } else {
-        subject
-            .to_lowercase()
-            .find(&what.to_lowercase())
-            .map(|i| i as i64 + 1)
-            .unwrap_or(0)
-    }
+fn str_index(semantics: StringSemantics, subject: &str, what: &str, case_matters: bool) -> i64 {
+    find_str(subject, what, case_matters, false)
+        .map(|found| moo_index(semantics, subject, found.start))
+        .unwrap_or(0)
 }
 
-fn str_rindex(subject: &str, what: &str, case_matters: bool) -> i64 {
-    if case_matters {
-        subject.rfind(what).map(|i| i as i64 + 1).unwrap_or(0)
-    } else {
-        subject
-            .to_lowercase()
-            .rfind(&what.to_lowercase())
-            .map(|i| i as i64 + 1)
-            .unwrap_or(0)
-    }
+fn str_rindex(semantics: StringSemantics, subject: &str, what: &str, case_matters: bool) -> i64 {
+    find_str(subject, what, case_matters, true)
+        .map(|found| moo_index(semantics, subject, found.start))
+        .unwrap_or(0)
 }
 
 fn bf_index(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
@@ -117,6 +147,7 @@ fn bf_index(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     let (subject, what) = (bf_args.args[0].variant(), bf_args.args[1].variant());
     match (subject, what) {
         (Variant::Str(subject), Variant::Str(what)) => Ok(Ret(v_int(str_index(
+            bf_args.exec_state.config.string_semantics(),
             subject.as_str(),
             what.as_str(),
             case_matters,
@@ -141,6 +172,7 @@ fn bf_rindex(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     let (subject, what) = (bf_args.args[0].variant(), bf_args.args[1].variant());
     match (subject, what) {
         (Variant::Str(subject), Variant::Str(what)) => Ok(Ret(v_int(str_rindex(
+            bf_args.exec_state.config.string_semantics(),
             subject.as_str(),
             what.as_str(),
             case_matters,
@@ -164,6 +196,49 @@ fn bf_strcmp(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(strcmp, bf_strcmp);
 
+/// The highest bcrypt cost `crypt()` will hash with. Each step doubles the work, and the cost comes
+/// from the caller's salt, so without a cap any programmer could tie up a task thread for hours.
+const MAX_BCRYPT_COST: u32 = 14;
+
+/// Crypt the text with the given salt, as `crypt()` does.
+/// A salt of just a bcrypt prefix (`$2a$`, `$2b$` or `$2y$`), optionally followed by a cost (e.g.
+/// `$2b$12$`), makes a fresh bcrypt hash with a random salt. Anything else, including a complete
+/// bcrypt hash, goes through the usual UNIX crypt, which picks the algorithm from the salt's format.
+/// So as ever, `crypt(text, stored) == stored` checks text against a stored hash.
+/// Either way, a bcrypt cost above `MAX_BCRYPT_COST` is refused rather than hashed.
+fn crypt(text: &str, salt: &str) -> Option<String> {
+    for (prefix, variant) in [
+        ("$2a$", BcryptVariant::V2a),
+        ("$2b$", BcryptVariant::V2b),
+        ("$2y$", BcryptVariant::V2y),
+    ] {
+        let Some(rest) = salt.strip_prefix(prefix) else {
+            continue;
+        };
+        let (cost, hash) = rest.split_once('$').unwrap_or((rest, ""));
+        if cost.len() > 2 || !cost.chars().all(|c| c.is_ascii_digit()) {
+            break;
+        }
+        if !cost.is_empty() && cost.parse::<u32>().ok()? > MAX_BCRYPT_COST {
+            return None;
+        }
+        if !hash.is_empty() {
+            break;
+        }
+        let setup = BcryptSetup {
+            salt: None,
+            cost: if cost.is_empty() {
+                None
+            } else {
+                Some(cost.parse().ok()?)
+            },
+            variant: Some(variant),
+        };
+        return pwhash::bcrypt::hash_with(setup, text).ok();
+    }
+    pwhash::unix::crypt(text, salt).ok()
+}
+
 /*
 str crypt (str text [, str salt])
 
@@ -172,6 +247,8 @@ string at least two characters long, the first two characters of which will be u
 encryption "salt" in the algorithm. If salt is not provided, a random pair of characters is used.
  In any case, the salt used is also returned as the first two characters of the resulting encrypted
  string.
+A salt starting with `$2b$` (or `$2a$` / `$2y$`) selects bcrypt instead; see `crypt` above.
+A bcrypt cost above 14 raises E_INVARG.
 */
 fn bf_crypt(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     if bf_args.args.is_empty() || bf_args.args.len() > 2 {
@@ -193,7 +270,7 @@ fn bf_crypt(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
         String::from(salt.as_str())
     };
     if let Variant::Str(text) = bf_args.args[0].variant() {
-        let crypted = pwhash::unix::crypt(text.as_str(), salt.as_str()).unwrap();
+        let crypted = crypt(text.as_str(), salt.as_str()).ok_or(BfErr::Code(E_INVARG))?;
         Ok(Ret(v_string(crypted)))
     } else {
         Err(BfErr::Code(E_TYPE))
@@ -215,6 +292,158 @@ fn bf_string_hash(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(string_hash, bf_string_hash);
 
+/// HMAC the data with the given key, using the named hash algorithm (`sha1`, `sha256` or `sha512`),
+/// rendered as hex the same way as `string_hash`. None if the algorithm isn't one of those.
+fn hmac_hex(algo: &str, key: &[u8], data: &[u8]) -> Option<String> {
+    fn hmac<M: Mac + KeyInit>(key: &[u8], data: &[u8]) -> String {
+        let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC takes keys of any length");
+        mac.update(data);
+        format!("{:x}", mac.finalize().into_bytes())
+    }
+    match algo.to_lowercase().as_str() {
+        "sha1" => Some(hmac::<Hmac<Sha1>>(key, data)),
+        "sha256" => Some(hmac::<Hmac<Sha256>>(key, data)),
+        "sha512" => Some(hmac::<Hmac<Sha512>>(key, data)),
+        _ => None,
+    }
+}
+
+/*
+str string_hmac (str text, str key [, str algorithm])
+
+Returns the HMAC of text under key, as a hex string. The algorithm is "sha256" unless given as
+"sha1" or "sha512"; any other raises E_INVARG.
+*/
+fn bf_string_hmac(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() < 2 || bf_args.args.len() > 3 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let (Variant::Str(text), Variant::Str(key)) =
+        (bf_args.args[0].variant(), bf_args.args[1].variant())
+    else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    let algo = match bf_args.args.get(2).map(|a| a.variant()) {
+        None => "sha256",
+        Some(Variant::Str(algo)) => algo.as_str(),
+        Some(_) => return Err(BfErr::Code(E_TYPE)),
+    };
+    let hmac = hmac_hex(algo, key.as_str().as_bytes(), text.as_str().as_bytes())
+        .ok_or(BfErr::Code(E_INVARG))?;
+    Ok(Ret(v_str(hmac.as_str())))
+}
+bf_declare!(string_hmac, bf_string_hmac);
+
+/// Split `subject` on every occurrence of `brk`, leaving out the empty pieces left by leading,
+/// trailing or adjacent delimiters unless `include_empty`.
+fn explode<'a>(subject: &'a str, brk: &str, include_empty: bool) -> Vec<&'a str> {
+    subject
+        .split(brk)
+        .filter(|piece| include_empty || !piece.is_empty())
+        .collect()
+}
+
+/*
+list explode (str subject [, str break [, include-empty]])
+
+Splits subject into a list of the pieces between occurrences of break (a space by default, and
+possibly several characters long). Empty pieces are left out unless include-empty is true.
+*/
+fn bf_explode(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.is_empty() || bf_args.args.len() > 3 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let Variant::Str(subject) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    let brk = match bf_args.args.get(1).map(|a| a.variant()) {
+        None => " ",
+        Some(Variant::Str(brk)) => brk.as_str(),
+        Some(_) => return Err(BfErr::Code(E_TYPE)),
+    };
+    if brk.is_empty() {
+        return Err(BfErr::Code(E_INVARG));
+    }
+    let include_empty = bf_args.args.get(2).map(|a| a.is_true()).unwrap_or(false);
+    let pieces = explode(subject.as_str(), brk, include_empty)
+        .into_iter()
+        .map(v_str)
+        .collect();
+    Ok(Ret(v_listv(pieces)))
+}
+bf_declare!(explode, bf_explode);
+
+/*
+str strjoin (list strings [, str separator])
+
+Concatenates a list of strings, with separator (a space by default) between each.
+*/
+fn bf_strjoin(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.is_empty() || bf_args.args.len() > 2 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let Variant::List(strings) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    let separator = match bf_args.args.get(1).map(|a| a.variant()) {
+        None => " ",
+        Some(Variant::Str(separator)) => separator.as_str(),
+        Some(_) => return Err(BfErr::Code(E_TYPE)),
+    };
+    let mut result = String::new();
+    for (i, s) in strings.iter().enumerate() {
+        let Variant::Str(s) = s.variant() else {
+            return Err(BfErr::Code(E_TYPE));
+        };
+        if i > 0 {
+            result.push_str(separator);
+        }
+        result.push_str(s.as_str());
+    }
+    Ok(Ret(v_string(result)))
+}
+bf_declare!(strjoin, bf_strjoin);
+
+/// The most bytes `random_bytes()` will produce in one call.
+const MAX_RANDOM_BYTES: i64 = 65536;
+
+/// Render bytes as a MOO binary string: printable ASCII other than `~` stands for itself, and
+/// every other byte is written `~XX`, in hex.
+fn encode_binary(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len());
+    for b in bytes {
+        if (b' '..=b'~').contains(b) && *b != b'~' {
+            result.push(*b as char);
+        } else {
+            result.push_str(&format!("~{b:02X}"));
+        }
+    }
+    result
+}
+
+/*
+str random_bytes (int count)
+
+Returns count bytes from the operating system's secure random source, as a binary string. Unlike
+random(), the result is never reproducible, so it's suitable for tokens and salts. At most 65536
+bytes may be asked for; E_INVARG otherwise.
+*/
+fn bf_random_bytes(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 1 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let Variant::Int(count) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    if !(0..=MAX_RANDOM_BYTES).contains(count) {
+        return Err(BfErr::Code(E_INVARG));
+    }
+    let mut bytes = vec![0u8; *count as usize];
+    OsRng.fill_bytes(&mut bytes);
+    Ok(Ret(v_string(encode_binary(&bytes))))
+}
+bf_declare!(random_bytes, bf_random_bytes);
+
 fn bf_binary_hash(_bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     unimplemented!("binary_hash")
 }
@@ -229,12 +458,18 @@ impl VM {
         self.builtins[offset_for_builtin("crypt")] = Arc::new(BfCrypt {});
         self.builtins[offset_for_builtin("string_hash")] = Arc::new(BfStringHash {});
         self.builtins[offset_for_builtin("binary_hash")] = Arc::new(BfBinaryHash {});
+        self.builtins[offset_for_builtin("string_hmac")] = Arc::new(BfStringHmac {});
+        self.builtins[offset_for_builtin("explode")] = Arc::new(BfExplode {});
+        self.builtins[offset_for_builtin("strjoin")] = Arc::new(BfStrjoin {});
+        self.builtins[offset_for_builtin("random_bytes")] = Arc::new(BfRandomBytes {});
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::builtins::bf_strings::strsub;
+    use moor_values::var::StringSemantics::{Bytes, CodePoints};
+
+    use crate::builtins::bf_strings::{crypt, explode, hmac_hex, str_index, str_rindex, strsub};
 
     #[test]
     fn test_strsub_remove_piece() {
@@ -286,4 +521,117 @@ mod tests {
         let expected = "foo bar baz";
         assert_eq!(strsub(subject, "fizz", "buzz", false), expected);
     }
+
+    #[test]
+    fn test_crypt_legacy_round_trip() {
+        let hashed = crypt("foobar", "J3").unwrap();
+        assert!(hashed.starts_with("J3"));
+        assert_eq!(crypt("foobar", &hashed).unwrap(), hashed);
+        assert_ne!(crypt("mumble", &hashed).unwrap(), hashed);
+    }
+
+    #[test]
+    fn test_crypt_bcrypt_round_trip() {
+        // Low cost, to keep the test quick.
+        let hashed = crypt("foobar", "$2b$04$").unwrap();
+        assert!(hashed.starts_with("$2b$04$"));
+        assert_eq!(crypt("foobar", &hashed).unwrap(), hashed);
+        assert_ne!(crypt("mumble", &hashed).unwrap(), hashed);
+
+        // Each fresh hash gets its own salt.
+        assert_ne!(crypt("foobar", "$2b$04$").unwrap(), hashed);
+
+        // The other prefixes, and the default cost.
+        assert!(crypt("foobar", "$2y$04").unwrap().starts_with("$2y$04$"));
+        assert!(crypt("foobar", "$2a$").unwrap().starts_with("$2a$"));
+    }
+
+    #[test]
+    fn test_crypt_bcrypt_cost_cap() {
+        assert_eq!(crypt("foobar", "$2b$31$"), None);
+        assert_eq!(crypt("foobar", "$2y$15"), None);
+        // A stored hash carries its own cost, and gets the same cap.
+        let hashed = crypt("foobar", "$2b$04$").unwrap();
+        assert_eq!(crypt("foobar", &hashed.replacen("$04$", "$31$", 1)), None);
+    }
+
+    // Test cases 1 and 2 from RFC 4231.
+    #[test]
+    fn test_hmac_rfc4231() {
+        let key = [0x0b; 20];
+        assert_eq!(
+            hmac_hex("sha256", &key, b"Hi There").unwrap(),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            hmac_hex("sha512", &key, b"Hi There").unwrap(),
+            "87aa7cdea5ef619d4ff0b4241a1d6cb02379f4e2ce4ec2787ad0b30545e17cde\
+             daa833b7d6b8a702038b274eaea3f4e4be9d914eeb61f1702e696c203a126854"
+        );
+
+        let data = b"what do ya want for nothing?";
+        assert_eq!(
+            hmac_hex("sha256", b"Jefe", data).unwrap(),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hmac_hex("SHA512", b"Jefe", data).unwrap(),
+            "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+             9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737"
+        );
+    }
+
+    #[test]
+    fn test_hmac_unknown_algorithm() {
+        assert_eq!(hmac_hex("md4", b"Jefe", b"data"), None);
+    }
+
+    #[test]
+    fn test_index_multibyte() {
+        // Offsets count characters, not bytes, and case folding works beyond ASCII.
+        assert_eq!(str_index(CodePoints, "café au lait", "au", false), 6);
+        assert_eq!(str_index(CodePoints, "CAFÉ", "é", false), 4);
+        assert_eq!(str_index(CodePoints, "CAFÉ", "é", true), 0);
+        assert_eq!(str_rindex(CodePoints, "éaéa", "a", false), 4);
+        assert_eq!(str_rindex(CodePoints, "éaéa", "é", false), 3);
+        assert_eq!(str_index(CodePoints, "éaéa", "", false), 1);
+        assert_eq!(str_rindex(CodePoints, "éaéa", "", false), 5);
+        assert_eq!(str_index(Bytes, "café au lait", "au", false), 7);
+    }
+
+    #[test]
+    fn test_strsub_multibyte() {
+        assert_eq!(strsub("café café", "É", "e", false), "cafe cafe");
+        assert_eq!(strsub("café café", "É", "e", true), "café café");
+        assert_eq!(strsub("naïve", "ï", "ii", false), "naiive");
+    }
+
+    #[test]
+    fn test_explode() {
+        assert_eq!(
+            explode("  foo bar  baz ", " ", false),
+            vec!["foo", "bar", "baz"]
+        );
+        assert_eq!(
+            explode(" foo  bar ", " ", true),
+            vec!["", "foo", "", "bar", ""]
+        );
+        assert_eq!(explode("a, b,, c", ", ", false), vec!["a", "b,", "c"]);
+        assert_eq!(
+            explode("::a::::b::", "::", true),
+            vec!["", "a", "", "b", ""]
+        );
+        assert!(explode("", " ", false).is_empty());
+    }
+
+    #[test]
+    fn test_encode_binary() {
+        assert_eq!(encode_binary(b"foo~bar\r\n"), "foo~7Ebar~0D~0A");
+        // Every byte value comes out as printable ASCII, one or three characters long.
+        for b in 0..=255u8 {
+            let encoded = encode_binary(&[b]);
+            assert!(encoded.bytes().all(|c| (b' '..=b'~').contains(&c)));
+            assert!(encoded.len() == 1 || (encoded.len() == 3 && encoded.starts_with('~')));
+        }
+    }
 }
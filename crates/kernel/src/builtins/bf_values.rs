@@ -16,10 +16,10 @@ use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
-use moor_compiler::offset_for_builtin;
+use moor_compiler::{offset_for_builtin, parse_literal};
 use moor_values::var::Error::{E_ARGS, E_INVARG, E_TYPE};
 use moor_values::var::Variant;
-use moor_values::var::{v_bool, v_float, v_int, v_obj, v_str};
+use moor_values::var::{v_bool, v_float, v_int, v_list, v_obj, v_str};
 use moor_values::AsByteBuffer;
 
 use crate::bf_declare;
@@ -59,6 +59,24 @@ fn bf_toliteral(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
 }
 bf_declare!(toliteral, bf_toliteral);
 
+/*
+value fromliteral (str literal)
+
+The inverse of toliteral(): parses a literal value without evaluating anything, raising E_INVARG
+if it's not a literal (variables, operators, calls, etc.).
+*/
+fn bf_fromliteral(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
+    if bf_args.args.len() != 1 {
+        return Err(BfErr::Code(E_ARGS));
+    }
+    let Variant::Str(literal) = bf_args.args[0].variant() else {
+        return Err(BfErr::Code(E_TYPE));
+    };
+    let value = parse_literal(literal.as_str()).map_err(|_| BfErr::Code(E_INVARG))?;
+    Ok(Ret(value))
+}
+bf_declare!(fromliteral, bf_fromliteral);
+
 fn bf_toint(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     if bf_args.args.len() != 1 {
         return Err(BfErr::Code(E_ARGS));
@@ -163,15 +181,23 @@ fn bf_length(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     }
 
     match bf_args.args[0].variant() {
-        Variant::Str(s) => Ok(Ret(v_int(s.len() as i64))),
+        Variant::Str(s) => Ok(Ret(v_int(
+            s.len_in(bf_args.exec_state.config.string_semantics()) as i64,
+        ))),
         Variant::List(l) => Ok(Ret(v_int(l.len() as i64))),
         _ => Err(BfErr::Code(E_TYPE)),
     }
 }
 bf_declare!(length, bf_length);
 
+/*
+int | list object_bytes (obj object [, detailed])
+
+The number of bytes the object takes up in the database. If `detailed` is true, that's instead
+broken down as {{"verbs", n}, {"props", n}, {"programs", n}, {"overhead", n}}.
+*/
 fn bf_object_bytes(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
-    if bf_args.args.len() != 1 {
+    if bf_args.args.is_empty() || bf_args.args.len() > 2 {
         return Err(BfErr::Code(E_ARGS));
     }
     let Variant::Obj(o) = bf_args.args[0].variant() else {
@@ -180,11 +206,28 @@ fn bf_object_bytes(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
     if !bf_args.world_state.valid(*o).map_err(world_state_bf_err)? {
         return Err(BfErr::Code(E_INVARG));
     };
-    let size = bf_args
+    let detailed = bf_args.args.get(1).map(|a| a.is_true()).unwrap_or(false);
+    if !detailed {
+        let size = bf_args
+            .world_state
+            .object_bytes(bf_args.caller_perms(), *o)
+            .map_err(world_state_bf_err)?;
+        return Ok(Ret(v_int(size as i64)));
+    }
+    let sizes = bf_args
         .world_state
-        .object_bytes(bf_args.caller_perms(), *o)
+        .object_bytes_detailed(bf_args.caller_perms(), *o)
         .map_err(world_state_bf_err)?;
-    Ok(Ret(v_int(size as i64)))
+    let breakdown = [
+        ("verbs", sizes.verbs),
+        ("props", sizes.props),
+        ("programs", sizes.programs),
+        ("overhead", sizes.overhead),
+    ]
+    .into_iter()
+    .map(|(category, size)| v_list(&[v_str(category), v_int(size as i64)]))
+    .collect::<Vec<_>>();
+    Ok(Ret(v_list(&breakdown)))
 }
 bf_declare!(object_bytes, bf_object_bytes);
 
@@ -203,5 +246,6 @@ impl VM {
         self.builtins[offset_for_builtin("object_bytes")] = Arc::new(BfObjectBytes {});
         self.builtins[offset_for_builtin("value_hash")] = Arc::new(BfValueHash {});
         self.builtins[offset_for_builtin("length")] = Arc::new(BfLength {});
+        self.builtins[offset_for_builtin("fromliteral")] = Arc::new(BfFromliteral {});
     }
 }
@@ -14,19 +14,18 @@
 
 use std::sync::Arc;
 
-use strum::EnumCount;
 use tracing::{error, warn};
 
 use moor_compiler::compile;
+use moor_compiler::disassemble;
 use moor_compiler::offset_for_builtin;
 use moor_compiler::program_to_tree;
 use moor_compiler::unparse;
-use moor_compiler::GlobalName;
 use moor_compiler::Program;
 use moor_values::model::ObjFlag;
 use moor_values::model::VerbDef;
 use moor_values::model::WorldStateError;
-use moor_values::model::{ArgSpec, VerbArgsSpec};
+use moor_values::model::{parse_verb_args_spec, VerbArgsSpec};
 use moor_values::model::{BinaryType, VerbAttrs, VerbFlag};
 use moor_values::model::{HasUuid, Named};
 use moor_values::util::BitEnum;
@@ -41,7 +40,7 @@ use moor_values::AsByteBuffer;
 use crate::bf_declare;
 use crate::builtins::BfRet::Ret;
 use crate::builtins::{world_state_bf_err, BfCallState, BfErr, BfRet, BuiltinFunction};
-use crate::tasks::command_parse::{parse_preposition_spec, preposition_to_string};
+use crate::tasks::command_parse::preposition_to_string;
 use crate::vm::VM;
 
 // verb_info (obj <object>, str <verb-desc>) ->  {<owner>, <perms>, <names>}
@@ -268,23 +267,14 @@ fn parse_verb_args(verbinfo: &List) -> Result<VerbArgsSpec, Error> {
     if verbinfo.len() != 3 {
         return Err(E_ARGS);
     }
-    match (
-        verbinfo.get(0).unwrap().variant(),
-        verbinfo.get(1).unwrap().variant(),
-        verbinfo.get(2).unwrap().variant(),
-    ) {
-        (Variant::Str(dobj_str), Variant::Str(prep_str), Variant::Str(iobj_str)) => {
-            let (Some(dobj), Some(prep), Some(iobj)) = (
-                ArgSpec::from_string(dobj_str.as_str()),
-                parse_preposition_spec(prep_str.as_str()),
-                ArgSpec::from_string(iobj_str.as_str()),
-            ) else {
-                return Err(E_INVARG);
-            };
-            Ok(VerbArgsSpec { dobj, prep, iobj })
-        }
-        _ => Err(E_INVARG),
+    let mut spec = Vec::with_capacity(verbinfo.len());
+    for item in verbinfo.iter() {
+        let Variant::Str(s) = item.variant() else {
+            return Err(E_INVARG);
+        };
+        spec.push(s.as_str().to_string());
     }
+    parse_verb_args_spec(&spec)
 }
 
 // set_verb_args (obj <object>, str <verb-desc>, list <args>) => none
@@ -648,51 +638,50 @@ fn bf_disassemble(bf_args: &mut BfCallState<'_>) -> Result<BfRet, BfErr> {
         BfErr::Code(E_INVARG)
     })?;
 
-    // The output of disassemble is a list of strings, one for each instruction in the verb's program.
-    // But we also want some basic information on # of labels. Fork vectors. Jazz like that.
-    // But I'll just keep it simple for now.
-
+    // The output of disassemble is a list of strings: a section each for the literals, labels,
+    // and variables, then one line for each instruction in the verb's program, and its forks.
+    let program = disassemble(&program);
     let mut disassembly = Vec::new();
     // Write literals indexed by their offset #
     disassembly.push(v_str("LITERALS:"));
     for (i, l) in program.literals.iter().enumerate() {
-        disassembly.push(v_string(format!("{: >3}: {}", i, l.to_literal())));
+        disassembly.push(v_string(format!("{: >3}: {}", i, l)));
     }
 
     // Write jump labels indexed by their offset & showing position & optional name
     disassembly.push(v_str("JUMP LABELS:"));
-    for (i, l) in program.jump_labels.iter().enumerate() {
-        if i < GlobalName::COUNT {
-            continue;
-        }
-        let name_of = match &l.name {
-            Some(name) => format!(" ({})", program.var_names.name_of(name).unwrap()),
+    for (i, position, name) in &program.jump_labels {
+        let name_of = match name {
+            Some(name) => format!(" ({})", name),
             None => "".to_string(),
         };
-        disassembly.push(v_string(format!("{: >3}: {}{}", i, l.position.0, name_of)));
+        disassembly.push(v_string(format!("{: >3}: {}{}", i, position, name_of)));
     }
 
     // Write variable names indexed by their offset
     disassembly.push(v_str("VARIABLES:"));
-    for (i, v) in program.var_names.names.iter().enumerate() {
+    for (i, v) in program.variables.iter().enumerate() {
         disassembly.push(v_string(format!("{: >3}: {}", i, v)));
     }
 
-    // TODO: dump fork vectors in program disassembly stream
-
     // Display main vector (program); opcodes are indexed by their offset
     disassembly.push(v_str("OPCODES:"));
-    for (i, op) in program.main_vector.iter().enumerate() {
-        let mut line_no_string = String::new();
-        let mut last_line_no = 0;
-        for (pc, line_no) in &program.line_number_spans {
-            if *pc == i {
-                line_no_string = format!("\t\t(line {})", last_line_no);
-                break;
-            }
-            last_line_no = *line_no;
+    for op in &program.main_vector {
+        let line_no_string = match op.line {
+            Some(line_no) => format!("\t\t(line {})", line_no),
+            None => String::new(),
+        };
+        disassembly.push(v_string(format!(
+            "{: >3}: {}{}",
+            op.offset, op, line_no_string
+        )));
+    }
+
+    for (i, fork_vector) in program.fork_vectors.iter().enumerate() {
+        disassembly.push(v_string(format!("FORK VECTOR {}:", i)));
+        for op in fork_vector {
+            disassembly.push(v_string(format!("{: >3}: {}", op.offset, op)));
         }
-        disassembly.push(v_string(format!("{: >3}: {:?}{}", i, op, line_no_string)));
     }
 
     Ok(Ret(v_listv(disassembly)))
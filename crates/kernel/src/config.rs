@@ -16,8 +16,103 @@
 //! available to all components. Used to hold things typically configured by CLI flags, etc.
 
 use std::path::PathBuf;
+use std::time::Duration;
+
+use moor_values::var::StringSemantics;
 
 #[derive(Debug, Default)]
 pub struct Config {
     pub textdump_output: Option<PathBuf>,
+    /// The directory `export_database()` writes its files into, by name. If unset, exports are
+    /// refused.
+    pub export_directory: Option<PathBuf>,
+    /// If set, tasks sample where their ticks are spent every this many ticks, and report the
+    /// heaviest consumers when aborted for exceeding their tick limit.
+    pub tick_profile_interval: Option<usize>,
+    /// Measure and index strings by byte rather than by code point, for legacy cores which
+    /// assumed bytes. See `string_semantics()`.
+    pub byte_string_semantics: bool,
+    /// If set, every task's `random()` stream starts from this seed, for reproducible runs.
+    pub random_seed: Option<u64>,
+    /// If set, programming a verb with code which compiles to exactly what it already has is a
+    /// no-op, rather than a write.
+    pub skip_unchanged_verb_programs: bool,
+    /// If set, `move()` just relocates the object, without consulting the destination's
+    /// `:accept` or calling `:exitfunc`/`:enterfunc`.
+    pub raw_moves: bool,
+    /// How many events the daemon queues on its event channel for a client which isn't reading
+    /// them (the ZMQ send high-water mark) before it starts dropping them, telling the client once
+    /// it catches up. 10,000 if unset: a few screens of output in a busy room.
+    pub narrative_hwm: Option<i32>,
+    /// If set, at most this many tasks are started while that many are executing; any more wait
+    /// their turn, first come first served. Tasks which are suspended, waiting on `read()`, or
+    /// sleeping out a fork delay don't count. This only limits starting tasks: ones resuming
+    /// (from `suspend()`, `read()` or the debugger) aren't held back, so while they run more than
+    /// this many may be executing at once.
+    pub max_concurrent_tasks: Option<usize>,
+    /// How long a task may sit paused for the debugger (holding its transaction open) before it's
+    /// aborted. Five minutes if unset.
+    pub debug_pause_timeout: Option<Duration>,
+    /// If set, the daemon drops connections which have been idle for longer than this, unless
+    /// they've been marked `idle-exempt` with `set_connection_option()`.
+    pub idle_timeout: Option<Duration>,
+    /// How many ticks background (forked, resumed) tasks run before giving way to any running
+    /// foreground tasks. 1,000 if unset.
+    pub background_tick_slice: Option<usize>,
+    /// The longest a background task waits for foreground tasks to finish before running its next
+    /// slice anyway. 50ms if unset.
+    pub max_background_wait: Option<Duration>,
+    /// If set, count invocations of, and time spent in, each verb, for `RequestPerformanceCounters`.
+    /// Off by default, since it costs a little on every verb call.
+    pub verb_metrics: bool,
+}
+
+impl Config {
+    /// The names of the optional behaviours switched on above, as listed by `server_features()`.
+    pub fn features(&self) -> Vec<&'static str> {
+        let mut features = vec![];
+        if self.byte_string_semantics {
+            features.push("byte_string_semantics");
+        }
+        if self.skip_unchanged_verb_programs {
+            features.push("skip_unchanged_verb_programs");
+        }
+        if self.raw_moves {
+            features.push("raw_moves");
+        }
+        features
+    }
+
+    /// How this server's strings are measured and indexed.
+    pub fn string_semantics(&self) -> StringSemantics {
+        if self.byte_string_semantics {
+            StringSemantics::Bytes
+        } else {
+            StringSemantics::CodePoints
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+
+    #[test]
+    fn test_features() {
+        assert!(Config::default().features().is_empty());
+        let config = Config {
+            byte_string_semantics: true,
+            skip_unchanged_verb_programs: true,
+            raw_moves: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.features(),
+            vec![
+                "byte_string_semantics",
+                "skip_unchanged_verb_programs",
+                "raw_moves"
+            ]
+        );
+    }
 }
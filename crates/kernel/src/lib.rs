@@ -18,3 +18,16 @@ pub mod matching;
 pub mod tasks;
 pub mod textdump;
 pub mod vm;
+
+/// The server version, as reported by `server_version()` and to RPC clients.
+pub const SERVER_VERSION: &str = "0.0.1";
+
+/// The version of each of the server's components, so cores and clients can tell what they're
+/// talking to: as returned by `server_version(1)`, and to RPC clients in their server info.
+pub fn component_versions() -> Vec<(&'static str, String)> {
+    vec![
+        ("kernel", SERVER_VERSION.to_string()),
+        ("db", moor_values::DATA_LAYOUT_VERSION.to_string()),
+        ("protocol", rpc_common::RPC_PROTOCOL_VERSION.to_string()),
+    ]
+}
@@ -16,7 +16,7 @@ use moor_values::model::ObjSet;
 
 use moor_values::model::WorldState;
 use moor_values::model::WorldStateError;
-use moor_values::var::Objid;
+use moor_values::var::{Objid, Variant};
 
 use crate::matching::match_env::MatchEnvironment;
 
@@ -32,9 +32,22 @@ impl<'a> MatchEnvironment for WsMatchEnv<'a> {
     }
 
     fn get_names(&self, oid: Objid) -> Result<Vec<String>, WorldStateError> {
-        let mut names = self.ws.names_of(self.perms, oid)?;
-        let mut object_names = vec![names.0];
-        object_names.append(&mut names.1);
+        // Matching looks at the names of everything around the player, so fetch the name and
+        // aliases of each in one go, rather than walking the inheritance chain for each.
+        let properties = self
+            .ws
+            .get_properties(self.perms, oid, &["name", "aliases"])?;
+        let mut object_names = vec![];
+        if let Some(Variant::Str(name)) = properties.get("name").map(|n| n.variant()) {
+            object_names.push(name.as_str().to_string());
+        }
+        if let Some(Variant::List(aliases)) = properties.get("aliases").map(|a| a.variant()) {
+            for alias in aliases.iter() {
+                if let Variant::Str(alias) = alias.variant() {
+                    object_names.push(alias.as_str().to_string());
+                }
+            }
+        }
         Ok(object_names)
     }
 
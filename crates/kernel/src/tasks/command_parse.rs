@@ -38,26 +38,6 @@ pub struct ParsedCommand {
     pub iobj: Option<Objid>,
 }
 
-/// Match a preposition for the form used by set_verb_args and friends, which means it must support
-/// numeric arguments for the preposition.
-pub fn parse_preposition_spec(repr: &str) -> Option<PrepSpec> {
-    match repr {
-        "any" => Some(PrepSpec::Any),
-        "none" => Some(PrepSpec::None),
-        _ => find_preposition(repr).map(|p| PrepSpec::Other(p)),
-    }
-}
-
-pub fn find_preposition(prep: &str) -> Option<Preposition> {
-    // If the string starts with a number (with or without # prefix), treat it as a preposition ID.
-    let numeric_offset = if prep.starts_with('#') { 1 } else { 0 };
-    if let Ok(id) = prep[numeric_offset..].parse::<u16>() {
-        return Preposition::from_repr(id);
-    }
-
-    Preposition::parse(prep)
-}
-
 pub fn preposition_to_string(ps: &PrepSpec) -> &str {
     match ps {
         PrepSpec::Any => "any",
@@ -0,0 +1,110 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Compiled programs, keyed by a hash of their source, so that saving the same verb source over
+//! and over (as editors tend to) doesn't mean compiling it over and over.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+use moor_compiler::{compile, CompileError, Program};
+
+/// Past this many entries, the cache is emptied and starts over.
+const MAX_ENTRIES: usize = 256;
+
+/// How well the cache is doing.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct CompileCacheStats {
+    pub hits: usize,
+    /// Each of which meant an actual compile.
+    pub misses: usize,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// The source is kept alongside the program to rule out hash collisions.
+    entries: HashMap<u64, (String, Program)>,
+    stats: CompileCacheStats,
+}
+
+/// Safe to share; the lock is only held for lookups and inserts, never while compiling, so one
+/// slow compile doesn't hold up everyone else's.
+#[derive(Default)]
+pub struct CompileCache {
+    inner: Mutex<Inner>,
+}
+
+impl CompileCache {
+    /// Compile `source`, or return the program it compiled to last time.
+    pub fn compile(&self, source: &str) -> Result<Program, CompileError> {
+        let mut hasher = DefaultHasher::new();
+        source.hash(&mut hasher);
+        let key = hasher.finish();
+
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if let Some((cached_source, program)) = inner.entries.get(&key) {
+                if cached_source == source {
+                    let program = program.clone();
+                    inner.stats.hits += 1;
+                    return Ok(program);
+                }
+            }
+            inner.stats.misses += 1;
+        }
+
+        // Two callers compiling the same new source at once will both compile it; that's no worse
+        // than having no cache at all.
+        let program = compile(source)?;
+        let mut inner = self.inner.lock().unwrap();
+        if inner.entries.len() >= MAX_ENTRIES {
+            inner.entries.clear();
+        }
+        inner
+            .entries
+            .insert(key, (source.to_string(), program.clone()));
+        Ok(program)
+    }
+
+    pub fn stats(&self) -> CompileCacheStats {
+        self.inner.lock().unwrap().stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tasks::compile_cache::{CompileCache, CompileCacheStats};
+
+    #[test]
+    fn test_identical_source_compiles_once() {
+        let cache = CompileCache::default();
+        let first = cache.compile("return 1 + 2;").unwrap();
+        let second = cache.compile("return 1 + 2;").unwrap();
+        assert_eq!(first, second);
+        assert_eq!(cache.stats(), CompileCacheStats { hits: 1, misses: 1 });
+
+        cache.compile("return 3;").unwrap();
+        assert_eq!(cache.stats(), CompileCacheStats { hits: 1, misses: 2 });
+    }
+
+    #[test]
+    fn test_failures_not_cached() {
+        let cache = CompileCache::default();
+        assert!(cache.compile("return 1 +;").is_err());
+        assert!(cache.compile("return 1 +;").is_err());
+        assert_eq!(cache.stats(), CompileCacheStats { hits: 0, misses: 2 });
+    }
+}
@@ -13,14 +13,18 @@
 //
 
 use crate::tasks::scheduler::TaskResult;
-use moor_values::var::{List, Objid};
+use moor_values::model::WorldState;
+use moor_values::var::{List, Objid, Var};
 use std::cell::Cell;
 use std::marker::PhantomData;
-use std::sync::MutexGuard;
+use std::sync::{Arc, MutexGuard};
 use std::time::SystemTime;
 
 pub mod command_parse;
+pub mod compile_cache;
+mod priority;
 pub mod scheduler;
+pub mod server_options;
 pub mod sessions;
 
 mod task;
@@ -29,6 +33,11 @@ pub mod vm_host;
 
 pub type TaskId = usize;
 
+/// Called with a task's own transaction and result just before the task commits successfully, so
+/// that whoever submitted it can read the world as the task left it. If the commit conflicts and
+/// the task is retried, this is called again for the retry.
+pub type CommitHook = Arc<dyn Fn(&dyn WorldState, &Var) + Send + Sync>;
+
 /// Just a handle to a task, with a receiver for the result.
 pub struct TaskHandle(TaskId, oneshot::Receiver<TaskResult>);
 impl TaskHandle {
@@ -130,9 +139,12 @@ pub mod vm_test_utils {
                 VMHostResponse::Suspend(_) => {
                     panic!("Unexpected suspend");
                 }
-                VMHostResponse::SuspendNeedInput => {
+                VMHostResponse::SuspendNeedInput(_) => {
                     panic!("Unexpected suspend need input");
                 }
+                VMHostResponse::DebugPause(_) => {
+                    panic!("Unexpected debug pause");
+                }
                 VMHostResponse::RollbackRetry => {
                     panic!("Unexpected rollback retry");
                 }
@@ -183,6 +195,7 @@ pub mod scheduler_test_utils {
     use crate::tasks::sessions::Session;
     use crate::vm::UncaughtException;
     use moor_values::model::CommandError;
+    use moor_values::util::parse_into_words;
     use moor_values::var::{Error::E_VERBNF, Objid, Var};
     use std::sync::Arc;
     use std::time::Duration;
@@ -241,4 +254,14 @@ pub mod scheduler_test_utils {
     ) -> Result<Var, SchedulerError> {
         execute(|| scheduler.submit_eval_task(player, player, code, session))
     }
+
+    pub fn call_out_of_band(
+        scheduler: Arc<Scheduler>,
+        session: Arc<dyn Session>,
+        player: Objid,
+        command: &str,
+    ) -> Result<Var, SchedulerError> {
+        let words = parse_into_words(command);
+        execute(|| scheduler.submit_out_of_band_task(player, words, command.to_string(), session))
+    }
 }
@@ -0,0 +1,90 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Two tiers of task priority, so that interactive work isn't starved by background work.
+//!
+//! Foreground tasks are those run on behalf of a client: commands, evals, and verbs invoked over
+//! RPC. Background tasks are forked ones, and any task which has resumed after `suspend()`ing.
+//! Background tasks run in short tick slices, and between slices give way for as long as any
+//! foreground task is running -- though never for longer than the maximum background wait, so
+//! that a steady stream of commands can't hold them off forever. Both the slice length and the
+//! wait are configurable (see `Config`).
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// How many ticks a background task runs before checking whether it should give way, by default.
+pub(crate) const DEFAULT_BACKGROUND_TICK_SLICE: usize = 1_000;
+
+/// The longest a background task waits on foreground tasks before running its next slice anyway,
+/// by default.
+pub(crate) const DEFAULT_MAX_BACKGROUND_WAIT: Duration = Duration::from_millis(50);
+
+/// The count of foreground tasks currently running, shared between the scheduler and its tasks,
+/// and the policy background tasks follow in giving way to them.
+#[derive(Clone)]
+pub(crate) struct ForegroundTasks {
+    running: Arc<(Mutex<usize>, Condvar)>,
+    background_tick_slice: usize,
+    max_background_wait: Duration,
+}
+
+impl Default for ForegroundTasks {
+    fn default() -> Self {
+        Self::new(DEFAULT_BACKGROUND_TICK_SLICE, DEFAULT_MAX_BACKGROUND_WAIT)
+    }
+}
+
+impl ForegroundTasks {
+    pub(crate) fn new(background_tick_slice: usize, max_background_wait: Duration) -> Self {
+        Self {
+            running: Default::default(),
+            // A task has to get some work done each slice.
+            background_tick_slice: background_tick_slice.max(1),
+            max_background_wait,
+        }
+    }
+
+    /// How many ticks a background task runs between giving way.
+    pub(crate) fn background_tick_slice(&self) -> usize {
+        self.background_tick_slice
+    }
+
+    /// Mark a foreground task as running, until the returned guard is dropped.
+    pub(crate) fn enter(&self) -> ForegroundGuard {
+        let (count, _) = &*self.running;
+        *count.lock().unwrap() += 1;
+        ForegroundGuard(self.clone())
+    }
+
+    /// Block while foreground tasks are running, for up to the maximum background wait.
+    pub(crate) fn yield_to_foreground(&self) {
+        let (count, cvar) = &*self.running;
+        let count = count.lock().unwrap();
+        let _ = cvar
+            .wait_timeout_while(count, self.max_background_wait, |count| *count > 0)
+            .unwrap();
+    }
+}
+
+/// Held by a foreground task for as long as it is running (i.e. not suspended or finished).
+pub(crate) struct ForegroundGuard(ForegroundTasks);
+
+impl Drop for ForegroundGuard {
+    fn drop(&mut self) {
+        let (count, cvar) = &*self.0.running;
+        *count.lock().unwrap() -= 1;
+        cvar.notify_all();
+    }
+}
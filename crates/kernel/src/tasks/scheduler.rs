@@ -12,8 +12,8 @@
 // this program. If not, see <https://www.gnu.org/licenses/>.
 //
 
-use std::collections::HashMap;
-use std::fs::File;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
@@ -22,7 +22,7 @@ use bincode::{Decode, Encode};
 use crossbeam_channel::Sender;
 
 use thiserror::Error;
-use tracing::{error, info, instrument, trace, warn};
+use tracing::{debug, error, info, instrument, trace, warn};
 use uuid::Uuid;
 
 use crossbeam_channel::internal::SelectHandle;
@@ -31,13 +31,16 @@ use std::sync::Mutex;
 use std::thread::yield_now;
 
 use moor_compiler::compile;
+use moor_compiler::disassemble;
 use moor_compiler::CompileError;
+use moor_compiler::Program;
 use moor_db::Database;
-use moor_values::model::{BinaryType, CommandError, HasUuid, VerbAttrs};
-use moor_values::model::{CommitResult, Perms};
+use moor_values::model::{BinaryType, CommandError, HasUuid, ObjFlag, VerbAttrs};
+use moor_values::model::{Breakpoint, PausedActivation};
+use moor_values::model::{CommitResult, Disassembly, Perms, WorldStateError};
 use moor_values::model::{VerbProgramError, WorldStateSource};
 use moor_values::var::Error::{E_INVARG, E_PERM};
-use moor_values::var::{v_err, v_int, v_none, v_string, List, Var};
+use moor_values::var::{v_bool, v_err, v_int, v_none, v_string, List, Var};
 use moor_values::var::{Objid, Variant};
 use moor_values::{AsByteBuffer, SYSTEM_OBJECT};
 use SchedulerError::{
@@ -49,20 +52,29 @@ use crate::config::Config;
 use crate::matching::match_env::MatchEnvironmentParseMatcher;
 use crate::matching::ws_match_env::WsMatchEnv;
 use crate::tasks::command_parse::ParseMatcher;
+use crate::tasks::compile_cache::{CompileCache, CompileCacheStats};
+use crate::tasks::priority::{
+    ForegroundTasks, DEFAULT_BACKGROUND_TICK_SLICE, DEFAULT_MAX_BACKGROUND_WAIT,
+};
 use crate::tasks::scheduler::SchedulerError::{TaskNotFound, VerbProgramFailed};
+use crate::tasks::server_options::ServerOptions;
 use crate::tasks::sessions::Session;
 use crate::tasks::task::Task;
 use crate::tasks::task_messages::{SchedulerControlMsg, TaskControlMsg, TaskStart};
-use crate::tasks::{TaskDescription, TaskHandle, TaskId};
-use crate::textdump::{make_textdump, TextdumpWriter};
-use crate::vm::Fork;
+use crate::tasks::{CommitHook, TaskDescription, TaskHandle, TaskId};
+use crate::textdump::{export_database, ExportFormat};
 use crate::vm::UncaughtException;
+use crate::vm::{set_verb_metrics, Fork};
 
 const SCHEDULER_TICK_TIME: Duration = Duration::from_millis(5);
 
 /// Number of times to retry a program compilation transaction in case of conflict, before giving up.
 const NUM_VERB_PROGRAM_ATTEMPTS: usize = 5;
 
+/// How long a task may sit paused for the debugger, holding its transaction open, before it's
+/// aborted, unless the config says otherwise.
+const DEFAULT_DEBUG_PAUSE_TIMEOUT: Duration = Duration::from_secs(300);
+
 /// Responsible for the dispatching, control, and accounting of tasks in the system.
 /// There should be only one scheduler per server.
 pub struct Scheduler {
@@ -73,8 +85,28 @@ pub struct Scheduler {
     running: Arc<AtomicBool>,
     database: Arc<dyn Database + Send + Sync>,
     next_task_id: AtomicUsize,
+    /// Counts suspensions, to give each suspended task its place in the resume order.
+    next_suspend_order: AtomicUsize,
     tasks: Mutex<HashMap<TaskId, TaskControl>>,
-    input_requests: Mutex<HashMap<Uuid, TaskId>>,
+    /// Limits for new tasks, as last loaded from `$server_options`.
+    server_options: Mutex<ServerOptions>,
+    /// Outstanding `read()` requests, and the task and player they're waiting on.
+    input_requests: Mutex<HashMap<Uuid, (TaskId, Objid)>>,
+    /// Programs compiled for `program_verb` and `check_program`, by source.
+    compile_cache: CompileCache,
+    /// Where tasks started from now on pause for the debugger, by the wizard who set them. Only
+    /// tasks with that wizard's permissions stop at them.
+    breakpoints: Mutex<HashMap<Objid, Vec<Breakpoint>>>,
+    /// The foreground tasks currently running, which background tasks give way to.
+    foreground: ForegroundTasks,
+    /// Tasks waiting for a free slot, when `max_concurrent_tasks` is set, oldest first.
+    task_queue: Mutex<VecDeque<QueuedTask>>,
+}
+
+/// A task which has been submitted, but whose thread won't be spawned until there's room for it.
+struct QueuedTask {
+    task_id: TaskId,
+    spawn: Box<dyn FnOnce() + Send>,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Decode, Encode)]
@@ -113,6 +145,10 @@ pub enum SchedulerError {
     TaskAbortedCancelled,
     #[error("Unable to program verb {0}")]
     VerbProgramFailed(VerbProgramError),
+    #[error("Task is not paused for the debugger: {0:?}")]
+    TaskNotPaused(TaskId),
+    #[error("No such variable: {0}")]
+    VariableNotFound(String),
 }
 
 /// Scheduler-side per-task record. Lives in the scheduler thread and owned by the scheduler and
@@ -120,6 +156,8 @@ pub enum SchedulerError {
 struct TaskControl {
     task_id: TaskId,
     player: Objid,
+    /// The permissions the task was started with; its owner, for the purposes of `kill_task()`.
+    perms: Objid,
     /// Outbound mailbox for messages from the scheduler to the task.
     task_control_sender: Sender<TaskControlMsg>,
     state_source: Arc<dyn WorldStateSource>,
@@ -127,10 +165,40 @@ struct TaskControl {
     suspended: bool,
     waiting_input: Option<Uuid>,
     resume_time: Option<SystemTime>,
+    /// Where this task falls among the suspended tasks, by when it (last) suspended. Tasks which
+    /// come due in the same scheduler tick are resumed in this order, oldest first, and
+    /// `queued_tasks()` lists them in it.
+    suspend_order: usize,
+    /// If the task is paused for the debugger, where.
+    debug_paused: Option<PausedActivation>,
+    /// If the task is paused for the debugger, when it's aborted if nobody resumes it.
+    debug_pause_deadline: Option<SystemTime>,
+    /// Whether the task's thread has been spawned, or it's still waiting in the task queue.
+    started: bool,
+    /// For a delayed fork, when the delay is up. Until then its thread is just sleeping.
+    start_after: Option<SystemTime>,
+    /// The task which forked this one, if it was forked, and hasn't since been killed by it
+    /// through `kill_forks()`.
+    parent: Option<TaskId>,
     // TODO: find a way for this not to be in a mutex.
     result_sender: Mutex<Option<oneshot::Sender<TaskResult>>>,
 }
 
+impl TaskControl {
+    /// Whether the task is actually executing, and so holding one of the `max_concurrent_tasks`
+    /// slots. Tasks which are suspended, waiting on `read()`, paused for the debugger, or still
+    /// sleeping out a fork delay aren't.
+    fn is_executing(&self) -> bool {
+        self.started
+            && !self.suspended
+            && self.waiting_input.is_none()
+            && self.debug_paused.is_none()
+            && !self
+                .start_after
+                .is_some_and(|start_after| start_after > SystemTime::now())
+    }
+}
+
 /// The set of actions that the scheduler needs to take in response to a task control message.
 enum TaskHandleResult {
     /// The final result of a task, to be sent back to the task's subscriber if there is one.
@@ -144,6 +212,10 @@ enum TaskHandleResult {
     },
     /// A request for a description of another task.
     Describe(TaskId, oneshot::Sender<Vec<TaskDescription>>),
+    /// A request for the ids of the tasks a task has forked.
+    ForkedTasks(TaskId, oneshot::Sender<Vec<TaskId>>),
+    /// A request to abort all the tasks a task has forked.
+    KillForks(TaskId, oneshot::Sender<usize>),
     Kill {
         requesting_task_id: TaskId,
         victim_task_id: TaskId,
@@ -157,21 +229,35 @@ enum TaskHandleResult {
         return_value: Var,
         result_sender: oneshot::Sender<Var>,
     },
-    Disconnect(TaskId, Objid),
+    Disconnect(TaskId, Objid, bool /* connection only */),
     Retry(TaskId),
 }
 
 /// Public facing interface for the scheduler.
 impl Scheduler {
     pub fn new(database: Arc<dyn Database + Send + Sync>, config: Config) -> Self {
+        set_verb_metrics(config.verb_metrics);
         let config = Arc::new(config);
         let (control_sender, control_receiver) = crossbeam_channel::unbounded();
         Self {
             running: Arc::new(AtomicBool::new(false)),
             database,
             next_task_id: Default::default(),
+            next_suspend_order: Default::default(),
             tasks: Default::default(),
             input_requests: Default::default(),
+            server_options: Default::default(),
+            compile_cache: Default::default(),
+            breakpoints: Default::default(),
+            foreground: ForegroundTasks::new(
+                config
+                    .background_tick_slice
+                    .unwrap_or(DEFAULT_BACKGROUND_TICK_SLICE),
+                config
+                    .max_background_wait
+                    .unwrap_or(DEFAULT_MAX_BACKGROUND_WAIT),
+            ),
+            task_queue: Default::default(),
             config,
             control_sender,
             control_receiver,
@@ -180,11 +266,17 @@ impl Scheduler {
 
     /// Execute the scheduler loop, run from the server process.
     pub fn run(self: Arc<Self>) {
+        self.reload_server_options();
         self.running.store(true, Ordering::SeqCst);
         self.clone().do_process();
         info!("Scheduler done.");
     }
 
+    /// The options last loaded from `$server_options`.
+    pub fn server_options(&self) -> ServerOptions {
+        *self.server_options.lock().unwrap()
+    }
+
     /// Submit a command to the scheduler for execution.
     #[instrument(skip(self, session))]
     pub fn submit_command_task(
@@ -208,6 +300,7 @@ impl Scheduler {
             self.control_sender.clone(),
             player,
             false,
+            None,
         )
     }
 
@@ -226,10 +319,10 @@ impl Scheduler {
         trace!(?input_request_id, ?input, "Received input for task");
 
         let mut input_requests = self.input_requests.lock().unwrap();
-        let Some(task_id) = input_requests.get(&input_request_id) else {
+        let Some((task_id, input_player)) = input_requests.get(&input_request_id) else {
             return Err(InputRequestNotFound(input_request_id.as_u128()));
         };
-        let task_id = *task_id;
+        let (task_id, input_player) = (*task_id, *input_player);
 
         let mut tasks = self.tasks.lock().unwrap();
         let Some(task) = tasks.get_mut(&task_id) else {
@@ -238,7 +331,7 @@ impl Scheduler {
         };
 
         // If the player doesn't match, we'll pretend we didn't even see it.
-        if task.player != player {
+        if input_player != player {
             warn!(
                 ?task_id,
                 ?input_request_id,
@@ -276,6 +369,26 @@ impl Scheduler {
         argstr: String,
         perms: Objid,
         session: Arc<dyn Session>,
+    ) -> Result<TaskHandle, SchedulerError> {
+        self.submit_verb_task_with_commit_hook(
+            player, vloc, verb, args, argstr, perms, session, None,
+        )
+    }
+
+    /// As `submit_verb_task`, but with a hook run against the task's transaction as it commits,
+    /// for callers that need to read the world exactly as the task left it.
+    #[instrument(skip(self, session, commit_hook))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn submit_verb_task_with_commit_hook(
+        &self,
+        player: Objid,
+        vloc: Objid,
+        verb: String,
+        args: Vec<Var>,
+        argstr: String,
+        perms: Objid,
+        session: Arc<dyn Session>,
+        commit_hook: Option<CommitHook>,
     ) -> Result<TaskHandle, SchedulerError> {
         let task_start = TaskStart::StartVerb {
             player,
@@ -293,6 +406,7 @@ impl Scheduler {
             self.control_sender.clone(),
             perms,
             false,
+            commit_hook,
         )
     }
 
@@ -321,6 +435,7 @@ impl Scheduler {
             self.control_sender.clone(),
             player,
             false,
+            None,
         )
     }
 
@@ -352,9 +467,79 @@ impl Scheduler {
             self.control_sender.clone(),
             perms,
             false,
+            None,
         )
     }
 
+    /// Compile the given code as `program_verb` would, but only to see whether it compiles; no
+    /// verb is touched. `perms` must be a programmer.
+    pub fn check_program(&self, perms: Objid, code: Vec<String>) -> Result<(), SchedulerError> {
+        let db = self.database.clone().world_state_source().unwrap();
+        let mut tx = db.new_world_state().unwrap();
+        let is_programmer = tx
+            .flags_of(perms)
+            .map(|flags| flags.contains(ObjFlag::Programmer))
+            .unwrap_or(false);
+        let _ = tx.rollback();
+        if !is_programmer {
+            return Err(CommandExecutionError(CommandError::PermissionDenied));
+        }
+
+        self.compile_cache
+            .compile(code.join("\n").as_str())
+            .map_err(|e| VerbProgramFailed(VerbProgramError::Diagnostics(vec![e.diagnostic()])))?;
+        Ok(())
+    }
+
+    /// The configuration the scheduler was started with, for the daemon's own settings in it.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// How often `program_verb` and `check_program` have been able to skip compiling.
+    pub fn compile_cache_stats(&self) -> CompileCacheStats {
+        self.compile_cache.stats()
+    }
+
+    /// Write the whole database out to `path` in the given format, from a snapshot, in the
+    /// background. If there's a `result_sender`, it's told how that went once it's done: true, or
+    /// an error.
+    pub fn export_database(
+        &self,
+        path: PathBuf,
+        format: ExportFormat,
+        result_sender: Option<oneshot::Sender<Var>>,
+    ) {
+        let db = self.database.clone();
+        let export = move || {
+            let loader_client = match db.loader_client() {
+                Ok(tx) => tx,
+                Err(e) => {
+                    error!(?e, "Could not start transaction for export");
+                    return v_err(E_INVARG);
+                }
+            };
+            info!(?format, "Exporting database to {}", path.display());
+            if let Err(e) = export_database(loader_client.as_ref(), &path, format) {
+                error!(?e, "Could not export database");
+                return v_err(E_INVARG);
+            }
+            info!("Database exported to {}", path.display());
+            v_bool(true)
+        };
+        let tr = std::thread::Builder::new()
+            .name("export-thread".to_string())
+            .spawn(move || {
+                let result = export();
+                if let Some(result_sender) = result_sender {
+                    result_sender.send(result).ok();
+                }
+            });
+        if let Err(e) = tr {
+            error!(?e, "Could not start export thread");
+        }
+    }
+
     /// Start a transaction, match the object name and verb name, and if it exists and the
     /// permissions are correct, program the verb with the given code.
     pub fn program_verb(
@@ -391,14 +576,22 @@ impl Scheduler {
                 return Err(VerbProgramFailed(VerbProgramError::NoVerbToProgram));
             }
 
-            let program = compile(code.join("\n").as_str()).map_err(|e| {
-                VerbProgramFailed(VerbProgramError::CompilationError(vec![format!("{:?}", e)]))
-            })?;
+            let program = self
+                .compile_cache
+                .compile(code.join("\n").as_str())
+                .map_err(|e| {
+                    VerbProgramFailed(VerbProgramError::Diagnostics(vec![e.diagnostic()]))
+                })?;
 
             // Now we have a program, we need to encode it.
             let binary = program
                 .with_byte_buffer(|d| Vec::from(d))
                 .expect("Failed to encode program byte stream");
+
+            if self.config.skip_unchanged_verb_programs && vi.binary().as_ref() == binary {
+                let _ = tx.rollback();
+                return Ok((o, verb_name));
+            }
             // Now we can update the verb.
             let update_attrs = VerbAttrs {
                 definer: None,
@@ -422,6 +615,57 @@ impl Scheduler {
         Err(VerbProgramFailed(VerbProgramError::DatabaseError))
     }
 
+    /// Match the object name and verb name, and if the verb exists and `perms` can read it,
+    /// return the disassembly of its program, as `disassemble()` would show it.
+    pub fn disassemble_verb(
+        &self,
+        player: Objid,
+        perms: Objid,
+        object_name: String,
+        verb_name: String,
+    ) -> Result<Disassembly, SchedulerError> {
+        let db = self.database.clone().world_state_source().unwrap();
+        let mut tx = db.new_world_state().unwrap();
+
+        let match_env = WsMatchEnv {
+            ws: tx.as_mut(),
+            perms,
+        };
+        let matcher = MatchEnvironmentParseMatcher {
+            env: match_env,
+            player,
+        };
+        let Ok(Some(o)) = matcher.match_object(&object_name) else {
+            let _ = tx.rollback();
+            return Err(CommandExecutionError(CommandError::NoObjectMatch));
+        };
+
+        // Finding a verb doesn't need read permission on it, but looking at its code does.
+        let vi = tx
+            .find_method_verb_on(perms, o, &verb_name)
+            .and_then(|vi| tx.retrieve_verb(perms, vi.verbdef().location(), vi.verbdef().uuid()));
+        let _ = tx.rollback();
+        let vi = match vi {
+            Ok(vi) => vi,
+            Err(WorldStateError::VerbPermissionDenied) => {
+                return Err(CommandExecutionError(CommandError::PermissionDenied));
+            }
+            Err(_) => return Err(CommandExecutionError(CommandError::NoCommandMatch)),
+        };
+
+        if vi.binary().is_empty() {
+            return Ok(disassemble(&Program::new()));
+        }
+        if vi.verbdef().binary_type() != BinaryType::LambdaMoo18X {
+            return Err(CouldNotStartTask);
+        }
+        let program = Program::from_bytes(vi.binary()).map_err(|e| {
+            error!(?e, "Could not decode program of {}:{}", o, verb_name);
+            CouldNotStartTask
+        })?;
+        Ok(disassemble(&program))
+    }
+
     pub fn submit_shutdown(
         &self,
         task: TaskId,
@@ -459,6 +703,10 @@ impl Scheduler {
         let mut tasks = Vec::new();
         let task_lock = self.tasks.lock().unwrap();
         for (task_id, task) in task_lock.iter() {
+            // Queued tasks have no thread to describe themselves yet.
+            if !task.started {
+                continue;
+            }
             trace!(task_id, "Requesting task description");
             let (t_send, t_reply) = oneshot::channel();
             let tcs = task.task_control_sender.clone();
@@ -482,6 +730,14 @@ impl Scheduler {
     /// Stop the scheduler run loop.
     pub fn stop(&self) -> Result<(), SchedulerError> {
         warn!("Issuing clean shutdown...");
+        {
+            // Tasks still waiting for a slot never started, so there's nothing to abort; just
+            // drop them.
+            let mut tasks = self.tasks.lock().unwrap();
+            for queued in self.task_queue.lock().unwrap().drain(..) {
+                tasks.remove(&queued.task_id);
+            }
+        }
         {
             // Send shut down to all the tasks.
             let tasks = self.tasks.lock().unwrap();
@@ -509,6 +765,17 @@ impl Scheduler {
         Ok(())
     }
 
+    /// How many tasks are executing, and how many are waiting for a slot to do so. Suspended
+    /// tasks, and those waiting on input or the debugger, are neither.
+    pub fn queue_info(&self) -> (usize, usize) {
+        self.queue_info_for(None)
+    }
+
+    /// Whether the given task is still known to the scheduler (i.e. it hasn't finished yet).
+    pub fn task_exists(&self, id: TaskId) -> bool {
+        self.tasks.lock().unwrap().contains_key(&id)
+    }
+
     pub fn abort_task(&self, id: TaskId) -> Result<(), SchedulerError> {
         let mut tasks = self.tasks.lock().unwrap();
         let task = tasks.get_mut(&id).ok_or(TaskNotFound(id))?;
@@ -518,9 +785,135 @@ impl Scheduler {
         }
         Ok(())
     }
+
+    /// Have tasks `owner` starts from now on (i.e. those running with `owner`'s permissions)
+    /// pause when they reach the given line. Tasks which are already running, and everybody
+    /// else's tasks, aren't affected.
+    pub fn set_breakpoint(&self, owner: Objid, breakpoint: Breakpoint) {
+        let mut breakpoints = self.breakpoints.lock().unwrap();
+        let breakpoints = breakpoints.entry(owner).or_default();
+        if !breakpoints.contains(&breakpoint) {
+            breakpoints.push(breakpoint);
+        }
+    }
+
+    /// Remove one of `owner`'s breakpoints, returning whether it was set.
+    pub fn clear_breakpoint(&self, owner: Objid, breakpoint: &Breakpoint) -> bool {
+        let mut breakpoints = self.breakpoints.lock().unwrap();
+        let Some(owned) = breakpoints.get_mut(&owner) else {
+            return false;
+        };
+        let before = owned.len();
+        owned.retain(|bp| bp != breakpoint);
+        let cleared = owned.len() != before;
+        if owned.is_empty() {
+            breakpoints.remove(&owner);
+        }
+        cleared
+    }
+
+    /// The tasks currently paused for the debugger, and where they're paused.
+    pub fn paused_tasks(&self) -> Vec<(TaskId, PausedActivation)> {
+        let tasks = self.tasks.lock().unwrap();
+        tasks
+            .values()
+            .filter_map(|task| {
+                let paused = task.debug_paused.clone()?;
+                Some((task.task_id, paused))
+            })
+            .collect()
+    }
+
+    /// The local variables of the verb a task is paused for the debugger in.
+    pub fn paused_task_locals(&self, id: TaskId) -> Result<Vec<(String, Var)>, SchedulerError> {
+        let tcs = {
+            let tasks = self.tasks.lock().unwrap();
+            let task = tasks.get(&id).ok_or(TaskNotFound(id))?;
+            if task.debug_paused.is_none() {
+                return Err(SchedulerError::TaskNotPaused(id));
+            }
+            task.task_control_sender.clone()
+        };
+        let (t_send, t_reply) = oneshot::channel();
+        tcs.send(TaskControlMsg::GetLocals(t_send))
+            .map_err(|_| TaskNotFound(id))?;
+        t_reply.recv().map_err(|_| TaskNotFound(id))
+    }
+
+    /// Change a local variable of the verb a task is paused for the debugger in. It only takes
+    /// effect once the task is resumed.
+    pub fn set_paused_task_local(
+        &self,
+        id: TaskId,
+        name: String,
+        value: Var,
+    ) -> Result<(), SchedulerError> {
+        let tcs = {
+            let tasks = self.tasks.lock().unwrap();
+            let task = tasks.get(&id).ok_or(TaskNotFound(id))?;
+            if task.debug_paused.is_none() {
+                return Err(SchedulerError::TaskNotPaused(id));
+            }
+            task.task_control_sender.clone()
+        };
+        let (t_send, t_reply) = oneshot::channel();
+        tcs.send(TaskControlMsg::SetLocal(name.clone(), value, t_send))
+            .map_err(|_| TaskNotFound(id))?;
+        if !t_reply.recv().map_err(|_| TaskNotFound(id))? {
+            return Err(SchedulerError::VariableNotFound(name));
+        }
+
+        // Keep what `paused_tasks()` reports in step.
+        let locals = self.paused_task_locals(id)?;
+        let mut tasks = self.tasks.lock().unwrap();
+        if let Some(paused) = tasks
+            .get_mut(&id)
+            .and_then(|task| task.debug_paused.as_mut())
+        {
+            paused.locals = locals;
+        }
+        Ok(())
+    }
+
+    /// Carry on with a task paused for the debugger: until the next breakpoint, or if `step`,
+    /// for just one opcode.
+    pub fn debug_resume(&self, id: TaskId, step: bool) -> Result<(), SchedulerError> {
+        let mut tasks = self.tasks.lock().unwrap();
+        let task = tasks.get_mut(&id).ok_or(TaskNotFound(id))?;
+        if task.debug_paused.take().is_none() {
+            return Err(SchedulerError::TaskNotPaused(id));
+        }
+        task.debug_pause_deadline = None;
+        task.task_control_sender
+            .send(TaskControlMsg::DebugResume(step))
+            .map_err(|_| CouldNotStartTask)?;
+        Ok(())
+    }
 }
 
 impl Scheduler {
+    /// Pick up any limits the core has set on `$server_options`, in a transaction of our own.
+    fn reload_server_options(&self) {
+        let mut ws = match self
+            .database
+            .clone()
+            .world_state_source()
+            .and_then(|source| source.new_world_state())
+        {
+            Ok(ws) => ws,
+            Err(e) => {
+                error!(?e, "Could not open transaction to load $server_options");
+                return;
+            }
+        };
+        let server_options = ServerOptions::load(ws.as_ref(), SYSTEM_OBJECT);
+        if let Err(e) = ws.rollback() {
+            warn!(?e, "Could not roll back $server_options transaction");
+        }
+        info!(?server_options, "Loaded server options");
+        *self.server_options.lock().unwrap() = server_options;
+    }
+
     fn do_process(&self) {
         // TODO: Improve scheduler "tick" and "prune" logic.  It's a bit of a mess.
         //  we might be able to use a vector of delay-futures for this instead, and just poll
@@ -537,6 +930,7 @@ impl Scheduler {
             // Or tasks that need pruning.
             let mut to_wake = Vec::new();
             let mut to_prune = Vec::new();
+            let mut to_abort = Vec::new();
             {
                 let tasks = self.tasks.lock().unwrap();
                 for (task_id, task) in tasks.iter() {
@@ -549,6 +943,14 @@ impl Scheduler {
                         continue;
                     }
 
+                    if task
+                        .debug_pause_deadline
+                        .is_some_and(|deadline| deadline <= SystemTime::now())
+                    {
+                        to_abort.push(*task_id);
+                        continue;
+                    }
+
                     if !task.suspended {
                         continue;
                     }
@@ -556,16 +958,23 @@ impl Scheduler {
                         continue;
                     };
                     if delay <= SystemTime::now() {
-                        to_wake.push(*task_id);
+                        to_wake.push((task.suspend_order, *task_id));
                     }
                 }
             }
+            // Resume in the order the tasks suspended, not the (arbitrary) order of the task map.
+            to_wake.sort_unstable();
+            let to_wake: Vec<_> = to_wake.into_iter().map(|(_, task_id)| task_id).collect();
             if !to_wake.is_empty() {
                 self.process_wake_ups(&to_wake);
             }
             if !to_prune.is_empty() {
                 self.process_task_removals(&to_prune);
             }
+            if !to_abort.is_empty() {
+                self.process_debug_timeouts(&to_abort);
+            }
+            self.start_queued_tasks();
             if let Ok(msg) = self.control_receiver.recv_timeout(SCHEDULER_TICK_TIME) {
                 let (task_id, msg) = msg;
                 if let Some(action) = self.handle_task_control_msg(task_id, msg) {
@@ -639,9 +1048,7 @@ impl Scheduler {
                     warn!(task_id, "Task not found for abort");
                     return None;
                 };
-                if let Err(send_error) = task
-                    .session
-                    .send_system_msg(task.player, "Aborted.".to_string().as_str())
+                if let Err(send_error) = task.session.send_system_msg(task.player, "Aborted.", None)
                 {
                     warn!("Could not send abort message to player: {:?}", send_error);
                 };
@@ -658,8 +1065,18 @@ impl Scheduler {
                     TaskResult::Error(TaskAbortedCancelled),
                 ))
             }
-            SchedulerControlMsg::TaskAbortLimitsReached(limit_reason) => {
-                let abort_reason_text = match limit_reason {
+            SchedulerControlMsg::TaskAbortLimitsReached(limit_reason, top_consumers) => {
+                for (site, ticks) in &top_consumers {
+                    warn!(
+                        ?task_id,
+                        definer = ?site.definer,
+                        verb = site.verb_name,
+                        line = site.line,
+                        ticks,
+                        "Tick consumer in aborted task"
+                    );
+                }
+                let mut abort_reason_text = match limit_reason {
                     AbortLimitReason::Ticks(t) => {
                         warn!(?task_id, ticks = t, "Task aborted, ticks exceeded");
                         format!("Abort: Task exceeded ticks limit of {}", t)
@@ -669,6 +1086,16 @@ impl Scheduler {
                         format!("Abort: Task exceeded time limit of {:?}", t)
                     }
                 };
+                // If the task was being profiled, say where its ticks went, so the player (or
+                // their wizard) knows which verb to look at.
+                if !top_consumers.is_empty() {
+                    let consumers: Vec<_> = top_consumers
+                        .iter()
+                        .map(|(site, ticks)| format!("{site} (~{ticks} ticks)"))
+                        .collect();
+                    abort_reason_text
+                        .push_str(&format!("; most ticks spent in {}", consumers.join(", ")));
+                }
 
                 // Commit the session.
                 let mut tasks = self.tasks.lock().unwrap();
@@ -678,7 +1105,7 @@ impl Scheduler {
                 };
 
                 task.session
-                    .send_system_msg(task.player, &abort_reason_text)
+                    .send_system_msg(task.player, &abort_reason_text, None)
                     .expect("Could not send abort message to player");
 
                 let _ = task.session.commit();
@@ -707,7 +1134,9 @@ impl Scheduler {
                 }
 
                 for l in traceback.iter() {
-                    if let Err(send_error) = task.session.send_system_msg(task.player, l.as_str()) {
+                    if let Err(send_error) =
+                        task.session.send_system_msg(task.player, l.as_str(), None)
+                    {
                         warn!("Could not send traceback to player: {:?}", send_error);
                     }
                 }
@@ -757,11 +1186,12 @@ impl Scheduler {
                 };
                 task.suspended = true;
                 task.resume_time = resume_time;
+                task.suspend_order = self.next_suspend_order.fetch_add(1, Ordering::SeqCst);
 
                 trace!(task_id, resume_time = ?task.resume_time, "Task suspended");
                 None
             }
-            SchedulerControlMsg::TaskRequestInput => {
+            SchedulerControlMsg::TaskRequestInput(input_player) => {
                 // Task has gone into suspension waiting for input from the client.
                 // Create a unique ID for this request, and we'll wake the task when the
                 // session receives input.
@@ -783,7 +1213,7 @@ impl Scheduler {
                         ));
                     };
 
-                    let Ok(()) = task.session.request_input(task.player, input_request_id) else {
+                    let Ok(()) = task.session.request_input(input_player, input_request_id) else {
                         warn!("Could not request input from session; aborting task");
                         return Some(TaskHandleResult::Result(
                             task_id,
@@ -793,14 +1223,49 @@ impl Scheduler {
                     task.waiting_input = Some(input_request_id);
                 }
                 let mut input_requests = self.input_requests.lock().unwrap();
-                input_requests.insert(input_request_id, task_id);
+                input_requests.insert(input_request_id, (task_id, input_player));
                 trace!(?task_id, "Task suspended waiting for input");
                 None
             }
+            SchedulerControlMsg::TaskDebugPause(paused) => {
+                let mut tasks = self.tasks.lock().unwrap();
+                let Some(task) = tasks.get_mut(&task_id) else {
+                    warn!(task_id, "Task not found for debug pause");
+                    return None;
+                };
+                // Flush the output so far, so it can be seen alongside where the task stopped.
+                let Ok(()) = task.session.commit() else {
+                    warn!("Could not commit session; aborting task");
+                    return Some(TaskHandleResult::Result(
+                        task_id,
+                        TaskResult::Error(TaskAbortedError),
+                    ));
+                };
+                debug!(task_id, ?paused, "Task paused for debugger");
+                task.debug_paused = Some(paused);
+                let timeout = self
+                    .config
+                    .debug_pause_timeout
+                    .unwrap_or(DEFAULT_DEBUG_PAUSE_TIMEOUT);
+                task.debug_pause_deadline = Some(SystemTime::now() + timeout);
+                None
+            }
+            SchedulerControlMsg::QueueInfo { player, reply } => {
+                if let Err(e) = reply.send(self.queue_info_for(player)) {
+                    error!(task_id, error = ?e, "Could not send queue info to requesting task");
+                }
+                None
+            }
             SchedulerControlMsg::DescribeOtherTasks(reply) => {
                 // Task is asking for a description of all other tasks.
                 Some(TaskHandleResult::Describe(task_id, reply))
             }
+            SchedulerControlMsg::ForkedTasks(reply) => {
+                Some(TaskHandleResult::ForkedTasks(task_id, reply))
+            }
+            SchedulerControlMsg::KillForks(reply) => {
+                Some(TaskHandleResult::KillForks(task_id, reply))
+            }
             SchedulerControlMsg::KillTask {
                 victim_task_id,
                 sender_permissions,
@@ -829,9 +1294,14 @@ impl Scheduler {
             SchedulerControlMsg::BootPlayer {
                 player,
                 sender_permissions: _,
+                connection_only,
             } => {
                 // Task is asking to boot a player.
-                Some(TaskHandleResult::Disconnect(task_id, player))
+                Some(TaskHandleResult::Disconnect(
+                    task_id,
+                    player,
+                    connection_only,
+                ))
             }
             SchedulerControlMsg::Notify { player, event } => {
                 // Task is asking to notify a player.
@@ -849,6 +1319,21 @@ impl Scheduler {
                 };
                 None
             }
+            SchedulerControlMsg::NotifyConnection { player, event } => {
+                let mut tasks = self.tasks.lock().unwrap();
+                let Some(task) = tasks.get_mut(&task_id) else {
+                    warn!(task_id, "Task not found for notify request");
+                    return None;
+                };
+                let Ok(()) = task.session.send_connection_event(player, event) else {
+                    warn!("Could not notify connection; aborting task");
+                    return Some(TaskHandleResult::Result(
+                        task_id,
+                        TaskResult::Error(TaskAbortedError),
+                    ));
+                };
+                None
+            }
             SchedulerControlMsg::Shutdown(msg) => {
                 info!("Shutting down scheduler. Reason: {msg:?}");
                 let result_mst = match self.stop() {
@@ -874,50 +1359,30 @@ impl Scheduler {
                     }
                 }
             }
+            SchedulerControlMsg::SetServerOptions(server_options) => {
+                info!(task_id, ?server_options, "Server options reloaded");
+                *self.server_options.lock().unwrap() = server_options;
+                None
+            }
             SchedulerControlMsg::Checkpoint => {
                 let Some(textdump_path) = self.config.textdump_output.clone() else {
                     error!("Cannot textdump as textdump_file not configured");
                     return None;
                 };
-
-                let db = self.database.clone();
-                let tr = std::thread::Builder::new()
-                    .name("textdump-thread".to_string())
-                    .spawn(move || {
-                        let loader_client = {
-                            match db.loader_client() {
-                                Ok(tx) => tx,
-                                Err(e) => {
-                                    error!(?e, "Could not start transaction for checkpoint");
-                                    return;
-                                }
-                            }
-                        };
-
-                        let Ok(mut output) = File::create(&textdump_path) else {
-                            error!("Could not open textdump file for writing");
-                            return;
-                        };
-
-                        info!("Creating textdump...");
-                        let textdump = make_textdump(
-                            loader_client.as_ref(),
-                            // just to be compatible with LambdaMOO import for now, hopefully.
-                            Some("** LambdaMOO Database, Format Version 4 **"),
-                        );
-
-                        info!("Writing textdump to {}", textdump_path.display());
-
-                        let mut writer = TextdumpWriter::new(&mut output);
-                        if let Err(e) = writer.write_textdump(&textdump) {
-                            error!(?e, "Could not write textdump");
-                            return;
-                        }
-                        info!("Textdump written to {}", textdump_path.display());
-                    });
-                if let Err(e) = tr {
-                    error!(?e, "Could not start textdump thread");
-                }
+                self.export_database(textdump_path, ExportFormat::Textdump, None);
+                None
+            }
+            SchedulerControlMsg::ExportDatabase {
+                name,
+                format,
+                result_sender,
+            } => {
+                let Some(export_directory) = self.config.export_directory.clone() else {
+                    warn!("Cannot export as export directory not configured");
+                    result_sender.send(v_err(E_PERM)).ok();
+                    return None;
+                };
+                self.export_database(export_directory.join(name), format, Some(result_sender));
                 None
             }
         }
@@ -933,6 +1398,7 @@ impl Scheduler {
         let player = fork.player;
         let delay = fork.delay;
         let progr = fork.progr;
+        let parent_task_id = fork.parent_task_id;
         let task_handle = self.new_task(
             TaskStart::StartFork {
                 fork_request: fork,
@@ -943,7 +1409,8 @@ impl Scheduler {
             delay,
             self.control_sender.clone(),
             progr,
-            false,
+            true,
+            None,
         )?;
 
         let task_id = task_handle.task_id();
@@ -951,12 +1418,14 @@ impl Scheduler {
         let Some(task_ref) = tasks.get_mut(&task_id) else {
             return Err(TaskNotFound(task_id));
         };
+        task_ref.parent = Some(parent_task_id);
 
         // If there's a delay on the fork, we will mark it in suspended state and put in the
         // delay time.
         if let Some(delay) = delay {
             task_ref.suspended = true;
             task_ref.resume_time = Some(SystemTime::now() + delay);
+            task_ref.suspend_order = self.next_suspend_order.fetch_add(1, Ordering::SeqCst);
         }
 
         Ok(task_id)
@@ -976,6 +1445,12 @@ impl Scheduler {
             TaskHandleResult::Describe(task_id, reply) => {
                 to_remove.extend(self.process_describe_request(task_id, reply))
             }
+            TaskHandleResult::ForkedTasks(task_id, reply) => {
+                to_remove.extend(self.process_forked_tasks_request(task_id, reply))
+            }
+            TaskHandleResult::KillForks(task_id, reply) => {
+                to_remove.extend(self.process_kill_forks_request(task_id, reply))
+            }
             TaskHandleResult::Kill {
                 requesting_task_id,
                 victim_task_id,
@@ -1004,8 +1479,8 @@ impl Scheduler {
                     result_sender,
                 ));
             }
-            TaskHandleResult::Disconnect(task_id, player) => {
-                self.process_disconnect(task_id, player);
+            TaskHandleResult::Disconnect(task_id, player, connection_only) => {
+                self.process_disconnect(task_id, player, connection_only);
             }
             TaskHandleResult::Retry(task_id) => {
                 to_remove.extend(self.process_retry_request(task_id));
@@ -1105,8 +1580,9 @@ impl Scheduler {
         );
         let tasks_lock = self.tasks.lock().unwrap();
         for (task_id, task) in tasks_lock.iter() {
-            // Tasks not in suspended state shouldn't be added.
-            if !task.suspended {
+            // Tasks not in suspended state shouldn't be added. Nor should those still waiting
+            // for a slot, which have no thread to answer us yet.
+            if !task.suspended || !task.started {
                 continue;
             }
             if *task_id != requesting_task_id {
@@ -1133,9 +1609,11 @@ impl Scheduler {
                     other_task = task_id,
                     "Got task description"
                 );
-                tasks.push(task_desc);
+                tasks.push((task.suspend_order, task_desc));
             }
         }
+        tasks.sort_by_key(|(suspend_order, _)| *suspend_order);
+        let tasks = tasks.into_iter().map(|(_, task_desc)| task_desc).collect();
         trace!(
             task = requesting_task_id,
             "Sending task descriptions back..."
@@ -1145,6 +1623,55 @@ impl Scheduler {
         to_remove
     }
 
+    fn process_forked_tasks_request(
+        &self,
+        requesting_task_id: TaskId,
+        reply: oneshot::Sender<Vec<TaskId>>,
+    ) -> Vec<TaskId> {
+        let tasks = self.tasks.lock().unwrap();
+        let mut forks: Vec<_> = tasks
+            .values()
+            .filter(|task| task.parent == Some(requesting_task_id))
+            .map(|task| task.task_id)
+            .collect();
+        forks.sort();
+        if let Err(e) = reply.send(forks) {
+            error!(task = requesting_task_id, error = ?e, "Could not send forked tasks to requesting task. Requesting task being removed.");
+            return vec![requesting_task_id];
+        }
+        vec![]
+    }
+
+    /// Abort every task forked by the requesting task. These are its own forks, so unlike
+    /// `kill_task()` there's no permission check to make.
+    fn process_kill_forks_request(
+        &self,
+        requesting_task_id: TaskId,
+        reply: oneshot::Sender<usize>,
+    ) -> Vec<TaskId> {
+        let mut to_remove = vec![];
+        let mut killed = 0;
+        let mut tasks = self.tasks.lock().unwrap();
+        for task in tasks.values_mut() {
+            if task.parent != Some(requesting_task_id) {
+                continue;
+            }
+            // The task goes away once it has finished aborting, which happens on its own thread;
+            // unlink it now so it's already gone from the parent's `forked_tasks()`.
+            task.parent = None;
+            killed += 1;
+            if let Err(e) = task.task_control_sender.send(TaskControlMsg::Abort) {
+                error!(task = task.task_id, error = ?e, "Could not send kill request to forked task. Task being removed.");
+                to_remove.push(task.task_id);
+            }
+        }
+        if let Err(e) = reply.send(killed) {
+            error!(task = requesting_task_id, error = ?e, "Could not send kill_forks result to requesting task. Requesting task being removed.");
+            to_remove.push(requesting_task_id);
+        }
+        to_remove
+    }
+
     fn process_kill_request(
         &self,
         requesting_task_id: TaskId,
@@ -1177,16 +1704,12 @@ impl Scheduler {
         };
 
         // We reject this outright if the sender permissions are not sufficient:
-        //   The either have to be the owner of the task (task.programmer == sender_permissions.task_perms)
-        //   Or they have to be a wizard.
-        // TODO: Verify kill task permissions is right
-        //   Will have to verify that it's enough that .player on task control can
-        //   be considered "owner" of the task, or there needs to be some more
-        //   elaborate consideration here?
+        //   They either have to be the owner of the task (the perms it was started with),
+        //   or they have to be a wizard.
         if !sender_permissions
             .check_is_wizard()
             .expect("Could not check wizard status for kill request")
-            && sender_permissions.who != victim_task.player
+            && sender_permissions.who != victim_task.perms
         {
             result_sender
                 .send(v_err(E_PERM))
@@ -1305,12 +1828,27 @@ impl Scheduler {
         None
     }
 
-    fn process_disconnect(&self, disconnect_task_id: TaskId, player: Objid) {
+    fn process_disconnect(&self, disconnect_task_id: TaskId, player: Objid, connection_only: bool) {
         let mut tasks = self.tasks.lock().unwrap();
         let Some(task) = tasks.get_mut(&disconnect_task_id) else {
             warn!(task = disconnect_task_id, "Disconnecting task not found");
             return;
         };
+
+        // Dropping just the one connection leaves the player's other connections, and so their
+        // tasks, alive.
+        if connection_only {
+            warn!(
+                ?player,
+                ?disconnect_task_id,
+                "Disconnecting player's connection"
+            );
+            if let Err(e) = task.session.disconnect_connection(player) {
+                warn!(?player, ?disconnect_task_id, error = ?e, "Could not disconnect player's connection");
+            }
+            return;
+        }
+
         // First disconnect the player...
         warn!(?player, ?disconnect_task_id, "Disconnecting player");
         if let Err(e) = task.session.disconnect(player) {
@@ -1341,6 +1879,69 @@ impl Scheduler {
         }
     }
 
+    /// Counts of running and queued tasks, optionally only those belonging to `player`.
+    fn queue_info_for(&self, player: Option<Objid>) -> (usize, usize) {
+        let tasks = self.tasks.lock().unwrap();
+        let (mut running, mut queued) = (0, 0);
+        for task in tasks.values() {
+            if player.is_some_and(|player| task.player != player) {
+                continue;
+            }
+            if task.is_executing() {
+                running += 1;
+            } else if !task.started {
+                queued += 1;
+            }
+        }
+        (running, queued)
+    }
+
+    /// Start as many queued tasks as there is now room for.
+    fn start_queued_tasks(&self) {
+        let mut tasks = self.tasks.lock().unwrap();
+        let mut task_queue = self.task_queue.lock().unwrap();
+        loop {
+            let running = tasks.values().filter(|task| task.is_executing()).count();
+            if self
+                .config
+                .max_concurrent_tasks
+                .is_some_and(|max| running >= max)
+            {
+                return;
+            }
+            let Some(queued) = task_queue.pop_front() else {
+                return;
+            };
+            // It may have been removed (e.g. aborted) while it waited.
+            let Some(task) = tasks.get_mut(&queued.task_id) else {
+                continue;
+            };
+            trace!(task_id = queued.task_id, "Starting queued task");
+            task.started = true;
+            (queued.spawn)();
+        }
+    }
+
+    /// Abort tasks which have been left paused for the debugger for too long, rolling back their
+    /// transactions.
+    fn process_debug_timeouts(&self, to_abort: &[TaskId]) {
+        let mut tasks = self.tasks.lock().unwrap();
+        for task_id in to_abort {
+            let Some(task) = tasks.get_mut(task_id) else {
+                continue;
+            };
+            warn!(
+                task_id,
+                "Task left paused for the debugger too long; aborting"
+            );
+            task.debug_paused = None;
+            task.debug_pause_deadline = None;
+            if let Err(e) = task.task_control_sender.send(TaskControlMsg::Abort) {
+                error!(task_id, error = ?e, "Could not send abort to paused task");
+            }
+        }
+    }
+
     fn process_task_removals(&self, to_remove: &[TaskId]) {
         let mut tasks = self.tasks.lock().unwrap();
         for task_id in to_remove {
@@ -1360,6 +1961,7 @@ impl Scheduler {
         control_sender: Sender<(TaskId, SchedulerControlMsg)>,
         perms: Objid,
         is_background: bool,
+        commit_hook: Option<CommitHook>,
     ) -> Result<TaskHandle, SchedulerError> {
         let task_id = self.next_task_id.fetch_add(1, Ordering::SeqCst);
         let (task_control_sender, task_control_receiver) = crossbeam_channel::unbounded();
@@ -1375,43 +1977,84 @@ impl Scheduler {
         // Spawn the task's thread.
         let task_state_source = state_source.clone();
         let task_session = session.clone();
+        let config = self.config.clone();
+        let server_options = *self.server_options.lock().unwrap();
+        let breakpoints = self
+            .breakpoints
+            .lock()
+            .unwrap()
+            .get(&perms)
+            .cloned()
+            .unwrap_or_default();
+        let foreground = self.foreground.clone();
 
         let (sender, receiver) = oneshot::channel();
         let name = format!("moor-task-{}-player-{}", task_id, player);
         let task_control = TaskControl {
             task_id,
             player,
+            perms,
             task_control_sender,
             state_source,
             session,
             suspended: false,
             waiting_input: None,
             resume_time: None,
+            suspend_order: 0,
+            debug_paused: None,
+            debug_pause_deadline: None,
+            started: false,
+            start_after: delay_start.map(|delay| SystemTime::now() + delay),
+            parent: None,
             result_sender: Mutex::new(Some(sender)),
         };
-        let mut tasks = self.tasks.lock().unwrap();
-        tasks.insert(task_id, task_control);
+        let spawn = move || {
+            std::thread::Builder::new()
+                .name(name)
+                .spawn(move || {
+                    trace!(?task_id, ?task_start, "Starting up task");
+                    Task::run(
+                        task_id,
+                        task_start,
+                        perms,
+                        delay_start,
+                        task_state_source,
+                        is_background,
+                        task_session,
+                        task_control_receiver,
+                        control_sender,
+                        config,
+                        server_options,
+                        breakpoints,
+                        foreground,
+                        commit_hook,
+                    );
+                    trace!(?task_id, "Completed task");
+                })
+                .expect("Could not spawn task thread");
+        };
 
         // Footgun warning: ALWAYS `self.tasks.insert` before spawning the task thread!
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.insert(task_id, task_control);
 
-        std::thread::Builder::new()
-            .name(name)
-            .spawn(move || {
-                trace!(?task_id, ?task_start, "Starting up task");
-                Task::run(
-                    task_id,
-                    task_start,
-                    perms,
-                    delay_start,
-                    task_state_source,
-                    is_background,
-                    task_session,
-                    task_control_receiver,
-                    control_sender,
-                );
-                trace!(?task_id, "Completed task");
-            })
-            .expect("Could not spawn task thread");
+        // If we're at the limit of concurrent tasks (or others are already waiting), get in line.
+        let mut task_queue = self.task_queue.lock().unwrap();
+        let running = tasks.values().filter(|task| task.is_executing()).count();
+        let at_limit = self
+            .config
+            .max_concurrent_tasks
+            .is_some_and(|max| running >= max);
+        if at_limit || !task_queue.is_empty() {
+            trace!(task_id, running, "Task queued, waiting for a free slot");
+            task_queue.push_back(QueuedTask {
+                task_id,
+                spawn: Box::new(spawn),
+            });
+        } else {
+            tasks.get_mut(&task_id).unwrap().started = true;
+            spawn();
+        }
 
         Ok(TaskHandle(task_id, receiver))
     }
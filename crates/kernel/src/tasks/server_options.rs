@@ -0,0 +1,116 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Execution limits which a core can tune at runtime via properties on `$server_options`.
+//!
+//! The honored properties are `fg_ticks`, `bg_ticks`, `fg_seconds`, `bg_seconds` and
+//! `max_stack_depth`. Each must be a positive integer; if it is missing or isn't, the server
+//! default is used instead. `connection_takeover` is a flag, off unless set to a true value.
+//! They are read when the scheduler starts, and again whenever a wizard calls
+//! `load_server_options()`.
+
+use moor_values::model::WorldState;
+use moor_values::var::{Objid, Variant};
+use moor_values::SYSTEM_OBJECT;
+
+const DEFAULT_FG_TICKS: usize = 60_000;
+const DEFAULT_BG_TICKS: usize = 30_000;
+const DEFAULT_FG_SECONDS: u64 = 5;
+const DEFAULT_BG_SECONDS: u64 = 3;
+const DEFAULT_MAX_STACK_DEPTH: usize = 50;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ServerOptions {
+    /// Tick budget for foreground (command, eval, verb) tasks.
+    pub fg_ticks: usize,
+    /// Tick budget for background (forked, resumed) tasks.
+    pub bg_ticks: usize,
+    /// Wall-clock budget for foreground tasks, in seconds.
+    pub fg_seconds: u64,
+    /// Wall-clock budget for background tasks, in seconds.
+    pub bg_seconds: u64,
+    /// Maximum depth of the activation stack.
+    pub max_stack_depth: usize,
+    /// Whether a player connecting while already connected takes over from (and drops) their
+    /// existing connections, with `user_redirected` called rather than `user_connected`.
+    pub connection_takeover: bool,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            fg_ticks: DEFAULT_FG_TICKS,
+            bg_ticks: DEFAULT_BG_TICKS,
+            fg_seconds: DEFAULT_FG_SECONDS,
+            bg_seconds: DEFAULT_BG_SECONDS,
+            max_stack_depth: DEFAULT_MAX_STACK_DEPTH,
+            connection_takeover: false,
+        }
+    }
+}
+
+impl ServerOptions {
+    /// Read the options from `$server_options` in the given transaction, falling back to the
+    /// defaults for anything which isn't set (including `$server_options` itself).
+    pub fn load(ws: &dyn WorldState, perms: Objid) -> Self {
+        let defaults = Self::default();
+        let Ok(server_options) = ws.retrieve_property(perms, SYSTEM_OBJECT, "server_options")
+        else {
+            return defaults;
+        };
+        let Variant::Obj(server_options) = server_options.variant() else {
+            return defaults;
+        };
+        let values = ws
+            .get_properties(
+                perms,
+                *server_options,
+                &[
+                    "fg_ticks",
+                    "bg_ticks",
+                    "fg_seconds",
+                    "bg_seconds",
+                    "max_stack_depth",
+                    "connection_takeover",
+                ],
+            )
+            .unwrap_or_default();
+        let option = |name: &str| -> Option<i64> {
+            match values.get(name)?.variant() {
+                Variant::Int(i) if *i > 0 => Some(*i),
+                _ => None,
+            }
+        };
+        Self {
+            fg_ticks: option("fg_ticks").map_or(defaults.fg_ticks, |v| v as usize),
+            bg_ticks: option("bg_ticks").map_or(defaults.bg_ticks, |v| v as usize),
+            fg_seconds: option("fg_seconds").map_or(defaults.fg_seconds, |v| v as u64),
+            bg_seconds: option("bg_seconds").map_or(defaults.bg_seconds, |v| v as u64),
+            max_stack_depth: option("max_stack_depth")
+                .map_or(defaults.max_stack_depth, |v| v as usize),
+            connection_takeover: values
+                .get("connection_takeover")
+                .map_or(defaults.connection_takeover, |v| v.is_true()),
+        }
+    }
+
+    /// The (ticks, seconds, stack depth) limits for a new task.
+    pub fn max_vm_values(&self, is_background: bool) -> (usize, u64, usize) {
+        if is_background {
+            (self.bg_ticks, self.bg_seconds, self.max_stack_depth)
+        } else {
+            (self.fg_ticks, self.fg_seconds, self.max_stack_depth)
+        }
+    }
+}
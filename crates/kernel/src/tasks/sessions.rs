@@ -12,8 +12,8 @@
 // this program. If not, see <https://www.gnu.org/licenses/>.
 //
 
-use moor_values::model::NarrativeEvent;
-use moor_values::var::Objid;
+use moor_values::model::{HostType, ListenerPoint, NarrativeEvent, PresenceKind};
+use moor_values::var::{Objid, Var};
 use std::sync::{Arc, RwLock};
 use thiserror::Error;
 use uuid::Uuid;
@@ -73,12 +73,38 @@ pub trait Session: Send + Sync {
     /// rollback.
     fn send_event(&self, player: Objid, event: NarrativeEvent) -> Result<(), SessionError>;
 
+    /// Like `send_event`, but only to the connection this session is for, rather than all of the
+    /// player's connections. For output that only makes sense where the task came from, e.g. a
+    /// prompt when the player has another session sitting in a menu.
+    fn send_connection_event(
+        &self,
+        player: Objid,
+        event: NarrativeEvent,
+    ) -> Result<(), SessionError>;
+
     /// Send non-spooled output to the given player's connection
     /// Examples of the kinds of messages that would be sent here are state-independent messages
     /// like login/logout messages, system error messages ("task aborted") or messages that are not
     /// generally co-incident with the mutable state of the world and that need not be logged
     /// across multiple connections, etc.
-    fn send_system_msg(&self, player: Objid, msg: &str) -> Result<(), SessionError>;
+    /// `content_type` is the type of `msg` (e.g. `text/html`) for clients which can render it, or
+    /// `None` for plain text.
+    fn send_system_msg(
+        &self,
+        player: Objid,
+        msg: &str,
+        content_type: Option<&str>,
+    ) -> Result<(), SessionError>;
+
+    /// Tell the given player's connections about `who`'s presence (typing, idle, etc).
+    /// Like system messages this is not spooled; presence is transient and has no business
+    /// waiting for the task to commit.
+    fn send_presence(
+        &self,
+        player: Objid,
+        who: Objid,
+        kind: PresenceKind,
+    ) -> Result<(), SessionError>;
 
     /// Process a (wizard) request for system shutdown, with an optional shutdown message.
     fn shutdown(&self, msg: Option<String>) -> Result<(), SessionError>;
@@ -91,16 +117,53 @@ pub trait Session: Send + Sync {
     /// Disconnect the given player's connection.
     fn disconnect(&self, player: Objid) -> Result<(), SessionError>;
 
-    /// Return the list of other currently-connected players.
-    fn connected_players(&self) -> Result<Vec<Objid>, SessionError>;
+    /// Disconnect only the connection this session is for, which must belong to the given player,
+    /// leaving any other connections they have alone.
+    fn disconnect_connection(&self, player: Objid) -> Result<(), SessionError>;
+
+    /// Invalidate every auth token issued to the given player so far, so that they have to log in
+    /// again to get a new one, and disconnect their current connections.
+    fn revoke_tokens(&self, player: Objid) -> Result<(), SessionError>;
+
+    /// Where the hosts are accepting connections.
+    fn listeners(&self) -> Result<Vec<ListenerPoint>, SessionError>;
+
+    /// Return the list of other currently-connected players, or with `include_all` every
+    /// connection, including those not yet logged in.
+    /// If `max_idle` is given, only those idle for at most that many seconds are returned.
+    fn connected_players(
+        &self,
+        include_all: bool,
+        max_idle: Option<f64>,
+    ) -> Result<Vec<Objid>, SessionError>;
 
     /// Return how many seconds the given player has been connected.
     fn connected_seconds(&self, player: Objid) -> Result<f64, SessionError>;
 
     /// Return how many seconds the given player has been idle (no tasks submitted).
     fn idle_seconds(&self, player: Objid) -> Result<f64, SessionError>;
+
+    /// Ask a host to open an outbound TCP connection to `host`:`port`, blocking until it's up.
+    /// Returns the (negative) connection object, which can be used with `notify` and `read` as
+    /// for any other connection.
+    fn open_network_connection(&self, host: &str, port: u16) -> Result<Objid, SessionError>;
+
+    /// Set one of the `CONNECTION_OPTIONS` on the given player's connections.
+    fn set_connection_option(
+        &self,
+        player: Objid,
+        option: &str,
+        value: Var,
+    ) -> Result<(), SessionError>;
+
+    /// The options which have been set on the given player's connections.
+    fn connection_options(&self, player: Objid) -> Result<Vec<(String, Var)>, SessionError>;
 }
 
+/// The connection options which can be set with `set_connection_option()`:
+///   `idle-exempt`: if true, the connection is never dropped for being idle (e.g. for bots).
+pub const CONNECTION_OPTIONS: &[&str] = &["idle-exempt"];
+
 #[derive(Debug, Error)]
 pub enum SessionError {
     #[error("No connection for player {0}")]
@@ -111,6 +174,20 @@ pub enum SessionError {
     CommitError(String),
     #[error("Invalid authorization token")]
     InvalidToken,
+    #[error("Could not open outbound connection: {0}")]
+    OutboundConnectionFailed(String),
+}
+
+/// What the test sessions report from `listeners()`: the one port a stock LambdaMOO would be
+/// listening on, which is what cores expect to see.
+fn standalone_listener() -> ListenerPoint {
+    ListenerPoint {
+        host_type: HostType::Tcp,
+        addr: ([0, 0, 0, 0], 7777).into(),
+        handler: Objid(0),
+        print_messages: true,
+        out_of_band_prefix: Some("#$#".to_string()),
+    }
 }
 
 /// A simple no-op implementation of the Sessions trait, for use in unit tests.
@@ -151,7 +228,29 @@ impl Session for NoopClientSession {
         Ok(())
     }
 
-    fn send_system_msg(&self, _player: Objid, _msg: &str) -> Result<(), SessionError> {
+    fn send_connection_event(
+        &self,
+        _player: Objid,
+        _msg: NarrativeEvent,
+    ) -> Result<(), SessionError> {
+        Ok(())
+    }
+
+    fn send_system_msg(
+        &self,
+        _player: Objid,
+        _msg: &str,
+        _content_type: Option<&str>,
+    ) -> Result<(), SessionError> {
+        Ok(())
+    }
+
+    fn send_presence(
+        &self,
+        _player: Objid,
+        _who: Objid,
+        _kind: PresenceKind,
+    ) -> Result<(), SessionError> {
         Ok(())
     }
 
@@ -164,7 +263,20 @@ impl Session for NoopClientSession {
     fn disconnect(&self, _player: Objid) -> Result<(), SessionError> {
         Ok(())
     }
-    fn connected_players(&self) -> Result<Vec<Objid>, SessionError> {
+    fn disconnect_connection(&self, _player: Objid) -> Result<(), SessionError> {
+        Ok(())
+    }
+    fn revoke_tokens(&self, _player: Objid) -> Result<(), SessionError> {
+        Ok(())
+    }
+    fn listeners(&self) -> Result<Vec<ListenerPoint>, SessionError> {
+        Ok(vec![standalone_listener()])
+    }
+    fn connected_players(
+        &self,
+        _include_all: bool,
+        _max_idle: Option<f64>,
+    ) -> Result<Vec<Objid>, SessionError> {
         Ok(vec![])
     }
 
@@ -175,6 +287,25 @@ impl Session for NoopClientSession {
     fn idle_seconds(&self, _player: Objid) -> Result<f64, SessionError> {
         Ok(0.0)
     }
+
+    fn open_network_connection(&self, _host: &str, _port: u16) -> Result<Objid, SessionError> {
+        Err(SessionError::OutboundConnectionFailed(
+            "no network available".to_string(),
+        ))
+    }
+
+    fn set_connection_option(
+        &self,
+        _player: Objid,
+        _option: &str,
+        _value: Var,
+    ) -> Result<(), SessionError> {
+        Ok(())
+    }
+
+    fn connection_options(&self, _player: Objid) -> Result<Vec<(String, Var)>, SessionError> {
+        Ok(vec![])
+    }
 }
 
 /// A 'mock' client connection which collects output in a vector of strings that tests can use to
@@ -252,11 +383,39 @@ impl Session for MockClientSession {
         Ok(())
     }
 
-    fn send_system_msg(&self, player: Objid, msg: &str) -> Result<(), SessionError> {
+    fn send_connection_event(
+        &self,
+        player: Objid,
+        msg: NarrativeEvent,
+    ) -> Result<(), SessionError> {
+        // There's only the one connection.
+        self.send_event(player, msg)
+    }
+
+    fn send_system_msg(
+        &self,
+        player: Objid,
+        msg: &str,
+        content_type: Option<&str>,
+    ) -> Result<(), SessionError> {
+        let mut system = self.system.write().unwrap();
+        match content_type {
+            Some(content_type) => system.push(format!("{}: [{}] {}", player.0, content_type, msg)),
+            None => system.push(format!("{}: {}", player.0, msg)),
+        }
+        Ok(())
+    }
+
+    fn send_presence(
+        &self,
+        player: Objid,
+        who: Objid,
+        kind: PresenceKind,
+    ) -> Result<(), SessionError> {
         self.system
             .write()
             .unwrap()
-            .push(format!("{}: {}", player.0, msg));
+            .push(format!("{}: presence {} {}", player.0, who.0, kind));
         Ok(())
     }
 
@@ -280,7 +439,27 @@ impl Session for MockClientSession {
         Ok(())
     }
 
-    fn connected_players(&self) -> Result<Vec<Objid>, SessionError> {
+    fn disconnect_connection(&self, _player: Objid) -> Result<(), SessionError> {
+        let mut system = self.system.write().unwrap();
+        system.push(String::from("disconnect_connection"));
+        Ok(())
+    }
+
+    fn revoke_tokens(&self, player: Objid) -> Result<(), SessionError> {
+        let mut system = self.system.write().unwrap();
+        system.push(format!("revoke_tokens {}", player.0));
+        Ok(())
+    }
+
+    fn listeners(&self) -> Result<Vec<ListenerPoint>, SessionError> {
+        Ok(vec![standalone_listener()])
+    }
+
+    fn connected_players(
+        &self,
+        _include_all: bool,
+        _max_idle: Option<f64>,
+    ) -> Result<Vec<Objid>, SessionError> {
         Ok(vec![])
     }
 
@@ -291,4 +470,23 @@ impl Session for MockClientSession {
     fn idle_seconds(&self, _player: Objid) -> Result<f64, SessionError> {
         Ok(0.0)
     }
+
+    fn open_network_connection(&self, _host: &str, _port: u16) -> Result<Objid, SessionError> {
+        Err(SessionError::OutboundConnectionFailed(
+            "no network available".to_string(),
+        ))
+    }
+
+    fn set_connection_option(
+        &self,
+        _player: Objid,
+        _option: &str,
+        _value: Var,
+    ) -> Result<(), SessionError> {
+        Ok(())
+    }
+
+    fn connection_options(&self, _player: Objid) -> Result<Vec<(String, Var)>, SessionError> {
+        Ok(vec![])
+    }
 }
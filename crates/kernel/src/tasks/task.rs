@@ -15,10 +15,11 @@
 use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, TryRecvError};
 
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 use tracing::{debug, error, trace, warn};
 
+use moor_values::model::Breakpoint;
 use moor_values::model::CommandError::PermissionDenied;
 use moor_values::model::VerbInfo;
 use moor_values::model::{CommandError, CommitResult, WorldStateError};
@@ -28,13 +29,17 @@ use moor_values::var::{v_int, v_string};
 use moor_values::var::{List, Objid};
 use moor_values::NOTHING;
 
+use crate::config::Config;
 use crate::matching::match_env::MatchEnvironmentParseMatcher;
 use crate::matching::ws_match_env::WsMatchEnv;
 use crate::tasks::command_parse::{parse_command, ParseCommandError, ParsedCommand};
 
+use crate::tasks::priority::{ForegroundGuard, ForegroundTasks};
+use crate::tasks::server_options::ServerOptions;
 use crate::tasks::sessions::Session;
 use crate::tasks::task_messages::{SchedulerControlMsg, TaskControlMsg, TaskStart};
 use crate::tasks::vm_host::{VMHostResponse, VmHost};
+use crate::tasks::CommitHook;
 use crate::tasks::{PhantomUnsend, PhantomUnsync, TaskDescription, TaskId, VerbCall};
 
 /// A task is a concurrent, transactionally isolated, thread of execution. It starts with the
@@ -67,6 +72,17 @@ pub(crate) struct Task {
     pub(crate) perms: Objid,
     /// The actual VM host which is managing the execution of this task.
     pub(crate) vm_host: VmHost,
+    /// Whether this task runs at background priority. Foreground tasks become background ones
+    /// once they've suspended and resumed.
+    pub(crate) is_background: bool,
+    /// The limits to switch to if we become a background task.
+    pub(crate) server_options: ServerOptions,
+    /// The foreground tasks which, if we're a background task, we give way to.
+    pub(crate) foreground: ForegroundTasks,
+    /// If we're a running foreground task, our place among them.
+    pub(crate) foreground_guard: Option<ForegroundGuard>,
+    /// What to run against our transaction before committing a successful result, if anything.
+    pub(crate) commit_hook: Option<CommitHook>,
     /// Should I die?
     pub(crate) done: bool,
 
@@ -74,65 +90,42 @@ pub(crate) struct Task {
     unsync: PhantomUnsync,
 }
 
-// TODO Propagate default ticks, seconds values from global config / args properly.
-//   Note these can be overridden in-core as well, server_options, will need caching, etc.
-const DEFAULT_FG_TICKS: usize = 60_000;
-const DEFAULT_BG_TICKS: usize = 30_000;
-const DEFAULT_FG_SECONDS: u64 = 5;
-const DEFAULT_BG_SECONDS: u64 = 3;
-const DEFAULT_MAX_STACK_DEPTH: usize = 50;
-
-fn max_vm_values(_ws: &mut dyn WorldState, is_background: bool) -> (usize, u64, usize) {
-    let (max_ticks, max_seconds, max_stack_depth) = if is_background {
-        (
-            DEFAULT_BG_TICKS,
-            DEFAULT_BG_SECONDS,
-            DEFAULT_MAX_STACK_DEPTH,
-        )
-    } else {
-        (
-            DEFAULT_FG_TICKS,
-            DEFAULT_FG_SECONDS,
-            DEFAULT_MAX_STACK_DEPTH,
-        )
-    };
-
-    //
-    // // Look up fg_ticks, fg_seconds, and max_stack_depth on $server_options.
-    // // These are optional properties, and if they are not set, we use the defaults.
-    // let wizperms = PermissionsContext::root_for(Objid(2), BitEnum::new_with(ObjFlag::Wizard));
-    // if let Ok(server_options) = ws
-    //     .retrieve_property(wizperms.clone(), Objid(0), "server_options")
-    //
-    // {
-    //     if let Variant::Obj(server_options) = server_options.variant() {
-    //         if let Ok(v) = ws
-    //             .retrieve_property(wizperms.clone(), *server_options, "fg_ticks")
-    //
-    //         {
-    //             if let Variant::Int(v) = v.variant() {
-    //                 max_ticks = *v as usize;
-    //             }
-    //         }
-    //         if let Ok(v) = ws
-    //             .retrieve_property(wizperms.clone(), *server_options, "fg_seconds")
-    //
-    //         {
-    //             if let Variant::Int(v) = v.variant() {
-    //                 max_seconds = *v as u64;
-    //             }
-    //         }
-    //         if let Ok(v) = ws
-    //             .retrieve_property(wizperms, *server_options, "max_stack_depth")
-    //
-    //         {
-    //             if let Variant::Int(v) = v.variant() {
-    //                 max_stack_depth = *v as usize;
-    //             }
-    //         }
-    //     }
-    // }
-    (max_ticks, max_seconds, max_stack_depth)
+/// How many of the heaviest tick consumers to report when a profiled task is aborted.
+const MAX_REPORTED_TICK_CONSUMERS: usize = 5;
+
+/// Describe a task which is still waiting out its start delay, from what it was asked to run.
+fn describe_delayed(
+    task_id: TaskId,
+    perms: Objid,
+    task_start: &TaskStart,
+    start_time: SystemTime,
+) -> TaskDescription {
+    match task_start {
+        TaskStart::StartFork { fork_request, .. } => {
+            let activation = &fork_request.activation;
+            TaskDescription {
+                task_id,
+                start_time: Some(start_time),
+                permissions: activation.permissions,
+                verb_name: activation.verb_name.clone(),
+                verb_definer: activation.verb_definer(),
+                line_number: activation
+                    .frame
+                    .find_line_no(activation.frame.pc)
+                    .unwrap_or(0),
+                this: activation.this,
+            }
+        }
+        _ => TaskDescription {
+            task_id,
+            start_time: Some(start_time),
+            permissions: perms,
+            verb_name: String::new(),
+            verb_definer: NOTHING,
+            line_number: 0,
+            this: NOTHING,
+        },
+    }
 }
 
 impl Task {
@@ -148,25 +141,60 @@ impl Task {
         session: Arc<dyn Session>,
         task_control_receiver: Receiver<TaskControlMsg>,
         control_sender: Sender<(TaskId, SchedulerControlMsg)>,
+        config: Arc<Config>,
+        server_options: ServerOptions,
+        breakpoints: Vec<Breakpoint>,
+        foreground: ForegroundTasks,
+        commit_hook: Option<CommitHook>,
     ) {
         // TODO: Defer task delay to the scheduler, and let it handle the delay?
         //   Instead of performing it in the task startup.
         if let Some(delay) = delay_start {
-            std::thread::sleep(delay);
+            // Stay responsive while we wait: a kill should cancel us now, not once the delay is
+            // up, and a listing of queued tasks still needs an answer from us.
+            let start_time = SystemTime::now() + delay;
+            let deadline = Instant::now() + delay;
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                match task_control_receiver.recv_timeout(remaining) {
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        warn!(task_id, "Channel closed before delayed task started");
+                        return;
+                    }
+                    Ok(TaskControlMsg::Abort) => {
+                        trace!(task_id, "Delayed task aborted before it started");
+                        control_sender
+                            .send((task_id, SchedulerControlMsg::TaskAbortCancelled))
+                            .expect("Could not send abort cancelled");
+                        return;
+                    }
+                    Ok(TaskControlMsg::Describe(reply_sender)) => {
+                        reply_sender
+                            .send(describe_delayed(task_id, perms, &task_start, start_time))
+                            .expect("Could not send task description");
+                    }
+                    Ok(_) => {
+                        warn!(
+                            task_id,
+                            "Unexpected control message for delayed task; ignoring"
+                        );
+                    }
+                }
+            }
         }
 
         // Start the transaction.
-        let mut world_state = state_source
+        let world_state = state_source
             .new_world_state()
             .expect("Could not start transaction for new task");
 
-        // Find out max ticks, etc. for this task. These are either pulled from server constants in
-        // the DB or from default constants.
-        let (max_ticks, max_seconds, max_stack_depth) =
-            max_vm_values(world_state.as_mut(), is_background);
+        // Find out max ticks, etc. for this task. These are either the defaults, or whatever the
+        // scheduler last loaded from `$server_options`.
+        let (max_ticks, max_seconds, max_stack_depth) = server_options.max_vm_values(is_background);
 
         let scheduler_control_sender = control_sender.clone();
-        let vm_host = VmHost::new(
+        let mut vm_host = VmHost::new(
             task_id,
             max_stack_depth,
             max_ticks,
@@ -174,12 +202,34 @@ impl Task {
             session.clone(),
             scheduler_control_sender.clone(),
         );
+        if let Some(interval) = config.tick_profile_interval {
+            vm_host.enable_tick_profile(interval);
+        }
+        if let Some(seed) = config.random_seed {
+            vm_host.seed_random(seed);
+        }
+        if !breakpoints.is_empty() {
+            vm_host.enable_debugger(breakpoints);
+        }
+        if is_background {
+            vm_host.set_background(
+                max_ticks,
+                Duration::from_secs(max_seconds),
+                foreground.background_tick_slice(),
+            );
+        }
+        vm_host.set_config(config.clone());
         let mut task = Task {
             task_id,
             task_start,
             scheduled_start_time: None,
             scheduler_control_sender: scheduler_control_sender.clone(),
             vm_host,
+            is_background,
+            server_options,
+            foreground,
+            foreground_guard: None,
+            commit_hook,
             world_state,
             perms,
             done: false,
@@ -196,6 +246,7 @@ impl Task {
         trace!(task_id = ?task.task_id, "Task started");
         while !task.done {
             if task.vm_host.is_running() {
+                task.wait_turn();
                 let vm_continuation = task.vm_dispatch();
                 if let Some(scheduler_msg) = vm_continuation {
                     scheduler_control_sender
@@ -223,6 +274,8 @@ impl Task {
                     }
                 }
             } else {
+                // Not running, so not holding up anyone else.
+                task.foreground_guard = None;
                 match task_control_receiver.recv_timeout(Duration::from_millis(50)) {
                     Ok(msg) => msg,
                     Err(RecvTimeoutError::Timeout) => {
@@ -250,6 +303,16 @@ impl Task {
         }
     }
 
+    /// Before running our next slice: if we're a background task, give way to any running
+    /// foreground tasks; if we're a foreground task, make sure background ones know we're running.
+    fn wait_turn(&mut self) {
+        if self.is_background {
+            self.foreground.yield_to_foreground();
+        } else if self.foreground_guard.is_none() {
+            self.foreground_guard = Some(self.foreground.enter());
+        }
+    }
+
     /// Set the task up to start executing, based on the task start configuration.
     fn setup_task_start(&mut self, task_start: TaskStart) -> bool {
         match task_start {
@@ -394,7 +457,7 @@ impl Task {
                 let resume_time = delay.map(|delay| SystemTime::now() + delay);
                 Some(SchedulerControlMsg::TaskSuspend(resume_time))
             }
-            VMHostResponse::SuspendNeedInput => {
+            VMHostResponse::SuspendNeedInput(input_player) => {
                 trace!(task_id = self.task_id, "Task suspend need input");
 
                 // VMHost is now suspended for input, and we'll be waiting for a ResumeReceiveInput
@@ -412,7 +475,16 @@ impl Task {
                 trace!(task_id = self.task_id, "Task suspended for input");
                 self.vm_host.stop();
 
-                Some(SchedulerControlMsg::TaskRequestInput)
+                Some(SchedulerControlMsg::TaskRequestInput(input_player))
+            }
+            VMHostResponse::DebugPause(paused) => {
+                trace!(task_id = self.task_id, ?paused, "Task paused for debugger");
+
+                // Unlike a suspend, the transaction stays open, so that the task carries on
+                // exactly where it was when it's resumed.
+                self.vm_host.stop();
+
+                Some(SchedulerControlMsg::TaskDebugPause(paused))
             }
             VMHostResponse::ContinueOk => {
                 self.done = false;
@@ -421,6 +493,10 @@ impl Task {
             VMHostResponse::CompleteSuccess(result) => {
                 trace!(task_id = self.task_id, result = ?result, "Task complete, success");
 
+                if let Some(commit_hook) = &self.commit_hook {
+                    commit_hook(self.world_state.as_ref(), &result);
+                }
+
                 let CommitResult::Success =
                     self.world_state.commit().expect("Could not attempt commit")
                 else {
@@ -471,7 +547,11 @@ impl Task {
                     .rollback()
                     .expect("Could not rollback world state");
 
-                Some(SchedulerControlMsg::TaskAbortLimitsReached(reason))
+                let top_consumers = self.vm_host.top_tick_consumers(MAX_REPORTED_TICK_CONSUMERS);
+                Some(SchedulerControlMsg::TaskAbortLimitsReached(
+                    reason,
+                    top_consumers,
+                ))
             }
             VMHostResponse::RollbackRetry => {
                 warn!(task_id = self.task_id, "Task rollback requested, retrying");
@@ -502,6 +582,17 @@ impl Task {
                     .new_world_state()
                     .expect("Unable to start new transaction");
                 self.scheduled_start_time = None;
+                // Having suspended, we carry on in the background, like a forked task.
+                if !self.is_background {
+                    let (max_ticks, max_seconds, _) = self.server_options.max_vm_values(true);
+                    self.vm_host.set_background(
+                        max_ticks,
+                        Duration::from_secs(max_seconds),
+                        self.foreground.background_tick_slice(),
+                    );
+                    self.is_background = true;
+                    self.foreground_guard = None;
+                }
                 self.vm_host.resume_execution(value);
                 None
             }
@@ -529,6 +620,30 @@ impl Task {
                 self.vm_host.resume_execution(v_string(input));
                 None
             }
+            TaskControlMsg::DebugResume(step) => {
+                trace!(
+                    task_id = self.task_id,
+                    step,
+                    "Resuming task from debugger pause"
+                );
+                assert!(!self.vm_host.is_running());
+                self.vm_host.debug_resume(step);
+                None
+            }
+            TaskControlMsg::GetLocals(reply_sender) => {
+                reply_sender
+                    .send(self.vm_host.locals())
+                    .expect("Could not send task locals");
+                None
+            }
+            TaskControlMsg::SetLocal(name, value, reply_sender) => {
+                // Changing variables out from under running code would be chaos.
+                let set = !self.vm_host.is_running() && self.vm_host.set_local(&name, value);
+                reply_sender
+                    .send(set)
+                    .expect("Could not send set local result");
+                None
+            }
             TaskControlMsg::Abort => {
                 // We've been asked to die. Go tell the VM host to abort, and roll back the
                 // transaction.
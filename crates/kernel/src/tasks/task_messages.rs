@@ -13,14 +13,16 @@
 //
 
 use crate::tasks::scheduler::AbortLimitReason;
+use crate::tasks::server_options::ServerOptions;
 use crate::tasks::{TaskDescription, TaskId};
+use crate::textdump::ExportFormat;
 use crate::vm::vm_unwind::UncaughtException;
-use crate::vm::Fork;
+use crate::vm::{Fork, TickSite};
 use std::sync::Arc;
 
 use moor_compiler::Program;
 
-use moor_values::model::{CommandError, NarrativeEvent};
+use moor_values::model::{CommandError, NarrativeEvent, PausedActivation};
 use moor_values::model::{Perms, WorldStateSource};
 use moor_values::var::Var;
 use moor_values::var::{List, Objid};
@@ -68,6 +70,15 @@ pub enum TaskControlMsg {
     ///   described, so I need to rethink this. Right now this is prevented by the
     ///   runtime, but it's not a good design.
     Describe(oneshot::Sender<TaskDescription>),
+    /// The scheduler is telling the task, which is paused for the debugger, to carry on: until
+    /// the next breakpoint, or if `true`, for just one opcode.
+    DebugResume(bool),
+    /// The scheduler is asking the task, which is paused for the debugger, for the local
+    /// variables of the verb it's paused in.
+    GetLocals(oneshot::Sender<Vec<(String, Var)>>),
+    /// The scheduler is telling the task, which is paused for the debugger, to set a local
+    /// variable of the verb it's paused in. The reply is false if there's no such variable.
+    SetLocal(String, Var, oneshot::Sender<bool>),
     /// The scheduler is telling the task to abort itself.
     Abort,
 }
@@ -90,14 +101,28 @@ pub enum SchedulerControlMsg {
     TaskRequestFork(Fork, oneshot::Sender<TaskId>),
     /// The task is letting us know it was cancelled.
     TaskAbortCancelled,
-    /// The task is letting us know that it has reached its abort limits.
-    TaskAbortLimitsReached(AbortLimitReason),
+    /// The task is letting us know that it has reached its abort limits, along with where it
+    /// spent the most ticks (if tick profiling is enabled).
+    TaskAbortLimitsReached(AbortLimitReason, Vec<(TickSite, usize)>),
     /// Tell the scheduler that the task in a suspended state, with a time to resume (if any)
     TaskSuspend(Option<SystemTime>),
-    /// Tell the scheduler we're suspending until we get input from the client.
-    TaskRequestInput,
+    /// Tell the scheduler we're suspending until we get input from the given player's client.
+    TaskRequestInput(Objid),
+    /// Tell the scheduler the task has paused for the debugger, and where.
+    TaskDebugPause(PausedActivation),
     /// Task is requesting a list of all other tasks known to the scheduler.
     DescribeOtherTasks(oneshot::Sender<Vec<TaskDescription>>),
+    /// Task is requesting the ids of the tasks it has forked which are still around.
+    ForkedTasks(oneshot::Sender<Vec<TaskId>>),
+    /// Task is requesting that the scheduler abort all the tasks it has forked, replying with how
+    /// many that was.
+    KillForks(oneshot::Sender<usize>),
+    /// Task is requesting the counts of running and queued tasks, for just the given player's
+    /// tasks if one is given.
+    QueueInfo {
+        player: Option<Objid>,
+        reply: oneshot::Sender<(usize, usize)>,
+    },
     /// Task is requesting that the scheduler abort another task.
     KillTask {
         victim_task_id: TaskId,
@@ -115,13 +140,30 @@ pub enum SchedulerControlMsg {
     BootPlayer {
         player: Objid,
         sender_permissions: Perms,
+        /// Only drop the connection the task is running on behalf of, rather than all of them.
+        connection_only: bool,
     },
+    /// Task has re-read `$server_options`, and new tasks should use these limits from now on.
+    SetServerOptions(ServerOptions),
     /// Task is requesting that a textdump checkpoint happen, to the configured file.
     Checkpoint,
+    /// Task is requesting that the whole database be written to the named file in the configured
+    /// export directory, in the given format. The reply comes once it's been written: true, or
+    /// the error to raise.
+    ExportDatabase {
+        name: String,
+        format: ExportFormat,
+        result_sender: oneshot::Sender<Var>,
+    },
     Notify {
         player: Objid,
         event: NarrativeEvent,
     },
+    /// Task wants to notify only the connection it's running for, which is one of `player`'s.
+    NotifyConnection {
+        player: Objid,
+        event: NarrativeEvent,
+    },
     /// Task requesting shutdown
     Shutdown(Option<String>),
 }
@@ -12,14 +12,16 @@
 // this program. If not, see <https://www.gnu.org/licenses/>.
 //
 
+use crate::config::Config;
 use crate::tasks::command_parse::ParsedCommand;
 use crate::tasks::scheduler::AbortLimitReason;
 use crate::tasks::sessions::Session;
 use crate::tasks::task_messages::SchedulerControlMsg;
 use crate::tasks::vm_host::VMHostResponse::{AbortLimit, ContinueOk, DispatchFork, Suspend};
 use crate::tasks::{PhantomUnsend, PhantomUnsync, TaskId, VerbCall};
-use crate::vm::{ExecutionResult, Fork, VerbExecutionRequest, VM};
-use crate::vm::{FinallyReason, VMExecState};
+use crate::vm::verb_metrics;
+use crate::vm::{Debugger, FinallyReason, VMExecState};
+use crate::vm::{ExecutionResult, Fork, TickProfile, TickSite, VerbExecutionRequest, VM};
 use crate::vm::{UncaughtException, VmExecParams};
 use bytes::Bytes;
 use crossbeam_channel::Sender;
@@ -28,11 +30,14 @@ use moor_compiler::{compile, Name};
 use moor_values::model::VerbInfo;
 use moor_values::model::WorldState;
 use moor_values::model::{BinaryType, ObjFlag};
+use moor_values::model::{Breakpoint, PausedActivation};
 use moor_values::var::Var;
 use moor_values::var::{List, Objid};
 use moor_values::AsByteBuffer;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 use tracing::{debug, error, trace, warn};
 
 /// Return values from exec_interpreter back to the Task scheduler loop
@@ -43,8 +48,10 @@ pub enum VMHostResponse {
     DispatchFork(Fork),
     /// Tell the task to suspend us.
     Suspend(Option<Duration>),
-    /// Tell the task Johnny 5 needs input from the client (`read` invocation).
-    SuspendNeedInput,
+    /// Tell the task Johnny 5 needs input from the given player's client (`read` invocation).
+    SuspendNeedInput(Objid),
+    /// Tell the task we've paused for the debugger, here.
+    DebugPause(PausedActivation),
     /// Task timed out or exceeded ticks.
     AbortLimit(AbortLimitReason),
     /// Tell the task that execution has completed, and the task is successful.
@@ -69,6 +76,9 @@ pub struct VmHost {
     max_ticks: usize,
     /// The maximum amount of time allotted to this task
     max_time: Duration,
+    /// If this is a background task, the (short) slices it runs in, so it can give way to
+    /// foreground ones.
+    background_tick_slice: Option<usize>,
     sessions: Arc<dyn Session>,
     scheduler_control_sender: Sender<(TaskId, SchedulerControlMsg)>,
     running: bool,
@@ -96,6 +106,7 @@ impl VmHost {
             max_stack_depth,
             max_ticks,
             max_time,
+            background_tick_slice: None,
             sessions,
             scheduler_control_sender,
             running: false,
@@ -227,10 +238,22 @@ impl VmHost {
         };
 
         // Grant the loop its next tick slice.
-        self.vm_exec_state.tick_slice = self.max_ticks - self.vm_exec_state.tick_count;
+        let remaining_ticks = self.max_ticks - self.vm_exec_state.tick_count;
+        self.vm_exec_state.tick_slice = match self.background_tick_slice {
+            Some(slice) => remaining_ticks.min(slice),
+            None => remaining_ticks,
+        };
 
         let pre_exec_tick_count = self.vm_exec_state.tick_count;
 
+        // The slice runs (at most) the verb on top of the stack, so that's who its time goes to.
+        let running_verb = self
+            .vm_exec_state
+            .stack
+            .last()
+            .and_then(verb_metrics::counters_for)
+            .map(|counters| (counters, Instant::now()));
+
         // Actually invoke the VM, asking it to loop until it's ready to yield back to us.
         let mut result = self.vm.exec(
             &exec_params,
@@ -239,6 +262,10 @@ impl VmHost {
             self.sessions.clone(),
         );
 
+        if let Some((counters, exec_start)) = running_verb {
+            counters.add_time(exec_start.elapsed());
+        }
+
         let post_exec_tick_count = self.vm_exec_state.tick_count;
         trace!(
             task_id,
@@ -319,8 +346,12 @@ impl VmHost {
                 ExecutionResult::Suspend(delay) => {
                     return Suspend(delay);
                 }
-                ExecutionResult::NeedInput => {
-                    return VMHostResponse::SuspendNeedInput;
+                ExecutionResult::NeedInput(player) => {
+                    return VMHostResponse::SuspendNeedInput(player);
+                }
+                ExecutionResult::DebugPause => {
+                    trace!(task_id, "Task paused for debugger");
+                    return VMHostResponse::DebugPause(self.paused_activation());
                 }
                 ExecutionResult::Complete(a) => {
                     trace!(task_id, "Task completed");
@@ -366,6 +397,46 @@ impl VmHost {
         debug!(task_id = self.vm_exec_state.task_id, "Resuming VMHost");
     }
 
+    /// Carry on after pausing for the debugger: until the next breakpoint, or if `step`, for
+    /// just one opcode.
+    pub fn debug_resume(&mut self, step: bool) {
+        let Some(debugger) = self.vm_exec_state.debugger.as_mut() else {
+            warn!(
+                task_id = self.vm_exec_state.task_id,
+                "Debug resume for task not being debugged"
+            );
+            return;
+        };
+        debugger.resume(step);
+        // Time spent paused doesn't count against the task.
+        self.vm_exec_state.start_time = Some(SystemTime::now());
+        self.running = true;
+    }
+
+    /// Where we are, for the debugger.
+    pub fn paused_activation(&self) -> PausedActivation {
+        let top = self.vm_exec_state.top();
+        PausedActivation {
+            definer: top.verb_definer(),
+            verb_name: top.verb_name.clone(),
+            line: top.frame.find_line_no(top.frame.pc + 1).unwrap_or(0),
+            this: top.this,
+            player: top.player,
+            locals: top.frame.locals(),
+        }
+    }
+
+    /// The local variables of the activation on top of the stack.
+    pub fn locals(&self) -> Vec<(String, Var)> {
+        self.vm_exec_state.top().frame.locals()
+    }
+
+    /// Set a local variable of the activation on top of the stack, returning false if it has no
+    /// variable by that name.
+    pub fn set_local(&mut self, name: &str, value: Var) -> bool {
+        self.vm_exec_state.top_mut().frame.set_local(name, value)
+    }
+
     pub fn is_running(&self) -> bool {
         self.running
     }
@@ -410,6 +481,44 @@ impl VmHost {
             .unwrap_or(0)
     }
 
+    /// Run as a background task from here on, with the given (background) limits, in slices of
+    /// `tick_slice` ticks.
+    pub fn set_background(&mut self, max_ticks: usize, max_time: Duration, tick_slice: usize) {
+        self.background_tick_slice = Some(tick_slice);
+        self.max_ticks = max_ticks;
+        self.max_time = max_time;
+        self.vm_exec_state.maximum_time = Some(max_time);
+    }
+
+    /// Pause this task at any of the given breakpoints it reaches.
+    pub fn enable_debugger(&mut self, breakpoints: Vec<Breakpoint>) {
+        self.vm_exec_state.debugger = Some(Debugger::new(breakpoints));
+    }
+
+    /// Start sampling where this task spends its ticks, every `interval` ticks.
+    pub fn enable_tick_profile(&mut self, interval: usize) {
+        self.vm_exec_state.tick_profile = Some(TickProfile::new(interval));
+    }
+
+    /// Seed this task's random number generator, so its `random()` results are reproducible.
+    pub fn seed_random(&mut self, seed: u64) {
+        self.vm_exec_state.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Make the server's configuration available to the builtins this task calls.
+    pub fn set_config(&mut self, config: Arc<Config>) {
+        self.vm_exec_state.config = config;
+    }
+
+    /// The `n` heaviest tick consumers so far, if profiling is enabled.
+    pub fn top_tick_consumers(&self, n: usize) -> Vec<(TickSite, usize)> {
+        self.vm_exec_state
+            .tick_profile
+            .as_ref()
+            .map(|p| p.top_consumers(n))
+            .unwrap_or_default()
+    }
+
     pub fn reset_ticks(&mut self) {
         self.vm_exec_state.tick_count = 0;
     }
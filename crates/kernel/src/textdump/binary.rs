@@ -0,0 +1,149 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! A binary alternative to the textdump format, for moving whole databases between moor servers:
+//! after a header line, a stream of bincode records (the version & users, then each object, then
+//! each verb program), each prefixed by its length as a little-endian u32.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+
+use bincode::{Decode, Encode};
+
+use moor_values::var::Objid;
+use moor_values::BINCODE_CONFIG;
+
+use crate::textdump::read::TextdumpReaderError;
+use crate::textdump::write_db::TextdumpSource;
+use crate::textdump::{Object, Textdump, Verb};
+
+const BINARY_DUMP_HEADER: &str = "** moor Binary Dump Version 1 **\n";
+
+/// A record as written, borrowing from the textdump so nothing needs copying.
+#[derive(Encode)]
+enum DumpRecordRef<'a> {
+    Header {
+        version: &'a str,
+        users: &'a [Objid],
+    },
+    Object(&'a Object),
+    Verb(&'a Verb),
+}
+
+/// A record as read back. Must have the same shape as `DumpRecordRef`.
+#[derive(Decode)]
+enum DumpRecord {
+    Header { version: String, users: Vec<Objid> },
+    Object(Object),
+    Verb(Verb),
+}
+
+fn write_record<W: Write>(writer: &mut W, record: &DumpRecordRef) -> Result<(), io::Error> {
+    let bytes = bincode::encode_to_vec(record, *BINCODE_CONFIG)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(&bytes)
+}
+
+pub fn write_binary_dump<W: Write>(writer: &mut W, textdump: &Textdump) -> Result<(), io::Error> {
+    writer.write_all(BINARY_DUMP_HEADER.as_bytes())?;
+    write_record(
+        writer,
+        &DumpRecordRef::Header {
+            version: &textdump.version,
+            users: &textdump.users,
+        },
+    )?;
+    for o in textdump.objects.values() {
+        write_record(writer, &DumpRecordRef::Object(o))?;
+    }
+    for v in textdump.verbs.values() {
+        write_record(writer, &DumpRecordRef::Verb(v))?;
+    }
+    Ok(())
+}
+
+/// Write the same as `write_binary_dump` would, but straight from a snapshot, an object at a time.
+pub fn write_binary_dump_source<W: Write>(
+    writer: &mut W,
+    source: &TextdumpSource,
+    version: &str,
+) -> Result<(), io::Error> {
+    writer.write_all(BINARY_DUMP_HEADER.as_bytes())?;
+    write_record(
+        writer,
+        &DumpRecordRef::Header {
+            version,
+            users: source.users(),
+        },
+    )?;
+    for objid in source.object_ids() {
+        write_record(writer, &DumpRecordRef::Object(&source.object(*objid)))?;
+    }
+    for objid in source.object_ids() {
+        for v in source.verbs(*objid) {
+            write_record(writer, &DumpRecordRef::Verb(&v))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn read_binary_dump<R: Read>(reader: R) -> Result<Textdump, TextdumpReaderError> {
+    let mut reader = BufReader::new(reader);
+    let mut header = String::new();
+    reader
+        .read_line(&mut header)
+        .map_err(TextdumpReaderError::IoError)?;
+    if header != BINARY_DUMP_HEADER {
+        return Err(TextdumpReaderError::ParseError(format!(
+            "not a binary dump (header: {:?})",
+            header.trim_end()
+        )));
+    }
+
+    let mut textdump = Textdump {
+        version: String::new(),
+        objects: BTreeMap::new(),
+        users: vec![],
+        verbs: BTreeMap::new(),
+    };
+    loop {
+        let mut len = [0u8; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(TextdumpReaderError::IoError(e)),
+        }
+        let mut bytes = vec![0u8; u32::from_le_bytes(len) as usize];
+        reader
+            .read_exact(&mut bytes)
+            .map_err(TextdumpReaderError::IoError)?;
+        let (record, _): (DumpRecord, _) = bincode::decode_from_slice(&bytes, *BINCODE_CONFIG)
+            .map_err(|e| TextdumpReaderError::ParseError(format!("bad record: {}", e)))?;
+        match record {
+            DumpRecord::Header { version, users } => {
+                textdump.version = version;
+                textdump.users = users;
+            }
+            DumpRecord::Object(o) => {
+                textdump.objects.insert(o.id, o);
+            }
+            DumpRecord::Verb(v) => {
+                textdump.verbs.insert((v.objid, v.verbnum), v);
+            }
+        }
+    }
+    Ok(textdump)
+}
@@ -0,0 +1,65 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use moor_db::loader::LoaderInterface;
+
+use crate::textdump::{write_binary_dump_source, TextdumpSource, TextdumpWriter};
+
+/// The version line we put on textdumps; just to be compatible with LambdaMOO import for now,
+/// hopefully.
+const EXPORT_TEXTDUMP_VERSION: &str = "** LambdaMOO Database, Format Version 4 **";
+
+/// The formats the whole database can be written out in.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ExportFormat {
+    /// LambdaMOO-compatible textdump.
+    Textdump,
+    /// moor's own binary dump; see `write_binary_dump`.
+    Binary,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "textdump" => Some(Self::Textdump),
+            "binary" => Some(Self::Binary),
+            _ => None,
+        }
+    }
+}
+
+/// Write everything visible to the given loader transaction out to `path`, in the given format.
+/// Objects are written as they're read, so the database is never held in memory all at once.
+pub fn export_database(
+    loader: &dyn LoaderInterface,
+    path: &Path,
+    format: ExportFormat,
+) -> Result<(), io::Error> {
+    let source = TextdumpSource::new(loader);
+    let mut output = BufWriter::new(File::create(path)?);
+    match format {
+        ExportFormat::Textdump => {
+            TextdumpWriter::new(&mut output).write_source(&source, EXPORT_TEXTDUMP_VERSION)?
+        }
+        ExportFormat::Binary => {
+            write_binary_dump_source(&mut output, &source, EXPORT_TEXTDUMP_VERSION)?
+        }
+    }
+    output.flush()
+}
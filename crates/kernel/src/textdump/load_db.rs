@@ -31,13 +31,14 @@ use moor_values::model::{ArgSpec, PrepSpec, VerbArgsSpec};
 use moor_values::model::{ObjAttrs, ObjFlag};
 use moor_values::util::BitEnum;
 use moor_values::var::Objid;
-use moor_values::var::Var;
+use moor_values::var::{v_list, v_objid, Var, Variant};
 use moor_values::{AsByteBuffer, NOTHING};
 
 use crate::textdump::read::TextdumpReaderError;
 use crate::textdump::{
-    Object, TextdumpReader, PREP_ANY, PREP_NONE, VF_ASPEC_ANY, VF_ASPEC_NONE, VF_ASPEC_THIS,
-    VF_DEBUG, VF_DOBJSHIFT, VF_EXEC, VF_IOBJSHIFT, VF_OBJMASK, VF_PERMMASK, VF_READ, VF_WRITE,
+    Object, Propval, Textdump, TextdumpReader, Verb, Verbdef, PREP_ANY, PREP_NONE, VF_ASPEC_ANY,
+    VF_ASPEC_NONE, VF_ASPEC_THIS, VF_DEBUG, VF_DOBJSHIFT, VF_EXEC, VF_IOBJSHIFT, VF_OBJMASK,
+    VF_PERMMASK, VF_READ, VF_WRITE,
 };
 
 struct RProp {
@@ -87,8 +88,24 @@ fn cv_aspec_flag(flags: u16) -> ArgSpec {
     }
 }
 
+/// How the object ids in a textdump are treated when it's loaded.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ImportMode {
+    /// Objects keep the ids they have in the textdump. Only sensible for an empty database.
+    #[default]
+    Preserve,
+    /// Objects are given fresh ids after the highest one already in the database, and all the
+    /// references between them are rewritten to match, so a core can be merged into a database
+    /// which already has objects in it.
+    Remap,
+}
+
 #[tracing::instrument(skip(ldr))]
-pub fn textdump_load(ldr: &dyn LoaderInterface, path: PathBuf) -> Result<(), TextdumpReaderError> {
+pub fn textdump_load(
+    ldr: &dyn LoaderInterface,
+    path: PathBuf,
+    mode: ImportMode,
+) -> Result<(), TextdumpReaderError> {
     let textdump_import_span = span!(tracing::Level::INFO, "textdump_import");
     let _enter = textdump_import_span.enter();
 
@@ -97,7 +114,14 @@ pub fn textdump_load(ldr: &dyn LoaderInterface, path: PathBuf) -> Result<(), Tex
 
     let br = BufReader::new(corefile);
 
-    read_textdump(ldr, br)?
+    match mode {
+        ImportMode::Preserve => read_textdump(ldr, br)?,
+        ImportMode::Remap => {
+            let mut tdr = TextdumpReader::new(br);
+            let td = tdr.read_textdump()?;
+            load_textdump_remapped(ldr, &td).map(|_| ())
+        }
+    }
 }
 
 pub fn read_textdump<T: io::Read>(
@@ -107,20 +131,134 @@ pub fn read_textdump<T: io::Read>(
     let mut tdr = TextdumpReader::new(reader);
     let td = tdr.read_textdump()?;
 
+    Ok(load_textdump(loader, &td))
+}
+
+/// Load a textdump into a database which may already have objects in it, giving every object in
+/// the textdump a fresh id. Returns the translation table from textdump ids to database ids.
+/// References to objects the textdump doesn't have become #-1. Object references inside verb
+/// program source are left alone.
+pub fn load_textdump_remapped(
+    loader: &dyn LoaderInterface,
+    td: &Textdump,
+) -> Result<BTreeMap<Objid, Objid>, TextdumpReaderError> {
+    let existing = loader
+        .get_objects()
+        .map_err(|e| TextdumpReaderError::LoadError("listing existing objects".to_string(), e))?;
+    let first = existing.iter().map(|o| o.0 + 1).max().unwrap_or(0);
+
+    // One pass to hand out the new ids...
+    let table: BTreeMap<Objid, Objid> = td
+        .objects
+        .keys()
+        .enumerate()
+        .map(|(i, old)| (*old, Objid(first + i as i64)))
+        .collect();
+
+    // ... and another to rewrite everything which refers to them.
+    let remapped = remap_textdump(td, &table);
+    load_textdump(loader, &remapped)?;
+    Ok(table)
+}
+
+/// Rewrite every object reference in the textdump through `table`. A reference to an object not
+/// in the table would otherwise land on whatever unrelated object has that id in the database, so
+/// it becomes #-1. Negative ids (#-1, $failed_match and so on) aren't references, and are kept.
+fn remap_textdump(td: &Textdump, table: &BTreeMap<Objid, Objid>) -> Textdump {
+    let remap = |o: Objid| match table.get(&o) {
+        Some(new) => *new,
+        None if o.0 < 0 => o,
+        None => NOTHING,
+    };
+    let objects = td
+        .objects
+        .values()
+        .map(|o| {
+            let object = Object {
+                id: remap(o.id),
+                owner: remap(o.owner),
+                location: remap(o.location),
+                contents: remap(o.contents),
+                next: remap(o.next),
+                parent: remap(o.parent),
+                child: remap(o.child),
+                sibling: remap(o.sibling),
+                name: o.name.clone(),
+                flags: o.flags,
+                verbdefs: o
+                    .verbdefs
+                    .iter()
+                    .map(|v| Verbdef {
+                        owner: remap(v.owner),
+                        ..v.clone()
+                    })
+                    .collect(),
+                propdefs: o.propdefs.clone(),
+                propvals: o
+                    .propvals
+                    .iter()
+                    .map(|p| Propval {
+                        value: remap_var(&p.value, &remap),
+                        owner: remap(p.owner),
+                        ..p.clone()
+                    })
+                    .collect(),
+            };
+            (object.id, object)
+        })
+        .collect();
+    let verbs = td
+        .verbs
+        .iter()
+        .map(|((objid, verbnum), verb)| {
+            let verb = Verb {
+                objid: remap(*objid),
+                ..verb.clone()
+            };
+            ((verb.objid, *verbnum), verb)
+        })
+        .collect();
+    Textdump {
+        version: td.version.clone(),
+        objects,
+        users: td.users.iter().map(|u| remap(*u)).collect(),
+        verbs,
+    }
+}
+
+fn remap_var(v: &Var, remap: &impl Fn(Objid) -> Objid) -> Var {
+    match v.variant() {
+        Variant::Obj(o) => v_objid(remap(*o)),
+        Variant::List(l) => {
+            let items: Vec<_> = l.iter().map(|item| remap_var(&item, remap)).collect();
+            v_list(&items)
+        }
+        _ => v.clone(),
+    }
+}
+
+/// Create everything described by an already-read textdump (or binary dump) in the database.
+pub fn load_textdump(
+    loader: &dyn LoaderInterface,
+    td: &Textdump,
+) -> Result<(), TextdumpReaderError> {
     info!("Instantiating objects");
-    for (objid, o) in &td.objects {
-        let flags: BitEnum<ObjFlag> = BitEnum::from_u8(o.flags);
-
-        trace!(
-            objid = ?objid, name=o.name, flags=?flags, "Creating object",
-        );
-        loader
-            .create_object(
-                Some(*objid),
-                &ObjAttrs::new(NOTHING, NOTHING, NOTHING, flags, &o.name),
+    let objects: Vec<_> = td
+        .objects
+        .iter()
+        .map(|(objid, o)| {
+            let flags: BitEnum<ObjFlag> = BitEnum::from_u8(o.flags);
+
+            trace!(
+                objid = ?objid, name=o.name, flags=?flags, "Creating object",
+            );
+            (
+                *objid,
+                ObjAttrs::new(NOTHING, NOTHING, NOTHING, flags, &o.name),
             )
-            .unwrap();
-    }
+        })
+        .collect();
+    loader.create_objects(&objects).unwrap();
 
     info!("Setting object attributes (parent/location/owner)");
     for (objid, o) in &td.objects {
@@ -242,5 +380,5 @@ pub fn read_textdump<T: io::Read>(
 
     info!("Import complete.");
 
-    Ok(Ok(()))
+    Ok(())
 }
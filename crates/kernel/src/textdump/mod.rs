@@ -16,13 +16,21 @@
 /// file.
 use std::collections::BTreeMap;
 
-pub use load_db::{read_textdump, textdump_load};
+use bincode::{Decode, Encode};
+
+pub use binary::{read_binary_dump, write_binary_dump, write_binary_dump_source};
+pub use export::{export_database, ExportFormat};
+pub use load_db::{
+    load_textdump, load_textdump_remapped, read_textdump, textdump_load, ImportMode,
+};
 use moor_values::var::Objid;
 use moor_values::var::Var;
 pub use read::TextdumpReader;
 pub use write::TextdumpWriter;
-pub use write_db::make_textdump;
+pub use write_db::{make_textdump, TextdumpSource};
 
+mod binary;
+mod export;
 mod load_db;
 mod read;
 mod write;
@@ -41,7 +49,7 @@ const VF_ASPEC_NONE: u16 = 0;
 const VF_ASPEC_ANY: u16 = 1;
 const VF_ASPEC_THIS: u16 = 2;
 
-#[derive(Clone)]
+#[derive(Clone, Encode, Decode)]
 pub struct Verbdef {
     pub name: String,
     pub owner: Objid,
@@ -49,7 +57,7 @@ pub struct Verbdef {
     pub prep: i16,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Encode, Decode)]
 pub struct Propval {
     pub value: Var,
     pub owner: Objid,
@@ -57,6 +65,7 @@ pub struct Propval {
     pub is_clear: bool,
 }
 
+#[derive(Encode, Decode)]
 pub struct Object {
     pub id: Objid,
     pub owner: Objid,
@@ -73,7 +82,7 @@ pub struct Object {
     pub propvals: Vec<Propval>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Encode, Decode)]
 pub struct Verb {
     pub objid: Objid,
     pub verbnum: usize,
@@ -19,6 +19,7 @@ use moor_values::var::Objid;
 use moor_values::var::{Var, VarType, Variant};
 
 use crate::textdump::read::TYPE_CLEAR;
+use crate::textdump::write_db::TextdumpSource;
 use crate::textdump::{Object, Propval, Textdump, Verb, Verbdef};
 
 pub struct TextdumpWriter<W: io::Write> {
@@ -109,51 +110,91 @@ impl<W: io::Write> TextdumpWriter<W> {
         Ok(())
     }
 
+    fn write_verb(&mut self, verb: &Verb) -> Result<(), io::Error> {
+        let Some(program) = &verb.program else {
+            return Ok(());
+        };
+
+        writeln!(
+            self.writer,
+            "#{}:{}\n{}\n.",
+            verb.objid.0, verb.verbnum, program
+        )
+    }
+
     fn write_verbs(&mut self, verbs: &BTreeMap<(Objid, usize), Verb>) -> Result<(), io::Error> {
         for verb in verbs.values() {
-            let Some(program) = verb.program.clone() else {
-                continue;
-            };
-
-            writeln!(
-                self.writer,
-                "#{}:{}\n{}\n.",
-                verb.objid.0, verb.verbnum, program
-            )?;
+            self.write_verb(verb)?;
         }
         Ok(())
     }
 
-    pub fn write_textdump(&mut self, textdump: &Textdump) -> Result<(), io::Error> {
-        writeln!(self.writer, "{}", &textdump.version)?;
+    fn write_header(
+        &mut self,
+        version: &str,
+        nobjs: usize,
+        nprogs: usize,
+        users: &[Objid],
+    ) -> Result<(), io::Error> {
+        writeln!(self.writer, "{}", version)?;
+        writeln!(self.writer, "{}\n{}\n0\n{}", nobjs, nprogs, users.len())?;
+        for user in users {
+            writeln!(self.writer, "{}", user.0)?;
+        }
+        Ok(())
+    }
+
+    fn write_trailer(&mut self) -> Result<(), io::Error> {
+        // TODO: Suspended tasks, clocks, queued tasks in textdump write
+        //    actually write clocks/tasks/suspended tasks, but for now we just write 0 for each
+        writeln!(self.writer, "0 clocks")?;
+        writeln!(self.writer, "0 queued tasks")?;
+        writeln!(self.writer, "0 suspended tasks")?;
+
+        Ok(())
+    }
 
+    pub fn write_textdump(&mut self, textdump: &Textdump) -> Result<(), io::Error> {
         // We only count the existence of programs, not verbs, here.
         let nprogs = textdump
             .verbs
             .iter()
             .filter(|(_, v)| v.program.is_some())
             .count();
-        writeln!(
-            self.writer,
-            "{}\n{}\n0\n{}",
+        self.write_header(
+            &textdump.version,
             textdump.objects.len(),
             nprogs,
-            textdump.users.len()
+            &textdump.users,
         )?;
-        for user in &textdump.users {
-            writeln!(self.writer, "{}", user.0)?;
-        }
         for object in textdump.objects.values() {
             self.write_object(object)?;
         }
         self.write_verbs(&textdump.verbs)?;
+        self.write_trailer()
+    }
 
-        // TODO: Suspended tasks, clocks, queued tasks in textdump write
-        //    actually write clocks/tasks/suspended tasks, but for now we just write 0 for each
-        writeln!(self.writer, "0 clocks")?;
-        writeln!(self.writer, "0 queued tasks")?;
-        writeln!(self.writer, "0 suspended tasks")?;
-
-        Ok(())
+    /// Write the same as `write_textdump` would, but straight from a snapshot, an object at a
+    /// time, rather than from a whole `Textdump` built up in memory.
+    pub fn write_source(
+        &mut self,
+        source: &TextdumpSource,
+        version: &str,
+    ) -> Result<(), io::Error> {
+        self.write_header(
+            version,
+            source.object_ids().len(),
+            source.program_count(),
+            source.users(),
+        )?;
+        for objid in source.object_ids() {
+            self.write_object(&source.object(*objid))?;
+        }
+        for objid in source.object_ids() {
+            for verb in source.verbs(*objid) {
+                self.write_verb(&verb)?;
+            }
+        }
+        self.write_trailer()
     }
 }
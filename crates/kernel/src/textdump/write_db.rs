@@ -17,7 +17,7 @@ use std::collections::BTreeMap;
 use moor_compiler::Program;
 use moor_db::loader::LoaderInterface;
 use moor_values::model::{ArgSpec, PrepSpec, ValSet, VerbArgsSpec};
-use moor_values::model::{BinaryType, VerbFlag};
+use moor_values::model::{BinaryType, VerbDef, VerbFlag};
 use moor_values::model::{HasUuid, Named};
 use moor_values::util::BitEnum;
 use moor_values::var::v_none;
@@ -55,63 +55,85 @@ fn cv_arg(flags: BitEnum<VerbFlag>, arg: VerbArgsSpec) -> (u16, i16) {
     (flags | arg_flags, prepflags)
 }
 
-/// Take a transaction, and scan the relations and build a Textdump representing a snapshot of the world as it
-/// exists in the transaction.
-pub fn make_textdump(tx: &dyn LoaderInterface, version: Option<&str>) -> Textdump {
-    // To create the objects list, we need to scan all objects.
-    // For now, the expectation would be we can simply iterate from 0 to max object, checking validity of each
-    // object, and then adding it to the list.
+/// A snapshot of the world as it exists in a transaction, ready to be turned into textdump
+/// objects and verbs one object at a time. Only the ids and how they link together are held in
+/// memory, so a whole database can be written out without building it all up first.
+pub struct TextdumpSource<'a> {
+    tx: &'a dyn LoaderInterface,
+    /// All the object ids, in order.
+    object_ids: Vec<Objid>,
+    /// Parent -> children, and location -> contents, each in id order.
+    children_map: BTreeMap<Objid, Vec<Objid>>,
+    contents_map: BTreeMap<Objid, Vec<Objid>>,
+    users: Vec<Objid>,
+}
 
-    // Find all the ids
-    let object_ids = tx.get_objects().expect("Failed to get objects");
+impl<'a> TextdumpSource<'a> {
+    pub fn new(tx: &'a dyn LoaderInterface) -> Self {
+        // Find all the ids
+        let mut object_ids: Vec<Objid> = tx
+            .get_objects()
+            .expect("Failed to get objects")
+            .iter()
+            .collect();
+        object_ids.sort();
+
+        // Build a map of parent -> children, and the same with location -> contents
+        let mut children_map = BTreeMap::new();
+        let mut contents_map = BTreeMap::new();
+        for id in &object_ids {
+            let obj = tx.get_object(*id).expect("Failed to get object");
+            children_map
+                .entry(obj.parent().unwrap_or(NOTHING))
+                .or_insert_with(Vec::new)
+                .push(*id);
+            contents_map
+                .entry(obj.location().unwrap_or(NOTHING))
+                .or_insert_with(Vec::new)
+                .push(*id);
+        }
 
-    // Retrieve all the objects
-    let mut db_objects = BTreeMap::new();
-    for id in object_ids.iter() {
-        db_objects.insert(id, tx.get_object(id).expect("Failed to get object"));
+        let users = tx
+            .get_players()
+            .expect("Failed to get players list")
+            .iter()
+            .collect();
+
+        Self {
+            tx,
+            object_ids,
+            children_map,
+            contents_map,
+            users,
+        }
     }
 
-    // Build a map of parent -> children
-    let mut children_map = BTreeMap::new();
-    for id in db_objects.keys() {
-        let obj = db_objects.get(id).expect("Failed to get object");
-        let parent = obj.parent().unwrap_or(NOTHING);
-        children_map
-            .entry(parent)
-            .or_insert_with(Vec::new)
-            .push(*id);
+    pub fn object_ids(&self) -> &[Objid] {
+        &self.object_ids
     }
 
-    // Same with location -> contents
-    let mut contents_map = BTreeMap::new();
-    for id in db_objects.keys() {
-        let obj = db_objects.get(id).expect("Failed to get object");
-        let location = obj.location().unwrap_or(NOTHING);
-        contents_map
-            .entry(location)
-            .or_insert_with(Vec::new)
-            .push(*id);
+    pub fn users(&self) -> &[Objid] {
+        &self.users
     }
 
-    // Objid -> Object
-    let mut objects = BTreeMap::new();
+    /// The textdump object for `db_objid`, with its verbdefs and properties but not its programs.
+    pub fn object(&self, db_objid: Objid) -> Object {
+        let tx = self.tx;
+        let db_obj = tx.get_object(db_objid).expect("Failed to get object");
 
-    // (Objid, usize) -> Verb, where usize is the verb number (0-indexed)
-    let mut verbs = BTreeMap::new();
-
-    for (db_objid, db_obj) in db_objects.iter() {
         // To find 'next' for contents, we seek the contents of our location, and find the object right after
         // the current object in that vector
         let location = db_obj.location().unwrap_or(NOTHING);
 
         let next = if location != NOTHING {
-            let roommates = contents_map
-                .get_mut(&location)
+            let roommates = self
+                .contents_map
+                .get(&location)
                 .expect("Failed to get contents");
 
             let position = roommates
                 .iter()
-                .position(|x| x == db_objid)
+                .position(|x| *x == db_objid)
                 .expect("Failed to find object in contents of location");
             // If position is at the end, 'next' is -1.
             if position == roommates.len() - 1 {
@@ -124,7 +146,7 @@ pub fn make_textdump(tx: &dyn LoaderInterface, version: Option<&str>) -> Textdum
         };
 
         // To find 'contents' we're looking for the first object whose location is the current object
-        let contents = match contents_map.get_mut(db_objid) {
+        let contents = match self.contents_map.get(&db_objid) {
             Some(contents) => *contents.first().unwrap_or(&Objid(-1)),
             None => NOTHING,
         };
@@ -132,12 +154,13 @@ pub fn make_textdump(tx: &dyn LoaderInterface, version: Option<&str>) -> Textdum
         let parent = db_obj.parent().unwrap_or(NOTHING);
 
         // Same for 'sibling' using children/parent
-        let siblings = children_map
-            .get_mut(&parent)
+        let siblings = self
+            .children_map
+            .get(&parent)
             .expect("Failed to get siblings");
         let position = siblings
             .iter()
-            .position(|x| x == db_objid)
+            .position(|x| *x == db_objid)
             .expect("Failed to find object in siblings");
         let sibling = if position == siblings.len() - 1 {
             NOTHING
@@ -146,13 +169,13 @@ pub fn make_textdump(tx: &dyn LoaderInterface, version: Option<&str>) -> Textdum
         };
 
         // To find child, we need to find the first object whose parent is the current object
-        let child = match children_map.get_mut(db_objid) {
+        let child = match self.children_map.get(&db_objid) {
             Some(children) => *children.first().unwrap_or(&NOTHING),
             None => NOTHING,
         };
 
         // Find the verbdefs and transform them into textdump verbdefs
-        let db_verbdefs = tx.get_object_verbs(*db_objid).expect("Failed to get verbs");
+        let db_verbdefs = tx.get_object_verbs(db_objid).expect("Failed to get verbs");
         let verbdefs = db_verbdefs
             .iter()
             .map(|db_verbdef| {
@@ -167,46 +190,15 @@ pub fn make_textdump(tx: &dyn LoaderInterface, version: Option<&str>) -> Textdum
                 }
             })
             .collect();
-        // Produce the verbmap
-        for (verbnum, verb) in db_verbdefs.iter().enumerate() {
-            // Get and decompile the binary. We only support MOO for now.
-            if verb.binary_type() != BinaryType::LambdaMoo18X {
-                panic!("Unsupported binary type: {:?}", verb.binary_type());
-            }
-
-            let binary = tx
-                .get_verb_binary(*db_objid, verb.uuid())
-                .expect("Failed to get verb binary");
-
-            let program = Program::from_bytes(binary).expect("Failed to parse verb binary");
-            let program = if !program.main_vector.is_empty() {
-                let ast = moor_compiler::program_to_tree(&program)
-                    .expect("Failed to decompile verb binary");
-                let program =
-                    moor_compiler::unparse(&ast).expect("Failed to decompile verb binary");
-                Some(program.join("\n"))
-            } else {
-                None
-            };
-            let objid = *db_objid;
-            verbs.insert(
-                (objid, verbnum),
-                Verb {
-                    objid,
-                    verbnum,
-                    program,
-                },
-            );
-        }
 
         // propvals have wonky logic which resolve relative to position in the inheritance hierarchy of
         // propdefs up to the root. So we grab that all from the loader_client, and then we can just
         // iterate through them all.
-        let properties = tx.get_all_property_values(*db_objid).unwrap();
+        let properties = tx.get_all_property_values(db_objid).unwrap();
 
         let mut propdefs = vec![];
         for (p, _) in &properties {
-            if p.definer() != *db_objid {
+            if p.definer() != db_objid {
                 break;
             }
             propdefs.push(p.name().into());
@@ -224,12 +216,11 @@ pub fn make_textdump(tx: &dyn LoaderInterface, version: Option<&str>) -> Textdum
                 is_clear,
             });
         }
-        // To construct the child linkage list, we need to scan all objects, and find all objects whose parent
-        // is the current object, and add them to the list.
-        let obj = Object {
-            id: *db_objid,
+
+        Object {
+            id: db_objid,
             owner: db_obj.owner().unwrap(),
-            location: db_obj.location().unwrap_or(NOTHING),
+            location,
             contents,
             next,
             parent,
@@ -240,21 +231,92 @@ pub fn make_textdump(tx: &dyn LoaderInterface, version: Option<&str>) -> Textdum
             verbdefs,
             propdefs,
             propvals,
-        };
+        }
+    }
 
-        objects.insert(*db_objid, obj);
+    /// The verbs on `db_objid`, in order, with their programs decompiled.
+    pub fn verbs(&self, db_objid: Objid) -> Vec<Verb> {
+        let db_verbdefs = self
+            .tx
+            .get_object_verbs(db_objid)
+            .expect("Failed to get verbs");
+        db_verbdefs
+            .iter()
+            .enumerate()
+            .map(|(verbnum, verb)| {
+                let program = self.program(db_objid, &verb);
+                let program = if !program.main_vector.is_empty() {
+                    let ast = moor_compiler::program_to_tree(&program)
+                        .expect("Failed to decompile verb binary");
+                    let program =
+                        moor_compiler::unparse(&ast).expect("Failed to decompile verb binary");
+                    Some(program.join("\n"))
+                } else {
+                    None
+                };
+                Verb {
+                    objid: db_objid,
+                    verbnum,
+                    program,
+                }
+            })
+            .collect()
+    }
+
+    /// How many verbs have a program to write out, without decompiling any of them.
+    pub fn program_count(&self) -> usize {
+        let mut count = 0;
+        for db_objid in &self.object_ids {
+            let db_verbdefs = self
+                .tx
+                .get_object_verbs(*db_objid)
+                .expect("Failed to get verbs");
+            for verb in db_verbdefs.iter() {
+                if !self.program(*db_objid, &verb).main_vector.is_empty() {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    fn program(&self, db_objid: Objid, verb: &VerbDef) -> Program {
+        // Get and parse the binary. We only support MOO for now.
+        if verb.binary_type() != BinaryType::LambdaMoo18X {
+            panic!("Unsupported binary type: {:?}", verb.binary_type());
+        }
+
+        let binary = self
+            .tx
+            .get_verb_binary(db_objid, verb.uuid())
+            .expect("Failed to get verb binary");
+
+        Program::from_bytes(binary).expect("Failed to parse verb binary")
     }
+}
+
+/// Take a transaction, and scan the relations and build a Textdump representing a snapshot of the world as it
+/// exists in the transaction.
+pub fn make_textdump(tx: &dyn LoaderInterface, version: Option<&str>) -> Textdump {
+    let source = TextdumpSource::new(tx);
+
+    // Objid -> Object
+    let mut objects = BTreeMap::new();
 
-    let users = tx
-        .get_players()
-        .expect("Failed to get players list")
-        .iter()
-        .collect();
+    // (Objid, usize) -> Verb, where usize is the verb number (0-indexed)
+    let mut verbs = BTreeMap::new();
+
+    for db_objid in source.object_ids() {
+        objects.insert(*db_objid, source.object(*db_objid));
+        for verb in source.verbs(*db_objid) {
+            verbs.insert((verb.objid, verb.verbnum), verb);
+        }
+    }
 
     Textdump {
         version: version.unwrap_or(MOOR_TEXTDUMP_DB_VERSION).to_string(),
         objects,
-        users,
+        users: source.users().to_vec(),
         verbs,
     }
 }
@@ -35,7 +35,7 @@ use moor_compiler::Program;
 use moor_compiler::{Label, Name};
 use moor_compiler::{Op, EMPTY_PROGRAM};
 
-// {this, verb-name, programmer, verb-loc, player, line-number}
+// {this, verb-name, programmer, verb-loc, player, line-number, is-builtin}
 #[derive(Clone)]
 pub struct Caller {
     pub this: Objid,
@@ -44,6 +44,7 @@ pub struct Caller {
     pub definer: Objid,
     pub player: Objid,
     pub line_number: usize,
+    pub is_builtin: bool,
 }
 
 // A Label that exists in a separate stack but is *relevant* only for the `valstack_pos`
@@ -158,6 +159,26 @@ impl Frame {
         self.environment.get(id.0 as usize)
     }
 
+    /// The variables which have been given values, by name.
+    pub(crate) fn locals(&self) -> Vec<(String, Var)> {
+        self.program
+            .var_names
+            .names
+            .iter()
+            .enumerate()
+            .filter_map(|(i, name)| self.environment.get(i).map(|v| (name.clone(), v.clone())))
+            .collect()
+    }
+
+    /// Set the named variable, returning false if the program has no variable by that name.
+    pub(crate) fn set_local(&mut self, name: &str, value: Var) -> bool {
+        let Some(id) = self.program.var_names.find_name(name) else {
+            return false;
+        };
+        self.set_env(&id, value);
+        true
+    }
+
     #[inline]
     pub fn set_var_offset(&mut self, offset: &Name, value: Var) -> Result<(), Error> {
         if offset.0 as usize >= self.environment.len() {
@@ -0,0 +1,80 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Pausing tasks at breakpoints, or before every opcode when single-stepping, so that a debugger
+//! can look at where they are.
+
+use moor_values::model::{Breakpoint, Named};
+
+use crate::vm::activation::Activation;
+
+/// The debugging state of a task started while its owner had breakpoints set. Other tasks don't
+/// get one, and so don't pay for the checks.
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    /// Pause before every opcode, rather than just on arriving at a breakpoint's line.
+    stepping: bool,
+    /// Set when resuming, so that the task doesn't immediately pause again where it already was.
+    resuming: bool,
+    /// The line each activation on the stack was last seen at, so that a breakpoint fires when
+    /// its line is arrived at, rather than before each of the line's opcodes.
+    lines: Vec<Option<usize>>,
+}
+
+impl Debugger {
+    pub fn new(breakpoints: Vec<Breakpoint>) -> Self {
+        Self {
+            breakpoints,
+            stepping: false,
+            resuming: false,
+            lines: vec![],
+        }
+    }
+
+    /// Called by the VM before each opcode it executes, with the activation about to execute it
+    /// on top of the stack.
+    pub(crate) fn should_pause(&mut self, stack: &[Activation]) -> bool {
+        let Some(activation) = stack.last() else {
+            return false;
+        };
+        // Line lookup expects the pc to have been advanced past the opcode, which it hasn't yet.
+        let line = activation
+            .frame
+            .find_line_no(activation.frame.pc + 1)
+            .unwrap_or(0);
+        let depth = stack.len();
+        self.lines.resize(depth, None);
+        let arrived = self.lines[depth - 1] != Some(line);
+        self.lines[depth - 1] = Some(line);
+
+        if std::mem::take(&mut self.resuming) {
+            return false;
+        }
+        if self.stepping {
+            return true;
+        }
+        arrived
+            && self.breakpoints.iter().any(|bp| {
+                bp.line == line
+                    && bp.definer == activation.verb_definer()
+                    && activation.verb_info.verbdef().matches_name(&bp.verb_name)
+            })
+    }
+
+    /// Carry on from a pause, either until the next breakpoint, or for just one opcode.
+    pub(crate) fn resume(&mut self, step: bool) {
+        self.stepping = step;
+        self.resuming = true;
+    }
+}
@@ -12,11 +12,17 @@
 // this program. If not, see <https://www.gnu.org/licenses/>.
 //
 
+use crate::config::Config;
 use crate::tasks::{PhantomUnsend, PhantomUnsync, TaskId};
 use crate::vm::activation::{Activation, Caller};
+use crate::vm::debugger::Debugger;
+use crate::vm::tick_profile::TickProfile;
 use moor_values::var::Objid;
 use moor_values::var::Var;
 use moor_values::NOTHING;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
 /// Represents the state of VM execution.
@@ -40,6 +46,15 @@ pub struct VMExecState {
     pub(crate) start_time: Option<SystemTime>,
     /// The amount of time the task is allowed to run.
     pub(crate) maximum_time: Option<Duration>,
+    /// If profiling is enabled, where ticks are being spent.
+    pub(crate) tick_profile: Option<TickProfile>,
+    /// If the task is being debugged, where it should pause.
+    pub(crate) debugger: Option<Debugger>,
+    /// This task's random number generator, used by `random()`. Seeded from entropy, unless the
+    /// daemon was given a seed or the task called `set_random_seed()`.
+    pub(crate) rng: StdRng,
+    /// The server's configuration, for builtins whose behaviour depends on it.
+    pub(crate) config: Arc<Config>,
 
     unsend: PhantomUnsend,
     unsync: PhantomUnsync,
@@ -55,24 +70,30 @@ impl VMExecState {
             max_ticks,
             tick_slice: 0,
             maximum_time: None,
+            tick_profile: None,
+            debugger: None,
+            rng: StdRng::from_entropy(),
+            config: Default::default(),
             unsend: Default::default(),
             unsync: Default::default(),
         }
     }
 
     /// Return the callers stack, in the format expected by the `callers` built-in function.
-    pub(crate) fn callers(&self) -> Vec<Caller> {
+    /// With `max_frames`, only that many of the innermost callers are described.
+    pub(crate) fn callers(&self, max_frames: Option<usize>) -> Vec<Caller> {
         let mut callers_iter = self.stack.iter().rev();
         callers_iter.next(); // skip the top activation, that's our current frame
 
         let mut callers = vec![];
-        for activation in callers_iter {
+        for activation in callers_iter.take(max_frames.unwrap_or(usize::MAX)) {
             let verb_name = activation.verb_name.clone();
             let definer = activation.verb_definer();
             let player = activation.player;
-            // TODO: Line numbers in 'callers' stack is not implemented
-            //   Should be possible now that we have decompilation
-            let line_number = 0;
+            let line_number = activation
+                .frame
+                .find_line_no(activation.frame.pc)
+                .unwrap_or(0);
             let this = activation.this;
             let perms = activation.permissions;
             let programmer = if activation.bf_index.is_some() {
@@ -87,6 +108,7 @@ impl VMExecState {
                 line_number,
                 this,
                 programmer,
+                is_builtin: activation.bf_index.is_some(),
             });
         }
         callers
@@ -140,9 +162,11 @@ impl VMExecState {
     }
 
     /// Update the permissions of the current task, as called by the `set_task_perms`
-    /// built-in.
+    /// built-in. This is the verb frame `task_perms` reads, not the built-in's own frame.
     pub(crate) fn set_task_perms(&mut self, perms: Objid) {
-        self.top_mut().permissions = perms;
+        if let Some(activation) = self.stack.iter_mut().rev().find(|a| a.bf_index.is_none()) {
+            activation.permissions = perms;
+        }
     }
 
     /// Pop a value off the value stack.
@@ -25,13 +25,19 @@ use crate::builtins::bf_server::BfNoop;
 use crate::builtins::BuiltinFunction;
 
 pub(crate) mod activation;
+pub(crate) mod debugger;
 pub(crate) mod exec_state;
+pub(crate) mod tick_profile;
+pub(crate) mod verb_metrics;
 pub(crate) mod vm_call;
 pub(crate) mod vm_execute;
 pub(crate) mod vm_unwind;
 
 // Exports to the rest of the kernel
+pub use debugger::Debugger;
 pub use exec_state::VMExecState;
+pub use tick_profile::{TickProfile, TickSite};
+pub use verb_metrics::{set_verb_metrics, verb_perf_counters, VerbCounters, VerbKey};
 pub use vm_call::VerbExecutionRequest;
 pub use vm_execute::{ExecutionResult, Fork, VmExecParams};
 pub use vm_unwind::{FinallyReason, UncaughtException};
@@ -0,0 +1,112 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Optional sampling of where a task's ticks are being spent, so that a task which is aborted for
+//! exceeding its tick limit can be attributed to the verb(s) responsible.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use moor_values::var::Objid;
+
+use crate::vm::activation::Activation;
+
+/// A location in MOO code which ticks were attributed to.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct TickSite {
+    pub definer: Objid,
+    pub verb_name: String,
+    pub line: usize,
+}
+
+impl Display for TickSite {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{} line {}", self.definer, self.verb_name, self.line)
+    }
+}
+
+/// Samples the running activation every `interval` ticks. Each sample is credited with the whole
+/// interval, so the totals are estimates whose resolution depends on the interval chosen.
+pub struct TickProfile {
+    interval: usize,
+    samples: HashMap<TickSite, usize>,
+}
+
+impl TickProfile {
+    pub fn new(interval: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            samples: HashMap::new(),
+        }
+    }
+
+    /// Called by the VM for every tick executed.
+    #[inline]
+    pub(crate) fn tick(&mut self, tick_count: usize, activation: &Activation) {
+        if tick_count % self.interval != 0 {
+            return;
+        }
+        let site = TickSite {
+            definer: activation.verb_definer(),
+            verb_name: activation.verb_name.clone(),
+            line: activation
+                .frame
+                .find_line_no(activation.frame.pc)
+                .unwrap_or(0),
+        };
+        *self.samples.entry(site).or_insert(0) += self.interval;
+    }
+
+    /// The `n` sites with the most ticks attributed to them, most expensive first.
+    pub fn top_consumers(&self, n: usize) -> Vec<(TickSite, usize)> {
+        let mut consumers: Vec<_> = self
+            .samples
+            .iter()
+            .map(|(site, ticks)| (site.clone(), *ticks))
+            .collect();
+        consumers.sort_by(|a, b| b.1.cmp(&a.1));
+        consumers.truncate(n);
+        consumers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use moor_values::var::Objid;
+
+    use crate::vm::tick_profile::{TickProfile, TickSite};
+
+    fn site(verb_name: &str, line: usize) -> TickSite {
+        TickSite {
+            definer: Objid(0),
+            verb_name: verb_name.to_string(),
+            line,
+        }
+    }
+
+    #[test]
+    fn test_top_consumers_most_expensive_first() {
+        let mut profile = TickProfile::new(10);
+        profile.samples.insert(site("cheap", 1), 10);
+        profile.samples.insert(site("costly", 2), 500);
+        profile.samples.insert(site("middling", 3), 120);
+
+        assert_eq!(
+            profile.top_consumers(2),
+            vec![(site("costly", 2), 500), (site("middling", 3), 120)]
+        );
+        assert_eq!(profile.top_consumers(5).len(), 3);
+        assert_eq!(site("costly", 2).to_string(), "#0:costly line 2");
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Per-verb invocation counts and execution time, accumulated since startup, so that operators
+//! can find the hottest verbs in a running core.
+//!
+//! Collection is off unless switched on with `set_verb_metrics`. When on, each verb's counters are
+//! atomics, found under a read lock without allocating; the write lock (and a copy of the verb's
+//! name) is only needed the first time a verb is seen.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use moor_values::var::Objid;
+use moor_values::NOTHING;
+
+use crate::vm::activation::Activation;
+
+/// A verb, as identified by where it's defined and the name it was called by.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct VerbKey {
+    pub definer: Objid,
+    pub verb_name: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct VerbCounters {
+    pub invocations: u64,
+    /// Time spent running the verb's own code. Time spent in verbs it calls is credited to them,
+    /// not to it.
+    pub cumulative_time: Duration,
+}
+
+/// The live counters for one verb.
+#[derive(Debug, Default)]
+pub(crate) struct VerbCounterCells {
+    invocations: AtomicU64,
+    cumulative_nanos: AtomicU64,
+}
+
+impl VerbCounterCells {
+    pub(crate) fn add_time(&self, elapsed: Duration) {
+        self.cumulative_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Whether verb metrics are being collected. See `set_verb_metrics`.
+static VERB_METRICS: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref VERB_COUNTERS: RwLock<HashMap<Objid, HashMap<String, Arc<VerbCounterCells>>>> =
+        RwLock::new(HashMap::new());
+}
+
+/// Switch collection of per-verb metrics on or off for the whole process. Counters gathered so
+/// far are kept either way.
+pub fn set_verb_metrics(enabled: bool) {
+    VERB_METRICS.store(enabled, Ordering::Relaxed);
+}
+
+/// The counters for the verb the given activation is running, if metrics are being collected and
+/// it is a verb (rather than a builtin or an eval).
+pub(crate) fn counters_for(activation: &Activation) -> Option<Arc<VerbCounterCells>> {
+    if !VERB_METRICS.load(Ordering::Relaxed) {
+        return None;
+    }
+    let definer = activation.verb_definer();
+    if definer == NOTHING {
+        return None;
+    }
+    let verb_name = activation.verb_name.as_str();
+    if let Some(cells) = VERB_COUNTERS
+        .read()
+        .unwrap()
+        .get(&definer)
+        .and_then(|verbs| verbs.get(verb_name))
+    {
+        return Some(cells.clone());
+    }
+    let mut counters = VERB_COUNTERS.write().unwrap();
+    Some(
+        counters
+            .entry(definer)
+            .or_default()
+            .entry(verb_name.to_string())
+            .or_default()
+            .clone(),
+    )
+}
+
+pub(crate) fn record_invocation(activation: &Activation) {
+    if let Some(cells) = counters_for(activation) {
+        cells.invocations.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The counters for every verb which has been run since startup.
+pub fn verb_perf_counters() -> Vec<(VerbKey, VerbCounters)> {
+    let counters = VERB_COUNTERS.read().unwrap();
+    counters
+        .iter()
+        .flat_map(|(definer, verbs)| {
+            verbs.iter().map(|(verb_name, cells)| {
+                (
+                    VerbKey {
+                        definer: *definer,
+                        verb_name: verb_name.clone(),
+                    },
+                    VerbCounters {
+                        invocations: cells.invocations.load(Ordering::Relaxed),
+                        cumulative_time: Duration::from_nanos(
+                            cells.cumulative_nanos.load(Ordering::Relaxed),
+                        ),
+                    },
+                )
+            })
+        })
+        .collect()
+}
@@ -27,6 +27,7 @@ use crate::tasks::command_parse::ParsedCommand;
 use crate::tasks::sessions::Session;
 use crate::tasks::VerbCall;
 use crate::vm::activation::Activation;
+use crate::vm::verb_metrics;
 use crate::vm::vm_unwind::FinallyReason;
 use crate::vm::{ExecutionResult, Fork, VM};
 use crate::vm::{VMExecState, VmExecParams};
@@ -189,6 +190,7 @@ impl VM {
         call_request: VerbExecutionRequest,
     ) {
         let a = Activation::for_call(call_request);
+        verb_metrics::record_invocation(&a);
         vm_state.stack.push(a);
     }
 
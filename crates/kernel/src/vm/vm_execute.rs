@@ -108,8 +108,11 @@ pub enum ExecutionResult {
     /// If the duration is None, then the task is suspended indefinitely, until it is killed or
     /// resumed using `resume()` or `kill_task()`.
     Suspend(Option<Duration>),
-    /// Request input from the client.
-    NeedInput,
+    /// Request input from the given player's client.
+    NeedInput(Objid),
+    /// The task is being debugged, and has reached a point it should pause at. It carries on
+    /// from the same opcode when resumed.
+    DebugPause,
     /// Request `eval` execution, which is a kind of special activation creation where we've already
     /// been given the program to execute instead of having to look it up.
     PerformEval {
@@ -194,6 +197,9 @@ impl VM {
             return ExecutionResult::Complete(v_none());
         }
 
+        // How strings are indexed and measured on this server.
+        let strings = state.config.string_semantics();
+
         // The per-execution slice count. This is used to limit the amount of work we do in a single
         // execution slice for this task.
         // We should not execute more than `tick_slice` in a single VM intruction fetch/execute
@@ -206,8 +212,16 @@ impl VM {
         //  `max_ticks` on the task is the total limit which is checked above us, outside this loop.
         let mut tick_slice_count = 0;
         while tick_slice_count < state.tick_slice {
+            if let Some(debugger) = state.debugger.as_mut() {
+                if debugger.should_pause(&state.stack) {
+                    return ExecutionResult::DebugPause;
+                }
+            }
             tick_slice_count += 1;
             state.tick_count += 1;
+            if let Some(tick_profile) = state.tick_profile.as_mut() {
+                tick_profile.tick(state.tick_count, state.stack.last().unwrap());
+            }
 
             // Borrow the top of the activation stack for the lifetime of this execution.
             let a = state.top_mut();
@@ -370,6 +384,26 @@ impl VM {
                     let result = list.push(tail);
                     f.poke(0, result);
                 }
+                Op::ListAddTailLocal(ident) => {
+                    let tail = f.pop();
+                    // Drop the copy pushed to check the variable, so it doesn't count as sharing.
+                    f.pop();
+                    let Some(local) = f.get_env(ident).cloned() else {
+                        return self.push_error(state, E_VARNF);
+                    };
+                    if !matches!(local.variant(), Variant::List(_)) {
+                        return self.push_error(state, E_TYPE);
+                    }
+                    // Clear the variable so that, unless the list is shared with something else,
+                    // ours is the only reference to it and it can grow without being copied.
+                    f.set_env(ident, v_none());
+                    let Variant::List(mut list) = local.take_variant() else {
+                        unreachable!("checked above");
+                    };
+                    let result = list.push(tail);
+                    f.set_env(ident, result.clone());
+                    f.push(result);
+                }
                 Op::ListAppend => {
                     let (tail, list) = (f.pop(), f.peek_top_mut());
 
@@ -397,7 +431,7 @@ impl VM {
                             return self.push_error(state, e);
                         }
                     };
-                    match lhs.index_set(i, rhs) {
+                    match lhs.index_set(i, rhs, strings) {
                         Ok(v) => {
                             f.poke(0, v);
                         }
@@ -521,7 +555,7 @@ impl VM {
                         Ok(i) => i,
                         Err(e) => return self.push_error(state, e),
                     };
-                    match list.index(index) {
+                    match list.index(index, strings) {
                         Err(e) => return self.push_error(state, e),
                         Ok(v) => f.push(v),
                     }
@@ -536,7 +570,7 @@ impl VM {
                         }
                     };
 
-                    match l.index(index) {
+                    match l.index(index, strings) {
                         Err(e) => {
                             f.pop();
                             return self.push_error(state, e);
@@ -547,13 +581,15 @@ impl VM {
                 Op::RangeRef => {
                     let (to, from, base) = (f.pop(), f.pop(), f.peek_top());
                     match (to.variant(), from.variant()) {
-                        (Variant::Int(to), Variant::Int(from)) => match base.range(*from, *to) {
-                            Err(e) => {
-                                f.pop();
-                                return self.push_error(state, e);
+                        (Variant::Int(to), Variant::Int(from)) => {
+                            match base.range(*from, *to, strings) {
+                                Err(e) => {
+                                    f.pop();
+                                    return self.push_error(state, e);
+                                }
+                                Ok(v) => f.poke(0, v),
                             }
-                            Ok(v) => f.poke(0, v),
-                        },
+                        }
                         (_, _) => return self.push_error(state, E_TYPE),
                     };
                 }
@@ -561,7 +597,7 @@ impl VM {
                     let (value, to, from, base) = (f.pop(), f.pop(), f.pop(), f.peek_top());
                     match (to.variant(), from.variant()) {
                         (Variant::Int(to), Variant::Int(from)) => {
-                            match base.rangeset(value, *from, *to) {
+                            match base.rangeset(value, *from, *to, strings) {
                                 Err(e) => {
                                     f.pop();
                                     return self.push_error(state, e);
@@ -586,7 +622,7 @@ impl VM {
                 }
                 Op::Length(offset) => {
                     let v = f.peek_abs(offset.0 as usize);
-                    match v.len() {
+                    match v.len(strings) {
                         Ok(l) => f.push(l),
                         Err(e) => return self.push_error(state, e),
                     }
@@ -17,16 +17,17 @@ mod tests {
 
     use pretty_assertions::assert_eq;
     use std::sync::Arc;
+    use std::time::Duration;
 
     use moor_values::model::PropFlag;
     use moor_values::model::VerbArgsSpec;
     use moor_values::model::{BinaryType, VerbFlag};
     use moor_values::model::{WorldState, WorldStateSource};
     use moor_values::util::BitEnum;
-    use moor_values::var::Error::E_DIV;
+    use moor_values::var::Error::{E_DIV, E_TYPE, E_VARNF};
     use moor_values::var::Objid;
     use moor_values::var::{
-        v_bool, v_empty_list, v_err, v_int, v_list, v_none, v_obj, v_objid, v_str, Var,
+        v_bool, v_empty_list, v_err, v_int, v_list, v_none, v_obj, v_objid, v_str, Var, Variant,
     };
 
     use moor_values::NOTHING;
@@ -34,6 +35,7 @@ mod tests {
 
     use crate::tasks::sessions::NoopClientSession;
     use crate::tasks::vm_test_utils::call_verb;
+    use crate::vm::{set_verb_metrics, verb_perf_counters};
     use moor_compiler::compile;
     use moor_compiler::Names;
     use moor_compiler::Op;
@@ -366,6 +368,30 @@ mod tests {
         assert_eq!(result, Ok(v_int(666)));
     }
 
+    #[test]
+    fn test_verb_perf_counters() {
+        set_verb_metrics(true);
+        let callee = compile("x = 0; for i in [1..100] x = x + i; endfor return x;").unwrap();
+        let caller = compile("for i in [1..10] #0:perf_counted_verb(); endfor return 1;").unwrap();
+        let mut state = test_db_with_verbs(&[
+            ("perf_counted_verb", &callee),
+            ("perf_counting_verb", &caller),
+        ])
+        .new_world_state()
+        .unwrap();
+        let session = Arc::new(NoopClientSession::new());
+        let result = call_verb(state.as_mut(), session, "perf_counting_verb", vec![]);
+        assert_eq!(result, Ok(v_int(1)));
+
+        let counters = verb_perf_counters();
+        let (_, callee_counters) = counters
+            .iter()
+            .find(|(key, _)| key.definer == SYSTEM_OBJECT && key.verb_name == "perf_counted_verb")
+            .expect("no counters for the called verb");
+        assert_eq!(callee_counters.invocations, 10);
+        assert!(callee_counters.cumulative_time > Duration::ZERO);
+    }
+
     fn world_with_test_program(program: &str) -> Box<dyn WorldState> {
         let binary = compile(program).unwrap();
         let db = test_db_with_verb("test", &binary);
@@ -533,7 +559,155 @@ mod tests {
         assert_eq!(result, Ok(v_none()));
     }
 
+    #[test]
+    fn test_uncaught_error_line_numbers() {
+        let program = "x = 1;\ny = 2;\nreturn x / (y - 2);";
+        let mut state = world_with_test_program(program);
+        let session = Arc::new(NoopClientSession::new());
+        let Err(exception) = call_verb(state.as_mut(), session, "test", vec![]) else {
+            panic!("Expected uncaught exception");
+        };
+        assert_eq!(exception.code, E_DIV);
+        let Variant::List(frame) = exception.stack[0].variant() else {
+            panic!("Expected traceback frame list");
+        };
+        assert_eq!(frame.get(5), Some(v_int(3)));
+        let Variant::Str(line) = exception.backtrace[0].variant() else {
+            panic!("Expected backtrace string");
+        };
+        assert!(line.as_str().contains("(line 3)"), "{}", line.as_str());
+    }
+
+    #[test]
+    fn test_backtrace_names_builtin() {
+        let mut state = world_with_test_program(r#"return sqrt("pi");"#);
+        let session = Arc::new(NoopClientSession::new());
+        let Err(exception) = call_verb(state.as_mut(), session, "test", vec![]) else {
+            panic!("Expected uncaught exception");
+        };
+        assert_eq!(exception.code, E_TYPE);
+        let mentions_sqrt = exception.backtrace.iter().any(|l| match l.variant() {
+            Variant::Str(s) => s.as_str().contains("built-in function sqrt()"),
+            _ => false,
+        });
+        assert!(mentions_sqrt, "{:?}", exception.backtrace);
+    }
+
+    #[test]
+    fn test_callers_line_numbers() {
+        let caller = compile("x = 1;\nreturn this:test_callee();").unwrap();
+        let callee = compile("return callers();").unwrap();
+        let mut state = test_db_with_verbs(&[("test_caller", &caller), ("test_callee", &callee)])
+            .new_world_state()
+            .unwrap();
+        let session = Arc::new(NoopClientSession::new());
+        let result = call_verb(state.as_mut(), session, "test_caller", vec![]).unwrap();
+        let Variant::List(callers) = result.variant() else {
+            panic!("Expected callers list");
+        };
+        let caller = callers.get(0).unwrap();
+        let Variant::List(frame) = caller.variant() else {
+            panic!("Expected caller frame list");
+        };
+        assert_eq!(frame.get(5), Some(v_int(2)));
+    }
+
+    #[test]
+    fn test_callers_depth_and_builtin_flag() {
+        let outer = compile("return this:test_middle();").unwrap();
+        let middle = compile("return this:test_inner();").unwrap();
+        let inner = compile("return {callers(), callers(0, 1), callers(1, 0)};").unwrap();
+        let mut state = test_db_with_verbs(&[
+            ("test_outer", &outer),
+            ("test_middle", &middle),
+            ("test_inner", &inner),
+        ])
+        .new_world_state()
+        .unwrap();
+        let session = Arc::new(NoopClientSession::new());
+        let result = call_verb(state.as_mut(), session, "test_outer", vec![]).unwrap();
+        let Variant::List(results) = result.variant() else {
+            panic!("Expected list of callers lists");
+        };
+        let frames = |i: usize| {
+            let Variant::List(frames) = results.get(i).unwrap().variant().clone() else {
+                panic!("Expected callers list");
+            };
+            frames
+        };
+
+        // The full chain, innermost first; none of these frames are builtins.
+        let all = frames(0);
+        assert_eq!(all.len(), 2);
+        let Variant::List(middle_frame) = all.get(0).unwrap().variant().clone() else {
+            panic!("Expected caller frame list");
+        };
+        assert_eq!(middle_frame.get(1), Some(v_str("test_middle")));
+        assert_eq!(middle_frame.get(6), Some(v_bool(false)));
+
+        // Limited to the innermost caller, without its line number.
+        let limited = frames(1);
+        assert_eq!(limited.len(), 1);
+        let Variant::List(frame) = limited.get(0).unwrap().variant().clone() else {
+            panic!("Expected caller frame list");
+        };
+        assert_eq!(frame.len(), 6);
+        assert_eq!(frame.get(1), Some(v_str("test_middle")));
+        assert_eq!(frame.get(5), Some(v_bool(false)));
+
+        assert_eq!(frames(2).len(), 0);
+    }
+
+    #[test]
+    fn test_callers_flags_builtin_frames() {
+        // create() calls out to :initialize on the new object, which is inherited from #0 here.
+        let outer = compile("create(#0); return $test;").unwrap();
+        let initialize = compile("$test = callers();").unwrap();
+        let mut state = test_db_with_verbs(&[("test", &outer), ("initialize", &initialize)])
+            .new_world_state()
+            .unwrap();
+        let session = Arc::new(NoopClientSession::new());
+        let result = call_verb(state.as_mut(), session, "test", vec![]).unwrap();
+        let Variant::List(frames) = result.variant() else {
+            panic!("Expected callers list");
+        };
+        assert_eq!(frames.len(), 2);
+        let frame = |i: usize| {
+            let Variant::List(frame) = frames.get(i).unwrap().variant().clone() else {
+                panic!("Expected caller frame list");
+            };
+            frame
+        };
+
+        // The builtin that called :initialize, then the verb that called it.
+        assert_eq!(frame(0).get(1), Some(v_str("create")));
+        assert_eq!(frame(0).get(6), Some(v_bool(true)));
+        assert_eq!(frame(1).get(1), Some(v_str("test")));
+        assert_eq!(frame(1).get(6), Some(v_bool(false)));
+    }
+
     #[test_case("return 1;", v_int(1); "simple return")]
+    #[test_case(
+        "a = {1}; b = a; a = {@a, 2}; b = {@b, 3}; return {a, b};",
+        v_list(&[v_list(&[v_int(1), v_int(2)]), v_list(&[v_int(1), v_int(3)])]);
+        "append to self is copy on write"
+    )]
+    #[test_case(
+        "l = {}; for i in [1..5] l = {@l, i}; endfor return l;",
+        v_list(&[v_int(1), v_int(2), v_int(3), v_int(4), v_int(5)]);
+        "append to self in a loop"
+    )]
+    #[test_case("l = 1; return `l = {@l, 2} ! ANY';", v_err(E_TYPE); "append to self non-list")]
+    #[test_case(
+        "l = 1; return `l = {@l, 1 / 0} ! ANY';",
+        v_err(E_TYPE);
+        "append to self checks the list before the item"
+    )]
+    #[test_case(
+        "return `l = {@l, 1 / 0} ! ANY';",
+        v_err(E_VARNF);
+        "append to self checks the variable before the item"
+    )]
     #[test_case(
         r#"rest = "me:words"; rest[1..0] = ""; return rest;"#,
         v_str("me:words"); "range assignment"
@@ -624,6 +798,27 @@ mod tests {
     #[test_case(r#"if (E_INVARG == (vi = `verb_info(#-1, "blerg") ! ANY')) return 666; endif return 333;"#, 
         v_int(666); "verb_info invalid object error")]
     #[test_case("return -9223372036854775808;", v_int(i64::MIN); "minint")]
+    #[test_case("try return 1/0; except (E_DIV) return 2; endtry", v_int(2); "except single code")]
+    #[test_case("try return {}[1]; except (E_DIV, E_RANGE) return 3; endtry",
+        v_int(3); "except code list")]
+    #[test_case("try return 1/0; except (ANY) return 4; endtry", v_int(4); "except any")]
+    #[test_case("try return 1/0; except (E_RANGE) return 1; except (E_DIV) return 2; endtry",
+        v_int(2); "except second clause")]
+    #[test_case("try return 1/0; except (E_DIV) return 1; except (ANY) return 2; endtry",
+        v_int(1); "except first matching clause wins")]
+    #[test_case(
+        r#"
+        try
+          try
+            return 1/0;
+          except (E_RANGE, E_PERM)
+            return 1;
+          endtry
+        except (E_DIV)
+          return 2;
+        endtry
+        "#,
+        v_int(2); "inner except does not catch other codes")]
     fn test_run(program: &str, expected_result: Var) {
         let mut state = world_with_test_program(program);
         let session = Arc::new(NoopClientSession::new());
@@ -93,6 +93,15 @@ impl FinallyReason {
     }
 }
 
+/// Does the codes value pushed for a catch/except clause catch `raise_code`?
+/// A non-list value (the compiler pushes `0` for `ANY`) catches everything.
+fn catches(codes: &Var, raise_code: Error) -> bool {
+    match codes.variant() {
+        Variant::List(codes) => codes.contains(&v_err(raise_code)),
+        _ => true,
+    }
+}
+
 impl VM {
     /// Find the currently active catch handler for a given error code, if any.
     /// Then return the stack offset (from now) of the activation frame containing the handler.
@@ -101,19 +110,19 @@ impl VM {
         let mut frame = state.stack.len() - 1;
         loop {
             let activation = &state.stack.get(frame)?;
-            for handler in &activation.frame.handler_stack {
+            for handler in activation.frame.handler_stack.iter().rev() {
                 if let HandlerType::Catch(cnt) = handler.handler_type {
-                    // Found one, now scan forwards from 'cnt' backwards in the valstack looking for either the first
-                    // non-list value, or a list containing the error code.
-                    // TODO check for 'cnt' being too large. not sure how to handle, tho
-                    // TODO this actually i think is wrong, it needs to pull two values off the stack
+                    // Found one. The compiler pushes one codes value per clause (the labels live
+                    // on the handler stack, not the value stack), so the `cnt` values directly
+                    // beneath the handler's position are the codes for each of its clauses.
                     let i = handler.valstack_pos;
-                    for j in (i - cnt)..i {
-                        if let Variant::List(codes) = &activation.frame.valstack[j].variant() {
-                            if !codes.contains(&v_err(raise_code)) {
-                                continue;
-                            }
-                        }
+                    let Some(codes) = i
+                        .checked_sub(cnt)
+                        .and_then(|start| activation.frame.valstack.get(start..i))
+                    else {
+                        continue;
+                    };
+                    if codes.iter().any(|c| catches(c, raise_code)) {
                         return Some(frame);
                     }
                 }
@@ -181,27 +190,26 @@ impl VM {
             if i != 0 {
                 pieces.push("... called from ".to_string());
             }
-            if a.bf_index.is_none() {
-                pieces.push(format!("{}:{}", a.verb_definer(), a.verb_name));
-            } else {
-                pieces.push(format!(
-                    "builtin {}",
-                    BUILTIN_DESCRIPTORS[a.bf_index.unwrap()].name.as_str()
-                ));
-            }
-            if a.verb_definer() != a.this {
-                pieces.push(format!(" (this == #{})", a.this.0));
-            }
-            if a.frame.find_line_no(a.frame.pc).is_some() {
-                pieces.push(format!(
-                    " (line {})",
-                    a.frame.find_line_no(a.frame.pc).unwrap()
-                ));
+            match a.bf_index {
+                None => {
+                    pieces.push(format!("{}:{}", a.verb_definer(), a.verb_name));
+                    if a.verb_definer() != a.this {
+                        pieces.push(format!(" (this == #{})", a.this.0));
+                    }
+                    if let Some(line_no) = a.frame.find_line_no(a.frame.pc) {
+                        pieces.push(format!(" (line {})", line_no));
+                    }
+                }
+                Some(bf_index) => {
+                    pieces.push(format!(
+                        "built-in function {}()",
+                        BUILTIN_DESCRIPTORS[bf_index].name.as_str()
+                    ));
+                }
             }
             if i == 0 {
                 pieces.push(format!(": {}", raise_msg));
             }
-            // TODO builtin-function name if a builtin
 
             let piece = pieces.join("");
             backtrace_list.push(v_str(&piece))
@@ -361,26 +369,28 @@ impl VM {
                         trace!(jump = ?label, ?why, "matched finally handler");
                         return ExecutionResult::More;
                     }
-                    HandlerType::Catch(_) => {
+                    HandlerType::Catch(cnt) => {
                         let FinallyReason::Raise { code, .. } = &why else {
                             continue;
                         };
 
-                        let Some(handler) = a.frame.pop_applicable_handler() else {
-                            continue;
-                        };
-                        let HandlerType::CatchLabel(pushed_label) = &handler.handler_type else {
-                            panic!("Expected CatchLabel");
-                        };
-
-                        // The value at the top of the stack could be the error codes list.
-                        let v = a.frame.pop();
-                        let found = match v.variant() {
-                            Variant::List(error_codes) => error_codes.contains(&v_err(*code)),
-                            _ => true,
-                        };
-                        if found {
-                            a.frame.jump(pushed_label);
+                        // Pop the (label, codes) pair for every clause. They come off last clause
+                        // first, so the last match seen is the first matching clause in the source.
+                        let mut found = None;
+                        for _ in 0..cnt {
+                            let Some(handler) = a.frame.handler_stack.pop() else {
+                                panic!("Missing CatchLabel for catch handler");
+                            };
+                            let HandlerType::CatchLabel(pushed_label) = handler.handler_type else {
+                                panic!("Expected CatchLabel");
+                            };
+                            let codes = a.frame.pop();
+                            if catches(&codes, *code) {
+                                found = Some(pushed_label);
+                            }
+                        }
+                        if let Some(pushed_label) = found {
+                            a.frame.jump(&pushed_label);
                             a.frame.push(v_list(&[v_err(*code)]));
                             return ExecutionResult::More;
                         }
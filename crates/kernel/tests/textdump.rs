@@ -19,14 +19,19 @@ mod test {
     use moor_db::loader::LoaderInterface;
     use moor_db::Database;
     use moor_db_wiredtiger::WiredTigerDB;
-    use moor_kernel::textdump::{make_textdump, read_textdump, textdump_load, TextdumpReader};
+    use moor_kernel::textdump::{
+        export_database, load_textdump, load_textdump_remapped, make_textdump, read_binary_dump,
+        read_textdump, textdump_load, ExportFormat, ImportMode, Propval, TextdumpReader,
+    };
+    use moor_values::model::PropFlag;
     use moor_values::model::VerbArgsSpec;
     use moor_values::model::VerbFlag;
-    use moor_values::model::WorldStateSource;
     use moor_values::model::{CommitResult, ValSet};
     use moor_values::model::{HasUuid, Named};
-    use moor_values::var::Objid;
-    use moor_values::{AsByteBuffer, SYSTEM_OBJECT};
+    use moor_values::model::{WorldState, WorldStateSource};
+    use moor_values::util::BitEnum;
+    use moor_values::var::{v_list, v_objid, v_str, Objid};
+    use moor_values::{AsByteBuffer, FAILED_MATCH, NOTHING, SYSTEM_OBJECT};
     use std::collections::BTreeSet;
     use std::fs::File;
     use std::io::{BufReader, Read};
@@ -42,7 +47,8 @@ mod test {
     }
 
     fn load_textdump_file(tx: &mut dyn LoaderInterface, path: &str) {
-        textdump_load(tx, PathBuf::from(path)).expect("Could not load textdump");
+        textdump_load(tx, PathBuf::from(path), ImportMode::Preserve)
+            .expect("Could not load textdump");
         assert_eq!(tx.commit().unwrap(), CommitResult::Success);
     }
 
@@ -177,7 +183,7 @@ mod test {
         let (db, _) = WiredTigerDB::open(None);
         let db = Arc::new(db);
         let mut tx = db.clone().loader_client().unwrap();
-        textdump_load(tx.as_mut(), minimal_db).unwrap();
+        textdump_load(tx.as_mut(), minimal_db, ImportMode::Preserve).unwrap();
         assert_eq!(tx.commit().unwrap(), CommitResult::Success);
 
         // Check a few things in a new transaction.
@@ -231,6 +237,194 @@ mod test {
         assert_diff(&input, &output, "", 0);
     }
 
+    /// Export a database in the binary format, import that into a fresh database, and check the
+    /// two have the same contents.
+    #[test]
+    fn export_binary_then_reimport() {
+        let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let minimal_db = manifest_dir.join("tests/Minimal.db");
+
+        let (db1, _) = WiredTigerDB::open(None);
+        let db1 = Arc::new(db1);
+        load_textdump_file(
+            db1.clone().loader_client().unwrap().as_mut(),
+            minimal_db.to_str().unwrap(),
+        );
+        // Minimal.db has no properties, so give it one to carry over.
+        let mut tx = db1.new_world_state().unwrap();
+        tx.define_property(
+            Objid(3),
+            SYSTEM_OBJECT,
+            SYSTEM_OBJECT,
+            "exported",
+            Objid(3),
+            BitEnum::new_with(PropFlag::Read),
+            Some(v_str("hello")),
+        )
+        .unwrap();
+        assert_eq!(tx.commit().unwrap(), CommitResult::Success);
+
+        let export_dir = tempfile::tempdir().unwrap();
+        let export_path = export_dir.path().join("export.bin");
+        let tx = db1.clone().loader_client().unwrap();
+        export_database(tx.as_ref(), &export_path, ExportFormat::Binary).unwrap();
+        drop(tx);
+
+        let td = read_binary_dump(File::open(&export_path).unwrap()).unwrap();
+        let (db2, _) = WiredTigerDB::open(None);
+        let db2 = Arc::new(db2);
+        let mut tx = db2.clone().loader_client().unwrap();
+        load_textdump(tx.as_ref(), &td).unwrap();
+        assert_eq!(tx.commit().unwrap(), CommitResult::Success);
+
+        let tx1 = db1.new_world_state().unwrap();
+        let tx2 = db2.new_world_state().unwrap();
+        assert_eq!(
+            tx1.max_object(Objid(3)).unwrap(),
+            tx2.max_object(Objid(3)).unwrap()
+        );
+        assert_eq!(
+            tx2.retrieve_property(Objid(3), SYSTEM_OBJECT, "exported")
+                .unwrap(),
+            v_str("hello")
+        );
+        let verb_names = |tx: &dyn WorldState| {
+            tx.verbs(Objid(3), SYSTEM_OBJECT)
+                .unwrap()
+                .iter()
+                .map(|v| v.names().join(" "))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(verb_names(tx1.as_ref()), verb_names(tx2.as_ref()));
+        drop((tx1, tx2));
+
+        // And everything else, too.
+        let version = "** LambdaMOO Database, Format Version 4 **";
+        assert_diff(
+            &write_textdump(db1, version),
+            &write_textdump(db2, version),
+            "",
+            0,
+        );
+    }
+
+    /// Exporting as a textdump streams it out object by object; check that comes out the same as
+    /// building the whole textdump and writing that.
+    #[test]
+    fn export_textdump_matches_write_textdump() {
+        let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let minimal_db = manifest_dir.join("tests/Minimal.db");
+
+        let (db, _) = WiredTigerDB::open(None);
+        let db = Arc::new(db);
+        load_textdump_file(
+            db.clone().loader_client().unwrap().as_mut(),
+            minimal_db.to_str().unwrap(),
+        );
+
+        let export_dir = tempfile::tempdir().unwrap();
+        let export_path = export_dir.path().join("export.db");
+        let tx = db.clone().loader_client().unwrap();
+        export_database(tx.as_ref(), &export_path, ExportFormat::Textdump).unwrap();
+        drop(tx);
+
+        let mut exported = String::new();
+        File::open(&export_path)
+            .unwrap()
+            .read_to_string(&mut exported)
+            .unwrap();
+        assert_diff(
+            &write_textdump(db, "** LambdaMOO Database, Format Version 4 **"),
+            &exported,
+            "",
+            0,
+        );
+    }
+
+    /// Import the same textdump twice into one database, remapping ids, and check the second copy
+    /// got its own objects with its references pointing among them.
+    #[test]
+    fn import_twice_with_remapping() {
+        let br = BufReader::new(get_minimal_db());
+        let mut td = TextdumpReader::new(br)
+            .read_textdump()
+            .expect("Failed to read textdump");
+        // Give the wizard some properties which refer to other objects in the dump,
+        let wizard = td.objects.get_mut(&Objid(3)).unwrap();
+        // and one which refers to an object it doesn't have.
+        wizard.propdefs = vec![
+            "home".to_string(),
+            "places".to_string(),
+            "elsewhere".to_string(),
+        ];
+        wizard.propvals = vec![
+            Propval {
+                value: v_objid(Objid(2)),
+                owner: Objid(3),
+                flags: 0,
+                is_clear: false,
+            },
+            Propval {
+                value: v_list(&[v_objid(Objid(2)), v_objid(NOTHING)]),
+                owner: Objid(3),
+                flags: 0,
+                is_clear: false,
+            },
+            Propval {
+                value: v_list(&[v_objid(Objid(50)), v_objid(FAILED_MATCH)]),
+                owner: Objid(3),
+                flags: 0,
+                is_clear: false,
+            },
+        ];
+
+        let (db, _) = WiredTigerDB::open(None);
+        let db = Arc::new(db);
+        let mut tx = db.clone().loader_client().unwrap();
+        let first = load_textdump_remapped(tx.as_ref(), &td).unwrap();
+        let second = load_textdump_remapped(tx.as_ref(), &td).unwrap();
+        assert_eq!(tx.commit().unwrap(), CommitResult::Success);
+
+        // The first lands on the ids it already had, the second after them.
+        assert!(first.iter().all(|(old, new)| old == new));
+        assert_eq!(
+            second.values().copied().collect::<Vec<_>>(),
+            vec![Objid(4), Objid(5), Objid(6), Objid(7)]
+        );
+
+        let tx = db.new_world_state().unwrap();
+        assert_eq!(tx.max_object(Objid(3)).unwrap(), Objid(7));
+        let new = |o: i64| second[&Objid(o)];
+        let (wizard, room, root) = (new(3), new(2), new(1));
+        assert_eq!(tx.names_of(wizard, wizard).unwrap().0, "Wizard");
+        assert_eq!(tx.parent_of(wizard, wizard).unwrap(), root);
+        assert_eq!(tx.location_of(wizard, wizard).unwrap(), room);
+        assert_eq!(tx.owner_of(room).unwrap(), wizard);
+        assert!(tx.contents_of(wizard, room).unwrap().contains(wizard));
+        assert_eq!(
+            tx.retrieve_property(wizard, wizard, "home").unwrap(),
+            v_objid(room)
+        );
+        assert_eq!(
+            tx.retrieve_property(wizard, wizard, "places").unwrap(),
+            v_list(&[v_objid(room), v_objid(NOTHING)])
+        );
+        // #50 isn't in the dump, so rather than landing on whatever #50 is here, it's #-1.
+        assert_eq!(
+            tx.retrieve_property(wizard, wizard, "elsewhere").unwrap(),
+            v_list(&[v_objid(NOTHING), v_objid(FAILED_MATCH)])
+        );
+        let verbs = tx.verbs(wizard, new(0)).unwrap();
+        assert!(verbs.iter().all(|v| v.owner() == wizard));
+
+        // And the first copy is untouched.
+        assert_eq!(tx.location_of(Objid(3), Objid(3)).unwrap(), Objid(2));
+        assert_eq!(
+            tx.retrieve_property(Objid(3), Objid(3), "home").unwrap(),
+            v_objid(Objid(2))
+        );
+    }
+
     #[test]
     // This is an expensive test, so it's not run by default.
     fn load_big_core() {
@@ -21,7 +21,7 @@ use moor_kernel::tasks::sessions::NoopClientSession;
 use moor_kernel::tasks::sessions::Session;
 use moor_kernel::tasks::vm_test_utils;
 use moor_kernel::tasks::vm_test_utils::ExecResult;
-use moor_kernel::textdump::textdump_load;
+use moor_kernel::textdump::{textdump_load, ImportMode};
 use moor_moot::test_db_path;
 use moor_values::model::CommitResult;
 use moor_values::model::Named;
@@ -48,7 +48,8 @@ pub fn testsuite_dir() -> PathBuf {
 #[allow(dead_code)]
 pub fn load_textdump(db: Arc<dyn Database>) {
     let mut tx = db.loader_client().unwrap();
-    textdump_load(tx.as_ref(), test_db_path()).expect("Could not load textdump");
+    textdump_load(tx.as_ref(), test_db_path(), ImportMode::Preserve)
+        .expect("Could not load textdump");
     assert_eq!(tx.commit().unwrap(), CommitResult::Success);
 }
 
@@ -17,20 +17,28 @@
 //! See example.moot for a full-fledged example
 
 mod common;
-use std::{path::Path, sync::Arc};
+use std::time::{Duration, Instant};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
 
-use common::{create_wiredtiger_db, testsuite_dir};
+use common::{compile_verbs, create_wiredtiger_db, testsuite_dir};
+use moor_compiler::compile;
 use moor_db::Database;
 use moor_kernel::{
     config::Config,
     tasks::{
-        scheduler::{Scheduler, SchedulerError},
+        scheduler::{Scheduler, SchedulerError, TaskResult},
         scheduler_test_utils,
-        sessions::{NoopClientSession, Session},
+        sessions::{MockClientSession, NoopClientSession, Session},
+        CommitHook, TaskId,
     },
 };
-use moor_moot::{execute_moot_test, MootRunner};
-use moor_values::var::{v_none, Objid, Var};
+use moor_moot::{execute_moot_test, MootRunner, WIZARD};
+use moor_values::model::{Breakpoint, PausedActivation, WorldState};
+use moor_values::var::{v_int, v_none, v_str, Objid, Var, Variant};
+use moor_values::SYSTEM_OBJECT;
 
 #[cfg(feature = "relbox")]
 use common::create_relbox_db;
@@ -72,6 +80,26 @@ impl MootRunner for SchedulerMootRunner {
         .inspect(|var| eprintln!("{player} << {var}"))
     }
 
+    fn out_of_band<S: AsRef<str>>(
+        &mut self,
+        player: Objid,
+        command: S,
+    ) -> Result<Var, SchedulerError> {
+        eprintln!("{player} >> {}", command.as_ref());
+        scheduler_test_utils::call_out_of_band(
+            self.scheduler.clone(),
+            self.session.clone(),
+            player,
+            command.as_ref(),
+        )
+        .inspect(|var| eprintln!("{player} << {var}"))
+    }
+
+    fn disconnect(&mut self, _player: Objid) -> Result<(), SchedulerError> {
+        // Tasks are submitted directly to the scheduler; there's no connection to drop.
+        Ok(())
+    }
+
     fn none(&self) -> Var {
         v_none()
     }
@@ -93,17 +121,32 @@ fn test(db: Arc<dyn Database + Send + Sync>, path: &Path) {
     if path.is_dir() {
         return;
     }
-    let scheduler = Arc::new(Scheduler::new(db, Config::default()));
+    with_scheduler(db, |scheduler| {
+        execute_moot_test(
+            SchedulerMootRunner::new(scheduler, Arc::new(NoopClientSession::new())),
+            path,
+        );
+    });
+}
+
+/// Run `f` against a scheduler running over `db`, shutting it down afterwards.
+fn with_scheduler(db: Arc<dyn Database + Send + Sync>, f: impl FnOnce(Arc<Scheduler>)) {
+    with_scheduler_config(db, Config::default(), f)
+}
+
+fn with_scheduler_config(
+    db: Arc<dyn Database + Send + Sync>,
+    config: Config,
+    f: impl FnOnce(Arc<Scheduler>),
+) {
+    let scheduler = Arc::new(Scheduler::new(db, config));
     let loop_scheduler = scheduler.clone();
     let scheduler_loop_jh = std::thread::Builder::new()
         .name("moor-scheduler".to_string())
         .spawn(move || loop_scheduler.run())
         .unwrap();
 
-    execute_moot_test(
-        SchedulerMootRunner::new(scheduler.clone(), Arc::new(NoopClientSession::new())),
-        path,
-    );
+    f(scheduler.clone());
 
     scheduler
         .submit_shutdown(0, Some("Test is done".to_string()))
@@ -111,6 +154,582 @@ fn test(db: Arc<dyn Database + Send + Sync>, path: &Path) {
     scheduler_loop_jh.join().unwrap();
 }
 
+#[test]
+fn test_suspend_subsecond() {
+    with_scheduler(create_wiredtiger_db(), |scheduler| {
+        let start = Instant::now();
+        let result = scheduler_test_utils::call_eval(
+            scheduler,
+            Arc::new(NoopClientSession::new()),
+            WIZARD,
+            "suspend(0.1); return 1;".to_string(),
+        );
+        let elapsed = start.elapsed();
+        assert_eq!(result.unwrap(), v_int(1));
+        assert!(
+            elapsed >= Duration::from_millis(100) && elapsed < Duration::from_millis(500),
+            "suspend(0.1) took {elapsed:?}"
+        );
+    });
+}
+
+/// Wait for the given task to pause for the debugger, and return where it paused.
+fn wait_for_pause(scheduler: &Scheduler, task_id: TaskId) -> PausedActivation {
+    let start = Instant::now();
+    loop {
+        if let Some((_, paused)) = scheduler
+            .paused_tasks()
+            .into_iter()
+            .find(|(id, _)| *id == task_id)
+        {
+            return paused;
+        }
+        assert!(
+            start.elapsed() < Duration::from_secs(1),
+            "task {task_id} never paused"
+        );
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn test_breakpoint_pauses_task() {
+    let db = create_wiredtiger_db();
+    compile_verbs(
+        db.clone().world_state_source().unwrap(),
+        &[(
+            "debug_me",
+            &compile("x = 1;\ny = x + 1;\nreturn y;").unwrap(),
+        )],
+    );
+    with_scheduler(db, |scheduler| {
+        scheduler.set_breakpoint(
+            WIZARD,
+            Breakpoint {
+                definer: SYSTEM_OBJECT,
+                verb_name: "debug_me".to_string(),
+                line: 2,
+            },
+        );
+        let task = scheduler
+            .submit_verb_task(
+                WIZARD,
+                SYSTEM_OBJECT,
+                "debug_me".to_string(),
+                vec![],
+                "".to_string(),
+                WIZARD,
+                Arc::new(NoopClientSession::new()),
+            )
+            .unwrap();
+        let task_id = task.task_id();
+
+        // Stopped before line 2 runs: `x` has been assigned, but `y` hasn't.
+        let paused = wait_for_pause(&scheduler, task_id);
+        assert_eq!(paused.definer, SYSTEM_OBJECT);
+        assert_eq!(paused.line, 2);
+        assert!(paused.locals.contains(&("x".to_string(), v_int(1))));
+        assert!(!paused.locals.iter().any(|(name, _)| name == "y"));
+
+        scheduler.debug_resume(task_id, false).unwrap();
+        match task
+            .into_receiver()
+            .recv_timeout(Duration::from_secs(1))
+            .unwrap()
+        {
+            TaskResult::Success(v) => assert_eq!(v, v_int(2)),
+            r => panic!("unexpected task result: {r:?}"),
+        }
+    });
+}
+
+/// The commit hook runs in the task's own transaction, so it sees what the task did, and has run
+/// by the time the result arrives.
+#[test]
+fn test_commit_hook_sees_task_writes() {
+    let db = create_wiredtiger_db();
+    compile_verbs(
+        db.clone().world_state_source().unwrap(),
+        &[(
+            "make_widget",
+            &compile("o = create(#-1);\no.name = \"widget\";\nreturn o;").unwrap(),
+        )],
+    );
+    with_scheduler(db, |scheduler| {
+        let seen = Arc::new(Mutex::new(None));
+        let commit_hook: CommitHook = {
+            let seen = seen.clone();
+            Arc::new(move |world_state: &dyn WorldState, result: &Var| {
+                let Variant::Obj(o) = result.variant() else {
+                    return;
+                };
+                *seen.lock().unwrap() = world_state.retrieve_property(WIZARD, *o, "name").ok();
+            })
+        };
+        let task = scheduler
+            .submit_verb_task_with_commit_hook(
+                WIZARD,
+                SYSTEM_OBJECT,
+                "make_widget".to_string(),
+                vec![],
+                "".to_string(),
+                WIZARD,
+                Arc::new(NoopClientSession::new()),
+                Some(commit_hook),
+            )
+            .unwrap();
+        match task
+            .into_receiver()
+            .recv_timeout(Duration::from_secs(1))
+            .unwrap()
+        {
+            TaskResult::Success(_) => {}
+            r => panic!("unexpected task result: {r:?}"),
+        }
+        assert_eq!(*seen.lock().unwrap(), Some(v_str("widget")));
+    });
+}
+
+/// notify_system() hands its content type to the session, and plain text goes without one.
+#[test]
+fn test_notify_system_passes_content_type() {
+    with_scheduler(create_wiredtiger_db(), |scheduler| {
+        let session = Arc::new(MockClientSession::new());
+        let result = scheduler_test_utils::call_eval(
+            scheduler,
+            session.clone(),
+            WIZARD,
+            r#"notify_system(player, "<b>hi</b>", "text/html"); notify_system(player, "plain", "text/plain"); notify_system(player, "bare"); return 1;"#
+                .to_string(),
+        );
+        assert_eq!(result.unwrap(), v_int(1));
+        assert_eq!(
+            session.system(),
+            vec![
+                format!("{}: [text/html] <b>hi</b>", WIZARD.0),
+                format!("{}: plain", WIZARD.0),
+                format!("{}: bare", WIZARD.0),
+            ]
+        );
+    });
+}
+
+/// A task aborted for running out of ticks, while being profiled, says where they went.
+#[test]
+fn test_tick_abort_reports_top_consumers() {
+    let db = create_wiredtiger_db();
+    compile_verbs(
+        db.clone().world_state_source().unwrap(),
+        &[(
+            "spin",
+            &compile("x = 0;\nwhile (1) x = x + 1; endwhile").unwrap(),
+        )],
+    );
+    let config = Config {
+        tick_profile_interval: Some(10),
+        ..Default::default()
+    };
+    with_scheduler_config(db, config, |scheduler| {
+        let session = Arc::new(MockClientSession::new());
+        let result = scheduler_test_utils::call_eval(
+            scheduler,
+            session.clone(),
+            WIZARD,
+            "#0:spin();".to_string(),
+        );
+        assert!(matches!(result, Err(SchedulerError::TaskAbortedLimit(_))));
+        let system = session.system();
+        assert!(
+            system
+                .iter()
+                .any(|msg| msg.contains("most ticks spent in #0:spin line 2")),
+            "{system:?}"
+        );
+    });
+}
+
+#[test]
+fn test_set_local_of_paused_task() {
+    let db = create_wiredtiger_db();
+    compile_verbs(
+        db.clone().world_state_source().unwrap(),
+        &[(
+            "debug_me",
+            &compile("x = 1;\ny = x + 1;\nreturn y;").unwrap(),
+        )],
+    );
+    with_scheduler(db, |scheduler| {
+        scheduler.set_breakpoint(
+            WIZARD,
+            Breakpoint {
+                definer: SYSTEM_OBJECT,
+                verb_name: "debug_me".to_string(),
+                line: 2,
+            },
+        );
+        let task = scheduler
+            .submit_verb_task(
+                WIZARD,
+                SYSTEM_OBJECT,
+                "debug_me".to_string(),
+                vec![],
+                "".to_string(),
+                WIZARD,
+                Arc::new(NoopClientSession::new()),
+            )
+            .unwrap();
+        let task_id = task.task_id();
+        wait_for_pause(&scheduler, task_id);
+
+        let locals = scheduler.paused_task_locals(task_id).unwrap();
+        assert!(locals.contains(&("x".to_string(), v_int(1))));
+
+        scheduler
+            .set_paused_task_local(task_id, "x".to_string(), v_int(41))
+            .unwrap();
+        assert_eq!(
+            scheduler.set_paused_task_local(task_id, "nope".to_string(), v_int(0)),
+            Err(SchedulerError::VariableNotFound("nope".to_string()))
+        );
+        let (_, paused) = scheduler
+            .paused_tasks()
+            .into_iter()
+            .find(|(id, _)| *id == task_id)
+            .unwrap();
+        assert!(paused.locals.contains(&("x".to_string(), v_int(41))));
+
+        scheduler.debug_resume(task_id, false).unwrap();
+        match task
+            .into_receiver()
+            .recv_timeout(Duration::from_secs(1))
+            .unwrap()
+        {
+            TaskResult::Success(v) => assert_eq!(v, v_int(42)),
+            r => panic!("unexpected task result: {r:?}"),
+        }
+
+        // Once it's carried on, its variables are no longer ours to change.
+        assert!(scheduler
+            .set_paused_task_local(task_id, "x".to_string(), v_int(0))
+            .is_err());
+    });
+}
+
+#[test]
+fn test_single_step_paused_task() {
+    let db = create_wiredtiger_db();
+    compile_verbs(
+        db.clone().world_state_source().unwrap(),
+        &[(
+            "debug_me",
+            &compile("x = 1;\ny = x + 1;\nreturn y;").unwrap(),
+        )],
+    );
+    with_scheduler(db, |scheduler| {
+        scheduler.set_breakpoint(
+            WIZARD,
+            Breakpoint {
+                definer: SYSTEM_OBJECT,
+                verb_name: "debug_me".to_string(),
+                line: 1,
+            },
+        );
+        let task = scheduler
+            .submit_verb_task(
+                WIZARD,
+                SYSTEM_OBJECT,
+                "debug_me".to_string(),
+                vec![],
+                "".to_string(),
+                WIZARD,
+                Arc::new(NoopClientSession::new()),
+            )
+            .unwrap();
+        let task_id = task.task_id();
+        assert_eq!(wait_for_pause(&scheduler, task_id).line, 1);
+
+        // Each step runs one opcode and pauses again, so it takes a few to get through line 1.
+        let mut steps = 0;
+        let paused = loop {
+            scheduler.debug_resume(task_id, true).unwrap();
+            steps += 1;
+            let paused = wait_for_pause(&scheduler, task_id);
+            if paused.line != 1 {
+                break paused;
+            }
+        };
+        assert!(steps > 1, "line 1 ran in a single step");
+        assert_eq!(paused.line, 2);
+        assert!(paused.locals.contains(&("x".to_string(), v_int(1))));
+        assert!(!paused.locals.iter().any(|(name, _)| name == "y"));
+
+        // Resuming without stepping runs to the end, as there are no more breakpoints.
+        scheduler.debug_resume(task_id, false).unwrap();
+        match task
+            .into_receiver()
+            .recv_timeout(Duration::from_secs(1))
+            .unwrap()
+        {
+            TaskResult::Success(v) => assert_eq!(v, v_int(2)),
+            r => panic!("unexpected task result: {r:?}"),
+        }
+    });
+}
+
+#[test]
+fn test_breakpoints_only_pause_their_owners_tasks() {
+    let db = create_wiredtiger_db();
+    compile_verbs(
+        db.clone().world_state_source().unwrap(),
+        &[(
+            "debug_me",
+            &compile("x = 1;\ny = x + 1;\nreturn y;").unwrap(),
+        )],
+    );
+    with_scheduler(db, |scheduler| {
+        // Some other wizard is debugging this verb...
+        scheduler.set_breakpoint(
+            Objid(2),
+            Breakpoint {
+                definer: SYSTEM_OBJECT,
+                verb_name: "debug_me".to_string(),
+                line: 2,
+            },
+        );
+        // ... which doesn't stop our task.
+        let result = scheduler
+            .submit_verb_task(
+                WIZARD,
+                SYSTEM_OBJECT,
+                "debug_me".to_string(),
+                vec![],
+                "".to_string(),
+                WIZARD,
+                Arc::new(NoopClientSession::new()),
+            )
+            .unwrap()
+            .into_receiver()
+            .recv_timeout(Duration::from_secs(1))
+            .unwrap();
+        match result {
+            TaskResult::Success(v) => assert_eq!(v, v_int(2)),
+            r => panic!("unexpected task result: {r:?}"),
+        }
+        assert!(scheduler.paused_tasks().is_empty());
+    });
+}
+
+#[test]
+fn test_paused_task_times_out() {
+    let db = create_wiredtiger_db();
+    compile_verbs(
+        db.clone().world_state_source().unwrap(),
+        &[(
+            "debug_me",
+            &compile("x = 1;\ny = x + 1;\nreturn y;").unwrap(),
+        )],
+    );
+    let config = Config {
+        debug_pause_timeout: Some(Duration::from_millis(50)),
+        ..Default::default()
+    };
+    with_scheduler_config(db, config, |scheduler| {
+        scheduler.set_breakpoint(
+            WIZARD,
+            Breakpoint {
+                definer: SYSTEM_OBJECT,
+                verb_name: "debug_me".to_string(),
+                line: 2,
+            },
+        );
+        let task = scheduler
+            .submit_verb_task(
+                WIZARD,
+                SYSTEM_OBJECT,
+                "debug_me".to_string(),
+                vec![],
+                "".to_string(),
+                WIZARD,
+                Arc::new(NoopClientSession::new()),
+            )
+            .unwrap();
+        wait_for_pause(&scheduler, task.task_id());
+
+        // Nobody resumes it, so it's aborted rather than holding its transaction open forever.
+        match task
+            .into_receiver()
+            .recv_timeout(Duration::from_secs(1))
+            .unwrap()
+        {
+            TaskResult::Error(SchedulerError::TaskAbortedCancelled) => {}
+            r => panic!("unexpected task result: {r:?}"),
+        }
+        assert!(scheduler.paused_tasks().is_empty());
+    });
+}
+
+/// While a foreground task runs, background tasks wait for it (up to the maximum background wait,
+/// which is long here), so a task forked by a command only gets going once the command is done.
+#[test]
+fn test_background_task_gives_way_to_foreground() {
+    let config = Config {
+        background_tick_slice: Some(10),
+        max_background_wait: Some(Duration::from_secs(30)),
+        ..Default::default()
+    };
+    with_scheduler_config(create_wiredtiger_db(), config, |scheduler| {
+        let session = Arc::new(MockClientSession::new());
+        let result = scheduler_test_utils::call_eval(
+            scheduler,
+            session.clone(),
+            WIZARD,
+            r#"fork (0) notify_system(player, "background"); endfork for i in [1..1000] endfor notify_system(player, "foreground"); return 1;"#
+                .to_string(),
+        );
+        assert_eq!(result.unwrap(), v_int(1));
+
+        let start = Instant::now();
+        while session.system().len() < 2 {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "forked task never ran"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            session.system(),
+            vec![
+                format!("{}: foreground", WIZARD.0),
+                format!("{}: background", WIZARD.0),
+            ]
+        );
+    });
+}
+
+#[test]
+fn test_max_concurrent_tasks() {
+    let db = create_wiredtiger_db();
+    // Tasks which run until they're aborted, so that what's running when is up to the test.
+    compile_verbs(
+        db.clone().world_state_source().unwrap(),
+        &[("busy", &compile("while (1)\nendwhile").unwrap())],
+    );
+    let config = Config {
+        max_concurrent_tasks: Some(2),
+        ..Default::default()
+    };
+    with_scheduler_config(db, config, |scheduler| {
+        // Loosen the limits so that the tasks don't run out of ticks first.
+        let result = scheduler_test_utils::call_eval(
+            scheduler.clone(),
+            Arc::new(NoopClientSession::new()),
+            WIZARD,
+            r#"o = create(#-1);
+               add_property(o, "fg_ticks", 1000000000, {player, "r"});
+               add_property(o, "fg_seconds", 60, {player, "r"});
+               add_property(#0, "server_options", o, {player, "r"});
+               load_server_options();
+               return 1;"#
+                .to_string(),
+        );
+        assert_eq!(result.unwrap(), v_int(1));
+
+        let tasks: Vec<_> = (0..6)
+            .map(|_| {
+                scheduler
+                    .submit_verb_task(
+                        WIZARD,
+                        SYSTEM_OBJECT,
+                        "busy".to_string(),
+                        vec![],
+                        "".to_string(),
+                        WIZARD,
+                        Arc::new(NoopClientSession::new()),
+                    )
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(scheduler.queue_info(), (2, 4));
+
+        // As each running task is aborted, the next in line takes its place, first come first
+        // served, and there are never more than two running at once.
+        for (aborted, handle) in tasks.into_iter().enumerate() {
+            scheduler.abort_task(handle.task_id()).unwrap();
+            assert!(matches!(
+                handle.into_receiver().recv_timeout(Duration::from_secs(5)),
+                Ok(TaskResult::Error(SchedulerError::TaskAbortedCancelled))
+            ));
+            let left = 5 - aborted;
+            let expected = (left.min(2), left.saturating_sub(2));
+            let start = Instant::now();
+            loop {
+                let (running, queued) = scheduler.queue_info();
+                assert!(running <= 2, "{running} tasks running at once");
+                if (running, queued) == expected {
+                    break;
+                }
+                assert!(
+                    start.elapsed() < Duration::from_secs(1),
+                    "expected {expected:?} running and queued, but have ({running}, {queued})"
+                );
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+    });
+}
+
+#[test]
+fn test_suspended_tasks_do_not_hold_slots() {
+    let db = create_wiredtiger_db();
+    compile_verbs(
+        db.clone().world_state_source().unwrap(),
+        &[
+            ("sleeper", &compile("suspend(30);\nreturn 1;").unwrap()),
+            ("quick", &compile("return 2;").unwrap()),
+        ],
+    );
+    let config = Config {
+        max_concurrent_tasks: Some(1),
+        ..Default::default()
+    };
+    with_scheduler_config(db, config, |scheduler| {
+        let submit = |verb: &str| {
+            scheduler
+                .submit_verb_task(
+                    WIZARD,
+                    SYSTEM_OBJECT,
+                    verb.to_string(),
+                    vec![],
+                    "".to_string(),
+                    WIZARD,
+                    Arc::new(NoopClientSession::new()),
+                )
+                .unwrap()
+                .into_receiver()
+        };
+        let _sleeper = submit("sleeper");
+
+        // Once it's suspended, it's neither running nor queued...
+        let start = Instant::now();
+        while scheduler.queue_info() != (0, 0) {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "sleeper never suspended"
+            );
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        // ... and the next task gets its slot straight away, rather than waiting 30 seconds.
+        match submit("quick")
+            .recv_timeout(Duration::from_secs(1))
+            .unwrap()
+        {
+            TaskResult::Success(v) => assert_eq!(v, v_int(2)),
+            r => panic!("unexpected task result: {r:?}"),
+        }
+    });
+}
+
 #[test]
 #[ignore = "Useful for debugging; just run a single test"]
 fn test_single() {
@@ -118,3 +737,25 @@ fn test_single() {
     // CARGO_PROFILE_RELEASE_DEBUG=true cargo flamegraph --test moot-suite -- test_single --ignored
     test_wiredtiger(&testsuite_dir().join("moot/single.moot"));
 }
+
+#[test]
+fn test_export_database_writes_into_export_directory() {
+    let export_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        export_directory: Some(export_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    with_scheduler_config(create_wiredtiger_db(), config, |scheduler| {
+        // The result only comes back once the file's been written.
+        let result = scheduler_test_utils::call_eval(
+            scheduler,
+            Arc::new(NoopClientSession::new()),
+            WIZARD,
+            r#"return export_database("out.db", "textdump");"#.to_string(),
+        );
+        assert_eq!(result.unwrap(), v_int(1));
+    });
+    let exported = std::fs::read_to_string(export_dir.path().join("out.db")).unwrap();
+    assert!(exported.starts_with("** LambdaMOO Database, Format Version 4 **\n"));
+    assert!(exported.ends_with("0 suspended tasks\n"));
+}
@@ -13,7 +13,19 @@
 //
 
 mod common;
-use common::{create_wiredtiger_db, AssertRunAsVerb};
+use common::{compile_verbs, create_wiredtiger_db, AssertRunAsVerb};
+use moor_compiler::compile;
+use moor_kernel::config::Config;
+use moor_kernel::tasks::compile_cache::CompileCacheStats;
+use moor_kernel::tasks::scheduler::{Scheduler, SchedulerError};
+use moor_values::model::{
+    BinaryType, CommandError, CommitResult, HasUuid, VerbArgsSpec, VerbAttrs, VerbFlag,
+    VerbProgramError, WorldStateSource,
+};
+use moor_values::util::BitEnum;
+use moor_values::var::Objid;
+use moor_values::{AsByteBuffer, NOTHING, SYSTEM_OBJECT};
+use std::sync::Arc;
 
 #[cfg(feature = "relbox")]
 use crate::common::create_relbox_db;
@@ -34,3 +46,171 @@ fn test_testhelper_verb_redefinition_wiredtiger() {
     db.assert_run_as_verb("return create(#2).name;", Ok("".into()));
     db.assert_run_as_verb("return 200;", Ok(200.into()));
 }
+
+#[test]
+fn test_compile_check_leaves_db_alone() {
+    let db = create_wiredtiger_db();
+    let wizard = Objid(2);
+    let snapshot = |ws: &Arc<dyn WorldStateSource>| {
+        let mut tx = ws.new_world_state().unwrap();
+        let snapshot = (
+            tx.max_object(wizard).unwrap(),
+            tx.verbs(wizard, SYSTEM_OBJECT).unwrap(),
+        );
+        tx.rollback().unwrap();
+        snapshot
+    };
+    let ws = db.clone().world_state_source().unwrap();
+    let before = snapshot(&ws);
+
+    let scheduler = Scheduler::new(db.clone(), Config::default());
+    assert_eq!(
+        scheduler.check_program(wizard, vec!["return 1 + 2;".to_string()]),
+        Ok(())
+    );
+    assert!(matches!(
+        scheduler.check_program(wizard, vec!["return 1 +;".to_string()]),
+        Err(SchedulerError::VerbProgramFailed(
+            VerbProgramError::Diagnostics(_)
+        ))
+    ));
+
+    assert!(snapshot(&ws) == before);
+}
+
+#[test]
+fn test_program_identical_source_compiles_and_writes_once() {
+    let db = create_wiredtiger_db();
+    let wizard = Objid(2);
+    let ws = db.clone().world_state_source().unwrap();
+    compile_verbs(ws.clone(), &[("test_verb", &compile("return 1;").unwrap())]);
+
+    let scheduler = Scheduler::new(
+        db.clone(),
+        Config {
+            skip_unchanged_verb_programs: true,
+            ..Default::default()
+        },
+    );
+    let program_verb = || {
+        scheduler
+            .program_verb(
+                wizard,
+                wizard,
+                "#0".to_string(),
+                "test_verb".to_string(),
+                vec!["return 2;".to_string()],
+            )
+            .unwrap();
+    };
+    let expected = compile("return 2;")
+        .unwrap()
+        .with_byte_buffer(|d| Vec::from(d))
+        .unwrap();
+
+    program_verb();
+    let mut tx = ws.new_world_state().unwrap();
+    let verb = tx.get_verb(wizard, SYSTEM_OBJECT, "test_verb").unwrap();
+    let binary = tx
+        .retrieve_verb(wizard, SYSTEM_OBJECT, verb.uuid())
+        .unwrap()
+        .binary();
+    assert_eq!(binary.as_ref(), expected.as_slice());
+
+    // Programming it again hits the cache, and writes nothing; had it written the same program
+    // again, this transaction's own write to the verb would now conflict with it.
+    program_verb();
+    assert_eq!(
+        scheduler.compile_cache_stats(),
+        CompileCacheStats { hits: 1, misses: 1 }
+    );
+    tx.update_verb_with_id(
+        wizard,
+        SYSTEM_OBJECT,
+        verb.uuid(),
+        VerbAttrs {
+            definer: None,
+            owner: None,
+            names: None,
+            flags: None,
+            args_spec: None,
+            binary_type: None,
+            binary: Some(expected),
+        },
+    )
+    .unwrap();
+    assert_eq!(tx.commit().unwrap(), CommitResult::Success);
+}
+
+#[test]
+fn test_disassemble_verb() {
+    let db = create_wiredtiger_db();
+    let wizard = Objid(2);
+    compile_verbs(
+        db.clone().world_state_source().unwrap(),
+        &[(
+            "test_verb",
+            &compile("x = 1; fork (0) x = x + 1; endfork return x;").unwrap(),
+        )],
+    );
+
+    let scheduler = Scheduler::new(db.clone(), Config::default());
+    let disassembly = scheduler
+        .disassemble_verb(wizard, wizard, "#0".to_string(), "test_verb".to_string())
+        .unwrap();
+    let opcodes: Vec<_> = disassembly
+        .main_vector
+        .iter()
+        .map(|op| op.opcode.as_str())
+        .collect();
+    assert!(opcodes.contains(&"Fork"));
+    assert!(opcodes.contains(&"Return"));
+    assert_eq!(disassembly.fork_vectors.len(), 1);
+    assert!(disassembly.fork_vectors[0]
+        .iter()
+        .any(|op| op.opcode == "Add"));
+
+    assert!(matches!(
+        scheduler.disassemble_verb(wizard, wizard, "#0".to_string(), "no_such_verb".to_string()),
+        Err(SchedulerError::CommandExecutionError(_))
+    ));
+}
+
+#[test]
+fn test_disassemble_needs_read_permission() {
+    let db = create_wiredtiger_db();
+    let wizard = Objid(2);
+    let mut tx = db
+        .clone()
+        .world_state_source()
+        .unwrap()
+        .new_world_state()
+        .unwrap();
+    let nobody = tx
+        .create_object(wizard, NOTHING, wizard, BitEnum::new())
+        .unwrap();
+    tx.add_verb(
+        wizard,
+        SYSTEM_OBJECT,
+        vec!["secret".to_string()],
+        wizard,
+        BitEnum::new_with(VerbFlag::Exec),
+        VerbArgsSpec::this_none_this(),
+        compile("return 1;").unwrap().make_copy_as_vec().unwrap(),
+        BinaryType::LambdaMoo18X,
+    )
+    .unwrap();
+    assert_eq!(tx.commit().unwrap(), CommitResult::Success);
+
+    // Anyone can call the verb, but only its owner (or a wizard) gets to read it.
+    let scheduler = Scheduler::new(db.clone(), Config::default());
+    assert!(matches!(
+        scheduler.disassemble_verb(nobody, nobody, "#0".to_string(), "secret".to_string()),
+        Err(SchedulerError::CommandExecutionError(
+            CommandError::PermissionDenied
+        ))
+    ));
+    assert!(scheduler
+        .disassemble_verb(wizard, wizard, "#0".to_string(), "secret".to_string())
+        .is_ok());
+}
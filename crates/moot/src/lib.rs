@@ -36,6 +36,9 @@ pub const PROGRAMMER: Objid = Objid(4);
 #[allow(dead_code)]
 pub const NONPROGRAMMER: Objid = Objid(5);
 
+/// Lines starting with this are sent as out-of-band commands, verbatim (prefix included).
+pub const OUT_OF_BAND_PREFIX: &str = "#$#";
+
 #[allow(dead_code)]
 static LOGGING_INIT: Once = Once::new();
 #[allow(dead_code)]
@@ -75,13 +78,31 @@ pub trait MootRunner {
         command: S,
     ) -> Result<Self::Value, Self::Error>;
 
+    fn out_of_band<S: AsRef<str>>(
+        &mut self,
+        player: Objid,
+        command: S,
+    ) -> Result<Self::Value, Self::Error>;
+
+    /// Forget `player`'s session, if the runner has one, so the next command starts a new one.
+    fn disconnect(&mut self, player: Objid) -> Result<(), Self::Error>;
+
     fn none(&self) -> Self::Value;
+
+    /// Queue a line of input to be sent on `player`'s connection when the next command asks for
+    /// it via `read()`. Runners which can't feed input to a connection fail the test instead.
+    fn queue_input(&mut self, player: Objid, line: &str) -> eyre::Result<()> {
+        Err(eyre::eyre!(
+            "This runner does not support interactive input; can't queue {line:?} for {player}"
+        ))
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
 pub enum CommandKind {
     Eval,
     Command,
+    OutOfBand,
 }
 impl From<char> for CommandKind {
     fn from(c: char) -> Self {
@@ -104,6 +125,7 @@ pub enum MootState<R: MootRunner> {
         line_no: usize,
         command: String,
         command_kind: CommandKind,
+        input: Vec<String>,
     },
     ReadingExpectation {
         runner: R,
@@ -111,6 +133,7 @@ pub enum MootState<R: MootRunner> {
         line_no: usize,
         command: String,
         command_kind: CommandKind,
+        input: Vec<String>,
         expectation: String,
     },
 }
@@ -120,25 +143,39 @@ impl<R: MootRunner> MootState<R> {
     }
 
     // Could implement this with `nom` I guess, but this seems simple enough, and it's probably easier to read.
+    //
+    // Expectation lines are ended by any line with a meaning of its own: empty, `//`, `@`, `;`, `%`
+    // or `#$#` (and `!` right after a command). To expect something which starts like that, escape
+    // it with a leading `\`, which is stripped; `<` is stripped the same way.
     pub fn process_line(self, new_line_no: usize, line: &str) -> eyre::Result<Self> {
         let line = line.trim_end_matches('\n');
         match self {
             MootState::Ready { runner, player } => {
-                if line.starts_with([';', '%']) {
+                if line.starts_with(OUT_OF_BAND_PREFIX) {
+                    Ok(MootState::ReadingCommand {
+                        runner,
+                        player,
+                        line_no: new_line_no,
+                        command: line.to_string(),
+                        command_kind: CommandKind::OutOfBand,
+                        input: Vec::new(),
+                    })
+                } else if line.starts_with([';', '%']) {
                     Ok(MootState::ReadingCommand {
                         runner,
                         player,
                         line_no: new_line_no,
                         command: line[1..].trim_start().to_string(),
                         command_kind: line.chars().next().unwrap().into(),
+                        input: Vec::new(),
                     })
                 } else if let Some(new_player) = line.strip_prefix('@') {
-                    Ok(MootState::new(runner, Self::player(new_player)?))
+                    Self::at_directive(runner, player, new_player)
                 } else if line.is_empty() || line.starts_with("//") {
                     Ok(MootState::new(runner, player))
                 } else {
                     Err(eyre::eyre!(
-                        "Expected a command (starting `;`), a comment (starting `//`), a player switch (starting `@`), a command (starting `%`), or an empty line"
+                        "Expected a command (starting `;`), a comment (starting `//`), a player switch (starting `@`), a command (starting `%`), an out-of-band command (starting `#$#`), or an empty line"
                     ))
                 }
             }
@@ -148,6 +185,7 @@ impl<R: MootRunner> MootState<R> {
                 line_no,
                 mut command,
                 command_kind,
+                mut input,
             } => {
                 if let Some(rest) = line.strip_prefix('>') {
                     command.push_str(rest);
@@ -157,22 +195,60 @@ impl<R: MootRunner> MootState<R> {
                         line_no,
                         command,
                         command_kind,
+                        input,
                     })
+                } else if let Some(rest) = line.strip_prefix('|') {
+                    input.push(rest.trim_start().to_string());
+                    Ok(MootState::ReadingCommand {
+                        runner,
+                        player,
+                        line_no,
+                        command,
+                        command_kind,
+                        input,
+                    })
+                } else if let Some(error) = line.strip_prefix('!') {
+                    Self::execute_raise_test(
+                        &mut runner,
+                        player,
+                        &command,
+                        command_kind,
+                        &input,
+                        error.trim(),
+                        line_no,
+                    )?;
+                    Ok(MootState::new(runner, player))
                 } else if let Some(new_player) = line.strip_prefix('@') {
-                    Self::execute_test(&mut runner, player, &command, command_kind, None, line_no)?;
-                    Ok(MootState::new(runner, Self::player(new_player)?))
-                } else if line.is_empty() || line.starts_with("//") || line.starts_with([';', '%'])
-                {
-                    Self::execute_test(&mut runner, player, &command, command_kind, None, line_no)?;
+                    Self::execute_test(
+                        &mut runner,
+                        player,
+                        &command,
+                        command_kind,
+                        &input,
+                        None,
+                        line_no,
+                    )?;
+                    Self::at_directive(runner, player, new_player)
+                } else if line.is_empty() || line.starts_with("//") || Self::starts_command(line) {
+                    Self::execute_test(
+                        &mut runner,
+                        player,
+                        &command,
+                        command_kind,
+                        &input,
+                        None,
+                        line_no,
+                    )?;
                     MootState::new(runner, player).process_line(new_line_no, line)
                 } else {
-                    let line = line.strip_prefix('<').unwrap_or(line);
+                    let line = Self::unescape_expectation(line);
                     Ok(MootState::ReadingExpectation {
                         runner,
                         player,
                         line_no,
                         command,
                         command_kind,
+                        input,
                         expectation: line.to_string(),
                     })
                 }
@@ -183,14 +259,16 @@ impl<R: MootRunner> MootState<R> {
                 line_no,
                 command,
                 command_kind,
+                input,
                 mut expectation,
             } => {
-                if line.is_empty() || line.starts_with("//") || line.starts_with([';', '%']) {
+                if line.is_empty() || line.starts_with("//") || Self::starts_command(line) {
                     Self::execute_test(
                         &mut runner,
                         player,
                         &command,
                         command_kind,
+                        &input,
                         Some(&expectation),
                         line_no,
                     )?;
@@ -198,12 +276,12 @@ impl<R: MootRunner> MootState<R> {
                 if line.is_empty() || line.starts_with("//") {
                     Ok(MootState::new(runner, player))
                 } else if let Some(new_player) = line.strip_prefix('@') {
-                    Ok(MootState::new(runner, Self::player(new_player)?))
-                } else if line.starts_with([';', '%']) {
+                    Self::at_directive(runner, player, new_player)
+                } else if Self::starts_command(line) {
                     MootState::new(runner, player).process_line(new_line_no, line)
                 } else {
                     expectation.push('\n');
-                    let line = line.strip_prefix('<').unwrap_or(line);
+                    let line = Self::unescape_expectation(line);
                     expectation.push_str(line);
                     Ok(MootState::ReadingExpectation {
                         runner,
@@ -211,6 +289,7 @@ impl<R: MootRunner> MootState<R> {
                         line_no,
                         command,
                         command_kind,
+                        input,
                         expectation,
                     })
                 }
@@ -227,25 +306,55 @@ impl<R: MootRunner> MootState<R> {
                 command,
                 line_no,
                 command_kind,
-            } => Self::execute_test(&mut runner, player, &command, command_kind, None, line_no),
+                input,
+            } => Self::execute_test(
+                &mut runner,
+                player,
+                &command,
+                command_kind,
+                &input,
+                None,
+                line_no,
+            ),
             MootState::ReadingExpectation {
                 mut runner,
                 player,
                 line_no,
                 command,
                 command_kind,
+                input,
                 expectation,
             } => Self::execute_test(
                 &mut runner,
                 player,
                 &command,
                 command_kind,
+                &input,
                 Some(&expectation),
                 line_no,
             ),
         }
     }
 
+    fn unescape_expectation(line: &str) -> &str {
+        line.strip_prefix(['\\', '<']).unwrap_or(line)
+    }
+
+    fn starts_command(line: &str) -> bool {
+        line.starts_with([';', '%']) || line.starts_with(OUT_OF_BAND_PREFIX)
+    }
+
+    /// `@<player>` switches to that player; `@disconnect` drops the current player's connection,
+    /// so that their next command connects (and logs in) afresh.
+    fn at_directive(mut runner: R, player: Objid, directive: &str) -> eyre::Result<Self> {
+        if directive == "disconnect" {
+            runner.disconnect(player)?;
+            Ok(MootState::new(runner, player))
+        } else {
+            Ok(MootState::new(runner, Self::player(directive)?))
+        }
+    }
+
     fn player(s: &str) -> eyre::Result<Objid> {
         match s {
             "wizard" => Ok(WIZARD),
@@ -260,6 +369,7 @@ impl<R: MootRunner> MootState<R> {
         player: Objid,
         command: &str,
         command_kind: CommandKind,
+        input: &[String],
         expectation: Option<&str>,
         line_no: usize,
     ) -> eyre::Result<()> {
@@ -271,12 +381,54 @@ impl<R: MootRunner> MootState<R> {
             runner.none()
         };
 
+        for line in input {
+            runner.queue_input(player, line)?;
+        }
         let actual = match command_kind {
             CommandKind::Eval => {
                 runner.eval(player, &format!("{command} \"moot-line:{line_no}\";"))
             }
             CommandKind::Command => runner.command(player, command),
-        }?;
+            CommandKind::OutOfBand => runner.out_of_band(player, command),
+        }
+        .wrap_err(format!("Line {line_no}: {command}"))?;
+        assert_eq!(actual, expected, "Line {line_no}: {command}");
+        Ok(())
+    }
+
+    /// Check that an eval raised the given error, rather than returning it (or anything else).
+    /// The harness does the `try ... except` wrapping, so this works the same for every runner.
+    fn execute_raise_test(
+        runner: &mut R,
+        player: Objid,
+        command: &str,
+        command_kind: CommandKind,
+        input: &[String],
+        error: &str,
+        line_no: usize,
+    ) -> eyre::Result<()> {
+        let CommandKind::Eval = command_kind else {
+            return Err(eyre::eyre!(
+                "Line {line_no}: `!` expectations are only supported for `;` commands"
+            ));
+        };
+        let expected = runner
+            .eval(WIZARD, format!("return {{\"raised\", {error}}};"))
+            .wrap_err(format!("Failed to compile expected error: {error}"))?;
+
+        for line in input {
+            runner.queue_input(player, line)?;
+        }
+        let actual = runner
+            .eval(
+                player,
+                format!(
+                    "try {command} \"moot-line:{line_no}\"; \
+                 except moot_err (ANY) return {{\"raised\", moot_err[1]}}; \
+                 endtry return {{\"returned\"}};"
+                ),
+            )
+            .wrap_err(format!("Line {line_no}: {command}"))?;
         assert_eq!(actual, expected, "Line {line_no}: {command}");
         Ok(())
     }
@@ -290,20 +442,25 @@ impl ManagedChild {
         // Rust tests capture output, and hide it if the test passes unless `--nocapture` is passed to `cargo test`.
         // This does *not* automatically apply to subprocesses, so: start threads to send subprocess output through
         // `print!` / `eprintln!` to get the same behavior.
+        // Set `MOOT_TIMESTAMPS` to also prefix each line with the time since the process was started, which helps
+        // with reconstructing the order of events across processes.
+        let started = std::env::var_os("MOOT_TIMESTAMPS").map(|_| Instant::now());
+        let prefix = move || match started {
+            Some(started) => format!("[{name} +{:04}ms]", started.elapsed().as_millis()),
+            None => format!("[{name}]"),
+        };
         let stdout = child.stdout.take().expect("Failed to get stdout");
         let stderr = child.stderr.take().expect("Failed to get stderr");
-        thread::spawn(|| {
-            let name = name.to_string();
+        thread::spawn(move || {
             let reader = BufReader::new(stdout);
             for line in reader.lines() {
-                println!("[{name}]: {}", line.expect("Failed to read line"));
+                println!("{}: {}", prefix(), line.expect("Failed to read line"));
             }
         });
-        thread::spawn(|| {
-            let name = name.to_string();
+        thread::spawn(move || {
             let reader = BufReader::new(stderr);
             for line in reader.lines() {
-                eprintln!("[{name}]: {}", line.expect("Failed to read line"));
+                eprintln!("{}: {}", prefix(), line.expect("Failed to read line"));
             }
         });
         Self { child }
@@ -315,15 +472,49 @@ impl Drop for ManagedChild {
     }
 }
 
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(1);
+const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub struct MootClient {
     stream: TcpStream,
+    read_timeout: Duration,
+    /// Lines to send in response to `read()` once the next command starts executing.
+    pending_input: Vec<String>,
 }
 impl MootClient {
-    pub fn new(port: u16) -> Result<Self, std::io::Error> {
+    pub fn new(
+        port: u16,
+        read_timeout: Duration,
+        write_timeout: Duration,
+    ) -> Result<Self, std::io::Error> {
         TcpStream::connect(format!("localhost:{port}")).and_then(|stream| {
-            stream.set_read_timeout(Some(Duration::from_secs(1)))?;
-            stream.set_write_timeout(Some(Duration::from_secs(1)))?;
-            Ok(Self { stream })
+            stream.set_read_timeout(Some(read_timeout))?;
+            stream.set_write_timeout(Some(write_timeout))?;
+            Ok(Self {
+                stream,
+                read_timeout,
+                pending_input: Vec::new(),
+            })
+        })
+    }
+
+    /// Read a line, turning a read timeout into an error which says what we were waiting for.
+    fn read_line(
+        &self,
+        reader: &mut impl BufRead,
+        buf: &mut String,
+        waiting_for: &str,
+    ) -> Result<usize, std::io::Error> {
+        reader.read_line(buf).map_err(|e| match e.kind() {
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!(
+                    "timed out after {:?} waiting for {waiting_for}",
+                    self.read_timeout
+                ),
+            ),
+            _ => e,
         })
     }
 
@@ -337,6 +528,10 @@ impl MootClient {
         writer.write_all(b"\n")
     }
 
+    pub fn queue_input<S: Into<String>>(&mut self, line: S) {
+        self.pending_input.push(line.into());
+    }
+
     fn port(&self) -> u16 {
         self.stream
             .local_addr()
@@ -357,7 +552,7 @@ impl MootClient {
         let mut buf = String::new();
         loop {
             buf.clear();
-            if reader.read_line(&mut buf)? == 0 {
+            if self.read_line(&mut reader, &mut buf, "prefix")? == 0 {
                 return Err(std::io::Error::new(
                     std::io::ErrorKind::UnexpectedEof,
                     "EOF while waiting for prefix",
@@ -370,10 +565,18 @@ impl MootClient {
             eprintln!("[waiting for prefix] {}", line);
         }
 
+        // The command is running now; feed it anything it's going to `read()`
+        for input in std::mem::take(&mut self.pending_input) {
+            eprintln!("{} >> {input}", self.port());
+            let mut writer = BufWriter::new(&self.stream);
+            writer.write_all(input.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+
         // Read until suffix
         loop {
             buf.clear();
-            reader.read_line(&mut buf)?;
+            self.read_line(&mut reader, &mut buf, "suffix")?;
             let line = buf.trim_end_matches(['\r', '\n']);
             if line == "-=!-v-!=-" {
                 break;
@@ -388,25 +591,42 @@ impl MootClient {
 pub struct TelnetMootRunner {
     port: u16,
     clients: HashMap<Objid, MootClient>,
+    read_timeout: Duration,
+    write_timeout: Duration,
+    connect_timeout: Duration,
 }
 impl TelnetMootRunner {
     pub fn new(port: u16) -> Self {
         Self {
             port,
             clients: HashMap::new(),
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            write_timeout: DEFAULT_WRITE_TIMEOUT,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
         }
     }
 
+    /// Allow more (or less) time for commands to produce output, and for connecting, than the
+    /// defaults of 1s per read/write and 5s to connect.
+    pub fn with_timeouts(mut self, read: Duration, write: Duration, connect: Duration) -> Self {
+        self.read_timeout = read;
+        self.write_timeout = write;
+        self.connect_timeout = connect;
+        self
+    }
+
     fn client(&mut self, player: Objid) -> &mut MootClient {
         self.clients.entry(player).or_insert_with(|| {
             let start = Instant::now();
             loop {
-                if let Ok(mut client) = MootClient::new(self.port) {
+                if let Ok(mut client) =
+                    MootClient::new(self.port, self.read_timeout, self.write_timeout)
+                {
                     client
                         .send_string(std::format!("connect {}", player))
                         .unwrap();
                     return client;
-                } else if start.elapsed() > Duration::from_secs(5) {
+                } else if start.elapsed() > self.connect_timeout {
                     panic!("Failed to connect to daemon");
                 } else {
                     std::thread::sleep(Duration::from_millis(10));
@@ -446,9 +666,32 @@ impl MootRunner for TelnetMootRunner {
         self.resolve_response(response)
     }
 
+    /// Out-of-band commands produce no delimited output over telnet, so this just sends the line
+    /// and moves on; the command may still be running when the next one starts.
+    fn out_of_band<S: AsRef<str>>(
+        &mut self,
+        player: Objid,
+        command: S,
+    ) -> Result<String, std::io::Error> {
+        self.client(player).send_string(command)?;
+        Ok(self.none())
+    }
+
+    fn disconnect(&mut self, player: Objid) -> Result<(), std::io::Error> {
+        if let Some(client) = self.clients.remove(&player) {
+            client.stream.shutdown(std::net::Shutdown::Both)?;
+        }
+        Ok(())
+    }
+
     fn none(&self) -> Self::Value {
         "0".to_string()
     }
+
+    fn queue_input(&mut self, player: Objid, line: &str) -> eyre::Result<()> {
+        self.client(player).queue_input(line);
+        Ok(())
+    }
 }
 
 pub fn execute_moot_test<R: MootRunner>(runner: R, path: &Path) {
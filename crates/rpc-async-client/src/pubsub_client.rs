@@ -18,7 +18,7 @@ use tmq::subscribe::Subscribe;
 use tracing::trace;
 use uuid::Uuid;
 
-use rpc_common::{BroadcastEvent, ConnectionEvent, RpcError};
+use rpc_common::{decode_connection_event, BroadcastEvent, ConnectionEvent, RpcError};
 
 pub async fn narrative_recv(
     client_id: Uuid,
@@ -30,7 +30,7 @@ pub async fn narrative_recv(
         ));
     };
 
-    // bincode decode the message, and it should be ConnectionEvent
+    // It should be a (framed) bincode encoded ConnectionEvent.
     if inbound.len() != 2 {
         return Err(RpcError::CouldNotDecode(format!(
             "Unexpected message length: {}",
@@ -53,12 +53,7 @@ pub async fn narrative_recv(
         return Err(RpcError::CouldNotDecode("Unexpected client ID".to_string()));
     }
 
-    let decode_result = bincode::decode_from_slice(event.as_ref(), bincode::config::standard());
-    let (msg, _msg_size): (ConnectionEvent, usize) = decode_result.map_err(|e| {
-        RpcError::CouldNotDecode(format!("Unable to decode narrative message: {}", e))
-    })?;
-
-    Ok(msg)
+    decode_connection_event(event.as_ref())
 }
 
 pub async fn broadcast_recv(subscribe: &mut Subscribe) -> Result<BroadcastEvent, RpcError> {
@@ -12,7 +12,7 @@
 // this program. If not, see <https://www.gnu.org/licenses/>.
 //
 
-use rpc_common::{RpcError, RpcRequest, RpcResult};
+use rpc_common::{RpcError, RpcRequest, RpcResult, RPC_PROTOCOL_VERSION};
 use tmq::request_reply::RequestSender;
 use tmq::Multipart;
 use tracing::error;
@@ -41,7 +41,11 @@ impl RpcSendClient {
     ) -> Result<RpcResult, RpcError> {
         let rpc_msg_payload = bincode::encode_to_vec(&rpc_msg, bincode::config::standard())
             .map_err(|e| RpcError::CouldNotSend(e.to_string()))?;
-        let message = Multipart::from(vec![client_id.as_bytes().to_vec(), rpc_msg_payload]);
+        let message = Multipart::from(vec![
+            client_id.as_bytes().to_vec(),
+            RPC_PROTOCOL_VERSION.to_be_bytes().to_vec(),
+            rpc_msg_payload,
+        ]);
         let rpc_request_sock = self.rcp_request_sock.take().ok_or(RpcError::CouldNotSend(
             "RPC request socket not initialized".to_string(),
         ))?;
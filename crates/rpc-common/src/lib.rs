@@ -12,15 +12,51 @@
 // this program. If not, see <https://www.gnu.org/licenses/>.
 //
 
+use bincode::error::DecodeError;
 use bincode::{Decode, Encode};
+pub use moor_values::model::{
+    Breakpoint, DisassembledOp, Disassembly, HostType, ListenerPoint, PausedActivation,
+    PresenceKind,
+};
 use moor_values::model::{CommandError, NarrativeEvent, VerbProgramError, WorldStateError};
 use moor_values::var::Objid;
 use moor_values::var::Var;
-use std::time::SystemTime;
+use std::borrow::Cow;
+use std::time::{Duration, SystemTime};
 use thiserror::Error;
 
 pub const BROADCAST_TOPIC: &[u8; 9] = b"broadcast";
 
+/// The version of the RPC protocol this build speaks. Hosts send it alongside each request, so
+/// that the daemon can tell a request it can't make sense of because the host is newer apart from
+/// one that's just garbage. Bump it whenever `RpcRequest`, or how events are sent to hosts,
+/// changes.
+pub const RPC_PROTOCOL_VERSION: u16 = 6;
+
+/// The oldest protocol version the daemon will take requests in. Hosts from before versioning
+/// (which send no version at all, read as 0) are turned away, since there's no telling what their
+/// requests look like.
+pub const MIN_RPC_PROTOCOL_VERSION: u16 = 1;
+
+/// Hosts speaking at least this version are sent `LoginSucceeded`, with the player's metadata, for
+/// a successful login. Older ones, which couldn't decode that, get `LoginResult(Some(..))`.
+pub const LOGIN_SUCCEEDED_PROTOCOL_VERSION: u16 = 3;
+
+/// Hosts speaking at least this version have the events for their clients framed by
+/// `frame_connection_event`. Older ones get the bare encoded event.
+pub const EVENT_FRAMING_PROTOCOL_VERSION: u16 = 6;
+
+/// The first byte of each event sent to a client on the narrative channel says how the rest of it
+/// is encoded.
+const EVENT_FRAME_PLAIN: u8 = 0;
+const EVENT_FRAME_ZSTD: u8 = 1;
+
+/// How often hosts trade their tokens in for fresh ones with `RefreshTokens`, whether or not the
+/// daemon has given them a TTL.
+pub const TOKEN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+/// The shortest token TTL the daemon will accept, leaving hosts room for a missed refresh or two.
+pub const MIN_TOKEN_TTL: Duration = Duration::from_secs(3 * 60);
+
 pub const MOOR_SESSION_TOKEN_FOOTER: &str = "key-id:moor_rpc";
 pub const MOOR_AUTH_TOKEN_FOOTER: &str = "key-id:moor_player";
 
@@ -48,7 +84,9 @@ pub struct AuthToken(pub String);
 pub enum RpcRequest {
     /// Establish a new connection, requesting a client token and a connection object
     ConnectionEstablish(String),
-    /// Anonymously request a sysprop (e.g. $login.welcome_message)
+    /// Anonymously request a sysprop (e.g. $login.welcome_message). Either the object or the
+    /// property may be a dotted path (e.g. "login.welcome_message" with an empty property), which
+    /// is followed from #0 link by link. The value is None if any link in the chain is missing.
     RequestSysProp(ClientToken, String, String),
     /// Login using the words (e.g. "create player bob" or "connect player bob") and return an
     /// auth token and the object id of the player. None if the login failed.
@@ -61,6 +99,12 @@ pub enum RpcRequest {
     Command(ClientToken, AuthToken, String),
     /// Attempt to program the object with the given verb code
     Program(ClientToken, AuthToken, String, String, Vec<String>),
+    /// As `Program`, but compilation failures come back as positioned diagnostics
+    /// (`VerbProgramError::Diagnostics`) rather than as lines of text.
+    ProgramWithDiagnostics(ClientToken, AuthToken, String, String, Vec<String>),
+    /// Compile the given verb code to check it, without storing it anywhere. Failures come back
+    /// as `VerbProgramError::Diagnostics`.
+    CompileCheck(ClientToken, AuthToken, Vec<String>),
     /// Respond to a request for input.
     RequestedInput(ClientToken, AuthToken, u128, String),
     /// Send an "out of band" command to be executed.
@@ -69,8 +113,75 @@ pub enum RpcRequest {
     Eval(ClientToken, AuthToken, String),
     /// Respond to a ping request.
     Pong(ClientToken, SystemTime),
-    /// We're done with this connection, buh-bye.
+    /// We're done with this connection, buh-bye. Any tasks it still has running are cancelled,
+    /// and if it was the player's last connection, `user_disconnected` is run for them.
     Detach(ClientToken),
+    /// The connection went away without a proper goodbye (the host is shutting down, or lost
+    /// track of it). Its tasks are cancelled and it's cleaned up, but `user_disconnected` is
+    /// skipped.
+    Abort(ClientToken),
+    /// Offer to make the outbound connection asked for by `OpenConnection`. The first host to
+    /// claim it gets `OutboundConnectionClaimed`, and the rest are refused.
+    ClaimOutboundConnection(u128 /* request id */),
+    /// An outbound connection this host claimed is up, and has been established (via
+    /// `ConnectionEstablish`, under the client id it was claimed with) as the client holding this
+    /// token.
+    OutboundConnectionEstablished(ClientToken, u128 /* request id */),
+    /// An outbound connection this host claimed could not be made. The token is the one given
+    /// back by `OutboundConnectionClaimed`.
+    OutboundConnectionFailed(ClientToken, u128 /* request id */, String),
+    /// Respond to a request for input on an outbound connection, which has no auth token.
+    OutboundRequestedInput(ClientToken, u128, String),
+    /// Ask for the server's version, uptime, and connection counts.
+    ServerInfo(ClientToken),
+    /// Trade in a still-valid client token, and auth token if logged in, for fresh ones before
+    /// they expire. Hosts send this every `TOKEN_REFRESH_INTERVAL`.
+    RefreshTokens(ClientToken, Option<AuthToken>),
+    /// (Wizard only) Invalidate all the auth tokens issued to the given player so far, forcing
+    /// them to log in again.
+    RevokeTokens(ClientToken, AuthToken, Objid),
+    /// A host announcing where it's accepting connections, for `listeners()`. Sent with a
+    /// client id identifying the host rather than any one connection; registering again under
+    /// the same id replaces what was there.
+    RegisterHost(Vec<ListenerPoint>),
+    /// (Wizard only) Ask for the per-verb invocation counts and execution times gathered since
+    /// startup. These are only collected if the daemon was started with `--verb-metrics`.
+    RequestPerformanceCounters(ClientToken, AuthToken),
+    /// Ask for the compiled form of the given (object, verb), for showing to the user in an
+    /// editor or debugger. The player must be able to read the verb.
+    DisassembleVerb(ClientToken, AuthToken, String, String),
+    /// (Wizard only) Drive the debugger.
+    Debug(ClientToken, AuthToken, DebugRequest),
+    /// Ask for the narrative events the player has been sent since the one numbered as given (see
+    /// `NarrativeEvent::seq`), oldest first, e.g. to catch up after reconnecting. Only the most
+    /// recent are kept, and none survive a daemon restart.
+    RequestHistory(ClientToken, AuthToken, u64),
+}
+
+/// What an external debugger can ask of the daemon.
+#[derive(Debug, Clone, Eq, PartialEq, Encode, Decode)]
+pub enum DebugRequest {
+    /// Pause tasks the requesting wizard starts from now on when they reach the given line. Other
+    /// players' tasks don't stop at it.
+    SetBreakpoint(Breakpoint),
+    ClearBreakpoint(Breakpoint),
+    /// Which tasks are paused, and where.
+    PausedTasks,
+    /// Carry on with a paused task: until the next breakpoint, or if `step`, for one opcode.
+    Resume {
+        task_id: usize,
+        step: bool,
+    },
+    /// The local variables of the verb a task is paused in.
+    Locals {
+        task_id: usize,
+    },
+    /// Change a local variable of the verb a task is paused in, before resuming it.
+    SetLocal {
+        task_id: usize,
+        name: String,
+        value: Var,
+    },
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Encode, Decode)]
@@ -79,6 +190,18 @@ pub enum ConnectType {
     Connected,
     Reconnected,
     Created,
+    /// Connected, taking over from the player's existing connections, which get dropped.
+    Redirected,
+}
+
+/// What a host usually needs to know about a player as soon as they've logged in, to render its
+/// initial UI without further requests.
+#[derive(Debug, Clone, Eq, PartialEq, Encode, Decode)]
+pub struct PlayerMetadata {
+    pub name: String,
+    pub location: Objid,
+    pub programmer: bool,
+    pub wizard: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Encode, Decode)]
@@ -100,6 +223,128 @@ pub enum RpcResponse {
     Disconnected,
     /// Verb was successfully programmed
     ProgramSuccess(Objid, String),
+    /// The code given to `CompileCheck` compiles.
+    CompileCheckPassed,
+    /// The outbound connection is this host's to make; the token goes with any failure report.
+    OutboundConnectionClaimed(ClientToken),
+    /// The outbound connection was handed to the task which asked for it, as this object.
+    OutboundConnectionAccepted(Objid),
+    ServerInfo(ServerInfo),
+    /// Replacements for the tokens given to `RefreshTokens`.
+    TokensRefreshed(ClientToken, Option<AuthToken>),
+    TokensRevoked,
+    HostRegistered,
+    PerformanceCounters(Vec<VerbPerfCounter>),
+    VerbDisassembly(Disassembly),
+    BreakpointSet,
+    /// Whether the breakpoint had been set.
+    BreakpointCleared(bool),
+    PausedTasks(Vec<(usize /* task id */, PausedActivation)>),
+    DebugResumed,
+    Locals(Vec<(String, Var)>),
+    LocalSet,
+    /// A successful login, for hosts new enough to take it (see
+    /// `LOGIN_SUCCEEDED_PROTOCOL_VERSION`). The metadata is best-effort, and read in the login's
+    /// own transaction; hosts that don't need it can ignore it.
+    LoginSucceeded(AuthToken, ConnectType, Objid, Option<PlayerMetadata>),
+    History(Vec<NarrativeEvent>),
+}
+
+/// How often a verb has been run since startup, and for how long.
+#[derive(Debug, Clone, Eq, PartialEq, Encode, Decode)]
+pub struct VerbPerfCounter {
+    pub definer: Objid,
+    pub verb_name: String,
+    pub invocations: u64,
+    /// Time spent in the verb's own code, not counting the verbs it calls.
+    pub cumulative_time: Duration,
+}
+
+/// A snapshot of the server's status, for clients and monitoring.
+#[derive(Debug, Clone, Eq, PartialEq, Encode, Decode)]
+pub struct ServerInfo {
+    /// Same as what `server_version()` returns.
+    pub version: String,
+    /// (component, version) pairs, same as what `server_version(1)` returns.
+    pub components: Vec<(String, String)>,
+    pub start_time: SystemTime,
+    /// Logged-in players with at least one connection.
+    pub connected_players: usize,
+    /// All connections, whether logged in or not, across all hosts.
+    pub connections: usize,
+    /// Hosts (telnet, web, ...) which have registered with the daemon.
+    pub hosts: usize,
+    /// Events dropped since startup because the host they were for wasn't keeping up.
+    pub events_dropped: u64,
+}
+
+/// Decode a request from a host which says it speaks protocol `version` (0 for hosts from before
+/// versioning), explaining why if it can't be. Requests in a version we don't support aren't
+/// decoded at all: even if the bytes happened to make sense, they might not mean what we'd think.
+pub fn decode_request(version: u16, body: &[u8]) -> Result<RpcRequest, RpcRequestError> {
+    if !(MIN_RPC_PROTOCOL_VERSION..=RPC_PROTOCOL_VERSION).contains(&version) {
+        return Err(RpcRequestError::UnsupportedVersion(
+            version,
+            MIN_RPC_PROTOCOL_VERSION,
+            RPC_PROTOCOL_VERSION,
+        ));
+    }
+    match bincode::decode_from_slice(body, bincode::config::standard()) {
+        Ok((request, _)) => Ok(request),
+        Err(DecodeError::UnexpectedVariant {
+            type_name, found, ..
+        }) => Err(RpcRequestError::UnknownMessage(format!(
+            "{type_name} variant {found}"
+        ))),
+        Err(_) => Err(RpcRequestError::InvalidRequest),
+    }
+}
+
+/// Wrap an encoded `ConnectionEvent` up for sending to a client, zstd-compressing it if it's at
+/// least `compress_threshold` bytes (and compressing actually makes it smaller).
+pub fn frame_connection_event(event_bytes: &[u8], compress_threshold: Option<usize>) -> Vec<u8> {
+    if compress_threshold.is_some_and(|threshold| event_bytes.len() >= threshold) {
+        if let Ok(compressed) = zstd::bulk::compress(event_bytes, 0) {
+            if compressed.len() < event_bytes.len() {
+                let mut frame = Vec::with_capacity(compressed.len() + 1);
+                frame.push(EVENT_FRAME_ZSTD);
+                frame.extend_from_slice(&compressed);
+                return frame;
+            }
+        }
+    }
+    let mut frame = Vec::with_capacity(event_bytes.len() + 1);
+    frame.push(EVENT_FRAME_PLAIN);
+    frame.extend_from_slice(event_bytes);
+    frame
+}
+
+/// Unwrap and decode an event framed by `frame_connection_event`.
+pub fn decode_connection_event(frame: &[u8]) -> Result<ConnectionEvent, RpcError> {
+    let event_bytes = match frame.split_first() {
+        Some((&EVENT_FRAME_PLAIN, event_bytes)) => Cow::Borrowed(event_bytes),
+        Some((&EVENT_FRAME_ZSTD, compressed)) => {
+            Cow::Owned(zstd::stream::decode_all(compressed).map_err(|e| {
+                RpcError::CouldNotDecode(format!("Unable to decompress narrative message: {}", e))
+            })?)
+        }
+        Some((encoding, _)) => {
+            return Err(RpcError::CouldNotDecode(format!(
+                "Unknown narrative message encoding: {}",
+                encoding
+            )))
+        }
+        None => {
+            return Err(RpcError::CouldNotDecode(
+                "Empty narrative message".to_string(),
+            ))
+        }
+    };
+    let (event, _) = bincode::decode_from_slice(&event_bytes, bincode::config::standard())
+        .map_err(|e| {
+            RpcError::CouldNotDecode(format!("Unable to decode narrative message: {}", e))
+        })?;
+    Ok(event)
 }
 
 /// Errors at the call/request level.
@@ -113,6 +358,8 @@ pub enum RpcRequestError {
     NoConnection,
     #[error("Could not retrieve system property")]
     ErrorCouldNotRetrieveSysProp(String),
+    /// The login verb raised an error or was aborted. (A login verb which just didn't return a
+    /// player is an ordinary failed login; see `RpcResponse::LoginResult`.)
     #[error("Could not login")]
     LoginTaskFailed,
     #[error("Could not create narrative session")]
@@ -127,6 +374,15 @@ pub enum RpcRequestError {
     InternalError(String),
     #[error("Attempt to program failed: {0:?}")]
     VerbProgramFailed(VerbProgramError),
+    #[error("Host speaks protocol version {0}, but the daemon only understands {1} through {2}")]
+    UnsupportedVersion(u16, u16, u16),
+    #[error("Unknown message: {0}")]
+    UnknownMessage(String),
+    #[error("Too many requests; slow down")]
+    TooManyRequests,
+    /// The login verb didn't finish in time, and was aborted.
+    #[error("Login took too long")]
+    LoginTimeout,
 }
 
 /// Events which occur over the pubsub channel, per client.
@@ -140,9 +396,17 @@ pub enum ConnectionEvent {
     /// the attached request id.
     RequestInput(u128),
     /// The system wants to send a message to the given object on its current active connections.
-    SystemMessage(Objid, String),
+    /// The last field is the message's content type (e.g. `text/html` or `text/djot`), for clients
+    /// which can render it richly; `None` means plain text. Other clients just show the text.
+    SystemMessage(Objid, String, Option<String>),
     /// The system wants to disconnect the given object from all its current active connections.
     Disconnect(),
+    /// The given object's presence changed (e.g. they started typing). Only meaningful to clients
+    /// which can display it; plain-text clients should ignore it.
+    Presence(Objid, PresenceKind),
+    /// This many events for the connection were dropped because it wasn't keeping up with them.
+    /// Sent ahead of the next event which does get through.
+    EventsLost(u64),
 }
 
 /// Events which occur over the pubsub channel, but are for all hosts.
@@ -155,5 +419,95 @@ pub enum BroadcastEvent {
     /// current time. This could be used in the future to synchronize event times, but isn't currently
     /// used.)
     PingPong(SystemTime),
+    /// The system wants an outbound TCP connection made to the given host and port, on behalf of
+    /// `open_network_connection()`. A host which can do this should claim it with
+    /// `ClaimOutboundConnection`, and if that succeeds, connect, establish the connection as usual,
+    /// and then reply with `OutboundConnectionEstablished` (or `OutboundConnectionFailed`) carrying
+    /// the same request id. Hosts which can't should ignore it.
+    OpenConnection {
+        request_id: u128,
+        host: String,
+        port: u16,
+    },
     // TODO: Shutdown, Broadcast BroadcastEvent messages in RPC layer
 }
+
+#[cfg(test)]
+mod tests {
+    use moor_values::model::NarrativeEvent;
+    use moor_values::var::Objid;
+
+    use crate::{
+        decode_connection_event, decode_request, frame_connection_event, ClientToken,
+        ConnectionEvent, RpcRequest, RpcRequestError, EVENT_FRAME_PLAIN, EVENT_FRAME_ZSTD,
+        MIN_RPC_PROTOCOL_VERSION, RPC_PROTOCOL_VERSION,
+    };
+
+    #[test]
+    fn test_decode_request() {
+        let request = RpcRequest::Detach(ClientToken("token".to_string()));
+        let body = bincode::encode_to_vec(&request, bincode::config::standard()).unwrap();
+        assert_eq!(decode_request(RPC_PROTOCOL_VERSION, &body), Ok(request));
+    }
+
+    #[test]
+    fn test_decode_unknown_variant() {
+        // A discriminant well past any variant we have, as a newer host might send.
+        let body = [250u8];
+        assert_eq!(
+            decode_request(RPC_PROTOCOL_VERSION, &body),
+            Err(RpcRequestError::UnknownMessage(
+                "RpcRequest variant 250".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_decode_unsupported_version() {
+        // Perfectly good bytes, but from hosts we can't trust to mean the same thing by them.
+        let request = RpcRequest::Detach(ClientToken("token".to_string()));
+        let body = bincode::encode_to_vec(&request, bincode::config::standard()).unwrap();
+        for version in [MIN_RPC_PROTOCOL_VERSION - 1, RPC_PROTOCOL_VERSION + 1] {
+            assert_eq!(
+                decode_request(version, &body),
+                Err(RpcRequestError::UnsupportedVersion(
+                    version,
+                    MIN_RPC_PROTOCOL_VERSION,
+                    RPC_PROTOCOL_VERSION
+                ))
+            );
+        }
+        assert_eq!(decode_request(MIN_RPC_PROTOCOL_VERSION, &body), Ok(request));
+    }
+
+    fn narrative(text: String) -> (ConnectionEvent, Vec<u8>) {
+        let event =
+            ConnectionEvent::Narrative(Objid(1), NarrativeEvent::notify_text(Objid(2), text));
+        let bytes = bincode::encode_to_vec(&event, bincode::config::standard()).unwrap();
+        (event, bytes)
+    }
+
+    #[test]
+    fn test_large_event_roundtrip() {
+        // A long table, say: big, and repetitive enough to compress well.
+        let (event, bytes) = narrative("| cell | cell | cell |\n".repeat(1000));
+
+        let compressed = frame_connection_event(&bytes, Some(1024));
+        assert_eq!(compressed[0], EVENT_FRAME_ZSTD);
+        assert!(compressed.len() < bytes.len());
+        assert_eq!(decode_connection_event(&compressed).unwrap(), event);
+
+        let uncompressed = frame_connection_event(&bytes, None);
+        assert_eq!(uncompressed[0], EVENT_FRAME_PLAIN);
+        assert_eq!(decode_connection_event(&uncompressed).unwrap(), event);
+    }
+
+    #[test]
+    fn test_small_event_stays_uncompressed() {
+        let (event, bytes) = narrative("hello".to_string());
+        let frame = frame_connection_event(&bytes, Some(1024));
+        assert_eq!(frame[0], EVENT_FRAME_PLAIN);
+        assert_eq!(&frame[1..], &bytes[..]);
+        assert_eq!(decode_connection_event(&frame).unwrap(), event);
+    }
+}
@@ -17,7 +17,7 @@ use tracing::trace;
 use uuid::Uuid;
 use zmq::Socket;
 
-use rpc_common::{BroadcastEvent, ConnectionEvent, RpcError};
+use rpc_common::{decode_connection_event, BroadcastEvent, ConnectionEvent, RpcError};
 
 /// Blocking receive on the narrative channel, returning a `ConnectionEvent`.
 pub fn narrative_recv(client_id: Uuid, subscribe: &Socket) -> Result<ConnectionEvent, RpcError> {
@@ -27,7 +27,7 @@ pub fn narrative_recv(client_id: Uuid, subscribe: &Socket) -> Result<ConnectionE
         ));
     };
 
-    // bincode decode the message, and it should be ConnectionEvent
+    // It should be a (framed) bincode encoded ConnectionEvent.
     if inbound.len() != 2 {
         return Err(RpcError::CouldNotDecode(format!(
             "Unexpected message length: {}",
@@ -47,12 +47,7 @@ pub fn narrative_recv(client_id: Uuid, subscribe: &Socket) -> Result<ConnectionE
         return Err(RpcError::CouldNotDecode("Unexpected client ID".to_string()));
     }
 
-    let decode_result = bincode::decode_from_slice(event.as_ref(), bincode::config::standard());
-    let (msg, _msg_size): (ConnectionEvent, usize) = decode_result.map_err(|e| {
-        RpcError::CouldNotDecode(format!("Unable to decode narrative message: {}", e))
-    })?;
-
-    Ok(msg)
+    decode_connection_event(event.as_ref())
 }
 
 /// Blocking receive on the broadcast channel, returning a `BroadcastEvent`.
@@ -48,6 +48,23 @@ struct Args {
     )]
     narrative_server: String,
 
+    #[arg(
+        long,
+        value_name = "print-messages",
+        help = "Print the standard ** Connected ** etc. lines to connections, for cores which don't print their own",
+        action = clap::ArgAction::Set,
+        default_value = "true"
+    )]
+    print_messages: bool,
+
+    #[arg(
+        long,
+        value_name = "out-of-band-prefix",
+        help = "Lines starting with this are sent out-of-band (to $do_out_of_band_command) rather than run as commands. Empty to send nothing out-of-band",
+        default_value = "#$#"
+    )]
+    out_of_band_prefix: String,
+
     #[arg(long, help = "Enable debug logging", default_value = "false")]
     debug: bool,
 }
@@ -82,6 +99,8 @@ async fn main() -> Result<(), eyre::Error> {
         telnet_sockaddr,
         args.rpc_server.as_str(),
         args.narrative_server.as_str(),
+        args.print_messages,
+        (!args.out_of_band_prefix.is_empty()).then_some(args.out_of_band_prefix),
     );
 
     info!("Host started.");
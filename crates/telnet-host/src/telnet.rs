@@ -12,7 +12,9 @@
 // this program. If not, see <https://www.gnu.org/licenses/>.
 //
 
+use std::collections::VecDeque;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::SystemTime;
 
 use eyre::bail;
@@ -24,25 +26,24 @@ use tmq::subscribe::Subscribe;
 use tmq::{request, subscribe};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::select;
+use tokio::time::{Instant, Interval};
 use tokio_util::codec::{Framed, LinesCodec};
-use tracing::{debug, error, info, trace};
+use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
 
-use moor_values::model::{CommandError, VerbProgramError};
+use moor_values::model::{CommandError, Event, VerbProgramError};
 use moor_values::util::parse_into_words;
 use moor_values::var::Objid;
+use moor_values::SYSTEM_OBJECT;
 use rpc_async_client::pubsub_client::{broadcast_recv, narrative_recv};
 use rpc_async_client::rpc_client::RpcSendClient;
 use rpc_common::RpcRequest::ConnectionEstablish;
 use rpc_common::{
-    AuthToken, BroadcastEvent, ClientToken, ConnectType, ConnectionEvent, RpcRequestError,
-    RpcResult, BROADCAST_TOPIC,
+    AuthToken, BroadcastEvent, ClientToken, ConnectType, ConnectionEvent, HostType, ListenerPoint,
+    RpcRequestError, RpcResult, BROADCAST_TOPIC, TOKEN_REFRESH_INTERVAL,
 };
 use rpc_common::{RpcRequest, RpcResponse};
 
-/// Out of band messages are prefixed with this string, e.g. for MCP clients.
-const OUT_OF_BAND_PREFIX: &str = "#$#";
-
 pub(crate) struct TelnetConnection {
     client_id: Uuid,
     /// Current PASETO token.
@@ -65,6 +66,7 @@ enum LineMode {
 impl TelnetConnection {
     async fn run(
         &mut self,
+        listener: &ListenerPoint,
         narrative_sub: &mut Subscribe,
         broadcast_sub: &mut Subscribe,
         rpc_client: &mut RpcSendClient,
@@ -86,16 +88,25 @@ impl TelnetConnection {
             bail!("Unable to authorize connection");
         };
 
-        let connect_message = match connect_type {
-            ConnectType::Connected => "** Connected **",
-            ConnectType::Reconnected => "** Reconnected **",
-            ConnectType::Created => "** Created **",
-        };
-        self.write.send(connect_message.to_string()).await?;
+        if listener.print_messages {
+            let connect_message = match connect_type {
+                ConnectType::Connected => "** Connected **",
+                ConnectType::Reconnected => "** Reconnected **",
+                ConnectType::Created => "** Created **",
+                ConnectType::Redirected => "** Redirected **",
+            };
+            self.write.send(connect_message.to_string()).await?;
+        }
 
         debug!(?player, client_id = ?self.client_id, "Entering command dispatch loop");
         if self
-            .command_loop(auth_token.clone(), narrative_sub, broadcast_sub, rpc_client)
+            .command_loop(
+                auth_token.clone(),
+                listener,
+                narrative_sub,
+                broadcast_sub,
+                rpc_client,
+            )
             .await
             .is_err()
         {
@@ -120,8 +131,12 @@ impl TelnetConnection {
         rpc_client: &mut RpcSendClient,
     ) -> Result<(AuthToken, Objid, ConnectType), eyre::Error> {
         debug!(client_id = ?self.client_id, "Entering auth loop");
+        let mut refresh = token_refresh_interval();
         loop {
             select! {
+                _ = refresh.tick() => {
+                    self.refresh_tokens(None, rpc_client).await?;
+                }
                 Ok(event) = broadcast_recv(broadcast_sub) => {
                     trace!(?event, "broadcast_event");
                     match event {
@@ -129,18 +144,23 @@ impl TelnetConnection {
                             let _ = rpc_client.make_rpc_call(self.client_id,
                                 RpcRequest::Pong(self.client_token.clone(), SystemTime::now())).await?;
                         }
+                        // Handled by the listen loop.
+                        BroadcastEvent::OpenConnection { .. } => {}
                     }
                 }
                 Ok(event) = narrative_recv(self.client_id, narrative_sub) => {
                     trace!(?event, "narrative_event");
                     match event {
-                        ConnectionEvent::SystemMessage(_author, msg) => {
-                            self.write.send(msg).await.with_context(|| "Unable to send message to client")?;
+                        ConnectionEvent::SystemMessage(_author, msg, content_type) => {
+                            self.write.send(plain_text(msg, content_type.as_deref())).await.with_context(|| "Unable to send message to client")?;
+                        }
+                        // Presence is for clients which can display it; as text it'd just be noise.
+                        ConnectionEvent::Presence(..) => {}
+                        ConnectionEvent::EventsLost(count) => {
+                            self.write.send(format!("** {} messages of output were lost **", count)).await.with_context(|| "Unable to send message to client")?;
                         }
                         ConnectionEvent::Narrative(_author, event) => {
-                            let msg = event.event();
-                            let moor_values::model::Event::TextNotify(msg_text) = msg;
-                            self.write.send(msg_text).await.with_context(|| "Unable to send message to client")?;
+                            self.write.send(narrative_text(event.event())).await.with_context(|| "Unable to send message to client")?;
                         }
                         ConnectionEvent::RequestInput(_request_id) => {
                             bail!("RequestInput before login");
@@ -160,9 +180,23 @@ impl TelnetConnection {
                     let words = parse_into_words(&line);
                     let response = rpc_client.make_rpc_call(self.client_id,
                         RpcRequest::LoginCommand(self.client_token.clone(), words, true)).await.expect("Unable to send login request to RPC server");
-                    if let RpcResult::Success(RpcResponse::LoginResult(Some((auth_token, connect_type, player)))) = response {
-                        info!(?player, client_id = ?self.client_id, "Login successful");
-                        return Ok((auth_token, player, connect_type))
+                    match response {
+                        RpcResult::Success(RpcResponse::LoginSucceeded(auth_token, connect_type, player, _metadata)) |
+                        RpcResult::Success(RpcResponse::LoginResult(Some((auth_token, connect_type, player)))) => {
+                            info!(?player, client_id = ?self.client_id, "Login successful");
+                            return Ok((auth_token, player, connect_type))
+                        }
+                        // A plain failed login is left to the core to explain.
+                        RpcResult::Success(RpcResponse::LoginResult(None)) => {}
+                        RpcResult::Failure(RpcRequestError::LoginTimeout) => {
+                            self.write.send("Login took too long; please try again.".to_string()).await?;
+                        }
+                        RpcResult::Failure(RpcRequestError::LoginTaskFailed) => {
+                            self.write.send("Login failed due to a server error.".to_string()).await?;
+                        }
+                        response => {
+                            warn!(?response, "Unexpected login response");
+                        }
                     }
                 }
             }
@@ -171,15 +205,20 @@ impl TelnetConnection {
 
     async fn command_loop(
         &mut self,
-        auth_token: AuthToken,
+        mut auth_token: AuthToken,
+        listener: &ListenerPoint,
         narrative_sub: &mut Subscribe,
         broadcast_sub: &mut Subscribe,
         rpc_client: &mut RpcSendClient,
     ) -> Result<(), eyre::Error> {
         let mut line_mode = LineMode::Input;
         let mut program_input = vec![];
+        let mut refresh = token_refresh_interval();
         loop {
             select! {
+                _ = refresh.tick() => {
+                    self.refresh_tokens(Some(&mut auth_token), rpc_client).await?;
+                }
                 line = self.read.next() => {
                     let Some(line) = line else {
                         info!("Connection closed");
@@ -226,9 +265,9 @@ impl TelnetConnection {
                                 continue
                             }
 
-                            // If the line begins with the out of band prefix, then send it that way,
-                            // instead. And really just fire and forget.
-                            if line.starts_with(OUT_OF_BAND_PREFIX) {
+                            // If the line begins with the listener's out of band prefix, then send it
+                            // that way, instead. And really just fire and forget.
+                            if listener.is_out_of_band(&line) {
                                 rpc_client.make_rpc_call(self.client_id, RpcRequest::OutOfBand(self.client_token.clone(), auth_token.clone(), line)).await?
                             } else {
                                 rpc_client.make_rpc_call(self.client_id, RpcRequest::Command(self.client_token.clone(), auth_token.clone(), line)).await?
@@ -282,6 +321,9 @@ impl TelnetConnection {
                         RpcResult::Failure(RpcRequestError::VerbProgramFailed(_)) => {
                             self.write.send("That object does not have that verb definition.".to_string()).await?;
                         }
+                        RpcResult::Failure(RpcRequestError::TooManyRequests) => {
+                            self.write.send("You're doing that too often; slow down.".to_string()).await?;
+                        }
                         RpcResult::Failure(e) => {
                             error!("Unhandled RPC error: {:?}", e);
                             continue;
@@ -303,24 +345,30 @@ impl TelnetConnection {
                             let _ = rpc_client.make_rpc_call(self.client_id,
                                 RpcRequest::Pong(self.client_token.clone(), SystemTime::now())).await?;
                         }
+                        // Handled by the listen loop.
+                        BroadcastEvent::OpenConnection { .. } => {}
                     }
                 }
                 Ok(event) = narrative_recv(self.client_id, narrative_sub) => {
                     match event {
-                        ConnectionEvent::SystemMessage(_author, msg) => {
-                            self.write.send(msg).await.with_context(|| "Unable to send message to client")?;
+                        ConnectionEvent::SystemMessage(_author, msg, content_type) => {
+                            self.write.send(plain_text(msg, content_type.as_deref())).await.with_context(|| "Unable to send message to client")?;
+                        }
+                        ConnectionEvent::Presence(..) => {}
+                        ConnectionEvent::EventsLost(count) => {
+                            self.write.send(format!("** {} messages of output were lost **", count)).await.with_context(|| "Unable to send message to client")?;
                         }
                         ConnectionEvent::Narrative(_author, event) => {
-                            let msg = event.event();
-                            let moor_values::model::Event::TextNotify(msg_text) = msg;
-                            self.write.send(msg_text).await.with_context(|| "Unable to send message to client")?;
+                            self.write.send(narrative_text(event.event())).await.with_context(|| "Unable to send message to client")?;
                         }
                         ConnectionEvent::RequestInput(request_id) => {
                             // Server is requesting that the next line of input get sent through as a response to this request.
                             line_mode = LineMode::WaitingReply(request_id);
                         }
                         ConnectionEvent::Disconnect() => {
-                            self.write.send("** Disconnected **".to_string()).await.expect("Unable to send disconnect message to client");
+                            if listener.print_messages {
+                                self.write.send("** Disconnected **".to_string()).await.expect("Unable to send disconnect message to client");
+                            }
                             self.write.close().await.expect("Unable to close connection");
                             return Ok(())
                         }
@@ -329,12 +377,358 @@ impl TelnetConnection {
             }
         }
     }
+
+    /// Trade our tokens in for fresh ones, so they don't expire under a long-lived connection.
+    /// The auth token, once we've logged in and have one, is refreshed along with the client token.
+    async fn refresh_tokens(
+        &mut self,
+        auth_token: Option<&mut AuthToken>,
+        rpc_client: &mut RpcSendClient,
+    ) -> Result<(), eyre::Error> {
+        let response = rpc_client
+            .make_rpc_call(
+                self.client_id,
+                RpcRequest::RefreshTokens(
+                    self.client_token.clone(),
+                    auth_token.as_deref().cloned(),
+                ),
+            )
+            .await?;
+        let RpcResult::Success(RpcResponse::TokensRefreshed(client_token, new_auth_token)) =
+            response
+        else {
+            bail!("Unable to refresh tokens: {:?}", response);
+        };
+        self.client_token = client_token;
+        if let (Some(auth_token), Some(new_auth_token)) = (auth_token, new_auth_token) {
+            *auth_token = new_auth_token;
+        }
+        Ok(())
+    }
+
+    /// Outbound connections never log in. Output is written to the socket as usual, but lines
+    /// read from it are held until a task asks for them with `read()`.
+    async fn outbound_loop(
+        &mut self,
+        narrative_sub: &mut Subscribe,
+        broadcast_sub: &mut Subscribe,
+        rpc_client: &mut RpcSendClient,
+    ) -> Result<(), eyre::Error> {
+        let mut pending_lines = VecDeque::new();
+        let mut waiting_reply = None;
+        let mut refresh = token_refresh_interval();
+        loop {
+            select! {
+                _ = refresh.tick() => {
+                    self.refresh_tokens(None, rpc_client).await?;
+                }
+                line = self.read.next() => {
+                    let Some(line) = line else {
+                        info!("Outbound connection closed");
+                        break;
+                    };
+                    pending_lines.push_back(line?);
+                }
+                Ok(event) = broadcast_recv(broadcast_sub) => {
+                    trace!(?event, "broadcast_event");
+                    match event {
+                        BroadcastEvent::PingPong(_server_time) => {
+                            let _ = rpc_client.make_rpc_call(self.client_id,
+                                RpcRequest::Pong(self.client_token.clone(), SystemTime::now())).await?;
+                        }
+                        // Handled by the listen loop.
+                        BroadcastEvent::OpenConnection { .. } => {}
+                    }
+                }
+                Ok(event) = narrative_recv(self.client_id, narrative_sub) => {
+                    match event {
+                        ConnectionEvent::SystemMessage(_author, msg, content_type) => {
+                            self.write.send(plain_text(msg, content_type.as_deref())).await.with_context(|| "Unable to send message to outbound connection")?;
+                        }
+                        ConnectionEvent::Presence(..) => {}
+                        ConnectionEvent::EventsLost(count) => {
+                            self.write.send(format!("** {} messages of output were lost **", count)).await.with_context(|| "Unable to send message to outbound connection")?;
+                        }
+                        ConnectionEvent::Narrative(_author, event) => {
+                            self.write.send(narrative_text(event.event())).await.with_context(|| "Unable to send message to outbound connection")?;
+                        }
+                        ConnectionEvent::RequestInput(request_id) => {
+                            waiting_reply = Some(request_id);
+                        }
+                        ConnectionEvent::Disconnect() => {
+                            self.write.close().await?;
+                            break;
+                        }
+                    }
+                }
+            }
+
+            // Hand over the oldest line we've got, if a task is waiting for one.
+            if let Some(request_id) = waiting_reply {
+                if let Some(line) = pending_lines.pop_front() {
+                    waiting_reply = None;
+                    rpc_client
+                        .make_rpc_call(
+                            self.client_id,
+                            RpcRequest::OutboundRequestedInput(
+                                self.client_token.clone(),
+                                request_id,
+                                line,
+                            ),
+                        )
+                        .await?;
+                }
+            }
+        }
+
+        rpc_client
+            .make_rpc_call(
+                self.client_id,
+                RpcRequest::Detach(self.client_token.clone()),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// Ticks every `TOKEN_REFRESH_INTERVAL`, the first time one interval from now.
+fn token_refresh_interval() -> Interval {
+    tokio::time::interval_at(
+        Instant::now() + TOKEN_REFRESH_INTERVAL,
+        TOKEN_REFRESH_INTERVAL,
+    )
+}
+
+/// Telnet is plain text, so rich content gets downgraded: HTML loses its markup, and anything else
+/// (djot, JSON, ...) is left as is, being readable enough already.
+fn plain_text(content: String, content_type: Option<&str>) -> String {
+    if content_type != Some("text/html") {
+        return content;
+    }
+    let mut text = String::with_capacity(content.len());
+    let mut in_tag = false;
+    for c in content.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+fn narrative_text(event: Event) -> String {
+    match event {
+        Event::TextNotify(text) => text,
+        Event::RichNotify(content, content_type) => plain_text(content, Some(&content_type)),
+    }
+}
+
+/// Let the RPC server know about a new connection, and subscribe to the events it'll be sent.
+async fn establish_connection(
+    zmq_ctx: &tmq::Context,
+    rpc_address: &str,
+    pubsub_address: &str,
+    client_id: Uuid,
+    stream: TcpStream,
+    peer_addr: SocketAddr,
+) -> Result<(TelnetConnection, Subscribe, Subscribe, RpcSendClient), eyre::Error> {
+    let rcp_request_sock = request(zmq_ctx)
+        .set_rcvtimeo(100)
+        .set_sndtimeo(100)
+        .connect(rpc_address)
+        .expect("Unable to bind RPC server for connection");
+
+    // And let the RPC server know we're here, and it should start sending events on the
+    // narrative subscription.
+    debug!(rpc_address, "Contacting RPC server to establish connection");
+    let mut rpc_client = RpcSendClient::new(rcp_request_sock);
+
+    let (token, connection_oid) = match rpc_client
+        .make_rpc_call(client_id, ConnectionEstablish(peer_addr.to_string()))
+        .await
+    {
+        Ok(RpcResult::Success(RpcResponse::NewConnection(token, objid))) => {
+            info!("Connection established, connection ID: {}", objid);
+            (token, objid)
+        }
+        Ok(RpcResult::Failure(f)) => {
+            bail!("RPC failure in connection establishment: {}", f);
+        }
+        Ok(_) => {
+            bail!("Unexpected response from RPC server");
+        }
+        Err(e) => {
+            bail!("Unable to establish connection: {}", e);
+        }
+    };
+    debug!(client_id = ?client_id, connection = ?connection_oid, "Connection established");
+
+    // Before attempting login, we subscribe to the narrative channel, using our client
+    // id. The daemon should be sending events here.
+    let narrative_sub = subscribe(zmq_ctx)
+        .connect(pubsub_address)
+        .expect("Unable to connect narrative subscriber ");
+    let narrative_sub = narrative_sub
+        .subscribe(&client_id.as_bytes()[..])
+        .expect("Unable to subscribe to narrative messages for client connection");
+
+    let broadcast_sub = subscribe(zmq_ctx)
+        .connect(pubsub_address)
+        .expect("Unable to connect broadcast subscriber ");
+    let broadcast_sub = broadcast_sub
+        .subscribe(BROADCAST_TOPIC)
+        .expect("Unable to subscribe to broadcast messages for client connection");
+
+    info!(
+        "Subscribed on pubsub socket for {:?}, socket addr {}",
+        client_id, pubsub_address
+    );
+
+    // Re-ify the connection.
+    let framed_stream = Framed::new(stream, LinesCodec::new());
+    let (write, read): (SplitSink<Framed<TcpStream, LinesCodec>, String>, _) =
+        framed_stream.split();
+    let connection = TelnetConnection {
+        client_token: token,
+        client_id,
+        write,
+        read,
+    };
+    Ok((connection, narrative_sub, broadcast_sub, rpc_client))
+}
+
+/// Register this host's listener with the daemon. We do this at startup and again on every ping,
+/// since the daemon forgets hosts it stops hearing from (and everything, if it restarts). Failing
+/// to is logged, but not fatal: it only affects what `listeners()` reports.
+async fn register_host(
+    zmq_ctx: &tmq::Context,
+    rpc_address: &str,
+    host_id: Uuid,
+    listener_point: &ListenerPoint,
+) {
+    let rcp_request_sock = request(zmq_ctx)
+        .set_rcvtimeo(100)
+        .set_sndtimeo(100)
+        .connect(rpc_address)
+        .expect("Unable to bind RPC server for host registration");
+    match RpcSendClient::new(rcp_request_sock)
+        .make_rpc_call(
+            host_id,
+            RpcRequest::RegisterHost(vec![listener_point.clone()]),
+        )
+        .await
+    {
+        Ok(RpcResult::Success(RpcResponse::HostRegistered)) => {
+            debug!(?host_id, "Registered host with RPC server");
+        }
+        response => {
+            warn!(?response, "Unable to register host with RPC server");
+        }
+    }
+}
+
+/// Claim the outbound connection the daemon asked for (via `open_network_connection()`), and if
+/// it's ours, open it and report back with the outcome.
+async fn open_outbound_connection(
+    zmq_ctx: tmq::Context,
+    rpc_address: String,
+    pubsub_address: String,
+    request_id: u128,
+    host: String,
+    port: u16,
+) -> Result<(), eyre::Error> {
+    // Every host hears the request, so only go ahead if we're the first to claim it. The
+    // connection is established under the client id we claimed it with.
+    let client_id = Uuid::new_v4();
+    let rcp_request_sock = request(&zmq_ctx)
+        .set_rcvtimeo(100)
+        .set_sndtimeo(100)
+        .connect(rpc_address.as_str())
+        .expect("Unable to bind RPC server for connection");
+    let mut claim_client = RpcSendClient::new(rcp_request_sock);
+    let claim_token = match claim_client
+        .make_rpc_call(client_id, RpcRequest::ClaimOutboundConnection(request_id))
+        .await?
+    {
+        RpcResult::Success(RpcResponse::OutboundConnectionClaimed(token)) => token,
+        response => {
+            debug!(
+                ?host,
+                port,
+                ?response,
+                "Outbound connection claimed elsewhere"
+            );
+            return Ok(());
+        }
+    };
+
+    let stream = match TcpStream::connect((host.as_str(), port)).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            warn!(?host, port, error = ?e, "Unable to open outbound connection");
+            claim_client
+                .make_rpc_call(
+                    client_id,
+                    RpcRequest::OutboundConnectionFailed(claim_token, request_id, e.to_string()),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+    let peer_addr = stream.peer_addr()?;
+    let (mut connection, mut narrative_sub, mut broadcast_sub, mut rpc_client) =
+        establish_connection(
+            &zmq_ctx,
+            rpc_address.as_str(),
+            pubsub_address.as_str(),
+            client_id,
+            stream,
+            peer_addr,
+        )
+        .await?;
+
+    let response = rpc_client
+        .make_rpc_call(
+            connection.client_id,
+            RpcRequest::OutboundConnectionEstablished(connection.client_token.clone(), request_id),
+        )
+        .await?;
+    let RpcResult::Success(RpcResponse::OutboundConnectionAccepted(connection_oid)) = response
+    else {
+        // Nobody wants it any more.
+        info!(
+            ?peer_addr,
+            ?response,
+            "Outbound connection not accepted; closing"
+        );
+        rpc_client
+            .make_rpc_call(
+                connection.client_id,
+                RpcRequest::Abort(connection.client_token.clone()),
+            )
+            .await?;
+        return Ok(());
+    };
+    info!(?peer_addr, connection = ?connection_oid, "Outbound connection accepted");
+
+    connection
+        .outbound_loop(&mut narrative_sub, &mut broadcast_sub, &mut rpc_client)
+        .await
 }
 
 pub async fn telnet_listen_loop(
     telnet_sockaddr: SocketAddr,
     rpc_address: &str,
     narrative_address: &str,
+    print_messages: bool,
+    out_of_band_prefix: Option<String>,
 ) -> Result<(), eyre::Error> {
     let listener = TcpListener::bind(telnet_sockaddr).await?;
     let zmq_ctx = tmq::Context::new();
@@ -342,84 +736,62 @@ pub async fn telnet_listen_loop(
         .set_io_threads(8)
         .expect("Unable to set ZMQ IO threads");
 
-    loop {
-        let (stream, peer_addr) = listener.accept().await?;
-        let zmq_ctx = zmq_ctx.clone();
-        let pubsub_address = narrative_address.to_string();
-        let rpc_address = rpc_address.to_string();
-        tokio::spawn(async move {
-            let client_id = Uuid::new_v4();
-            info!(peer_addr = ?peer_addr, client_id = ?client_id,
-                "Accepted connection"
-            );
-
-            let rcp_request_sock = request(&zmq_ctx)
-                .set_rcvtimeo(100)
-                .set_sndtimeo(100)
-                .connect(rpc_address.as_str())
-                .expect("Unable to bind RPC server for connection");
-
-            // And let the RPC server know we're here, and it should start sending events on the
-            // narrative subscription.
-            debug!(rpc_address, "Contacting RPC server to establish connection");
-            let mut rpc_client = RpcSendClient::new(rcp_request_sock);
-
-            let (token, connection_oid) = match rpc_client
-                .make_rpc_call(client_id, ConnectionEstablish(peer_addr.to_string()))
-                .await
-            {
-                Ok(RpcResult::Success(RpcResponse::NewConnection(token, objid))) => {
-                    info!("Connection established, connection ID: {}", objid);
-                    (token, objid)
-                }
-                Ok(RpcResult::Failure(f)) => {
-                    bail!("RPC failure in connection establishment: {}", f);
-                }
-                Ok(_) => {
-                    bail!("Unexpected response from RPC server");
-                }
-                Err(e) => {
-                    bail!("Unable to establish connection: {}", e);
-                }
-            };
-            debug!(client_id = ?client_id, connection = ?connection_oid, "Connection established");
-
-            // Before attempting login, we subscribe to the narrative channel, using our client
-            // id. The daemon should be sending events here.
-            let narrative_sub = subscribe(&zmq_ctx)
-                .connect(pubsub_address.as_str())
-                .expect("Unable to connect narrative subscriber ");
-            let mut narrative_sub = narrative_sub
-                .subscribe(&client_id.as_bytes()[..])
-                .expect("Unable to subscribe to narrative messages for client connection");
-
-            let broadcast_sub = subscribe(&zmq_ctx)
-                .connect(pubsub_address.as_str())
-                .expect("Unable to connect broadcast subscriber ");
-            let mut broadcast_sub = broadcast_sub
-                .subscribe(BROADCAST_TOPIC)
-                .expect("Unable to subscribe to broadcast messages for client connection");
-
-            info!(
-                "Subscribed on pubsub socket for {:?}, socket addr {}",
-                client_id, pubsub_address
-            );
-
-            // Re-ify the connection.
-            let framed_stream = Framed::new(stream, LinesCodec::new());
-            let (write, read): (SplitSink<Framed<TcpStream, LinesCodec>, String>, _) =
-                framed_stream.split();
-            let mut tcp_connection = TelnetConnection {
-                client_token: token,
-                client_id,
-                write,
-                read,
-            };
+    // Tell the daemon where we are, for `listeners()`.
+    let listener_point = ListenerPoint {
+        host_type: HostType::Tcp,
+        addr: telnet_sockaddr,
+        handler: SYSTEM_OBJECT,
+        print_messages,
+        out_of_band_prefix,
+    };
+    let host_id = Uuid::new_v4();
+    register_host(&zmq_ctx, rpc_address, host_id, &listener_point).await;
+    let listener_point = Arc::new(listener_point);
+
+    // We listen on the broadcast channel ourselves, for pings (to which we answer by registering
+    // again) and requests to open outbound connections.
+    let mut broadcast_sub = subscribe(&zmq_ctx)
+        .connect(narrative_address)
+        .expect("Unable to connect broadcast subscriber ")
+        .subscribe(BROADCAST_TOPIC)
+        .expect("Unable to subscribe to broadcast messages for listener");
 
-            tcp_connection
-                .run(&mut narrative_sub, &mut broadcast_sub, &mut rpc_client)
-                .await?;
-            Ok(())
-        });
+    loop {
+        select! {
+            accepted = listener.accept() => {
+                let (stream, peer_addr) = accepted?;
+                let zmq_ctx = zmq_ctx.clone();
+                let pubsub_address = narrative_address.to_string();
+                let rpc_address = rpc_address.to_string();
+                let listener_point = listener_point.clone();
+                tokio::spawn(async move {
+                    info!(peer_addr = ?peer_addr, "Accepted connection");
+                    let (mut tcp_connection, mut narrative_sub, mut broadcast_sub, mut rpc_client) =
+                        establish_connection(&zmq_ctx, rpc_address.as_str(), pubsub_address.as_str(), Uuid::new_v4(), stream, peer_addr).await?;
+                    tcp_connection
+                        .run(&listener_point, &mut narrative_sub, &mut broadcast_sub, &mut rpc_client)
+                        .await?;
+                    Ok::<(), eyre::Error>(())
+                });
+            }
+            Ok(event) = broadcast_recv(&mut broadcast_sub) => {
+                let (request_id, host, port) = match event {
+                    BroadcastEvent::OpenConnection { request_id, host, port } => (request_id, host, port),
+                    BroadcastEvent::PingPong(_) => {
+                        register_host(&zmq_ctx, rpc_address, host_id, &listener_point).await;
+                        continue;
+                    }
+                };
+                info!(?host, port, "Opening outbound connection");
+                tokio::spawn(open_outbound_connection(
+                    zmq_ctx.clone(),
+                    rpc_address.to_string(),
+                    narrative_address.to_string(),
+                    request_id,
+                    host,
+                    port,
+                ));
+            }
+        }
     }
 }
@@ -12,12 +12,19 @@
 // this program. If not, see <https://www.gnu.org/licenses/>.
 //
 
-use moor_moot::{test_db_path, ManagedChild};
+use moor_moot::{test_db_path, ManagedChild, MootClient, NONPROGRAMMER, PROGRAMMER, WIZARD};
+use moor_values::var::Objid;
 use serial_test::serial;
 use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
     path::{Path, PathBuf},
     process::{Command, Stdio},
-    sync::OnceLock,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant},
 };
 
 /// The current DB implementation reserves this much RAM. Default is 1TB, and
@@ -42,6 +49,11 @@ fn daemon_host_bin() -> &'static PathBuf {
 }
 
 fn start_daemon(workdir: &Path) -> ManagedChild {
+    start_daemon_with(workdir, &[])
+}
+
+/// Like `start_daemon`, but passing the daemon some extra arguments.
+fn start_daemon_with(workdir: &Path, extra_args: &[&str]) -> ManagedChild {
     ManagedChild::new(
         "daemon",
         Command::new(daemon_host_bin())
@@ -50,6 +62,7 @@ fn start_daemon(workdir: &Path) -> ManagedChild {
             .arg("--generate-keypair")
             .arg("--max-buffer-pool-bytes")
             .arg(MAX_BUFFER_POOL_BYTES.to_string())
+            .args(extra_args)
             .arg("test.db")
             .current_dir(workdir)
             .stdout(Stdio::piped())
@@ -74,10 +87,16 @@ fn telnet_host_bin() -> &'static PathBuf {
 }
 
 fn start_telnet_host() -> ManagedChild {
+    start_telnet_host_on("0.0.0.0:8080")
+}
+
+fn start_telnet_host_on(telnet_address: &str) -> ManagedChild {
     ManagedChild::new(
         "telnet-host",
         Command::new(telnet_host_bin())
             .arg("--debug")
+            .arg("--telnet-address")
+            .arg(telnet_address)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()
@@ -117,3 +136,262 @@ fn test_echo() {
 fn test_suspend_notify() {
     test_moot_with_telnet_host("suspend_notify");
 }
+
+#[cfg(target_os = "linux")]
+#[test]
+#[serial(telnet_host)]
+fn test_read() {
+    test_moot_with_telnet_host("read");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+#[serial(telnet_host)]
+fn test_presence_not_shown() {
+    test_moot_with_telnet_host("presence");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+#[serial(telnet_host)]
+fn test_notify_content_type() {
+    test_moot_with_telnet_host("notify_content_type");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+#[serial(telnet_host)]
+fn test_connection_options() {
+    test_moot_with_telnet_host("connection_options");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+#[serial(telnet_host)]
+fn test_open_network_connection() {
+    // A line echo server for the MOO to connect out to, which counts the connections it gets.
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind echo server");
+    let port = listener.local_addr().unwrap().port();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    {
+        let accepted = accepted.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    break;
+                };
+                accepted.fetch_add(1, Ordering::SeqCst);
+                std::thread::spawn(move || {
+                    let mut writer = stream.try_clone().expect("Failed to clone stream");
+                    for line in BufReader::new(stream).lines() {
+                        let Ok(line) = line else {
+                            break;
+                        };
+                        if writeln!(writer, "{line}").is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    let daemon_workdir = tempfile::TempDir::new().expect("Failed to create temporary directory");
+    let _daemon = start_daemon(daemon_workdir.path());
+    // Both hosts hear the request, but only one of them should make the connection.
+    let _telnet_host = start_telnet_host();
+    let _other_telnet_host = start_telnet_host_on("0.0.0.0:8081");
+    drop(connect_client_on(8081, WIZARD));
+
+    let mut wizard = connect_client(WIZARD);
+    assert_eq!(
+        wizard
+            .command(format!(
+                r#"; c = open_network_connection("127.0.0.1", {port}); notify(c, "ping"); return read(c);"#
+            ))
+            .unwrap(),
+        r#""ping""#
+    );
+    // Give the other host the chance to connect too, if it were going to.
+    std::thread::sleep(Duration::from_millis(500));
+    assert_eq!(accepted.load(Ordering::SeqCst), 1);
+
+    let mut programmer = connect_client(PROGRAMMER);
+    assert_eq!(
+        programmer
+            .command(format!(
+                r#"; return `open_network_connection("127.0.0.1", {port}) ! ANY';"#
+            ))
+            .unwrap(),
+        "E_PERM"
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+#[serial(telnet_host)]
+fn test_reconnect() {
+    test_moot_with_telnet_host("reconnect");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+#[serial(telnet_host)]
+fn test_connected_players() {
+    test_moot_with_telnet_host("connected_players");
+}
+
+/// Log in as `player` on a fresh telnet connection, retrying while the hosts start up.
+fn connect_client(player: Objid) -> MootClient {
+    connect_client_on(8080, player)
+}
+
+/// Like `connect_client`, but to the telnet host listening on the given port.
+fn connect_client_on(port: u16, player: Objid) -> MootClient {
+    let start = Instant::now();
+    loop {
+        if let Ok(mut client) =
+            MootClient::new(port, Duration::from_secs(1), Duration::from_secs(1))
+        {
+            client.send_string(format!("connect {player}")).unwrap();
+            return client;
+        } else if start.elapsed() > Duration::from_secs(5) {
+            panic!("Failed to connect to telnet host");
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+#[serial(telnet_host)]
+fn test_boot_one_of_several_connections() {
+    let daemon_workdir = tempfile::TempDir::new().expect("Failed to create temporary directory");
+    let _daemon = start_daemon(daemon_workdir.path());
+    let _telnet_host = start_telnet_host();
+
+    // Count calls to $user_disconnected, so we can tell when the player is considered gone.
+    let mut wizard = connect_client(WIZARD);
+    for setup in [
+        r#"; add_property(#0, "disconnects", 0, {player, ""});"#,
+        r#"; add_verb(#0, {player, "rxd", "user_disconnected"}, {"this", "none", "this"});"#,
+        r#"; set_verb_code(#0, "user_disconnected", {"this.disconnects = this.disconnects + 1;"});"#,
+    ] {
+        wizard.command(setup).unwrap();
+    }
+
+    let mut first = connect_client(PROGRAMMER);
+    assert_eq!(first.command("; return 1;").unwrap(), "1");
+    let mut second = connect_client(PROGRAMMER);
+    assert_eq!(second.command("; return 1;").unwrap(), "1");
+
+    // Booting just the second connection leaves the first alive, and the player still connected.
+    let _ = second.command("; boot_player(player, 1);");
+    assert_eq!(
+        first
+            .command("; return player in connected_players() > 0;")
+            .unwrap(),
+        "1"
+    );
+    assert!(second.command("; return 1;").is_err());
+    assert_eq!(wizard.command("; return #0.disconnects;").unwrap(), "0");
+
+    // Booting the last connection is what makes the player leave.
+    let _ = first.command("; boot_player(player, 1);");
+    let start = Instant::now();
+    while wizard.command("; return #0.disconnects;").unwrap() != "1" {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "user_disconnected was not called"
+        );
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+#[serial(telnet_host)]
+fn test_idle_connections_are_dropped() {
+    let daemon_workdir = tempfile::TempDir::new().expect("Failed to create temporary directory");
+    let _daemon = start_daemon_with(daemon_workdir.path(), &["--idle-timeout", "1"]);
+    let _telnet_host = start_telnet_host();
+
+    // Count calls to $user_disconnected, so we can tell when the idle player has been dropped.
+    let mut wizard = connect_client(WIZARD);
+    for setup in [
+        r#"; set_connection_option(player, "idle-exempt", 1);"#,
+        r#"; add_property(#0, "disconnects", 0, {player, ""});"#,
+        r#"; add_verb(#0, {player, "rxd", "user_disconnected"}, {"this", "none", "this"});"#,
+        r#"; set_verb_code(#0, "user_disconnected", {"this.disconnects = this.disconnects + 1;"});"#,
+    ] {
+        wizard.command(setup).unwrap();
+    }
+
+    // A bot which says it may sit idle, and a player who just goes quiet.
+    let mut bot = connect_client(PROGRAMMER);
+    assert_eq!(
+        bot.command(r#"; set_connection_option(player, "idle-exempt", 1); return 1;"#)
+            .unwrap(),
+        "1"
+    );
+    let mut idler = connect_client(NONPROGRAMMER);
+
+    // The reaper runs every few seconds; give it a few goes.
+    let start = Instant::now();
+    while wizard.command("; return #0.disconnects;").unwrap() != "1" {
+        assert!(
+            start.elapsed() < Duration::from_secs(20),
+            "user_disconnected was not called for the idle connection"
+        );
+        std::thread::sleep(Duration::from_millis(250));
+    }
+    assert_eq!(
+        wizard
+            .command(format!(
+                "; return {{{PROGRAMMER} in connected_players(), {NONPROGRAMMER} in connected_players()}};"
+            ))
+            .unwrap(),
+        "{1, 0}"
+    );
+    assert!(idler.command("; return 1;").is_err());
+    assert_eq!(bot.command("; return 1;").unwrap(), "1");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+#[serial(telnet_host)]
+fn test_connection_takeover() {
+    let daemon_workdir = tempfile::TempDir::new().expect("Failed to create temporary directory");
+    let _daemon = start_daemon(daemon_workdir.path());
+    let _telnet_host = start_telnet_host();
+
+    // Turn on takeover, and count calls to $user_redirected.
+    let mut wizard = connect_client(WIZARD);
+    for setup in [
+        r#"; add_property(#0, "server_options", create($nothing), {player, "r"});"#,
+        r#"; add_property($server_options, "connection_takeover", 1, {player, "r"});"#,
+        "; load_server_options();",
+        r#"; add_property(#0, "redirects", 0, {player, ""});"#,
+        r#"; add_verb(#0, {player, "rxd", "user_redirected"}, {"this", "none", "this"});"#,
+        r#"; set_verb_code(#0, "user_redirected", {"this.redirects = this.redirects + 1;"});"#,
+    ] {
+        wizard.command(setup).unwrap();
+    }
+
+    let mut first = connect_client(PROGRAMMER);
+    assert_eq!(first.command("; return 1;").unwrap(), "1");
+    assert_eq!(wizard.command("; return #0.redirects;").unwrap(), "0");
+
+    // Connecting again takes over from the first connection.
+    let mut second = connect_client(PROGRAMMER);
+    assert_eq!(second.command("; return 1;").unwrap(), "1");
+    let start = Instant::now();
+    while wizard.command("; return #0.redirects;").unwrap() != "1" {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "user_redirected was not called"
+        );
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    assert!(first.command("; return 1;").is_err());
+}
@@ -0,0 +1,79 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Benchmarks of the list representation, for comparing the list backings against each other.
+//! Run both with and without `--features list_impl_buffer`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use moor_values::var::{v_int, v_list, Var, Variant};
+use moor_values::AsByteBuffer;
+
+const LIST_SIZE: i64 = 10_000;
+
+fn build_by_push(n: i64) -> Var {
+    let mut list = v_list(&[]);
+    for i in 0..n {
+        let Variant::List(mut l) = list.take_variant() else {
+            unreachable!()
+        };
+        list = l.push(v_int(i));
+    }
+    list
+}
+
+fn list_benches(c: &mut Criterion) {
+    let mut group = c.benchmark_group("list");
+
+    group.bench_function("build_by_push", |b| {
+        b.iter(|| black_box(build_by_push(LIST_SIZE)));
+    });
+
+    let built = build_by_push(LIST_SIZE);
+    let Variant::List(l) = built.variant() else {
+        unreachable!()
+    };
+    group.bench_function("random_access", |b| {
+        b.iter(|| {
+            // A fixed stride through the list, so the access pattern isn't sequential.
+            let mut i = 0;
+            for _ in 0..LIST_SIZE {
+                i = (i + 7919) % l.len();
+                black_box(l.get(i));
+            }
+        });
+    });
+
+    // The same list over and over; with list_impl_buffer, only the first time builds the buffer.
+    group.bench_function("serialize", |b| {
+        b.iter(|| black_box(built.as_bytes().unwrap()));
+    });
+
+    // A list being changed and then stored, which measures it and then serializes it.
+    group.bench_function("push_measure_serialize", |b| {
+        b.iter(|| {
+            let Variant::List(mut l) = built.variant().clone() else {
+                unreachable!()
+            };
+            let pushed = l.push(v_int(LIST_SIZE));
+            black_box(pushed.size_bytes());
+            black_box(pushed.as_bytes().unwrap())
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, list_benches);
+criterion_main!(benches);
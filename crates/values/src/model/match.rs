@@ -17,6 +17,7 @@ use bincode::{Decode, Encode};
 use strum::FromRepr;
 
 use crate::encode::{DecodingError, EncodingError};
+use crate::var::Error;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, FromRepr, Hash, Ord, PartialOrd, Encode, Decode)]
 #[repr(u8)]
@@ -133,6 +134,35 @@ pub enum PrepSpec {
     Other(Preposition),
 }
 
+impl PrepSpec {
+    /// Match a preposition for the form used by set_verb_args and friends, which means it must
+    /// support numeric arguments for the preposition, and the full slash-separated form that
+    /// `verb_args` hands back.
+    #[must_use]
+    pub fn parse(repr: &str) -> Option<Self> {
+        match repr {
+            "any" => Some(Self::Any),
+            "none" => Some(Self::None),
+            _ => find_preposition(repr).map(Self::Other),
+        }
+    }
+}
+
+fn find_preposition(prep: &str) -> Option<Preposition> {
+    // If the string starts with a number (with or without # prefix), treat it as a preposition ID.
+    let numeric_offset = if prep.starts_with('#') { 1 } else { 0 };
+    if let Ok(id) = prep[numeric_offset..].parse::<u16>() {
+        return Preposition::from_repr(id);
+    }
+
+    // "with/using" and friends, as produced by `Preposition::to_string`.
+    if prep.contains('/') {
+        return prep.split('/').next().and_then(Preposition::parse);
+    }
+
+    Preposition::parse(prep)
+}
+
 impl LayoutAs<i16> for PrepSpec {
     type ReadError = DecodingError;
     type WriteError = EncodingError;
@@ -180,6 +210,23 @@ impl VerbArgsSpec {
     }
 }
 
+/// Parse and validate a `{dobj, prep, iobj}` verb argument specification, as taken by `add_verb`
+/// and `set_verb_args` (and returned by `verb_args`). `E_ARGS` if it isn't three items, `E_INVARG`
+/// if any of them isn't valid.
+pub fn parse_verb_args_spec<S: AsRef<str>>(spec: &[S]) -> Result<VerbArgsSpec, Error> {
+    let [dobj, prep, iobj] = spec else {
+        return Err(Error::E_ARGS);
+    };
+    let (Some(dobj), Some(prep), Some(iobj)) = (
+        ArgSpec::from_string(dobj.as_ref()),
+        PrepSpec::parse(prep.as_ref()),
+        ArgSpec::from_string(iobj.as_ref()),
+    ) else {
+        return Err(Error::E_INVARG);
+    };
+    Ok(VerbArgsSpec { dobj, prep, iobj })
+}
+
 impl LayoutAs<u32> for VerbArgsSpec {
     type ReadError = DecodingError;
     type WriteError = EncodingError;
@@ -222,4 +269,38 @@ mod tests {
         let spec2 = VerbArgsSpec::try_read(v).unwrap();
         assert_eq!(spec, spec2);
     }
+
+    #[test]
+    fn parse_verb_args_spec_forms() {
+        use super::{parse_verb_args_spec, ArgSpec, PrepSpec, Preposition, VerbArgsSpec};
+        use crate::var::Error::{E_ARGS, E_INVARG};
+
+        let with = VerbArgsSpec {
+            dobj: ArgSpec::Any,
+            prep: PrepSpec::Other(Preposition::WithUsing),
+            iobj: ArgSpec::This,
+        };
+        assert_eq!(parse_verb_args_spec(&["any", "with", "this"]), Ok(with));
+        assert_eq!(parse_verb_args_spec(&["any", "using", "this"]), Ok(with));
+        assert_eq!(parse_verb_args_spec(&["any", "#0", "this"]), Ok(with));
+        // What `verb_args` returns can be handed straight back.
+        assert_eq!(
+            parse_verb_args_spec(&["any", "with/using", "this"]),
+            Ok(with)
+        );
+
+        assert_eq!(
+            parse_verb_args_spec(&["this", "none", "this"]),
+            Ok(VerbArgsSpec::this_none_this())
+        );
+        assert_eq!(parse_verb_args_spec(&["this", "none"]), Err(E_ARGS));
+        assert_eq!(
+            parse_verb_args_spec(&["them", "none", "this"]),
+            Err(E_INVARG)
+        );
+        assert_eq!(
+            parse_verb_args_spec(&["this", "beyond", "this"]),
+            Err(E_INVARG)
+        );
+    }
 }
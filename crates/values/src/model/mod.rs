@@ -13,6 +13,7 @@
 //
 
 use bincode::{Decode, Encode};
+use std::net::SocketAddr;
 use std::time::SystemTime;
 use strum::Display;
 
@@ -25,14 +26,20 @@ pub use crate::model::objset::{ObjSet, ObjSetIter};
 pub use crate::model::permissions::Perms;
 pub use crate::model::propdef::{PropDef, PropDefs};
 pub use crate::model::props::{PropAttr, PropAttrs, PropFlag, PropPerms};
-pub use crate::model::r#match::{ArgSpec, PrepSpec, Preposition, VerbArgsSpec};
+pub use crate::model::r#match::{
+    parse_verb_args_spec, ArgSpec, PrepSpec, Preposition, VerbArgsSpec,
+};
 pub use crate::model::verb_info::VerbInfo;
 pub use crate::model::verbdef::{VerbDef, VerbDefs};
 pub use crate::model::verbs::{BinaryType, VerbAttr, VerbAttrs, VerbFlag, Vid};
-pub use crate::model::world_state::{WorldState, WorldStateSource};
+pub use crate::model::world_state::{
+    IntegrityFinding, IntegrityProblem, ObjectBytes, WorldState, WorldStateSource,
+    BYTE_QUOTA_PROPERTY, OWNERSHIP_QUOTA_PROPERTY,
+};
 
 use crate::var::Error;
 use crate::var::Objid;
+use crate::var::Var;
 
 mod defset;
 mod r#match;
@@ -78,6 +85,8 @@ pub enum WorldStateError {
     DuplicatePropertyDefinition(Objid, String),
     #[error("Property type mismatch")]
     PropertyTypeMismatch,
+    #[error("Property can't be cleared on its definer: {0}.{1}")]
+    ClearDefinedProperty(Objid, String),
 
     #[error("Verb not found: {0}:{1}")]
     VerbNotFound(Objid, String),
@@ -96,6 +105,9 @@ pub enum WorldStateError {
     #[error("Ambiguous object match: {0}")]
     AmbiguousMatch(String),
 
+    #[error("Quota exceeded for owner: {0}")]
+    QuotaExceeded(Objid),
+
     // Catch-alls for system level object DB errors.
     #[error("DB communications/internal error: {0}")]
     DatabaseError(String),
@@ -121,6 +133,8 @@ impl WorldStateError {
             Self::PropertyDefinitionNotFound(_, _) => Error::E_PROPNF,
             Self::DuplicatePropertyDefinition(_, _) => Error::E_INVARG,
             Self::PropertyTypeMismatch => Error::E_TYPE,
+            Self::ClearDefinedProperty(_, _) => Error::E_INVARG,
+            Self::QuotaExceeded(_) => Error::E_QUOTA,
             _ => {
                 panic!("Unhandled error code: {:?}", self);
             }
@@ -156,6 +170,10 @@ impl From<WorldStateError> for Error {
 pub struct NarrativeEvent {
     /// When the event happened, in the server's system time.
     timestamp: SystemTime,
+    /// Where the event falls among all those published to the same player. Zero until the event
+    /// is published. Unlike the timestamp this is strictly increasing, even across daemon
+    /// restarts, so it's what to order a player's events by.
+    seq: u64,
     /// The object that authored or caused the event.
     author: Objid,
     /// The event itself.
@@ -167,26 +185,118 @@ pub struct NarrativeEvent {
 pub enum Event {
     /// The typical "something happened" descriptive event.
     TextNotify(String),
+    /// The same, but as (content, content type) in something richer than plain text, e.g.
+    /// `text/html` or `text/djot`. Clients which can't render it show it as plain text instead.
+    RichNotify(String, String),
     // TODO: Other Event types on Session stream
     //   other events that might happen here would be things like (local) "object moved" or "object
     //   created."
 }
 
+/// A transient signal about what a player is up to, for clients which can show it (e.g. "X is
+/// typing"). Unlike narrative events these aren't spooled, and clients which can't render them
+/// (like telnet) just drop them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum PresenceKind {
+    Typing,
+    Idle,
+    Active,
+}
+
+/// What kind of host a listener belongs to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum HostType {
+    Tcp,
+    WebSocket,
+}
+
+/// A place a host is accepting connections, as registered with the daemon by the host, and as
+/// reported in-MOO by `listeners()`.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ListenerPoint {
+    pub host_type: HostType,
+    pub addr: SocketAddr,
+    /// The object whose verbs (`do_login_command` and friends) handle connections made here.
+    pub handler: Objid,
+    /// Whether the host prints the standard `** Connected **`-style lines to connections made
+    /// here. Cores which print their own turn this off.
+    pub print_messages: bool,
+    /// Lines starting with this are sent out-of-band (to `$do_out_of_band_command`) rather than
+    /// run as commands. Usually `#$#`, for MCP; None if the host doesn't do out-of-band at all.
+    pub out_of_band_prefix: Option<String>,
+}
+
+impl ListenerPoint {
+    /// Whether a line of input read from a connection made here should go out-of-band. An empty
+    /// prefix is treated as no prefix, rather than matching every line.
+    pub fn is_out_of_band(&self, line: &str) -> bool {
+        self.out_of_band_prefix
+            .as_deref()
+            .is_some_and(|prefix| !prefix.is_empty() && line.starts_with(prefix))
+    }
+}
+
+/// A line of a verb at which tasks being debugged pause.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct Breakpoint {
+    /// The object the verb is defined on.
+    pub definer: Objid,
+    /// Any of the verb's names.
+    pub verb_name: String,
+    pub line: usize,
+}
+
+/// Where a task paused by the debugger is stopped: the verb activation on top of its stack, and
+/// that activation's local variables.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct PausedActivation {
+    pub definer: Objid,
+    pub verb_name: String,
+    /// The line the task is about to execute.
+    pub line: usize,
+    pub this: Objid,
+    pub player: Objid,
+    /// The variables which have been assigned values, by name.
+    pub locals: Vec<(String, Var)>,
+}
+
 impl NarrativeEvent {
     #[must_use]
     pub fn notify_text(author: Objid, event: String) -> Self {
         Self {
             timestamp: SystemTime::now(),
+            seq: 0,
             author,
             event: Event::TextNotify(event),
         }
     }
 
+    #[must_use]
+    pub fn notify_rich(author: Objid, content: String, content_type: String) -> Self {
+        Self {
+            timestamp: SystemTime::now(),
+            seq: 0,
+            author,
+            event: Event::RichNotify(content, content_type),
+        }
+    }
+
     #[must_use]
     pub fn timestamp(&self) -> SystemTime {
         self.timestamp
     }
     #[must_use]
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+    /// The same event, numbered as the `seq`th published to its player.
+    #[must_use]
+    pub fn with_seq(self, seq: u64) -> Self {
+        Self { seq, ..self }
+    }
+    #[must_use]
     pub fn author(&self) -> Objid {
         self.author
     }
@@ -216,4 +326,135 @@ pub enum VerbProgramError {
     NoVerbToProgram,
     CompilationError(Vec<String>),
     DatabaseError,
+    /// The source didn't compile, with a diagnostic for each problem the compiler found.
+    Diagnostics(Vec<CompileDiagnostic>),
+}
+
+#[derive(Debug, Clone, Copy, Decode, Encode, PartialEq, Eq, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+}
+
+/// A problem the compiler found in a program's source, positioned (where the compiler knows the
+/// position) so that an editor can point at the offending text.
+#[derive(Debug, Clone, Decode, Encode, PartialEq, Eq)]
+pub struct CompileDiagnostic {
+    /// 1-based line number, if known.
+    pub line: Option<usize>,
+    /// 1-based column number, if known.
+    pub column: Option<usize>,
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+}
+
+impl std::fmt::Display for CompileDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(
+                f,
+                "{} at line {}, column {}: {}",
+                self.severity, line, column, self.message
+            ),
+            (Some(line), None) => write!(f, "{} at line {}: {}", self.severity, line, self.message),
+            _ => write!(f, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+/// A verb's compiled program, broken out for display, as produced by `disassemble()`.
+#[derive(Debug, Clone, Decode, Encode, PartialEq, Eq)]
+pub struct Disassembly {
+    /// The program's literals, as MOO literals, indexed by their offset.
+    pub literals: Vec<String>,
+    /// (label offset, position in the opcode vector, the variable it belongs to if any) for each
+    /// jump label.
+    pub jump_labels: Vec<(usize, usize, Option<String>)>,
+    /// The program's variable names, indexed by their offset.
+    pub variables: Vec<String>,
+    pub main_vector: Vec<DisassembledOp>,
+    pub fork_vectors: Vec<Vec<DisassembledOp>>,
+}
+
+/// One instruction in a `Disassembly`.
+#[derive(Debug, Clone, Decode, Encode, PartialEq, Eq)]
+pub struct DisassembledOp {
+    pub offset: usize,
+    /// The source line the instruction starts, if it starts one. Not tracked for fork vectors.
+    pub line: Option<usize>,
+    /// The opcode's mnemonic, e.g. `Imm`.
+    pub opcode: String,
+    /// The opcode's operands as the compiler renders them, e.g. `Label(0)`; empty if it has none.
+    pub operands: String,
+}
+
+impl std::fmt::Display for DisassembledOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.operands.is_empty() {
+            write!(f, "{}", self.opcode)
+        } else if self.operands.starts_with('{') {
+            write!(f, "{} {}", self.opcode, self.operands)
+        } else {
+            write!(f, "{}({})", self.opcode, self.operands)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{HostType, ListenerPoint};
+    use crate::var::Objid;
+
+    #[test]
+    fn test_listener_point_bincode_roundtrip() {
+        let point = ListenerPoint {
+            host_type: HostType::Tcp,
+            addr: "127.0.0.1:7777".parse().unwrap(),
+            handler: Objid(0),
+            print_messages: false,
+            out_of_band_prefix: Some("#$#".to_string()),
+        };
+        let bytes = bincode::encode_to_vec(&point, bincode::config::standard()).unwrap();
+        let (decoded, _): (ListenerPoint, _) =
+            bincode::decode_from_slice(&bytes, bincode::config::standard()).unwrap();
+        assert_eq!(decoded, point);
+    }
+
+    #[test]
+    fn test_custom_out_of_band_prefix() {
+        let mcp = ListenerPoint {
+            host_type: HostType::Tcp,
+            addr: "127.0.0.1:7777".parse().unwrap(),
+            handler: Objid(0),
+            print_messages: true,
+            out_of_band_prefix: Some("#$#".to_string()),
+        };
+        let custom = ListenerPoint {
+            addr: "127.0.0.1:7778".parse().unwrap(),
+            out_of_band_prefix: Some("@@oob ".to_string()),
+            ..mcp.clone()
+        };
+        let none = ListenerPoint {
+            addr: "127.0.0.1:7779".parse().unwrap(),
+            out_of_band_prefix: None,
+            ..mcp.clone()
+        };
+
+        assert!(mcp.is_out_of_band("#$#mcp version: 2.1"));
+        assert!(!mcp.is_out_of_band("@@oob ping"));
+
+        assert!(custom.is_out_of_band("@@oob ping"));
+        assert!(!custom.is_out_of_band("#$#mcp version: 2.1"));
+        assert!(!custom.is_out_of_band("say @@oob ping"));
+
+        assert!(!none.is_out_of_band("#$#mcp version: 2.1"));
+        assert!(!none.is_out_of_band("look"));
+
+        let empty = ListenerPoint {
+            out_of_band_prefix: Some(String::new()),
+            ..mcp.clone()
+        };
+        assert!(!empty.is_out_of_band("look"));
+    }
 }
@@ -171,6 +171,26 @@ impl ObjSet {
         self.iter().any(|o| o == oid)
     }
 
+    /// The members of this set which aren't in `other`, in this set's order.
+    #[must_use]
+    pub fn difference(&self, other: &Self) -> Self {
+        if self.0.is_empty() || other.0.is_empty() {
+            return self.clone();
+        }
+        let other = other.iter().collect::<HashSet<_>>();
+        self.iter().filter(|o| !other.contains(o)).collect()
+    }
+
+    /// The members of this set which are also in `other`, in this set's order.
+    #[must_use]
+    pub fn intersection(&self, other: &Self) -> Self {
+        if self.0.is_empty() || other.0.is_empty() {
+            return EMPTY_OBJSET.clone();
+        }
+        let other = other.iter().collect::<HashSet<_>>();
+        self.iter().filter(|o| other.contains(o)).collect()
+    }
+
     /// Set equality comparison, because Eq/PartialEq for this type is "same bytes", this is actual
     /// logical equality, but less efficient.
     #[must_use]
@@ -250,3 +270,62 @@ impl Default for ObjSet {
         Self::empty()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{ObjSet, ValSet};
+    use crate::var::Objid;
+
+    #[test]
+    fn test_contains() {
+        let set = ObjSet::from_items(&[Objid(1), Objid(2)]);
+        assert!(set.contains(Objid(1)));
+        assert!(set.contains(Objid(2)));
+        assert!(!set.contains(Objid(3)));
+        assert!(!ObjSet::empty().contains(Objid(1)));
+    }
+
+    #[test]
+    fn test_difference() {
+        let a = ObjSet::from_items(&[Objid(1), Objid(2), Objid(3)]);
+        let b = ObjSet::from_items(&[Objid(2), Objid(4)]);
+        assert_eq!(a.difference(&b), ObjSet::from_items(&[Objid(1), Objid(3)]));
+        assert_eq!(b.difference(&a), ObjSet::from_items(&[Objid(4)]));
+        assert_eq!(a.difference(&a), ObjSet::empty());
+
+        // Empty sets on either side.
+        assert_eq!(a.difference(&ObjSet::empty()), a);
+        assert_eq!(ObjSet::empty().difference(&a), ObjSet::empty());
+        assert_eq!(
+            ObjSet::empty().difference(&ObjSet::empty()),
+            ObjSet::empty()
+        );
+    }
+
+    #[test]
+    fn test_intersection() {
+        let a = ObjSet::from_items(&[Objid(3), Objid(1), Objid(2)]);
+        let b = ObjSet::from_items(&[Objid(2), Objid(4), Objid(3)]);
+        // Keeps the order of the left hand side.
+        assert_eq!(
+            a.intersection(&b),
+            ObjSet::from_items(&[Objid(3), Objid(2)])
+        );
+        assert_eq!(
+            b.intersection(&a),
+            ObjSet::from_items(&[Objid(2), Objid(3)])
+        );
+        assert_eq!(
+            a.intersection(&ObjSet::from_items(&[Objid(5)])),
+            ObjSet::empty()
+        );
+
+        // Empty sets on either side.
+        assert_eq!(a.intersection(&ObjSet::empty()), ObjSet::empty());
+        assert_eq!(ObjSet::empty().intersection(&a), ObjSet::empty());
+        assert_eq!(
+            ObjSet::empty().intersection(&ObjSet::empty()),
+            ObjSet::empty()
+        );
+    }
+}
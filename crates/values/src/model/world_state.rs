@@ -12,6 +12,8 @@
 // this program. If not, see <https://www.gnu.org/licenses/>.
 //
 
+use std::collections::HashMap;
+
 use uuid::Uuid;
 
 use crate::model::objects::ObjFlag;
@@ -28,6 +30,69 @@ use crate::util::BitEnum;
 use crate::var::Objid;
 use crate::var::Var;
 
+/// The property on an object's owner holding how many more objects they may create, as in
+/// LambdaMOO: at zero or below, `create()` raises `E_QUOTA`. Owners without it (or with a
+/// non-integer value in it) have no limit.
+pub const OWNERSHIP_QUOTA_PROPERTY: &str = "ownership_quota";
+
+/// The property on an object's owner holding the most bytes all their objects together may take
+/// up; adding verbs or properties which would take them past it raises `E_QUOTA`.
+pub const BYTE_QUOTA_PROPERTY: &str = "byte_quota";
+
+/// The bytes used by an object, by what they're used for.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct ObjectBytes {
+    /// Verb definitions (names, flags, argument specs...).
+    pub verbs: usize,
+    /// Property definitions and values.
+    pub props: usize,
+    /// Compiled verb programs.
+    pub programs: usize,
+    /// The object's own attributes: name, owner, flags, parent, location.
+    pub overhead: usize,
+}
+
+impl ObjectBytes {
+    pub fn total(&self) -> usize {
+        self.verbs + self.props + self.programs + self.overhead
+    }
+}
+
+/// The kinds of structural inconsistency `verify_database` looks for.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum IntegrityProblem {
+    /// The object's parent doesn't exist.
+    BadParent,
+    /// The object's location doesn't exist.
+    BadLocation,
+    /// Something which doesn't exist (any more) still has the object as its parent.
+    StaleChild,
+    /// Something which doesn't exist (any more) still has the object as its location.
+    StaleContent,
+    /// A property defined on (or inherited by) the object has no value slot on it.
+    MissingPropval,
+}
+
+impl IntegrityProblem {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IntegrityProblem::BadParent => "bad_parent",
+            IntegrityProblem::BadLocation => "bad_location",
+            IntegrityProblem::StaleChild => "stale_child",
+            IntegrityProblem::StaleContent => "stale_content",
+            IntegrityProblem::MissingPropval => "missing_propval",
+        }
+    }
+}
+
+/// One inconsistency found when verifying the database.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct IntegrityFinding {
+    pub kind: IntegrityProblem,
+    pub obj: Objid,
+    pub detail: String,
+}
+
 /// A "world state" is anything which represents the shared, mutable, state of the user's
 /// environment during verb execution. This includes the location of objects, their contents,
 /// their properties, their verbs, etc.
@@ -69,6 +134,13 @@ pub trait WorldState {
     /// Return the number of bytes used by the given object and all its attributes.
     fn object_bytes(&self, perms: Objid, obj: Objid) -> Result<usize, WorldStateError>;
 
+    /// As `object_bytes`, but broken down by what the bytes are used for.
+    fn object_bytes_detailed(
+        &self,
+        perms: Objid,
+        obj: Objid,
+    ) -> Result<ObjectBytes, WorldStateError>;
+
     /// Create a new object, assigning it a new unique object id.
     /// If owner is #-1, the object's is set to itself.
     /// Note it is the caller's responsibility to execute :initialize).
@@ -116,6 +188,16 @@ pub trait WorldState {
         pname: &str,
     ) -> Result<Var, WorldStateError>;
 
+    /// Retrieve several properties from the given object, walking its inheritance chain once for
+    /// all of them rather than once per property.
+    /// Properties which don't exist or which `perms` can't read are left out of the result.
+    fn get_properties(
+        &self,
+        perms: Objid,
+        obj: Objid,
+        pnames: &[&str],
+    ) -> Result<HashMap<String, Var>, WorldStateError>;
+
     /// Get information about a property, without walking the inheritance tree.
     /// Returns the PropDef as well as the owner of the property.
     fn get_property_info(
@@ -152,6 +234,7 @@ pub trait WorldState {
 
     /// Clear a property on the given object. That is, remove its local value, if any, and
     /// ensure that it is purely inherited.
+    /// The property's definer has nothing to inherit from, so can't be cleared.
     fn clear_property(
         &mut self,
         perms: Objid,
@@ -265,6 +348,9 @@ pub trait WorldState {
     /// Get the object that is the parent of the given object.
     fn parent_of(&self, perms: Objid, obj: Objid) -> Result<Objid, WorldStateError>;
 
+    /// Return the given object and all its ancestors, nearest first.
+    fn ancestors_of(&self, perms: Objid, obj: Objid) -> Result<ObjSet, WorldStateError>;
+
     /// Change the parent of the given object.
     /// This manages the movement of property definitions between the old and new parents.
     fn change_parent(
@@ -286,6 +372,11 @@ pub trait WorldState {
     /// Returns the (rough) total number of bytes used by database storage subsystem.
     fn db_usage(&self) -> Result<usize, WorldStateError>;
 
+    /// Scan the whole database for structural inconsistencies (dangling parents or locations,
+    /// children or contents which no longer exist, missing property values).
+    /// Wizard only.
+    fn verify_database(&self, perms: Objid) -> Result<Vec<IntegrityFinding>, WorldStateError>;
+
     /// Commit all modifications made to the state of this world since the start of its transaction.
     fn commit(&mut self) -> Result<CommitResult, WorldStateError>;
 
@@ -66,56 +66,44 @@ pub fn quote_str(s: &str) -> String {
     output
 }
 
+/// Split a line into words the way LambdaMOO does, for command parsing and the like.
+/// Words are separated by whitespace, except inside double quotes, which group everything up to
+/// the closing quote into the one word. Quotes may open or close mid-word (`foo"bar baz"` is the
+/// single word `foobar baz`), `""` is an empty word, and an unbalanced quote runs to the end of
+/// the line. A backslash takes the character after it literally, including a quote or a space; a
+/// lone trailing backslash is dropped.
 pub fn parse_into_words(input: &str) -> Vec<String> {
-    // Initialize state variables.
+    let mut words = vec![];
+    // The word being built, if we're in one. Tracked separately from its contents so that an
+    // empty quoted string still counts as a word.
+    let mut word: Option<String> = None;
     let mut in_quotes = false;
-    let mut previous_char_was_backslash = false;
-
-    // Define the fold function's logic as a closure.
-    let accumulate_words = |mut acc: Vec<String>, c| {
-        if previous_char_was_backslash {
-            // Handle escaped characters.
-            if let Some(last_word) = acc.last_mut() {
-                last_word.push(c);
-            } else {
-                acc.push(c.to_string());
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() && !in_quotes => {
+                words.extend(word.take());
             }
-            previous_char_was_backslash = false;
-        } else if c == '\\' {
-            // Mark the next character as escaped.
-            previous_char_was_backslash = true;
-        } else if c == '"' {
-            // Toggle whether we're inside quotes.
-            in_quotes = !in_quotes;
-        } else if c.is_whitespace() && !in_quotes {
-            // Add a new empty string to the accumulator if we've reached a whitespace boundary.
-            if let Some(last_word) = acc.last() {
-                if !last_word.is_empty() {
-                    acc.push(String::new());
-                }
+            '"' => {
+                in_quotes = !in_quotes;
+                word.get_or_insert_with(String::new);
             }
-        } else {
-            // Append the current character to the last word in the accumulator,
-            // or create a new word if there isn't one yet.
-            if let Some(last_word) = acc.last_mut() {
-                last_word.push(c);
-            } else {
-                acc.push(c.to_string());
+            '\\' => {
+                let word = word.get_or_insert_with(String::new);
+                if let Some(escaped) = chars.next() {
+                    word.push(escaped);
+                }
             }
+            c => word.get_or_insert_with(String::new).push(c),
         }
-        acc
-    };
-
-    // Use the fold function to accumulate the words in the input string.
-    let words = input.chars().fold(vec![], accumulate_words);
-
-    // Filter out empty strings and return the result.
-    words.into_iter().filter(|w| !w.is_empty()).collect()
+    }
+    words.extend(word);
+    words
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::util::{quote_str, verbname_cmp};
+    use crate::util::{parse_into_words, quote_str, verbname_cmp};
 
     #[test]
     fn test_string_quote() {
@@ -165,4 +153,70 @@ mod tests {
         // Regression for 'do_object' matching 'do'
         assert!(!verbname_cmp("do", "do_object"));
     }
+
+    #[test]
+    fn test_parse_into_words_whitespace() {
+        assert!(parse_into_words("").is_empty());
+        assert!(parse_into_words("   ").is_empty());
+        assert_eq!(
+            parse_into_words("  get   the\tlamp  "),
+            vec!["get", "the", "lamp"]
+        );
+    }
+
+    #[test]
+    fn test_parse_into_words_quoted_groups() {
+        assert_eq!(
+            parse_into_words(r#"put "big red ball" in box"#),
+            vec!["put", "big red ball", "in", "box"]
+        );
+        // Quotes can start and end mid-word, and are dropped either way.
+        assert_eq!(
+            parse_into_words(r#"foo"bar baz"qux next"#),
+            vec!["foobar bazqux", "next"]
+        );
+        assert_eq!(
+            parse_into_words(r#""one""two" three"#),
+            vec!["onetwo", "three"]
+        );
+    }
+
+    #[test]
+    fn test_parse_into_words_empty_quotes() {
+        assert_eq!(parse_into_words(r#"say """#), vec!["say", ""]);
+        assert_eq!(parse_into_words(r#""" "" x"#), vec!["", "", "x"]);
+        // Empty quotes inside a word don't split it.
+        assert_eq!(parse_into_words(r#"a""b"#), vec!["ab"]);
+    }
+
+    #[test]
+    fn test_parse_into_words_unbalanced_quotes() {
+        assert_eq!(
+            parse_into_words(r#"say "hello there   world"#),
+            vec!["say", "hello there   world"]
+        );
+        assert_eq!(parse_into_words(r#"look ""#), vec!["look", ""]);
+    }
+
+    #[test]
+    fn test_parse_into_words_escapes() {
+        // An escaped quote is just a quote, inside a word or inside quotes.
+        assert_eq!(parse_into_words(r#"say it\"s"#), vec!["say", "it\"s"]);
+        assert_eq!(
+            parse_into_words(r#""a \"quoted\" word" b"#),
+            vec!["a \"quoted\" word", "b"]
+        );
+        // It doesn't open a group, either.
+        assert_eq!(
+            parse_into_words(r#"\"not grouped\""#),
+            vec!["\"not", "grouped\""]
+        );
+        // Escaped spaces and backslashes.
+        assert_eq!(parse_into_words(r"a\ b c"), vec!["a b", "c"]);
+        assert_eq!(parse_into_words(r"a\\b"), vec!["a\\b"]);
+        // Any other escaped character is taken as is.
+        assert_eq!(parse_into_words(r"\n"), vec!["n"]);
+        // A trailing backslash has nothing to escape, and goes away.
+        assert_eq!(parse_into_words(r"foo\"), vec!["foo"]);
+    }
 }
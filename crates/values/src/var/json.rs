@@ -0,0 +1,180 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! Conversion between MOO values and JSON.
+//!
+//! | MOO            | JSON                                                             |
+//! |----------------|------------------------------------------------------------------|
+//! | none           | `null`                                                           |
+//! | INT            | number                                                           |
+//! | FLOAT          | number (always with a fractional part or exponent)               |
+//! | STR            | string                                                           |
+//! | LIST           | array                                                            |
+//! | OBJ            | `{"oid": 123}`                                                   |
+//! | ERR            | `{"error_code": 13, "error_name": "E_INVARG", "error_msg": "…"}` |
+//!
+//! Going the other way, JSON `true`/`false` become `1`/`0`, and only the `error_code` of an error
+//! object is needed. Any other JSON object can't be converted.
+
+use serde_json::{json, Map, Number, Value};
+
+use crate::var::{v_err, v_float, v_int, v_listv, v_none, v_objid, v_str, Error, Objid};
+use crate::var::{Var, Variant};
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum JsonError {
+    #[error("JSON number {0} is out of range for a MOO value")]
+    NumberOutOfRange(Number),
+    #[error("{0} is not a MOO error code")]
+    InvalidErrorCode(Value),
+    #[error("JSON object {0} is not an object id or an error")]
+    UntaggedObject(Value),
+}
+
+/// Render the given value as JSON, as laid out above.
+pub fn to_json(v: &Var) -> Value {
+    match v.variant() {
+        Variant::None => Value::Null,
+        Variant::Str(s) => Value::String(s.to_string()),
+        Variant::Obj(o) => json!({ "oid": o.0 }),
+        Variant::Int(i) => Value::Number(Number::from(*i)),
+        Variant::Float(f) => json!(*f),
+        Variant::Err(e) => json!({
+            "error_code": (*e) as u8,
+            "error_name": e.name(),
+            "error_msg": e.message(),
+        }),
+        Variant::List(l) => Value::Array(l.iter().map(|e| to_json(&e)).collect()),
+    }
+}
+
+/// Build a value from the given JSON, as laid out above.
+pub fn from_json(v: &Value) -> Result<Var, JsonError> {
+    match v {
+        Value::Null => Ok(v_none()),
+        Value::Bool(b) => Ok(v_int(*b as i64)),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Ok(v_int(i))
+            } else if n.is_f64() {
+                Ok(v_float(n.as_f64().unwrap()))
+            } else {
+                Err(JsonError::NumberOutOfRange(n.clone()))
+            }
+        }
+        Value::String(s) => Ok(v_str(s)),
+        Value::Array(a) => {
+            let elements: Result<Vec<_>, _> = a.iter().map(from_json).collect();
+            Ok(v_listv(elements?))
+        }
+        Value::Object(o) => from_json_object(o),
+    }
+}
+
+fn from_json_object(o: &Map<String, Value>) -> Result<Var, JsonError> {
+    if let Some(oid) = o.get("oid") {
+        let Some(oid) = oid.as_i64() else {
+            return Err(JsonError::UntaggedObject(Value::Object(o.clone())));
+        };
+        return Ok(v_objid(Objid(oid)));
+    }
+    if let Some(code) = o.get("error_code") {
+        let error = code
+            .as_u64()
+            .and_then(|c| u8::try_from(c).ok())
+            .and_then(Error::from_repr)
+            .ok_or_else(|| JsonError::InvalidErrorCode(code.clone()))?;
+        return Ok(v_err(error));
+    }
+    Err(JsonError::UntaggedObject(Value::Object(o.clone())))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::var::json::{from_json, to_json, JsonError};
+    use crate::var::Error::{E_INVARG, E_PERM};
+    use crate::var::{v_err, v_float, v_int, v_list, v_none, v_objid, v_str, Objid};
+
+    #[test]
+    fn test_scalars() {
+        assert_eq!(to_json(&v_none()), json!(null));
+        assert_eq!(to_json(&v_int(-5)), json!(-5));
+        assert_eq!(to_json(&v_float(1.5)), json!(1.5));
+        assert_eq!(to_json(&v_str("hi")), json!("hi"));
+
+        assert_eq!(from_json(&json!(null)), Ok(v_none()));
+        assert_eq!(from_json(&json!(-5)), Ok(v_int(-5)));
+        assert_eq!(from_json(&json!(1.5)), Ok(v_float(1.5)));
+        assert_eq!(from_json(&json!("hi")), Ok(v_str("hi")));
+        assert_eq!(from_json(&json!(true)), Ok(v_int(1)));
+        assert_eq!(from_json(&json!(false)), Ok(v_int(0)));
+    }
+
+    #[test]
+    fn test_tagged_types() {
+        assert_eq!(to_json(&v_objid(Objid(123))), json!({"oid": 123}));
+        assert_eq!(from_json(&json!({"oid": 123})), Ok(v_objid(Objid(123))));
+        assert_eq!(from_json(&json!({"oid": -1})), Ok(v_objid(Objid(-1))));
+
+        assert_eq!(
+            to_json(&v_err(E_INVARG)),
+            json!({"error_code": 13, "error_name": "E_INVARG", "error_msg": E_INVARG.message()})
+        );
+        assert_eq!(from_json(&json!({"error_code": 3})), Ok(v_err(E_PERM)));
+
+        assert_eq!(
+            from_json(&json!({"error_code": 99})),
+            Err(JsonError::InvalidErrorCode(json!(99)))
+        );
+        assert_eq!(
+            from_json(&json!({"oid": "abc"})),
+            Err(JsonError::UntaggedObject(json!({"oid": "abc"})))
+        );
+        assert_eq!(
+            from_json(&json!({"foo": 1})),
+            Err(JsonError::UntaggedObject(json!({"foo": 1})))
+        );
+        assert!(matches!(
+            from_json(&json!(u64::MAX)),
+            Err(JsonError::NumberOutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn test_round_trip_nested() {
+        let v = v_list(&[
+            v_int(1),
+            v_list(&[
+                v_str("two"),
+                v_list(&[v_float(3.25), v_objid(Objid(4))]),
+                v_list(&[]),
+            ]),
+            v_err(E_PERM),
+            v_none(),
+        ]);
+        let json = to_json(&v);
+        assert_eq!(
+            json,
+            json!([
+                1,
+                ["two", [3.25, {"oid": 4}], []],
+                {"error_code": 3, "error_name": "E_PERM", "error_msg": E_PERM.message()},
+                null
+            ])
+        );
+        assert_eq!(from_json(&json), Ok(v));
+    }
+}
@@ -20,7 +20,7 @@ use bincode::{Decode, Encode};
 use bytes::Bytes;
 
 #[allow(unused_imports)]
-use crate::var::list_impl_buffer::ListImplBuffer;
+use crate::var::list_impl_hybrid::ListImplHybrid;
 #[allow(unused_imports)]
 use crate::var::list_impl_vector::ListImplVector;
 
@@ -29,7 +29,7 @@ use crate::var::Var;
 use crate::{AsByteBuffer, DecodingError, EncodingError};
 
 #[cfg(feature = "list_impl_buffer")]
-type ListImpl = ListImplBuffer;
+type ListImpl = ListImplHybrid;
 
 #[cfg(not(feature = "list_impl_buffer"))]
 type ListImpl = ListImplVector;
@@ -82,6 +82,23 @@ impl List {
         Var::new(Variant::List(Self(self.0.setremove(value))))
     }
 
+    /// Remove the first found instance of the given value from the list, comparing strings
+    /// case-sensitively.
+    #[must_use]
+    pub fn setremove_case_sensitive(&mut self, value: &Var) -> Var {
+        let position = match value.variant() {
+            Variant::Str(s) => self.iter().position(|item| match item.variant() {
+                Variant::Str(s2) => s.as_str() == s2.as_str(),
+                _ => false,
+            }),
+            _ => self.iter().position(|item| item.eq(value)),
+        };
+        match position {
+            Some(index) => self.remove_at(index),
+            None => Var::new(Variant::List(self.clone())),
+        }
+    }
+
     pub fn insert(&mut self, index: isize, value: Var) -> Var {
         Var::new(Variant::List(Self(self.0.insert(index, value))))
     }
@@ -250,6 +267,29 @@ mod tests {
         );
     }
 
+    #[test]
+    pub fn setremove_case_sensitive() {
+        // Var equality ignores case, so compare the literals to see which string was removed.
+        let mut list = List::from_slice(&[v_string("Foo".into()), v_string("foo".into())]);
+        assert_eq!(
+            list.setremove_case_sensitive(&v_string("foo".into()))
+                .to_literal(),
+            r#"{"Foo"}"#
+        );
+        let mut list = List::from_slice(&[v_string("foo".into()), v_string("Foo".into())]);
+        assert_eq!(
+            list.setremove_case_sensitive(&v_string("Foo".into()))
+                .to_literal(),
+            r#"{"foo"}"#
+        );
+        let mut list = List::from_slice(&[v_string("Foo".into())]);
+        assert_eq!(
+            list.setremove_case_sensitive(&v_string("foo".into()))
+                .to_literal(),
+            r#"{"Foo"}"#
+        );
+    }
+
     #[test]
     pub fn list_display() {
         let list = List::from_slice(&[v_int(1), v_string("foo".into()), v_int(3)]);
@@ -0,0 +1,290 @@
+// Copyright (C) 2024 Ryan Daum <ryan.daum@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+//
+
+//! A list which starts out in the compact `ListImplBuffer` form, and moves over to the growable
+//! `ListImplVector` form once it's being mutated and is big enough that rebuilding the buffer on
+//! every change would hurt. Whatever form it's in, it always serializes as a buffer, so what lands
+//! in the database (or goes over the wire) is the same either way.
+
+use std::sync::OnceLock;
+
+use bincode::de::{BorrowDecoder, Decoder};
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{BorrowDecode, Decode, Encode};
+use bytes::Bytes;
+
+use crate::var::list_impl_buffer::ListImplBuffer;
+use crate::var::list_impl_vector::ListImplVector;
+use crate::var::Var;
+use crate::{AsByteBuffer, DecodingError, EncodingError};
+
+/// Below this many items, a buffer is cheap enough to rebuild that it stays a buffer when mutated.
+const GROWABLE_THRESHOLD: usize = 8;
+
+#[derive(Clone, Debug)]
+pub enum ListImplHybrid {
+    Buffer(ListImplBuffer),
+    /// The items, and the buffer form of them once it's been asked for; a list that's serialized
+    /// or measured more than once between mutations only pays for building it the first time.
+    Vector(ListImplVector, OnceLock<ListImplBuffer>),
+}
+
+impl ListImplHybrid {
+    pub fn new() -> Self {
+        Self::Buffer(ListImplBuffer::new())
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Buffer(b) => b.len(),
+            Self::Vector(v, _) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::Buffer(b) => b.is_empty(),
+            Self::Vector(v, _) => v.is_empty(),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<Var> {
+        match self {
+            Self::Buffer(b) => b.get(index),
+            Self::Vector(v, _) => v.get(index),
+        }
+    }
+
+    pub fn from_slice(vec: &[Var]) -> Self {
+        Self::Buffer(ListImplBuffer::from_slice(vec))
+    }
+
+    fn vector(items: ListImplVector) -> Self {
+        Self::Vector(items, OnceLock::new())
+    }
+
+    /// Switch to the vector form if we're about to be mutated and are past the threshold.
+    fn for_mutation(&mut self) -> &mut Self {
+        if let Self::Buffer(b) = self {
+            if b.len() >= GROWABLE_THRESHOLD {
+                *self = Self::vector(ListImplVector::from_vec(b.iter().collect()));
+            }
+        }
+        self
+    }
+
+    /// The buffer form of this list, as used for serialization.
+    fn to_buffer(&self) -> ListImplBuffer {
+        match self {
+            Self::Buffer(b) => b.clone(),
+            Self::Vector(v, buffer) => buffer
+                .get_or_init(|| ListImplBuffer::from_slice(&v[..]))
+                .clone(),
+        }
+    }
+
+    fn to_vector(&self) -> ListImplVector {
+        match self {
+            Self::Buffer(b) => ListImplVector::from_vec(b.iter().collect()),
+            Self::Vector(v, _) => v.clone(),
+        }
+    }
+
+    pub fn push(&mut self, v: Var) -> Self {
+        match self.for_mutation() {
+            Self::Buffer(b) => Self::Buffer(b.push(v)),
+            Self::Vector(l, _) => Self::vector(l.push(v)),
+        }
+    }
+
+    pub fn pop_front(&self) -> (Var, Self) {
+        match self {
+            Self::Buffer(b) => {
+                let (item, rest) = b.pop_front();
+                (item, Self::Buffer(rest))
+            }
+            Self::Vector(v, _) => {
+                let (item, rest) = v.pop_front();
+                (item, Self::vector(rest))
+            }
+        }
+    }
+
+    pub fn append(&mut self, other: Self) -> Self {
+        if self.len() + other.len() >= GROWABLE_THRESHOLD {
+            if let Self::Buffer(_) = self {
+                *self = Self::vector(self.to_vector());
+            }
+        }
+        match (self, other) {
+            (Self::Buffer(b), Self::Buffer(o)) => Self::Buffer(b.append(o)),
+            (Self::Buffer(b), o) => Self::Buffer(b.append(o.to_buffer())),
+            (Self::Vector(l, _), o) => Self::vector(l.append(o.to_vector())),
+        }
+    }
+
+    pub fn remove_at(&mut self, index: usize) -> Self {
+        match self.for_mutation() {
+            Self::Buffer(b) => Self::Buffer(b.remove_at(index)),
+            Self::Vector(l, _) => Self::vector(l.remove_at(index)),
+        }
+    }
+
+    /// Remove the first found instance of the given value from the list.
+    #[must_use]
+    pub fn setremove(&mut self, value: &Var) -> Self {
+        match self.for_mutation() {
+            Self::Buffer(b) => Self::Buffer(b.setremove(value)),
+            Self::Vector(l, _) => Self::vector(l.setremove(value)),
+        }
+    }
+
+    pub fn insert(&mut self, index: isize, value: Var) -> Self {
+        match self.for_mutation() {
+            Self::Buffer(b) => Self::Buffer(b.insert(index, value)),
+            Self::Vector(l, _) => Self::vector(l.insert(index, value)),
+        }
+    }
+
+    pub fn set(&mut self, index: usize, value: Var) -> Self {
+        match self.for_mutation() {
+            Self::Buffer(b) => Self::Buffer(b.set(index, value)),
+            Self::Vector(l, _) => Self::vector(l.set(index, value)),
+        }
+    }
+}
+
+impl AsByteBuffer for ListImplHybrid {
+    fn size_bytes(&self) -> usize {
+        match self {
+            Self::Buffer(b) => b.size_bytes(),
+            Self::Vector(..) => self.to_buffer().size_bytes(),
+        }
+    }
+
+    fn with_byte_buffer<R, F: FnMut(&[u8]) -> R>(&self, f: F) -> Result<R, EncodingError> {
+        self.to_buffer().with_byte_buffer(f)
+    }
+
+    fn make_copy_as_vec(&self) -> Result<Vec<u8>, EncodingError> {
+        self.to_buffer().make_copy_as_vec()
+    }
+
+    fn from_bytes(bytes: Bytes) -> Result<Self, DecodingError>
+    where
+        Self: Sized,
+    {
+        Ok(Self::Buffer(ListImplBuffer::from_bytes(bytes)?))
+    }
+
+    fn as_bytes(&self) -> Result<Bytes, EncodingError> {
+        self.to_buffer().as_bytes()
+    }
+}
+
+impl Encode for ListImplHybrid {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.to_buffer().encode(encoder)
+    }
+}
+
+impl Decode for ListImplHybrid {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Ok(Self::Buffer(ListImplBuffer::decode(decoder)?))
+    }
+}
+
+impl<'de> BorrowDecode<'de> for ListImplHybrid {
+    fn borrow_decode<D: BorrowDecoder<'de>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Ok(Self::Buffer(ListImplBuffer::borrow_decode(decoder)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::var::list_impl_hybrid::{ListImplHybrid, GROWABLE_THRESHOLD};
+    use crate::var::v_int;
+    use crate::AsByteBuffer;
+
+    #[test]
+    fn test_grows_into_vector() {
+        let mut l = ListImplHybrid::new();
+        for i in 0..GROWABLE_THRESHOLD {
+            l = l.push(v_int(i as i64));
+            assert!(matches!(l, ListImplHybrid::Buffer(_)));
+        }
+        l = l.push(v_int(GROWABLE_THRESHOLD as i64));
+        assert!(matches!(l, ListImplHybrid::Vector(..)));
+        assert_eq!(l.len(), GROWABLE_THRESHOLD + 1);
+        for i in 0..=GROWABLE_THRESHOLD {
+            assert_eq!(l.get(i), Some(v_int(i as i64)));
+        }
+    }
+
+    #[test]
+    fn test_serializes_as_buffer() {
+        let mut l = ListImplHybrid::new();
+        for i in 0..100 {
+            l = l.push(v_int(i));
+        }
+        assert!(matches!(l, ListImplHybrid::Vector(..)));
+        let bytes = l.as_bytes().unwrap();
+        let decoded = ListImplHybrid::from_bytes(bytes).unwrap();
+        assert!(matches!(decoded, ListImplHybrid::Buffer(_)));
+        assert_eq!(decoded.len(), 100);
+        for i in 0..100 {
+            assert_eq!(decoded.get(i), l.get(i));
+        }
+    }
+
+    #[test]
+    fn test_buffer_built_once_per_version() {
+        let mut l = ListImplHybrid::from_slice(&(0..20).map(v_int).collect::<Vec<_>>());
+        l = l.push(v_int(20));
+        let ListImplHybrid::Vector(_, buffer) = &l else {
+            panic!("expected a vector");
+        };
+        assert!(buffer.get().is_none());
+
+        // Measuring and serializing share the one buffer...
+        let size = l.size_bytes();
+        let bytes = l.as_bytes().unwrap();
+        assert_eq!(size, bytes.len());
+        let ListImplHybrid::Vector(_, buffer) = &l else {
+            panic!("expected a vector");
+        };
+        assert_eq!(buffer.get().unwrap().as_bytes().unwrap(), bytes);
+
+        // ...and a mutated list starts afresh, rather than serializing stale contents.
+        let l = l.set(0, v_int(100));
+        let ListImplHybrid::Vector(_, buffer) = &l else {
+            panic!("expected a vector");
+        };
+        assert!(buffer.get().is_none());
+        let decoded = ListImplHybrid::from_bytes(l.as_bytes().unwrap()).unwrap();
+        assert_eq!(decoded.get(0), Some(v_int(100)));
+    }
+
+    #[test]
+    fn test_mixed_append() {
+        let mut small = ListImplHybrid::from_slice(&[v_int(1), v_int(2)]);
+        let big = ListImplHybrid::from_slice(&(0..20).map(v_int).collect::<Vec<_>>());
+        let l = small.append(big);
+        assert!(matches!(l, ListImplHybrid::Vector(..)));
+        assert_eq!(l.len(), 22);
+        assert_eq!(l.get(0), Some(v_int(1)));
+        assert_eq!(l.get(21), Some(v_int(19)));
+    }
+}
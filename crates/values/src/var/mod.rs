@@ -31,17 +31,21 @@ use strum::FromRepr;
 use crate::encode::BINCODE_CONFIG;
 use crate::util::quote_str;
 pub use crate::var::error::{Error, ErrorPack};
+pub use crate::var::json::{from_json, to_json, JsonError};
 pub use crate::var::list::List;
 pub use crate::var::objid::Objid;
-pub use crate::var::string::Str;
+pub use crate::var::string::{Str, StringSemantics};
 pub use crate::var::variant::Variant;
 use crate::{AsByteBuffer, DecodingError, EncodingError};
 
 mod error;
+mod json;
 mod list;
 #[allow(dead_code)]
 mod list_impl_buffer;
 #[allow(dead_code)]
+mod list_impl_hybrid;
+#[allow(dead_code)]
 mod list_impl_vector;
 mod objid;
 mod string;
@@ -512,6 +516,7 @@ mod tests {
 
     use crate::var::error::Error;
     use crate::var::error::Error::{E_RANGE, E_TYPE};
+    use crate::var::string::StringSemantics::CodePoints;
     use crate::var::{v_empty_list, v_err, v_float, v_int, v_list, v_obj, v_str};
 
     #[test]
@@ -576,12 +581,21 @@ mod tests {
 
     #[test]
     fn test_index() {
-        assert_eq!(v_list(&[v_int(1), v_int(2)]).index(0), Ok(v_int(1)));
-        assert_eq!(v_list(&[v_int(1), v_int(2)]).index(1), Ok(v_int(2)));
-        assert_eq!(v_list(&[v_int(1), v_int(2)]).index(2), Ok(v_err(E_RANGE)));
-        assert_eq!(v_str("ab").index(0), Ok(v_str("a")));
-        assert_eq!(v_str("ab").index(1), Ok(v_str("b")));
-        assert_eq!(v_str("ab").index(2), Ok(v_err(E_RANGE)));
+        assert_eq!(
+            v_list(&[v_int(1), v_int(2)]).index(0, CodePoints),
+            Ok(v_int(1))
+        );
+        assert_eq!(
+            v_list(&[v_int(1), v_int(2)]).index(1, CodePoints),
+            Ok(v_int(2))
+        );
+        assert_eq!(
+            v_list(&[v_int(1), v_int(2)]).index(2, CodePoints),
+            Ok(v_err(E_RANGE))
+        );
+        assert_eq!(v_str("ab").index(0, CodePoints), Ok(v_str("a")));
+        assert_eq!(v_str("ab").index(1, CodePoints), Ok(v_str("b")));
+        assert_eq!(v_str("ab").index(2, CodePoints), Ok(v_err(E_RANGE)));
     }
 
     #[test]
@@ -836,22 +850,22 @@ mod tests {
         // {1,2,3,4}[1..2] = {"a", "b", "c"} => {1, "a", "b", "c", 4}
         let value = v_list(&[v_str("a"), v_str("b"), v_str("c")]);
         let expected = v_list(&[v_int(1), v_str("a"), v_str("b"), v_str("c"), v_int(4)]);
-        assert_eq!(base.rangeset(value, 2, 3).unwrap(), expected);
+        assert_eq!(base.rangeset(value, 2, 3, CodePoints).unwrap(), expected);
 
         // {1,2,3,4}[1..2] = {"a"} => {1, "a", 4}
         let value = v_list(&[v_str("a")]);
         let expected = v_list(&[v_int(1), v_str("a"), v_int(4)]);
-        assert_eq!(base.rangeset(value, 2, 3).unwrap(), expected);
+        assert_eq!(base.rangeset(value, 2, 3, CodePoints).unwrap(), expected);
 
         // {1,2,3,4}[1..2] = {} => {1,4}
         let value = v_empty_list();
         let expected = v_list(&[v_int(1), v_int(4)]);
-        assert_eq!(base.rangeset(value, 2, 3).unwrap(), expected);
+        assert_eq!(base.rangeset(value, 2, 3, CodePoints).unwrap(), expected);
 
         // {1,2,3,4}[1..2] = {"a", "b"} => {1, "a", "b", 4}
         let value = v_list(&[v_str("a"), v_str("b")]);
         let expected = v_list(&[v_int(1), v_str("a"), v_str("b"), v_int(4)]);
-        assert_eq!(base.rangeset(value, 2, 3).unwrap(), expected);
+        assert_eq!(base.rangeset(value, 2, 3, CodePoints).unwrap(), expected);
     }
 
     #[test]
@@ -860,28 +874,28 @@ mod tests {
         let base = v_str("12345");
         let value = v_str("abc");
         let expected = v_str("1abc45");
-        let result = base.rangeset(value, 2, 3);
+        let result = base.rangeset(value, 2, 3, CodePoints);
         assert_eq!(result, Ok(expected));
 
         // Test interior replacement
         let base = v_str("12345");
         let value = v_str("ab");
         let expected = v_str("1ab45");
-        let result = base.rangeset(value, 2, 3);
+        let result = base.rangeset(value, 2, 3, CodePoints);
         assert_eq!(result, Ok(expected));
 
         // Test interior deletion
         let base = v_str("12345");
         let value = v_str("");
         let expected = v_str("145");
-        let result = base.rangeset(value, 2, 3);
+        let result = base.rangeset(value, 2, 3, CodePoints);
         assert_eq!(result, Ok(expected));
 
         // Test interior subtraction
         let base = v_str("12345");
         let value = v_str("z");
         let expected = v_str("1z45");
-        let result = base.rangeset(value, 2, 3);
+        let result = base.rangeset(value, 2, 3, CodePoints);
         assert_eq!(result, Ok(expected));
     }
 
@@ -890,23 +904,23 @@ mod tests {
         // test on integer list
         let int_list = v_list(&[1.into(), 2.into(), 3.into(), 4.into(), 5.into()]);
         assert_eq!(
-            int_list.range(2, 4)?,
+            int_list.range(2, 4, CodePoints)?,
             v_list(&[2.into(), 3.into(), 4.into()])
         );
 
         // test on string
         let string = v_str("hello world");
-        assert_eq!(string.range(2, 7)?, v_str("ello w"));
+        assert_eq!(string.range(2, 7, CodePoints)?, v_str("ello w"));
 
         // range with upper higher than lower, moo returns empty list for this (!)
         let empty_list = v_empty_list();
-        assert_eq!(empty_list.range(1, 0), Ok(v_empty_list()));
+        assert_eq!(empty_list.range(1, 0, CodePoints), Ok(v_empty_list()));
         // test on out of range
         let int_list = v_list(&[1.into(), 2.into(), 3.into()]);
-        assert_eq!(int_list.range(2, 4), Ok(v_err(E_RANGE)));
+        assert_eq!(int_list.range(2, 4, CodePoints), Ok(v_err(E_RANGE)));
         // test on type mismatch
         let var_int = v_int(10);
-        assert_eq!(var_int.range(1, 5), Ok(v_err(E_TYPE)));
+        assert_eq!(var_int.range(1, 5, CodePoints), Ok(v_err(E_TYPE)));
 
         Ok(())
     }
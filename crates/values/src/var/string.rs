@@ -29,6 +29,30 @@ use crate::var::error::Error;
 use crate::var::{v_err, v_str, v_string, Var};
 use crate::{AsByteBuffer, DecodingError, EncodingError};
 
+/// How string lengths, indexes and ranges are counted. MOO code sees Unicode code points by
+/// default: `length("café")` is 4, and `"café"[4]` is `"é"`. Legacy cores which assumed byte
+/// offsets can be run with `Bytes`, in which case offsets which would split a multibyte character
+/// are out of range. Which applies is server configuration, passed in wherever strings are
+/// measured or indexed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StringSemantics {
+    #[default]
+    CodePoints,
+    Bytes,
+}
+
+impl StringSemantics {
+    /// The (0-based) string offset MOO code sees for a byte offset into `s`, which must be on a
+    /// character boundary.
+    #[must_use]
+    pub fn offset_of(self, s: &str, byte_offset: usize) -> usize {
+        match self {
+            StringSemantics::CodePoints => s[..byte_offset].chars().count(),
+            StringSemantics::Bytes => byte_offset,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Str(Bytes);
 
@@ -40,29 +64,56 @@ impl Str {
         Self(sr)
     }
 
-    pub fn get(&self, offset: usize) -> Option<Var> {
-        let s = from_utf8(&self.0).unwrap();
-        let r = s.get(offset..offset + 1);
-        r.map(v_str)
+    /// Turn a range of string offsets (counted as `semantics` says) into the byte range it
+    /// covers, if it's within the string and (for bytes) on character boundaries.
+    pub fn byte_range(
+        &self,
+        semantics: StringSemantics,
+        range: Range<usize>,
+    ) -> Option<Range<usize>> {
+        let s = self.as_str();
+        if range.start > range.end {
+            return None;
+        }
+        if semantics == StringSemantics::Bytes {
+            return (range.end <= s.len()
+                && s.is_char_boundary(range.start)
+                && s.is_char_boundary(range.end))
+            .then_some(range);
+        }
+        let mut boundaries = s
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(s.len()));
+        let start = boundaries.nth(range.start)?;
+        let end = if range.end == range.start {
+            start
+        } else {
+            boundaries.nth(range.end - range.start - 1)?
+        };
+        Some(start..end)
+    }
+
+    pub fn get(&self, semantics: StringSemantics, offset: usize) -> Option<Var> {
+        self.get_range(semantics, offset..offset + 1)
     }
 
     #[must_use]
-    pub fn set(&self, offset: usize, r: &Self) -> Var {
-        if r.len() != 1 {
+    pub fn set(&self, semantics: StringSemantics, offset: usize, r: &Self) -> Var {
+        if r.len_in(semantics) != 1 {
             return v_err(Error::E_RANGE);
         }
-        if offset >= self.0.len() {
+        let Some(range) = self.byte_range(semantics, offset..offset + 1) else {
             return v_err(Error::E_RANGE);
-        }
-        let mut s = from_utf8(&self.0).unwrap().to_string();
-        s.replace_range(offset..=offset, r.as_str());
+        };
+        let mut s = self.as_str().to_string();
+        s.replace_range(range, r.as_str());
         v_string(s)
     }
 
-    pub fn get_range(&self, range: Range<usize>) -> Option<Var> {
-        let s = from_utf8(&self.0).unwrap();
-        let r = s.get(range);
-        r.map(v_str)
+    pub fn get_range(&self, semantics: StringSemantics, range: Range<usize>) -> Option<Var> {
+        let range = self.byte_range(semantics, range)?;
+        Some(v_str(&self.as_str()[range]))
     }
 
     #[must_use]
@@ -84,9 +135,20 @@ impl Str {
         v_string(format!("{}{}", from_utf8(&self.0).unwrap(), other))
     }
 
+    /// The length in code points.
     #[must_use]
     pub fn len(&self) -> usize {
-        from_utf8(&self.0).unwrap().len()
+        self.len_in(StringSemantics::CodePoints)
+    }
+
+    /// The length, counted as `semantics` says.
+    #[must_use]
+    pub fn len_in(&self, semantics: StringSemantics) -> usize {
+        let s = from_utf8(&self.0).unwrap();
+        match semantics {
+            StringSemantics::CodePoints => s.chars().count(),
+            StringSemantics::Bytes => s.len(),
+        }
     }
 
     #[must_use]
@@ -100,9 +162,11 @@ impl Str {
     }
 
     #[must_use]
-    pub fn substring(&self, range: Range<usize>) -> Self {
-        let s = from_utf8(&self.0).unwrap();
-        let s = s.get(range).unwrap_or("");
+    pub fn substring(&self, semantics: StringSemantics, range: Range<usize>) -> Self {
+        let s = match self.byte_range(semantics, range) {
+            Some(range) => &self.as_str()[range],
+            None => "",
+        };
         Self::from_string(s.to_string())
     }
 }
@@ -199,3 +263,42 @@ impl AsByteBuffer for Str {
         Ok(self.0.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::var::string::{Str, StringSemantics};
+    use crate::var::{v_str, Variant};
+
+    #[test]
+    fn test_code_point_and_byte_semantics() {
+        let code_points = StringSemantics::CodePoints;
+        let s = Str::from_string("café au lait".to_string());
+        assert_eq!(s.len(), 12);
+        assert_eq!(s.len_in(code_points), 12);
+        assert_eq!(s.get(code_points, 3), Some(v_str("é")));
+        assert_eq!(s.get_range(code_points, 2..5), Some(v_str("fé ")));
+        assert_eq!(s.substring(code_points, 0..4).as_str(), "café");
+        assert_eq!(s.get(code_points, 12), None);
+        let Variant::Str(set) = s
+            .set(code_points, 3, &Str::from_string("e".to_string()))
+            .variant()
+            .clone()
+        else {
+            panic!("expected a string");
+        };
+        assert_eq!(set.as_str(), "cafe au lait");
+
+        let bytes = StringSemantics::Bytes;
+        assert_eq!(s.len_in(bytes), 13);
+        assert_eq!(s.get_range(bytes, 0..3), Some(v_str("caf")));
+        assert_eq!(s.get_range(bytes, 3..5), Some(v_str("é")));
+        // Offsets which split the multibyte character are out of range.
+        assert_eq!(s.get(bytes, 3), None);
+        assert_eq!(s.get_range(bytes, 1..4), None);
+        assert_eq!(s.get(bytes, 5), Some(v_str(" ")));
+
+        // " au" starts after "café", which is 4 code points but 5 bytes.
+        assert_eq!(code_points.offset_of(s.as_str(), 5), 4);
+        assert_eq!(bytes.offset_of(s.as_str(), 5), 5);
+    }
+}
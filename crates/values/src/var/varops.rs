@@ -19,6 +19,7 @@ use paste::paste;
 
 use crate::var::error::Error;
 use crate::var::error::Error::{E_INVARG, E_RANGE, E_TYPE};
+use crate::var::string::StringSemantics;
 use crate::var::variant::Variant;
 use crate::var::{v_empty_list, v_empty_str, v_listv, Var};
 use crate::var::{v_err, v_float, v_int};
@@ -51,7 +52,14 @@ impl Var {
         }
     }
 
-    pub fn index_set(&mut self, i: usize, value: Self) -> Result<Self, Error> {
+    /// Strings here, and in the other indexing and range operations below, are counted as
+    /// `strings` says.
+    pub fn index_set(
+        &mut self,
+        i: usize,
+        value: Self,
+        strings: StringSemantics,
+    ) -> Result<Self, Error> {
         match self.variant_mut() {
             Variant::List(l) => {
                 if !i < l.len() {
@@ -61,7 +69,7 @@ impl Var {
                 Ok(l.set(i, value))
             }
             Variant::Str(s) => {
-                if !i < s.len() {
+                if !i < s.len_in(strings) {
                     return Err(E_RANGE);
                 }
 
@@ -69,11 +77,11 @@ impl Var {
                     return Err(E_INVARG);
                 };
 
-                if value.len() != 1 {
+                if value.len_in(strings) != 1 {
                     return Err(E_INVARG);
                 }
 
-                Ok(s.set(i, value))
+                Ok(s.set(strings, i, value))
             }
             _ => Err(E_TYPE),
         }
@@ -144,21 +152,21 @@ impl Var {
         }
     }
 
-    pub fn len(&self) -> Result<Self, Error> {
+    pub fn len(&self, strings: StringSemantics) -> Result<Self, Error> {
         match self.variant() {
-            Variant::Str(s) => Ok(v_int(s.len() as i64)),
+            Variant::Str(s) => Ok(v_int(s.len_in(strings) as i64)),
             Variant::List(l) => Ok(v_int(l.len() as i64)),
             _ => Ok(v_err(E_TYPE)),
         }
     }
 
-    pub fn index(&self, idx: usize) -> Result<Self, Error> {
+    pub fn index(&self, idx: usize, strings: StringSemantics) -> Result<Self, Error> {
         match self.variant() {
             Variant::List(l) => match l.get(idx) {
                 None => Ok(v_err(E_RANGE)),
                 Some(v) => Ok(v.clone()),
             },
-            Variant::Str(s) => match s.get(idx) {
+            Variant::Str(s) => match s.get(strings, idx) {
                 None => Ok(v_err(E_RANGE)),
                 Some(v) => Ok(v),
             },
@@ -166,10 +174,10 @@ impl Var {
         }
     }
 
-    pub fn range(&self, from: i64, to: i64) -> Result<Self, Error> {
+    pub fn range(&self, from: i64, to: i64, strings: StringSemantics) -> Result<Self, Error> {
         match self.variant() {
             Variant::Str(s) => {
-                let len = s.len() as i64;
+                let len = s.len_in(strings) as i64;
                 if to < from {
                     return Ok(v_empty_str());
                 }
@@ -177,7 +185,11 @@ impl Var {
                     return Ok(v_err(E_RANGE));
                 }
                 let (from, to) = (from as usize, to as usize);
-                Ok(s.get_range(from - 1..to).unwrap())
+                // Under byte semantics, a range which splits a character is out of range too.
+                match s.get_range(strings, from - 1..to) {
+                    Some(v) => Ok(v),
+                    None => Ok(v_err(E_RANGE)),
+                }
             }
             Variant::List(l) => {
                 let len = l.len() as i64;
@@ -198,11 +210,18 @@ impl Var {
         }
     }
 
-    pub fn rangeset(&self, value: Self, from: i64, to: i64) -> Result<Self, Error> {
+    pub fn rangeset(
+        &self,
+        value: Self,
+        from: i64,
+        to: i64,
+        strings: StringSemantics,
+    ) -> Result<Self, Error> {
         let (base_len, val_len) = match (self.variant(), value.variant()) {
-            (Variant::Str(base_str), Variant::Str(val_str)) => {
-                (base_str.len() as i64, val_str.len() as i64)
-            }
+            (Variant::Str(base_str), Variant::Str(val_str)) => (
+                base_str.len_in(strings) as i64,
+                val_str.len_in(strings) as i64,
+            ),
             (Variant::List(base_list), Variant::List(val_list)) => {
                 (base_list.len() as i64, val_list.len() as i64)
             }
@@ -226,12 +245,14 @@ impl Var {
         let (from, to) = (from as usize, to as usize);
         let ans = match (self.variant(), value.variant()) {
             (Variant::Str(base_str), Variant::Str(_value_str)) => {
-                let ans = base_str.get_range(0..from - 1).unwrap_or_else(v_empty_str);
+                let ans = base_str
+                    .get_range(strings, 0..from - 1)
+                    .unwrap_or_else(v_empty_str);
                 let ans = ans.add(&value)?;
 
                 ans.add(
                     &base_str
-                        .get_range(to..base_str.len())
+                        .get_range(strings, to..base_len as usize)
                         .unwrap_or_else(v_empty_str),
                 )?
             }
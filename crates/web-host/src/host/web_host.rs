@@ -12,7 +12,6 @@
 // this program. If not, see <https://www.gnu.org/licenses/>.
 //
 
-use crate::host::var_as_json;
 use crate::host::ws_connection::WebSocketConnection;
 use axum::body::{Body, Bytes};
 use axum::extract::{ConnectInfo, Path, State, WebSocketUpgrade};
@@ -21,14 +20,18 @@ use axum::response::{IntoResponse, Response};
 use axum::{Form, Json};
 use eyre::eyre;
 
-use moor_values::var::Objid;
+use moor_values::var::{to_json, Objid};
+use moor_values::SYSTEM_OBJECT;
+use rpc_async_client::pubsub_client::broadcast_recv;
 use rpc_async_client::rpc_client::RpcSendClient;
 use rpc_common::AuthToken;
 use rpc_common::RpcRequest::{Attach, ConnectionEstablish};
-use rpc_common::{ClientToken, RpcRequestError};
+use rpc_common::{BroadcastEvent, ClientToken, HostType, ListenerPoint, RpcRequestError};
 use rpc_common::{ConnectType, RpcRequest, RpcResponse, RpcResult, BROADCAST_TOPIC};
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::time::UNIX_EPOCH;
 use tmq::{request, subscribe};
 use tracing::warn;
 use tracing::{debug, error, info};
@@ -45,6 +48,8 @@ pub struct WebHost {
     zmq_context: tmq::Context,
     rpc_addr: String,
     pubsub_addr: String,
+    /// Whether to send connections the standard `** Connected **`-style lines.
+    print_messages: bool,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -58,17 +63,85 @@ pub enum WsHostError {
 }
 
 impl WebHost {
-    pub fn new(rpc_addr: String, narrative_addr: String) -> Self {
+    pub fn new(rpc_addr: String, narrative_addr: String, print_messages: bool) -> Self {
         let tmq_context = tmq::Context::new();
         Self {
             zmq_context: tmq_context,
             rpc_addr,
             pubsub_addr: narrative_addr,
+            print_messages,
         }
     }
 }
 
 impl WebHost {
+    /// Tell the RPC server where we're accepting connections, for `listeners()`, and tell it again
+    /// on every ping, since it forgets hosts it stops hearing from (and everything, if it
+    /// restarts). Runs until the broadcast channel fails. Failing to register is logged, but not
+    /// fatal.
+    pub async fn register_host(&self, addr: SocketAddr) {
+        let listener_point = ListenerPoint {
+            host_type: HostType::WebSocket,
+            addr,
+            handler: SYSTEM_OBJECT,
+            print_messages: self.print_messages,
+            // Websocket clients have no out-of-band channel.
+            out_of_band_prefix: None,
+        };
+        let host_id = Uuid::new_v4();
+        let mut broadcast_sub = match subscribe(&self.zmq_context)
+            .connect(self.pubsub_addr.as_str())
+            .and_then(|sub| sub.subscribe(BROADCAST_TOPIC))
+        {
+            Ok(sub) => sub,
+            Err(e) => {
+                warn!(error = ?e, "Unable to subscribe to broadcasts for host registration");
+                return;
+            }
+        };
+        self.send_registration(host_id, &listener_point).await;
+        loop {
+            match broadcast_recv(&mut broadcast_sub).await {
+                Ok(BroadcastEvent::PingPong(_)) => {
+                    self.send_registration(host_id, &listener_point).await;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!(error = ?e, "Lost broadcast channel; no longer registering host");
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn send_registration(&self, host_id: Uuid, listener_point: &ListenerPoint) {
+        let rcp_request_sock = match request(&self.zmq_context)
+            .set_rcvtimeo(100)
+            .set_sndtimeo(100)
+            .connect(self.rpc_addr.as_str())
+        {
+            Ok(sock) => sock,
+            Err(e) => {
+                warn!(error = ?e, "Unable to contact RPC server to register host");
+                return;
+            }
+        };
+        match RpcSendClient::new(rcp_request_sock)
+            .make_rpc_call(
+                host_id,
+                RpcRequest::RegisterHost(vec![listener_point.clone()]),
+            )
+            .await
+        {
+            Ok(RpcResult::Success(RpcResponse::HostRegistered)) => {
+                debug!(?host_id, "Registered host with RPC server");
+            }
+            response => {
+                warn!(?response, "Unable to register host with RPC server");
+            }
+        }
+    }
+
     /// Contact the RPC server to validate an auth token, and return the object ID of the player
     /// and the client token and rpc client to use for the connection.
     pub async fn attach_authenticated(
@@ -167,6 +240,7 @@ impl WebHost {
             client_token,
             auth_token,
             rpc_client,
+            print_messages: self.print_messages,
         })
     }
 
@@ -275,8 +349,8 @@ async fn auth_handler(
         )
         .await
         .expect("Unable to send login request to RPC server");
-    let RpcResult::Success(RpcResponse::LoginResult(Some((auth_token, _connect_type, player)))) =
-        response
+    let (RpcResult::Success(RpcResponse::LoginSucceeded(auth_token, _, player, _))
+    | RpcResult::Success(RpcResponse::LoginResult(Some((auth_token, _, player))))) = response
     else {
         error!(?response, "Login failed");
 
@@ -336,7 +410,7 @@ pub async fn welcome_message_handler(
     {
         Ok(rpc_response) => match rpc_response {
             RpcResult::Success(RpcResponse::SysPropValue(Some(value))) => {
-                Json(var_as_json(&value)).into_response()
+                Json(to_json(&value)).into_response()
             }
             RpcResult::Success(RpcResponse::SysPropValue(None)) => {
                 StatusCode::NOT_FOUND.into_response()
@@ -368,6 +442,78 @@ pub async fn welcome_message_handler(
     response
 }
 
+#[derive(Serialize)]
+struct ServerStatus {
+    version: String,
+    /// Component name -> version.
+    components: HashMap<String, String>,
+    /// Seconds since the Unix epoch.
+    start_time: u64,
+    uptime_seconds: u64,
+    connected_players: usize,
+    connections: usize,
+    hosts: usize,
+    /// Events dropped because a host wasn't keeping up.
+    events_dropped: u64,
+}
+
+/// Stand-alone HTTP GET handler for the server's version, uptime, and connection counts.
+pub async fn status_handler(
+    State(host): State<WebHost>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> Response {
+    let (client_id, mut rpc_client, client_token) =
+        match host.establish_client_connection(addr).await {
+            Ok((client_id, rpc_client, client_token)) => (client_id, rpc_client, client_token),
+            Err(WsHostError::AuthenticationFailed) => return StatusCode::FORBIDDEN.into_response(),
+            Err(e) => {
+                error!("Unable to establish connection: {}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+
+    let response = match rpc_client
+        .make_rpc_call(client_id, RpcRequest::ServerInfo(client_token.clone()))
+        .await
+    {
+        Ok(RpcResult::Success(RpcResponse::ServerInfo(info))) => Json(ServerStatus {
+            version: info.version,
+            components: info.components.into_iter().collect(),
+            start_time: info
+                .start_time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            uptime_seconds: info.start_time.elapsed().unwrap_or_default().as_secs(),
+            connected_players: info.connected_players,
+            connections: info.connections,
+            hosts: info.hosts,
+            events_dropped: info.events_dropped,
+        })
+        .into_response(),
+        Ok(RpcResult::Success(r)) => {
+            error!("Unexpected response from RPC server: {:?}", r);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Ok(RpcResult::Failure(f)) => {
+            error!("RPC failure in server status retrieval: {:?}", f);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(e) => {
+            error!("RPC failure in server status retrieval: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    };
+
+    // We're done with this RPC connection, so we detach it.
+    let _ = rpc_client
+        .make_rpc_call(client_id, RpcRequest::Detach(client_token.clone()))
+        .await
+        .expect("Unable to send detach to RPC server");
+
+    response
+}
+
 /// Evaluate a MOO expression and return the result.
 pub async fn eval_handler(
     State(host): State<WebHost>,
@@ -421,7 +567,7 @@ pub async fn eval_handler(
         Ok(rpc_response) => match rpc_response {
             RpcResult::Success(RpcResponse::EvalResult(value)) => {
                 debug!("Eval result: {:?}", value);
-                Json(var_as_json(&value)).into_response()
+                Json(to_json(&value)).into_response()
             }
             RpcResult::Success(r) => {
                 error!("Unexpected response from RPC server: {:?}", r);
@@ -430,6 +576,9 @@ pub async fn eval_handler(
             RpcResult::Failure(RpcRequestError::PermissionDenied) => {
                 StatusCode::INTERNAL_SERVER_ERROR.into_response()
             }
+            RpcResult::Failure(RpcRequestError::TooManyRequests) => {
+                StatusCode::TOO_MANY_REQUESTS.into_response()
+            }
             RpcResult::Failure(f) => {
                 error!("RPC failure in welcome message retrieval: {:?}", f);
                 StatusCode::INTERNAL_SERVER_ERROR.into_response()
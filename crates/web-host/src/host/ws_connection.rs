@@ -15,7 +15,7 @@
 use axum::extract::ws::{Message, WebSocket};
 use futures_util::stream::SplitSink;
 use futures_util::{SinkExt, StreamExt};
-use moor_values::model::CommandError;
+use moor_values::model::{CommandError, Event, NarrativeEvent};
 use moor_values::var::Objid;
 use rpc_async_client::pubsub_client::broadcast_recv;
 use rpc_async_client::pubsub_client::narrative_recv;
@@ -24,11 +24,13 @@ use rpc_common::BroadcastEvent;
 use rpc_common::ConnectionEvent;
 use rpc_common::{
     AuthToken, ClientToken, ConnectType, RpcRequest, RpcRequestError, RpcResponse, RpcResult,
+    TOKEN_REFRESH_INTERVAL,
 };
 use std::net::SocketAddr;
 use std::time::SystemTime;
 use tmq::subscribe::Subscribe;
 use tokio::select;
+use tokio::time::Instant;
 use tracing::{debug, error, info, trace};
 use uuid::Uuid;
 pub struct WebSocketConnection {
@@ -40,6 +42,7 @@ pub struct WebSocketConnection {
     pub(crate) client_token: ClientToken,
     pub(crate) auth_token: AuthToken,
     pub(crate) rpc_client: RpcSendClient,
+    pub(crate) print_messages: bool,
 }
 
 /// The JSON output of a narrative event.
@@ -50,7 +53,34 @@ pub struct NarrativeOutput {
     system_message: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     message: Option<String>,
+    /// The content type of the message, if it's something other than plain text.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    /// A presence signal (`typing`, `idle` or `active`) about `origin_player`, instead of a message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence: Option<String>,
     server_time: SystemTime,
+    /// The event's place among all those sent to this player, for putting them in order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seq: Option<u64>,
+}
+
+impl NarrativeOutput {
+    fn narrative(author: Objid, event: &NarrativeEvent) -> Self {
+        let (message, content_type) = match event.event() {
+            Event::TextNotify(msg) => (msg, None),
+            Event::RichNotify(msg, content_type) => (msg, Some(content_type)),
+        };
+        Self {
+            origin_player: author.0,
+            system_message: None,
+            message: Some(message),
+            content_type,
+            presence: None,
+            server_time: event.timestamp(),
+            seq: Some(event.seq()),
+        }
+    }
 }
 
 impl WebSocketConnection {
@@ -58,27 +88,43 @@ impl WebSocketConnection {
         info!("New connection from {}, {}", self.peer_addr, self.player);
         let (mut ws_sender, mut ws_receiver) = stream.split();
 
-        let connect_message = match connect_type {
-            ConnectType::Connected => "** Connected **",
-            ConnectType::Reconnected => "** Reconnected **",
-            ConnectType::Created => "** Created **",
-        };
-        Self::emit_event(
-            &mut ws_sender,
-            NarrativeOutput {
-                origin_player: self.player.0,
-                system_message: Some(connect_message.to_string()),
-                message: None,
-                server_time: SystemTime::now(),
-            },
-        )
-        .await;
+        if self.print_messages {
+            let connect_message = match connect_type {
+                ConnectType::Connected => "** Connected **",
+                ConnectType::Reconnected => "** Reconnected **",
+                ConnectType::Created => "** Created **",
+                ConnectType::Redirected => "** Redirected **",
+            };
+            Self::emit_event(
+                &mut ws_sender,
+                NarrativeOutput {
+                    origin_player: self.player.0,
+                    system_message: Some(connect_message.to_string()),
+                    message: None,
+                    content_type: None,
+                    presence: None,
+                    server_time: SystemTime::now(),
+                    seq: None,
+                },
+            )
+            .await;
+        }
 
         debug!(client_id = ?self.client_id, "Entering command dispatch loop");
 
         let mut expecting_input = None;
+        let mut refresh = tokio::time::interval_at(
+            Instant::now() + TOKEN_REFRESH_INTERVAL,
+            TOKEN_REFRESH_INTERVAL,
+        );
         loop {
             select! {
+                _ = refresh.tick() => {
+                    if !self.refresh_tokens().await {
+                        ws_sender.close().await.expect("Unable to close connection");
+                        return;
+                    }
+                }
                 line = ws_receiver.next() => {
                     let Some(Ok(line)) = line else {
                         info!("Connection closed");
@@ -93,40 +139,64 @@ impl WebSocketConnection {
                             let _ = self.rpc_client.make_rpc_call(self.client_id,
                                 RpcRequest::Pong(self.client_token.clone(), SystemTime::now())).await.expect("Unable to send pong to RPC server");
                         }
+                        // Outbound connections are the telnet host's business.
+                        BroadcastEvent::OpenConnection { .. } => {}
                     }
                 }
                 Ok(event) = narrative_recv(self.client_id, &mut self.narrative_sub) => {
                     trace!(?event, "narrative_event");
                     match event {
-                        ConnectionEvent::SystemMessage(author, msg) => {
+                        ConnectionEvent::SystemMessage(author, msg, content_type) => {
                             Self::emit_event(&mut ws_sender, NarrativeOutput {
                                 origin_player: author.0,
                                 system_message: Some(msg),
                                 message: None,
+                                content_type,
+                                presence: None,
                                 server_time: SystemTime::now(),
+                                seq: None,
                             }).await;
                         }
                         ConnectionEvent::Narrative(author, event) => {
-                            let msg = event.event();
-                            Self::emit_event(&mut ws_sender, NarrativeOutput {
-                                origin_player: author.0,
-                                system_message: None,
-                                message: Some(match msg {
-                                    moor_values::model::Event::TextNotify(msg) => msg,
-                                }),
-                                server_time: event.timestamp(),
-                            }).await;
+                            Self::emit_event(&mut ws_sender, NarrativeOutput::narrative(author, &event)).await;
                         }
                         ConnectionEvent::RequestInput(request_id) => {
                             expecting_input = Some(request_id);
                         }
-                        ConnectionEvent::Disconnect() => {
+                        ConnectionEvent::EventsLost(count) => {
                             Self::emit_event(&mut ws_sender, NarrativeOutput {
                                 origin_player: self.player.0,
-                                system_message: Some("** Disconnected **".to_string()),
+                                system_message: Some(format!("** {} messages of output were lost **", count)),
+                                message: None,
+                                content_type: None,
+                                presence: None,
+                                server_time: SystemTime::now(),
+                                seq: None,
+                            }).await;
+                        }
+                        ConnectionEvent::Presence(who, kind) => {
+                            Self::emit_event(&mut ws_sender, NarrativeOutput {
+                                origin_player: who.0,
+                                system_message: None,
                                 message: None,
+                                content_type: None,
+                                presence: Some(kind.to_string()),
                                 server_time: SystemTime::now(),
+                                seq: None,
                             }).await;
+                        }
+                        ConnectionEvent::Disconnect() => {
+                            if self.print_messages {
+                                Self::emit_event(&mut ws_sender, NarrativeOutput {
+                                    origin_player: self.player.0,
+                                    system_message: Some("** Disconnected **".to_string()),
+                                    message: None,
+                                    content_type: None,
+                                    presence: None,
+                                    server_time: SystemTime::now(),
+                                    seq: None,
+                                }).await;
+                            }
                             ws_sender.close().await.expect("Unable to close connection");
                             return ;
                         }
@@ -136,6 +206,32 @@ impl WebSocketConnection {
         }
     }
 
+    /// Trade our tokens in for fresh ones, so they don't expire under a long-lived connection.
+    /// False if the daemon wouldn't, in which case this session is over.
+    async fn refresh_tokens(&mut self) -> bool {
+        let response = self
+            .rpc_client
+            .make_rpc_call(
+                self.client_id,
+                RpcRequest::RefreshTokens(self.client_token.clone(), Some(self.auth_token.clone())),
+            )
+            .await
+            .expect("Unable to send token refresh to RPC server");
+        match response {
+            RpcResult::Success(RpcResponse::TokensRefreshed(client_token, auth_token)) => {
+                self.client_token = client_token;
+                if let Some(auth_token) = auth_token {
+                    self.auth_token = auth_token;
+                }
+                true
+            }
+            response => {
+                error!(?response, "Unable to refresh tokens");
+                false
+            }
+        }
+    }
+
     async fn process_line(
         &mut self,
         line: Message,
@@ -183,7 +279,10 @@ impl WebSocketConnection {
                         origin_player: self.player.0,
                         system_message: Some("I don't understand that.".to_string()),
                         message: None,
+                        content_type: None,
+                        presence: None,
                         server_time: SystemTime::now(),
+                        seq: None,
                     },
                 )
                 .await;
@@ -195,7 +294,10 @@ impl WebSocketConnection {
                         origin_player: self.player.0,
                         system_message: Some("I don't know what you're talking about.".to_string()),
                         message: None,
+                        content_type: None,
+                        presence: None,
                         server_time: SystemTime::now(),
+                        seq: None,
                     },
                 )
                 .await;
@@ -207,7 +309,10 @@ impl WebSocketConnection {
                         origin_player: self.player.0,
                         system_message: Some("I don't know how to do that.".to_string()),
                         message: None,
+                        content_type: None,
+                        presence: None,
                         server_time: SystemTime::now(),
+                        seq: None,
                     },
                 )
                 .await;
@@ -219,7 +324,25 @@ impl WebSocketConnection {
                         origin_player: self.player.0,
                         system_message: Some("You can't do that.".to_string()),
                         message: None,
+                        content_type: None,
+                        presence: None,
                         server_time: SystemTime::now(),
+                        seq: None,
+                    },
+                )
+                .await;
+            }
+            RpcResult::Failure(RpcRequestError::TooManyRequests) => {
+                Self::emit_event(
+                    ws_sender,
+                    NarrativeOutput {
+                        origin_player: self.player.0,
+                        system_message: Some("You're doing that too often; slow down.".to_string()),
+                        message: None,
+                        content_type: None,
+                        presence: None,
+                        server_time: SystemTime::now(),
+                        seq: None,
                     },
                 )
                 .await;
@@ -243,3 +366,25 @@ impl WebSocketConnection {
             .expect("Unable to send message to client");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use moor_values::model::NarrativeEvent;
+    use moor_values::var::Objid;
+
+    use crate::host::ws_connection::NarrativeOutput;
+
+    #[test]
+    fn test_rich_narrative_keeps_content_type() {
+        let event =
+            NarrativeEvent::notify_rich(Objid(2), "<b>hi</b>".to_string(), "text/html".to_string());
+        let json = serde_json::to_value(NarrativeOutput::narrative(Objid(2), &event)).unwrap();
+        assert_eq!(json["message"], "<b>hi</b>");
+        assert_eq!(json["content_type"], "text/html");
+
+        let event = NarrativeEvent::notify_text(Objid(2), "hi".to_string());
+        let json = serde_json::to_value(NarrativeOutput::narrative(Objid(2), &event)).unwrap();
+        assert_eq!(json["message"], "hi");
+        assert!(json.get("content_type").is_none());
+    }
+}
@@ -52,6 +52,15 @@ struct Args {
         default_value = "tcp://0.0.0.0:7898"
     )]
     narrative_server: String,
+
+    #[arg(
+        long,
+        value_name = "print-messages",
+        help = "Send the standard ** Connected ** etc. lines to connections, for cores which don't print their own",
+        action = clap::ArgAction::Set,
+        default_value = "true"
+    )]
+    print_messages: bool,
 }
 
 fn mk_routes(web_host: WebHost) -> eyre::Result<Router> {
@@ -71,6 +80,7 @@ fn mk_routes(web_host: WebHost) -> eyre::Result<Router> {
         .route("/auth/connect", post(host::connect_auth_handler))
         .route("/auth/create", post(host::create_auth_handler))
         .route("/welcome", get(host::welcome_message_handler))
+        .route("/status", get(host::status_handler))
         .route("/eval", post(host::eval_handler))
         .with_state(web_host);
 
@@ -93,11 +103,15 @@ async fn main() -> Result<(), eyre::Error> {
     tracing::subscriber::set_global_default(main_subscriber)
         .expect("Unable to set configure logging");
 
-    let ws_host = WebHost::new(args.rpc_server, args.narrative_server);
+    let ws_host = WebHost::new(args.rpc_server, args.narrative_server, args.print_messages);
+
+    let address = &args.listen_address.parse::<SocketAddr>().unwrap();
+    let registrar = ws_host.clone();
+    let registered_address = *address;
+    tokio::spawn(async move { registrar.register_host(registered_address).await });
 
     let main_router = mk_routes(ws_host).expect("Unable to create main router");
 
-    let address = &args.listen_address.parse::<SocketAddr>().unwrap();
     info!(address=?address, "Listening");
 
     let listener = TcpListener::bind(address)